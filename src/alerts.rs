@@ -0,0 +1,305 @@
+//! This module allows watching system activity metrics for threshold crossings
+//!
+//! Monitoring tools commonly want to warn their users when some activity metric
+//! goes through the roof, for example when the system starts thrashing between
+//! processes. This module provides a small layer on top of the sampled data of
+//! /proc/stat which detects such conditions.
+
+use ::data::SampledData;
+use ::procfs::stat;
+use std::time::Duration;
+
+
+/// User-defined thresholds above which an alert should be raised
+///
+/// Each threshold is optional, and conditions without a threshold are not
+/// monitored.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Thresholds {
+    /// Maximal acceptable amount of context switches per second
+    pub context_switch_rate: Option<f64>,
+
+    /// Maximal acceptable amount of hardware interrupts per second
+    pub interrupt_rate: Option<f64>,
+
+    /// Maximal acceptable amount of runnable processes (run queue length)
+    pub runnable_processes: Option<u16>,
+}
+
+
+/// Kind of condition which triggered an alert
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AlertKind {
+    /// The context switch rate exceeded its threshold
+    ContextSwitchRate,
+
+    /// The hardware interrupt rate exceeded its threshold
+    InterruptRate,
+
+    /// The amount of runnable processes exceeded its threshold
+    RunnableProcesses,
+}
+//
+/// Number of alert kinds
+const NUM_ALERT_KINDS: usize = 3;
+
+
+/// Alert raised by a ThresholdWatcher
+#[derive(Clone, Debug, PartialEq)]
+pub struct Alert {
+    /// Condition which triggered the alert
+    pub kind: AlertKind,
+
+    /// Index of the /proc/stat sample where the threshold was crossed
+    pub sample_index: usize,
+
+    /// Value of the metric at that sample
+    pub value: f64,
+
+    /// Threshold which was crossed
+    pub threshold: f64,
+}
+
+
+/// Mechanism for watching sampled /proc/stat data for threshold crossings
+///
+/// The watcher is meant to be fed the same growing stat::Data again and again,
+/// for example after each call to the sampler's sample() method. It remembers
+/// which samples it has already checked, so that each sample is only checked
+/// once, and it only raises an alert when a threshold is newly crossed: a
+/// metric which stays above its threshold will not raise alerts again until
+/// it has gone back below it.
+///
+/// Rates are computed from the sampling period, which is assumed to be fixed.
+/// Across reboots, they only account for post-reboot activity, and intervals
+/// during which a counter went backwards without a reboot are not checked, as
+/// no meaningful rate can be computed for them.
+///
+pub struct ThresholdWatcher {
+    /// Thresholds above which alerts should be raised
+    thresholds: Thresholds,
+
+    /// Time elapsed between two consecutive samples
+    sampling_period: Duration,
+
+    /// Index of the next sample to be checked
+    next_sample: usize,
+
+    /// Truth that each kind of alert is currently tripped
+    tripped: [bool; NUM_ALERT_KINDS],
+}
+//
+impl ThresholdWatcher {
+    /// Set up a watcher with certain thresholds, for data which is sampled
+    /// with a certain period
+    pub fn new(thresholds: Thresholds, sampling_period: Duration) -> Self {
+        Self {
+            thresholds,
+            sampling_period,
+            next_sample: 0,
+            tripped: [false; NUM_ALERT_KINDS],
+        }
+    }
+
+    /// Check the samples which were added since the last check, and report
+    /// the thresholds that were newly crossed in these samples
+    pub fn check(&mut self, data: &stat::Data) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+        let num_samples = data.len();
+        let non_monotonic = data.non_monotonic_intervals();
+        for sample in self.next_sample..num_samples {
+            // Check the run queue length, which is directly sampled
+            if let (Some(threshold), Some(runnable)) =
+                (self.thresholds.runnable_processes, data.runnable_processes())
+            {
+                self.update(AlertKind::RunnableProcesses,
+                            sample,
+                            runnable[sample] as f64,
+                            threshold as f64,
+                            &mut alerts);
+            }
+
+            // Rates can only be computed starting from the second sample, and
+            // not over intervals where counters went backwards
+            if sample == 0 || non_monotonic.contains(&(sample-1)) {
+                continue;
+            }
+            let period = self.sampling_period;
+            if let (Some(threshold), Some(rate)) =
+                (self.thresholds.context_switch_rate,
+                 data.context_switches().and_then(|ctxt| {
+                     data.rate_between(ctxt, sample-1, sample, period)
+                 }))
+            {
+                self.update(AlertKind::ContextSwitchRate,
+                            sample,
+                            rate,
+                            threshold,
+                            &mut alerts);
+            }
+            if let (Some(threshold), Some(rate)) =
+                (self.thresholds.interrupt_rate,
+                 data.interrupts().and_then(|intr| {
+                     data.rate_between(intr.total(), sample-1, sample, period)
+                 }))
+            {
+                self.update(AlertKind::InterruptRate,
+                            sample,
+                            rate,
+                            threshold,
+                            &mut alerts);
+            }
+        }
+        self.next_sample = ::std::cmp::max(self.next_sample, num_samples);
+        alerts
+    }
+
+    /// Notify the watcher that the oldest samples of the data were dropped
+    /// (e.g. by a bounded sampler), so that it can renumber its samples
+    pub fn samples_dropped(&mut self, count: usize) {
+        self.next_sample = self.next_sample.saturating_sub(count);
+    }
+
+    /// INTERNAL: Check a metric against its threshold, and raise an alert if
+    ///           that threshold was newly crossed
+    fn update(&mut self,
+              kind: AlertKind,
+              sample_index: usize,
+              value: f64,
+              threshold: f64,
+              alerts: &mut Vec<Alert>) {
+        let tripped = &mut self.tripped[kind as usize];
+        let above = value > threshold;
+        if above && !*tripped {
+            alerts.push(Alert { kind, sample_index, value, threshold });
+        }
+        *tripped = above;
+    }
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use ::procfs::stat;
+    use std::time::Duration;
+    use super::{Alert, AlertKind, ThresholdWatcher, Thresholds};
+
+    /// Check that thresholds trip once, and re-arm after going back below
+    #[test]
+    fn threshold_crossing() {
+        // Watch context switches, interrupts and the run queue length
+        let mut watcher = ThresholdWatcher::new(
+            Thresholds {
+                context_switch_rate: Some(1000.0),
+                interrupt_rate: Some(500.0),
+                runnable_processes: Some(4),
+            },
+            Duration::from_secs(1)
+        );
+
+        // Initially, nothing should be tripped
+        let mut samples = vec!["intr 1000\nctxt 1000\nprocs_running 1\n",
+                               "intr 1100\nctxt 1500\nprocs_running 2\n"];
+        let data = stat::Data::from_samples(&samples);
+        assert_eq!(watcher.check(&data), Vec::new());
+
+        // Then the context switch rate and run queue go above threshold
+        samples.push("intr 1200\nctxt 3000\nprocs_running 8\n");
+        let data = stat::Data::from_samples(&samples);
+        assert_eq!(watcher.check(&data), vec![
+            Alert {
+                kind: AlertKind::RunnableProcesses,
+                sample_index: 2,
+                value: 8.0,
+                threshold: 4.0,
+            },
+            Alert {
+                kind: AlertKind::ContextSwitchRate,
+                sample_index: 2,
+                value: 1500.0,
+                threshold: 1000.0,
+            },
+        ]);
+
+        // Checking the same data again should not fire the alerts again
+        assert_eq!(watcher.check(&data), Vec::new());
+
+        // Staying above a threshold should not fire it again either, but
+        // crossing a new one should
+        samples.push("intr 2000\nctxt 5000\nprocs_running 8\n");
+        let data = stat::Data::from_samples(&samples);
+        assert_eq!(watcher.check(&data), vec![
+            Alert {
+                kind: AlertKind::InterruptRate,
+                sample_index: 3,
+                value: 800.0,
+                threshold: 500.0,
+            },
+        ]);
+
+        // Going back below a threshold re-arms it
+        samples.push("intr 2100\nctxt 5100\nprocs_running 1\n");
+        samples.push("intr 2200\nctxt 7000\nprocs_running 1\n");
+        let data = stat::Data::from_samples(&samples);
+        assert_eq!(watcher.check(&data), vec![
+            Alert {
+                kind: AlertKind::ContextSwitchRate,
+                sample_index: 5,
+                value: 1900.0,
+                threshold: 1000.0,
+            },
+        ]);
+    }
+
+    /// Check that counter decreases do not raise alerts or crash the watcher
+    #[test]
+    fn counter_decrease() {
+        let mut watcher = ThresholdWatcher::new(
+            Thresholds {
+                context_switch_rate: Some(1000.0),
+                interrupt_rate: Some(500.0),
+                runnable_processes: None,
+            },
+            Duration::from_secs(1)
+        );
+
+        // Counters going backwards without a reboot are skipped, and the
+        // following intervals are checked as usual
+        let data = stat::Data::from_samples(&[
+            "intr 5000\nctxt 9000\nbtime 100\n",
+            "intr 10\nctxt 20\nbtime 100\n",
+            "intr 1000\nctxt 1030\nbtime 100\n",
+        ]);
+        assert_eq!(watcher.check(&data), vec![
+            Alert {
+                kind: AlertKind::ContextSwitchRate,
+                sample_index: 2,
+                value: 1010.0,
+                threshold: 1000.0,
+            },
+            Alert {
+                kind: AlertKind::InterruptRate,
+                sample_index: 2,
+                value: 990.0,
+                threshold: 500.0,
+            },
+        ]);
+
+        // Across a reboot, only post-reboot activity is accounted for
+        let mut watcher = ThresholdWatcher::new(
+            Thresholds {
+                context_switch_rate: Some(1000.0),
+                interrupt_rate: None,
+                runnable_processes: None,
+            },
+            Duration::from_secs(1)
+        );
+        let data = stat::Data::from_samples(&[
+            "ctxt 9000\nbtime 100\n",
+            "ctxt 20\nbtime 200\n",
+        ]);
+        assert_eq!(watcher.check(&data), Vec::new());
+    }
+}
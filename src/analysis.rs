@@ -0,0 +1,287 @@
+//! This module contains statistical helpers for analyzing sampled data
+//!
+//! Samplers record raw time series, and it is up to the user to make sense of
+//! them. Some analyses are needed often enough, for example in automated
+//! anomaly detection, that they are worth providing here.
+
+use std::time::Instant;
+
+
+/// Find the sampling intervals during which a counter grew abnormally fast
+///
+/// Given the samples of a monotonically increasing counter (e.g. the total
+/// number of context switches from /proc/stat) and the time at which each of
+/// them was acquired, this computes the counter's growth rate over each
+/// sampling interval, then flags the rates which lie more than 1.5 times the
+/// interquartile range below the first quartile or above the third quartile
+/// (aka Tukey's fences). This is useful for automatically detecting anomalies
+/// such as interrupt storms or context switch spikes.
+///
+/// The result is a list of interval indices, where interval N lies between
+/// samples N and N+1. Intervals with no elapsed time have no meaningful rate
+/// and are never flagged.
+///
+/// Quartiles are not meaningful on very small data sets, so no outlier is
+/// reported if there are fewer than 4 samples.
+///
+pub fn rate_outliers(values: &[u64], timestamps: &[Instant]) -> Vec<usize> {
+    assert_eq!(values.len(), timestamps.len(),
+               "There should be one timestamp per sample");
+    if values.len() < 4 {
+        return Vec::new();
+    }
+
+    // Compute the counter's growth rate over each sampling interval
+    let rates: Vec<(usize, f64)> =
+        values.windows(2)
+              .zip(timestamps.windows(2))
+              .enumerate()
+              .filter_map(|(idx, (values, timestamps))| {
+                  let elapsed = timestamps[1] - timestamps[0];
+                  let secs = elapsed.as_secs() as f64
+                             + elapsed.subsec_nanos() as f64 * 1e-9;
+                  let delta = values[1].saturating_sub(values[0]);
+                  if secs > 0.0 {
+                      Some((idx, delta as f64 / secs))
+                  } else {
+                      None
+                  }
+              })
+              .collect();
+
+    // Report the intervals whose rate lies outside of Tukey's fences
+    tukey_outliers(rates)
+}
+
+
+/// Find the sampling intervals which were abnormally short or long
+///
+/// Given the time at which each sample was acquired, this flags the intervals
+/// between consecutive samples whose duration lies outside of Tukey's fences
+/// (see rate_outliers()). This is useful for detecting hiccups of the sampling
+/// loop, e.g. due to the system being overloaded or suspended, which make the
+/// rates computed over the affected intervals less trustworthy.
+///
+/// As in rate_outliers(), interval N lies between samples N and N+1, and no
+/// outlier is reported if there are fewer than 4 samples.
+///
+pub fn interval_outliers(timestamps: &[Instant]) -> Vec<usize> {
+    if timestamps.len() < 4 {
+        return Vec::new();
+    }
+    let intervals: Vec<(usize, f64)> =
+        timestamps.windows(2)
+                  .enumerate()
+                  .map(|(idx, timestamps)| {
+                      let elapsed = timestamps[1] - timestamps[0];
+                      (idx, elapsed.as_secs() as f64
+                            + elapsed.subsec_nanos() as f64 * 1e-9)
+                  })
+                  .collect();
+    tukey_outliers(intervals)
+}
+
+
+/// Running mean and variance of a stream of values, computed in O(1) memory
+///
+/// This is meant for monitors which do not retain their samples, but still
+/// want to characterize the distribution of some quantity (e.g. the growth
+/// rate of a counter over each sampling interval) over an arbitrarily long
+/// run. It uses Welford's online algorithm, which unlike the naive sum of
+/// squares approach does not suffer from catastrophic cancellation.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RunningStats {
+    /// Number of values which were fed so far
+    count: u64,
+
+    /// Mean of these values
+    mean: f64,
+
+    /// Sum of the squared differences between these values and their mean
+    squared_deviations: f64,
+}
+//
+impl RunningStats {
+    /// Set up an accumulator, which has not seen any value yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed a new value into the accumulator
+    pub fn push(&mut self, x: f64) {
+        self.count += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.count as f64;
+        self.squared_deviations += delta * (x - self.mean);
+    }
+
+    /// Number of values which were fed so far
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Mean of the values fed so far, if there is any
+    pub fn mean(&self) -> Option<f64> {
+        if self.count > 0 { Some(self.mean) } else { None }
+    }
+
+    /// Sample variance of the values fed so far (with Bessel's correction),
+    /// which is only defined once at least two values were fed
+    pub fn variance(&self) -> Option<f64> {
+        if self.count > 1 {
+            Some(self.squared_deviations / (self.count - 1) as f64)
+        } else {
+            None
+        }
+    }
+
+    /// Sample standard deviation of the values fed so far, which is only
+    /// defined once at least two values were fed
+    pub fn stddev(&self) -> Option<f64> {
+        self.variance().map(f64::sqrt)
+    }
+}
+
+
+/// INTERNAL: Report the indices of the values which lie more than 1.5 times
+///           the interquartile range below the first quartile or above the
+///           third quartile (aka Tukey's fences)
+fn tukey_outliers(values: Vec<(usize, f64)>) -> Vec<usize> {
+    // Compute Tukey's fences from the quartiles of the values
+    let mut sorted_values: Vec<f64> = values.iter().map(|&(_, v)| v).collect();
+    if sorted_values.is_empty() {
+        return Vec::new();
+    }
+    sorted_values.sort_by(|a, b| {
+        a.partial_cmp(b).expect("Values can't be NaN")
+    });
+    let first_quartile = quantile(&sorted_values, 0.25);
+    let third_quartile = quantile(&sorted_values, 0.75);
+    let iqr = third_quartile - first_quartile;
+    let lower_fence = first_quartile - 1.5 * iqr;
+    let upper_fence = third_quartile + 1.5 * iqr;
+
+    // Report the values which lie outside of these fences
+    values.into_iter()
+          .filter(|&(_, value)| value < lower_fence || value > upper_fence)
+          .map(|(idx, _)| idx)
+          .collect()
+}
+
+
+/// INTERNAL: Compute a quantile of sorted data, interpolating linearly between
+///           the two nearest data points
+fn quantile(sorted_data: &[f64], fraction: f64) -> f64 {
+    let position = fraction * (sorted_data.len() - 1) as f64;
+    let lower_idx = position.floor() as usize;
+    let upper_idx = position.ceil() as usize;
+    let weight = position - lower_idx as f64;
+    sorted_data[lower_idx] * (1.0 - weight) + sorted_data[upper_idx] * weight
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use super::{interval_outliers, quantile, rate_outliers, RunningStats};
+
+    /// Generate timestamps at a regular interval of 10ms
+    fn regular_timestamps(count: usize) -> Vec<Instant> {
+        let start = Instant::now();
+        (0..count).map(|i| start + Duration::from_millis(10 * i as u64))
+                  .collect()
+    }
+
+    /// Check that quantiles are interpolated correctly
+    #[test]
+    fn quantiles() {
+        let data = [1.0, 2.0, 3.0, 4.0, 5.0];
+        assert_eq!(quantile(&data, 0.0), 1.0);
+        assert_eq!(quantile(&data, 0.25), 2.0);
+        assert_eq!(quantile(&data, 0.5), 3.0);
+        assert_eq!(quantile(&data, 1.0), 5.0);
+        assert_eq!(quantile(&[1.0, 2.0], 0.25), 1.25);
+    }
+
+    /// Check that an injected rate spike is flagged
+    #[test]
+    fn injected_spike() {
+        // The counter normally grows by 100-104 per interval, except for a
+        // spike of 1000 during interval 5
+        let increments = [100, 102, 101, 104, 100, 1000, 103, 101, 102];
+        let mut values = vec![5000];
+        for increment in increments.iter() {
+            let last = *values.last().unwrap();
+            values.push(last + increment);
+        }
+        let timestamps = regular_timestamps(values.len());
+        assert_eq!(rate_outliers(&values, &timestamps), vec![5]);
+    }
+
+    /// Check that edge cases are handled correctly
+    #[test]
+    fn edge_cases() {
+        // Fewer than 4 samples yield no outlier
+        let values = [0, 10, 1000];
+        assert!(rate_outliers(&values, &regular_timestamps(3)).is_empty());
+
+        // All-equal rates yield no outlier
+        let values: Vec<u64> = (0..10).map(|i| 42 * i).collect();
+        let timestamps = regular_timestamps(values.len());
+        assert!(rate_outliers(&values, &timestamps).is_empty());
+
+        // Intervals with no elapsed time are ignored
+        let timestamps = vec![timestamps[0]; values.len()];
+        assert!(rate_outliers(&values, &timestamps).is_empty());
+    }
+
+    /// Check that a sampling hiccup is flagged
+    #[test]
+    fn sampling_hiccup() {
+        // Samples are acquired every 10ms, except for a 200ms hiccup before
+        // sample 4
+        let start = Instant::now();
+        let millis = [0, 10, 20, 30, 230, 240, 250, 260];
+        let timestamps: Vec<Instant> =
+            millis.iter()
+                  .map(|&ms| start + Duration::from_millis(ms))
+                  .collect();
+        assert_eq!(interval_outliers(&timestamps), vec![3]);
+
+        // Regular sampling and short captures yield no outlier
+        assert!(interval_outliers(&regular_timestamps(10)).is_empty());
+        assert!(interval_outliers(&timestamps[3..6]).is_empty());
+    }
+
+    /// Check that running statistics match their batch-computed counterpart
+    #[test]
+    fn running_stats() {
+        // Statistics are undefined until enough values were fed
+        let mut stats = RunningStats::new();
+        assert_eq!(stats.count(), 0);
+        assert_eq!(stats.mean(), None);
+        stats.push(42.0);
+        assert_eq!(stats.mean(), Some(42.0));
+        assert_eq!(stats.variance(), None);
+        assert_eq!(stats.stddev(), None);
+
+        // Values with a large offset would expose catastrophic cancellation
+        let values: Vec<f64> = (0..1000).map(|i| 1e9 + (i % 17) as f64 * 0.5)
+                                        .collect();
+        let mut stats = RunningStats::new();
+        for &value in &values {
+            stats.push(value);
+        }
+        let count = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / count;
+        let variance = values.iter()
+                             .map(|value| (value - mean).powi(2))
+                             .sum::<f64>() / (count - 1.0);
+        assert_eq!(stats.count(), values.len() as u64);
+        assert!((stats.mean().unwrap() - mean).abs() < 1e-6);
+        assert!((stats.variance().unwrap() - variance).abs() < 1e-6);
+        assert!((stats.stddev().unwrap() - variance.sqrt()).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,213 @@
+//! This module implements a compact binary storage format for sampled data
+//!
+//! Text formats such as JSON or CSV are very bulky when used to store captures
+//! taken at high sampling rates, so sampled data containers may also be saved
+//! to disk and loaded back using the simple binary format implemented here.
+//!
+//! Every container starts with a header, made of a 4-byte magic number which
+//! identifies the kind of container, followed by a format version number and
+//! a container-specific description of the data schema (i.e. which optional
+//! entries are present). The sampled time series then follow in column-major
+//! order, each being stored as a sample count followed by its values. All
+//! integers are stored in little-endian byte order.
+
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+
+/// Version of the binary format, to be bumped on every incompatible change
+const FORMAT_VERSION: u8 = 1;
+
+
+/// Something which can be stored in the binary format
+pub(crate) trait BinaryValue: Sized {
+    /// Write the value to a binary output
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()>;
+
+    /// Read a value back from a binary input
+    fn read_from<R: Read>(reader: &mut R) -> io::Result<Self>;
+}
+//
+// Integers are stored in little-endian byte order
+macro_rules! impl_binary_integer {
+    ($($int:ty),*) => {
+        $(
+            impl BinaryValue for $int {
+                fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+                    writer.write_all(&self.to_le_bytes())
+                }
+
+                fn read_from<R: Read>(reader: &mut R) -> io::Result<Self> {
+                    let mut bytes = [0; ::std::mem::size_of::<$int>()];
+                    reader.read_exact(&mut bytes)?;
+                    Ok(<$int>::from_le_bytes(bytes))
+                }
+            }
+        )*
+    };
+}
+impl_binary_integer!(u8, u16, u32, u64, i64);
+//
+// Sizes and indices are stored as 64-bit integers, for portability
+impl BinaryValue for usize {
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        (*self as u64).write_to(writer)
+    }
+
+    fn read_from<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let value = u64::read_from(reader)?;
+        if value > usize::max_value() as u64 {
+            return Err(invalid_data("Size does not fit in host memory"));
+        }
+        Ok(value as usize)
+    }
+}
+//
+// Strings are stored as a byte length followed by UTF-8 bytes
+impl BinaryValue for String {
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.len().write_to(writer)?;
+        writer.write_all(self.as_bytes())
+    }
+
+    fn read_from<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let length = usize::read_from(reader)?;
+        let mut bytes = Vec::new();
+        reader.take(length as u64).read_to_end(&mut bytes)?;
+        if bytes.len() != length {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                      "Truncated string"));
+        }
+        String::from_utf8(bytes).map_err(|_| invalid_data("Invalid UTF-8"))
+    }
+}
+//
+// Shared labels are stored like strings. They are not interned again when read
+// back, as the interning pool is never freed and binary files could bring in an
+// unbounded amount of labels. Each container then gets its own copy.
+impl BinaryValue for Arc<str> {
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.len().write_to(writer)?;
+        writer.write_all(self.as_bytes())
+    }
+
+    fn read_from<R: Read>(reader: &mut R) -> io::Result<Self> {
+        String::read_from(reader).map(Arc::from)
+    }
+}
+//
+// Time series are stored as a sample count followed by the samples
+impl<T: BinaryValue> BinaryValue for Vec<T> {
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.len().write_to(writer)?;
+        for value in self.iter() {
+            value.write_to(writer)?;
+        }
+        Ok(())
+    }
+
+    fn read_from<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let length = usize::read_from(reader)?;
+        let mut values = Vec::new();
+        for _ in 0..length {
+            values.push(T::read_from(reader)?);
+        }
+        Ok(values)
+    }
+}
+
+
+/// Write the header which identifies a kind of container
+pub(crate) fn write_header<W: Write>(writer: &mut W,
+                                     magic: &[u8; 4]) -> io::Result<()> {
+    writer.write_all(magic)?;
+    FORMAT_VERSION.write_to(writer)
+}
+
+
+/// Check the header which identifies a kind of container
+pub(crate) fn read_header<R: Read>(reader: &mut R,
+                                   magic: &[u8; 4]) -> io::Result<()> {
+    let mut actual_magic = [0; 4];
+    reader.read_exact(&mut actual_magic)?;
+    if actual_magic != *magic {
+        return Err(invalid_data("Unexpected kind of binary data"));
+    }
+    if u8::read_from(reader)? != FORMAT_VERSION {
+        return Err(invalid_data("Unsupported binary format version"));
+    }
+    Ok(())
+}
+
+
+/// Write an optional entry of a container, if present. Whether it is present
+/// must have been recorded in the container's header beforehand.
+pub(crate) fn write_optional<T, W>(value: &Option<T>,
+                                   writer: &mut W) -> io::Result<()>
+    where T: BinaryValue,
+          W: Write
+{
+    match *value {
+        Some(ref value) => value.write_to(writer),
+        None => Ok(()),
+    }
+}
+
+
+/// Read an optional entry of a container, knowing from the container's header
+/// whether it is present
+pub(crate) fn read_optional<T, R>(present: bool,
+                                  reader: &mut R) -> io::Result<Option<T>>
+    where T: BinaryValue,
+          R: Read
+{
+    if present {
+        T::read_from(reader).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+
+/// Build the error which is emitted when reading back invalid binary data
+pub(crate) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_owned())
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+    use super::{read_header, write_header, BinaryValue};
+
+    /// Check that basic values survive a round trip through the binary format
+    #[test]
+    fn round_trip() {
+        let mut buffer = Vec::new();
+        0x1234u16.write_to(&mut buffer).unwrap();
+        (-42i64).write_to(&mut buffer).unwrap();
+        "héhé".to_owned().write_to(&mut buffer).unwrap();
+        vec![1u64, 2, 3].write_to(&mut buffer).unwrap();
+        assert_eq!(&buffer[..2], &[0x34, 0x12]);
+
+        let mut reader = &buffer[..];
+        assert_eq!(u16::read_from(&mut reader).unwrap(), 0x1234);
+        assert_eq!(i64::read_from(&mut reader).unwrap(), -42);
+        assert_eq!(String::read_from(&mut reader).unwrap(), "héhé");
+        assert_eq!(Vec::<u64>::read_from(&mut reader).unwrap(), vec![1, 2, 3]);
+        assert!(reader.is_empty());
+    }
+
+    /// Check that headers are checked, and that truncation is detected
+    #[test]
+    fn invalid_data() {
+        let mut buffer = Vec::new();
+        write_header(&mut buffer, b"TEST").unwrap();
+        assert!(read_header(&mut &buffer[..], b"TEST").is_ok());
+        assert_eq!(read_header(&mut &buffer[..], b"ELSE").unwrap_err().kind(),
+                   ErrorKind::InvalidData);
+        assert_eq!(u64::read_from(&mut &buffer[..]).unwrap_err().kind(),
+                   ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,281 @@
+//! This module provides a uniform, column-oriented view of sampled data
+//!
+//! Each sampled data container has its own interface, which is tailored to
+//! the pseudo-file that it comes from. This is convenient for code which knows
+//! what it is looking for, but gets in the way of generic tooling such as data
+//! exporters, which just want to enumerate every time series that was sampled.
+//! For this purpose, data containers implement the Columns trait, which
+//! transposes them into a list of named columns of uniform length.
+//!
+//! Column names are stable identifiers, which are derived from the pseudo-file
+//! that the data comes from (e.g. "cpu0_idle" for /proc/stat, "MemFree" for
+//! /proc/meminfo). Data exporters should build on this interface rather than
+//! accessing individual fields of the data containers.
+//!
+//! If the "ndarray" feature is enabled, the columns can also be gathered into
+//! a two-dimensional array of floating-point numbers, for matrix analysis.
+//! Similarly, the "arrow" feature allows handing the columns over to Apache
+//! Arrow based analytics tools, as a record batch.
+
+#[cfg(feature = "arrow")]
+use arrow::array::{ArrayRef, Float64Array, TimestampNanosecondArray,
+                   UInt64Array};
+#[cfg(feature = "arrow")]
+use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+#[cfg(feature = "arrow")]
+use arrow::error::ArrowError;
+#[cfg(feature = "arrow")]
+use arrow::record_batch::RecordBatch;
+use bytesize::ByteSize;
+#[cfg(feature = "ndarray")]
+use ndarray::Array2;
+#[cfg(feature = "arrow")]
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(any(feature = "arrow", feature = "influx", feature = "ndjson"))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+
+/// Time series of sampled values, tagged with the physical meaning of values
+#[derive(Clone, Debug, PartialEq)]
+pub enum Column {
+    /// Raw counters or gauges (e.g. a number of context switches)
+    U64(Vec<u64>),
+
+    /// Durations, in nanoseconds
+    Nanos(Vec<u64>),
+
+    /// Data volumes, in bytes
+    Bytes(Vec<u64>),
+
+    /// Real-valued quantities, where NaN denotes a missing sample
+    F64(Vec<f64>),
+}
+//
+impl Column {
+    /// Number of samples in this column
+    pub fn len(&self) -> usize {
+        match *self {
+            Column::U64(ref values)
+            | Column::Nanos(ref values)
+            | Column::Bytes(ref values) => values.len(),
+            Column::F64(ref values) => values.len(),
+        }
+    }
+
+    /// Truth that this column contains no sample
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Value of a certain sample, converted to a floating-point number. As
+    /// in the underlying data, durations are in nanoseconds and data volumes
+    /// are in bytes.
+    pub fn get_f64(&self, index: usize) -> f64 {
+        match *self {
+            Column::U64(ref values)
+            | Column::Nanos(ref values)
+            | Column::Bytes(ref values) => values[index] as f64,
+            Column::F64(ref values) => values[index],
+        }
+    }
+
+    /// Build a column from integer counters or gauges
+    pub(crate) fn counts<T: Copy + Into<u64>>(values: &[T]) -> Self {
+        Column::U64(values.iter().map(|&value| value.into()).collect())
+    }
+
+    /// Build a column from durations
+    pub(crate) fn durations(values: &[Duration]) -> Self {
+        Column::Nanos(
+            values.iter()
+                  .map(|duration| duration.as_secs() * 1_000_000_000
+                                  + duration.subsec_nanos() as u64)
+                  .collect()
+        )
+    }
+
+    /// Build a column from durations expressed in milliseconds
+    pub(crate) fn millis(values: &[u64]) -> Self {
+        Column::Nanos(values.iter().map(|&ms| ms * 1_000_000).collect())
+    }
+
+    /// Build a column from data volumes
+    pub(crate) fn bytes(values: &[ByteSize]) -> Self {
+        Column::Bytes(values.iter().map(|size| size.as_usize() as u64)
+                                   .collect())
+    }
+
+    /// Build a column from a time series which is only defined for some
+    /// samples (given by their indices), out of "len" samples in total
+    pub(crate) fn sparse<T, F>(len: usize,
+                               sample_indices: &[usize],
+                               values: &[T],
+                               to_f64: F) -> Self
+        where T: Copy,
+              F: Fn(T) -> f64
+    {
+        let mut column = vec![::std::f64::NAN; len];
+        for (&idx, &value) in sample_indices.iter().zip(values.iter()) {
+            column[idx] = to_f64(value);
+        }
+        Column::F64(column)
+    }
+}
+
+
+/// Column-oriented view of a sampled data container
+pub trait Columns {
+    /// List every time series of the container as a named column. All columns
+    /// have one entry per sample, and are listed in a stable order.
+    fn columns(&self) -> Vec<(String, Column)>;
+
+    /// Gather every time series of the container into a two-dimensional array,
+    /// where rows are samples and columns are fields, along with the names of
+    /// the columns (in the same order as in columns())
+    #[cfg(feature = "ndarray")]
+    fn to_array2(&self) -> (Array2<f64>, Vec<String>) {
+        let columns = self.columns();
+        let num_rows = columns.first().map_or(0, |&(_, ref col)| col.len());
+        let mut values = Vec::with_capacity(num_rows * columns.len());
+        for row in 0..num_rows {
+            values.extend(columns.iter().map(|&(_, ref col)| col.get_f64(row)));
+        }
+        let array = Array2::from_shape_vec((num_rows, columns.len()), values)
+                           .expect("Columns should all have the same length");
+        (array, columns.into_iter().map(|(name, _)| name).collect())
+    }
+
+    /// Gather every time series of the container into an Apache Arrow record
+    /// batch, where rows are samples and columns are fields
+    ///
+    /// The wall-clock time at which each sample was acquired must be provided.
+    /// It becomes the first column of the batch, called "timestamp", with a
+    /// nanosecond resolution. The other columns are those of columns(), in the
+    /// same order: integer columns (counters, durations in nanoseconds and
+    /// data volumes in bytes) become UInt64 columns, and real-valued columns
+    /// become Float64 columns, where missing samples are null. The column
+    /// storage is handed over to Arrow without being copied again.
+    ///
+    #[cfg(feature = "arrow")]
+    fn to_record_batch(&self,
+                       timestamps: &[SystemTime])
+        -> Result<RecordBatch, ArrowError>
+    {
+        let timestamps_ns = timestamps.iter()
+                                      .map(|&time| nanos_since_epoch(time))
+                                      .collect::<Vec<_>>();
+        let mut fields = vec![
+            Field::new("timestamp",
+                       DataType::Timestamp(TimeUnit::Nanosecond, None),
+                       false)
+        ];
+        let mut arrays: Vec<ArrayRef> = vec![
+            Arc::new(TimestampNanosecondArray::from(timestamps_ns))
+        ];
+        for (name, column) in self.columns() {
+            match column {
+                Column::U64(values)
+                | Column::Nanos(values)
+                | Column::Bytes(values) => {
+                    fields.push(Field::new(name, DataType::UInt64, false));
+                    arrays.push(Arc::new(UInt64Array::from(values)));
+                },
+                Column::F64(values) => {
+                    fields.push(Field::new(name, DataType::Float64, true));
+                    arrays.push(Arc::new(
+                        values.into_iter()
+                              .map(|x| if x.is_nan() { None } else { Some(x) })
+                              .collect::<Float64Array>()
+                    ));
+                },
+            }
+        }
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)
+    }
+}
+
+
+/// Append the columns of a nested data container to a column list, prefixing
+/// their names (e.g. "cpu0_" + "idle")
+pub(crate) fn extend_prefixed<C: Columns>(columns: &mut Vec<(String, Column)>,
+                                          prefix: &str,
+                                          nested: &C) {
+    columns.extend(nested.columns().into_iter().map(|(name, column)| {
+        (format!("{}{}", prefix, name), column)
+    }));
+}
+
+
+/// Express a wall-clock time as signed nanoseconds since the Unix epoch
+///
+/// Times which predate the epoch, which a badly set system clock can produce,
+/// come out negative. Times which are more than ~292 years away from the
+/// epoch saturate.
+///
+#[cfg(any(feature = "arrow", feature = "influx", feature = "ndjson"))]
+pub(crate) fn nanos_since_epoch(timestamp: SystemTime) -> i64 {
+    let nanos = |duration: Duration| {
+        (duration.as_secs() as i64).saturating_mul(1_000_000_000)
+                                   .saturating_add(duration.subsec_nanos()
+                                                   as i64)
+    };
+    match timestamp.duration_since(UNIX_EPOCH) {
+        Ok(after) => nanos(after),
+        Err(before) => -nanos(before.duration()),
+    }
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use bytesize::ByteSize;
+    use std::time::Duration;
+    #[cfg(any(feature = "arrow", feature = "influx", feature = "ndjson"))]
+    use std::time::UNIX_EPOCH;
+    use super::Column;
+
+    /// Check that columns are built correctly from the various value types
+    #[test]
+    fn column_builders() {
+        assert_eq!(Column::counts(&[1u32, 2, 3]), Column::U64(vec![1, 2, 3]));
+        assert_eq!(Column::durations(&[Duration::new(1, 5)]),
+                   Column::Nanos(vec![1_000_000_005]));
+        assert_eq!(Column::millis(&[3]), Column::Nanos(vec![3_000_000]));
+        assert_eq!(Column::bytes(&[ByteSize::kib(2)]),
+                   Column::Bytes(vec![2048]));
+        assert_eq!(Column::U64(Vec::new()).len(), 0);
+        assert!(Column::F64(Vec::new()).is_empty());
+    }
+
+    /// Check that sparse time series are padded with NaNs
+    #[test]
+    fn sparse_columns() {
+        let column = Column::sparse(4, &[1, 2], &[-5i32, 7], |x| x as f64);
+        match column {
+            Column::F64(values) => {
+                assert_eq!(values.len(), 4);
+                assert!(values[0].is_nan());
+                assert_eq!(values[1], -5.);
+                assert_eq!(values[2], 7.);
+                assert!(values[3].is_nan());
+            },
+            _ => panic!("Sparse columns should be real-valued"),
+        }
+    }
+
+    /// Check that wall-clock times are converted to signed timestamps
+    #[cfg(any(feature = "arrow", feature = "influx", feature = "ndjson"))]
+    #[test]
+    fn nanos_since_epoch() {
+        use super::nanos_since_epoch;
+        let after = UNIX_EPOCH + Duration::new(1500000100, 5);
+        assert_eq!(nanos_since_epoch(after), 1_500_000_100_000_000_005);
+        assert_eq!(nanos_since_epoch(UNIX_EPOCH), 0);
+        let before = UNIX_EPOCH - Duration::new(2, 500);
+        assert_eq!(nanos_since_epoch(before), -2_000_000_500);
+        let far = UNIX_EPOCH + Duration::from_secs(u64::max_value() / 2);
+        assert_eq!(nanos_since_epoch(far), i64::max_value());
+    }
+}
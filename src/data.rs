@@ -6,21 +6,146 @@
 //!
 //! The following describes what we expect from such containers.
 
+use std::error::Error;
+use std::fmt;
+use std::io;
+
 
 /// What we expect from all sampled data containers. In an ideal type system,
 /// everything should be inside of this trait, but since we can't write code
 /// which is generic over the number of lifetimes parameters in the parsed file
 /// sample type, we'll also need one subtrait per parsed file sample type.
-pub(crate) trait SampledData {
+///
+/// This trait is part of the public plugin interface (see the "plugin" module),
+/// so user-defined data containers implement it too.
+///
+pub trait SampledData {
     /// Tell how many data samples are present in this container, and in debug
     /// mode, also check that any redundant metadata is consistent
     fn len(&self) -> usize;
+
+    /// Drop the oldest samples from this container, keeping the most recent
+    /// ones. The amount of dropped samples must not exceed len().
+    fn drop_oldest(&mut self, count: usize);
+
+    /// Tell the length of the shortest time series inside of this container,
+    /// without checking consistency (unlike len())
+    fn min_len(&self) -> usize;
+
+    /// Drop the newest samples from all time series of this container, keeping
+    /// only the oldest "length" ones. The length must not exceed min_len().
+    fn truncate(&mut self, length: usize);
+
+    /// Repair a container whose inner time series ended up with inconsistent
+    /// lengths, by truncating them all to the length of the shortest one, and
+    /// return that length.
+    ///
+    /// This should never be needed in normal operation. But if a sample was
+    /// only partially stored, for example because its parsing panicked and the
+    /// panic was caught, this allows salvaging the complete samples.
+    ///
+    fn truncate_to_consistent(&mut self) -> usize {
+        let length = self.min_len();
+        self.truncate(length);
+        length
+    }
+
+    /// Complete this container with information about the host which is not
+    /// found in the pseudo-file itself, such as device names from sysfs
+    ///
+    /// Samplers only call this when they are created for the live pseudo-files
+    /// of the host. It is not called when data comes from another source, such
+    /// as replayed snapshots or forwarded file contents, since the information
+    /// would then describe the wrong host. By default, nothing is done.
+    ///
+    fn load_host_details(&mut self) {}
+}
+
+
+/// Error which is emitted when the structure of a pseudo-file changes between
+/// two samples in a way that its data container cannot follow, for example
+/// when a kernel update adds a new CPU timer to /proc/stat
+///
+/// Samplers report it as an I/O error of kind InvalidData, from which it can be
+/// recovered using the get_ref() and downcast_ref() methods.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct SchemaChanged {
+    /// Description of what changed
+    description: &'static str,
+}
+//
+impl SchemaChanged {
+    /// Report a schema change
+    pub(crate) fn new(description: &'static str) -> Self {
+        Self {
+            description,
+        }
+    }
+}
+//
+impl fmt::Display for SchemaChanged {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unsupported pseudo-file schema change: {}", self.description)
+    }
+}
+//
+impl Error for SchemaChanged {}
+//
+impl From<SchemaChanged> for io::Error {
+    fn from(error: SchemaChanged) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, error)
+    }
+}
+
+
+/// Outcome of pushing a sample into a data container. Most containers accept
+/// every sample and return (), whereas containers which can detect schema
+/// changes return a Result.
+pub(crate) trait PushOutcome {
+    /// Convert the outcome into a Result
+    fn into_result(self) -> Result<(), SchemaChanged>;
+}
+//
+impl PushOutcome for () {
+    fn into_result(self) -> Result<(), SchemaChanged> {
+        Ok(())
+    }
+}
+//
+impl PushOutcome for Result<(), SchemaChanged> {
+    fn into_result(self) -> Result<(), SchemaChanged> {
+        self
+    }
+}
+
+
+/// Find the index associated with the highest value in a sequence of (index,
+/// value) pairs, or None if the sequence is empty. Ties are resolved in favor
+/// of the earliest index, and values which cannot be compared (such as NaN)
+/// are ignored.
+pub(crate) fn argmax<T, I>(indexed_values: I) -> Option<usize>
+    where T: PartialOrd,
+          I: IntoIterator<Item=(usize, T)>
+{
+    let mut best: Option<(usize, T)> = None;
+    for (index, value) in indexed_values {
+        let is_better = match best {
+            Some((_, ref best_value)) => value > *best_value,
+            None => value.partial_cmp(&value).is_some(),
+        };
+        if is_better {
+            best = Some((index, value));
+        }
+    }
+    best.map(|(index, _)| index)
 }
 
 
 /// Sampled data container for data with no lifetime parameter (for example,
-/// data which is coming out of an eager parser)
-pub(crate) trait SampledData0 : SampledData {
+/// data which is coming out of an eager parser, such as user-defined parsers)
+pub trait SampledData0 : SampledData {
+    /// Parsed pseudo-file sample which this container stores
     type Input;
 
     /// Construct container using a sample of parsed data for schema analysis
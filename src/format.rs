@@ -0,0 +1,163 @@
+//! This module provides helpers for displaying sampled data to humans
+//!
+//! Command-line frontends to this library all need to turn data volumes,
+//! durations and event rates into short strings. Doing so with the following
+//! functions keeps the output format uniform across frontends. The exact output
+//! of these functions is considered part of the library's interface.
+
+use bytesize::ByteSize;
+use std::time::Duration;
+
+
+/// Binary prefixes used for data volumes, in increasing order of magnitude
+const BINARY_PREFIXES: [&str; 6] = ["Ki", "Mi", "Gi", "Ti", "Pi", "Ei"];
+
+/// Decimal prefixes used for event counts, in increasing order of magnitude
+const DECIMAL_PREFIXES: [&str; 6] = ["k", "M", "G", "T", "P", "E"];
+
+
+/// Format a data volume using binary prefixes (e.g. "12.3 MiB")
+pub fn fmt_bytes(size: ByteSize) -> String {
+    fmt_data_volume(size.as_usize() as f64)
+}
+
+
+/// Format a data rate, in bytes per second, using binary prefixes (e.g.
+/// "12.3 MiB/s")
+pub fn fmt_rate(bytes_per_sec: f64) -> String {
+    fmt_data_volume(bytes_per_sec) + "/s"
+}
+
+
+/// Format a duration compactly (e.g. "340ms", "1.5s", "2m05s")
+///
+/// Sub-second durations are rounded down to an integer amount of the largest
+/// suitable unit (ns, µs or ms). Durations below one minute are displayed in
+/// seconds, rounded to one decimal, and longer ones as minutes and seconds or
+/// hours and minutes, rounded down. Durations which round up to one minute
+/// are displayed as such (e.g. "1m00s", never "60.0s").
+///
+pub fn fmt_duration_compact(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let nanos = duration.subsec_nanos();
+    let tenths = secs * 10 + ((nanos + 50_000_000) / 100_000_000) as u64;
+    if secs >= 3600 {
+        format!("{}h{:02}m", secs / 3600, (secs % 3600) / 60)
+    } else if tenths >= 600 {
+        let secs = secs.max(60);
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else if secs >= 1 {
+        format!("{}.{}s", tenths / 10, tenths % 10)
+    } else if nanos >= 1_000_000 {
+        format!("{}ms", nanos / 1_000_000)
+    } else if nanos >= 1_000 {
+        format!("{}µs", nanos / 1_000)
+    } else {
+        format!("{}ns", nanos)
+    }
+}
+
+
+/// Format an event rate, in events per second, using decimal prefixes (e.g.
+/// "45.2k/s" for 45200 events per second)
+pub fn fmt_count_rate(per_sec: f64) -> String {
+    let (value, prefix) = scale(per_sec, 1000., &DECIMAL_PREFIXES, 1);
+    format!("{:.1}{}/s", value, prefix)
+}
+
+
+/// INTERNAL: Format an amount of bytes using binary prefixes
+fn fmt_data_volume(bytes: f64) -> String {
+    let (value, prefix) = scale(bytes, 1024., &BINARY_PREFIXES, 0);
+    if prefix.is_empty() {
+        format!("{:.0} B", value)
+    } else {
+        format!("{:.1} {}B", value, prefix)
+    }
+}
+
+
+/// INTERNAL: Scale a value down by powers of some base until it is smaller
+///           than that base, and return the scaled value with the matching
+///           unit prefix (or an empty prefix if no scaling was needed)
+///
+/// The comparison with the base is carried out after rounding the value to the
+/// amount of decimals that it will be displayed with, which is one for scaled
+/// values and "unscaled_decimals" otherwise. This way, a value which rounds up
+/// to the base is displayed with the next prefix (e.g. "1.0 MiB" instead of
+/// "1024.0 KiB").
+///
+fn scale(mut value: f64,
+         base: f64,
+         prefixes: &[&'static str],
+         unscaled_decimals: i32) -> (f64, &'static str) {
+    let mut prefix = "";
+    let mut decimals = unscaled_decimals;
+    for &next_prefix in prefixes {
+        let precision = 10f64.powi(decimals);
+        if (value * precision).round().abs() < base * precision { break; }
+        value /= base;
+        prefix = next_prefix;
+        decimals = 1;
+    }
+    (value, prefix)
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use bytesize::ByteSize;
+    use std::time::Duration;
+    use super::{fmt_bytes, fmt_count_rate, fmt_duration_compact, fmt_rate};
+
+    /// Check the formatting of data volumes and data rates
+    #[test]
+    fn data_volumes() {
+        assert_eq!(fmt_bytes(ByteSize::b(0)), "0 B");
+        assert_eq!(fmt_bytes(ByteSize::b(1023)), "1023 B");
+        assert_eq!(fmt_bytes(ByteSize::kib(1)), "1.0 KiB");
+        assert_eq!(fmt_bytes(ByteSize::kib(1536)), "1.5 MiB");
+        assert_eq!(fmt_bytes(ByteSize::gib(3)), "3.0 GiB");
+        assert_eq!(fmt_rate(512.), "512 B/s");
+        assert_eq!(fmt_rate(12.3 * 1024. * 1024.), "12.3 MiB/s");
+        assert_eq!(fmt_rate(2. * 1024f64.powi(4)), "2.0 TiB/s");
+
+        // Values which round up to the next unit are displayed in that unit
+        assert_eq!(fmt_rate(1023.4), "1023 B/s");
+        assert_eq!(fmt_rate(1023.6), "1.0 KiB/s");
+        assert_eq!(fmt_rate(1023.94 * 1024.), "1023.9 KiB/s");
+        assert_eq!(fmt_rate(1023.96 * 1024.), "1.0 MiB/s");
+        assert_eq!(fmt_bytes(ByteSize::b(1024 * 1024 - 1)), "1.0 MiB");
+    }
+
+    /// Check the formatting of durations
+    #[test]
+    fn durations() {
+        assert_eq!(fmt_duration_compact(Duration::new(0, 0)), "0ns");
+        assert_eq!(fmt_duration_compact(Duration::new(0, 999)), "999ns");
+        assert_eq!(fmt_duration_compact(Duration::new(0, 57_144)), "57µs");
+        assert_eq!(fmt_duration_compact(Duration::from_millis(340)), "340ms");
+        assert_eq!(fmt_duration_compact(Duration::from_millis(1500)), "1.5s");
+        assert_eq!(fmt_duration_compact(Duration::from_secs(59)), "59.0s");
+        assert_eq!(fmt_duration_compact(Duration::from_millis(1049)), "1.0s");
+        assert_eq!(fmt_duration_compact(Duration::from_millis(59_949)),
+                   "59.9s");
+        assert_eq!(fmt_duration_compact(Duration::from_millis(59_950)),
+                   "1m00s");
+        assert_eq!(fmt_duration_compact(Duration::from_secs(125)), "2m05s");
+        assert_eq!(fmt_duration_compact(Duration::from_secs(7320)), "2h02m");
+    }
+
+    /// Check the formatting of event rates
+    #[test]
+    fn count_rates() {
+        assert_eq!(fmt_count_rate(0.), "0.0/s");
+        assert_eq!(fmt_count_rate(12.34), "12.3/s");
+        assert_eq!(fmt_count_rate(45_200.), "45.2k/s");
+        assert_eq!(fmt_count_rate(3_000_000.), "3.0M/s");
+        assert_eq!(fmt_count_rate(999.94), "999.9/s");
+        assert_eq!(fmt_count_rate(999.96), "1.0k/s");
+        assert_eq!(fmt_count_rate(999_960.), "1.0M/s");
+    }
+}
@@ -0,0 +1,52 @@
+//! This module provides interning of the keys of pseudo-files
+//!
+//! Some pseudo-files, such as /proc/meminfo, are sets of labeled records
+//! whose labels must be kept around by the data containers. These labels are
+//! the same for every sampler of a given file, and tools which spawn many
+//! samplers (e.g. one per process) would thus store many copies of them. By
+//! interning the labels, all samplers share a single allocation per label.
+//!
+//! Labels are only interned when a data container is created from a live
+//! pseudo-file, so this has no impact on the performance of sampling itself.
+//! Interned labels are never freed, which is fine as pseudo-files only feature
+//! a small set of them. Labels which are read back from binary files are not
+//! interned, as those could bring in an arbitrary amount of them.
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+
+lazy_static! {
+    /// Every label that was interned so far
+    static ref POOL: Mutex<HashSet<Arc<str>>> = Mutex::new(HashSet::new());
+}
+
+
+/// Get a shared copy of a label, allocating it if it was never seen before
+pub(crate) fn intern(label: &str) -> Arc<str> {
+    let mut pool = POOL.lock().unwrap_or_else(|poison| poison.into_inner());
+    if let Some(interned) = pool.get(label) {
+        return interned.clone();
+    }
+    let interned: Arc<str> = Arc::from(label);
+    pool.insert(interned.clone());
+    interned
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::intern;
+
+    /// Check that identical labels share the same allocation
+    #[test]
+    fn sharing() {
+        let first = intern("MemTotal");
+        let second = intern(&String::from("MemTotal"));
+        assert_eq!(&*first, "MemTotal");
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(!Arc::ptr_eq(&first, &intern("MemFree")));
+    }
+}
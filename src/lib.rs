@@ -1,7 +1,7 @@
 //! This library is a sampling interface to Linux' pseudo-filesystems
 //!
-//! It currently covers procfs (aka "/proc"), and may in the future also cover
-//! sysfs (aka "/sys") for selected purposes.
+//! It currently covers procfs (aka "/proc"), along with sysfs (aka "/sys") for
+//! selected purposes such as thermal monitoring.
 //!
 //! Its main design goal is to allow taking periodical measurements of system
 //! activity, as described by the Linux kernel's procfs API, at a relatively
@@ -10,19 +10,38 @@
 
 #[macro_use] extern crate lazy_static;
 
+#[cfg(feature = "arrow")] extern crate arrow;
 extern crate bytesize;
 extern crate chrono;
+#[cfg(feature = "flate2")] extern crate flate2;
 extern crate libc;
+#[cfg(feature = "ndarray")] extern crate ndarray;
 extern crate regex;
+#[cfg(all(test, feature = "ndjson"))] extern crate serde_json;
 extern crate testbench;
 
 #[macro_use] mod sampler;
 
+pub mod alerts;
+pub mod analysis;
+#[cfg(feature = "binary")] mod binary;
+pub mod columns;
 mod data;
+pub mod format;
+mod intern;
+pub mod pacer;
 mod parser;
+pub mod plugin;
 pub mod procfs;
 mod reader;
+mod replay;
+#[cfg(test)] mod scratch;
 mod splitter;
+mod streaming;
+pub mod sysfs;
+pub mod system;
+
+pub use data::SchemaChanged;
 
 
 /// Performance benchmarks
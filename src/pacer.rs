@@ -0,0 +1,196 @@
+//! This module allows sampling at a rate which fits a CPU overhead budget
+//!
+//! Sampling pseudo-files at a fixed rate is simple, but the CPU cost of each
+//! sample depends on the file and on the host system (e.g. /proc/stat grows
+//! with the number of CPU cores). Users who care more about the CPU overhead
+//! of monitoring than about its exact sampling rate can instead use a Pacer,
+//! which measures how long sampling takes and adjusts the sampling interval so
+//! that the fraction of CPU time spent sampling stays close to a target budget.
+
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+
+/// Interface to pseudo-file samplers which is needed by the Pacer
+///
+/// This trait is implemented by every sampler of this crate.
+///
+pub trait PacedSampler {
+    /// Container in which the sampler stores its samples
+    type Data;
+
+    /// Acquire a new sample of data from the pseudo-file
+    fn sample(&mut self) -> io::Result<()>;
+
+    /// Access the samples that were acquired so far
+    fn samples(&self) -> &Self::Data;
+}
+
+
+/// Default weight of new sampling cost measurements in the moving average
+const DEFAULT_SMOOTHING: f64 = 0.1;
+
+
+/// Controller which picks sampling intervals to meet a CPU overhead budget
+///
+/// The cost of each sample is measured, and an exponentially weighted moving
+/// average (EWMA) of these measurements is kept. This way, a transient stall
+/// (e.g. the sampling thread being preempted) only has a small effect on the
+/// sampling interval, whereas a lasting change in sampling cost will shift it
+/// after a few samples.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pacer {
+    /// Weight of new measurements in the moving average, between 0 and 1
+    smoothing: f64,
+
+    /// Moving average of the sampling cost in seconds, if measured yet
+    mean_cost: Option<f64>,
+}
+//
+impl Pacer {
+    /// Create a new pacer with a default amount of smoothing
+    pub fn new() -> Self {
+        Self::with_smoothing(DEFAULT_SMOOTHING)
+    }
+
+    /// Create a new pacer with a custom amount of smoothing
+    ///
+    /// The smoothing factor is the weight given to each new measurement of the
+    /// sampling cost, between 0 (exclusive) and 1 (inclusive). Smaller values
+    /// make the pacer more robust to transient stalls, but slower to react to
+    /// lasting changes in sampling cost. A value of 1 disables smoothing.
+    ///
+    pub fn with_smoothing(smoothing: f64) -> Self {
+        assert!(smoothing > 0. && smoothing <= 1.,
+                "Smoothing factor should be in the ]0, 1] range");
+        Self {
+            smoothing,
+            mean_cost: None,
+        }
+    }
+
+    /// Record the measured duration of a sample
+    pub fn record_cost(&mut self, cost: Duration) {
+        let cost = Self::secs(cost);
+        self.mean_cost = Some(match self.mean_cost {
+            Some(mean) => mean + self.smoothing * (cost - mean),
+            None => cost,
+        });
+    }
+
+    /// Smoothed sampling cost, if at least one sample was measured
+    pub fn mean_cost(&self) -> Option<Duration> {
+        self.mean_cost.map(Self::duration)
+    }
+
+    /// Suggest how long to sleep before the next sample, so that the fraction
+    /// of time spent sampling stays close to a target overhead budget (e.g.
+    /// 0.001 for 0.1% of a CPU core)
+    ///
+    /// If no sample was measured yet, there is no need to wait.
+    ///
+    pub fn next_interval(&self, budget: f64) -> Duration {
+        assert!(budget > 0. && budget <= 1.,
+                "CPU overhead budget should be in the ]0, 1] range");
+        match self.mean_cost {
+            // Sampling cost / (sampling cost + sleep time) = budget
+            Some(cost) => Self::duration(cost * (1. / budget - 1.)),
+            None => Duration::new(0, 0),
+        }
+    }
+
+    /// Sample repeatedly, with sampling intervals chosen to keep the CPU
+    /// overhead close to a target budget
+    ///
+    /// After each sample, the user-provided closure is called with the sampled
+    /// data, and sampling continues for as long as it returns true. Sampling
+    /// errors interrupt the sampling loop and are reported to the caller.
+    ///
+    pub fn run<S, F>(&mut self,
+                     sampler: &mut S,
+                     budget: f64,
+                     mut f: F) -> io::Result<()>
+        where S: PacedSampler,
+              F: FnMut(&S::Data) -> bool
+    {
+        loop {
+            let start = Instant::now();
+            sampler.sample()?;
+            self.record_cost(start.elapsed());
+            if !f(sampler.samples()) { return Ok(()); }
+            thread::sleep(self.next_interval(budget));
+        }
+    }
+
+    /// INTERNAL: Convert a Duration into a number of seconds
+    fn secs(duration: Duration) -> f64 {
+        duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1e-9
+    }
+
+    /// INTERNAL: Convert a number of seconds into a Duration
+    fn duration(secs: f64) -> Duration {
+        let whole_secs = secs.trunc();
+        Duration::new(whole_secs as u64, ((secs - whole_secs) * 1e9) as u32)
+    }
+}
+//
+impl Default for Pacer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use ::data::SampledData;
+    use ::procfs::uptime;
+    use std::time::Duration;
+    use super::Pacer;
+
+    /// Check that sampling intervals follow the overhead budget
+    #[test]
+    fn next_interval() {
+        let mut pacer = Pacer::new();
+        assert_eq!(pacer.mean_cost(), None);
+        assert_eq!(pacer.next_interval(0.01), Duration::new(0, 0));
+        pacer.record_cost(Duration::from_millis(1));
+        assert_eq!(pacer.mean_cost(), Some(Duration::from_millis(1)));
+        let interval = pacer.next_interval(0.01);
+        assert!(interval > Duration::from_micros(98_999));
+        assert!(interval < Duration::from_micros(99_001));
+        assert_eq!(pacer.next_interval(1.), Duration::new(0, 0));
+    }
+
+    /// Check that a transient stall does not blow up the sampling interval
+    #[test]
+    fn smoothing() {
+        let mut pacer = Pacer::with_smoothing(0.1);
+        pacer.record_cost(Duration::from_millis(1));
+        pacer.record_cost(Duration::from_millis(101));
+        let mean_cost = pacer.mean_cost().unwrap();
+        assert!(mean_cost > Duration::from_micros(10_999));
+        assert!(mean_cost < Duration::from_micros(11_001));
+
+        // Without smoothing, only the last measurement matters
+        let mut pacer = Pacer::with_smoothing(1.);
+        pacer.record_cost(Duration::from_millis(1));
+        pacer.record_cost(Duration::from_millis(101));
+        assert_eq!(pacer.mean_cost(), Some(Duration::from_millis(101)));
+    }
+
+    /// Check that the sampling loop works and can be interrupted
+    #[test]
+    fn sampling_loop() {
+        let mut sampler = uptime::Sampler::new()
+                                          .expect("Failed to create a sampler");
+        let mut pacer = Pacer::new();
+        pacer.run(&mut sampler, 0.5, |data| data.len() < 3)
+             .expect("Failed to sample data");
+        assert_eq!(sampler.samples().len(), 3);
+        assert!(pacer.mean_cost().is_some());
+    }
+}
@@ -17,7 +17,11 @@
 /// All pseudo-file parsers are expected to implement the following trait, which
 /// covers basic initialization. The parsing mechanism itself has several
 /// possible variations, which will be covered by more specialized traits below.
-pub(crate) trait PseudoFileParser {
+///
+/// This trait is part of the public plugin interface (see the "plugin" module),
+/// so user-defined parsers implement it too.
+///
+pub trait PseudoFileParser {
     /// Setup a parser by analyzing a first sample of the file
     fn new(initial_contents: &str) -> Self;
 }
@@ -28,8 +32,16 @@ pub(crate) trait PseudoFileParser {
 /// struct, and returns that struct. For most files, it has a relatively high
 /// overhead, but it can be convenient and fast enough for small files so we
 /// include it for the sake of completeness.
-pub(crate) trait EagerParser : PseudoFileParser {
+///
+/// This is also the parsing mechanism of user-defined parsers, as it is the
+/// only one which can be expressed as a trait in the Rust versions that we
+/// support (see the "plugin" module).
+///
+pub trait EagerParser : PseudoFileParser {
+    /// Parsed contents of the pseudo-file
     type Output;
+
+    /// Parse the full contents of the pseudo-file
     fn parse(&mut self, file_contents: &str) -> Self::Output;
 }
 
@@ -0,0 +1,193 @@
+//! This module allows users to sample pseudo-files which this crate does not
+//! know about
+//!
+//! Every sampler of this crate follows the same pattern: a reader fetches the
+//! text of a pseudo-file, a parser decodes it, and a data container stores the
+//! decoded samples. Users who need to sample some other pseudo-file can plug
+//! their own parser and container into this pattern, by implementing...
+//!
+//! - PseudoFileParser and EagerParser for their parser, which turns the text
+//!   of the pseudo-file into some parsed output type.
+//! - SampledData and SampledData0 for their container, which stores that
+//!   parsed output. For simple files, which contain a single value, Vec<T> can
+//!   be used as a container for any T which implements FromStr.
+//!
+//! ...and then using the generic Sampler of this module, which provides the
+//! same sampling interface as the samplers of this crate.
+//!
+//! The samplers of this crate use a more efficient streaming parser design,
+//! where parsed records borrow from the text of the pseudo-file. This design
+//! cannot be expressed by a trait in the Rust versions that we support, which
+//! is why user-defined parsers must produce owned output.
+
+pub use ::data::{SampledData, SampledData0};
+pub use ::parser::{EagerParser, PseudoFileParser};
+use ::reader::ProcFileReader;
+use std::io;
+use std::path::Path;
+use std::time::Instant;
+
+
+/// Mechanism for sampling measurements from a pseudo-file, using a
+/// user-defined parser and data container
+pub struct Sampler<P, D> {
+    /// Reader object for the pseudo-file
+    reader: ProcFileReader,
+
+    /// Eager parser for the pseudo-file
+    parser: P,
+
+    /// Samples of data extracted from the pseudo-file
+    samples: D,
+
+    /// Time at which each stored sample was acquired
+    timestamps: Vec<Instant>,
+}
+//
+impl<P, D> Sampler<P, D>
+    where P: EagerParser,
+          D: SampledData0<Input=P::Output>
+{
+    /// Create a new sampler for some pseudo-file
+    ///
+    /// As with the samplers of this crate, the parser and data container are
+    /// set up using a first sample of the file, which is not stored.
+    ///
+    pub fn new<L: AsRef<Path>>(file_location: L) -> io::Result<Self> {
+        let mut reader = ProcFileReader::open(file_location)?;
+        let (parser, samples) = reader.sample(|file| {
+            let mut parser = P::new(file);
+            let samples = D::new(parser.parse(file));
+            (parser, samples)
+        })?;
+        Ok(
+            Self {
+                reader,
+                parser,
+                samples,
+                timestamps: Vec::new(),
+            }
+        )
+    }
+
+    /// Acquire a new sample of data from the pseudo-file
+    pub fn sample(&mut self) -> io::Result<()> {
+        let timestamp = Instant::now();
+        {
+            let parser = &mut self.parser;
+            let samples = &mut self.samples;
+            self.reader.sample(|file| samples.push(parser.parse(file)))?;
+        }
+        self.timestamps.push(timestamp);
+        Ok(())
+    }
+
+    /// Access the samples that were acquired so far
+    pub fn samples(&self) -> &D {
+        &self.samples
+    }
+
+    /// Time at which each of the samples was acquired
+    pub fn timestamps(&self) -> &[Instant] {
+        &self.timestamps
+    }
+
+    /// Number of samples that were acquired so far
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Truth that no sample was acquired yet
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+//
+impl<P, D> ::pacer::PacedSampler for Sampler<P, D>
+    where P: EagerParser,
+          D: SampledData0<Input=P::Output>
+{
+    type Data = D;
+
+    fn sample(&mut self) -> io::Result<()> {
+        Sampler::sample(self)
+    }
+
+    fn samples(&self) -> &D {
+        Sampler::samples(self)
+    }
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::{EagerParser, PseudoFileParser, SampledData, SampledData0,
+                Sampler};
+
+    /// User-defined parser, which extracts the uptime from /proc/uptime
+    struct UptimeParser;
+    //
+    impl PseudoFileParser for UptimeParser {
+        fn new(_initial_contents: &str) -> Self { UptimeParser }
+    }
+    //
+    impl EagerParser for UptimeParser {
+        type Output = f64;
+
+        fn parse(&mut self, file_contents: &str) -> f64 {
+            file_contents.split_whitespace()
+                         .next()
+                         .expect("Missing uptime")
+                         .parse()
+                         .expect("Failed to parse uptime")
+        }
+    }
+
+    /// User-defined container, which only keeps the largest sample
+    #[derive(Default)]
+    struct MaxData(Option<f64>, usize);
+    //
+    impl SampledData for MaxData {
+        fn len(&self) -> usize { self.1 }
+        fn drop_oldest(&mut self, count: usize) { self.1 -= count; }
+        fn min_len(&self) -> usize { self.1 }
+        fn truncate(&mut self, length: usize) { self.1 = length; }
+    }
+    //
+    impl SampledData0 for MaxData {
+        type Input = f64;
+
+        fn new(_sample: f64) -> Self { Self::default() }
+
+        fn push(&mut self, sample: f64) {
+            self.0 = Some(self.0.map_or(sample, |max| max.max(sample)));
+            self.1 += 1;
+        }
+    }
+
+    /// Check that user-defined parsers can be used with any container
+    #[test]
+    fn user_defined_parser() {
+        // Using Vec as a container
+        let mut sampler = Sampler::<UptimeParser, Vec<f64>>::new("/proc/uptime")
+                                  .expect("Failed to create a sampler");
+        assert!(sampler.is_empty());
+        sampler.sample().expect("Failed to acquire a sample");
+        sampler.sample().expect("Failed to acquire a sample");
+        assert_eq!(sampler.len(), 2);
+        assert_eq!(sampler.timestamps().len(), 2);
+        assert!(sampler.samples()[0] <= sampler.samples()[1]);
+
+        // Using a user-defined container
+        let mut sampler = Sampler::<UptimeParser, MaxData>::new("/proc/uptime")
+                                  .expect("Failed to create a sampler");
+        sampler.sample().expect("Failed to acquire a sample");
+        assert_eq!(sampler.len(), 1);
+        assert!(sampler.samples().0.expect("Missing sample") > 0.);
+
+        // Missing files are reported as I/O errors
+        assert!(Sampler::<UptimeParser, Vec<f64>>::new("/proc/nonexistent")
+                        .is_err());
+    }
+}
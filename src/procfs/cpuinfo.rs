@@ -0,0 +1,180 @@
+//! This module contains a parser for the CPU topology from /proc/cpuinfo
+//!
+//! Since the CPU topology is not expected to change during a normal performance
+//! measurement, this module is not designed for sampling, unlike others, but
+//! only for a one-time readout that subsequently gets re-used.
+//!
+//! Only the topology-related records of /proc/cpuinfo are parsed for now, as
+//! the other ones are architecture-specific and not needed for analysis.
+
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{Read, Result};
+
+
+/// Location of a hardware CPU thread in the physical CPU topology
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ThreadLocation {
+    /// Identifier of the CPU thread, as used by /proc/stat ("cpuN")
+    pub processor: u32,
+
+    /// Identifier of the physical package (aka socket) hosting the thread
+    pub physical_id: u32,
+
+    /// Identifier of the physical core hosting the thread, which is only
+    /// unique within a given physical package
+    pub core_id: u32,
+}
+
+
+/// Physical topology of the host's CPUs, as described by /proc/cpuinfo
+///
+/// Some architectures and virtual machines do not report their topology in
+/// /proc/cpuinfo. In that case, every thread is assumed to be a separate core
+/// of a single package, which is how the Linux kernel behaves.
+///
+#[derive(Debug, Eq, PartialEq)]
+pub struct CpuTopology {
+    /// Location of each CPU thread, in /proc/cpuinfo order
+    threads: Vec<ThreadLocation>,
+}
+//
+impl CpuTopology {
+    /// Load the CPU topology from /proc/cpuinfo
+    pub fn load() -> Result<Self> {
+        let mut file = File::open("/proc/cpuinfo")?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        Ok(Self::parse(&contents))
+    }
+
+    /// Build a CPU topology by hand, e.g. for analyzing data which was sampled
+    /// on another host
+    pub fn new(threads: Vec<ThreadLocation>) -> Self {
+        Self { threads }
+    }
+
+    /// Location of each CPU thread, in /proc/cpuinfo order
+    pub fn threads(&self) -> &[ThreadLocation] {
+        &self.threads
+    }
+
+    /// Location of a certain CPU thread, if known
+    pub fn thread(&self, processor: u32) -> Option<&ThreadLocation> {
+        self.threads.iter().find(|loc| loc.processor == processor)
+    }
+
+    /// Number of physical packages, computed as the largest package
+    /// identifier plus one so that it can be used to index per-package data
+    pub fn package_count(&self) -> usize {
+        self.threads.iter()
+                    .map(|loc| loc.physical_id as usize + 1)
+                    .max()
+                    .unwrap_or(0)
+    }
+
+    /// Physical cores, identified as (physical_id, core_id) pairs, in
+    /// ascending order
+    pub fn cores(&self) -> Vec<(u32, u32)> {
+        let cores: BTreeSet<(u32, u32)> =
+            self.threads.iter()
+                        .map(|loc| (loc.physical_id, loc.core_id))
+                        .collect();
+        cores.into_iter().collect()
+    }
+
+    /// INTERNAL: Parse the contents of /proc/cpuinfo, which is made of one
+    ///           block of "key : value" lines per CPU thread
+    fn parse(contents: &str) -> Self {
+        let mut threads = Vec::new();
+        for block in contents.split("\n\n") {
+            let mut processor = None;
+            let mut physical_id = None;
+            let mut core_id = None;
+            for line in block.lines() {
+                let mut fields = line.splitn(2, ':');
+                let key = fields.next().expect("Split always yields a field")
+                                .trim();
+                let value = match fields.next() {
+                    Some(value) => value.trim(),
+                    None => continue,
+                };
+                let parse_id = || -> u32 {
+                    value.parse().expect("Failed to parse a CPU identifier")
+                };
+                match key {
+                    "processor" => processor = Some(parse_id()),
+                    "physical id" => physical_id = Some(parse_id()),
+                    "core id" => core_id = Some(parse_id()),
+                    _ => {},
+                }
+            }
+            if let Some(processor) = processor {
+                threads.push(ThreadLocation {
+                    processor,
+                    physical_id: physical_id.unwrap_or(0),
+                    core_id: core_id.unwrap_or(processor),
+                });
+            }
+        }
+        Self { threads }
+    }
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::{CpuTopology, ThreadLocation};
+
+    /// Check that the topology of a hyperthreaded dual-socket host is parsed
+    #[test]
+    fn dual_socket() {
+        let mut contents = String::new();
+        for &(processor, physical_id, core_id) in &[(0, 0, 0), (1, 1, 0),
+                                                   (2, 0, 0), (3, 1, 0),
+                                                   (4, 0, 1), (5, 1, 1)] {
+            contents.push_str(&format!("processor\t: {}\n\
+                                        vendor_id\t: GenuineIntel\n\
+                                        physical id\t: {}\n\
+                                        core id\t\t: {}\n\
+                                        flags\t\t: fpu vme de pse\n\n",
+                                       processor, physical_id, core_id));
+        }
+        let topology = CpuTopology::parse(&contents);
+        assert_eq!(topology.threads().len(), 6);
+        assert_eq!(topology.thread(3),
+                   Some(&ThreadLocation { processor: 3,
+                                          physical_id: 1,
+                                          core_id: 0 }));
+        assert_eq!(topology.thread(6), None);
+        assert_eq!(topology.package_count(), 2);
+        assert_eq!(topology.cores(), vec![(0, 0), (0, 1), (1, 0), (1, 1)]);
+    }
+
+    /// Check that hosts which do not report their topology are handled
+    #[test]
+    fn missing_topology() {
+        let topology = CpuTopology::parse("processor\t: 0\n\
+                                           BogoMIPS\t: 48.00\n\n\
+                                           processor\t: 1\n\
+                                           BogoMIPS\t: 48.00\n\n\
+                                           Hardware\t: BCM2835\n");
+        let location = |processor, core_id| ThreadLocation {
+            processor,
+            physical_id: 0,
+            core_id,
+        };
+        assert_eq!(topology.threads(), &[location(0, 0), location(1, 1)]);
+        assert_eq!(topology.package_count(), 1);
+        assert_eq!(topology.cores(), vec![(0, 0), (0, 1)]);
+    }
+
+    /// Check that the host's topology can be loaded
+    #[test]
+    fn host_topology() {
+        let topology = CpuTopology::load().expect("Failed to load topology");
+        assert!(!topology.threads().is_empty());
+        assert!(topology.package_count() > 0);
+    }
+}
@@ -0,0 +1,1455 @@
+//! This module contains a sampling parser for /proc/diskstats
+//!
+//! Kernel block device names are not always meaningful to humans: on systems
+//! with LVM, LUKS or multipath, most of them are device-mapper devices named
+//! "dm-N". Friendlier names are thus resolved via sysfs when a sampler of the
+//! host's /proc/diskstats is created.
+
+use ::columns::{self, Column, Columns};
+use ::data::{SampledData, SchemaChanged};
+use ::parser::PseudoFileParser;
+use ::procfs::version::LINUX_VERSION;
+use ::splitter::{SplitColumns, SplitLinesBySpace};
+use ::streaming::{StreamingItem, StreamingIterator};
+use bytesize::ByteSize;
+use std::fs::File;
+use std::io::Read;
+use std::time::Duration;
+
+
+// Implement a sampler for /proc/diskstats
+define_sampler!{ Sampler : "/proc/diskstats" => Parser
+                                             => RecordStream
+                                             => Data }
+//
+// The sampler also provides device utilization, which requires the sample
+// timestamps
+impl Sampler {
+    /// Fraction of the time between two samples during which a block device
+    /// was busy doing I/O (this is iostat's "%util" column, divided by 100)
+    ///
+    /// Returns None if there is no such device, if no time elapsed between
+    /// these samples, or if the device's counters were reset in between (see
+    /// SampledStats::reset_indices()). Devices which can serve requests in
+    /// parallel, such as RAIDs and SSDs, can be at 100% utilization without
+    /// being saturated.
+    ///
+    pub fn utilization_between(&self,
+                               device: &str,
+                               older: usize,
+                               newer: usize) -> Option<f64> {
+        assert!(older <= newer, "Samples should be ordered by age");
+        let stats = self.samples.device(device)?;
+        let elapsed = self.timestamps[newer] - self.timestamps[older];
+        let elapsed_ms = elapsed.as_secs() as f64 * 1000.
+                         + elapsed.subsec_nanos() as f64 / 1_000_000.;
+        if elapsed_ms == 0. || stats.reset_between(older, newer) {
+            return None;
+        }
+        let busy_ms = stats.time_io_ms[newer] - stats.time_io_ms[older];
+        Some(busy_ms as f64 / elapsed_ms)
+    }
+}
+
+
+/// Location of the sysfs directories of block devices, which are named after
+/// their device numbers (e.g. "/sys/dev/block/253:0")
+const SYSFS_BLOCK_ROOT: &str = "/sys/dev/block";
+
+
+/// Incremental parser for /proc/diskstats
+#[derive(Debug, PartialEq)]
+pub struct Parser {}
+//
+impl PseudoFileParser for Parser {
+    /// Build a parser, using an initial file sample. Here, this is used to
+    /// perform quick schema validation, just to maximize the odds that failure,
+    /// if any, will occur at initialization time rather than run time.
+    fn new(initial_contents: &str) -> Self {
+        RecordStream::new(initial_contents).for_each(|record| {
+            record.device_numbers();
+            record.parse_statistics();
+        });
+        Self {}
+    }
+}
+//
+// TODO: Implement IncrementalParser once that trait is usable in stable Rust
+impl Parser {
+    /// Parse a pseudo-file sample into a stream of records
+    pub fn parse<'a>(&mut self, file_contents: &'a str) -> RecordStream<'a> {
+        RecordStream::new(file_contents)
+    }
+}
+///
+///
+/// Stream of records from /proc/diskstats
+///
+/// This streaming iterator should yield a stream of records, each representing
+/// a line of /proc/diskstats (i.e. the statistics of one block device).
+///
+pub struct RecordStream<'a> {
+    /// Iterator into the lines and columns of /proc/diskstats
+    file_lines: SplitLinesBySpace<'a>,
+}
+//
+impl<'a, 'b> StreamingItem<'b> for RecordStream<'a> {
+    /// We produce records from /proc/diskstats
+    type Item = Record<'a, 'b>;
+}
+//
+impl<'a> StreamingIterator for RecordStream<'a> {
+    /// Extract the next record from /proc/diskstats
+    fn next<'b>(&'b mut self) -> Option<Record<'a, 'b>> {
+        RecordStream::next(self)
+    }
+}
+//
+impl<'a> RecordStream<'a> {
+    /// Extract the next record from /proc/diskstats
+    pub fn next<'b>(&'b mut self) -> Option<Record<'a, 'b>>
+        where 'a: 'b
+    {
+        self.file_lines.next().map(Record::new)
+    }
+
+    /// Create a record stream from raw contents
+    fn new(file_contents: &'a str) -> Self {
+        Self {
+            file_lines: SplitLinesBySpace::new(file_contents),
+        }
+    }
+}
+///
+///
+/// Record from /proc/diskstats (statistics of one block device)
+pub struct Record<'a, 'b> where 'a: 'b {
+    /// Major device number
+    major_field: &'a str,
+
+    /// Minor device number
+    minor_field: &'a str,
+
+    /// Device name
+    device_name: &'a str,
+
+    /// Data columns of the record
+    data_columns: SplitColumns<'a, 'b>,
+}
+//
+impl<'a, 'b> Record<'a, 'b> {
+    /// Tell which block device this record is about
+    pub fn device_name(&self) -> &'a str {
+        self.device_name
+    }
+
+    /// Parse the major and minor device numbers of the block device
+    pub fn device_numbers(&self) -> (u32, u32) {
+        (self.major_field.parse().expect("Failed to parse major number"),
+         self.minor_field.parse().expect("Failed to parse minor number"))
+    }
+
+    /// Parse the statistics of the block device
+    fn parse_statistics(self) -> Statistics {
+        Statistics::new(self.data_columns)
+    }
+
+    /// Construct a record from associated file columns
+    fn new(mut file_columns: SplitColumns<'a, 'b>) -> Self {
+        let mut next_column = || -> &'a str {
+            file_columns.next().expect("Missing block device identifier")
+        };
+        let major_field = next_column();
+        let minor_field = next_column();
+        let device_name = next_column();
+        Self {
+            major_field,
+            minor_field,
+            device_name,
+            data_columns: file_columns,
+        }
+    }
+}
+
+
+/// Statistics of a block device, as found in /proc/diskstats
+///
+/// Some statistics were added in a certain Linux release and will only be
+/// provided by sufficiently recent kernels:
+///
+/// * Discard statistics (4 columns) appeared in **Linux 4.18+**
+/// * Flush statistics (2 columns) appeared in **Linux 5.5+**
+///
+/// NOTE: Kernels older than Linux 2.6.25 used a shorter 4-column format for
+///       partitions, but since that is incredibly ancient, we propose not to
+///       support it.
+///
+#[derive(Debug, PartialEq)]
+struct Statistics {
+    /// Number of reads completed successfully
+    reads_completed: u64,
+
+    /// Number of adjacent reads which were merged together
+    reads_merged: u64,
+
+    /// Number of 512-byte sectors read successfully
+    sectors_read: u64,
+
+    /// Time spent reading (in milliseconds)
+    time_reading_ms: u64,
+
+    /// Number of writes completed successfully
+    writes_completed: u64,
+
+    /// Number of adjacent writes which were merged together
+    writes_merged: u64,
+
+    /// Number of 512-byte sectors written successfully
+    sectors_written: u64,
+
+    /// Time spent writing (in milliseconds)
+    time_writing_ms: u64,
+
+    /// Number of I/Os currently in progress (this is not a counter)
+    ios_in_progress: u64,
+
+    /// Time spent doing I/Os (in milliseconds)
+    time_io_ms: u64,
+
+    /// Time spent doing I/Os, weighted by the number of I/Os in progress
+    weighted_time_io_ms: u64,
+
+    /// Number of discards completed successfully (since Linux 4.18)
+    discards_completed: Option<u64>,
+
+    /// Number of adjacent discards which were merged (since Linux 4.18)
+    discards_merged: Option<u64>,
+
+    /// Number of 512-byte sectors discarded (since Linux 4.18)
+    sectors_discarded: Option<u64>,
+
+    /// Time spent discarding, in milliseconds (since Linux 4.18)
+    time_discarding_ms: Option<u64>,
+
+    /// Number of flush requests completed successfully (since Linux 5.5)
+    flushes_completed: Option<u64>,
+
+    /// Time spent flushing, in milliseconds (since Linux 5.5)
+    time_flushing_ms: Option<u64>,
+}
+//
+impl Statistics {
+    /// Decode the block device statistics
+    fn new<'a, 'b>(data_columns: SplitColumns<'a, 'b>) -> Self {
+        // Parse all the columns, then check that their amount is one that we
+        // know about: the original format, plus discards, plus flushes.
+        let columns: Vec<u64> = data_columns.map(|column| {
+            column.parse().expect("Failed to parse block device statistic")
+        }).collect();
+        let num_columns = columns.len();
+        assert!(num_columns >= 11, "Some block device statistics are missing");
+        debug_assert!(num_columns <= 17,
+                      "Unknown block device statistics detected");
+        let has_discards = num_columns >= 15;
+        let has_flushes = num_columns >= 17;
+
+        // Decode the statistics
+        let optional = |idx: usize, present: bool| -> Option<u64> {
+            if present { Some(columns[idx]) } else { None }
+        };
+        Self {
+            reads_completed: columns[0],
+            reads_merged: columns[1],
+            sectors_read: columns[2],
+            time_reading_ms: columns[3],
+            writes_completed: columns[4],
+            writes_merged: columns[5],
+            sectors_written: columns[6],
+            time_writing_ms: columns[7],
+            ios_in_progress: columns[8],
+            time_io_ms: columns[9],
+            weighted_time_io_ms: columns[10],
+            discards_completed: optional(11, has_discards),
+            discards_merged: optional(12, has_discards),
+            sectors_discarded: optional(13, has_discards),
+            time_discarding_ms: optional(14, has_discards),
+            flushes_completed: optional(15, has_flushes),
+            time_flushing_ms: optional(16, has_flushes),
+        }
+    }
+
+    /// Tell whether discard statistics are present
+    fn has_discards(&self) -> bool {
+        self.discards_completed.is_some()
+    }
+
+    /// Tell whether flush statistics are present
+    fn has_flushes(&self) -> bool {
+        self.flushes_completed.is_some()
+    }
+
+    /// Extract the raw values of all counters, in the order expected by the
+    /// overflow unwinding cache (see SampledStats). Missing counters are zero.
+    fn counters(&self) -> [u64; NUM_COUNTERS] {
+        let optional = |opt: Option<u64>| opt.unwrap_or(0);
+        [
+            self.reads_completed,
+            self.reads_merged,
+            self.sectors_read,
+            self.time_reading_ms,
+            self.writes_completed,
+            self.writes_merged,
+            self.sectors_written,
+            self.time_writing_ms,
+            self.time_io_ms,
+            self.weighted_time_io_ms,
+            optional(self.discards_completed),
+            optional(self.discards_merged),
+            optional(self.sectors_discarded),
+            optional(self.time_discarding_ms),
+            optional(self.flushes_completed),
+            optional(self.time_flushing_ms),
+        ]
+    }
+}
+///
+///
+/// Number of monotonic counters in a /proc/diskstats record: every statistic
+/// except for the number of I/Os in progress, including optional ones
+const NUM_COUNTERS: usize = 16;
+///
+/// Size of the sectors in which /proc/diskstats counts data volumes, which is
+/// always 512 bytes regardless of the device's actual sector size
+const SECTOR_SIZE: u64 = 512;
+///
+/// Largest counter increase between two samples which we deem plausible when
+/// a counter seemingly decreased, so that a 32-bit overflow may have happened
+/// on a 64-bit host (see SampledStats::wrapped_around)
+const MAX_WRAPPED_INCREASE: u64 = 1 << 31;
+
+
+/// Data samples from /proc/diskstats, in structure-of-array layout
+///
+/// As in other parts of this library, we assume that the set of block devices
+/// will not change over the course of a series of sampling measurements.
+///
+#[derive(Debug, PartialEq)]
+pub struct Data {
+    /// Statistics of every block device, in file order
+    devices: Vec<SampledStats>,
+
+    /// Number of samples that were recorded
+    sample_count: usize,
+}
+//
+impl SampledData for Data {
+    /// Tell how many samples are present in the data store + check consistency
+    fn len(&self) -> usize {
+        debug_assert!(self.devices.iter().all(|device| {
+            device.len() == self.sample_count
+        }));
+        self.sample_count
+    }
+
+    /// Drop the oldest samples from the data store
+    fn drop_oldest(&mut self, count: usize) {
+        assert!(count <= self.sample_count, "Not enough samples to drop");
+        for device in self.devices.iter_mut() {
+            device.drop_oldest(count);
+        }
+        self.sample_count -= count;
+    }
+
+    /// Tell the length of the shortest time series in the data store
+    fn min_len(&self) -> usize {
+        self.devices.iter()
+                    .map(|device| device.min_len())
+                    .fold(self.sample_count, ::std::cmp::min)
+    }
+
+    /// Drop the newest samples from the data store
+    fn truncate(&mut self, length: usize) {
+        assert!(length <= self.sample_count, "Not enough samples to keep");
+        for device in self.devices.iter_mut() {
+            device.truncate(length);
+        }
+        self.sample_count = length;
+    }
+
+    /// Resolve the human-readable names of block devices via sysfs
+    fn load_host_details(&mut self) {
+        self.resolve_friendly_names(Path::new(SYSFS_BLOCK_ROOT));
+    }
+}
+//
+// TODO: Implement SampledDataIncremental once that is usable in stable Rust
+impl Data {
+    /// Statistics of every block device, in the order of /proc/diskstats
+    pub fn devices(&self) -> &[SampledStats] {
+        &self.devices
+    }
+
+    /// Statistics of a specific block device, if it exists
+    pub fn device(&self, name: &str) -> Option<&SampledStats> {
+        self.devices.iter().find(|device| device.name == name)
+    }
+
+    /// Human-readable name of the block device with certain (major, minor)
+    /// device numbers, if it exists (see SampledStats::friendly_name())
+    pub fn friendly_name(&self, device_numbers: (u32, u32)) -> Option<&str> {
+        self.devices.iter()
+                    .find(|device| (device.major, device.minor)
+                                       == device_numbers)
+                    .map(|device| device.friendly_name())
+    }
+
+    /// Create a new block device data store, using a first sample to know
+    /// which devices are present on this system
+    fn new(mut stream: RecordStream) -> Self {
+        let mut devices = Vec::new();
+        while let Some(record) = stream.next() {
+            let name = record.device_name();
+            let (major, minor) = record.device_numbers();
+            let stats = record.parse_statistics();
+            devices.push(SampledStats::new(name, major, minor, &stats));
+        }
+        Self {
+            devices,
+            sample_count: 0,
+        }
+    }
+
+    /// Parse the contents of /proc/diskstats and add a data sample to all
+    /// corresponding entries in the internal data store
+    fn push(&mut self, mut stream: RecordStream) -> Result<(), SchemaChanged> {
+        // Record the statistics of each device, for as long as devices appear
+        // in the same order as in the first sample
+        let mut recorded = 0;
+        for device in self.devices.iter_mut() {
+            match stream.next() {
+                Some(ref record) if record.device_name() != device.name => {
+                    break
+                },
+                Some(record) => device.push(record.parse_statistics()),
+                None => break,
+            }
+            recorded += 1;
+        }
+
+        // If the set of block devices changed, which notably happens when the
+        // kernel hands over an empty readout, roll back the partial sample
+        if recorded < self.devices.len() || stream.next().is_some() {
+            for device in self.devices[..recorded].iter_mut() {
+                device.truncate(self.sample_count);
+            }
+            return Err(SchemaChanged::new("The set of block devices changed"));
+        }
+        self.sample_count += 1;
+        Ok(())
+    }
+
+    /// INTERNAL: Resolve the human-readable names of block devices, given the
+    ///           sysfs directory where they are indexed by device number
+    fn resolve_friendly_names(&mut self, sysfs_root: &Path) {
+        for device in self.devices.iter_mut() {
+            device.friendly_name = resolve_friendly_name(sysfs_root,
+                                                         &device.name,
+                                                         device.major,
+                                                         device.minor);
+        }
+    }
+}
+//
+/// Statistics are exported under the name of the block device, followed by
+/// the name of the statistic (e.g. "sda_sectors_read")
+impl Columns for Data {
+    fn columns(&self) -> Vec<(String, Column)> {
+        let mut columns = Vec::new();
+        for device in self.devices.iter() {
+            columns::extend_prefixed(&mut columns,
+                                     &format!("{}_", device.name),
+                                     device);
+        }
+        columns
+    }
+}
+///
+///
+/// Sampled statistics of one block device
+///
+/// Counters from /proc/diskstats are stored as "unsigned long" by the kernel,
+/// which is only 32-bit wide on 32-bit architectures and can thus overflow
+/// within days on a busy disk. We unwind such overflows so that the counters
+/// stored here increase monotonically. Counters which decrease for another
+/// reason, e.g. because the device was removed and added again, are reset.
+///
+#[derive(Debug, PartialEq)]
+pub struct SampledStats {
+    /// Name of the block device
+    name: String,
+
+    /// Human-readable name of the block device, resolved via sysfs
+    friendly_name: String,
+
+    /// Major device number
+    major: u32,
+
+    /// Minor device number
+    minor: u32,
+
+    /// Number of reads completed successfully
+    reads_completed: Vec<u64>,
+
+    /// Number of adjacent reads which were merged together
+    reads_merged: Vec<u64>,
+
+    /// Number of 512-byte sectors read successfully
+    sectors_read: Vec<u64>,
+
+    /// Time spent reading (in milliseconds)
+    time_reading_ms: Vec<u64>,
+
+    /// Number of writes completed successfully
+    writes_completed: Vec<u64>,
+
+    /// Number of adjacent writes which were merged together
+    writes_merged: Vec<u64>,
+
+    /// Number of 512-byte sectors written successfully
+    sectors_written: Vec<u64>,
+
+    /// Time spent writing (in milliseconds)
+    time_writing_ms: Vec<u64>,
+
+    /// Number of I/Os in progress
+    ios_in_progress: Vec<u64>,
+
+    /// Time spent doing I/Os (in milliseconds)
+    time_io_ms: Vec<u64>,
+
+    /// Time spent doing I/Os, weighted by the number of I/Os in progress
+    weighted_time_io_ms: Vec<u64>,
+
+    /// Number of discards completed successfully (since Linux 4.18)
+    discards_completed: Option<Vec<u64>>,
+
+    /// Number of adjacent discards which were merged (since Linux 4.18)
+    discards_merged: Option<Vec<u64>>,
+
+    /// Number of 512-byte sectors discarded (since Linux 4.18)
+    sectors_discarded: Option<Vec<u64>>,
+
+    /// Time spent discarding, in milliseconds (since Linux 4.18)
+    time_discarding_ms: Option<Vec<u64>>,
+
+    /// Number of flush requests completed successfully (since Linux 5.5)
+    flushes_completed: Option<Vec<u64>>,
+
+    /// Time spent flushing, in milliseconds (since Linux 5.5)
+    time_flushing_ms: Option<Vec<u64>>,
+
+    /// INTERNAL: Raw counter values from the last sample, used to detect
+    ///           counter overflows (see Statistics::counters for the order)
+    last_raw_counters: [u64; NUM_COUNTERS],
+
+    /// INTERNAL: Amount that must be added to each raw counter value in order
+    ///           to unwind the overflows which were observed so far
+    overflow_offsets: [u64; NUM_COUNTERS],
+
+    /// Indices of the samples at which some counters were found to have been
+    /// reset, e.g. because the device was removed and added again
+    reset_indices: Vec<usize>,
+}
+//
+impl SampledStats {
+    /// Name of the block device
+    pub fn name(&self) -> &str { &self.name }
+
+    /// Human-readable name of the block device, as resolved when sampling
+    /// started (e.g. "vg0-root" for the LVM logical volume behind "dm-0")
+    ///
+    /// Only device-mapper devices have a human-readable name. For other
+    /// devices, if the name cannot be resolved, or if the samples do not come
+    /// from this host's /proc/diskstats, this is the same as name().
+    ///
+    pub fn friendly_name(&self) -> &str { &self.friendly_name }
+
+    /// Major device number
+    pub fn major(&self) -> u32 { self.major }
+
+    /// Minor device number
+    pub fn minor(&self) -> u32 { self.minor }
+
+    /// Number of reads completed successfully
+    pub fn reads_completed(&self) -> &[u64] { &self.reads_completed }
+
+    /// Number of adjacent reads which were merged together
+    pub fn reads_merged(&self) -> &[u64] { &self.reads_merged }
+
+    /// Number of 512-byte sectors read successfully
+    pub fn sectors_read(&self) -> &[u64] { &self.sectors_read }
+
+    /// Time spent reading (in milliseconds)
+    pub fn time_reading_ms(&self) -> &[u64] { &self.time_reading_ms }
+
+    /// Number of writes completed successfully
+    pub fn writes_completed(&self) -> &[u64] { &self.writes_completed }
+
+    /// Number of adjacent writes which were merged together
+    pub fn writes_merged(&self) -> &[u64] { &self.writes_merged }
+
+    /// Number of 512-byte sectors written successfully
+    pub fn sectors_written(&self) -> &[u64] { &self.sectors_written }
+
+    /// Time spent writing (in milliseconds)
+    pub fn time_writing_ms(&self) -> &[u64] { &self.time_writing_ms }
+
+    /// Number of I/Os in progress
+    pub fn ios_in_progress(&self) -> &[u64] { &self.ios_in_progress }
+
+    /// Time spent doing I/Os (in milliseconds)
+    pub fn time_io_ms(&self) -> &[u64] { &self.time_io_ms }
+
+    /// Time spent doing I/Os, weighted by the number of I/Os in progress
+    pub fn weighted_time_io_ms(&self) -> &[u64] { &self.weighted_time_io_ms }
+
+    /// Number of discards completed successfully (since Linux 4.18)
+    pub fn discards_completed(&self) -> Option<&[u64]> {
+        self.discards_completed.as_ref().map(|vec| &vec[..])
+    }
+
+    /// Number of adjacent discards which were merged (since Linux 4.18)
+    pub fn discards_merged(&self) -> Option<&[u64]> {
+        self.discards_merged.as_ref().map(|vec| &vec[..])
+    }
+
+    /// Number of 512-byte sectors discarded (since Linux 4.18)
+    pub fn sectors_discarded(&self) -> Option<&[u64]> {
+        self.sectors_discarded.as_ref().map(|vec| &vec[..])
+    }
+
+    /// Time spent discarding, in milliseconds (since Linux 4.18)
+    pub fn time_discarding_ms(&self) -> Option<&[u64]> {
+        self.time_discarding_ms.as_ref().map(|vec| &vec[..])
+    }
+
+    /// Number of flush requests completed successfully (since Linux 5.5)
+    pub fn flushes_completed(&self) -> Option<&[u64]> {
+        self.flushes_completed.as_ref().map(|vec| &vec[..])
+    }
+
+    /// Time spent flushing, in milliseconds (since Linux 5.5)
+    pub fn time_flushing_ms(&self) -> Option<&[u64]> {
+        self.time_flushing_ms.as_ref().map(|vec| &vec[..])
+    }
+
+    /// Indices of the samples at which some counters of this device were
+    /// found to have been reset, with respect to the previous sample
+    pub fn reset_indices(&self) -> &[usize] { &self.reset_indices }
+
+    /// Increase of a counter of this device (such as reads_completed())
+    /// between two sample indices, accounting for counter resets
+    ///
+    /// If the counters were reset between these samples, the counter value
+    /// from the newer sample is returned as the best available estimate of
+    /// the increase.
+    ///
+    pub fn delta(&self, counter: &[u64], older: usize, newer: usize) -> u64 {
+        assert!(older <= newer, "Samples should be ordered by age");
+        if self.reset_between(older, newer) {
+            counter[newer]
+        } else {
+            counter[newer] - counter[older]
+        }
+    }
+
+    /// Average size of the reads completed between two samples (this is the
+    /// read part of iostat's "avgrq-sz" column)
+    ///
+    /// If no read was completed in between, the average size is zero.
+    ///
+    pub fn avg_read_size_between(&self,
+                                 older: usize,
+                                 newer: usize) -> ByteSize {
+        self.avg_size_between(&self.reads_completed,
+                               &self.sectors_read,
+                               older,
+                               newer)
+    }
+
+    /// Average time taken by the reads completed between two samples, from
+    /// queuing to completion (this is iostat's "r_await" column)
+    ///
+    /// If no read was completed in between, the average latency is zero.
+    ///
+    pub fn avg_read_latency_between(&self,
+                                    older: usize,
+                                    newer: usize) -> Duration {
+        self.avg_latency_between(&self.reads_completed,
+                                  &self.time_reading_ms,
+                                  older,
+                                  newer)
+    }
+
+    /// Average size of the writes completed between two samples (this is the
+    /// write part of iostat's "avgrq-sz" column)
+    ///
+    /// If no write was completed in between, the average size is zero.
+    ///
+    pub fn avg_write_size_between(&self,
+                                  older: usize,
+                                  newer: usize) -> ByteSize {
+        self.avg_size_between(&self.writes_completed,
+                               &self.sectors_written,
+                               older,
+                               newer)
+    }
+
+    /// Average time taken by the writes completed between two samples, from
+    /// queuing to completion (this is iostat's "w_await" column)
+    ///
+    /// If no write was completed in between, the average latency is zero.
+    ///
+    pub fn avg_write_latency_between(&self,
+                                     older: usize,
+                                     newer: usize) -> Duration {
+        self.avg_latency_between(&self.writes_completed,
+                                  &self.time_writing_ms,
+                                  older,
+                                  newer)
+    }
+
+    /// INTERNAL: Compute the average size of completed requests
+    fn avg_size_between(&self,
+                        completed: &[u64],
+                        sectors: &[u64],
+                        older: usize,
+                        newer: usize) -> ByteSize {
+        let requests = self.delta(completed, older, newer);
+        if requests == 0 {
+            return ByteSize::b(0);
+        }
+        let bytes = self.delta(sectors, older, newer) * SECTOR_SIZE;
+        ByteSize::b((bytes / requests) as usize)
+    }
+
+    /// INTERNAL: Compute the average latency of completed requests
+    fn avg_latency_between(&self,
+                           completed: &[u64],
+                           time_ms: &[u64],
+                           older: usize,
+                           newer: usize) -> Duration {
+        let requests = self.delta(completed, older, newer);
+        if requests == 0 {
+            return Duration::new(0, 0);
+        }
+        let nanos = self.delta(time_ms, older, newer) * 1_000_000 / requests;
+        Duration::new(nanos / 1_000_000_000, (nanos % 1_000_000_000) as u32)
+    }
+
+    /// INTERNAL: Truth that the counters were reset between two samples
+    fn reset_between(&self, older: usize, newer: usize) -> bool {
+        self.reset_indices.iter().any(|&idx| idx > older && idx <= newer)
+    }
+
+    /// Set up storage for the statistics of a block device, using a first
+    /// sample to know which optional statistics are provided by the kernel
+    fn new(name: &str,
+           major: u32,
+           minor: u32,
+           stats: &Statistics) -> Self {
+        // Check that the optional statistics match the kernel version, if it
+        // is known (otherwise, we trust the file layout)
+        let has_discards = stats.has_discards();
+        let has_flushes = stats.has_flushes();
+        let version_known = !LINUX_VERSION.is_unknown();
+        debug_assert!(!version_known || !has_discards
+                          || LINUX_VERSION.greater_eq(4, 18, 0),
+                      "Discard statistics should only appear in Linux 4.18+");
+        debug_assert!(!version_known || !has_flushes
+                          || LINUX_VERSION.greater_eq(5, 5, 0),
+                      "Flush statistics should only appear in Linux 5.5+");
+
+        // Create the statistics
+        let conditional_vec = |present: bool| -> Option<Vec<u64>> {
+            if present { Some(Vec::new()) } else { None }
+        };
+        Self {
+            name: name.to_owned(),
+            friendly_name: name.to_owned(),
+            major,
+            minor,
+            reads_completed: Vec::new(),
+            reads_merged: Vec::new(),
+            sectors_read: Vec::new(),
+            time_reading_ms: Vec::new(),
+            writes_completed: Vec::new(),
+            writes_merged: Vec::new(),
+            sectors_written: Vec::new(),
+            time_writing_ms: Vec::new(),
+            ios_in_progress: Vec::new(),
+            time_io_ms: Vec::new(),
+            weighted_time_io_ms: Vec::new(),
+            discards_completed: conditional_vec(has_discards),
+            discards_merged: conditional_vec(has_discards),
+            sectors_discarded: conditional_vec(has_discards),
+            time_discarding_ms: conditional_vec(has_discards),
+            flushes_completed: conditional_vec(has_flushes),
+            time_flushing_ms: conditional_vec(has_flushes),
+            last_raw_counters: [0; NUM_COUNTERS],
+            overflow_offsets: [0; NUM_COUNTERS],
+            reset_indices: Vec::new(),
+        }
+    }
+
+    /// Add a sample of block device statistics to the data store
+    fn push(&mut self, stats: Statistics) {
+        // The set of optional statistics should not have changed
+        assert_eq!(stats.has_discards(), self.discards_completed.is_some(),
+                   "Unsupported schema change");
+        assert_eq!(stats.has_flushes(), self.flushes_completed.is_some(),
+                   "Unsupported schema change");
+
+        // Unwind counter overflows, and handle counter resets. This includes
+        // the millisecond timers, which must be unwound before they are
+        // converted to durations (see the Columns implementation).
+        let mut counters = stats.counters();
+        let mut reset = false;
+        for (idx, counter) in counters.iter_mut().enumerate() {
+            let last_raw = self.last_raw_counters[idx];
+            if *counter < last_raw {
+                if Self::wrapped_around(last_raw, *counter) {
+                    self.overflow_offsets[idx] += 1 << 32;
+                } else {
+                    self.overflow_offsets[idx] = 0;
+                    reset = true;
+                }
+            }
+            self.last_raw_counters[idx] = *counter;
+            *counter += self.overflow_offsets[idx];
+        }
+        if reset {
+            self.reset_indices.push(self.reads_completed.len());
+        }
+
+        // Store the unwound counters, in the order of Statistics::counters
+        {
+            let mut series = [Some(&mut self.reads_completed),
+                              Some(&mut self.reads_merged),
+                              Some(&mut self.sectors_read),
+                              Some(&mut self.time_reading_ms),
+                              Some(&mut self.writes_completed),
+                              Some(&mut self.writes_merged),
+                              Some(&mut self.sectors_written),
+                              Some(&mut self.time_writing_ms),
+                              Some(&mut self.time_io_ms),
+                              Some(&mut self.weighted_time_io_ms),
+                              self.discards_completed.as_mut(),
+                              self.discards_merged.as_mut(),
+                              self.sectors_discarded.as_mut(),
+                              self.time_discarding_ms.as_mut(),
+                              self.flushes_completed.as_mut(),
+                              self.time_flushing_ms.as_mut()];
+            for (vec, &counter) in series.iter_mut().zip(counters.iter()) {
+                if let Some(ref mut vec) = *vec {
+                    vec.push(counter);
+                }
+            }
+        }
+
+        // The number of I/Os in progress is not a counter, store it as is
+        self.ios_in_progress.push(stats.ios_in_progress);
+    }
+
+    /// INTERNAL: Truth that a counter which went from one raw value to a
+    ///           lower one wrapped around 32 bits in the meantime
+    ///
+    /// On 32-bit hosts, this is the only way for an "unsigned long" counter to
+    /// decrease without being reset. Elsewhere, the kernel only wraps some
+    /// counters around 32 bits (e.g. older kernels' millisecond timers), so
+    /// we only assume a wraparound if the previous value was close enough to
+    /// u32::MAX for the counter to have increased by a plausible amount.
+    ///
+    fn wrapped_around(last_raw: u64, raw: u64) -> bool {
+        if cfg!(target_pointer_width = "32") {
+            return true;
+        }
+        last_raw < 1 << 32 && (1 << 32) - last_raw + raw <= MAX_WRAPPED_INCREASE
+    }
+
+    /// Tell how many samples are present + check consistency
+    fn len(&self) -> usize {
+        // Check the mandatory statistics
+        let length = self.ios_in_progress.len();
+        debug_assert_eq!(length, self.reads_completed.len());
+        debug_assert_eq!(length, self.weighted_time_io_ms.len());
+
+        // Check the length of the optional statistics for consistency
+        let optional_len = |op: &Option<Vec<u64>>| -> usize {
+            op.as_ref().map_or(length, |vec| vec.len())
+        };
+        debug_assert_eq!(length, optional_len(&self.discards_completed));
+        debug_assert_eq!(length, optional_len(&self.flushes_completed));
+
+        // Return the overall length
+        length
+    }
+
+    /// Drop the oldest samples from the data store
+    fn drop_oldest(&mut self, count: usize) {
+        // Drop the mandatory statistics
+        for vec in [&mut self.reads_completed,
+                    &mut self.reads_merged,
+                    &mut self.sectors_read,
+                    &mut self.time_reading_ms,
+                    &mut self.writes_completed,
+                    &mut self.writes_merged,
+                    &mut self.sectors_written,
+                    &mut self.time_writing_ms,
+                    &mut self.ios_in_progress,
+                    &mut self.time_io_ms,
+                    &mut self.weighted_time_io_ms].iter_mut() {
+            vec.drain(..count);
+        }
+
+        // Drop the optional statistics, if present
+        for opt in [&mut self.discards_completed,
+                    &mut self.discards_merged,
+                    &mut self.sectors_discarded,
+                    &mut self.time_discarding_ms,
+                    &mut self.flushes_completed,
+                    &mut self.time_flushing_ms].iter_mut() {
+            if let Some(ref mut vec) = **opt {
+                vec.drain(..count);
+            }
+        }
+
+        // Shift the counter reset indices accordingly
+        self.reset_indices.retain(|&idx| idx >= count);
+        for idx in self.reset_indices.iter_mut() {
+            *idx -= count;
+        }
+    }
+
+    /// Tell the length of the shortest time series, without checking
+    /// consistency
+    fn min_len(&self) -> usize {
+        let mandatory_len =
+            [&self.reads_completed,
+             &self.reads_merged,
+             &self.sectors_read,
+             &self.time_reading_ms,
+             &self.writes_completed,
+             &self.writes_merged,
+             &self.sectors_written,
+             &self.time_writing_ms,
+             &self.ios_in_progress,
+             &self.time_io_ms,
+             &self.weighted_time_io_ms].iter()
+                                       .map(|vec| vec.len())
+                                       .min()
+                                       .expect("Statistics should exist");
+        [&self.discards_completed,
+         &self.discards_merged,
+         &self.sectors_discarded,
+         &self.time_discarding_ms,
+         &self.flushes_completed,
+         &self.time_flushing_ms].iter()
+                                .filter_map(|opt| opt.as_ref())
+                                .map(|vec| vec.len())
+                                .fold(mandatory_len, ::std::cmp::min)
+    }
+
+    /// Drop the newest samples from the data store
+    fn truncate(&mut self, length: usize) {
+        // Truncate the mandatory statistics
+        for vec in [&mut self.reads_completed,
+                    &mut self.reads_merged,
+                    &mut self.sectors_read,
+                    &mut self.time_reading_ms,
+                    &mut self.writes_completed,
+                    &mut self.writes_merged,
+                    &mut self.sectors_written,
+                    &mut self.time_writing_ms,
+                    &mut self.ios_in_progress,
+                    &mut self.time_io_ms,
+                    &mut self.weighted_time_io_ms].iter_mut() {
+            vec.truncate(length);
+        }
+
+        // Truncate the optional statistics, if present
+        for opt in [&mut self.discards_completed,
+                    &mut self.discards_merged,
+                    &mut self.sectors_discarded,
+                    &mut self.time_discarding_ms,
+                    &mut self.flushes_completed,
+                    &mut self.time_flushing_ms].iter_mut() {
+            if let Some(ref mut vec) = **opt {
+                vec.truncate(length);
+            }
+        }
+
+        // Forget about the counter resets of the dropped samples
+        self.reset_indices.retain(|&idx| idx < length);
+    }
+}
+//
+/// Durations are exported in nanoseconds, and thus lose the "_ms" suffix of
+/// the accessor methods
+impl Columns for SampledStats {
+    fn columns(&self) -> Vec<(String, Column)> {
+        let mut columns = vec![
+            ("reads_completed", Column::counts(&self.reads_completed)),
+            ("reads_merged", Column::counts(&self.reads_merged)),
+            ("sectors_read", Column::counts(&self.sectors_read)),
+            ("time_reading", Column::millis(&self.time_reading_ms)),
+            ("writes_completed", Column::counts(&self.writes_completed)),
+            ("writes_merged", Column::counts(&self.writes_merged)),
+            ("sectors_written", Column::counts(&self.sectors_written)),
+            ("time_writing", Column::millis(&self.time_writing_ms)),
+            ("ios_in_progress", Column::counts(&self.ios_in_progress)),
+            ("time_io", Column::millis(&self.time_io_ms)),
+            ("weighted_time_io", Column::millis(&self.weighted_time_io_ms)),
+        ];
+        let optional = [
+            ("discards_completed", &self.discards_completed, false),
+            ("discards_merged", &self.discards_merged, false),
+            ("sectors_discarded", &self.sectors_discarded, false),
+            ("time_discarding", &self.time_discarding_ms, true),
+            ("flushes_completed", &self.flushes_completed, false),
+            ("time_flushing", &self.time_flushing_ms, true),
+        ];
+        columns.extend(optional.iter().filter_map(|&(name, vec, is_time)| {
+            vec.as_ref().map(|vec| {
+                (name, if is_time { Column::millis(vec) }
+                       else { Column::counts(vec) })
+            })
+        }));
+        columns.into_iter()
+               .map(|(name, column)| (name.to_owned(), column))
+               .collect()
+    }
+}
+
+
+/// INTERNAL: Resolve the human-readable name of a block device, given the
+///           sysfs directory where block devices are indexed by device number
+///
+/// Device-mapper devices expose their name in a "dm/name" file. If there is no
+/// such file or it is empty, we fall back to the kernel's device name.
+///
+fn resolve_friendly_name(sysfs_root: &Path,
+                         name: &str,
+                         major: u32,
+                         minor: u32) -> String {
+    let name_path = sysfs_root.join(format!("{}:{}", major, minor))
+                              .join("dm")
+                              .join("name");
+    let mut dm_name = String::new();
+    match File::open(name_path).and_then(|mut f| f.read_to_string(&mut dm_name))
+    {
+        Ok(_) if !dm_name.trim().is_empty() => dm_name.trim().to_owned(),
+        _ => name.to_owned(),
+    }
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use ::columns::{Column, Columns};
+    use ::data::SampledData;
+    use ::parser::PseudoFileParser;
+    use ::scratch::{scratch_directory, write_file};
+    use bytesize::ByteSize;
+    use std::fs;
+    use std::time::Duration;
+    use super::{Data, Parser, RecordStream, Sampler, Statistics};
+
+    /// Block device statistics in the original 14-column format
+    const LEGACY_FILE: &str =
+        "   8       0 sda 1 2 3 4 5 6 7 8 9 10 11\n\
+            8       1 sda1 12 13 14 15 16 17 18 19 20 21 22\n";
+
+    /// Block device statistics with discards (Linux 4.18+)
+    const DISCARD_FILE: &str =
+        "   8       0 sda 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15\n";
+
+    /// Block device statistics in the modern 20-column format (Linux 5.5+)
+    const MODERN_FILE: &str =
+        " 254       0 vda 6068 4670 2001306 5283 3128 4379 1589816 2840 0 \
+                          2388 8498 1518 0 1211208 372 62 2\n \
+          254       1 vda1 5821 4670 1989338 5185 3128 4379 1589816 2840 0 \
+                           2324 8397 1518 0 1211208 372 0 0\n";
+
+    /// Check that records are parsed correctly
+    #[test]
+    fn record_parsing() {
+        // Check the legacy format
+        let mut stream = RecordStream::new(LEGACY_FILE);
+        {
+            let record = stream.next().expect("Missing record");
+            assert_eq!(record.device_name(), "sda");
+            assert_eq!(record.device_numbers(), (8, 0));
+            assert_eq!(
+                record.parse_statistics(),
+                Statistics {
+                    reads_completed: 1,
+                    reads_merged: 2,
+                    sectors_read: 3,
+                    time_reading_ms: 4,
+                    writes_completed: 5,
+                    writes_merged: 6,
+                    sectors_written: 7,
+                    time_writing_ms: 8,
+                    ios_in_progress: 9,
+                    time_io_ms: 10,
+                    weighted_time_io_ms: 11,
+                    discards_completed: None,
+                    discards_merged: None,
+                    sectors_discarded: None,
+                    time_discarding_ms: None,
+                    flushes_completed: None,
+                    time_flushing_ms: None,
+                }
+            );
+        }
+        {
+            let record = stream.next().expect("Missing record");
+            assert_eq!(record.device_name(), "sda1");
+            assert_eq!(record.device_numbers(), (8, 1));
+        }
+        assert!(stream.next().is_none());
+
+        // Check the format with discard statistics
+        let mut stream = RecordStream::new(DISCARD_FILE);
+        {
+            let stats = stream.next().expect("Missing record")
+                              .parse_statistics();
+            assert_eq!(stats.discards_completed, Some(12));
+            assert_eq!(stats.discards_merged, Some(13));
+            assert_eq!(stats.sectors_discarded, Some(14));
+            assert_eq!(stats.time_discarding_ms, Some(15));
+            assert_eq!(stats.flushes_completed, None);
+            assert_eq!(stats.time_flushing_ms, None);
+        }
+
+        // Check the modern format, with discard and flush statistics
+        let mut stream = RecordStream::new(MODERN_FILE);
+        {
+            let record = stream.next().expect("Missing record");
+            assert_eq!(record.device_name(), "vda");
+            assert_eq!(record.device_numbers(), (254, 0));
+            assert_eq!(
+                record.parse_statistics(),
+                Statistics {
+                    reads_completed: 6068,
+                    reads_merged: 4670,
+                    sectors_read: 2001306,
+                    time_reading_ms: 5283,
+                    writes_completed: 3128,
+                    writes_merged: 4379,
+                    sectors_written: 1589816,
+                    time_writing_ms: 2840,
+                    ios_in_progress: 0,
+                    time_io_ms: 2388,
+                    weighted_time_io_ms: 8498,
+                    discards_completed: Some(1518),
+                    discards_merged: Some(0),
+                    sectors_discarded: Some(1211208),
+                    time_discarding_ms: Some(372),
+                    flushes_completed: Some(62),
+                    time_flushing_ms: Some(2),
+                }
+            );
+        }
+        {
+            let record = stream.next().expect("Missing record");
+            assert_eq!(record.device_name(), "vda1");
+            assert_eq!(record.device_numbers(), (254, 1));
+        }
+        assert!(stream.next().is_none());
+    }
+
+    /// Check that the parser works on all supported formats
+    #[test]
+    fn parser() {
+        for file in [LEGACY_FILE, DISCARD_FILE, MODERN_FILE].iter() {
+            let mut parser = Parser::new(file);
+            let mut stream = parser.parse(file);
+            while let Some(record) = stream.next() {
+                record.parse_statistics();
+            }
+        }
+    }
+
+    /// Check that the sampled data works as expected
+    #[test]
+    fn sampled_data() {
+        // The presence of optional statistics follows the first sample
+        let legacy = Data::new(RecordStream::new(LEGACY_FILE));
+        assert_eq!(legacy.len(), 0);
+        assert_eq!(legacy.devices().len(), 2);
+        let sda = legacy.device("sda").expect("Missing device");
+        assert_eq!((sda.major(), sda.minor()), (8, 0));
+        assert_eq!(sda.discards_completed(), None);
+        assert_eq!(sda.flushes_completed(), None);
+        let modern = Data::new(RecordStream::new(MODERN_FILE));
+        let vda = modern.device("vda").expect("Missing device");
+        assert_eq!(vda.discards_completed(), Some(&[][..]));
+        assert_eq!(vda.flushes_completed(), Some(&[][..]));
+
+        // Pushing samples should fill all the statistics
+        let mut data = Data::new(RecordStream::new(DISCARD_FILE));
+        data.push(RecordStream::new(DISCARD_FILE)).unwrap();
+        assert_eq!(data.len(), 1);
+        {
+            let sda = data.device("sda").expect("Missing device");
+            assert_eq!(sda.reads_completed(), &[1]);
+            assert_eq!(sda.ios_in_progress(), &[9]);
+            assert_eq!(sda.weighted_time_io_ms(), &[11]);
+            assert_eq!(sda.discards_completed(), Some(&[12][..]));
+            assert_eq!(sda.time_discarding_ms(), Some(&[15][..]));
+            assert_eq!(sda.flushes_completed(), None);
+        }
+        data.push(RecordStream::new(
+            "   8       0 sda 2 3 4 5 6 7 8 9 0 11 12 13 14 15 16\n"
+        )).unwrap();
+        assert_eq!(data.len(), 2);
+
+        // Empty readouts are rejected without modifying the store
+        assert!(data.push(RecordStream::new("")).is_err());
+        assert_eq!(data.len(), 2);
+        assert_eq!(data.device("sda").unwrap().reads_completed(), &[1, 2]);
+
+        // Dropping old samples should work as expected
+        data.drop_oldest(1);
+        assert_eq!(data.len(), 1);
+        let sda = data.device("sda").expect("Missing device");
+        assert_eq!(sda.reads_completed(), &[2]);
+        assert_eq!(sda.ios_in_progress(), &[0]);
+        assert_eq!(sda.discards_completed(), Some(&[13][..]));
+    }
+
+    /// Check that 32-bit counter overflows are unwound
+    #[test]
+    fn overflow_unwinding() {
+        let mut data = Data::new(RecordStream::new(MODERN_FILE));
+        data.push(RecordStream::new(
+            "254 0 vda 4294967290 0 0 0 0 0 0 0 3 0 0 0 0 0 0 4294967295 0\n\
+             254 1 vda1 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n"
+        )).unwrap();
+        data.push(RecordStream::new(
+            "254 0 vda 10 0 0 0 0 0 0 0 1 0 0 0 0 0 0 3 0\n\
+             254 1 vda1 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n"
+        )).unwrap();
+        data.push(RecordStream::new(
+            "254 0 vda 20 0 0 0 0 0 0 0 2 0 0 0 0 0 0 5 0\n\
+             254 1 vda1 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n"
+        )).unwrap();
+        let vda = data.device("vda").expect("Missing device");
+        assert_eq!(vda.reads_completed(),
+                   &[4294967290, 4294967306, 4294967316]);
+        assert_eq!(vda.flushes_completed(),
+                   Some(&[4294967295, 4294967299, 4294967301][..]));
+
+        // The number of I/Os in progress is not a counter, and may decrease
+        assert_eq!(vda.ios_in_progress(), &[3, 1, 2]);
+
+        // On 64-bit hosts, counters which decrease from values far from
+        // u32::MAX were reset, e.g. because the device was added again
+        if cfg!(target_pointer_width = "64") {
+            data.push(RecordStream::new(
+                "254 0 vda 5 0 0 0 0 0 0 0 0 0 0 0 0 0 0 1 0\n\
+                 254 1 vda1 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n"
+            )).unwrap();
+            let vda = data.device("vda").expect("Missing device");
+            assert_eq!(vda.reads_completed()[3], 5);
+            assert_eq!(vda.flushes_completed().unwrap()[3], 1);
+        }
+    }
+
+    /// Check that millisecond counters are unwound before being exported as
+    /// durations, so that the exported durations never jump backward
+    #[test]
+    fn duration_overflow_unwinding() {
+        let mut data = Data::new(RecordStream::new(LEGACY_FILE));
+        for &time_reading_ms in &[4294967000u64, 4294967295, 200, 1200] {
+            let sample = format!(
+                "8 0 sda 1 2 3 {} 5 6 7 8 9 10 11
+                 8 1 sda1 12 13 14 15 16 17 18 19 20 21 22
+",
+                time_reading_ms
+            );
+            data.push(RecordStream::new(&sample)).unwrap();
+        }
+        let sda = data.device("sda").expect("Missing device");
+        assert_eq!(sda.time_reading_ms(),
+                   &[4294967000, 4294967295, 4294967496, 4294968496]);
+        let time_reading = sda.columns()
+                              .into_iter()
+                              .find(|&(ref name, _)| name == "time_reading")
+                              .expect("Missing time_reading column");
+        match time_reading.1 {
+            Column::Nanos(nanos) => {
+                assert_eq!(nanos[2] - nanos[1], 201_000_000);
+                assert!(nanos.windows(2).all(|pair| pair[0] < pair[1]));
+            },
+            _ => panic!("Times should be exported as durations"),
+        }
+    }
+
+    /// Check that iostat-like metrics are computed correctly
+    #[test]
+    fn iostat_metrics() {
+        let mut data = Data::new(RecordStream::new(LEGACY_FILE));
+        data.push(RecordStream::new("8 0 sda 10 0 80 30 5 0 40 10 0 100 0
+                                      8 1 sda1 0 0 0 0 0 0 0 0 0 0 0
+")).unwrap();
+        data.push(RecordStream::new("8 0 sda 14 0 112 32 5 0 40 10 0 350 0
+                                      8 1 sda1 0 0 0 0 0 0 0 0 0 0 0
+")).unwrap();
+        let sda = data.device("sda").expect("Missing device");
+
+        // 4 reads of 32 sectors in total, taking 2ms in total
+        assert_eq!(sda.avg_read_size_between(0, 1), ByteSize::kib(4));
+        assert_eq!(sda.avg_read_latency_between(0, 1),
+                   Duration::new(0, 500_000));
+
+        // No write completed in between
+        assert_eq!(sda.avg_write_size_between(0, 1), ByteSize::b(0));
+        assert_eq!(sda.avg_write_latency_between(0, 1), Duration::new(0, 0));
+    }
+
+    /// Check that device utilization is computed from the sample timestamps
+    #[test]
+    fn utilization() {
+        // Replace the samples of a sampler with known data, acquired 500ms
+        // apart from each other, during which sda was busy for 250ms
+        let mut sampler = Sampler::new().expect("Failed to create a sampler");
+        let mut data = Data::new(RecordStream::new(LEGACY_FILE));
+        data.push(RecordStream::new("8 0 sda 10 0 80 30 5 0 40 10 0 100 0
+                                      8 1 sda1 0 0 0 0 0 0 0 0 0 0 0
+")).unwrap();
+        data.push(RecordStream::new("8 0 sda 14 0 112 32 5 0 40 10 0 350 0
+                                      8 1 sda1 0 0 0 0 0 0 0 0 0 0 0
+")).unwrap();
+        let start = ::std::time::Instant::now();
+        sampler.samples = data;
+        sampler.timestamps = vec![start, start + Duration::from_millis(500)];
+
+        // Check the utilization, which is undefined for unknown devices and
+        // when no time elapsed
+        assert_eq!(sampler.utilization_between("sda", 0, 1), Some(0.5));
+        assert_eq!(sampler.utilization_between("sda1", 0, 1), Some(0.));
+        assert_eq!(sampler.utilization_between("sdb", 0, 1), None);
+        assert_eq!(sampler.utilization_between("sda", 1, 1), None);
+    }
+
+    /// Check that counter resets are recorded, and that metrics computed
+    /// across them do not treat them as a negative amount of activity
+    #[test]
+    fn counter_resets() {
+        // On 64-bit hosts, counters which decrease from values far from
+        // u32::MAX were reset (elsewhere, they are assumed to wrap around)
+        if cfg!(target_pointer_width = "32") {
+            return;
+        }
+        let mut sampler = Sampler::new().expect("Failed to create a sampler");
+        let mut data = Data::new(RecordStream::new(LEGACY_FILE));
+        data.push(RecordStream::new("8 0 sda 1000 0 8000 300 5 0 40 10 0 900 0
+                                      8 1 sda1 0 0 0 0 0 0 0 0 0 0 0
+")).unwrap();
+        data.push(RecordStream::new("8 0 sda 4 0 32 2 0 0 0 0 0 100 0
+                                      8 1 sda1 0 0 0 0 0 0 0 0 0 0 0
+")).unwrap();
+        data.push(RecordStream::new("8 0 sda 8 0 64 4 0 0 0 0 0 350 0
+                                      8 1 sda1 0 0 0 0 0 0 0 0 0 0 0
+")).unwrap();
+        {
+            let sda = data.device("sda").expect("Missing device");
+            assert_eq!(sda.reset_indices(), &[1]);
+            assert_eq!(data.device("sda1").unwrap().reset_indices(),
+                       &[] as &[usize]);
+
+            // Across a reset, the newer counter values are the best estimate
+            assert_eq!(sda.delta(sda.reads_completed(), 0, 1), 4);
+            assert_eq!(sda.delta(sda.reads_completed(), 0, 2), 8);
+            assert_eq!(sda.delta(sda.reads_completed(), 1, 2), 4);
+            assert_eq!(sda.avg_read_size_between(0, 1), ByteSize::kib(4));
+            assert_eq!(sda.avg_read_latency_between(0, 2),
+                       Duration::new(0, 500_000));
+            assert_eq!(sda.avg_write_size_between(0, 1), ByteSize::b(0));
+        }
+
+        // Utilization is undefined across a reset
+        let start = ::std::time::Instant::now();
+        sampler.samples = data;
+        sampler.timestamps = vec![start,
+                                  start + Duration::from_millis(500),
+                                  start + Duration::from_millis(1000)];
+        assert_eq!(sampler.utilization_between("sda", 0, 1), None);
+        assert_eq!(sampler.utilization_between("sda", 0, 2), None);
+        assert_eq!(sampler.utilization_between("sda", 1, 2), Some(0.5));
+
+        // Reset indices follow the samples which they refer to
+        sampler.samples.drop_oldest(1);
+        assert_eq!(sampler.samples.device("sda").unwrap().reset_indices(),
+                   &[0]);
+        sampler.samples.truncate(1);
+        assert_eq!(sampler.samples.device("sda").unwrap().reset_indices(),
+                   &[0]);
+    }
+
+    /// Check that friendly device names are resolved correctly
+    #[test]
+    fn friendly_names() {
+        // Device-mapper devices are named after their "dm/name" file
+        let root = scratch_directory("diskstats_friendly_names");
+        fs::create_dir_all(root.join("253:0/dm"))
+           .expect("Failed to create directory");
+        write_file(&root.join("253:0/dm/name"), "vg0-root\n");
+        assert_eq!(super::resolve_friendly_name(&root, "dm-0", 253, 0),
+                   "vg0-root");
+
+        // An empty name or a missing file lead to the kernel's name being used
+        fs::create_dir_all(root.join("253:1/dm"))
+           .expect("Failed to create directory");
+        write_file(&root.join("253:1/dm/name"), "\n");
+        assert_eq!(super::resolve_friendly_name(&root, "dm-1", 253, 1),
+                   "dm-1");
+        assert_eq!(super::resolve_friendly_name(&root, "sda", 8, 0), "sda");
+
+        // Names are not resolved by the data store itself, as the samples
+        // may come from another host, so regular names are used at first
+        let mut data = Data::new(RecordStream::new(LEGACY_FILE));
+        assert_eq!(data.friendly_name((8, 0)), Some("sda"));
+        assert_eq!(data.friendly_name((8, 1)), Some("sda1"));
+        assert_eq!(data.friendly_name((9, 9)), None);
+
+        // Once resolved, device-mapper names are used, and other devices keep
+        // their kernel name
+        fs::create_dir_all(root.join("8:1/dm"))
+           .expect("Failed to create directory");
+        write_file(&root.join("8:1/dm/name"), "not-a-partition\n");
+        data.resolve_friendly_names(&root);
+        assert_eq!(data.friendly_name((8, 0)), Some("sda"));
+        assert_eq!(data.friendly_name((8, 1)), Some("not-a-partition"));
+        fs::remove_dir_all(root).expect("Failed to clean up");
+    }
+
+    /// Check that the sampler works well
+    define_sampler_tests!{ super::Sampler }
+}
+
+
+/// Performance benchmarks
+///
+/// See the lib-wide benchmarks module for details on how to use these.
+///
+#[cfg(test)]
+mod benchmarks {
+    define_sampler_benchs!{ super::Sampler,
+                            "/proc/diskstats",
+                            100_000 }
+}
@@ -0,0 +1,390 @@
+//! This module contains a sampling parser for /proc/filesystems
+//!
+//! Unlike most pseudo-files, /proc/filesystems does not contain counters, but
+//! lists the filesystems that the running kernel supports. This list can change
+//! over time as kernel modules are loaded (e.g. when a FUSE or overlayfs mount
+//! is first attempted), so it is sampled like other files, but stored as a
+//! series of snapshots rather than as a set of time series.
+
+use ::columns::{Column, Columns};
+use ::data::{SampledData, SchemaChanged};
+use ::parser::PseudoFileParser;
+use std::str::Lines;
+
+
+// Implement a sampler for /proc/filesystems
+define_sampler!{ Sampler : "/proc/filesystems" => Parser
+                                               => RecordStream
+                                               => Data }
+
+
+/// Incremental parser for /proc/filesystems
+#[derive(Debug, PartialEq)]
+pub struct Parser {}
+//
+impl PseudoFileParser for Parser {
+    /// Build a parser, using an initial file sample. Here, this is used to
+    /// perform quick schema validation, just to maximize the odds that failure,
+    /// if any, will occur at initialization time rather than run time.
+    fn new(initial_contents: &str) -> Self {
+        let mut validation_stream = RecordStream::new(initial_contents);
+        while let Some(record) = validation_stream.next() {
+            record.supports_devices();
+            record.name();
+        }
+        Self {}
+    }
+}
+//
+// TODO: Implement IncrementalParser once that trait is usable in stable Rust
+impl Parser {
+    /// Parse a pseudo-file sample into a stream of records
+    pub fn parse<'a>(&mut self, file_contents: &'a str) -> RecordStream<'a> {
+        RecordStream::new(file_contents)
+    }
+}
+///
+///
+/// Stream of records from /proc/filesystems
+///
+/// This streaming iterator should yield a stream of records, each representing
+/// a line of /proc/filesystems (i.e. a supported filesystem type).
+///
+pub struct RecordStream<'a> {
+    /// Iterator into the lines of /proc/filesystems
+    file_lines: Lines<'a>,
+
+    /// Truth that the readout was empty
+    empty_readout: bool,
+}
+//
+impl<'a> RecordStream<'a> {
+    /// Extract the next record from /proc/filesystems
+    pub fn next(&mut self) -> Option<Record<'a>> {
+        self.file_lines.next().map(Record::new)
+    }
+
+    /// Create a record stream from raw contents
+    fn new(file_contents: &'a str) -> Self {
+        Self {
+            file_lines: file_contents.lines(),
+            empty_readout: file_contents.is_empty(),
+        }
+    }
+}
+///
+///
+/// Record from /proc/filesystems (one supported filesystem type)
+///
+/// Each line of the file is made of an optional "nodev" marker, followed by a
+/// tab and the filesystem name. We cannot split it by spaces, as the marker
+/// column is empty for filesystems which are backed by a block device.
+///
+pub struct Record<'a> {
+    /// Contents of the optional marker column ("nodev" or empty)
+    marker: &'a str,
+
+    /// Name of the filesystem
+    name: &'a str,
+}
+//
+impl<'a> Record<'a> {
+    /// Truth that this filesystem needs to be backed by a block device (as
+    /// opposed to virtual and network filesystems, which are marked "nodev")
+    pub fn supports_devices(&self) -> bool {
+        match self.marker {
+            "" => true,
+            "nodev" => false,
+            _ => panic!("Unexpected filesystem marker"),
+        }
+    }
+
+    /// Name of the filesystem
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Construct a record from a line of /proc/filesystems
+    fn new(line: &'a str) -> Self {
+        let mut columns = line.split('\t');
+        let marker = columns.next().expect("Missing filesystem marker");
+        let name = columns.next().expect("Missing filesystem name");
+        debug_assert_eq!(columns.next(), None,
+                         "Unexpected column in /proc/filesystems");
+        Self {
+            marker,
+            name,
+        }
+    }
+}
+
+
+/// Data samples from /proc/filesystems
+///
+/// The list of supported filesystems changes very rarely, so a new snapshot of
+/// that list is only stored when it changes, and each sample refers to the
+/// snapshot which was current at the time.
+///
+#[derive(Debug, PartialEq)]
+pub struct Data {
+    /// Distinct snapshots of the filesystem list, in sampling order
+    snapshots: Vec<Vec<Filesystem>>,
+
+    /// Index of the snapshot that applies to each sample
+    sample_snapshots: Vec<usize>,
+}
+//
+impl SampledData for Data {
+    /// Tell how many samples are present in the data store + check consistency
+    fn len(&self) -> usize {
+        let num_snapshots = self.snapshots.len();
+        debug_assert!(self.sample_snapshots.iter()
+                                           .all(|&idx| idx < num_snapshots));
+        self.sample_snapshots.len()
+    }
+
+    /// Drop the oldest samples from the data store
+    fn drop_oldest(&mut self, count: usize) {
+        self.sample_snapshots.drain(..count);
+        let first_used = self.sample_snapshots.first()
+                                              .cloned()
+                                              .unwrap_or(self.snapshots.len());
+        self.snapshots.drain(..first_used);
+        for idx in self.sample_snapshots.iter_mut() {
+            *idx -= first_used;
+        }
+    }
+
+    /// Tell the length of the shortest time series in the data store
+    fn min_len(&self) -> usize {
+        self.sample_snapshots.len()
+    }
+
+    /// Drop the newest samples from the data store
+    fn truncate(&mut self, length: usize) {
+        self.sample_snapshots.truncate(length);
+        let num_used = self.sample_snapshots.last().map_or(0, |&idx| idx + 1);
+        self.snapshots.truncate(num_used);
+    }
+}
+//
+// TODO: Implement SampledDataIncremental once that is usable in stable Rust
+impl Data {
+    /// Filesystems which were supported at the time of a certain sample
+    pub fn filesystems(&self, sample_idx: usize) -> &[Filesystem] {
+        &self.snapshots[self.sample_snapshots[sample_idx]]
+    }
+
+    /// Look up a filesystem by name (e.g. "overlay" or "fuse") in a certain
+    /// sample, returning None if it was not supported at the time
+    pub fn find(&self, sample_idx: usize, name: &str) -> Option<&Filesystem> {
+        self.filesystems(sample_idx).iter().find(|fs| fs.name == name)
+    }
+
+    /// Sample indices at which the list of supported filesystems changed
+    pub fn changes(&self) -> Vec<usize> {
+        (1..self.sample_snapshots.len()).filter(|&idx| {
+            self.sample_snapshots[idx] != self.sample_snapshots[idx-1]
+        }).collect()
+    }
+
+    /// Create a new filesystem list data store
+    fn new(_stream: RecordStream) -> Self {
+        Self {
+            snapshots: Vec::new(),
+            sample_snapshots: Vec::new(),
+        }
+    }
+
+    /// Parse the contents of /proc/filesystems and add a data sample to the
+    /// internal data store
+    ///
+    /// Empty readouts, which may occur when racing with the kernel, are
+    /// rejected, as a running kernel always supports some filesystems.
+    ///
+    fn push(&mut self, mut stream: RecordStream) -> Result<(), SchemaChanged> {
+        if stream.empty_readout {
+            return Err(
+                SchemaChanged::new("The /proc/filesystems readout is empty")
+            );
+        }
+
+        // In the common case, the list did not change since the last snapshot,
+        // and we can check that without allocating anything
+        let mut matching = 0;
+        let mut mismatch = None;
+        if let Some(last_snapshot) = self.snapshots.last() {
+            while let Some(record) = stream.next() {
+                match last_snapshot.get(matching) {
+                    Some(fs) if fs.matches(&record) => matching += 1,
+                    _ => {
+                        mismatch = Some(Filesystem::new(&record));
+                        break;
+                    }
+                }
+            }
+            if mismatch.is_none() && matching == last_snapshot.len() {
+                let last_idx = self.snapshots.len() - 1;
+                self.sample_snapshots.push(last_idx);
+                return Ok(());
+            }
+        }
+
+        // Otherwise, build a new snapshot from the common prefix, the first
+        // mismatching record, and the remaining records
+        let mut snapshot = self.snapshots.last()
+                                         .map(|last| last[..matching].to_vec())
+                                         .unwrap_or_default();
+        snapshot.extend(mismatch);
+        while let Some(record) = stream.next() {
+            snapshot.push(Filesystem::new(&record));
+        }
+        self.sample_snapshots.push(self.snapshots.len());
+        self.snapshots.push(snapshot);
+        Ok(())
+    }
+}
+//
+/// The list of filesystems is not a numerical quantity, so only the number of
+/// supported filesystems is exported, as a "filesystems" column
+impl Columns for Data {
+    fn columns(&self) -> Vec<(String, Column)> {
+        let counts = self.sample_snapshots.iter()
+                         .map(|&idx| self.snapshots[idx].len() as u64)
+                         .collect();
+        vec![("filesystems".to_owned(), Column::U64(counts))]
+    }
+}
+///
+///
+/// Filesystem supported by the kernel, as listed in /proc/filesystems
+#[derive(Clone, Debug, PartialEq)]
+pub struct Filesystem {
+    /// Truth that this filesystem is backed by a block device
+    supports_devices: bool,
+
+    /// Name of the filesystem
+    name: String,
+}
+//
+impl Filesystem {
+    /// Truth that this filesystem needs to be backed by a block device (as
+    /// opposed to virtual and network filesystems, which are marked "nodev")
+    pub fn supports_devices(&self) -> bool {
+        self.supports_devices
+    }
+
+    /// Name of the filesystem
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// INTERNAL: Build a filesystem description from a record
+    fn new(record: &Record) -> Self {
+        Self {
+            supports_devices: record.supports_devices(),
+            name: record.name().to_owned(),
+        }
+    }
+
+    /// INTERNAL: Truth that a record describes this filesystem
+    fn matches(&self, record: &Record) -> bool {
+        self.name == record.name()
+            && self.supports_devices == record.supports_devices()
+    }
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use ::data::SampledData;
+    use super::{Data, Parser, PseudoFileParser, Record, RecordStream};
+
+    /// Check that records are parsed correctly, with and without the marker
+    #[test]
+    fn record_parsing() {
+        let record = Record::new("nodev\tsysfs");
+        assert!(!record.supports_devices());
+        assert_eq!(record.name(), "sysfs");
+        let record = Record::new("\text4");
+        assert!(record.supports_devices());
+        assert_eq!(record.name(), "ext4");
+    }
+
+    /// Check that record streams work as expected
+    #[test]
+    fn record_stream() {
+        let file = "nodev\tproc\n\text4\nnodev\tfuse\n";
+        let mut parser = Parser::new(file);
+        let mut stream = parser.parse(file);
+        assert_eq!(stream.next().map(|r| r.name()), Some("proc"));
+        assert_eq!(stream.next().map(|r| r.name()), Some("ext4"));
+        assert_eq!(stream.next().map(|r| r.name()), Some("fuse"));
+        assert!(stream.next().is_none());
+    }
+
+    /// Check that snapshots are stored correctly, and only when needed
+    #[test]
+    fn sampled_data() {
+        // Build some mock file contents, in which overlayfs appears later
+        let file1 = "nodev\tproc\n\text4\n";
+        let file2 = "nodev\tproc\n\text4\nnodev\toverlay\n";
+
+        // Initialize a data store and check its initial state
+        let mut data = Data::new(RecordStream::new(file1));
+        assert_eq!(data.len(), 0);
+
+        // Push samples, and check that identical ones share a snapshot
+        data.push(RecordStream::new(file1)).unwrap();
+        data.push(RecordStream::new(file1)).unwrap();
+        data.push(RecordStream::new(file2)).unwrap();
+        data.push(RecordStream::new(file2)).unwrap();
+        assert_eq!(data.len(), 4);
+        assert_eq!(data.snapshots.len(), 2);
+        assert_eq!(data.changes(), vec![2]);
+
+        // Empty readouts are rejected instead of being stored as a snapshot
+        assert!(data.push(RecordStream::new("")).is_err());
+        assert_eq!(data.len(), 4);
+        assert_eq!(data.snapshots.len(), 2);
+
+        // Check the recorded filesystem lists
+        assert_eq!(data.filesystems(1).len(), 2);
+        assert_eq!(data.filesystems(3).len(), 3);
+        assert!(data.find(1, "overlay").is_none());
+        let overlay = data.find(3, "overlay").expect("Missing overlayfs");
+        assert!(!overlay.supports_devices());
+        let ext4 = data.find(0, "ext4").expect("Missing ext4");
+        assert!(ext4.supports_devices());
+
+        // Check that dropping old samples also drops unused snapshots
+        data.drop_oldest(2);
+        assert_eq!(data.len(), 2);
+        assert_eq!(data.snapshots.len(), 1);
+        assert_eq!(data.changes(), Vec::<usize>::new());
+        assert_eq!(data.filesystems(0).len(), 3);
+
+        // Check that truncation does the same with newer snapshots
+        data.push(RecordStream::new(file1)).unwrap();
+        assert_eq!(data.snapshots.len(), 2);
+        data.truncate(2);
+        assert_eq!(data.snapshots.len(), 1);
+        assert_eq!(data.len(), 2);
+    }
+
+    /// Check that the sampler works well
+    define_sampler_tests!{ super::Sampler }
+}
+
+
+/// Performance benchmarks
+///
+/// See the lib-wide benchmarks module for details on how to use these.
+///
+#[cfg(test)]
+mod benchmarks {
+    define_sampler_benchs!{ super::Sampler,
+                            "/proc/filesystems",
+                            1_000_000 }
+}
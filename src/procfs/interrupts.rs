@@ -0,0 +1,580 @@
+//! This module contains a sampling parser for /proc/interrupts
+//!
+//! Unlike the "intr" record of /proc/stat, which only counts how many times
+//! each IRQ fired overall, this file tells how many times each IRQ was handled
+//! by each CPU. This is what one needs in order to tell whether interrupts are
+//! well balanced across CPUs, or whether e.g. a network card's IRQs are all
+//! pinned to a single, overloaded core.
+
+use ::columns::{Column, Columns};
+use ::data::{SampledData, SchemaChanged};
+use ::parser::PseudoFileParser;
+use ::splitter::{SplitColumns, SplitLinesBySpace};
+use std::iter::Peekable;
+
+
+// Implement a sampler for /proc/interrupts
+define_sampler!{ Sampler : "/proc/interrupts" => Parser
+                                               => RecordStream
+                                               => Data }
+
+
+/// Incremental parser for /proc/interrupts
+#[derive(Debug, PartialEq)]
+pub struct Parser {}
+//
+impl PseudoFileParser for Parser {
+    /// Build a parser, using an initial file sample. Here, this is used to
+    /// perform quick schema validation, just to maximize the odds that failure,
+    /// if any, will occur at initialization time rather than run time.
+    fn new(initial_contents: &str) -> Self {
+        let mut validation_stream = RecordStream::new(initial_contents);
+        while let Some(record) = validation_stream.next() {
+            record.irq();
+            record.parse_fields();
+        }
+        Self {}
+    }
+}
+//
+// TODO: Implement IncrementalParser once that trait is usable in stable Rust
+impl Parser {
+    /// Parse a pseudo-file sample into a stream of records
+    pub fn parse<'a>(&mut self, file_contents: &'a str) -> RecordStream<'a> {
+        RecordStream::new(file_contents)
+    }
+}
+///
+///
+/// Stream of records from /proc/interrupts
+///
+/// This streaming iterator should yield a stream of records, each representing
+/// a line of /proc/interrupts (i.e. the per-CPU counters of one IRQ).
+///
+pub struct RecordStream<'a> {
+    /// Iterator into the lines and columns of /proc/interrupts
+    file_lines: SplitLinesBySpace<'a>,
+
+    /// Identifiers of the online CPUs, as listed in the table header
+    cpus: Vec<u32>,
+}
+//
+impl<'a> RecordStream<'a> {
+    /// Extract the next record from /proc/interrupts
+    pub fn next<'b>(&'b mut self) -> Option<Record<'a, 'b>>
+        where 'a: 'b
+    {
+        let cpu_count = self.cpus.len();
+        self.file_lines.next().map(|columns| Record::new(columns, cpu_count))
+    }
+
+    /// Identifiers of the online CPUs, in the order of the per-CPU counters
+    pub fn cpus(&self) -> &[u32] {
+        &self.cpus
+    }
+
+    /// Create a record stream from raw contents
+    fn new(file_contents: &'a str) -> Self {
+        // The file starts with a header which lists the online CPUs (e.g.
+        // "CPU0 CPU1"). Empty readouts, which may occur when racing with the
+        // kernel, yield no record at all.
+        let mut file_lines = SplitLinesBySpace::new(file_contents);
+        let cpus = match file_lines.next() {
+            Some(header) => header.map(|cpu| {
+                                      debug_assert!(cpu.starts_with("CPU"),
+                                                    "Unexpected CPU header");
+                                      cpu[3..].parse()
+                                              .expect("Failed to parse CPU ID")
+                                  })
+                                  .collect(),
+            None => Vec::new(),
+        };
+        Self {
+            file_lines,
+            cpus,
+        }
+    }
+}
+///
+///
+/// Record from /proc/interrupts (per-CPU counters of one IRQ)
+pub struct Record<'a, 'b> where 'a: 'b {
+    /// IRQ label field, followed by a colon
+    irq_field: &'a str,
+
+    /// Data columns of the record
+    data_columns: SplitColumns<'a, 'b>,
+
+    /// Number of online CPUs, which bounds the number of counters
+    cpu_count: usize,
+}
+//
+impl<'a, 'b> Record<'a, 'b> {
+    /// Tell which IRQ this record is about (e.g. "0", "NMI" or "LOC")
+    pub fn irq(&self) -> &'a str {
+        // The IRQ field should end with a colon
+        debug_assert_eq!(self.irq_field.bytes().next_back(), Some(b':'),
+                         "Incorrectly formatted IRQ label");
+
+        // The text before that colon is the IRQ label
+        let field_length = self.irq_field.len();
+        assert!(field_length >= 2, "Unexpected empty IRQ label");
+        &self.irq_field[..field_length-1]
+    }
+
+    /// Parse the per-CPU counters and the description of the IRQ
+    fn parse_fields(self) -> RecordFields<'a, 'b> {
+        RecordFields::new(self.data_columns.peekable(), self.cpu_count)
+    }
+
+    /// Construct a record from associated file columns
+    fn new(mut file_columns: SplitColumns<'a, 'b>, cpu_count: usize) -> Self {
+        Self {
+            irq_field: file_columns.next().expect("Missing IRQ label"),
+            data_columns: file_columns,
+            cpu_count,
+        }
+    }
+}
+
+
+/// Per-CPU counters of an IRQ, as found in /proc/interrupts, followed by the
+/// description of the IRQ, which is only decoded on demand
+///
+/// Most IRQs have one counter per online CPU, but some architecture-specific
+/// records only have one global counter (e.g. "ERR" and "MIS" on x86).
+///
+struct RecordFields<'a, 'b> where 'a: 'b {
+    /// Number of times the IRQ was handled by each CPU
+    counts: Vec<u64>,
+
+    /// Remaining columns, which describe the IRQ (e.g. "IO-APIC 1-edge i8042")
+    description_columns: Peekable<SplitColumns<'a, 'b>>,
+}
+//
+impl<'a, 'b> RecordFields<'a, 'b> {
+    /// Decode the per-CPU counters of an IRQ
+    fn new(mut data_columns: Peekable<SplitColumns<'a, 'b>>,
+           cpu_count: usize) -> Self {
+        let mut counts = Vec::with_capacity(cpu_count);
+        while counts.len() < cpu_count {
+            match data_columns.peek().map(|column| column.parse()) {
+                Some(Ok(count)) => counts.push(count),
+                _ => break,
+            }
+            data_columns.next();
+        }
+        assert!(!counts.is_empty(), "Missing IRQ counter");
+        Self {
+            counts,
+            description_columns: data_columns,
+        }
+    }
+
+    /// Decode the description of the IRQ
+    fn description(self) -> String {
+        self.description_columns.collect::<Vec<_>>().join(" ")
+    }
+}
+
+
+/// Data samples from /proc/interrupts, in structure-of-array layout
+///
+/// As with /proc/net/dev, the set of IRQs is assumed to be stable, and so is
+/// the set of online CPUs. If either changes (e.g. due to device or CPU
+/// hotplug), the sampler reports a schema change and no data is stored.
+///
+#[derive(Debug, PartialEq)]
+pub struct Data {
+    /// Identifiers of the online CPUs, in the order of the per-CPU counters
+    cpus: Vec<u32>,
+
+    /// Statistics of every IRQ, in file order
+    irqs: Vec<IrqStats>,
+
+    /// Number of samples that were recorded
+    sample_count: usize,
+}
+//
+impl SampledData for Data {
+    /// Tell how many samples are present in the data store + check consistency
+    fn len(&self) -> usize {
+        debug_assert!(self.irqs.iter().all(|irq| {
+            irq.len() == self.sample_count
+        }));
+        self.sample_count
+    }
+
+    /// Drop the oldest samples from the data store
+    fn drop_oldest(&mut self, count: usize) {
+        assert!(count <= self.sample_count, "Not enough samples to drop");
+        for irq in self.irqs.iter_mut() {
+            irq.drop_oldest(count);
+        }
+        self.sample_count -= count;
+    }
+
+    /// Tell the length of the shortest time series in the data store
+    fn min_len(&self) -> usize {
+        self.irqs.iter()
+                 .map(|irq| irq.min_len())
+                 .fold(self.sample_count, ::std::cmp::min)
+    }
+
+    /// Drop the newest samples from the data store
+    fn truncate(&mut self, length: usize) {
+        assert!(length <= self.sample_count, "Not enough samples to keep");
+        for irq in self.irqs.iter_mut() {
+            irq.truncate(length);
+        }
+        self.sample_count = length;
+    }
+}
+//
+// TODO: Implement SampledDataIncremental once that is usable in stable Rust
+impl Data {
+    /// Identifiers of the online CPUs, in the order of the per-CPU counters
+    pub fn cpus(&self) -> &[u32] {
+        &self.cpus
+    }
+
+    /// Statistics of every IRQ, in the order of /proc/interrupts
+    pub fn irqs(&self) -> &[IrqStats] {
+        &self.irqs
+    }
+
+    /// Statistics of a specific IRQ, if it exists
+    pub fn irq(&self, name: &str) -> Option<&IrqStats> {
+        self.irqs.iter().find(|irq| irq.name == name)
+    }
+
+    /// Fraction of the firings of an IRQ which were handled by the busiest
+    /// CPU, between two sample indices
+    ///
+    /// This is 1.0 when a single CPU handled all firings, as happens when the
+    /// IRQ is pinned to one core, and 1/N when the firings were spread evenly
+    /// across N CPUs. IRQs which only have a global counter are reported as
+    /// 1.0. Returns None if there is no such IRQ, or if it did not fire
+    /// between these samples.
+    ///
+    pub fn affinity_imbalance(&self,
+                              irq: &str,
+                              older: usize,
+                              newer: usize) -> Option<f64> {
+        assert!(older <= newer, "Samples should be ordered by age");
+        let irq = self.irq(irq)?;
+        let deltas = irq.counts.iter().map(|counts| {
+            counts[newer].saturating_sub(counts[older])
+        });
+        let (total, busiest) = deltas.fold((0, 0), |(total, busiest), delta| {
+            (total + delta, busiest.max(delta))
+        });
+        if total > 0 {
+            Some(busiest as f64 / total as f64)
+        } else {
+            None
+        }
+    }
+
+    /// Create a new interrupt data store, using a first sample to know which
+    /// IRQs and CPUs are present on this system
+    fn new(mut stream: RecordStream) -> Self {
+        let mut irqs = Vec::new();
+        while let Some(record) = stream.next() {
+            let name = record.irq();
+            let fields = record.parse_fields();
+            let cpu_count = fields.counts.len();
+            irqs.push(IrqStats::new(name, fields.description(), cpu_count));
+        }
+        Self {
+            cpus: stream.cpus().to_owned(),
+            irqs,
+            sample_count: 0,
+        }
+    }
+
+    /// Parse the contents of /proc/interrupts and add a data sample to all
+    /// corresponding entries in the internal data store
+    fn push(&mut self, mut stream: RecordStream) -> Result<(), SchemaChanged> {
+        // Empty readouts, which may occur when racing with the kernel, have no
+        // header, whereas there is always at least one online CPU
+        if stream.cpus().is_empty() {
+            return Err(
+                SchemaChanged::new("The /proc/interrupts readout is empty")
+            );
+        }
+
+        // CPU hotplug changes the set of per-CPU counters
+        if stream.cpus() != &self.cpus[..] {
+            return Err(SchemaChanged::new("The set of online CPUs changed"));
+        }
+
+        // Record the counters of each IRQ, for as long as IRQs appear in the
+        // same order as in the first sample
+        let mut recorded = 0;
+        for irq in self.irqs.iter_mut() {
+            match stream.next() {
+                Some(ref record) if record.irq() != irq.name => break,
+                Some(record) => irq.push(record.parse_fields().counts),
+                None => break,
+            }
+            recorded += 1;
+        }
+
+        // If the set of IRQs changed, roll back the partial sample
+        if recorded < self.irqs.len() || stream.next().is_some() {
+            for irq in self.irqs[..recorded].iter_mut() {
+                irq.truncate(self.sample_count);
+            }
+            return Err(SchemaChanged::new("The set of IRQs changed"));
+        }
+        self.sample_count += 1;
+        Ok(())
+    }
+}
+//
+/// Counters are exported under the name of the IRQ, followed by the
+/// identifier of the CPU (e.g. "NMI_cpu0"), or by "total" for IRQs which only
+/// have a global counter (e.g. "ERR_total"). On single-CPU hosts, the latter
+/// cannot be told apart from per-CPU counters, and are exported as such.
+impl Columns for Data {
+    fn columns(&self) -> Vec<(String, Column)> {
+        let mut columns = Vec::new();
+        for irq in self.irqs.iter() {
+            if irq.counts.len() == self.cpus.len() {
+                for (cpu, counts) in self.cpus.iter().zip(irq.counts.iter()) {
+                    columns.push((format!("{}_cpu{}", irq.name, cpu),
+                                  Column::counts(counts)));
+                }
+            } else {
+                for counts in irq.counts.iter() {
+                    columns.push((format!("{}_total", irq.name),
+                                  Column::counts(counts)));
+                }
+            }
+        }
+        columns
+    }
+}
+///
+///
+/// Sampled per-CPU counters of one IRQ
+#[derive(Debug, PartialEq)]
+pub struct IrqStats {
+    /// Label of the IRQ
+    name: String,
+
+    /// Description of the IRQ, as of the first sample
+    description: String,
+
+    /// Number of times the IRQ was handled, for each CPU
+    counts: Vec<Vec<u64>>,
+}
+//
+impl IrqStats {
+    /// Label of the IRQ (e.g. "0", "NMI" or "LOC")
+    pub fn name(&self) -> &str { &self.name }
+
+    /// Description of the IRQ (e.g. "IO-APIC 1-edge i8042"), which is empty
+    /// for some architecture-specific records
+    pub fn description(&self) -> &str { &self.description }
+
+    /// Number of times the IRQ was handled by each CPU, in the order of
+    /// Data::cpus(). IRQs which only have a global counter have a single
+    /// time series here.
+    pub fn counts(&self) -> &[Vec<u64>] { &self.counts }
+
+    /// Start tracking the counters of an IRQ
+    fn new(name: &str, description: String, cpu_count: usize) -> Self {
+        Self {
+            name: name.to_owned(),
+            description,
+            counts: vec![Vec::new(); cpu_count],
+        }
+    }
+
+    /// Record the counters of this IRQ for a new sample
+    fn push(&mut self, counts: Vec<u64>) {
+        assert_eq!(counts.len(), self.counts.len(),
+                   "Unexpected number of IRQ counters");
+        for (series, count) in self.counts.iter_mut().zip(counts) {
+            series.push(count);
+        }
+    }
+
+    /// Tell how many samples are present + check consistency
+    fn len(&self) -> usize {
+        let length = self.counts.first().map_or(0, |series| series.len());
+        debug_assert!(self.min_len() == length);
+        length
+    }
+
+    /// Drop the oldest samples
+    fn drop_oldest(&mut self, count: usize) {
+        for series in self.counts.iter_mut() {
+            series.drain(..count);
+        }
+    }
+
+    /// Tell the length of the shortest time series
+    fn min_len(&self) -> usize {
+        self.counts.iter().map(|series| series.len()).min().unwrap_or(0)
+    }
+
+    /// Drop the newest samples
+    fn truncate(&mut self, length: usize) {
+        for series in self.counts.iter_mut() {
+            series.truncate(length);
+        }
+    }
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use ::columns::{Column, Columns};
+    use ::data::SampledData;
+    use ::parser::PseudoFileParser;
+    use super::{Data, Parser, RecordStream};
+
+    /// Build mock file contents for a dual-CPU host, from the counters of the
+    /// timer IRQ (0) and of a network card IRQ (42), and of the error counter
+    fn mock_file(timer: [u64; 2], network: [u64; 2], errors: u64) -> String {
+        format!("           CPU0       CPU1\n  \
+                 0: {:>10} {:>10}   IO-APIC   2-edge      timer\n \
+                 42: {:>10} {:>10}   PCI-MSI 524288-edge      eth0\n\
+                 NMI:          0          0   Non-maskable interrupts\n\
+                 ERR: {:>10}\n",
+                timer[0], timer[1], network[0], network[1], errors)
+    }
+
+    /// Check that record streams parse the header and records as expected
+    #[test]
+    fn record_stream() {
+        let file = mock_file([36, 12], [1000, 0], 3);
+        let mut parser = Parser::new(&file);
+        let mut stream = parser.parse(&file);
+        assert_eq!(stream.cpus(), &[0, 1]);
+        {
+            let record = stream.next().expect("Missing first record");
+            assert_eq!(record.irq(), "0");
+            let fields = record.parse_fields();
+            assert_eq!(fields.counts, vec![36, 12]);
+            assert_eq!(fields.description(), "IO-APIC 2-edge timer");
+        }
+        {
+            let record = stream.next().expect("Missing second record");
+            assert_eq!(record.irq(), "42");
+            let fields = record.parse_fields();
+            assert_eq!(fields.counts, vec![1000, 0]);
+            assert_eq!(fields.description(), "PCI-MSI 524288-edge eth0");
+        }
+        assert_eq!(stream.next().expect("Missing NMI record").irq(), "NMI");
+        {
+            let record = stream.next().expect("Missing ERR record");
+            assert_eq!(record.irq(), "ERR");
+            let fields = record.parse_fields();
+            assert_eq!(fields.counts, vec![3]);
+            assert_eq!(fields.description(), "");
+        }
+        assert!(stream.next().is_none());
+    }
+
+    /// Check that sampled data works as expected, and that changes to the set
+    /// of IRQs or CPUs are reported without storing any data
+    #[test]
+    fn sampled_data() {
+        let file1 = mock_file([36, 12], [1000, 0], 0);
+        let file2 = mock_file([50, 30], [1900, 100], 1);
+        let mut data = Data::new(RecordStream::new(&file1));
+        assert_eq!(data.len(), 0);
+        assert_eq!(data.cpus(), &[0, 1]);
+        assert_eq!(data.irqs().len(), 4);
+        data.push(RecordStream::new(&file1)).unwrap();
+        data.push(RecordStream::new(&file2)).unwrap();
+        assert_eq!(data.len(), 2);
+        let network = data.irq("42").expect("Missing IRQ");
+        assert_eq!(network.name(), "42");
+        assert_eq!(network.description(), "PCI-MSI 524288-edge eth0");
+        assert_eq!(network.counts(), &[vec![1000, 1900], vec![0, 100]]);
+        assert_eq!(data.irq("ERR").unwrap().counts(), &[vec![0, 1]]);
+        assert!(data.irq("LOC").is_none());
+
+        // IRQs which appear or disappear are schema changes
+        let missing = "           CPU0       CPU1\n  \
+                       0:         60         40   IO-APIC   2-edge   timer\n";
+        assert!(data.push(RecordStream::new(missing)).is_err());
+        let added = format!("{}LOC:          5          5   Local timer \
+                             interrupts\n",
+                            mock_file([70, 50], [2000, 200], 1));
+        assert!(data.push(RecordStream::new(&added)).is_err());
+
+        // So is CPU hotplug
+        let offline = "           CPU0\n  \
+                       0:         60   IO-APIC   2-edge      timer\n";
+        assert!(data.push(RecordStream::new(offline)).is_err());
+
+        // Empty readouts are not mistaken for CPU hotplug
+        let empty = data.push(RecordStream::new("")).unwrap_err();
+        assert!(empty.to_string().ends_with("readout is empty"));
+        assert_eq!(data.len(), 2);
+        assert_eq!(data.min_len(), 2);
+        assert_eq!(data.irq("0").unwrap().counts(), &[vec![36, 50],
+                                                      vec![12, 30]]);
+
+        // Counters are exported as columns
+        let columns = data.columns();
+        assert_eq!(columns.len(), 7);
+        assert_eq!(columns[2], ("42_cpu0".to_owned(),
+                                Column::U64(vec![1000, 1900])));
+        assert_eq!(columns[6], ("ERR_total".to_owned(),
+                                Column::U64(vec![0, 1])));
+
+        // Dropping old samples works
+        data.drop_oldest(1);
+        assert_eq!(data.len(), 1);
+        assert_eq!(data.irq("42").unwrap().counts(), &[vec![1900],
+                                                       vec![100]]);
+    }
+
+    /// Check that the affinity imbalance of IRQs is measured properly
+    #[test]
+    fn affinity_imbalance() {
+        let mut data = Data::new(RecordStream::new(&mock_file([0, 0],
+                                                              [0, 0],
+                                                              0)));
+        for &(timer, network) in &[([100, 100], [1000, 0]),
+                                   ([150, 150], [1900, 100]),
+                                   ([200, 200], [1900, 100])] {
+            data.push(RecordStream::new(&mock_file(timer, network, 0)))
+                .unwrap();
+        }
+
+        // The timer IRQ is perfectly balanced across both CPUs
+        assert_eq!(data.affinity_imbalance("0", 0, 1), Some(0.5));
+
+        // The network IRQ is mostly handled by CPU 0
+        assert_eq!(data.affinity_imbalance("42", 0, 1), Some(0.9));
+
+        // IRQs which did not fire and unknown IRQs are not measured
+        assert_eq!(data.affinity_imbalance("42", 1, 2), None);
+        assert_eq!(data.affinity_imbalance("NMI", 0, 2), None);
+        assert_eq!(data.affinity_imbalance("LOC", 0, 2), None);
+    }
+
+    /// Check that the sampler works well
+    define_sampler_tests!{ super::Sampler }
+}
+
+
+/// Performance benchmarks
+///
+/// See the lib-wide benchmarks module for details on how to use these.
+///
+#[cfg(test)]
+mod benchmarks {
+    define_sampler_benchs!{ super::Sampler,
+                            "/proc/interrupts",
+                            100_000 }
+}
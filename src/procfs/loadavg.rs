@@ -0,0 +1,360 @@
+//! This module contains a sampling parser for /proc/loadavg
+//!
+//! The load averages are exponentially decaying averages of the number of
+//! tasks which are either runnable or in uninterruptible sleep (which usually
+//! means waiting for IO), over time windows of 1, 5 and 15 minutes. Because IO
+//! waits are included, they are a poor measure of CPU utilization. But they
+//! are what many dashboards display, and comparing the three averages tells
+//! whether the load of the system is rising or falling.
+//!
+//! The scheduling entity counts and last allocated PID which follow the load
+//! averages in /proc/loadavg are not sampled.
+
+use ::columns::{Column, Columns};
+use ::data::{SampledData, SchemaChanged};
+use ::parser::PseudoFileParser;
+use std::str::SplitWhitespace;
+
+
+// Implement a sampler for /proc/loadavg
+define_sampler!{ Sampler : "/proc/loadavg" => Parser => FieldStream => Data }
+
+
+/// Incremental parser for /proc/loadavg
+#[derive(Debug, PartialEq)]
+pub struct Parser {}
+//
+impl PseudoFileParser for Parser {
+    /// Build a parser, using an initial file sample. Here, this is used to
+    /// perform quick schema validation, just to maximize the odds that failure,
+    /// if any, will occur at initialization time rather than run time.
+    fn new(initial_contents: &str) -> Self {
+        let mut stream = FieldStream::new(initial_contents);
+        for _ in 0..3 {
+            stream.next().expect("Missing load average");
+        }
+        Self {}
+    }
+}
+//
+// TODO: Implement IncrementalParser once that trait is usable in stable Rust
+impl Parser {
+    /// Begin to parse a pseudo-file sample, streaming its data out
+    pub fn parse<'a>(&mut self, file_contents: &'a str) -> FieldStream<'a> {
+        FieldStream::new(file_contents)
+    }
+}
+///
+///
+/// Stream of parsed data from /proc/loadavg
+///
+/// This iterator should successively yield...
+///
+/// * The load average over the last minute
+/// * The load average over the last 5 minutes
+/// * The load average over the last 15 minutes
+/// * A None terminator
+///
+pub struct FieldStream<'a> {
+    /// Extracted columns from /proc/loadavg
+    file_columns: SplitWhitespace<'a>,
+
+    /// Number of load averages which remain to be parsed
+    remaining: usize,
+}
+//
+impl<'a> Iterator for FieldStream<'a> {
+    /// We output load averages
+    type Item = f64;
+
+    /// Parse the next load average from /proc/loadavg
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 { return None; }
+        self.remaining -= 1;
+        self.file_columns.next().map(|column| {
+            column.parse().expect("Failed to parse load average")
+        })
+    }
+}
+//
+impl<'a> FieldStream<'a> {
+    /// Set up a FieldStream for a certain sample of /proc/loadavg
+    fn new(file_contents: &'a str) -> Self {
+        Self {
+            file_columns: file_contents.split_whitespace(),
+            remaining: 3,
+        }
+    }
+}
+
+
+/// Load averages from a single sample of /proc/loadavg
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LoadAverages {
+    /// Load average over the last minute
+    pub one_minute: f64,
+
+    /// Load average over the last 5 minutes
+    pub five_minutes: f64,
+
+    /// Load average over the last 15 minutes
+    pub fifteen_minutes: f64,
+}
+
+
+/// Direction in which the load of the system is evolving
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Trend {
+    /// Shorter-term load averages are higher than longer-term ones
+    Rising,
+
+    /// Shorter-term load averages are lower than longer-term ones
+    Falling,
+
+    /// The load averages are not consistently ordered
+    Steady,
+}
+
+
+/// Data samples from /proc/loadavg, in structure-of-array layout
+#[derive(Debug, PartialEq)]
+pub struct Data {
+    /// Load average over the last minute
+    one_minute: Vec<f64>,
+
+    /// Load average over the last 5 minutes
+    five_minutes: Vec<f64>,
+
+    /// Load average over the last 15 minutes
+    fifteen_minutes: Vec<f64>,
+}
+//
+impl SampledData for Data {
+    /// Tell how many samples are present in the data store + check consistency
+    fn len(&self) -> usize {
+        let length = self.one_minute.len();
+        debug_assert_eq!(length, self.five_minutes.len());
+        debug_assert_eq!(length, self.fifteen_minutes.len());
+        length
+    }
+
+    /// Drop the oldest samples from the data store
+    fn drop_oldest(&mut self, count: usize) {
+        self.one_minute.drain(..count);
+        self.five_minutes.drain(..count);
+        self.fifteen_minutes.drain(..count);
+    }
+
+    /// Tell the length of the shortest time series in the data store
+    fn min_len(&self) -> usize {
+        [self.one_minute.len(),
+         self.five_minutes.len(),
+         self.fifteen_minutes.len()].iter()
+                                    .cloned()
+                                    .min()
+                                    .expect("There should be some time series")
+    }
+
+    /// Drop the newest samples from the data store
+    fn truncate(&mut self, length: usize) {
+        self.one_minute.truncate(length);
+        self.five_minutes.truncate(length);
+        self.fifteen_minutes.truncate(length);
+    }
+}
+//
+// TODO: Implement SampledDataIncremental once that is usable in stable Rust
+impl Data {
+    /// Load average over the last minute
+    pub fn one_minute(&self) -> &[f64] { &self.one_minute }
+
+    /// Load average over the last 5 minutes
+    pub fn five_minutes(&self) -> &[f64] { &self.five_minutes }
+
+    /// Load average over the last 15 minutes
+    pub fn fifteen_minutes(&self) -> &[f64] { &self.fifteen_minutes }
+
+    /// Load averages from the latest sample, if any
+    pub fn latest(&self) -> Option<LoadAverages> {
+        let last = self.len().checked_sub(1)?;
+        Some(
+            LoadAverages {
+                one_minute: self.one_minute[last],
+                five_minutes: self.five_minutes[last],
+                fifteen_minutes: self.fifteen_minutes[last],
+            }
+        )
+    }
+
+    /// Load averages from the latest sample, divided by the number of CPUs
+    /// (hardware threads) of the system, if any sample was acquired
+    ///
+    /// A normalized load of 1 means that there was, on average, one task which
+    /// was running, runnable or waiting for IO per CPU.
+    ///
+    pub fn load_per_cpu(&self, cpu_count: usize) -> Option<LoadAverages> {
+        assert!(cpu_count > 0, "There should be at least one CPU");
+        let cpu_count = cpu_count as f64;
+        self.latest().map(|load| {
+            LoadAverages {
+                one_minute: load.one_minute / cpu_count,
+                five_minutes: load.five_minutes / cpu_count,
+                fifteen_minutes: load.fifteen_minutes / cpu_count,
+            }
+        })
+    }
+
+    /// Classify the evolution of the load, by comparing the load averages of
+    /// the latest sample, if any
+    ///
+    /// The load is deemed to be rising if the 1-minute average is higher than
+    /// the 5-minute average, which is itself higher than the 15-minute one,
+    /// and falling if the opposite holds. Since the kernel already averages
+    /// the load over these time windows, a single sample is enough.
+    ///
+    pub fn load_trend(&self) -> Option<Trend> {
+        self.latest().map(|load| {
+            if load.one_minute > load.five_minutes
+               && load.five_minutes > load.fifteen_minutes
+            {
+                Trend::Rising
+            } else if load.one_minute < load.five_minutes
+                      && load.five_minutes < load.fifteen_minutes
+            {
+                Trend::Falling
+            } else {
+                Trend::Steady
+            }
+        })
+    }
+
+    /// Create a new load average data store
+    fn new(_stream: FieldStream) -> Self {
+        Self {
+            one_minute: Vec::new(),
+            five_minutes: Vec::new(),
+            fifteen_minutes: Vec::new(),
+        }
+    }
+
+    /// Push a new stream of parsed data from /proc/loadavg into the store
+    ///
+    /// Empty readouts, which may occur when racing with the kernel, carry no
+    /// load average and are rejected without modifying the store.
+    ///
+    fn push(&mut self, mut stream: FieldStream) -> Result<(), SchemaChanged> {
+        let mut averages = [0.; 3];
+        for average in averages.iter_mut() {
+            *average = stream.next().ok_or_else(|| {
+                SchemaChanged::new("A load average is missing")
+            })?;
+        }
+        self.one_minute.push(averages[0]);
+        self.five_minutes.push(averages[1]);
+        self.fifteen_minutes.push(averages[2]);
+        Ok(())
+    }
+}
+//
+impl Columns for Data {
+    fn columns(&self) -> Vec<(String, Column)> {
+        vec![("load1".to_owned(), Column::F64(self.one_minute.clone())),
+             ("load5".to_owned(), Column::F64(self.five_minutes.clone())),
+             ("load15".to_owned(), Column::F64(self.fifteen_minutes.clone()))]
+    }
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::{Data, LoadAverages, Parser, PseudoFileParser, SampledData,
+                Sampler, Trend};
+
+    /// Check that parsing load averages works
+    #[test]
+    fn parse_data() {
+        let mut parser = Parser::new("0.52 0.58 0.59 1/467 12345\n");
+        let mut stream = parser.parse("1.50 0.75 0.25 3/470 12399\n");
+        assert_eq!(stream.next(), Some(1.5));
+        assert_eq!(stream.next(), Some(0.75));
+        assert_eq!(stream.next(), Some(0.25));
+        assert_eq!(stream.next(), None);
+    }
+
+    /// Check that sampled data works as expected
+    #[test]
+    fn sampled_data() {
+        let mut parser = Parser::new("0.52 0.58 0.59 1/467 12345\n");
+        let mut data = Data::new(parser.parse("0.52 0.58 0.59 1/467 12345\n"));
+        assert_eq!(data.len(), 0);
+        assert_eq!(data.latest(), None);
+        data.push(parser.parse("0.52 0.58 0.59 1/467 12345\n")).unwrap();
+        data.push(parser.parse("4.00 2.00 1.00 5/470 12399\n")).unwrap();
+        assert_eq!(data.len(), 2);
+        assert_eq!(data.one_minute(), &[0.52, 4.]);
+        assert_eq!(data.five_minutes(), &[0.58, 2.]);
+        assert_eq!(data.fifteen_minutes(), &[0.59, 1.]);
+        assert_eq!(data.latest(), Some(LoadAverages { one_minute: 4.,
+                                                      five_minutes: 2.,
+                                                      fifteen_minutes: 1. }));
+
+        // Empty readouts are rejected without modifying the store
+        assert!(data.push(parser.parse("")).is_err());
+        assert_eq!(data.len(), 2);
+
+        data.drop_oldest(1);
+        assert_eq!(data.len(), 1);
+        assert_eq!(data.one_minute(), &[4.]);
+    }
+
+    /// Check that load averages are normalized by the CPU count
+    #[test]
+    fn load_per_cpu() {
+        let mut parser = Parser::new("8.00 4.00 2.00 9/470 12399\n");
+        let mut data = Data::new(parser.parse("8.00 4.00 2.00 9/470 12399\n"));
+        assert_eq!(data.load_per_cpu(4), None);
+        data.push(parser.parse("8.00 4.00 2.00 9/470 12399\n")).unwrap();
+        assert_eq!(data.load_per_cpu(1), data.latest());
+        assert_eq!(data.load_per_cpu(4),
+                   Some(LoadAverages { one_minute: 2.,
+                                       five_minutes: 1.,
+                                       fifteen_minutes: 0.5 }));
+    }
+
+    /// Check that load trends are classified correctly
+    #[test]
+    fn load_trend() {
+        let trend = |contents: &str| -> Option<Trend> {
+            let mut parser = Parser::new(contents);
+            let mut data = Data::new(parser.parse(contents));
+            data.push(parser.parse(contents)).unwrap();
+            data.load_trend()
+        };
+        assert_eq!(trend("3.00 2.00 1.00 4/470 12399"), Some(Trend::Rising));
+        assert_eq!(trend("1.00 2.00 3.00 1/470 12399"), Some(Trend::Falling));
+        assert_eq!(trend("1.00 1.00 1.00 1/470 12399"), Some(Trend::Steady));
+        assert_eq!(trend("3.00 1.00 2.00 4/470 12399"), Some(Trend::Steady));
+        assert_eq!(trend("1.00 3.00 2.00 1/470 12399"), Some(Trend::Steady));
+
+        // Without any sample, there is no trend
+        let mut parser = Parser::new("1.00 2.00 3.00 1/470 12399");
+        let data = Data::new(parser.parse("1.00 2.00 3.00 1/470 12399"));
+        assert_eq!(data.load_trend(), None);
+    }
+
+    /// Check that the sampler works well
+    define_sampler_tests!{ Sampler }
+}
+
+
+/// Performance benchmarks
+///
+/// See the lib-wide benchmarks module for details on how to use these.
+///
+#[cfg(test)]
+mod benchmarks {
+    define_sampler_benchs!{ super::Sampler,
+                            "/proc/loadavg",
+                            3_000_000 }
+}
@@ -0,0 +1,476 @@
+//! This module contains a sampling parser for /proc/locks
+
+use ::columns::{Column, Columns};
+use ::data::SampledData;
+use ::parser::PseudoFileParser;
+use ::splitter::{SplitColumns, SplitLinesBySpace};
+
+
+// Implement a sampler for /proc/locks
+define_sampler!{ Sampler : "/proc/locks" => Parser => RecordStream => Data }
+
+
+/// Incremental parser for /proc/locks
+#[derive(Debug, PartialEq)]
+pub struct Parser {}
+//
+impl PseudoFileParser for Parser {
+    /// Build a parser, using an initial file sample. Here, this is used to
+    /// perform quick schema validation, just to maximize the odds that failure,
+    /// if any, will occur at initialization time rather than run time.
+    fn new(initial_contents: &str) -> Self {
+        let mut validation_stream = RecordStream::new(initial_contents);
+        while let Some(record) = validation_stream.next() {
+            record.parse_fields();
+        }
+        Self {}
+    }
+}
+//
+// TODO: Implement IncrementalParser once that trait is usable in stable Rust
+impl Parser {
+    /// Parse a pseudo-file sample into a stream of records
+    pub fn parse<'a>(&mut self, file_contents: &'a str) -> RecordStream<'a> {
+        RecordStream::new(file_contents)
+    }
+}
+///
+///
+/// Stream of records from /proc/locks
+///
+/// This streaming iterator should yield a stream of records, each representing
+/// a line of /proc/locks (i.e. an active file lock, or a process blocked
+/// waiting for that lock).
+///
+pub struct RecordStream<'a> {
+    /// Iterator into the lines and columns of /proc/locks
+    file_lines: SplitLinesBySpace<'a>,
+}
+//
+impl<'a> RecordStream<'a> {
+    /// Extract the next record from /proc/locks
+    pub fn next<'b>(&'b mut self) -> Option<Record<'a, 'b>>
+        where 'a: 'b
+    {
+        self.file_lines.next().map(Record::new)
+    }
+
+    /// Create a record stream from raw contents
+    fn new(file_contents: &'a str) -> Self {
+        Self {
+            file_lines: SplitLinesBySpace::new(file_contents),
+        }
+    }
+}
+///
+///
+/// Record from /proc/locks, such as "1: POSIX ADVISORY WRITE 1234 08:01:1234 0
+/// EOF". Processes which are blocked waiting for a lock are listed right after
+/// it, with a "->" marker in front of the lock type.
+pub struct Record<'a, 'b> where 'a: 'b {
+    /// Data columns of the record, after the lock identifier
+    data_columns: SplitColumns<'a, 'b>,
+}
+//
+impl<'a, 'b> Record<'a, 'b> {
+    /// Parse the lock description
+    fn parse_fields(self) -> RecordFields {
+        RecordFields::new(self.data_columns)
+    }
+
+    /// Construct a record from associated file columns
+    fn new(mut file_columns: SplitColumns<'a, 'b>) -> Self {
+        // The lock identifier is a number followed by a colon
+        let lock_id = file_columns.next().expect("Missing lock identifier");
+        debug_assert_eq!(lock_id.bytes().next_back(), Some(b':'),
+                         "Incorrectly formatted lock identifier");
+        Self {
+            data_columns: file_columns,
+        }
+    }
+}
+
+
+/// Description of a file lock from /proc/locks
+#[derive(Debug, PartialEq)]
+struct RecordFields {
+    /// Whether this line describes a process blocked waiting for the lock
+    blocked: bool,
+
+    /// Kind of lock
+    kind: LockKind,
+
+    /// Access mode of the lock
+    mode: LockMode,
+
+    /// Process holding the lock, or -1 for locks which are not owned by a
+    /// process (e.g. open file description locks)
+    pid: i32,
+}
+//
+impl RecordFields {
+    /// Decode the lock description
+    fn new<'a, 'b>(mut data_columns: SplitColumns<'a, 'b>) -> Self {
+        // Check whether this is a blocked waiter
+        let mut kind_column = data_columns.next().expect("Missing lock type");
+        let blocked = kind_column == "->";
+        if blocked {
+            kind_column = data_columns.next().expect("Missing lock type");
+        }
+
+        // Decode the kind of lock
+        let kind = match kind_column {
+            "POSIX" => LockKind::Posix,
+            "FLOCK" => LockKind::Flock,
+            "OFDLCK" => LockKind::OpenFileDescription,
+            _ => LockKind::Other,
+        };
+
+        // Skip the advisory/mandatory (or lease state) column
+        data_columns.next().expect("Missing lock enforcement mode");
+
+        // Decode the access mode of the lock
+        let mode_column = data_columns.next().expect("Missing lock mode");
+        let mode = match mode_column {
+            "READ" => LockMode::Read,
+            "WRITE" => LockMode::Write,
+            _ => LockMode::Other,
+        };
+
+        // Decode the process holding the lock
+        let pid = data_columns.next().expect("Missing lock owner")
+                              .parse().expect("Failed to parse lock owner");
+
+        // The remaining columns (file identifier and locked byte range) are
+        // not of interest to us, so we ignore them
+        Self {
+            blocked,
+            kind,
+            mode,
+            pid,
+        }
+    }
+}
+///
+/// Kinds of file locks
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum LockKind {
+    /// POSIX record lock (fcntl/lockf)
+    Posix,
+
+    /// BSD whole-file lock (flock)
+    Flock,
+
+    /// Open file description lock (since Linux 3.15)
+    OpenFileDescription,
+
+    /// Leases, delegations, or some lock type unknown to this parser
+    Other,
+}
+///
+/// Access modes of file locks
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum LockMode {
+    /// Shared lock
+    Read,
+
+    /// Exclusive lock
+    Write,
+
+    /// Some other mode (e.g. a lease being broken)
+    Other,
+}
+
+
+/// Data samples from /proc/locks, in structure-of-array layout
+///
+/// Rather than tracking every individual lock, which would be costly and could
+/// not be done in a structure-of-array layout as locks come and go, we count
+/// the active locks of each type and mode in every sample.
+///
+#[derive(Debug, PartialEq)]
+pub struct Data {
+    /// Number of POSIX read locks
+    posix_read: Vec<u32>,
+
+    /// Number of POSIX write locks
+    posix_write: Vec<u32>,
+
+    /// Number of flock read locks
+    flock_read: Vec<u32>,
+
+    /// Number of flock write locks
+    flock_write: Vec<u32>,
+
+    /// Number of open file description read locks
+    ofd_read: Vec<u32>,
+
+    /// Number of open file description write locks
+    ofd_write: Vec<u32>,
+
+    /// Number of other locks (leases, delegations, unknown types or modes)
+    other: Vec<u32>,
+
+    /// Number of processes blocked waiting for a lock
+    blocked_waiters: Vec<u32>,
+}
+//
+impl SampledData for Data {
+    /// Tell how many samples are present in the data store + check consistency
+    fn len(&self) -> usize {
+        let length = self.posix_read.len();
+        debug_assert_eq!(length, self.posix_write.len());
+        debug_assert_eq!(length, self.flock_read.len());
+        debug_assert_eq!(length, self.flock_write.len());
+        debug_assert_eq!(length, self.ofd_read.len());
+        debug_assert_eq!(length, self.ofd_write.len());
+        debug_assert_eq!(length, self.other.len());
+        debug_assert_eq!(length, self.blocked_waiters.len());
+        length
+    }
+
+    /// Drop the oldest samples from the data store
+    fn drop_oldest(&mut self, count: usize) {
+        self.posix_read.drain(..count);
+        self.posix_write.drain(..count);
+        self.flock_read.drain(..count);
+        self.flock_write.drain(..count);
+        self.ofd_read.drain(..count);
+        self.ofd_write.drain(..count);
+        self.other.drain(..count);
+        self.blocked_waiters.drain(..count);
+    }
+
+    /// Tell the length of the shortest time series in the data store
+    fn min_len(&self) -> usize {
+        [&self.posix_read, &self.posix_write, &self.flock_read,
+         &self.flock_write, &self.ofd_read, &self.ofd_write, &self.other,
+         &self.blocked_waiters].iter()
+                               .map(|vec| vec.len())
+                               .min()
+                               .expect("There should be some time series")
+    }
+
+    /// Drop the newest samples from the data store
+    fn truncate(&mut self, length: usize) {
+        for vec in [&mut self.posix_read, &mut self.posix_write,
+                    &mut self.flock_read, &mut self.flock_write,
+                    &mut self.ofd_read, &mut self.ofd_write, &mut self.other,
+                    &mut self.blocked_waiters].iter_mut() {
+            vec.truncate(length);
+        }
+    }
+}
+//
+// TODO: Implement SampledDataIncremental once that is usable in stable Rust
+impl Data {
+    /// Number of POSIX read locks
+    pub fn posix_read(&self) -> &[u32] { &self.posix_read }
+
+    /// Number of POSIX write locks
+    pub fn posix_write(&self) -> &[u32] { &self.posix_write }
+
+    /// Number of flock read locks
+    pub fn flock_read(&self) -> &[u32] { &self.flock_read }
+
+    /// Number of flock write locks
+    pub fn flock_write(&self) -> &[u32] { &self.flock_write }
+
+    /// Number of open file description read locks
+    pub fn ofd_read(&self) -> &[u32] { &self.ofd_read }
+
+    /// Number of open file description write locks
+    pub fn ofd_write(&self) -> &[u32] { &self.ofd_write }
+
+    /// Number of other locks (leases, delegations, unknown types or modes)
+    pub fn other(&self) -> &[u32] { &self.other }
+
+    /// Number of processes blocked waiting for a lock
+    pub fn blocked_waiters(&self) -> &[u32] { &self.blocked_waiters }
+
+    /// Create a new lock statistics data store
+    fn new(_stream: RecordStream) -> Self {
+        Self {
+            posix_read: Vec::new(),
+            posix_write: Vec::new(),
+            flock_read: Vec::new(),
+            flock_write: Vec::new(),
+            ofd_read: Vec::new(),
+            ofd_write: Vec::new(),
+            other: Vec::new(),
+            blocked_waiters: Vec::new(),
+        }
+    }
+
+    /// Parse the contents of /proc/locks and add a data sample to the
+    /// internal data store
+    ///
+    /// Unlike other pseudo-files, /proc/locks is legitimately empty when no
+    /// file lock is held, as is common in containers, so empty readouts are
+    /// recorded as such rather than rejected.
+    ///
+    fn push(&mut self, mut stream: RecordStream) {
+        // Count the locks of each type
+        let mut posix_read = 0;
+        let mut posix_write = 0;
+        let mut flock_read = 0;
+        let mut flock_write = 0;
+        let mut ofd_read = 0;
+        let mut ofd_write = 0;
+        let mut other = 0;
+        let mut blocked_waiters = 0;
+        while let Some(record) = stream.next() {
+            let fields = record.parse_fields();
+            if fields.blocked {
+                blocked_waiters += 1;
+                continue;
+            }
+            match (fields.kind, fields.mode) {
+                (LockKind::Posix, LockMode::Read) => posix_read += 1,
+                (LockKind::Posix, LockMode::Write) => posix_write += 1,
+                (LockKind::Flock, LockMode::Read) => flock_read += 1,
+                (LockKind::Flock, LockMode::Write) => flock_write += 1,
+                (LockKind::OpenFileDescription, LockMode::Read) => {
+                    ofd_read += 1
+                },
+                (LockKind::OpenFileDescription, LockMode::Write) => {
+                    ofd_write += 1
+                },
+                _ => other += 1,
+            }
+        }
+
+        // Record the lock counts
+        self.posix_read.push(posix_read);
+        self.posix_write.push(posix_write);
+        self.flock_read.push(flock_read);
+        self.flock_write.push(flock_write);
+        self.ofd_read.push(ofd_read);
+        self.ofd_write.push(ofd_write);
+        self.other.push(other);
+        self.blocked_waiters.push(blocked_waiters);
+    }
+}
+//
+impl Columns for Data {
+    fn columns(&self) -> Vec<(String, Column)> {
+        [("posix_read", &self.posix_read),
+         ("posix_write", &self.posix_write),
+         ("flock_read", &self.flock_read),
+         ("flock_write", &self.flock_write),
+         ("ofd_read", &self.ofd_read),
+         ("ofd_write", &self.ofd_write),
+         ("other", &self.other),
+         ("blocked_waiters", &self.blocked_waiters)]
+            .iter()
+            .map(|&(name, vec)| (name.to_owned(), Column::counts(vec)))
+            .collect()
+    }
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use ::splitter::split_line_and_run;
+    use super::{Data, LockKind, LockMode, Parser, PseudoFileParser, Record,
+                RecordFields, SampledData};
+
+    /// Check that lock descriptions are parsed properly
+    #[test]
+    fn record_fields() {
+        with_record_fields("1: POSIX  ADVISORY  WRITE 1234 08:01:1234 0 EOF",
+                           |fields| {
+            assert_eq!(fields, RecordFields {
+                blocked: false,
+                kind: LockKind::Posix,
+                mode: LockMode::Write,
+                pid: 1234,
+            });
+        });
+        with_record_fields("1: -> POSIX  ADVISORY  WRITE 1235 08:01:1234 0 EOF",
+                           |fields| {
+            assert_eq!(fields, RecordFields {
+                blocked: true,
+                kind: LockKind::Posix,
+                mode: LockMode::Write,
+                pid: 1235,
+            });
+        });
+        with_record_fields("3: OFDLCK ADVISORY  READ  -1 00:06:9337 128 128",
+                           |fields| {
+            assert_eq!(fields, RecordFields {
+                blocked: false,
+                kind: LockKind::OpenFileDescription,
+                mode: LockMode::Read,
+                pid: -1,
+            });
+        });
+        with_record_fields("4: LEASE  BREAKING  UNLCK 42 08:01:666 0 EOF",
+                           |fields| {
+            assert_eq!(fields.kind, LockKind::Other);
+            assert_eq!(fields.mode, LockMode::Other);
+        });
+    }
+
+    /// Check that lock counts are sampled properly
+    #[test]
+    fn sampled_data() {
+        // Build some mock file contents
+        let file1 = "1: POSIX  ADVISORY  WRITE 1234 08:01:1234 0 EOF\n\
+                     1: -> POSIX  ADVISORY  WRITE 1235 08:01:1234 0 EOF\n\
+                     1: -> POSIX  ADVISORY  WRITE 1236 08:01:1234 0 EOF\n\
+                     2: FLOCK  ADVISORY  WRITE 456 00:13:789 0 EOF\n\
+                     3: OFDLCK ADVISORY  READ  -1 00:06:9337 128 128\n\
+                     4: POSIX  ADVISORY  READ  42 08:01:666 0 EOF\n\
+                     5: POSIX  ADVISORY  READ  43 08:01:667 0 EOF\n\
+                     6: LEASE  ACTIVE    READ  44 08:01:668 0 EOF\n";
+        let file2 = "";
+
+        // Check that the data store works as expected
+        let mut parser = Parser::new(file1);
+        let mut data = Data::new(parser.parse(file1));
+        assert_eq!(data.len(), 0);
+        data.push(parser.parse(file1));
+        data.push(parser.parse(file2));
+        assert_eq!(data.len(), 2);
+        assert_eq!(data.posix_read(), &[2, 0]);
+        assert_eq!(data.posix_write(), &[1, 0]);
+        assert_eq!(data.flock_read(), &[0, 0]);
+        assert_eq!(data.flock_write(), &[1, 0]);
+        assert_eq!(data.ofd_read(), &[1, 0]);
+        assert_eq!(data.ofd_write(), &[0, 0]);
+        assert_eq!(data.other(), &[1, 0]);
+        assert_eq!(data.blocked_waiters(), &[2, 0]);
+
+        // Check that dropping old samples works
+        data.drop_oldest(1);
+        assert_eq!(data.len(), 1);
+        assert_eq!(data.posix_read(), &[0]);
+    }
+
+    /// Build the lock description associated with a certain line of text, and
+    /// run code taking that as a parameter
+    fn with_record_fields<F, R>(line_of_text: &str, functor: F) -> R
+        where F: FnOnce(RecordFields) -> R
+    {
+        split_line_and_run(line_of_text, |columns| {
+            functor(Record::new(columns).parse_fields())
+        })
+    }
+
+    /// Check that the sampler works well
+    define_sampler_tests!{ super::Sampler }
+}
+
+
+/// Performance benchmarks
+///
+/// See the lib-wide benchmarks module for details on how to use these.
+///
+#[cfg(test)]
+mod benchmarks {
+    define_sampler_benchs!{ super::Sampler,
+                            "/proc/locks",
+                            1_000_000 }
+}
@@ -1,12 +1,32 @@
 //! This module contains a sampling parser for /proc/meminfo
 
-use ::data::SampledData;
+#[cfg(feature = "binary")]
+use ::binary::{self, BinaryValue};
+use ::columns::{Column, Columns};
+use ::data::{argmax, SampledData, SchemaChanged};
+use ::intern::intern;
 use ::parser::PseudoFileParser;
 use ::splitter::{SplitColumns, SplitLinesBySpace};
+use ::streaming::{StreamingItem, StreamingIterator};
 use bytesize::ByteSize;
+#[cfg(feature = "binary")]
+use std::io::{Read, Write};
+use std::sync::Arc;
 
 // Implement a sampler for /proc/meminfo
-define_sampler!{ Sampler : "/proc/meminfo" => Parser => Data }
+define_sampler!{ Sampler : "/proc/meminfo" => Parser => RecordStream => Data }
+
+
+/// Data volumes which are not bounded by the amount of physical RAM, because
+/// they describe swap space or virtual address space (see unit_anomalies())
+const UNBOUNDED_DATA_VOLUMES: [&str; 8] = ["SwapTotal", "SwapFree", "Zswapped",
+                                           "CommitLimit", "Committed_AS",
+                                           "VmallocTotal", "VmallocUsed",
+                                           "VmallocChunk"];
+
+/// Prefix of the data volumes describing the page table mappings of physical
+/// memory, which also cover memory that the kernel does not count in MemTotal
+const DIRECT_MAP_PREFIX: &str = "DirectMap";
 
 
 /// Incremental parser for /proc/meminfo
@@ -18,13 +38,12 @@ impl PseudoFileParser for Parser {
     /// perform quick schema validation, just to maximize the odds that failure,
     /// if any, will occur at initialization time rather than run time.
     fn new(initial_contents: &str) -> Self {
-        let mut validation_stream = RecordStream::new(initial_contents);
-        while let Some(record) = validation_stream.next() {
+        RecordStream::new(initial_contents).for_each(|record| {
             let label = record.label();
             let payload = record.extract_payload();
             debug_assert!(payload.kind() != PayloadKind::Unsupported,
                           "Missing support for record {}", label);
-        }
+        });
         Self {}
     }
 }
@@ -48,6 +67,18 @@ pub struct RecordStream<'a> {
     file_lines: SplitLinesBySpace<'a>,
 }
 //
+impl<'a, 'b> StreamingItem<'b> for RecordStream<'a> {
+    /// We produce records from /proc/meminfo
+    type Item = Record<'a, 'b>;
+}
+//
+impl<'a> StreamingIterator for RecordStream<'a> {
+    /// Parse the next record from /proc/meminfo into a stream of fields
+    fn next<'b>(&'b mut self) -> Option<Record<'a, 'b>> {
+        RecordStream::next(self)
+    }
+}
+//
 impl<'a> RecordStream<'a> {
     /// Parse the next record from /proc/meminfo into a stream of fields
     pub fn next<'b>(&'b mut self) -> Option<Record<'a, 'b>>
@@ -76,6 +107,10 @@ pub struct Record<'a, 'b> where 'a: 'b {
 //
 impl<'a, 'b> Record<'a, 'b> {
     /// Tell how this record is labeled
+    ///
+    /// Some labels feature parentheses, as in "Active(anon):". Only the final
+    /// colon is stripped, so these are reported as e.g. "Active(anon)".
+    ///
     pub fn label(&self) -> &'a str {
         // The label field of a meminfo record should end with a colon
         debug_assert_eq!(self.label_field.bytes().next_back(), Some(b':'),
@@ -128,7 +163,8 @@ impl<'a> Payload<'a> {
         // In debug mode, validate that we are indeed on a data volume
         debug_assert_eq!(self.kind(), PayloadKind::DataVolume);
 
-        // Parse data volume, which is in kibibytes (no matter what Linux says)
+        // Parse data volume, which is in kibibytes (no matter what Linux says).
+        // Data::unit_anomalies() can be used to cross-check this assumption.
         ByteSize::kib(self.amount as usize)
     }
 
@@ -142,7 +178,7 @@ impl<'a> Payload<'a> {
     }
 
     /// Construct a payload from associated file columns
-    fn new<'b>(mut payload_columns: SplitColumns<'a, 'b>) -> Self {
+    pub(crate) fn new<'b>(mut payload_columns: SplitColumns<'a, 'b>) -> Self {
         let amount = payload_columns.next().expect("Missing amount field")
                                     .parse().expect("Expected a number");
         Self {
@@ -165,6 +201,24 @@ pub enum PayloadKind {
 }
 
 
+/// Data volume from /proc/meminfo whose magnitude is implausible, which
+/// suggests that its unit was misinterpreted (see Data::unit_anomalies())
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnitAnomaly {
+    /// Key of the offending record (e.g. "Hugepagesize")
+    pub key: String,
+
+    /// Index of the first sample where the data volume was implausible
+    pub first_sample: usize,
+
+    /// Data volume at that sample, as interpreted by this parser
+    pub amount: ByteSize,
+
+    /// Total amount of RAM at that sample, as reported by MemTotal
+    pub mem_total: ByteSize,
+}
+
+
 /// Data samples from /proc/meminfo, in structure-of-array layout
 ///
 /// As /proc/meminfo is just a (large) set of named data volumes with a few
@@ -181,8 +235,9 @@ pub struct Data {
     /// Sampled meminfo payloads, in the order in which it appears in the file
     data: Vec<SampledPayloads>,
 
-    /// Keys associated with each record, again in file order
-    keys: Vec<String>,
+    /// Keys associated with each record, again in file order. These are
+    /// interned, as every meminfo sampler features the same keys.
+    keys: Vec<Arc<str>>,
 }
 //
 impl SampledData for Data {
@@ -197,18 +252,337 @@ impl SampledData for Data {
         // Return the number of samples in the data store
         length
     }
+
+    /// Drop the oldest samples from the data store
+    fn drop_oldest(&mut self, count: usize) {
+        for rec in self.data.iter_mut() {
+            rec.drop_oldest(count);
+        }
+    }
+
+    /// Tell the length of the shortest record in the data store
+    fn min_len(&self) -> usize {
+        self.data.iter().map(|rec| rec.len()).min().unwrap_or(0)
+    }
+
+    /// Drop the newest samples from the data store
+    fn truncate(&mut self, length: usize) {
+        for rec in self.data.iter_mut() {
+            rec.truncate(length);
+        }
+    }
 }
 //
 // TODO: Implement SampledDataIncremental once that is usable in stable Rust
 impl Data {
+    /// Labels of the records from /proc/meminfo, in file order
+    pub fn keys<'a>(&'a self) -> impl Iterator<Item=&'a str> + 'a {
+        self.keys.iter().map(|key| &**key)
+    }
+
+    /// Samples of a data volume record (e.g. "MemFree"), if present
+    ///
+    /// This performs a linear search across the records of /proc/meminfo, so
+    /// if you need frequent access, consider caching the result.
+    ///
+    pub fn data_volume(&self, key: &str) -> Option<&[ByteSize]> {
+        match self.find(key) {
+            Some(&SampledPayloads::DataVolume(ref vec)) => Some(vec),
+            _ => None,
+        }
+    }
+
+    /// Samples of a raw counter record (e.g. "HugePages_Free"), if present
+    pub fn counter(&self, key: &str) -> Option<&[u64]> {
+        match self.find(key) {
+            Some(&SampledPayloads::Counter(ref vec)) => Some(vec),
+            _ => None,
+        }
+    }
+
+    /// Index of the sample with the highest memory usage, if any
+    ///
+    /// Used memory is computed as MemTotal - MemAvailable. On kernels older
+    /// than Linux 3.14, which do not provide MemAvailable, MemFree is used
+    /// instead, which overestimates usage as it counts caches as used. Returns
+    /// None if there are no samples or if MemTotal is not available.
+    ///
+    pub fn argmax_used_memory(&self) -> Option<usize> {
+        let total = self.data_volume("MemTotal")?;
+        let available = self.data_volume("MemAvailable")
+                            .or_else(|| self.data_volume("MemFree"))?;
+        argmax(total.iter().zip(available.iter()).enumerate().map(
+            |(idx, (total, available))| {
+                (idx, total.as_usize().saturating_sub(available.as_usize()))
+            }
+        ))
+    }
+
+    /// Amount of memory used by hugepages, for each sample, if available
+    ///
+    /// This is computed as (HugePages_Total - HugePages_Free) * Hugepagesize.
+    /// Note that the former two records are raw page counts, whereas the latter
+    /// is a data volume. Returns None if the kernel does not provide hugepage
+    /// statistics (e.g. because it was built without hugepage support).
+    ///
+    pub fn hugepage_bytes_in_use(&self) -> Option<Vec<ByteSize>> {
+        let total = self.counter("HugePages_Total")?;
+        let free = self.counter("HugePages_Free")?;
+        let page_size = self.data_volume("Hugepagesize")?;
+        Some(
+            total.iter().zip(free.iter()).zip(page_size.iter()).map(
+                |((&total, &free), page_size)| {
+                    let pages_in_use = total.saturating_sub(free) as usize;
+                    ByteSize::b(pages_in_use * page_size.as_usize())
+                }
+            ).collect()
+        )
+    }
+
+    /// Amount of swap space in use, for each sample, if available
+    ///
+    /// This is computed as SwapTotal - SwapFree. Returns None if the kernel
+    /// does not provide swap statistics (e.g. because it was built without
+    /// swap support).
+    ///
+    pub fn swap_used(&self) -> Option<Vec<ByteSize>> {
+        let total = self.data_volume("SwapTotal")?;
+        let free = self.data_volume("SwapFree")?;
+        Some(
+            total.iter().zip(free.iter()).map(|(total, free)| {
+                ByteSize::b(total.as_usize().saturating_sub(free.as_usize()))
+            }).collect()
+        )
+    }
+
+    /// Fraction of swap space in use, for each sample, if available
+    ///
+    /// Returns None if the kernel does not provide swap statistics, or if no
+    /// swap space was configured during any sample (swap is disabled).
+    /// Samples taken while no swap space was configured, e.g. because swap was
+    /// temporarily turned off, are reported as using no swap.
+    ///
+    pub fn swap_used_fraction(&self) -> Option<Vec<f64>> {
+        let total = self.data_volume("SwapTotal")?;
+        if total.iter().all(|total| total.as_usize() == 0) {
+            return None;
+        }
+        let used = self.swap_used()?;
+        Some(
+            total.iter().zip(used.iter()).map(|(total, used)| {
+                if total.as_usize() > 0 {
+                    used.as_usize() as f64 / total.as_usize() as f64
+                } else {
+                    0.
+                }
+            }).collect()
+        )
+    }
+
+    /// Amount of memory which is waiting to be written back to storage, for
+    /// each sample, if available (this is the "Dirty" record)
+    pub fn dirty_bytes(&self) -> Option<&[ByteSize]> {
+        self.data_volume("Dirty")
+    }
+
+    /// Amount of memory which is being written back to storage, for each
+    /// sample, if available (this is the "Writeback" record)
+    pub fn writeback_bytes(&self) -> Option<&[ByteSize]> {
+        self.data_volume("Writeback")
+    }
+
+    /// Fraction of total memory which is dirty or being written back, for each
+    /// sample, if available
+    ///
+    /// A high fraction indicates write pressure on storage: once it crosses
+    /// the vm.dirty_ratio threshold, processes which write to files get
+    /// throttled by the kernel. Returns None if any of the Dirty, Writeback
+    /// and MemTotal records is missing.
+    ///
+    pub fn dirty_plus_writeback_fraction_of_total(&self) -> Option<Vec<f64>> {
+        let dirty = self.dirty_bytes()?;
+        let writeback = self.writeback_bytes()?;
+        let total = self.data_volume("MemTotal")?;
+        Some(
+            dirty.iter().zip(writeback.iter()).zip(total.iter()).map(
+                |((dirty, writeback), total)| {
+                    if total.as_usize() > 0 {
+                        (dirty.as_usize() + writeback.as_usize()) as f64
+                            / total.as_usize() as f64
+                    } else {
+                        0.
+                    }
+                }
+            ).collect()
+        )
+    }
+
+    /// Amount of memory used by the kernel's slab allocator, for each sample,
+    /// if available (this is the "Slab" record)
+    pub fn slab_bytes(&self) -> Option<&[ByteSize]> {
+        self.data_volume("Slab")
+    }
+
+    /// Part of the slab memory which can be reclaimed under memory pressure,
+    /// such as dentry and inode caches, for each sample, if available (this is
+    /// the "SReclaimable" record, which appeared in Linux 2.6.19)
+    pub fn sreclaimable_bytes(&self) -> Option<&[ByteSize]> {
+        self.data_volume("SReclaimable")
+    }
+
+    /// Part of the slab memory which cannot be reclaimed, for each sample, if
+    /// available (this is the "SUnreclaim" record, which appeared in Linux
+    /// 2.6.19)
+    ///
+    /// Steady growth of this quantity usually indicates a kernel memory leak,
+    /// whereas growth of the reclaimable part is normally just caching.
+    ///
+    pub fn sunreclaim_bytes(&self) -> Option<&[ByteSize]> {
+        self.data_volume("SUnreclaim")
+    }
+
+    /// Amount of memory which processes have allocated, even if they have not
+    /// used it yet, for each sample, if available (this is the "Committed_AS"
+    /// record)
+    pub fn committed_as_bytes(&self) -> Option<&[ByteSize]> {
+        self.data_volume("Committed_AS")
+    }
+
+    /// Amount of memory which can be allocated under strict overcommit
+    /// accounting, for each sample, if available (this is the "CommitLimit"
+    /// record, which appeared in Linux 2.6.10)
+    pub fn commit_limit_bytes(&self) -> Option<&[ByteSize]> {
+        self.data_volume("CommitLimit")
+    }
+
+    /// Ratio of committed memory to the commit limit, for each sample, if
+    /// available
+    ///
+    /// A ratio which approaches or exceeds 1.0 warns of imminent allocation
+    /// failures (under strict overcommit accounting) or OOM kills (otherwise).
+    /// Returns None if either the Committed_AS or CommitLimit record is
+    /// missing.
+    ///
+    pub fn overcommit_ratio(&self) -> Option<Vec<f64>> {
+        let committed = self.committed_as_bytes()?;
+        let limit = self.commit_limit_bytes()?;
+        Some(
+            committed.iter().zip(limit.iter()).map(|(committed, limit)| {
+                if limit.as_usize() > 0 {
+                    committed.as_usize() as f64 / limit.as_usize() as f64
+                } else {
+                    0.
+                }
+            }).collect()
+        )
+    }
+
+    /// Amount of recently used anonymous memory (e.g. process heaps and
+    /// stacks), for each sample, if available (this is the "Active(anon)"
+    /// record, which appeared in Linux 2.6.28)
+    pub fn active_anon_bytes(&self) -> Option<&[ByteSize]> {
+        self.data_volume("Active(anon)")
+    }
+
+    /// Amount of less recently used anonymous memory, which is a candidate
+    /// for being swapped out, for each sample, if available (this is the
+    /// "Inactive(anon)" record, which appeared in Linux 2.6.28)
+    pub fn inactive_anon_bytes(&self) -> Option<&[ByteSize]> {
+        self.data_volume("Inactive(anon)")
+    }
+
+    /// Amount of recently used file-backed memory (page cache), for each
+    /// sample, if available (this is the "Active(file)" record, which appeared
+    /// in Linux 2.6.28)
+    pub fn active_file_bytes(&self) -> Option<&[ByteSize]> {
+        self.data_volume("Active(file)")
+    }
+
+    /// Amount of less recently used file-backed memory, which is the first to
+    /// be reclaimed under memory pressure, for each sample, if available (this
+    /// is the "Inactive(file)" record, which appeared in Linux 2.6.28)
+    pub fn inactive_file_bytes(&self) -> Option<&[ByteSize]> {
+        self.data_volume("Inactive(file)")
+    }
+
+    /// Amount of file-backed memory which the kernel can reclaim by dropping
+    /// it from the page cache, for each sample, if available
+    ///
+    /// This is computed as Active(file) + Inactive(file). Dirty pages must be
+    /// written back before they can be dropped, so not all of this memory can
+    /// be reclaimed immediately. Returns None if either record is missing.
+    ///
+    pub fn reclaimable_file_cache_bytes(&self) -> Option<Vec<ByteSize>> {
+        let active = self.active_file_bytes()?;
+        let inactive = self.inactive_file_bytes()?;
+        Some(
+            active.iter().zip(inactive.iter()).map(|(active, inactive)| {
+                ByteSize::b(active.as_usize() + inactive.as_usize())
+            }).collect()
+        )
+    }
+
+    /// Data volumes whose magnitude looks implausible, if any
+    ///
+    /// This parser assumes that the "kB" suffix of /proc/meminfo stands for
+    /// kibibytes, and that every record with this suffix is a data volume.
+    /// As a sanity check of this assumption, records which describe physical
+    /// memory are cross-checked against MemTotal: none of them should exceed
+    /// the amount of RAM present on the system.
+    ///
+    /// Records describing swap or virtual address space, such as SwapTotal
+    /// or VmallocTotal, can legitimately exceed MemTotal and are not checked.
+    /// Neither are the DirectMap records, which count memory that the kernel
+    /// reserves for itself. Each offending record is reported once, at the
+    /// first sample where it exceeded MemTotal. Nothing is reported if
+    /// MemTotal is not available.
+    ///
+    pub fn unit_anomalies(&self) -> Vec<UnitAnomaly> {
+        let mem_total = match self.data_volume("MemTotal") {
+            Some(mem_total) => mem_total,
+            None => return Vec::new(),
+        };
+        self.keys.iter().zip(self.data.iter()).filter_map(|(key, payloads)| {
+            let volumes = match *payloads {
+                SampledPayloads::DataVolume(ref vec) => vec,
+                _ => return None,
+            };
+            if UNBOUNDED_DATA_VOLUMES.contains(&&**key)
+               || key.starts_with(DIRECT_MAP_PREFIX)
+            {
+                return None;
+            }
+            volumes.iter().zip(mem_total.iter()).position(|(amount, total)| {
+                amount.as_usize() > total.as_usize()
+            }).map(|first_sample| UnitAnomaly {
+                key: key.to_string(),
+                first_sample,
+                amount: volumes[first_sample],
+                mem_total: mem_total[first_sample],
+            })
+        }).collect()
+    }
+
+    /// INTERNAL: Create an empty data store, which expects no record
+    fn empty() -> Self {
+        Self {
+            data: Vec::new(),
+            keys: Vec::new(),
+        }
+    }
+
+    /// INTERNAL: Look up the sampled payloads associated with a certain key
+    fn find(&self, key: &str) -> Option<&SampledPayloads> {
+        self.keys.iter()
+                 .position(|k| &**k == key)
+                 .map(|idx| &self.data[idx])
+    }
+
     /// Create a new memory info data store, using a first sample to know the
     /// structure of /proc/meminfo on this system
     fn new(mut stream: RecordStream) -> Self {
         // Our data store will eventually go there
-        let mut store = Self {
-            data: Vec::new(),
-            keys: Vec::new(),
-        };
+        let mut store = Self::empty();
 
         // For initial record of /proc/meminfo...
         while let Some(record) = stream.next() {
@@ -219,7 +593,7 @@ impl Data {
             let data = SampledPayloads::new(record.extract_payload());
 
             // Memorize the key and payload store in our data store
-            store.keys.push(label.to_owned());
+            store.keys.push(intern(label));
             store.data.push(data);
         }
 
@@ -229,29 +603,194 @@ impl Data {
 
     /// Parse the contents of /proc/meminfo and add a data sample to all
     /// corresponding entries in the internal data store
-    fn push(&mut self, mut stream: RecordStream) {
+    fn push(&mut self, stream: RecordStream) -> Result<(), SchemaChanged> {
+        self.push_sample(stream, true)
+    }
+
+    /// Parse a batch of /proc/meminfo samples, such as the snapshots of a
+    /// recorded capture, and add them to the internal data store
+    ///
+    /// This is faster than pushing the samples one by one, as storage is
+    /// reserved up front and, in release builds, the record labels are only
+    /// checked for the first sample of the batch. This assumes that the
+    /// schema of /proc/meminfo does not change within the batch, which is the
+    /// case for a capture of a single system.
+    ///
+    pub fn push_many<'a, I>(&mut self, streams: I) -> Result<(), SchemaChanged>
+        where I: IntoIterator<Item=RecordStream<'a>>
+    {
+        let mut streams = streams.into_iter();
+        let additional = streams.size_hint().0;
+        for payloads in self.data.iter_mut() {
+            payloads.reserve(additional);
+        }
+        if let Some(first_stream) = streams.next() {
+            self.push_sample(first_stream, true)?;
+        }
+        for stream in streams {
+            self.push_sample(stream, false)?;
+        }
+        Ok(())
+    }
+
+    /// INTERNAL: Add a sample to the internal data store, optionally checking
+    ///           the record labels in release builds
+    ///
+    /// If a record has disappeared, which notably happens when the kernel
+    /// hands over an empty readout, the partial sample is rolled back.
+    ///
+    fn push_sample(&mut self,
+                   mut stream: RecordStream,
+                   check_labels: bool) -> Result<(), SchemaChanged> {
         // This time, we know how lines of /proc/meminfo map to our members
+        let sample_count = self.len();
+        let mut recorded = 0;
         for (data, key) in self.data.iter_mut().zip(self.keys.iter()) {
             // We start by iterating over records and checking that each record
             // that we observed during initialization is still around
-            let record = stream.next().expect("A record has disappeared");
+            let record = match stream.next() {
+                Some(record) => record,
+                None => break,
+            };
             let label = record.label();
 
             // In release mode, we use the length of the header as a checksum
             // to make sure that the internal structure did not change during
             // sampling. In debug mode, we fully check the header.
-            assert_eq!(label.len(), key.len(),
-                       "Unsupported structural meminfo change during sampling");
-            debug_assert_eq!(label, key,
+            if check_labels {
+                assert_eq!(
+                    label.len(), key.len(),
+                    "Unsupported structural meminfo change during sampling"
+                );
+            }
+            debug_assert_eq!(label, &**key,
                              "Unsupported meminfo change during sampling");
 
             // Forward the payload to its target
             data.push(record.extract_payload());
+            recorded += 1;
+        }
+
+        // If a record has disappeared, roll back the partial sample
+        if recorded < self.data.len() {
+            for data in self.data[..recorded].iter_mut() {
+                data.truncate(sample_count);
+            }
+            return Err(SchemaChanged::new("A meminfo record has disappeared"));
         }
 
         // In debug mode, we also check that records did not appear out of blue
         debug_assert!(stream.next().is_none(),
                       "A meminfo record appeared out of nowhere");
+        Ok(())
+    }
+}
+//
+/// Records are exported under their key (e.g. "MemFree"), except for those
+/// whose payload is not supported by this parser
+impl Columns for Data {
+    fn columns(&self) -> Vec<(String, Column)> {
+        self.keys.iter().zip(self.data.iter()).filter_map(|(key, payloads)| {
+            let column = match *payloads {
+                SampledPayloads::DataVolume(ref vec) => Column::bytes(vec),
+                SampledPayloads::Counter(ref vec) => Column::counts(vec),
+                SampledPayloads::Unsupported(_) => return None,
+            };
+            Some((key.to_string(), column))
+        }).collect()
+    }
+}
+//
+/// Compact binary serialization, see the "binary" module for the general
+/// format. Here, the header lists the meminfo keys and the kind of payload
+/// associated with each of them, and is followed by the sampled payloads.
+#[cfg(feature = "binary")]
+impl Data {
+    /// Save the sampled data in binary form
+    pub fn write_binary<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        // Write the header
+        binary::write_header(&mut writer, BINARY_MAGIC)?;
+        self.keys.write_to(&mut writer)?;
+        let kinds: Vec<u8> = self.data.iter()
+                                      .map(|payloads| payloads.binary_tag())
+                                      .collect();
+        kinds.write_to(&mut writer)?;
+
+        // Write the sampled payloads, data volumes being stored in bytes
+        for payloads in self.data.iter() {
+            match *payloads {
+                SampledPayloads::DataVolume(ref vec) => {
+                    let bytes: Vec<u64> = vec.iter()
+                                             .map(|v| v.as_usize() as u64)
+                                             .collect();
+                    bytes.write_to(&mut writer)?;
+                },
+                SampledPayloads::Counter(ref vec) => vec.write_to(&mut writer)?,
+                SampledPayloads::Unsupported(count) => {
+                    count.write_to(&mut writer)?
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// Load sampled data which was saved by write_binary()
+    pub fn read_binary<R: Read>(mut reader: R) -> io::Result<Self> {
+        // Read the header
+        binary::read_header(&mut reader, BINARY_MAGIC)?;
+        let keys = Vec::<Arc<str>>::read_from(&mut reader)?;
+        let kinds = Vec::<u8>::read_from(&mut reader)?;
+        if kinds.len() != keys.len() {
+            return Err(binary::invalid_data("Inconsistent meminfo header"));
+        }
+
+        // Read the sampled payloads
+        let mut data = Vec::with_capacity(kinds.len());
+        for kind in kinds {
+            data.push(
+                match kind {
+                    0 => {
+                        let bytes = Vec::<u64>::read_from(&mut reader)?;
+                        SampledPayloads::DataVolume(
+                            bytes.into_iter()
+                                 .map(|b| ByteSize::b(b as usize))
+                                 .collect()
+                        )
+                    },
+                    1 => SampledPayloads::Counter(Vec::read_from(&mut reader)?),
+                    2 => {
+                        let count = usize::read_from(&mut reader)?;
+                        SampledPayloads::Unsupported(count)
+                    },
+                    _ => {
+                        return Err(binary::invalid_data("Invalid payload kind"))
+                    },
+                }
+            );
+        }
+        Ok(Self { data, keys })
+    }
+}
+//
+/// Magic number identifying meminfo data in the binary format
+#[cfg(feature = "binary")]
+const BINARY_MAGIC: &[u8; 4] = b"PFMI";
+//
+/// Builders for expected data stores, which keep unit tests concise
+#[cfg(test)]
+impl Data {
+    /// Expect a data volume record with a certain key and samples
+    fn with_data_volume(mut self, key: &str, samples: Vec<ByteSize>) -> Self {
+        self.keys.push(intern(key));
+        self.data.push(SampledPayloads::DataVolume(samples));
+        self
+    }
+
+    /// Expect a raw counter record with a certain key and samples
+    fn with_counter(mut self, key: &str, samples: Vec<u64>) -> Self {
+        self.keys.push(intern(key));
+        self.data.push(SampledPayloads::Counter(samples));
+        self
     }
 }
 
@@ -315,6 +854,15 @@ impl SampledPayloads {
         }
     }
 
+    /// Reserve storage for some additional samples
+    fn reserve(&mut self, additional: usize) {
+        match *self {
+            SampledPayloads::DataVolume(ref mut v) => v.reserve(additional),
+            SampledPayloads::Counter(ref mut v)    => v.reserve(additional),
+            SampledPayloads::Unsupported(_)        => {},
+        }
+    }
+
     /// Tell how many samples are present in the data store
     fn len(&self) -> usize {
         match *self {
@@ -323,6 +871,40 @@ impl SampledPayloads {
             SampledPayloads::Unsupported(count) => count,
         }
     }
+
+    /// Drop the oldest samples from the data store
+    fn drop_oldest(&mut self, count: usize) {
+        match *self {
+            SampledPayloads::DataVolume(ref mut v) => { v.drain(..count); },
+            SampledPayloads::Counter(ref mut v)    => { v.drain(..count); },
+            SampledPayloads::Unsupported(ref mut sample_count) => {
+                assert!(count <= *sample_count, "Not enough samples to drop");
+                *sample_count -= count;
+            },
+        }
+    }
+
+    /// Tag identifying the kind of payload in the binary format
+    #[cfg(feature = "binary")]
+    fn binary_tag(&self) -> u8 {
+        match *self {
+            SampledPayloads::DataVolume(_)  => 0,
+            SampledPayloads::Counter(_)     => 1,
+            SampledPayloads::Unsupported(_) => 2,
+        }
+    }
+
+    /// Drop the newest samples from the data store
+    fn truncate(&mut self, length: usize) {
+        match *self {
+            SampledPayloads::DataVolume(ref mut v) => v.truncate(length),
+            SampledPayloads::Counter(ref mut v)    => v.truncate(length),
+            SampledPayloads::Unsupported(ref mut sample_count) => {
+                assert!(length <= *sample_count, "Not enough samples to keep");
+                *sample_count = length;
+            },
+        }
+    }
 }
 
 
@@ -332,7 +914,8 @@ mod tests {
     use bytesize;
     use ::splitter::split_line_and_run;
     use super::{ByteSize, Data, Parser, Payload, PayloadKind, PseudoFileParser,
-                Record, RecordStream, SampledData, SampledPayloads};
+                Record, RecordStream, SampledData, SampledPayloads,
+                UnitAnomaly};
 
     /// Check that payload parsing works as expected
     #[test]
@@ -406,6 +989,11 @@ mod tests {
             assert_eq!(payload.kind(), PayloadKind::DataVolume);
             assert_eq!(payload.parse_data_volume(), ByteSize::kib(10248));
         });
+        with_record("Active(anon):    2097152 kB", |record| {
+            assert_eq!(record.label(), "Active(anon)");
+            assert_eq!(record.extract_payload().parse_data_volume(),
+                       ByteSize::kib(2097152));
+        });
     }
 
     /// Check that record streams work as expected
@@ -456,18 +1044,12 @@ mod tests {
         // Build a data sampler for that file
         let initial_records = RecordStream::new(&initial_contents);
         let mut sampled_data = Data::new(initial_records);
-        assert_eq!(sampled_data, Data {
-            data: vec![SampledPayloads::Counter(Vec::new()),
-                       SampledPayloads::DataVolume(Vec::new()),
-                       SampledPayloads::DataVolume(Vec::new()),
-                       SampledPayloads::Counter(Vec::new()),
-                       SampledPayloads::Counter(Vec::new())],
-            keys: vec!["What".to_string(),
-                       "Could".to_string(),
-                       "Possibly".to_string(),
-                       "Go".to_string(),
-                       "Wrong".to_string()]
-        });
+        assert_eq!(sampled_data,
+                   Data::empty().with_counter("What", Vec::new())
+                                .with_data_volume("Could", Vec::new())
+                                .with_data_volume("Possibly", Vec::new())
+                                .with_counter("Go", Vec::new())
+                                .with_counter("Wrong", Vec::new()));
         assert_eq!(sampled_data.len(), 0);
 
         // Try to acquire one data sample and see how well that works out
@@ -477,22 +1059,275 @@ mod tests {
                              "Go:       50161",
                              "Wrong:    6484"].join("\n");
         let file_records = RecordStream::new(&file_contents);
-        sampled_data.push(file_records);
-        assert_eq!(sampled_data, Data {
-            data: vec![SampledPayloads::Counter(vec![9876]),
-                       SampledPayloads::DataVolume(vec![ByteSize::kib(6514)]),
-                       SampledPayloads::DataVolume(vec![ByteSize::kib(98753)]),
-                       SampledPayloads::Counter(vec![50161]),
-                       SampledPayloads::Counter(vec![6484])],
-            keys: vec!["What".to_string(),
-                       "Could".to_string(),
-                       "Possibly".to_string(),
-                       "Go".to_string(),
-                       "Wrong".to_string()]
-        });
+        sampled_data.push(file_records).unwrap();
+        assert_eq!(
+            sampled_data,
+            Data::empty().with_counter("What", vec![9876])
+                         .with_data_volume("Could", vec![ByteSize::kib(6514)])
+                         .with_data_volume("Possibly",
+                                           vec![ByteSize::kib(98753)])
+                         .with_counter("Go", vec![50161])
+                         .with_counter("Wrong", vec![6484])
+        );
         assert_eq!(sampled_data.len(), 1);
     }
 
+    /// Check that bulk pushes are equivalent to individual pushes
+    #[test]
+    fn bulk_push() {
+        let samples = ["MemTotal: 16316412 kB\nMemFree: 1230496 kB\nWhat: 1",
+                       "MemTotal: 16316412 kB\nMemFree: 1230412 kB\nWhat: 2",
+                       "MemTotal: 16316412 kB\nMemFree: 1229468 kB\nWhat: 4"];
+        let mut expected = Data::new(RecordStream::new(samples[0]));
+        for sample in samples.iter() {
+            expected.push(RecordStream::new(sample)).unwrap();
+        }
+        let mut data = Data::new(RecordStream::new(samples[0]));
+        data.push_many(samples.iter().map(|sample| RecordStream::new(sample)))
+            .unwrap();
+        assert_eq!(data, expected);
+        assert_eq!(data.len(), 3);
+    }
+
+    /// Check that samples with missing records are rolled back
+    #[test]
+    fn missing_records() {
+        let sample = "MemTotal: 16316412 kB\nMemFree: 1230496 kB\nWhat: 1";
+        let mut data = Data::new(RecordStream::new(sample));
+        data.push(RecordStream::new(sample)).unwrap();
+        let mut expected = Data::new(RecordStream::new(sample));
+        expected.push(RecordStream::new(sample)).unwrap();
+        assert!(data.push(RecordStream::new("")).is_err());
+        assert!(data.push(RecordStream::new("MemTotal: 16316412 kB"))
+                    .is_err());
+        assert_eq!(data, expected);
+        assert_eq!(data.len(), 1);
+    }
+
+    /// Check that the sample with peak memory usage is found
+    #[test]
+    fn peak_memory_usage() {
+        // Without the relevant records, there is nothing to be found
+        let data = Data::empty().with_counter("What", vec![1, 2, 3]);
+        assert_eq!(data.argmax_used_memory(), None);
+
+        // MemAvailable is used when present, and usage peaks in the middle
+        let kib = |values: &[usize]| -> Vec<ByteSize> {
+            values.iter().map(|&v| ByteSize::kib(v)).collect()
+        };
+        let data = Data::empty().with_data_volume("MemTotal",
+                                                  kib(&[100, 100, 100, 100]))
+                                .with_data_volume("MemFree",
+                                                  kib(&[90, 10, 50, 10]))
+                                .with_data_volume("MemAvailable",
+                                                  kib(&[90, 60, 20, 40]));
+        assert_eq!(data.argmax_used_memory(), Some(2));
+
+        // Otherwise, MemFree is used as a fallback
+        let data = Data::empty().with_data_volume("MemTotal",
+                                                  kib(&[100, 100, 100]))
+                                .with_data_volume("MemFree", kib(&[90, 5, 50]));
+        assert_eq!(data.argmax_used_memory(), Some(1));
+    }
+
+    /// Check that hugepage memory usage is computed correctly
+    #[test]
+    fn hugepage_usage() {
+        // Without hugepage records, there is nothing to be computed
+        let data = data_from_sample(&["MemTotal:       16316412 kB",
+                                      "MemFree:         1230496 kB"]);
+        assert_eq!(data.hugepage_bytes_in_use(), None);
+
+        // Otherwise, the memory usage of hugepages is computed
+        let data = data_from_sample(&["MemTotal:       16316412 kB",
+                                      "HugePages_Total:      16",
+                                      "HugePages_Free:       10",
+                                      "HugePages_Rsvd:        2",
+                                      "HugePages_Surp:        0",
+                                      "Hugepagesize:       2048 kB"]);
+        assert_eq!(data.hugepage_bytes_in_use(),
+                   Some(vec![ByteSize::kib(6 * 2048)]));
+    }
+
+    /// Check that swap usage is computed correctly
+    #[test]
+    fn swap_usage() {
+        // Without swap records, there is nothing to be computed
+        let data = data_from_sample(&["MemTotal:       16316412 kB",
+                                      "MemFree:         1230496 kB"]);
+        assert_eq!(data.swap_used(), None);
+        assert_eq!(data.swap_used_fraction(), None);
+
+        // With swap disabled, no swap is used, and the fraction is undefined
+        let data = data_from_sample(&["MemTotal:       16316412 kB",
+                                      "SwapTotal:             0 kB",
+                                      "SwapFree:              0 kB"]);
+        assert_eq!(data.swap_used(), Some(vec![ByteSize::kib(0)]));
+        assert_eq!(data.swap_used_fraction(), None);
+
+        // With swap enabled, swap usage is computed
+        let data = data_from_sample(&["MemTotal:       16316412 kB",
+                                      "SwapTotal:       8388604 kB",
+                                      "SwapFree:        6291453 kB"]);
+        assert_eq!(data.swap_used(), Some(vec![ByteSize::kib(2097151)]));
+        assert_eq!(data.swap_used_fraction(),
+                   Some(vec![2097151. / 8388604.]));
+    }
+
+    /// Check that dirty and writeback memory are reported correctly
+    #[test]
+    fn write_pressure() {
+        // With all records present, the fraction is computed
+        let data = data_from_sample(&["MemTotal:        1048576 kB",
+                                      "Dirty:             65536 kB",
+                                      "Writeback:         16384 kB"]);
+        assert_eq!(data.dirty_bytes(), Some(&[ByteSize::kib(65536)][..]));
+        assert_eq!(data.writeback_bytes(), Some(&[ByteSize::kib(16384)][..]));
+        assert_eq!(data.dirty_plus_writeback_fraction_of_total(),
+                   Some(vec![0.078125]));
+
+        // If any record is missing, the fraction cannot be computed
+        let data = data_from_sample(&["MemTotal:        1048576 kB",
+                                      "Dirty:             65536 kB"]);
+        assert_eq!(data.writeback_bytes(), None);
+        assert_eq!(data.dirty_plus_writeback_fraction_of_total(), None);
+        let data = data_from_sample(&["Dirty:             65536 kB",
+                                      "Writeback:         16384 kB"]);
+        assert_eq!(data.dirty_plus_writeback_fraction_of_total(), None);
+    }
+
+    /// Check that the slab memory breakdown is reported correctly
+    #[test]
+    fn slab_breakdown() {
+        // Modern kernels split slab memory into a reclaimable and an
+        // unreclaimable part
+        let data = data_from_sample(&["Slab:             897088 kB",
+                                      "SReclaimable:     658856 kB",
+                                      "SUnreclaim:       238232 kB"]);
+        assert_eq!(data.slab_bytes(), Some(&[ByteSize::kib(897088)][..]));
+        assert_eq!(data.sreclaimable_bytes(),
+                   Some(&[ByteSize::kib(658856)][..]));
+        assert_eq!(data.sunreclaim_bytes(), Some(&[ByteSize::kib(238232)][..]));
+
+        // Very old kernels only report the total
+        let data = data_from_sample(&["Slab:             897088 kB"]);
+        assert_eq!(data.slab_bytes(), Some(&[ByteSize::kib(897088)][..]));
+        assert_eq!(data.sreclaimable_bytes(), None);
+        assert_eq!(data.sunreclaim_bytes(), None);
+    }
+
+    /// Check that the overcommit ratio is computed correctly
+    #[test]
+    fn overcommit() {
+        // Build a data store from two meminfo file samples
+        let sample = |file_contents: &[&str]| -> Data {
+            let mut data = data_from_sample(file_contents);
+            data.push(RecordStream::new(
+                &file_contents.join("\n")
+                              .replace("Committed_AS:     1048576",
+                                       "Committed_AS:     2621440")
+            )).unwrap();
+            data
+        };
+
+        // With both records present, the ratio is computed
+        let data = sample(&["CommitLimit:      2097152 kB",
+                            "Committed_AS:     1048576 kB"]);
+        assert_eq!(data.commit_limit_bytes(),
+                   Some(&[ByteSize::kib(2097152), ByteSize::kib(2097152)][..]));
+        assert_eq!(data.committed_as_bytes(),
+                   Some(&[ByteSize::kib(1048576), ByteSize::kib(2621440)][..]));
+        assert_eq!(data.overcommit_ratio(), Some(vec![0.5, 1.25]));
+
+        // If either record is missing, the ratio cannot be computed
+        let data = sample(&["Committed_AS:     1048576 kB"]);
+        assert_eq!(data.commit_limit_bytes(), None);
+        assert_eq!(data.overcommit_ratio(), None);
+    }
+
+    /// Check that the anonymous and file-backed page accounting is reported
+    #[test]
+    fn anon_file_pages() {
+        // Labels with parentheses are parsed correctly
+        let data = data_from_sample(&["Active:          5242880 kB",
+                                      "Inactive:        3145728 kB",
+                                      "Active(anon):    4194304 kB",
+                                      "Inactive(anon):  1048576 kB",
+                                      "Active(file):    1048576 kB",
+                                      "Inactive(file):  2097152 kB"]);
+        let keys: Vec<&str> = data.keys().collect();
+        assert_eq!(keys, vec!["Active", "Inactive",
+                              "Active(anon)", "Inactive(anon)",
+                              "Active(file)", "Inactive(file)"]);
+        assert_eq!(data.active_anon_bytes(),
+                   Some(&[ByteSize::kib(4194304)][..]));
+        assert_eq!(data.inactive_anon_bytes(),
+                   Some(&[ByteSize::kib(1048576)][..]));
+        assert_eq!(data.active_file_bytes(),
+                   Some(&[ByteSize::kib(1048576)][..]));
+        assert_eq!(data.inactive_file_bytes(),
+                   Some(&[ByteSize::kib(2097152)][..]));
+        assert_eq!(data.reclaimable_file_cache_bytes(),
+                   Some(vec![ByteSize::kib(3145728)]));
+
+        // Kernels older than 2.6.28 only report the totals
+        let data = data_from_sample(&["Active:          5242880 kB",
+                                      "Inactive:        3145728 kB"]);
+        assert_eq!(data.active_anon_bytes(), None);
+        assert_eq!(data.inactive_file_bytes(), None);
+        assert_eq!(data.reclaimable_file_cache_bytes(), None);
+    }
+
+    /// Check that implausible data volumes are reported
+    #[test]
+    fn unit_anomalies() {
+        // A plausible meminfo sample, whose swap and address space volumes
+        // are larger than RAM, should not raise any alarm
+        const PLAUSIBLE: &str = "MemTotal:        8388608 kB\n\
+                                 MemFree:         1048576 kB\n\
+                                 SwapTotal:      16777216 kB\n\
+                                 Committed_AS:   12582912 kB\n\
+                                 VmallocTotal: 34359738367 kB\n\
+                                 HugePages_Total:      16\n\
+                                 Hugepagesize:       2048 kB\n\
+                                 DirectMap1G:     9437184 kB\n";
+        let mut data = Data::new(RecordStream::new(PLAUSIBLE));
+        data.push(RecordStream::new(PLAUSIBLE)).unwrap();
+        assert_eq!(data.unit_anomalies(), Vec::new());
+
+        // A page size reported in bytes rather than kibibytes would imply
+        // pages larger than the RAM, which is reported at its first occurrence
+        let implausible = PLAUSIBLE.replace("      2048 kB", "2097152000 kB");
+        data.push(RecordStream::new(&implausible)).unwrap();
+        data.push(RecordStream::new(&implausible)).unwrap();
+        assert_eq!(data.unit_anomalies(), vec![UnitAnomaly {
+            key: "Hugepagesize".to_owned(),
+            first_sample: 1,
+            amount: ByteSize::kib(2097152000),
+            mem_total: ByteSize::kib(8388608),
+        }]);
+
+        // Without MemTotal, there is nothing to cross-check against
+        let data = Data::new(RecordStream::new("Hugepagesize: 2097152000 kB"));
+        assert_eq!(data.unit_anomalies(), Vec::new());
+    }
+
+    /// Check that sampled data survives a round trip through binary storage
+    #[cfg(feature = "binary")]
+    #[test]
+    fn binary_round_trip() {
+        let data = Data::empty().with_data_volume("MemTotal",
+                                                  vec![ByteSize::kib(42),
+                                                       ByteSize::kib(42)])
+                                .with_counter("HugePages_Free", vec![3, 2]);
+        let mut buffer = Vec::new();
+        data.write_binary(&mut buffer).expect("Failed to write binary data");
+        let data2 = Data::read_binary(&buffer[..])
+                         .expect("Failed to read binary data");
+        assert_eq!(data2, data);
+        assert!(Data::read_binary(&buffer[..buffer.len()-1]).is_err());
+    }
+
     /// Call a function with a payload that parses into a certain data volume
     fn with_data_volume_payload<F, R>(data_volume: ByteSize, operation: F) -> R
         where F: FnOnce(Payload) -> R
@@ -533,6 +1368,15 @@ mod tests {
         operation(payload)
     }
 
+    /// Build a data store from a single meminfo file sample, given as a list
+    /// of lines
+    fn data_from_sample(file_contents: &[&str]) -> Data {
+        let file_contents = file_contents.join("\n");
+        let mut data = Data::new(RecordStream::new(&file_contents));
+        data.push(RecordStream::new(&file_contents)).unwrap();
+        data
+    }
+
     /// Call a function with a record matching a certain line of meminfo text
     fn with_record<F, R>(record_str: &str, operation: F) -> R
         where F: FnOnce(Record) -> R
@@ -566,7 +1410,46 @@ mod tests {
 ///
 #[cfg(test)]
 mod benchmarks {
+    use super::{Data, RecordStream};
+
     define_sampler_benchs!{ super::Sampler,
                             "/proc/meminfo",
                             500_000 }
+
+    /// Number of samples in the replay benchmarks
+    const REPLAY_LENGTH: usize = 1_000;
+
+    /// Benchmark for storing a replayed capture with individual pushes...
+    #[test]
+    #[ignore]
+    fn replay_single_pushes() {
+        let contents = read_contents();
+        testbench::benchmark(100, || {
+            let mut data = Data::new(RecordStream::new(&contents));
+            for _ in 0..REPLAY_LENGTH {
+                data.push(RecordStream::new(&contents)).unwrap();
+            }
+        });
+    }
+
+    /// ...and with a bulk push
+    #[test]
+    #[ignore]
+    fn replay_bulk_push() {
+        let contents = read_contents();
+        testbench::benchmark(100, || {
+            let mut data = Data::new(RecordStream::new(&contents));
+            data.push_many((0..REPLAY_LENGTH).map(|_| {
+                RecordStream::new(&contents)
+            })).unwrap();
+        });
+    }
+
+    /// INTERNAL: Read the current contents of /proc/meminfo
+    fn read_contents() -> String {
+        let mut reader = ProcFileReader::open("/proc/meminfo")
+                                        .expect("Failed to open /proc/meminfo");
+        reader.sample(|contents| contents.to_owned())
+              .expect("Failed to read /proc/meminfo")
+    }
 }
@@ -7,7 +7,17 @@
 //! Each submodule corresponds to one file in /proc, and is named as close to
 //! that file as allowed by the Rust module system.
 
+pub mod cpuinfo;
+pub mod diskstats;
+pub mod filesystems;
+pub mod interrupts;
+pub mod loadavg;
+pub mod locks;
 pub mod meminfo;
+pub mod net;
+pub mod process;
 pub mod stat;
+pub mod sys;
 pub mod uptime;
 pub mod version;
+pub mod vmstat;
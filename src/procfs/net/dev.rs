@@ -0,0 +1,1002 @@
+//! This module contains a sampling parser for /proc/net/dev
+//!
+//! This file provides traffic statistics for every network interface. What
+//! people usually want to know is how fast data is flowing through these
+//! interfaces, so in addition to the raw counters, the sampler provides
+//! throughput and packet rate accessors based on the sample timestamps.
+//!
+//! The counters of an interface are reset to zero when some network drivers
+//! bring it down and up again. Such resets are detected, and rates are not
+//! computed across them, as the counter decrease would otherwise come out as
+//! an enormous bogus rate.
+//!
+//! Rising error and drop counts are a classic symptom of cabling, buffering or
+//! driver problems, so their rates are also provided, along with a quick check
+//! of whether they increased at all during the measurement.
+
+use ::columns::{Column, Columns};
+use ::data::{SampledData, SchemaChanged};
+use ::parser::PseudoFileParser;
+use ::splitter::{SplitColumns, SplitLinesBySpace};
+
+
+// Implement a sampler for /proc/net/dev
+define_sampler!{ Sampler : "/proc/net/dev" => Parser => RecordStream => Data }
+//
+// The sampler also provides rates, which require the sample timestamps
+impl Sampler {
+    /// Rate at which a network interface received data between two samples,
+    /// in bytes per second
+    ///
+    /// Returns None if there is no such interface, or if no time elapsed
+    /// between these samples. If the interface's counters were reset between
+    /// these samples, only the post-reset traffic is accounted for.
+    ///
+    pub fn rx_throughput_between(&self,
+                                 interface: &str,
+                                 older: usize,
+                                 newer: usize) -> Option<f64> {
+        self.interface_rate(interface, older, newer, InterfaceStats::rx_bytes)
+    }
+
+    /// Rate at which a network interface transmitted data between two
+    /// samples, in bytes per second (see rx_throughput_between())
+    pub fn tx_throughput_between(&self,
+                                 interface: &str,
+                                 older: usize,
+                                 newer: usize) -> Option<f64> {
+        self.interface_rate(interface, older, newer, InterfaceStats::tx_bytes)
+    }
+
+    /// Rate at which a network interface received packets between two
+    /// samples, in packets per second (see rx_throughput_between())
+    pub fn rx_packet_rate_between(&self,
+                                  interface: &str,
+                                  older: usize,
+                                  newer: usize) -> Option<f64> {
+        self.interface_rate(interface,
+                            older,
+                            newer,
+                            InterfaceStats::rx_packets)
+    }
+
+    /// Rate at which a network interface transmitted packets between two
+    /// samples, in packets per second (see rx_throughput_between())
+    pub fn tx_packet_rate_between(&self,
+                                  interface: &str,
+                                  older: usize,
+                                  newer: usize) -> Option<f64> {
+        self.interface_rate(interface,
+                            older,
+                            newer,
+                            InterfaceStats::tx_packets)
+    }
+
+    /// Rate at which a network interface encountered receive errors between
+    /// two samples, in errors per second (see rx_throughput_between())
+    pub fn rx_error_rate_between(&self,
+                                 interface: &str,
+                                 older: usize,
+                                 newer: usize) -> Option<f64> {
+        self.interface_rate(interface,
+                            older,
+                            newer,
+                            InterfaceStats::rx_errors)
+    }
+
+    /// Rate at which a network interface dropped incoming packets between two
+    /// samples, in packets per second (see rx_throughput_between())
+    pub fn rx_drop_rate_between(&self,
+                                interface: &str,
+                                older: usize,
+                                newer: usize) -> Option<f64> {
+        self.interface_rate(interface, older, newer, InterfaceStats::rx_drops)
+    }
+
+    /// Rate at which a network interface encountered transmit errors between
+    /// two samples, in errors per second (see rx_throughput_between())
+    pub fn tx_error_rate_between(&self,
+                                 interface: &str,
+                                 older: usize,
+                                 newer: usize) -> Option<f64> {
+        self.interface_rate(interface,
+                            older,
+                            newer,
+                            InterfaceStats::tx_errors)
+    }
+
+    /// Rate at which a network interface dropped outgoing packets between two
+    /// samples, in packets per second (see rx_throughput_between())
+    pub fn tx_drop_rate_between(&self,
+                                interface: &str,
+                                older: usize,
+                                newer: usize) -> Option<f64> {
+        self.interface_rate(interface, older, newer, InterfaceStats::tx_drops)
+    }
+
+    /// Rate at which all network interfaces received data between two
+    /// samples, in bytes per second
+    ///
+    /// The loopback interface "lo" usually dominates this figure, although
+    /// its traffic never leaves the machine, so it can be left out by setting
+    /// the exclude_loopback flag. Returns None if no time elapsed between
+    /// these samples.
+    ///
+    pub fn total_rx_throughput_between(&self,
+                                       older: usize,
+                                       newer: usize,
+                                       exclude_loopback: bool)
+        -> Option<f64>
+    {
+        self.total_rate(older,
+                        newer,
+                        exclude_loopback,
+                        InterfaceStats::rx_bytes)
+    }
+
+    /// Rate at which all network interfaces transmitted data between two
+    /// samples, in bytes per second (see total_rx_throughput_between())
+    pub fn total_tx_throughput_between(&self,
+                                       older: usize,
+                                       newer: usize,
+                                       exclude_loopback: bool)
+        -> Option<f64>
+    {
+        self.total_rate(older,
+                        newer,
+                        exclude_loopback,
+                        InterfaceStats::tx_bytes)
+    }
+
+    /// Rate at which all network interfaces received packets between two
+    /// samples, in packets per second (see total_rx_throughput_between())
+    pub fn total_rx_packet_rate_between(&self,
+                                        older: usize,
+                                        newer: usize,
+                                        exclude_loopback: bool)
+        -> Option<f64>
+    {
+        self.total_rate(older,
+                        newer,
+                        exclude_loopback,
+                        InterfaceStats::rx_packets)
+    }
+
+    /// Rate at which all network interfaces transmitted packets between two
+    /// samples, in packets per second (see total_rx_throughput_between())
+    pub fn total_tx_packet_rate_between(&self,
+                                        older: usize,
+                                        newer: usize,
+                                        exclude_loopback: bool)
+        -> Option<f64>
+    {
+        self.total_rate(older,
+                        newer,
+                        exclude_loopback,
+                        InterfaceStats::tx_packets)
+    }
+
+    /// INTERNAL: Rate of change of a counter of some network interface
+    fn interface_rate<F>(&self,
+                         interface: &str,
+                         older: usize,
+                         newer: usize,
+                         counter: F) -> Option<f64>
+        where F: Fn(&InterfaceStats) -> &[u64]
+    {
+        let stats = self.samples.interface(interface)?;
+        let secs = self.elapsed_secs(older, newer)?;
+        Some(stats.delta(counter(stats), older, newer) as f64 / secs)
+    }
+
+    /// INTERNAL: Rate of change of a counter, summed over all interfaces
+    fn total_rate<F>(&self,
+                     older: usize,
+                     newer: usize,
+                     exclude_loopback: bool,
+                     counter: F) -> Option<f64>
+        where F: Fn(&InterfaceStats) -> &[u64]
+    {
+        let secs = self.elapsed_secs(older, newer)?;
+        let total: u64 =
+            self.samples
+                .interfaces()
+                .iter()
+                .filter(|stats| !(exclude_loopback && stats.is_loopback()))
+                .map(|stats| stats.delta(counter(stats), older, newer))
+                .sum();
+        Some(total as f64 / secs)
+    }
+
+    /// INTERNAL: Wall clock time elapsed between two samples, in seconds, or
+    ///           None if no time elapsed
+    fn elapsed_secs(&self, older: usize, newer: usize) -> Option<f64> {
+        assert!(older <= newer, "Samples should be ordered by age");
+        let elapsed = self.timestamps[newer] - self.timestamps[older];
+        let secs = elapsed.as_secs() as f64
+                   + elapsed.subsec_nanos() as f64 * 1e-9;
+        if secs > 0.0 { Some(secs) } else { None }
+    }
+}
+
+
+/// Incremental parser for /proc/net/dev
+#[derive(Debug, PartialEq)]
+pub struct Parser {}
+//
+impl PseudoFileParser for Parser {
+    /// Build a parser, using an initial file sample. Here, this is used to
+    /// perform quick schema validation, just to maximize the odds that failure,
+    /// if any, will occur at initialization time rather than run time.
+    fn new(initial_contents: &str) -> Self {
+        let mut validation_stream = RecordStream::new(initial_contents);
+        while let Some(record) = validation_stream.next() {
+            record.interface();
+            record.parse_fields();
+        }
+        Self {}
+    }
+}
+//
+// TODO: Implement IncrementalParser once that trait is usable in stable Rust
+impl Parser {
+    /// Parse a pseudo-file sample into a stream of records
+    pub fn parse<'a>(&mut self, file_contents: &'a str) -> RecordStream<'a> {
+        RecordStream::new(file_contents)
+    }
+}
+///
+///
+/// Stream of records from /proc/net/dev
+///
+/// This streaming iterator should yield a stream of records, each representing
+/// a line of /proc/net/dev (i.e. the traffic statistics of an interface).
+///
+pub struct RecordStream<'a> {
+    /// Iterator into the lines and columns of /proc/net/dev
+    file_lines: SplitLinesBySpace<'a>,
+}
+//
+impl<'a> RecordStream<'a> {
+    /// Extract the next record from /proc/net/dev
+    pub fn next<'b>(&'b mut self) -> Option<Record<'a, 'b>>
+        where 'a: 'b
+    {
+        self.file_lines.next().map(Record::new)
+    }
+
+    /// Create a record stream from raw contents
+    fn new(file_contents: &'a str) -> Self {
+        // The file starts with two lines of table headers, which we skip.
+        // Empty readouts, which may occur when racing with the kernel, yield
+        // no record at all.
+        let mut file_lines = SplitLinesBySpace::new(file_contents);
+        if !file_contents.is_empty() {
+            for _ in 0..2 {
+                file_lines.next().expect("Missing /proc/net/dev header");
+            }
+        }
+        Self {
+            file_lines,
+        }
+    }
+}
+///
+///
+/// Record from /proc/net/dev (traffic statistics of one network interface)
+pub struct Record<'a, 'b> where 'a: 'b {
+    /// Interface name field, followed by a colon
+    interface_field: &'a str,
+
+    /// Data columns of the record
+    data_columns: SplitColumns<'a, 'b>,
+}
+//
+impl<'a, 'b> Record<'a, 'b> {
+    /// Tell which network interface this record is about
+    pub fn interface(&self) -> &'a str {
+        // The interface field should end with a colon
+        debug_assert_eq!(self.interface_field.bytes().next_back(), Some(b':'),
+                         "Incorrectly formatted network interface name");
+
+        // The text before that colon is the interface name
+        let field_length = self.interface_field.len();
+        assert!(field_length >= 2, "Unexpected empty interface name");
+        &self.interface_field[..field_length-1]
+    }
+
+    /// Parse the traffic statistics of the network interface
+    fn parse_fields(self) -> RecordFields {
+        RecordFields::new(self.data_columns)
+    }
+
+    /// Construct a record from associated file columns
+    fn new(mut file_columns: SplitColumns<'a, 'b>) -> Self {
+        Self {
+            interface_field: file_columns.next()
+                                         .expect("Missing interface name"),
+            data_columns: file_columns,
+        }
+    }
+}
+
+
+/// Traffic statistics of a network interface, as found in /proc/net/dev
+///
+/// The file also features FIFO, framing, compression, multicast, collision
+/// and carrier statistics, which are rarely of interest for performance
+/// studies and are thus only validated, not kept.
+///
+#[derive(Debug, PartialEq)]
+struct RecordFields {
+    /// Data received, in bytes
+    rx_bytes: u64,
+
+    /// Packets received
+    rx_packets: u64,
+
+    /// Receive errors
+    rx_errors: u64,
+
+    /// Received packets which were dropped
+    rx_drops: u64,
+
+    /// Data transmitted, in bytes
+    tx_bytes: u64,
+
+    /// Packets transmitted
+    tx_packets: u64,
+
+    /// Transmit errors
+    tx_errors: u64,
+
+    /// Packets which were dropped instead of being transmitted
+    tx_drops: u64,
+}
+//
+impl RecordFields {
+    /// Decode the traffic statistics of a network interface
+    fn new<'a, 'b>(mut data_columns: SplitColumns<'a, 'b>) -> Self {
+        // Scope added to address current borrow checker limitation
+        let fields = {
+            // This is how we parse the next counter
+            let mut next_counter = || -> u64 {
+                data_columns.next()
+                            .expect("Missing network interface statistic")
+                            .parse()
+                            .expect("Failed to parse network counter")
+            };
+
+            // Receive statistics come first, then transmit statistics, each
+            // featuring 8 counters of which we only keep the first 4
+            let mut parse_direction = || -> [u64; 4] {
+                let kept = [next_counter(), next_counter(),
+                            next_counter(), next_counter()];
+                for _ in 0..4 { next_counter(); }
+                kept
+            };
+            let rx = parse_direction();
+            let tx = parse_direction();
+            Self {
+                rx_bytes: rx[0],
+                rx_packets: rx[1],
+                rx_errors: rx[2],
+                rx_drops: rx[3],
+                tx_bytes: tx[0],
+                tx_packets: tx[1],
+                tx_errors: tx[2],
+                tx_drops: tx[3],
+            }
+        };
+
+        // In debug mode, check that nothing weird appeared in the input
+        debug_assert_eq!(data_columns.next(), None,
+                         "Unexpected additional network statistic");
+
+        // Return the traffic statistics
+        fields
+    }
+}
+
+
+/// Data samples from /proc/net/dev, in structure-of-array layout
+///
+/// Unlike wireless interfaces, which commonly come and go during a measurement,
+/// the set of network interfaces is assumed to be stable. If it changes, the
+/// sampler reports a schema change and no data is stored.
+///
+#[derive(Debug, PartialEq)]
+pub struct Data {
+    /// Statistics of every network interface, in file order
+    interfaces: Vec<InterfaceStats>,
+
+    /// Number of samples that were recorded
+    sample_count: usize,
+}
+//
+impl SampledData for Data {
+    /// Tell how many samples are present in the data store + check consistency
+    fn len(&self) -> usize {
+        debug_assert!(self.interfaces.iter().all(|iface| {
+            iface.len() == self.sample_count
+        }));
+        self.sample_count
+    }
+
+    /// Drop the oldest samples from the data store
+    fn drop_oldest(&mut self, count: usize) {
+        assert!(count <= self.sample_count, "Not enough samples to drop");
+        for iface in self.interfaces.iter_mut() {
+            iface.drop_oldest(count);
+        }
+        self.sample_count -= count;
+    }
+
+    /// Tell the length of the shortest time series in the data store
+    fn min_len(&self) -> usize {
+        self.interfaces.iter()
+                       .map(|iface| iface.min_len())
+                       .fold(self.sample_count, ::std::cmp::min)
+    }
+
+    /// Drop the newest samples from the data store
+    fn truncate(&mut self, length: usize) {
+        assert!(length <= self.sample_count, "Not enough samples to keep");
+        for iface in self.interfaces.iter_mut() {
+            iface.truncate(length);
+        }
+        self.sample_count = length;
+    }
+}
+//
+// TODO: Implement SampledDataIncremental once that is usable in stable Rust
+impl Data {
+    /// Statistics of every network interface, in the order of /proc/net/dev
+    pub fn interfaces(&self) -> &[InterfaceStats] {
+        &self.interfaces
+    }
+
+    /// Statistics of a specific network interface, if it exists
+    pub fn interface(&self, name: &str) -> Option<&InterfaceStats> {
+        self.interfaces.iter().find(|iface| iface.name == name)
+    }
+
+    /// Indices of the samples which were acquired after the counters of a
+    /// network interface were reset, or nothing if there is no such interface
+    /// (see InterfaceStats::reset_indices())
+    pub fn reset_indices(&self, interface: &str) -> Vec<usize> {
+        self.interface(interface)
+            .map(|iface| iface.reset_indices().to_vec())
+            .unwrap_or_default()
+    }
+
+    /// Truth that the error or drop counters of a network interface increased
+    /// at some point of the measurement, or false if there is no such
+    /// interface (see InterfaceStats::has_errors())
+    pub fn has_errors(&self, interface: &str) -> bool {
+        self.interface(interface).map_or(false, InterfaceStats::has_errors)
+    }
+
+    /// Create a new network interface data store, using a first sample to
+    /// know which interfaces are present on this system
+    fn new(mut stream: RecordStream) -> Self {
+        let mut interfaces = Vec::new();
+        while let Some(record) = stream.next() {
+            interfaces.push(InterfaceStats::new(record.interface()));
+        }
+        Self {
+            interfaces,
+            sample_count: 0,
+        }
+    }
+
+    /// Parse the contents of /proc/net/dev and add a data sample to all
+    /// corresponding entries in the internal data store
+    fn push(&mut self, mut stream: RecordStream) -> Result<(), SchemaChanged> {
+        // Record the statistics of each interface, for as long as interfaces
+        // appear in the same order as in the first sample
+        let mut recorded = 0;
+        for iface in self.interfaces.iter_mut() {
+            match stream.next() {
+                Some(ref record) if record.interface() != iface.name => break,
+                Some(record) => iface.push(record.parse_fields()),
+                None => break,
+            }
+            recorded += 1;
+        }
+
+        // If the set of interfaces changed, roll back the partial sample
+        if recorded < self.interfaces.len() || stream.next().is_some() {
+            for iface in self.interfaces[..recorded].iter_mut() {
+                iface.truncate(self.sample_count);
+            }
+            return Err(
+                SchemaChanged::new("The set of network interfaces changed")
+            );
+        }
+        self.sample_count += 1;
+        Ok(())
+    }
+}
+//
+/// Statistics are exported under the name of the network interface, followed
+/// by the name of the statistic (e.g. "eth0_rx_bytes")
+impl Columns for Data {
+    fn columns(&self) -> Vec<(String, Column)> {
+        let mut columns = Vec::new();
+        for iface in self.interfaces.iter() {
+            let bytes = |vec: &Vec<u64>| Column::Bytes(vec.clone());
+            let interface_columns = vec![
+                ("rx_bytes", bytes(&iface.rx_bytes)),
+                ("rx_packets", Column::counts(&iface.rx_packets)),
+                ("rx_errors", Column::counts(&iface.rx_errors)),
+                ("rx_drops", Column::counts(&iface.rx_drops)),
+                ("tx_bytes", bytes(&iface.tx_bytes)),
+                ("tx_packets", Column::counts(&iface.tx_packets)),
+                ("tx_errors", Column::counts(&iface.tx_errors)),
+                ("tx_drops", Column::counts(&iface.tx_drops)),
+            ];
+            columns.extend(interface_columns.into_iter().map(|(name, col)| {
+                (format!("{}_{}", iface.name, name), col)
+            }));
+        }
+        columns
+    }
+}
+///
+///
+/// Sampled traffic statistics of one network interface
+#[derive(Debug, PartialEq)]
+pub struct InterfaceStats {
+    /// Name of the network interface
+    name: String,
+
+    /// Data received, in bytes
+    rx_bytes: Vec<u64>,
+
+    /// Packets received
+    rx_packets: Vec<u64>,
+
+    /// Receive errors
+    rx_errors: Vec<u64>,
+
+    /// Received packets which were dropped
+    rx_drops: Vec<u64>,
+
+    /// Data transmitted, in bytes
+    tx_bytes: Vec<u64>,
+
+    /// Packets transmitted
+    tx_packets: Vec<u64>,
+
+    /// Transmit errors
+    tx_errors: Vec<u64>,
+
+    /// Packets which were dropped instead of being transmitted
+    tx_drops: Vec<u64>,
+
+    /// Indices of the samples which were taken after a counter reset, as
+    /// detected by a counter decreasing. Deltas must not be computed across
+    /// these sample boundaries.
+    reset_indices: Vec<usize>,
+}
+//
+impl InterfaceStats {
+    /// Name of the network interface
+    pub fn name(&self) -> &str { &self.name }
+
+    /// Truth that this is the loopback interface, whose traffic never leaves
+    /// the machine
+    pub fn is_loopback(&self) -> bool { self.name == "lo" }
+
+    /// Data received, in bytes
+    pub fn rx_bytes(&self) -> &[u64] { &self.rx_bytes }
+
+    /// Packets received
+    pub fn rx_packets(&self) -> &[u64] { &self.rx_packets }
+
+    /// Receive errors
+    pub fn rx_errors(&self) -> &[u64] { &self.rx_errors }
+
+    /// Received packets which were dropped
+    pub fn rx_drops(&self) -> &[u64] { &self.rx_drops }
+
+    /// Data transmitted, in bytes
+    pub fn tx_bytes(&self) -> &[u64] { &self.tx_bytes }
+
+    /// Packets transmitted
+    pub fn tx_packets(&self) -> &[u64] { &self.tx_packets }
+
+    /// Transmit errors
+    pub fn tx_errors(&self) -> &[u64] { &self.tx_errors }
+
+    /// Packets which were dropped instead of being transmitted
+    pub fn tx_drops(&self) -> &[u64] { &self.tx_drops }
+
+    /// Indices of the samples which were acquired after a counter reset
+    ///
+    /// Some network drivers reset the counters of an interface when it goes
+    /// down and up again. This is detected by any counter of the interface
+    /// decreasing between two consecutive samples.
+    ///
+    pub fn reset_indices(&self) -> &[usize] { &self.reset_indices }
+
+    /// Increase of a counter of this interface (such as rx_bytes()) between
+    /// two sample indices, accounting for counter resets
+    ///
+    /// If the counters were reset between these samples, the counter value
+    /// from the newer sample is returned as the best available estimate of
+    /// the increase.
+    ///
+    pub fn delta(&self, counter: &[u64], older: usize, newer: usize) -> u64 {
+        assert!(older <= newer, "Samples should be ordered by age");
+        let reset = self.reset_indices
+                        .iter()
+                        .any(|&idx| idx > older && idx <= newer);
+        if reset {
+            counter[newer]
+        } else {
+            counter[newer] - counter[older]
+        }
+    }
+
+    /// Truth that any of the error or drop counters of this interface
+    /// increased between two consecutive samples
+    ///
+    /// Errors and drops which occurred before the first sample do not count,
+    /// as they may be long gone. Counter resets are accounted for as in
+    /// delta(), so errors which occurred after a reset do count.
+    ///
+    pub fn has_errors(&self) -> bool {
+        let counters = [&self.rx_errors, &self.rx_drops,
+                        &self.tx_errors, &self.tx_drops];
+        (1..self.len()).any(|newer| {
+            counters.iter()
+                    .any(|counter| self.delta(counter, newer-1, newer) > 0)
+        })
+    }
+
+    /// Start tracking the statistics of a network interface
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            rx_bytes: Vec::new(),
+            rx_packets: Vec::new(),
+            rx_errors: Vec::new(),
+            rx_drops: Vec::new(),
+            tx_bytes: Vec::new(),
+            tx_packets: Vec::new(),
+            tx_errors: Vec::new(),
+            tx_drops: Vec::new(),
+            reset_indices: Vec::new(),
+        }
+    }
+
+    /// Record the statistics of this interface for a new sample
+    fn push(&mut self, fields: RecordFields) {
+        // If any counter went down, the counters were reset
+        let sample_idx = self.rx_bytes.len();
+        if sample_idx > 0 {
+            let last = sample_idx - 1;
+            let reset = fields.rx_bytes < self.rx_bytes[last]
+                        || fields.rx_packets < self.rx_packets[last]
+                        || fields.rx_errors < self.rx_errors[last]
+                        || fields.rx_drops < self.rx_drops[last]
+                        || fields.tx_bytes < self.tx_bytes[last]
+                        || fields.tx_packets < self.tx_packets[last]
+                        || fields.tx_errors < self.tx_errors[last]
+                        || fields.tx_drops < self.tx_drops[last];
+            if reset {
+                self.reset_indices.push(sample_idx);
+            }
+        }
+
+        // Record the new counter values
+        self.rx_bytes.push(fields.rx_bytes);
+        self.rx_packets.push(fields.rx_packets);
+        self.rx_errors.push(fields.rx_errors);
+        self.rx_drops.push(fields.rx_drops);
+        self.tx_bytes.push(fields.tx_bytes);
+        self.tx_packets.push(fields.tx_packets);
+        self.tx_errors.push(fields.tx_errors);
+        self.tx_drops.push(fields.tx_drops);
+    }
+
+    /// INTERNAL: All the time series of this interface
+    fn counters_mut(&mut self) -> [&mut Vec<u64>; 8] {
+        [&mut self.rx_bytes, &mut self.rx_packets,
+         &mut self.rx_errors, &mut self.rx_drops,
+         &mut self.tx_bytes, &mut self.tx_packets,
+         &mut self.tx_errors, &mut self.tx_drops]
+    }
+
+    /// Tell how many samples are present + check consistency
+    fn len(&self) -> usize {
+        let length = self.rx_bytes.len();
+        debug_assert!(self.min_len() == length);
+        length
+    }
+
+    /// Drop the oldest samples
+    fn drop_oldest(&mut self, count: usize) {
+        for counter in self.counters_mut().iter_mut() {
+            counter.drain(..count);
+        }
+        self.reset_indices.retain(|&idx| idx >= count);
+        for idx in self.reset_indices.iter_mut() {
+            *idx -= count;
+        }
+    }
+
+    /// Tell the length of the shortest time series
+    fn min_len(&self) -> usize {
+        [self.rx_packets.len(), self.rx_errors.len(), self.rx_drops.len(),
+         self.tx_bytes.len(), self.tx_packets.len(), self.tx_errors.len(),
+         self.tx_drops.len()].iter()
+                             .fold(self.rx_bytes.len(), |min, &len| {
+                                 min.min(len)
+                             })
+    }
+
+    /// Drop the newest samples
+    fn truncate(&mut self, length: usize) {
+        for counter in self.counters_mut().iter_mut() {
+            counter.truncate(length);
+        }
+        self.reset_indices.retain(|&idx| idx < length);
+    }
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use ::data::SampledData;
+    use ::parser::PseudoFileParser;
+    use std::time::Duration;
+    use super::{Data, Parser, RecordFields, RecordStream, Sampler};
+
+    /// Table header of /proc/net/dev
+    const HEADER: &str =
+        "Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed\n";
+
+    /// Build mock file contents, from (interface, rx bytes, tx bytes) tuples.
+    /// Packet counts are set to a tenth of byte counts.
+    fn mock_file(interfaces: &[(&str, u64, u64)]) -> String {
+        let mut file = HEADER.to_owned();
+        for &(name, rx, tx) in interfaces {
+            file.push_str(&format!(
+                "{:>6}: {} {} 1 2 0 0 0 0 {} {} 3 4 0 0 0 0\n",
+                name, rx, rx / 10, tx, tx / 10
+            ));
+        }
+        file
+    }
+
+    /// Check that record streams skip the headers and work as expected
+    #[test]
+    fn record_stream() {
+        let mut file = HEADER.to_owned();
+        file.push_str("    lo: 125219711   11680    0    0    0     0          0         0 125219711   11680    0    0    0     0       0          0\n");
+        file.push_str("  eth0: 9876543 54321 7 8 0 0 0 12 1234567 4321 5 6 0 3 0 0\n");
+        let mut parser = Parser::new(&file);
+        let mut stream = parser.parse(&file);
+        {
+            let record = stream.next().expect("Missing first record");
+            assert_eq!(record.interface(), "lo");
+            let fields = record.parse_fields();
+            assert_eq!(fields.rx_bytes, 125219711);
+            assert_eq!(fields.tx_packets, 11680);
+        }
+        {
+            let record = stream.next().expect("Missing second record");
+            assert_eq!(record.interface(), "eth0");
+            assert_eq!(record.parse_fields(), RecordFields {
+                rx_bytes: 9876543,
+                rx_packets: 54321,
+                rx_errors: 7,
+                rx_drops: 8,
+                tx_bytes: 1234567,
+                tx_packets: 4321,
+                tx_errors: 5,
+                tx_drops: 6,
+            });
+        }
+        assert!(stream.next().is_none());
+    }
+
+    /// Check that sampled data works as expected, and that changes to the set
+    /// of interfaces are reported without storing any data
+    #[test]
+    fn sampled_data() {
+        let file1 = mock_file(&[("lo", 100, 100), ("eth0", 1000, 500)]);
+        let file2 = mock_file(&[("lo", 200, 200), ("eth0", 3000, 800)]);
+        let mut data = Data::new(RecordStream::new(&file1));
+        assert_eq!(data.len(), 0);
+        assert_eq!(data.interfaces().len(), 2);
+        data.push(RecordStream::new(&file1)).unwrap();
+        data.push(RecordStream::new(&file2)).unwrap();
+        assert_eq!(data.len(), 2);
+        let eth0 = data.interface("eth0").expect("Missing interface");
+        assert!(!eth0.is_loopback());
+        assert_eq!(eth0.rx_bytes(), &[1000, 3000]);
+        assert_eq!(eth0.rx_packets(), &[100, 300]);
+        assert_eq!(eth0.rx_errors(), &[1, 1]);
+        assert_eq!(eth0.rx_drops(), &[2, 2]);
+        assert_eq!(eth0.tx_bytes(), &[500, 800]);
+        assert_eq!(eth0.tx_packets(), &[50, 80]);
+        assert_eq!(eth0.tx_errors(), &[3, 3]);
+        assert_eq!(eth0.tx_drops(), &[4, 4]);
+        assert_eq!(eth0.delta(eth0.tx_bytes(), 0, 1), 300);
+        assert!(data.interface("lo").unwrap().is_loopback());
+
+        // Interfaces which appear or disappear are schema changes
+        let missing = mock_file(&[("lo", 300, 300)]);
+        assert!(data.push(RecordStream::new(&missing)).is_err());
+        let added = mock_file(&[("lo", 300, 300),
+                                ("eth0", 4000, 900),
+                                ("eth1", 0, 0)]);
+        assert!(data.push(RecordStream::new(&added)).is_err());
+        assert_eq!(data.len(), 2);
+        assert_eq!(data.min_len(), 2);
+        assert_eq!(data.interface("lo").unwrap().rx_bytes(), &[100, 200]);
+
+        // Dropping old samples works
+        data.drop_oldest(1);
+        assert_eq!(data.len(), 1);
+        assert_eq!(data.interface("lo").unwrap().rx_bytes(), &[200]);
+    }
+
+    /// Check that throughputs and packet rates are computed correctly
+    #[test]
+    fn rates() {
+        // Replace the samples of a sampler with known data, acquired two
+        // seconds apart from each other
+        let mut sampler = Sampler::new().expect("Failed to create a sampler");
+        let file1 = mock_file(&[("lo", 1000, 1000), ("eth0", 1000, 500)]);
+        let file2 = mock_file(&[("lo", 9000, 9000), ("eth0", 3000, 900)]);
+        let mut data = Data::new(RecordStream::new(&file1));
+        data.push(RecordStream::new(&file1)).unwrap();
+        data.push(RecordStream::new(&file2)).unwrap();
+        let start = ::std::time::Instant::now();
+        sampler.samples = data;
+        sampler.timestamps = vec![start, start + Duration::from_secs(2)];
+
+        // Check the per-interface rates
+        assert_eq!(sampler.rx_throughput_between("eth0", 0, 1), Some(1000.));
+        assert_eq!(sampler.tx_throughput_between("eth0", 0, 1), Some(200.));
+        assert_eq!(sampler.rx_packet_rate_between("eth0", 0, 1), Some(100.));
+        assert_eq!(sampler.tx_packet_rate_between("eth0", 0, 1), Some(20.));
+        assert_eq!(sampler.rx_throughput_between("eth1", 0, 1), None);
+        assert_eq!(sampler.rx_throughput_between("eth0", 1, 1), None);
+
+        // Check the aggregate rates, with and without loopback
+        assert_eq!(sampler.total_rx_throughput_between(0, 1, false),
+                   Some(5000.));
+        assert_eq!(sampler.total_rx_throughput_between(0, 1, true),
+                   Some(1000.));
+        assert_eq!(sampler.total_tx_throughput_between(0, 1, true),
+                   Some(200.));
+        assert_eq!(sampler.total_rx_packet_rate_between(0, 1, false),
+                   Some(500.));
+        assert_eq!(sampler.total_tx_packet_rate_between(0, 1, false),
+                   Some(420.));
+    }
+
+    /// Check that counter resets are detected and not accounted as traffic
+    #[test]
+    fn counter_resets() {
+        // The eth0 interface goes down and up between samples 1 and 2
+        let files = [mock_file(&[("lo", 100, 100), ("eth0", 5000, 2000)]),
+                     mock_file(&[("lo", 200, 200), ("eth0", 7000, 3000)]),
+                     mock_file(&[("lo", 300, 300), ("eth0", 400, 100)]),
+                     mock_file(&[("lo", 400, 400), ("eth0", 900, 300)])];
+        let mut sampler = Sampler::new().expect("Failed to create a sampler");
+        let mut data = Data::new(RecordStream::new(&files[0]));
+        for file in files.iter() {
+            data.push(RecordStream::new(file)).unwrap();
+        }
+        assert_eq!(data.reset_indices("eth0"), vec![2]);
+        assert_eq!(data.reset_indices("lo"), Vec::<usize>::new());
+        assert_eq!(data.reset_indices("eth1"), Vec::<usize>::new());
+
+        // Deltas across the reset only account for post-reset traffic
+        {
+            let eth0 = data.interface("eth0").unwrap();
+            assert_eq!(eth0.delta(eth0.rx_bytes(), 0, 1), 2000);
+            assert_eq!(eth0.delta(eth0.rx_bytes(), 1, 2), 400);
+            assert_eq!(eth0.delta(eth0.rx_bytes(), 0, 3), 900);
+            assert_eq!(eth0.delta(eth0.rx_bytes(), 2, 3), 500);
+        }
+
+        // So do the rates
+        let start = ::std::time::Instant::now();
+        sampler.samples = data;
+        sampler.timestamps = (0..4).map(|i| start + Duration::from_secs(i))
+                                   .collect();
+        assert_eq!(sampler.rx_throughput_between("eth0", 1, 2), Some(400.));
+        assert_eq!(sampler.tx_throughput_between("eth0", 1, 2), Some(100.));
+        assert_eq!(sampler.total_rx_throughput_between(1, 2, false),
+                   Some(500.));
+
+        // Reset indices are kept in sync when samples are dropped
+        sampler.samples.drop_oldest(1);
+        assert_eq!(sampler.samples.reset_indices("eth0"), vec![1]);
+        sampler.samples.truncate(1);
+        assert_eq!(sampler.samples.reset_indices("eth0"), Vec::<usize>::new());
+
+        // A partial sample which is rolled back does not leave a reset behind
+        let mut data = Data::new(RecordStream::new(&files[0]));
+        data.push(RecordStream::new(&files[1])).unwrap();
+        let added = mock_file(&[("lo", 0, 0),
+                                ("eth0", 0, 0),
+                                ("eth1", 0, 0)]);
+        assert!(data.push(RecordStream::new(&added)).is_err());
+        assert_eq!(data.reset_indices("lo"), Vec::<usize>::new());
+    }
+
+    /// Check that error and drop rates are computed correctly
+    #[test]
+    fn error_rates() {
+        // Packets start being dropped on eth0, but not on eth1
+        let files = [
+            format!("{}  eth0: 1000 100 5 10 0 0 0 0 1000 100 0 0 0 0 0 0\n\
+                          eth1: 1000 100 3 0 0 0 0 0 1000 100 0 0 0 0 0 0\n",
+                    HEADER),
+            format!("{}  eth0: 2000 200 5 30 0 0 0 0 2000 200 1 0 0 0 0 0\n\
+                          eth1: 2000 200 3 0 0 0 0 0 2000 200 0 0 0 0 0 0\n",
+                    HEADER),
+            format!("{}  eth0: 3000 300 5 70 0 0 0 0 3000 300 1 2 0 0 0 0\n\
+                          eth1: 3000 300 3 0 0 0 0 0 3000 300 0 0 0 0 0 0\n",
+                    HEADER),
+        ];
+        let mut sampler = Sampler::new().expect("Failed to create a sampler");
+        let mut data = Data::new(RecordStream::new(&files[0]));
+        for file in files.iter() {
+            data.push(RecordStream::new(file)).unwrap();
+        }
+        let start = ::std::time::Instant::now();
+        sampler.samples = data;
+        sampler.timestamps = (0..3).map(|i| start + Duration::from_secs(2*i))
+                                   .collect();
+
+        // Check the per-interface rates
+        assert_eq!(sampler.rx_error_rate_between("eth0", 0, 2), Some(0.));
+        assert_eq!(sampler.rx_drop_rate_between("eth0", 0, 1), Some(10.));
+        assert_eq!(sampler.rx_drop_rate_between("eth0", 1, 2), Some(20.));
+        assert_eq!(sampler.tx_error_rate_between("eth0", 0, 1), Some(0.5));
+        assert_eq!(sampler.tx_drop_rate_between("eth0", 0, 2), Some(0.5));
+        assert_eq!(sampler.rx_drop_rate_between("eth1", 0, 2), Some(0.));
+        assert_eq!(sampler.rx_drop_rate_between("eth2", 0, 2), None);
+
+        // Errors which predate the measurement are not reported
+        assert!(sampler.samples.has_errors("eth0"));
+        assert!(!sampler.samples.has_errors("eth1"));
+        assert!(!sampler.samples.has_errors("eth2"));
+
+        // Errors which occur after a counter reset are reported
+        let reset =
+            format!("{}  eth0: 100 10 0 0 0 0 0 0 100 10 0 0 0 0 0 0\n\
+                          eth1: 100 10 1 0 0 0 0 0 100 10 0 0 0 0 0 0\n",
+                    HEADER);
+        sampler.samples.push(RecordStream::new(&reset)).unwrap();
+        assert_eq!(sampler.samples.reset_indices("eth1"), vec![3]);
+        assert!(sampler.samples.has_errors("eth1"));
+    }
+
+    /// Check that the sampler works well
+    define_sampler_tests!{ super::Sampler }
+}
+
+
+/// Performance benchmarks
+///
+/// See the lib-wide benchmarks module for details on how to use these.
+///
+#[cfg(test)]
+mod benchmarks {
+    define_sampler_benchs!{ super::Sampler,
+                            "/proc/net/dev",
+                            100_000 }
+}
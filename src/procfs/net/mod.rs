@@ -0,0 +1,12 @@
+//! This module contains parsers for the contents of /proc/net
+//!
+//! /proc/net is actually a symlink to /proc/self/net, which describes the
+//! network configuration and statistics of the active process' networking
+//! namespace. Each submodule corresponds to one file in there.
+
+pub mod dev;
+pub mod tcp;
+pub mod tcp6;
+pub mod udp;
+pub mod udp6;
+pub mod wireless;
@@ -0,0 +1,472 @@
+//! This module contains a sampling parser for /proc/net/tcp
+//!
+//! On busy servers, this file can list many thousands of connections, and we
+//! are usually not interested in any individual one of them. So instead of
+//! storing connection details, this parser builds a histogram of connection
+//! states for every sample, which is what one needs in order to diagnose
+//! issues such as an accumulation of sockets in the TIME_WAIT state.
+//!
+//! The same parser is used for /proc/net/tcp6, see the "tcp6" module.
+
+use ::columns::{Column, Columns};
+use ::data::{SampledData, SchemaChanged};
+use ::parser::PseudoFileParser;
+use ::splitter::{SplitColumns, SplitLinesBySpace};
+
+
+// Implement a sampler for /proc/net/tcp. Socket tables can be large on busy
+// servers, so we reserve some room for them upfront (~400 sockets).
+define_sampler!{ Sampler : "/proc/net/tcp", buffer 65536 => Parser
+                                                         => RecordStream
+                                                         => Data }
+
+
+/// Incremental parser for /proc/net/tcp and /proc/net/tcp6
+#[derive(Debug, PartialEq)]
+pub struct Parser {}
+//
+impl PseudoFileParser for Parser {
+    /// Build a parser, using an initial file sample. Here, this is used to
+    /// perform quick schema validation, just to maximize the odds that failure,
+    /// if any, will occur at initialization time rather than run time.
+    fn new(initial_contents: &str) -> Self {
+        let mut validation_stream = RecordStream::new(initial_contents);
+        while let Some(record) = validation_stream.next() {
+            Record::check_address(record.local_address());
+            Record::check_address(record.remote_address());
+            debug_assert!(record.state().is_some(),
+                          "Unknown TCP connection state");
+        }
+        Self {}
+    }
+}
+//
+// TODO: Implement IncrementalParser once that trait is usable in stable Rust
+impl Parser {
+    /// Parse a pseudo-file sample into a stream of records
+    pub fn parse<'a>(&mut self, file_contents: &'a str) -> RecordStream<'a> {
+        RecordStream::new(file_contents)
+    }
+}
+///
+///
+/// Stream of records from /proc/net/tcp or /proc/net/tcp6
+///
+/// This streaming iterator should yield a stream of records, each representing
+/// a line of the file (i.e. one TCP socket). Records are parsed lazily, and
+/// nothing is allocated per socket.
+///
+pub struct RecordStream<'a> {
+    /// Iterator into the lines and columns of the file
+    file_lines: SplitLinesBySpace<'a>,
+
+    /// Truth that the readout was empty, not even featuring a header
+    empty_readout: bool,
+}
+//
+impl<'a> RecordStream<'a> {
+    /// Extract the next record from the file
+    pub fn next<'b>(&'b mut self) -> Option<Record<'a>>
+        where 'a: 'b
+    {
+        self.file_lines.next().map(Record::new)
+    }
+
+    /// Create a record stream from raw contents
+    fn new(file_contents: &'a str) -> Self {
+        // The file starts with a line of table headers, which we skip. Empty
+        // readouts, which may occur when racing with the kernel, yield no
+        // record at all.
+        let mut file_lines = SplitLinesBySpace::new(file_contents);
+        let empty_readout = file_contents.is_empty();
+        if !empty_readout {
+            file_lines.next().expect("Missing /proc/net/tcp header");
+        }
+        Self {
+            file_lines,
+            empty_readout,
+        }
+    }
+}
+///
+///
+/// Record from /proc/net/tcp or /proc/net/tcp6 (one TCP socket)
+///
+/// Only the leading columns of the record are extracted, as the remaining ones
+/// (queue sizes, timers, owner...) are not used by this parser.
+///
+pub struct Record<'a> {
+    /// Local address and port, in hexadecimal "ADDRESS:PORT" format
+    local_address: &'a str,
+
+    /// Remote address and port, in hexadecimal "ADDRESS:PORT" format
+    remote_address: &'a str,
+
+    /// Connection state, as a hexadecimal kernel code
+    state_column: &'a str,
+}
+//
+impl<'a> Record<'a> {
+    /// Local address and port of the socket
+    ///
+    /// Addresses are given in hexadecimal form, using 8 hex digits for IPv4
+    /// (in /proc/net/tcp) and 32 hex digits for IPv6 (in /proc/net/tcp6),
+    /// followed by a colon and a 4-digit hexadecimal port number.
+    ///
+    pub fn local_address(&self) -> &'a str {
+        self.local_address
+    }
+
+    /// Remote address and port of the socket (see local_address())
+    pub fn remote_address(&self) -> &'a str {
+        self.remote_address
+    }
+
+    /// Decode the state of the TCP connection, if it is known to this parser
+    ///
+    /// Future kernels may introduce new connection states. Those, along with
+    /// malformed state codes, are reported as None.
+    ///
+    pub fn state(&self) -> Option<TcpState> {
+        u8::from_str_radix(self.state_column, 16).ok()
+                                                 .and_then(TcpState::from_code)
+    }
+
+    /// INTERNAL: Check that a socket address looks like what we expect
+    fn check_address(address: &str) {
+        let mut parts = address.split(':');
+        let host = parts.next().expect("Missing socket address");
+        let port = parts.next().expect("Missing socket port");
+        assert!(host.len() == 8 || host.len() == 32,
+                "Unexpected socket address length");
+        assert_eq!(port.len(), 4, "Unexpected socket port length");
+        debug_assert!(host.chars().chain(port.chars())
+                                  .all(|c| c.is_digit(16)),
+                      "Non-hexadecimal socket address detected");
+        debug_assert_eq!(parts.next(), None, "Unexpected socket address data");
+    }
+
+    /// Construct a record from associated file columns
+    fn new<'b>(mut file_columns: SplitColumns<'a, 'b>) -> Self
+        where 'a: 'b
+    {
+        // The first column is the socket slot number, which we do not need
+        let slot = file_columns.next().expect("Missing socket slot number");
+        debug_assert_eq!(slot.bytes().next_back(), Some(b':'),
+                         "Incorrectly formatted socket slot number");
+
+        // Then come the addresses and the connection state
+        let mut next_column = || -> &'a str {
+            file_columns.next().expect("Missing TCP socket column")
+        };
+        Self {
+            local_address: next_column(),
+            remote_address: next_column(),
+            state_column: next_column(),
+        }
+    }
+}
+
+
+/// State of a TCP connection, as defined by the Linux kernel
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TcpState {
+    /// Connection is open and exchanging data
+    Established = 0x01,
+
+    /// Connection request was sent, waiting for a reply
+    SynSent = 0x02,
+
+    /// Connection request was received and answered
+    SynRecv = 0x03,
+
+    /// Local end was closed, waiting for acknowledgement
+    FinWait1 = 0x04,
+
+    /// Local end was closed and acknowledged, waiting for remote end
+    FinWait2 = 0x05,
+
+    /// Connection is closed, lingering to absorb stray packets
+    TimeWait = 0x06,
+
+    /// Socket is not in use
+    Close = 0x07,
+
+    /// Remote end was closed, waiting for the local end to close
+    CloseWait = 0x08,
+
+    /// Both ends were closed, waiting for final acknowledgement
+    LastAck = 0x09,
+
+    /// Socket is waiting for incoming connection requests
+    Listen = 0x0A,
+
+    /// Both ends were closed simultaneously
+    Closing = 0x0B,
+
+    /// Lightweight request socket (since Linux 4.4)
+    NewSynRecv = 0x0C,
+}
+//
+impl TcpState {
+    /// Every TCP state, in kernel code order
+    pub const ALL: [TcpState; NUM_STATES] = [
+        TcpState::Established,
+        TcpState::SynSent,
+        TcpState::SynRecv,
+        TcpState::FinWait1,
+        TcpState::FinWait2,
+        TcpState::TimeWait,
+        TcpState::Close,
+        TcpState::CloseWait,
+        TcpState::LastAck,
+        TcpState::Listen,
+        TcpState::Closing,
+        TcpState::NewSynRecv,
+    ];
+
+    /// Decode a kernel TCP state code, if it is known
+    pub fn from_code(code: u8) -> Option<Self> {
+        if code >= 1 && (code as usize) <= NUM_STATES {
+            Some(Self::ALL[code as usize - 1])
+        } else {
+            None
+        }
+    }
+
+    /// Name of this state in the Linux kernel sources, in lower case (e.g.
+    /// "time_wait" for TCP_TIME_WAIT)
+    pub fn name(self) -> &'static str {
+        match self {
+            TcpState::Established => "established",
+            TcpState::SynSent => "syn_sent",
+            TcpState::SynRecv => "syn_recv",
+            TcpState::FinWait1 => "fin_wait1",
+            TcpState::FinWait2 => "fin_wait2",
+            TcpState::TimeWait => "time_wait",
+            TcpState::Close => "close",
+            TcpState::CloseWait => "close_wait",
+            TcpState::LastAck => "last_ack",
+            TcpState::Listen => "listen",
+            TcpState::Closing => "closing",
+            TcpState::NewSynRecv => "new_syn_recv",
+        }
+    }
+
+    /// INTERNAL: Position of this state in state-indexed arrays
+    fn index(self) -> usize {
+        self as usize - 1
+    }
+}
+//
+/// Number of known TCP states
+const NUM_STATES: usize = 12;
+
+
+/// Data samples from /proc/net/tcp, in structure-of-array layout
+///
+/// For each sample, we record how many sockets were in each TCP state.
+///
+#[derive(Debug, PartialEq)]
+pub struct Data {
+    /// Number of sockets in each TCP state, indexed by TcpState::index()
+    connections: [Vec<u32>; NUM_STATES],
+}
+//
+impl SampledData for Data {
+    /// Tell how many samples are present in the data store + check consistency
+    fn len(&self) -> usize {
+        let length = self.connections[0].len();
+        debug_assert!(self.connections.iter().all(|vec| vec.len() == length));
+        length
+    }
+
+    /// Drop the oldest samples from the data store
+    fn drop_oldest(&mut self, count: usize) {
+        for vec in self.connections.iter_mut() {
+            vec.drain(..count);
+        }
+    }
+
+    /// Tell the length of the shortest time series in the data store
+    fn min_len(&self) -> usize {
+        self.connections.iter()
+                        .map(|vec| vec.len())
+                        .min()
+                        .expect("TCP states should exist")
+    }
+
+    /// Drop the newest samples from the data store
+    fn truncate(&mut self, length: usize) {
+        for vec in self.connections.iter_mut() {
+            vec.truncate(length);
+        }
+    }
+}
+//
+// TODO: Implement SampledDataIncremental once that is usable in stable Rust
+impl Data {
+    /// Number of sockets which were in a certain TCP state, for each sample
+    pub fn connections(&self, state: TcpState) -> &[u32] {
+        &self.connections[state.index()]
+    }
+
+    /// Create a new TCP connection state data store
+    pub(super) fn new(_stream: RecordStream) -> Self {
+        Self {
+            connections: Default::default(),
+        }
+    }
+
+    /// Parse the contents of /proc/net/tcp and add a data sample to the
+    /// internal data store
+    ///
+    /// Sockets whose state is unknown to this parser are not counted. Empty
+    /// readouts, which lack the header of the file, are rejected.
+    ///
+    pub(super) fn push(&mut self,
+                       mut stream: RecordStream) -> Result<(), SchemaChanged> {
+        if stream.empty_readout {
+            return Err(
+                SchemaChanged::new("The /proc/net/tcp header is missing")
+            );
+        }
+
+        // Build the histogram of TCP states on the stack
+        let mut histogram = [0u32; NUM_STATES];
+        while let Some(record) = stream.next() {
+            if let Some(state) = record.state() {
+                histogram[state.index()] += 1;
+            }
+        }
+
+        // Store it in the data store
+        for (vec, &count) in self.connections.iter_mut().zip(histogram.iter()) {
+            vec.push(count);
+        }
+        Ok(())
+    }
+}
+//
+/// Each TCP state is exported under its name (see TcpState::name())
+impl Columns for Data {
+    fn columns(&self) -> Vec<(String, Column)> {
+        TcpState::ALL.iter().map(|&state| {
+            (state.name().to_owned(), Column::counts(self.connections(state)))
+        }).collect()
+    }
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use ::data::SampledData;
+    use ::parser::PseudoFileParser;
+    use super::{Data, Parser, RecordStream, TcpState};
+
+    /// Header of /proc/net/tcp
+    const HEADER: &str =
+        "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n";
+
+    /// Check that TCP state codes are decoded properly
+    #[test]
+    fn state_codes() {
+        assert_eq!(TcpState::from_code(0x00), None);
+        assert_eq!(TcpState::from_code(0x01), Some(TcpState::Established));
+        assert_eq!(TcpState::from_code(0x06), Some(TcpState::TimeWait));
+        assert_eq!(TcpState::from_code(0x0A), Some(TcpState::Listen));
+        assert_eq!(TcpState::from_code(0x0C), Some(TcpState::NewSynRecv));
+        assert_eq!(TcpState::from_code(0x0D), None);
+    }
+
+    /// Check that record streams skip the header and work for IPv4 and IPv6
+    #[test]
+    fn record_stream() {
+        let mut file = HEADER.to_owned();
+        file.push_str("   0: 00000000:07E8 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 662 1 0000000047647ebb 100 0 0 10 0\n");
+        file.push_str("   1: 00000000000000000000000001000000:0277 00000000000000000000000001000000:E5C2 06 00000000:00000000 03:00000A6B 00000000     0        0 0 3 0000000000000000\n");
+        let mut parser = Parser::new(&file);
+        let mut stream = parser.parse(&file);
+        {
+            let record = stream.next().expect("Missing first record");
+            assert_eq!(record.local_address(), "00000000:07E8");
+            assert_eq!(record.remote_address(), "00000000:0000");
+            assert_eq!(record.state(), Some(TcpState::Listen));
+        }
+        {
+            let record = stream.next().expect("Missing second record");
+            assert_eq!(record.local_address(),
+                       "00000000000000000000000001000000:0277");
+            assert_eq!(record.state(), Some(TcpState::TimeWait));
+        }
+        assert!(stream.next().is_none());
+    }
+
+    /// Check that sampled data works as expected
+    #[test]
+    fn sampled_data() {
+        // Build some mock file contents
+        let with_states = |states: &[&str]| -> String {
+            let mut file = HEADER.to_owned();
+            for (slot, state) in states.iter().enumerate() {
+                file.push_str(&format!(
+                    "{:4}: 0100007F:1F90 0100007F:A2C4 {} 00000000:00000000 \
+                     00:00000000 00000000  1000        0 1234 1\n",
+                    slot, state
+                ));
+            }
+            file
+        };
+        let file1 = with_states(&[]);
+        let file2 = with_states(&["01", "06", "06", "0A"]);
+        let file3 = with_states(&["06", "08", "0D"]);
+
+        // Initialize a data store and check its initial state
+        let mut data = Data::new(RecordStream::new(&file1));
+        assert_eq!(data.len(), 0);
+
+        // Push some samples
+        data.push(RecordStream::new(&file1)).unwrap();
+        data.push(RecordStream::new(&file2)).unwrap();
+        data.push(RecordStream::new(&file3)).unwrap();
+        assert_eq!(data.len(), 3);
+
+        // Empty readouts, which lack the header, are rejected
+        assert!(data.push(RecordStream::new("")).is_err());
+        assert_eq!(data.len(), 3);
+
+        // Check the recorded histograms
+        assert_eq!(data.connections(TcpState::Established), &[0, 1, 0]);
+        assert_eq!(data.connections(TcpState::TimeWait), &[0, 2, 1]);
+        assert_eq!(data.connections(TcpState::CloseWait), &[0, 0, 1]);
+        assert_eq!(data.connections(TcpState::Listen), &[0, 1, 0]);
+        assert_eq!(data.connections(TcpState::Closing), &[0, 0, 0]);
+
+        // Sockets in unknown states are not counted
+        let total: u32 = TcpState::ALL.iter()
+                                      .map(|&state| data.connections(state)[2])
+                                      .sum();
+        assert_eq!(total, 2);
+
+        // Check that dropping old samples works
+        data.drop_oldest(2);
+        assert_eq!(data.len(), 1);
+        assert_eq!(data.connections(TcpState::TimeWait), &[1]);
+    }
+
+    /// Check that the sampler works well
+    define_sampler_tests!{ super::Sampler }
+}
+
+
+/// Performance benchmarks
+///
+/// See the lib-wide benchmarks module for details on how to use these.
+///
+#[cfg(test)]
+mod benchmarks {
+    define_sampler_benchs!{ super::Sampler,
+                            "/proc/net/tcp",
+                            100_000 }
+}
@@ -0,0 +1,365 @@
+//! This module contains a sampling parser for /proc/net/udp
+//!
+//! Like the TCP socket table, the UDP socket table can be very large on busy
+//! servers, and we are rarely interested in individual sockets. This parser
+//! thus only records aggregate statistics for every sample: the number of
+//! sockets, the total amount of data waiting in their queues, and the total
+//! number of datagrams that they dropped. The latter is a good indicator of
+//! saturation on UDP-heavy services.
+//!
+//! The same parser is used for /proc/net/udp6, see the "udp6" module.
+
+use ::columns::{Column, Columns};
+use ::data::{SampledData, SchemaChanged};
+use ::parser::PseudoFileParser;
+use ::splitter::{SplitColumns, SplitLinesBySpace};
+
+
+// Implement a sampler for /proc/net/udp
+define_sampler!{ Sampler : "/proc/net/udp", buffer 65536 => Parser
+                                                         => RecordStream
+                                                         => Data }
+
+
+/// Incremental parser for /proc/net/udp and /proc/net/udp6
+#[derive(Debug, PartialEq)]
+pub struct Parser {}
+//
+impl PseudoFileParser for Parser {
+    /// Build a parser, using an initial file sample. Here, this is used to
+    /// perform quick schema validation, just to maximize the odds that failure,
+    /// if any, will occur at initialization time rather than run time.
+    fn new(initial_contents: &str) -> Self {
+        let mut validation_stream = RecordStream::new(initial_contents);
+        while let Some(record) = validation_stream.next() {
+            record.queues();
+            record.drops();
+        }
+        Self {}
+    }
+}
+//
+// TODO: Implement IncrementalParser once that trait is usable in stable Rust
+impl Parser {
+    /// Parse a pseudo-file sample into a stream of records
+    pub fn parse<'a>(&mut self, file_contents: &'a str) -> RecordStream<'a> {
+        RecordStream::new(file_contents)
+    }
+}
+///
+///
+/// Stream of records from /proc/net/udp or /proc/net/udp6
+///
+/// This streaming iterator should yield a stream of records, each representing
+/// a line of the file (i.e. one UDP socket). Records are parsed lazily, and
+/// nothing is allocated per socket.
+///
+pub struct RecordStream<'a> {
+    /// Iterator into the lines and columns of the file
+    file_lines: SplitLinesBySpace<'a>,
+
+    /// Truth that the readout was empty, not even featuring a header
+    empty_readout: bool,
+}
+//
+impl<'a> RecordStream<'a> {
+    /// Extract the next record from the file
+    pub fn next<'b>(&'b mut self) -> Option<Record<'a>>
+        where 'a: 'b
+    {
+        self.file_lines.next().map(Record::new)
+    }
+
+    /// Create a record stream from raw contents
+    fn new(file_contents: &'a str) -> Self {
+        // The file starts with a line of table headers, which we skip. Empty
+        // readouts, which may occur when racing with the kernel, yield no
+        // record at all.
+        let mut file_lines = SplitLinesBySpace::new(file_contents);
+        let empty_readout = file_contents.is_empty();
+        if !empty_readout {
+            file_lines.next().expect("Missing /proc/net/udp header");
+        }
+        Self {
+            file_lines,
+            empty_readout,
+        }
+    }
+}
+///
+///
+/// Record from /proc/net/udp or /proc/net/udp6 (one UDP socket)
+///
+/// Only the queue sizes and the drop counter are extracted, as the other
+/// columns (addresses, timers, owner...) are not used by this parser.
+///
+pub struct Record<'a> {
+    /// Transmit and receive queue sizes, in hexadecimal "TX:RX" format
+    queues_column: &'a str,
+
+    /// Number of dropped datagrams, in decimal format
+    drops_column: &'a str,
+}
+//
+impl<'a> Record<'a> {
+    /// Amount of data waiting in the transmit and receive queues of the
+    /// socket, in bytes, as a (transmit, receive) pair
+    pub fn queues(&self) -> (u64, u64) {
+        let mut queues = self.queues_column.split(':');
+        let mut next_queue = || -> u64 {
+            let hex = queues.next().expect("Missing UDP socket queue size");
+            u64::from_str_radix(hex, 16).expect("Failed to parse queue size")
+        };
+        let tx_queue = next_queue();
+        let rx_queue = next_queue();
+        debug_assert_eq!(queues.next(), None, "Unexpected socket queue data");
+        (tx_queue, rx_queue)
+    }
+
+    /// Number of datagrams which were dropped by the socket
+    pub fn drops(&self) -> u64 {
+        self.drops_column.parse().expect("Failed to parse UDP drop count")
+    }
+
+    /// Construct a record from associated file columns
+    fn new<'b>(mut file_columns: SplitColumns<'a, 'b>) -> Self
+        where 'a: 'b
+    {
+        // The first column is the socket slot number, which we do not need
+        let slot = file_columns.next().expect("Missing socket slot number");
+        debug_assert_eq!(slot.bytes().next_back(), Some(b':'),
+                         "Incorrectly formatted socket slot number");
+
+        // Then come the addresses and the socket state, which we skip, and the
+        // queue sizes, which we want. The drop counter is the last column.
+        let queues_column = file_columns.nth(3)
+                                        .expect("Missing UDP socket queues");
+        let drops_column = file_columns.last()
+                                       .expect("Missing UDP socket drops");
+        Self {
+            queues_column,
+            drops_column,
+        }
+    }
+}
+
+
+/// Data samples from /proc/net/udp, in structure-of-array layout
+///
+/// For each sample, we record aggregate statistics over all UDP sockets.
+///
+#[derive(Debug, PartialEq)]
+pub struct Data {
+    /// Number of UDP sockets
+    sockets: Vec<u64>,
+
+    /// Total amount of data in the transmit queues of all sockets, in bytes
+    tx_queue_bytes: Vec<u64>,
+
+    /// Total amount of data in the receive queues of all sockets, in bytes
+    rx_queue_bytes: Vec<u64>,
+
+    /// Total number of datagrams dropped by all sockets
+    drops: Vec<u64>,
+}
+//
+impl SampledData for Data {
+    /// Tell how many samples are present in the data store + check consistency
+    fn len(&self) -> usize {
+        let length = self.sockets.len();
+        debug_assert_eq!(self.tx_queue_bytes.len(), length);
+        debug_assert_eq!(self.rx_queue_bytes.len(), length);
+        debug_assert_eq!(self.drops.len(), length);
+        length
+    }
+
+    /// Drop the oldest samples from the data store
+    fn drop_oldest(&mut self, count: usize) {
+        self.sockets.drain(..count);
+        self.tx_queue_bytes.drain(..count);
+        self.rx_queue_bytes.drain(..count);
+        self.drops.drain(..count);
+    }
+
+    /// Tell the length of the shortest time series in the data store
+    fn min_len(&self) -> usize {
+        [self.tx_queue_bytes.len(), self.rx_queue_bytes.len(), self.drops.len()]
+            .iter()
+            .fold(self.sockets.len(), |min, &len| min.min(len))
+    }
+
+    /// Drop the newest samples from the data store
+    fn truncate(&mut self, length: usize) {
+        self.sockets.truncate(length);
+        self.tx_queue_bytes.truncate(length);
+        self.rx_queue_bytes.truncate(length);
+        self.drops.truncate(length);
+    }
+}
+//
+// TODO: Implement SampledDataIncremental once that is usable in stable Rust
+impl Data {
+    /// Number of UDP sockets
+    pub fn sockets(&self) -> &[u64] { &self.sockets }
+
+    /// Total amount of data in the transmit queues of all sockets, in bytes
+    pub fn tx_queue_bytes(&self) -> &[u64] { &self.tx_queue_bytes }
+
+    /// Total amount of data in the receive queues of all sockets, in bytes
+    pub fn rx_queue_bytes(&self) -> &[u64] { &self.rx_queue_bytes }
+
+    /// Total number of datagrams dropped by the sockets which were open at the
+    /// time of sampling
+    ///
+    /// Beware that this is not a monotonic counter: it goes down when a socket
+    /// which dropped datagrams is closed.
+    ///
+    pub fn drops(&self) -> &[u64] { &self.drops }
+
+    /// Create a new UDP socket data store
+    pub(super) fn new(_stream: RecordStream) -> Self {
+        Self {
+            sockets: Vec::new(),
+            tx_queue_bytes: Vec::new(),
+            rx_queue_bytes: Vec::new(),
+            drops: Vec::new(),
+        }
+    }
+
+    /// Parse the contents of /proc/net/udp and add a data sample to the
+    /// internal data store
+    ///
+    /// Empty readouts, which lack the header of the file, are rejected.
+    ///
+    pub(super) fn push(&mut self,
+                       mut stream: RecordStream) -> Result<(), SchemaChanged> {
+        if stream.empty_readout {
+            return Err(
+                SchemaChanged::new("The /proc/net/udp header is missing")
+            );
+        }
+
+        // Aggregate the socket statistics on the stack
+        let (mut sockets, mut tx_queue, mut rx_queue, mut drops) = (0, 0, 0, 0);
+        while let Some(record) = stream.next() {
+            let (tx, rx) = record.queues();
+            sockets += 1;
+            tx_queue += tx;
+            rx_queue += rx;
+            drops += record.drops();
+        }
+
+        // Store them in the data store
+        self.sockets.push(sockets);
+        self.tx_queue_bytes.push(tx_queue);
+        self.rx_queue_bytes.push(rx_queue);
+        self.drops.push(drops);
+        Ok(())
+    }
+}
+//
+impl Columns for Data {
+    fn columns(&self) -> Vec<(String, Column)> {
+        let bytes = |vec: &Vec<u64>| Column::Bytes(vec.clone());
+        vec![("sockets".to_owned(), Column::counts(&self.sockets)),
+             ("tx_queue".to_owned(), bytes(&self.tx_queue_bytes)),
+             ("rx_queue".to_owned(), bytes(&self.rx_queue_bytes)),
+             ("drops".to_owned(), Column::counts(&self.drops))]
+    }
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use ::data::SampledData;
+    use ::parser::PseudoFileParser;
+    use super::{Data, Parser, RecordStream};
+
+    /// Header of /proc/net/udp
+    const HEADER: &str =
+        "   sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode ref pointer drops\n";
+
+    /// Check that record streams skip the header and work for IPv4 and IPv6
+    #[test]
+    fn record_stream() {
+        let mut file = HEADER.to_owned();
+        file.push_str(" 1053: 3500007F:0035 00000000:0000 07 00000000:00000000 00:00000000 00000000   101        0 20311 2 0000000088b1bf4e 0\n");
+        file.push_str("  836: 00000000000000000000000000000000:14E9 00000000000000000000000000000000:0000 07 00000A00:0001F400 00:00000000 00000000   116        0 26442 2 00000000d0ef3e0c 42\n");
+        let mut parser = Parser::new(&file);
+        let mut stream = parser.parse(&file);
+        {
+            let record = stream.next().expect("Missing first record");
+            assert_eq!(record.queues(), (0, 0));
+            assert_eq!(record.drops(), 0);
+        }
+        {
+            let record = stream.next().expect("Missing second record");
+            assert_eq!(record.queues(), (0xA00, 0x1F400));
+            assert_eq!(record.drops(), 42);
+        }
+        assert!(stream.next().is_none());
+    }
+
+    /// Check that sampled data works as expected
+    #[test]
+    fn sampled_data() {
+        // Build some mock file contents
+        let with_sockets = |sockets: &[(&str, u64)]| -> String {
+            let mut file = HEADER.to_owned();
+            for (slot, &(queues, drops)) in sockets.iter().enumerate() {
+                file.push_str(&format!(
+                    "{:5}: 0100007F:1F90 00000000:0000 07 {} 00:00000000 \
+                     00000000  1000        0 1234 2 0000000000000000 {}\n",
+                    slot, queues, drops
+                ));
+            }
+            file
+        };
+        let file1 = with_sockets(&[]);
+        let file2 = with_sockets(&[("00000010:00000100", 3),
+                                   ("00000001:00001000", 0)]);
+        let file3 = with_sockets(&[("00000000:00000200", 5)]);
+
+        // Initialize a data store and check its initial state
+        let mut data = Data::new(RecordStream::new(&file1));
+        assert_eq!(data.len(), 0);
+
+        // Push some samples
+        data.push(RecordStream::new(&file1)).unwrap();
+        data.push(RecordStream::new(&file2)).unwrap();
+        data.push(RecordStream::new(&file3)).unwrap();
+        assert_eq!(data.len(), 3);
+
+        // Empty readouts, which lack the header, are rejected
+        assert!(data.push(RecordStream::new("")).is_err());
+        assert_eq!(data.len(), 3);
+
+        // Check the recorded aggregates
+        assert_eq!(data.sockets(), &[0, 2, 1]);
+        assert_eq!(data.tx_queue_bytes(), &[0, 0x11, 0]);
+        assert_eq!(data.rx_queue_bytes(), &[0, 0x1100, 0x200]);
+        assert_eq!(data.drops(), &[0, 3, 5]);
+
+        // Check that dropping old samples works
+        data.drop_oldest(2);
+        assert_eq!(data.len(), 1);
+        assert_eq!(data.sockets(), &[1]);
+        assert_eq!(data.drops(), &[5]);
+    }
+
+    /// Check that the sampler works well
+    define_sampler_tests!{ super::Sampler }
+}
+
+
+/// Performance benchmarks
+///
+/// See the lib-wide benchmarks module for details on how to use these.
+///
+#[cfg(test)]
+mod benchmarks {
+    define_sampler_benchs!{ super::Sampler,
+                            "/proc/net/udp",
+                            100_000 }
+}
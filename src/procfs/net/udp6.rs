@@ -0,0 +1,35 @@
+//! This module contains a sampling parser for /proc/net/udp6
+//!
+//! This file has the same format as /proc/net/udp, except for the use of IPv6
+//! socket addresses, so the parser and data store of the "udp" module are used.
+
+use ::parser::PseudoFileParser;
+pub use super::udp::{Data, Parser, Record, RecordStream};
+
+
+// Implement a sampler for /proc/net/udp6
+define_sampler!{ Sampler : "/proc/net/udp6", buffer 65536 => Parser
+                                                          => RecordStream
+                                                          => Data }
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use ::data::SampledData;
+
+    /// Check that the sampler works well
+    define_sampler_tests!{ super::Sampler }
+}
+
+
+/// Performance benchmarks
+///
+/// See the lib-wide benchmarks module for details on how to use these.
+///
+#[cfg(test)]
+mod benchmarks {
+    define_sampler_benchs!{ super::Sampler,
+                            "/proc/net/udp6",
+                            100_000 }
+}
@@ -0,0 +1,734 @@
+//! This module contains a sampling parser for /proc/net/wireless
+
+use ::columns::{Column, Columns};
+use ::data::SampledData;
+use ::parser::PseudoFileParser;
+use ::splitter::{SplitColumns, SplitLinesBySpace};
+
+
+// Implement a sampler for /proc/net/wireless
+define_sampler!{ Sampler : "/proc/net/wireless" => Parser
+                                                => RecordStream
+                                                => Data }
+
+
+/// Incremental parser for /proc/net/wireless
+#[derive(Debug, PartialEq)]
+pub struct Parser {}
+//
+impl PseudoFileParser for Parser {
+    /// Build a parser, using an initial file sample. Here, this is used to
+    /// perform quick schema validation, just to maximize the odds that failure,
+    /// if any, will occur at initialization time rather than run time.
+    fn new(initial_contents: &str) -> Self {
+        let mut validation_stream = RecordStream::new(initial_contents);
+        while let Some(record) = validation_stream.next() {
+            record.interface();
+            record.parse_fields();
+        }
+        Self {}
+    }
+}
+//
+// TODO: Implement IncrementalParser once that trait is usable in stable Rust
+impl Parser {
+    /// Parse a pseudo-file sample into a stream of records
+    pub fn parse<'a>(&mut self, file_contents: &'a str) -> RecordStream<'a> {
+        RecordStream::new(file_contents)
+    }
+}
+///
+///
+/// Stream of records from /proc/net/wireless
+///
+/// This streaming iterator should yield a stream of records, each representing
+/// a line of /proc/net/wireless (i.e. the statistics of a wireless interface).
+///
+pub struct RecordStream<'a> {
+    /// Iterator into the lines and columns of /proc/net/wireless
+    file_lines: SplitLinesBySpace<'a>,
+}
+//
+impl<'a> RecordStream<'a> {
+    /// Extract the next record from /proc/net/wireless
+    pub fn next<'b>(&'b mut self) -> Option<Record<'a, 'b>>
+        where 'a: 'b
+    {
+        self.file_lines.next().map(Record::new)
+    }
+
+    /// Create a record stream from raw contents
+    fn new(file_contents: &'a str) -> Self {
+        // The file starts with two lines of table headers, which we skip.
+        // Empty readouts, which may occur when racing with the kernel, yield
+        // no record at all.
+        let mut file_lines = SplitLinesBySpace::new(file_contents);
+        if !file_contents.is_empty() {
+            for _ in 0..2 {
+                file_lines.next().expect("Missing /proc/net/wireless header");
+            }
+        }
+        Self {
+            file_lines,
+        }
+    }
+}
+///
+///
+/// Record from /proc/net/wireless (statistics of one wireless interface)
+pub struct Record<'a, 'b> where 'a: 'b {
+    /// Interface name field, followed by a colon
+    interface_field: &'a str,
+
+    /// Data columns of the record
+    data_columns: SplitColumns<'a, 'b>,
+}
+//
+impl<'a, 'b> Record<'a, 'b> {
+    /// Tell which network interface this record is about
+    pub fn interface(&self) -> &'a str {
+        // The interface field should end with a colon
+        debug_assert_eq!(self.interface_field.bytes().next_back(), Some(b':'),
+                         "Incorrectly formatted wireless interface name");
+
+        // The text before that colon is the interface name
+        let field_length = self.interface_field.len();
+        assert!(field_length >= 2, "Unexpected empty interface name");
+        &self.interface_field[..field_length-1]
+    }
+
+    /// Parse the statistics of the network interface
+    fn parse_fields(self) -> RecordFields {
+        RecordFields::new(self.data_columns)
+    }
+
+    /// Construct a record from associated file columns
+    fn new(mut file_columns: SplitColumns<'a, 'b>) -> Self {
+        Self {
+            interface_field: file_columns.next()
+                                         .expect("Missing interface name"),
+            data_columns: file_columns,
+        }
+    }
+}
+
+
+/// Statistics of a wireless network interface, as found in /proc/net/wireless
+#[derive(Debug, PartialEq)]
+struct RecordFields {
+    /// Device-dependent status word
+    status: u16,
+
+    /// Overall quality of the link (device-dependent scale)
+    link_quality: i32,
+
+    /// Received signal level (usually in dBm)
+    signal_level: i32,
+
+    /// Background noise level (usually in dBm)
+    noise_level: i32,
+
+    /// Packets discarded due to a wrong network ID...
+    discarded_nwid: u64,
+
+    /// ...due to decryption failures...
+    discarded_crypt: u64,
+
+    /// ...due to MAC reassembly failures...
+    discarded_frag: u64,
+
+    /// ...due to excessive retransmissions...
+    discarded_retry: u64,
+
+    /// ...or for other reasons
+    discarded_misc: u64,
+
+    /// Missed beacons from the access point
+    missed_beacons: u64,
+}
+//
+impl RecordFields {
+    /// Decode the wireless interface statistics
+    fn new<'a, 'b>(mut data_columns: SplitColumns<'a, 'b>) -> Self {
+        // Scope added to address current borrow checker limitation
+        let fields = {
+            // This is how we fetch the next column
+            let mut next_column = || -> &'a str {
+                data_columns.next().expect("Missing wireless statistic")
+            };
+
+            // The status word comes first, in hexadecimal notation
+            let status = u16::from_str_radix(next_column(), 16)
+                             .expect("Failed to parse wireless status");
+
+            // Then come the link quality figures
+            let link_quality = Self::parse_quality(next_column());
+            let signal_level = Self::parse_quality(next_column());
+            let noise_level = Self::parse_quality(next_column());
+
+            // And finally the packet loss counters
+            let mut parse_counter = || -> u64 {
+                next_column().parse()
+                             .expect("Failed to parse wireless counter")
+            };
+            Self {
+                status,
+                link_quality,
+                signal_level,
+                noise_level,
+                discarded_nwid: parse_counter(),
+                discarded_crypt: parse_counter(),
+                discarded_frag: parse_counter(),
+                discarded_retry: parse_counter(),
+                discarded_misc: parse_counter(),
+                missed_beacons: parse_counter(),
+            }
+        };
+
+        // In debug mode, check that nothing weird appeared in the input
+        debug_assert_eq!(data_columns.next(), None,
+                         "Unexpected additional wireless statistic");
+
+        // Return the wireless statistics
+        fields
+    }
+
+    /// Parse a link quality figure
+    ///
+    /// The kernel appends a dot to these figures when they were updated since
+    /// the last readout (e.g. "70."), which we must strip before parsing.
+    ///
+    fn parse_quality(column: &str) -> i32 {
+        let digits = if column.bytes().next_back() == Some(b'.') {
+                         &column[..column.len()-1]
+                     } else {
+                         column
+                     };
+        digits.parse().expect("Failed to parse link quality")
+    }
+}
+
+
+/// Data samples from /proc/net/wireless, in structure-of-array layout
+///
+/// Wireless interfaces may come and go during a measurement (e.g. when a
+/// Wi-Fi adapter is switched off). We handle this by keeping one set of time
+/// series per interface ever observed, along with the indices of the samples
+/// where that interface was present, and by recording the sample indices at
+/// which the set of active interfaces changed.
+///
+#[derive(Debug, PartialEq)]
+pub struct Data {
+    /// Statistics of every interface that we have seen, in order of appearance
+    interfaces: Vec<InterfaceData>,
+
+    /// Interfaces from the last sample (as indices in "interfaces"), in the
+    /// order in which they appear in the file
+    active_interfaces: Vec<usize>,
+
+    /// Number of samples that were recorded
+    sample_count: usize,
+
+    /// Sample indices at which the set of active interfaces changed
+    interface_set_changes: Vec<usize>,
+}
+//
+impl SampledData for Data {
+    /// Tell how many samples are present in the data store + check consistency
+    fn len(&self) -> usize {
+        debug_assert!(self.interfaces.iter().all(|iface| {
+            iface.sample_indices.last().map_or(true, |&idx| {
+                idx < self.sample_count
+            })
+        }));
+        self.sample_count
+    }
+
+    /// Drop the oldest samples from the data store
+    fn drop_oldest(&mut self, count: usize) {
+        // Drop the oldest samples from each interface
+        for iface in self.interfaces.iter_mut() {
+            iface.drop_samples_before(count);
+        }
+
+        // Update the sample indices of interface set changes
+        let num_old_changes =
+            self.interface_set_changes.iter()
+                                      .take_while(|&&idx| idx < count)
+                                      .count();
+        self.interface_set_changes.drain(..num_old_changes);
+        for idx in self.interface_set_changes.iter_mut() {
+            *idx -= count;
+        }
+
+        // Update the sample count
+        assert!(count <= self.sample_count, "Not enough samples to drop");
+        self.sample_count -= count;
+    }
+
+    /// Tell how many complete samples are present in the data store. A sample
+    /// is incomplete if the statistics of some interface were only partially
+    /// recorded for that sample.
+    fn min_len(&self) -> usize {
+        self.interfaces.iter()
+                       .filter_map(|iface| iface.first_incomplete_sample())
+                       .fold(self.sample_count, ::std::cmp::min)
+    }
+
+    /// Drop the newest samples from the data store
+    fn truncate(&mut self, length: usize) {
+        assert!(length <= self.sample_count, "Not enough samples to keep");
+        for iface in self.interfaces.iter_mut() {
+            iface.drop_samples_from(length);
+        }
+        self.interface_set_changes.retain(|&idx| idx < length);
+        self.sample_count = length;
+    }
+}
+//
+// TODO: Implement SampledDataIncremental once that is usable in stable Rust
+impl Data {
+    /// Statistics of every wireless interface that was observed so far
+    pub fn interfaces(&self) -> &[InterfaceData] {
+        &self.interfaces
+    }
+
+    /// Statistics of a specific wireless interface, if it was observed
+    pub fn interface(&self, name: &str) -> Option<&InterfaceData> {
+        self.interfaces.iter().find(|iface| iface.name == name)
+    }
+
+    /// Sample indices at which the set of wireless interfaces changed
+    pub fn interface_set_changes(&self) -> &[usize] {
+        &self.interface_set_changes
+    }
+
+    /// Create a new wireless statistics data store, using a first sample to
+    /// know which interfaces are present on this system
+    fn new(mut stream: RecordStream) -> Self {
+        // Our data store will eventually go there
+        let mut store = Self {
+            interfaces: Vec::new(),
+            active_interfaces: Vec::new(),
+            sample_count: 0,
+            interface_set_changes: Vec::new(),
+        };
+
+        // Register each interface which is initially present
+        while let Some(record) = stream.next() {
+            let iface_idx = store.interfaces.len();
+            store.interfaces.push(InterfaceData::new(record.interface()));
+            store.active_interfaces.push(iface_idx);
+        }
+
+        // Return our data collection setup
+        store
+    }
+
+    /// Parse the contents of /proc/net/wireless and add a data sample to all
+    /// corresponding entries in the internal data store
+    fn push(&mut self, mut stream: RecordStream) {
+        // Go through the records of the file
+        let sample_idx = self.sample_count;
+        let mut set_changed = false;
+        let mut position = 0;
+        while let Some(record) = stream.next() {
+            // In the common case, interfaces appear in the same order as in
+            // the previous sample, so we can avoid looking up their name
+            let name = record.interface();
+            let iface_idx = match self.active_interfaces.get(position) {
+                Some(&idx) if self.interfaces[idx].name == name => idx,
+                _ => {
+                    set_changed = true;
+                    self.find_or_insert(name)
+                }
+            };
+
+            // Keep the list of active interfaces up to date
+            if position < self.active_interfaces.len() {
+                self.active_interfaces[position] = iface_idx;
+            } else {
+                self.active_interfaces.push(iface_idx);
+            }
+            position += 1;
+
+            // Record the statistics of that interface
+            self.interfaces[iface_idx].push(sample_idx, record.parse_fields());
+        }
+
+        // Check if some interfaces have disappeared
+        if position < self.active_interfaces.len() {
+            set_changed = true;
+            self.active_interfaces.truncate(position);
+        }
+
+        // Record the changes to the set of interfaces, if any
+        if set_changed {
+            self.interface_set_changes.push(sample_idx);
+        }
+        self.sample_count += 1;
+    }
+
+    /// INTERNAL: Find an interface by name, or start tracking it if it is new
+    fn find_or_insert(&mut self, name: &str) -> usize {
+        match self.interfaces.iter().position(|iface| iface.name == name) {
+            Some(idx) => idx,
+            None => {
+                self.interfaces.push(InterfaceData::new(name));
+                self.interfaces.len() - 1
+            }
+        }
+    }
+}
+//
+/// Statistics are exported under the name of the interface, followed by the
+/// name of the statistic (e.g. "wlan0_signal_level"). As interfaces may come
+/// and go, the samples where an interface was absent are NaN.
+impl Columns for Data {
+    fn columns(&self) -> Vec<(String, Column)> {
+        let mut columns = Vec::new();
+        for interface in self.interfaces.iter() {
+            let indices = &interface.sample_indices[..];
+            let sparse_i32 = |values: &[i32]| {
+                Column::sparse(self.sample_count, indices, values, f64::from)
+            };
+            let sparse_u64 = |values: &[u64]| {
+                Column::sparse(self.sample_count, indices, values, |x| x as f64)
+            };
+            let interface_columns = vec![
+                ("status",
+                 Column::sparse(self.sample_count,
+                                indices,
+                                &interface.status,
+                                f64::from)),
+                ("link_quality", sparse_i32(&interface.link_quality)),
+                ("signal_level", sparse_i32(&interface.signal_level)),
+                ("noise_level", sparse_i32(&interface.noise_level)),
+                ("discarded_nwid", sparse_u64(&interface.discarded_nwid)),
+                ("discarded_crypt", sparse_u64(&interface.discarded_crypt)),
+                ("discarded_frag", sparse_u64(&interface.discarded_frag)),
+                ("discarded_retry", sparse_u64(&interface.discarded_retry)),
+                ("discarded_misc", sparse_u64(&interface.discarded_misc)),
+                ("missed_beacons", sparse_u64(&interface.missed_beacons)),
+            ];
+            columns.extend(interface_columns.into_iter().map(|(name, col)| {
+                (format!("{}_{}", interface.name, name), col)
+            }));
+        }
+        columns
+    }
+}
+///
+///
+/// Sampled statistics of one wireless network interface
+#[derive(Debug, PartialEq)]
+pub struct InterfaceData {
+    /// Name of the network interface
+    name: String,
+
+    /// Indices of the samples in which this interface was present
+    sample_indices: Vec<usize>,
+
+    /// Device-dependent status word
+    status: Vec<u16>,
+
+    /// Overall quality of the link (device-dependent scale)
+    link_quality: Vec<i32>,
+
+    /// Received signal level (usually in dBm)
+    signal_level: Vec<i32>,
+
+    /// Background noise level (usually in dBm)
+    noise_level: Vec<i32>,
+
+    /// Packets discarded due to a wrong network ID
+    discarded_nwid: Vec<u64>,
+
+    /// Packets discarded due to decryption failures
+    discarded_crypt: Vec<u64>,
+
+    /// Packets discarded due to MAC reassembly failures
+    discarded_frag: Vec<u64>,
+
+    /// Packets discarded due to excessive retransmissions
+    discarded_retry: Vec<u64>,
+
+    /// Packets discarded for other reasons
+    discarded_misc: Vec<u64>,
+
+    /// Missed beacons from the access point
+    missed_beacons: Vec<u64>,
+}
+//
+impl InterfaceData {
+    /// Name of the network interface
+    pub fn name(&self) -> &str { &self.name }
+
+    /// Indices of the samples in which this interface was present. All other
+    /// time series of this interface are aligned with this one.
+    pub fn sample_indices(&self) -> &[usize] { &self.sample_indices }
+
+    /// Device-dependent status word
+    pub fn status(&self) -> &[u16] { &self.status }
+
+    /// Overall quality of the link (device-dependent scale)
+    pub fn link_quality(&self) -> &[i32] { &self.link_quality }
+
+    /// Received signal level (usually in dBm)
+    pub fn signal_level(&self) -> &[i32] { &self.signal_level }
+
+    /// Background noise level (usually in dBm)
+    pub fn noise_level(&self) -> &[i32] { &self.noise_level }
+
+    /// Packets discarded due to a wrong network ID
+    pub fn discarded_nwid(&self) -> &[u64] { &self.discarded_nwid }
+
+    /// Packets discarded due to decryption failures
+    pub fn discarded_crypt(&self) -> &[u64] { &self.discarded_crypt }
+
+    /// Packets discarded due to MAC reassembly failures
+    pub fn discarded_frag(&self) -> &[u64] { &self.discarded_frag }
+
+    /// Packets discarded due to excessive retransmissions
+    pub fn discarded_retry(&self) -> &[u64] { &self.discarded_retry }
+
+    /// Packets discarded for other reasons
+    pub fn discarded_misc(&self) -> &[u64] { &self.discarded_misc }
+
+    /// Missed beacons from the access point
+    pub fn missed_beacons(&self) -> &[u64] { &self.missed_beacons }
+
+    /// Start tracking the statistics of a new interface
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            sample_indices: Vec::new(),
+            status: Vec::new(),
+            link_quality: Vec::new(),
+            signal_level: Vec::new(),
+            noise_level: Vec::new(),
+            discarded_nwid: Vec::new(),
+            discarded_crypt: Vec::new(),
+            discarded_frag: Vec::new(),
+            discarded_retry: Vec::new(),
+            discarded_misc: Vec::new(),
+            missed_beacons: Vec::new(),
+        }
+    }
+
+    /// Record the statistics of this interface for a certain sample
+    fn push(&mut self, sample_idx: usize, fields: RecordFields) {
+        self.sample_indices.push(sample_idx);
+        self.status.push(fields.status);
+        self.link_quality.push(fields.link_quality);
+        self.signal_level.push(fields.signal_level);
+        self.noise_level.push(fields.noise_level);
+        self.discarded_nwid.push(fields.discarded_nwid);
+        self.discarded_crypt.push(fields.discarded_crypt);
+        self.discarded_frag.push(fields.discarded_frag);
+        self.discarded_retry.push(fields.discarded_retry);
+        self.discarded_misc.push(fields.discarded_misc);
+        self.missed_beacons.push(fields.missed_beacons);
+    }
+
+    /// Drop the statistics from samples older than a certain sample index,
+    /// and renumber the remaining samples accordingly
+    fn drop_samples_before(&mut self, sample_idx: usize) {
+        let count = self.sample_indices.iter()
+                                       .take_while(|&&idx| idx < sample_idx)
+                                       .count();
+        self.sample_indices.drain(..count);
+        for idx in self.sample_indices.iter_mut() {
+            *idx -= sample_idx;
+        }
+        self.status.drain(..count);
+        self.link_quality.drain(..count);
+        self.signal_level.drain(..count);
+        self.noise_level.drain(..count);
+        self.discarded_nwid.drain(..count);
+        self.discarded_crypt.drain(..count);
+        self.discarded_frag.drain(..count);
+        self.discarded_retry.drain(..count);
+        self.discarded_misc.drain(..count);
+        self.missed_beacons.drain(..count);
+    }
+
+    /// Index of the first sample for which the statistics of this interface
+    /// were only partially recorded, if any
+    fn first_incomplete_sample(&self) -> Option<usize> {
+        let complete_len = [self.status.len(),
+                            self.link_quality.len(),
+                            self.signal_level.len(),
+                            self.noise_level.len(),
+                            self.discarded_nwid.len(),
+                            self.discarded_crypt.len(),
+                            self.discarded_frag.len(),
+                            self.discarded_retry.len(),
+                            self.discarded_misc.len(),
+                            self.missed_beacons.len()].iter()
+                                                     .cloned()
+                                                     .min()
+                                                     .expect("No statistic");
+        self.sample_indices.get(complete_len).cloned()
+    }
+
+    /// Drop the statistics from samples at or after a certain sample index
+    fn drop_samples_from(&mut self, sample_idx: usize) {
+        let count = self.sample_indices.iter()
+                                       .take_while(|&&idx| idx < sample_idx)
+                                       .count();
+        self.sample_indices.truncate(count);
+        self.status.truncate(count);
+        self.link_quality.truncate(count);
+        self.signal_level.truncate(count);
+        self.noise_level.truncate(count);
+        self.discarded_nwid.truncate(count);
+        self.discarded_crypt.truncate(count);
+        self.discarded_frag.truncate(count);
+        self.discarded_retry.truncate(count);
+        self.discarded_misc.truncate(count);
+        self.missed_beacons.truncate(count);
+    }
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use ::splitter::split_line_and_run;
+    use super::{Data, Parser, PseudoFileParser, RecordFields, RecordStream,
+                SampledData};
+
+    /// Table header of /proc/net/wireless
+    const HEADER: &str =
+        "Inter-| sta-|   Quality        |   Discarded packets               | Missed | WE
+ face | tus | link level noise |  nwid  crypt   frag  retry   misc | beacon | 22\n";
+
+    /// Check that link quality figures are parsed properly, including the
+    /// trailing dot which the kernel uses to mark updated figures
+    #[test]
+    fn parse_quality() {
+        assert_eq!(RecordFields::parse_quality("70."), 70);
+        assert_eq!(RecordFields::parse_quality("70"), 70);
+        assert_eq!(RecordFields::parse_quality("-40."), -40);
+        assert_eq!(RecordFields::parse_quality("-256"), -256);
+    }
+
+    /// Check that wireless statistics are parsed properly
+    #[test]
+    fn record_fields() {
+        split_line_and_run("0000   70.  -40.  -256  1  2  3  4  5  6",
+                           |columns| {
+            assert_eq!(RecordFields::new(columns), RecordFields {
+                status: 0,
+                link_quality: 70,
+                signal_level: -40,
+                noise_level: -256,
+                discarded_nwid: 1,
+                discarded_crypt: 2,
+                discarded_frag: 3,
+                discarded_retry: 4,
+                discarded_misc: 5,
+                missed_beacons: 6,
+            });
+        });
+    }
+
+    /// Check that record streams skip the headers and work as expected
+    #[test]
+    fn record_stream() {
+        let mut file = HEADER.to_owned();
+        file.push_str(" wlan0: 0000   54.  -56.  -256        0      0      0      0     12        0\n");
+        file.push_str("  wlp3s0: 001f   70.  -40   -256        0      0      0      0      0        3\n");
+        let mut parser = Parser::new(&file);
+        let mut stream = parser.parse(&file);
+        {
+            let record = stream.next().expect("Missing first record");
+            assert_eq!(record.interface(), "wlan0");
+            let fields = record.parse_fields();
+            assert_eq!(fields.link_quality, 54);
+            assert_eq!(fields.discarded_misc, 12);
+        }
+        {
+            let record = stream.next().expect("Missing second record");
+            assert_eq!(record.interface(), "wlp3s0");
+            let fields = record.parse_fields();
+            assert_eq!(fields.status, 0x1f);
+            assert_eq!(fields.signal_level, -40);
+            assert_eq!(fields.missed_beacons, 3);
+        }
+        assert!(stream.next().is_none());
+    }
+
+    /// Check that a file without any wireless interface is handled correctly
+    #[test]
+    fn no_interface() {
+        let mut data = Data::new(RecordStream::new(HEADER));
+        assert_eq!(data.interfaces().len(), 0);
+        data.push(RecordStream::new(HEADER));
+        assert_eq!(data.len(), 1);
+        assert_eq!(data.interface_set_changes(), &[] as &[usize]);
+    }
+
+    /// Check that sampled data works as expected, including when interfaces
+    /// come and go between samples
+    #[test]
+    fn sampled_data() {
+        // Build some mock file contents
+        let with_interfaces = |ifaces: &[(&str, i32)]| -> String {
+            let mut file = HEADER.to_owned();
+            for &(name, quality) in ifaces {
+                file.push_str(&format!(
+                    "{}: 0000   {}.  -50.  -256  0  0  0  0  0  0\n",
+                    name, quality
+                ));
+            }
+            file
+        };
+        let file1 = with_interfaces(&[("wlan0", 10)]);
+        let file2 = with_interfaces(&[("wlan0", 20), ("wlan1", 30)]);
+        let file3 = with_interfaces(&[("wlan1", 40)]);
+
+        // Initialize a data store and check its initial state
+        let mut data = Data::new(RecordStream::new(&file1));
+        assert_eq!(data.len(), 0);
+        assert_eq!(data.interfaces().len(), 1);
+
+        // Push a sample with the same interfaces
+        data.push(RecordStream::new(&file1));
+        assert_eq!(data.len(), 1);
+        assert_eq!(data.interface_set_changes(), &[] as &[usize]);
+
+        // Push a sample where an interface appeared
+        data.push(RecordStream::new(&file2));
+        assert_eq!(data.len(), 2);
+        assert_eq!(data.interface_set_changes(), &[1]);
+
+        // Push a sample where an interface disappeared
+        data.push(RecordStream::new(&file3));
+        assert_eq!(data.len(), 3);
+        assert_eq!(data.interface_set_changes(), &[1, 2]);
+
+        // Check the recorded interface statistics
+        let wlan0 = data.interface("wlan0").expect("wlan0 should be known");
+        assert_eq!(wlan0.sample_indices(), &[0, 1]);
+        assert_eq!(wlan0.link_quality(), &[10, 20]);
+        let wlan1 = data.interface("wlan1").expect("wlan1 should be known");
+        assert_eq!(wlan1.sample_indices(), &[1, 2]);
+        assert_eq!(wlan1.link_quality(), &[30, 40]);
+        assert_eq!(wlan1.signal_level(), &[-50, -50]);
+
+        // Check that dropping old samples renumbers everything correctly
+        data.drop_oldest(2);
+        assert_eq!(data.len(), 1);
+        assert_eq!(data.interface_set_changes(), &[0]);
+        let wlan0 = data.interface("wlan0").expect("wlan0 should be known");
+        assert_eq!(wlan0.sample_indices(), &[] as &[usize]);
+        let wlan1 = data.interface("wlan1").expect("wlan1 should be known");
+        assert_eq!(wlan1.sample_indices(), &[0]);
+        assert_eq!(wlan1.link_quality(), &[40]);
+    }
+
+    // NOTE: /proc/net/wireless only exists on hosts with wireless extensions,
+    //       so we cannot unconditionally test the sampler against it.
+}
@@ -0,0 +1,210 @@
+//! This module contains a sampler for the number of entries of /proc/<pid>/fd
+//!
+//! Each open file descriptor of a process is listed as a symbolic link in its
+//! /proc/<pid>/fd directory. Counting these links over time is the standard
+//! way to detect file descriptor leaks: a process whose descriptor count keeps
+//! growing is bound to eventually hit its RLIMIT_NOFILE.
+//!
+//! Unlike other samplers, which read a pseudo-file, this sampler reads a
+//! directory. The generic sampler definition (define_sampler) is built around
+//! ProcFileReader, a parser and a record stream, none of which make sense for
+//! a directory listing, so this sampler is written by hand. It provides the
+//! core of the generic interface (new(), for_pid(), sample(), samples(),
+//! timestamps(), and the Pacer integration), but not the features which only
+//! make sense for file contents, such as from_source(), sample_with(), bounded
+//! capacity or replay. Its process() also always returns an identity, since
+//! the sampled directory always belongs to a known process.
+
+use ::columns::{Column, Columns};
+use ::data::SampledData;
+use ::procfs::process::identity::ProcessIdentity;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+
+/// Mechanism for sampling the number of open file descriptors of a process
+pub struct Sampler {
+    /// Directory which lists the file descriptors of the process
+    fd_dir: PathBuf,
+
+    /// Identity of the sampled process
+    process: ProcessIdentity,
+
+    /// Samples of the file descriptor count
+    samples: Data,
+
+    /// Time at which each stored sample was acquired
+    timestamps: Vec<Instant>,
+}
+//
+impl Sampler {
+    /// Create a new sampler for /proc/self/fd, which describes the process in
+    /// which this code is running
+    ///
+    /// The count then includes the file descriptor which is briefly used to
+    /// read the directory during sampling.
+    ///
+    pub fn new() -> io::Result<Self> {
+        Self::open(PathBuf::from("/proc/self/fd"), ProcessIdentity::for_self()?)
+    }
+
+    /// Create a new sampler for /proc/<pid>/fd
+    pub fn for_pid(pid: u32) -> io::Result<Self> {
+        Self::open(PathBuf::from(format!("/proc/{}/fd", pid)),
+                   ProcessIdentity::for_pid(pid)?)
+    }
+
+    /// Acquire a new sample of the file descriptor count
+    ///
+    /// If the process has exited, its /proc/<pid>/fd directory has vanished,
+    /// and an I/O error is returned without storing anything. The same goes
+    /// if its PID was handed over to another process in the meantime (see
+    /// ProcessIdentity::check()).
+    ///
+    pub fn sample(&mut self) -> io::Result<()> {
+        self.process.check()?;
+        let timestamp = Instant::now();
+        let count = Self::count_entries(&self.fd_dir)?;
+
+        // The PID may have been handed over to another process while the
+        // directory was being read, in which case the count is discarded
+        self.process.check()?;
+        self.samples.counts.push(count);
+        self.timestamps.push(timestamp);
+        Ok(())
+    }
+
+    /// Access the samples that were acquired so far
+    pub fn samples(&self) -> &Data {
+        &self.samples
+    }
+
+    /// Time at which each of the samples was acquired
+    pub fn timestamps(&self) -> &[Instant] {
+        &self.timestamps
+    }
+
+    /// Identity of the sampled process
+    pub fn process(&self) -> &ProcessIdentity {
+        &self.process
+    }
+
+    /// Command line of the sampled process
+    pub fn command_line(&self) -> &str {
+        self.process.command_line()
+    }
+
+    /// INTERNAL: Create a sampler for some file descriptor directory, checking
+    ///           that it can be read
+    fn open(fd_dir: PathBuf, process: ProcessIdentity) -> io::Result<Self> {
+        Self::count_entries(&fd_dir)?;
+        Ok(
+            Self {
+                fd_dir,
+                process,
+                samples: Data { counts: Vec::new() },
+                timestamps: Vec::new(),
+            }
+        )
+    }
+
+    /// INTERNAL: Count the entries of a directory
+    fn count_entries(dir: &Path) -> io::Result<usize> {
+        let mut count = 0;
+        for entry in fs::read_dir(dir)? {
+            entry?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+//
+impl ::pacer::PacedSampler for Sampler {
+    type Data = Data;
+
+    fn sample(&mut self) -> io::Result<()> {
+        Sampler::sample(self)
+    }
+
+    fn samples(&self) -> &Data {
+        Sampler::samples(self)
+    }
+}
+
+
+/// Samples of the number of open file descriptors of a process
+#[derive(Debug, PartialEq)]
+pub struct Data {
+    /// Number of open file descriptors
+    counts: Vec<usize>,
+}
+//
+impl SampledData for Data {
+    /// Tell how many samples are present in the data store
+    fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Drop the oldest samples from the data store
+    fn drop_oldest(&mut self, count: usize) {
+        self.counts.drain(..count);
+    }
+
+    /// Tell the length of the shortest time series in the data store
+    fn min_len(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Drop the newest samples from the data store
+    fn truncate(&mut self, length: usize) {
+        self.counts.truncate(length);
+    }
+}
+//
+impl Data {
+    /// Number of open file descriptors
+    pub fn counts(&self) -> &[usize] {
+        &self.counts
+    }
+}
+//
+/// The file descriptor count is exported as "fd_count"
+impl Columns for Data {
+    fn columns(&self) -> Vec<(String, Column)> {
+        vec![("fd_count".to_owned(),
+              Column::U64(self.counts.iter().map(|&c| c as u64).collect()))]
+    }
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use ::data::SampledData;
+    use std::fs::File;
+    use super::Sampler;
+
+    /// Check that the file descriptors of the current process are counted
+    #[test]
+    fn open_files() {
+        let mut sampler = Sampler::new().expect("Failed to create a sampler");
+        assert!(!sampler.command_line().is_empty());
+        sampler.sample().expect("Failed to sample file descriptors");
+        let files: Vec<File> =
+            (0..4).map(|_| File::open("/proc/self/stat").unwrap()).collect();
+        sampler.sample().expect("Failed to sample file descriptors");
+        assert_eq!(sampler.samples().len(), 2);
+        assert_eq!(sampler.timestamps().len(), 2);
+        let counts = sampler.samples().counts();
+        assert!(counts[1] >= files.len());
+
+        // Other threads of the test harness may open and close files at the
+        // same time, so we can only check that sampling worked
+        assert!(counts.iter().all(|&count| count > 0));
+    }
+
+    /// Check that the sampler handles process exits well
+    define_pid_sampler_tests!{ Sampler }
+}
@@ -0,0 +1,236 @@
+//! This module identifies the process behind a /proc/<pid> directory
+//!
+//! Process IDs are recycled by the kernel: once a process has exited, its PID
+//! can be handed over to a new process. When sampling some process for a long
+//! time, the data of an unrelated process could then silently end up in the
+//! same time series. To prevent this, per-process samplers record the time at
+//! which the process was started, from /proc/<pid>/stat, and check before each
+//! sample that it did not change. They also record the process' command line,
+//! from /proc/<pid>/cmdline, so that users can tell which process is which.
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+
+/// Identity of a process, as recorded when sampling of it started
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProcessIdentity {
+    /// PID of the process, or None for the process in which this code runs
+    pid: Option<u32>,
+
+    /// Location of the /proc/<pid>/stat file of the process
+    stat_path: PathBuf,
+
+    /// Command line of the process, with arguments separated by spaces
+    command_line: String,
+
+    /// Time at which the process started, in clock ticks since boot
+    start_time: u64,
+}
+//
+impl ProcessIdentity {
+    /// Identify the process in which this code is running
+    pub fn for_self() -> io::Result<Self> {
+        Self::read(None, Path::new("/proc/self"))
+    }
+
+    /// Identify the process with a certain PID
+    pub fn for_pid(pid: u32) -> io::Result<Self> {
+        Self::read(Some(pid), &PathBuf::from(format!("/proc/{}", pid)))
+    }
+
+    /// Command line of the process, with arguments separated by spaces
+    ///
+    /// Kernel threads and zombie processes have no command line. For those,
+    /// the name of the executable is displayed in brackets instead, as in ps
+    /// (e.g. "[kthreadd]").
+    ///
+    pub fn command_line(&self) -> &str { &self.command_line }
+
+    /// Time at which the process started, in clock ticks since boot
+    pub fn start_time(&self) -> u64 { self.start_time }
+
+    /// Check that the PID still refers to the same process
+    ///
+    /// If the process exited, an I/O error is returned. If its PID is now used
+    /// by another process, an I/O error of kind NotFound is returned, which
+    /// wraps a PidReused error that can be recovered using the get_ref() and
+    /// downcast_ref() methods.
+    ///
+    /// The process in which this code is running cannot be replaced as long as
+    /// the code runs, so it is not checked.
+    ///
+    pub fn check(&self) -> io::Result<()> {
+        let pid = match self.pid {
+            Some(pid) => pid,
+            None => return Ok(()),
+        };
+        let (start_time, _) = read_stat(&self.stat_path)?;
+        if start_time == self.start_time {
+            Ok(())
+        } else {
+            Err(PidReused { pid }.into())
+        }
+    }
+
+    /// INTERNAL: Read the identity of the process behind a /proc directory
+    fn read(pid: Option<u32>, process_dir: &Path) -> io::Result<Self> {
+        let stat_path = process_dir.join("stat");
+        let (start_time, executable) = read_stat(&stat_path)?;
+        let mut cmdline = Vec::new();
+        File::open(process_dir.join("cmdline"))?.read_to_end(&mut cmdline)?;
+        let command_line = match parse_command_line(&cmdline) {
+            ref args if args.is_empty() => format!("[{}]", executable),
+            args => args,
+        };
+        Ok(
+            Self {
+                pid,
+                stat_path,
+                command_line,
+                start_time,
+            }
+        )
+    }
+}
+
+
+/// Error which is emitted when the PID of a sampled process was handed over to
+/// another process, meaning that the original process has exited
+#[derive(Clone, Debug, PartialEq)]
+pub struct PidReused {
+    /// PID which now refers to another process
+    pid: u32,
+}
+//
+impl PidReused {
+    /// PID which now refers to another process
+    pub fn pid(&self) -> u32 { self.pid }
+}
+//
+impl fmt::Display for PidReused {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PID {} now refers to another process", self.pid)
+    }
+}
+//
+impl Error for PidReused {}
+//
+impl From<PidReused> for io::Error {
+    fn from(error: PidReused) -> Self {
+        io::Error::new(io::ErrorKind::NotFound, error)
+    }
+}
+
+
+/// INTERNAL: Read the start time and executable name of a process from its
+///           /proc/<pid>/stat file
+fn read_stat(stat_path: &Path) -> io::Result<(u64, String)> {
+    let mut contents = String::new();
+    File::open(stat_path)?.read_to_string(&mut contents)?;
+    parse_stat(&contents).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData,
+                       "Unexpected /proc/<pid>/stat format")
+    })
+}
+
+
+/// INTERNAL: Extract the start time and executable name of a process from the
+///           contents of its /proc/<pid>/stat file
+///
+/// The executable name is the second field, which is put in parentheses as it
+/// may contain spaces and parentheses of its own. The start time is the 22nd
+/// field, which is counted from the last closing parenthesis.
+///
+fn parse_stat(contents: &str) -> Option<(u64, String)> {
+    let name_start = contents.find('(')? + 1;
+    let name_end = contents.rfind(')')?;
+    if name_end < name_start { return None; }
+    let start_time = contents[name_end+1..].split_whitespace()
+                                           .nth(19)?
+                                           .parse()
+                                           .ok()?;
+    Some((start_time, contents[name_start..name_end].to_owned()))
+}
+
+
+/// INTERNAL: Turn the contents of /proc/<pid>/cmdline, where arguments are
+///           terminated by NUL bytes, into a space-separated command line
+fn parse_command_line(contents: &[u8]) -> String {
+    let contents = String::from_utf8_lossy(contents);
+    contents.trim_end_matches('\0')
+            .split('\0')
+            .collect::<Vec<_>>()
+            .join(" ")
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+    use std::thread;
+    use std::time::Duration;
+    use super::{parse_command_line, parse_stat, PidReused, ProcessIdentity};
+
+    /// Check that command lines are split and joined correctly
+    #[test]
+    fn command_line() {
+        assert_eq!(parse_command_line(b"sleep\x0010\x00"), "sleep 10");
+        assert_eq!(parse_command_line(b"a\x00\x00b\x00"), "a  b");
+        assert_eq!(parse_command_line(b"nginx: worker"), "nginx: worker");
+        assert_eq!(parse_command_line(b""), "");
+    }
+
+    /// Check that start times and executable names are extracted correctly
+    #[test]
+    fn stat_parsing() {
+        const STAT: &str =
+            "1234 (my (weird) exe) S 1 1234 1234 0 -1 4194560 100 0 0 0 \
+             5 3 0 0 20 0 1 0 987654 12345678 300 18446744073709551615\n";
+        assert_eq!(parse_stat(STAT),
+                   Some((987654, "my (weird) exe".to_owned())));
+        assert_eq!(parse_stat("1234 (cat) S 1 1234"), None);
+        assert_eq!(parse_stat("garbage"), None);
+    }
+
+    /// Check that running processes are identified, and that PID reuse is
+    /// detected
+    #[test]
+    fn identity() {
+        let mut child = Command::new("sleep").arg("10")
+                                             .spawn()
+                                             .expect("Failed to start child");
+
+        // The child's command line is only set up at the very end of exec(),
+        // which may complete after spawn() returns, so give it some time
+        thread::sleep(Duration::from_millis(100));
+        let mut identity = ProcessIdentity::for_pid(child.id())
+                                           .expect("Failed to identify child");
+        assert_eq!(identity.command_line(), "sleep 10");
+        identity.check().expect("The child should still be there");
+
+        // Pretend that the PID was handed over to another process
+        identity.start_time += 1;
+        let error = identity.check().expect_err("PID reuse was not detected");
+        let reused = error.get_ref()
+                          .and_then(|error| error.downcast_ref::<PidReused>())
+                          .expect("PID reuse should be reported as such");
+        assert_eq!(reused.pid(), child.id());
+
+        // Once the process is gone, checking it should fail
+        identity.start_time -= 1;
+        child.kill().expect("Failed to kill child");
+        child.wait().expect("Failed to wait for child");
+        assert!(identity.check().is_err());
+
+        // The process in which this code runs is not checked
+        let own_identity = ProcessIdentity::for_self()
+                                          .expect("Failed to identify self");
+        assert!(own_identity.start_time() > 0);
+        own_identity.check().expect("Self-identity should always be valid");
+    }
+}
@@ -0,0 +1,15 @@
+//! This module contains parsers for the contents of /proc/<pid>
+//!
+//! Each process running on the system gets a /proc/<pid> directory, which
+//! describes its activity. The running process can also refer to its own
+//! directory as /proc/self, which is what samplers use by default. Each
+//! submodule corresponds to one file (or directory, for fd_count) in there,
+//! except for the identity module, which is shared by all samplers in order to
+//! tell which process they are sampling.
+
+pub mod fd_count;
+pub mod identity;
+pub mod schedstat;
+pub mod smaps_rollup;
+pub mod status;
+pub mod wchan;
@@ -0,0 +1,246 @@
+//! This module contains a sampling parser for /proc/<pid>/schedstat
+//!
+//! This file tells how much time a task spent running on a CPU and waiting in
+//! a scheduler run queue, the latter being a direct measure of the scheduling
+//! latency experienced by the task.
+//!
+//! If the process exits while it is being sampled, the kernel fails the read
+//! of this file, so the sampler's sample() method reports an I/O error, like
+//! other per-process samplers.
+
+use ::columns::{Column, Columns};
+use ::data::{SampledData, SchemaChanged};
+use ::parser::PseudoFileParser;
+use std::str::SplitWhitespace;
+use std::time::Duration;
+
+
+// Implement a sampler for /proc/<pid>/schedstat
+define_sampler!{ Sampler : pid "schedstat" => Parser => FieldStream => Data }
+
+
+/// Incremental parser for /proc/<pid>/schedstat
+#[derive(Debug, PartialEq)]
+pub struct Parser {}
+//
+impl PseudoFileParser for Parser {
+    /// Build a parser, using an initial file sample. Here, this is used to
+    /// perform quick schema validation, just to maximize the odds that failure,
+    /// if any, will occur at initialization time rather than run time.
+    fn new(initial_contents: &str) -> Self {
+        let mut stream = FieldStream::new(initial_contents);
+        for _ in 0..3 {
+            stream.next().expect("Missing scheduler statistic");
+        }
+        debug_assert_eq!(stream.next(), None, "Unsupported field detected");
+        Self {}
+    }
+}
+//
+// TODO: Implement IncrementalParser once that trait is usable in stable Rust
+impl Parser {
+    /// Begin to parse a pseudo-file sample, streaming its data out
+    pub fn parse<'a>(&mut self, file_contents: &'a str) -> FieldStream<'a> {
+        FieldStream::new(file_contents)
+    }
+}
+///
+///
+/// Stream of parsed data from /proc/<pid>/schedstat
+///
+/// This iterator should successively yield...
+///
+/// * The time spent running on a CPU, in nanoseconds
+/// * The time spent waiting in a run queue, in nanoseconds
+/// * The number of timeslices that were run on a CPU
+/// * A None terminator
+///
+pub struct FieldStream<'a> {
+    /// Extracted columns from /proc/<pid>/schedstat
+    file_columns: SplitWhitespace<'a>,
+}
+//
+impl<'a> Iterator for FieldStream<'a> {
+    /// We output raw integer counters
+    type Item = u64;
+
+    /// Parse the next counter from /proc/<pid>/schedstat
+    fn next(&mut self) -> Option<Self::Item> {
+        self.file_columns.next().map(|column| {
+            column.parse().expect("Failed to parse scheduler statistic")
+        })
+    }
+}
+//
+impl<'a> FieldStream<'a> {
+    /// Set up a FieldStream for a certain sample of /proc/<pid>/schedstat
+    fn new(file_contents: &'a str) -> Self {
+        Self {
+            file_columns: file_contents.split_whitespace(),
+        }
+    }
+}
+
+
+/// Data samples from /proc/<pid>/schedstat, in structure-of-array layout
+#[derive(Debug, PartialEq)]
+pub struct Data {
+    /// Time spent running on a CPU
+    run_time: Vec<Duration>,
+
+    /// Time spent waiting in a run queue for a CPU to become available
+    wait_time: Vec<Duration>,
+
+    /// Number of timeslices run on a CPU
+    timeslices: Vec<u64>,
+}
+//
+impl SampledData for Data {
+    /// Tell how many samples are present in the data store + check consistency
+    fn len(&self) -> usize {
+        let length = self.run_time.len();
+        debug_assert_eq!(length, self.wait_time.len());
+        debug_assert_eq!(length, self.timeslices.len());
+        length
+    }
+
+    /// Drop the oldest samples from the data store
+    fn drop_oldest(&mut self, count: usize) {
+        self.run_time.drain(..count);
+        self.wait_time.drain(..count);
+        self.timeslices.drain(..count);
+    }
+
+    /// Tell the length of the shortest time series in the data store
+    fn min_len(&self) -> usize {
+        [self.run_time.len(),
+         self.wait_time.len(),
+         self.timeslices.len()].iter()
+                               .cloned()
+                               .min()
+                               .expect("There should be some time series")
+    }
+
+    /// Drop the newest samples from the data store
+    fn truncate(&mut self, length: usize) {
+        self.run_time.truncate(length);
+        self.wait_time.truncate(length);
+        self.timeslices.truncate(length);
+    }
+}
+//
+// TODO: Implement SampledDataIncremental once that is usable in stable Rust
+impl Data {
+    /// Time spent running on a CPU
+    pub fn run_time(&self) -> &[Duration] { &self.run_time }
+
+    /// Time spent waiting in a run queue (aka scheduling latency)
+    pub fn wait_time(&self) -> &[Duration] { &self.wait_time }
+
+    /// Number of timeslices run on a CPU
+    pub fn timeslices(&self) -> &[u64] { &self.timeslices }
+
+    /// Create a new scheduler statistics data store
+    fn new(_stream: FieldStream) -> Self {
+        Self {
+            run_time: Vec::new(),
+            wait_time: Vec::new(),
+            timeslices: Vec::new(),
+        }
+    }
+
+    /// Push a new stream of parsed data from /proc/<pid>/schedstat
+    ///
+    /// Empty readouts, which may occur when racing with the kernel, carry no
+    /// scheduler statistics and are rejected without modifying the store.
+    ///
+    fn push(&mut self, mut stream: FieldStream) -> Result<(), SchemaChanged> {
+        let mut fields = [0; 3];
+        for field in fields.iter_mut() {
+            *field = stream.next().ok_or_else(|| {
+                SchemaChanged::new("Missing scheduler stat")
+            })?;
+        }
+        self.run_time.push(Self::nanoseconds(fields[0]));
+        self.wait_time.push(Self::nanoseconds(fields[1]));
+        self.timeslices.push(fields[2]);
+        debug_assert_eq!(stream.next(), None,
+                         "Unsupported entry in /proc/<pid>/schedstat");
+        Ok(())
+    }
+
+    /// INTERNAL: Convert an amount of nanoseconds into a Duration
+    fn nanoseconds(nanos: u64) -> Duration {
+        Duration::new(nanos / 1_000_000_000, (nanos % 1_000_000_000) as u32)
+    }
+}
+//
+impl Columns for Data {
+    fn columns(&self) -> Vec<(String, Column)> {
+        vec![("run_time".to_owned(), Column::durations(&self.run_time)),
+             ("wait_time".to_owned(), Column::durations(&self.wait_time)),
+             ("timeslices".to_owned(), Column::counts(&self.timeslices))]
+    }
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::{Data, Parser, PseudoFileParser, SampledData, Sampler};
+
+    /// Check that parsing scheduler statistics works
+    #[test]
+    fn parse_data() {
+        let mut parser = Parser::new("0 57144 1\n");
+        let mut stream = parser.parse("1234567890 42 17\n");
+        assert_eq!(stream.next(), Some(1234567890));
+        assert_eq!(stream.next(), Some(42));
+        assert_eq!(stream.next(), Some(17));
+        assert_eq!(stream.next(), None);
+    }
+
+    /// Check that sampled data works as expected
+    #[test]
+    fn sampled_data() {
+        let mut parser = Parser::new("0 57144 1\n");
+        let mut data = Data::new(parser.parse("0 57144 1\n"));
+        assert_eq!(data.len(), 0);
+        data.push(parser.parse("0 57144 1\n")).unwrap();
+        data.push(parser.parse("2500000000 1000057144 3\n")).unwrap();
+        assert_eq!(data.len(), 2);
+        assert_eq!(data.run_time(), &[Duration::new(0, 0),
+                                      Duration::new(2, 500_000_000)]);
+        assert_eq!(data.wait_time(), &[Duration::new(0, 57144),
+                                       Duration::new(1, 57144)]);
+        assert_eq!(data.timeslices(), &[1, 3]);
+
+        // Empty readouts are rejected without modifying the store
+        assert!(data.push(parser.parse("")).is_err());
+        assert_eq!(data.len(), 2);
+        assert_eq!(data.run_time().len(), 2);
+
+        data.drop_oldest(1);
+        assert_eq!(data.len(), 1);
+        assert_eq!(data.timeslices(), &[3]);
+    }
+
+    /// Check that the sampler works well
+    define_sampler_tests!{ Sampler }
+
+    /// Check that the sampler handles process exits well
+    define_pid_sampler_tests!{ Sampler }
+}
+
+
+/// Performance benchmarks
+///
+/// See the lib-wide benchmarks module for details on how to use these.
+///
+#[cfg(test)]
+mod benchmarks {
+    define_sampler_benchs!{ super::Sampler,
+                            "/proc/self/schedstat",
+                            1_000_000 }
+}
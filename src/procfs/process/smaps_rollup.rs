@@ -0,0 +1,352 @@
+//! This module contains a sampling parser for /proc/<pid>/smaps_rollup
+//!
+//! This file, available since Linux 4.14, sums up the memory mapping
+//! statistics of /proc/<pid>/smaps over every mapping of a process. It is much
+//! cheaper to read than smaps itself, which makes it suitable for sampling.
+
+use ::columns::{Column, Columns};
+use ::data::{SampledData, SchemaChanged};
+use ::intern::intern;
+use ::parser::PseudoFileParser;
+use ::procfs::meminfo::{Payload, PayloadKind};
+use ::procfs::version::LINUX_VERSION;
+use ::splitter::{SplitColumns, SplitLinesBySpace};
+use bytesize::ByteSize;
+use std::sync::Arc;
+
+
+// Implement a sampler for /proc/<pid>/smaps_rollup
+define_sampler!{ Sampler : pid "smaps_rollup" => Parser
+                                               => RecordStream
+                                               => Data }
+
+
+/// Incremental parser for /proc/<pid>/smaps_rollup
+#[derive(Debug, PartialEq)]
+pub struct Parser {}
+//
+impl PseudoFileParser for Parser {
+    /// Build a parser, using an initial file sample. Here, this is used to
+    /// check that the kernel supports smaps_rollup and to perform quick schema
+    /// validation, in order to maximize the odds that failure, if any, will
+    /// occur at initialization time rather than run time.
+    fn new(initial_contents: &str) -> Self {
+        assert!(LINUX_VERSION.is_unknown()
+                    || LINUX_VERSION.greater_eq(4, 14, 0),
+                "/proc/<pid>/smaps_rollup requires Linux 4.14 or newer");
+        let mut validation_stream = RecordStream::new(initial_contents);
+        while let Some(record) = validation_stream.next() {
+            record.label();
+            assert_eq!(record.extract_payload().kind(),
+                       PayloadKind::DataVolume,
+                       "Expected only data volumes in smaps_rollup");
+        }
+        Self {}
+    }
+}
+//
+// TODO: Implement IncrementalParser once that trait is usable in stable Rust
+impl Parser {
+    /// Parse a pseudo-file sample into a stream of records
+    pub fn parse<'a>(&mut self, file_contents: &'a str) -> RecordStream<'a> {
+        RecordStream::new(file_contents)
+    }
+}
+///
+///
+/// Stream of records from /proc/<pid>/smaps_rollup
+///
+/// This streaming iterator should yield a stream of records, each representing
+/// a line of the file (i.e. a labeled data volume) after the header.
+///
+pub struct RecordStream<'a> {
+    /// Iterator into the lines and columns of the file
+    file_lines: SplitLinesBySpace<'a>,
+}
+//
+impl<'a> RecordStream<'a> {
+    /// Parse the next record from the file into a stream of fields
+    pub fn next<'b>(&'b mut self) -> Option<Record<'a, 'b>>
+        where 'a: 'b
+    {
+        self.file_lines.next().map(Record::new)
+    }
+
+    /// Create a record stream from raw contents
+    fn new(file_contents: &'a str) -> Self {
+        // The file starts with a pseudo-mapping header, which we skip. Empty
+        // readouts, which may occur when racing with the kernel, yield no
+        // record at all.
+        let mut file_lines = SplitLinesBySpace::new(file_contents);
+        if !file_contents.is_empty() {
+            file_lines.next().expect("Missing smaps_rollup header");
+        }
+        Self {
+            file_lines,
+        }
+    }
+}
+///
+///
+/// Record from /proc/<pid>/smaps_rollup (labeled data volume)
+pub struct Record<'a, 'b> where 'a: 'b {
+    /// Label of the active record, including the trailing colon
+    label_field: &'a str,
+
+    /// Iterator into the payload's columns
+    payload_columns: SplitColumns<'a, 'b>,
+}
+//
+impl<'a, 'b> Record<'a, 'b> {
+    /// Tell how this record is labeled (e.g. "Pss")
+    pub fn label(&self) -> &'a str {
+        let label_length = self.label_field.len();
+        assert!(label_length > 1 && self.label_field.ends_with(':'),
+                "Incorrectly formatted smaps_rollup label");
+        &self.label_field[..label_length-1]
+    }
+
+    /// Extract the payload from the active record
+    ///
+    /// We share the logic of /proc/meminfo here, as both files use the same
+    /// (slightly misleading) convention of denoting kibibytes as "kB".
+    ///
+    pub fn extract_payload(self) -> Payload<'a> {
+        Payload::new(self.payload_columns)
+    }
+
+    /// Construct a record from associated file columns
+    fn new(mut record_columns: SplitColumns<'a, 'b>) -> Self {
+        let label_field = record_columns.next().expect("Record label missing");
+        Self {
+            label_field,
+            payload_columns: record_columns,
+        }
+    }
+}
+
+
+/// Data samples from /proc/<pid>/smaps_rollup, in structure-of-array layout
+///
+/// As with /proc/meminfo, the layout is optimized for fast sampling rather
+/// than fast lookup of a specific key. The most useful records have dedicated
+/// accessors, and the other ones can be looked up by key.
+///
+#[derive(Debug, PartialEq)]
+pub struct Data {
+    /// Sampled data volumes, in file order
+    volumes: Vec<Vec<ByteSize>>,
+
+    /// Keys associated with each record, again in file order. These are
+    /// interned, as they are the same for every process.
+    keys: Vec<Arc<str>>,
+}
+//
+impl SampledData for Data {
+    /// Tell how many samples are present in the data store + check consistency
+    fn len(&self) -> usize {
+        let length = self.volumes.first().map_or(0, |vec| vec.len());
+        debug_assert!(self.volumes.iter().all(|vec| vec.len() == length));
+        length
+    }
+
+    /// Drop the oldest samples from the data store
+    fn drop_oldest(&mut self, count: usize) {
+        for vec in self.volumes.iter_mut() {
+            vec.drain(..count);
+        }
+    }
+
+    /// Tell the length of the shortest time series in the data store
+    fn min_len(&self) -> usize {
+        self.volumes.iter().map(|vec| vec.len()).min().unwrap_or(0)
+    }
+
+    /// Drop the newest samples from the data store
+    fn truncate(&mut self, length: usize) {
+        for vec in self.volumes.iter_mut() {
+            vec.truncate(length);
+        }
+    }
+}
+//
+// TODO: Implement SampledDataIncremental once that is usable in stable Rust
+impl Data {
+    /// Labels of the records from /proc/<pid>/smaps_rollup, in file order
+    pub fn keys<'a>(&'a self) -> impl Iterator<Item=&'a str> + 'a {
+        self.keys.iter().map(|key| &**key)
+    }
+
+    /// Samples of a record (e.g. "Private_Dirty"), if present
+    ///
+    /// This performs a linear search across the records, so if you need
+    /// frequent access, consider caching the result.
+    ///
+    pub fn data_volume(&self, key: &str) -> Option<&[ByteSize]> {
+        self.keys.iter()
+                 .position(|k| &**k == key)
+                 .map(|idx| &self.volumes[idx][..])
+    }
+
+    /// Resident set size, i.e. memory which is mapped in RAM
+    pub fn rss(&self) -> Option<&[ByteSize]> {
+        self.data_volume("Rss")
+    }
+
+    /// Proportional set size, i.e. resident memory where each page that is
+    /// shared with N processes only counts for 1/N
+    ///
+    /// This is the fairest way to attribute memory usage to processes which
+    /// share some of their memory mappings (e.g. shared libraries).
+    ///
+    pub fn pss(&self) -> Option<&[ByteSize]> {
+        self.data_volume("Pss")
+    }
+
+    /// Amount of memory which was swapped out
+    pub fn swap(&self) -> Option<&[ByteSize]> {
+        self.data_volume("Swap")
+    }
+
+    /// Create a new smaps_rollup data store, using a first sample to know the
+    /// structure of /proc/<pid>/smaps_rollup
+    fn new(mut stream: RecordStream) -> Self {
+        let mut keys = Vec::new();
+        while let Some(record) = stream.next() {
+            keys.push(intern(record.label()));
+        }
+        Self {
+            volumes: vec![Vec::new(); keys.len()],
+            keys,
+        }
+    }
+
+    /// Parse the contents of /proc/<pid>/smaps_rollup and add a data sample to
+    /// all corresponding entries in the internal data store
+    ///
+    /// If a record has disappeared, which notably happens when the kernel
+    /// hands over an empty readout, the partial sample is rolled back.
+    ///
+    fn push(&mut self, mut stream: RecordStream) -> Result<(), SchemaChanged> {
+        let sample_count = self.len();
+        let mut recorded = 0;
+        for (key, vec) in self.keys.iter().zip(self.volumes.iter_mut()) {
+            let record = match stream.next() {
+                Some(record) => record,
+                None => break,
+            };
+            debug_assert_eq!(record.label(), &**key,
+                             "Unsupported schema change");
+            vec.push(record.extract_payload().parse_data_volume());
+            recorded += 1;
+        }
+
+        // If a record has disappeared, roll back the partial sample
+        if recorded < self.volumes.len() {
+            for vec in self.volumes[..recorded].iter_mut() {
+                vec.truncate(sample_count);
+            }
+            return Err(
+                SchemaChanged::new("A smaps_rollup record has disappeared")
+            );
+        }
+        debug_assert!(stream.next().is_none(), "Unsupported schema change");
+        Ok(())
+    }
+}
+//
+/// Records are exported under their key (e.g. "Pss")
+impl Columns for Data {
+    fn columns(&self) -> Vec<(String, Column)> {
+        self.keys.iter()
+                 .zip(self.volumes.iter())
+                 .map(|(key, vec)| (key.to_string(), Column::bytes(vec)))
+                 .collect()
+    }
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use bytesize::ByteSize;
+    use super::{Data, Parser, PseudoFileParser, SampledData, Sampler};
+
+    /// Build a mock smaps_rollup file
+    fn mock_file(rss: usize, pss: usize, swap: usize) -> String {
+        format!("558b553c8000-7ffe8fc26000 ---p 00000000 00:00 0  [rollup]\n\
+                 Rss:           {:5} kB\n\
+                 Pss:           {:5} kB\n\
+                 Private_Dirty:   100 kB\n\
+                 Swap:          {:5} kB\n", rss, pss, swap)
+    }
+
+    /// Check that parsing smaps_rollup records works
+    #[test]
+    fn record_stream() {
+        let file = mock_file(1384, 402, 0);
+        let mut parser = Parser::new(&file);
+        let mut stream = parser.parse(&file);
+        {
+            let record = stream.next().expect("Missing first record");
+            assert_eq!(record.label(), "Rss");
+            assert_eq!(record.extract_payload().parse_data_volume(),
+                       ByteSize::kib(1384));
+        }
+        for &label in &["Pss", "Private_Dirty", "Swap"] {
+            let record = stream.next().expect("Missing record");
+            assert_eq!(record.label(), label);
+        }
+        assert!(stream.next().is_none());
+    }
+
+    /// Check that sampled data works as expected
+    #[test]
+    fn sampled_data() {
+        let initial = mock_file(1384, 402, 0);
+        let mut parser = Parser::new(&initial);
+        let mut data = Data::new(parser.parse(&initial));
+        let keys: Vec<&str> = data.keys().collect();
+        assert_eq!(keys, &["Rss", "Pss", "Private_Dirty", "Swap"]);
+        assert_eq!(data.len(), 0);
+        data.push(parser.parse(&initial)).unwrap();
+        data.push(parser.parse(&mock_file(2048, 1024, 4))).unwrap();
+        assert_eq!(data.len(), 2);
+        assert_eq!(data.rss(), Some(&[ByteSize::kib(1384),
+                                      ByteSize::kib(2048)][..]));
+        assert_eq!(data.pss(), Some(&[ByteSize::kib(402),
+                                      ByteSize::kib(1024)][..]));
+        assert_eq!(data.swap(), Some(&[ByteSize::kib(0),
+                                       ByteSize::kib(4)][..]));
+        assert_eq!(data.data_volume("Private_Dirty"),
+                   Some(&[ByteSize::kib(100), ByteSize::kib(100)][..]));
+        assert_eq!(data.data_volume("Anonymous"), None);
+
+        // Empty readouts are rejected without modifying the store
+        assert!(data.push(parser.parse("")).is_err());
+        assert_eq!(data.len(), 2);
+        assert_eq!(data.rss().map(|rss| rss.len()), Some(2));
+
+        data.drop_oldest(1);
+        assert_eq!(data.len(), 1);
+        assert_eq!(data.pss(), Some(&[ByteSize::kib(1024)][..]));
+    }
+
+    /// Check that the sampler works well
+    define_sampler_tests!{ Sampler }
+
+    /// Check that the sampler handles process exits well
+    define_pid_sampler_tests!{ Sampler }
+}
+
+
+/// Performance benchmarks
+///
+/// See the lib-wide benchmarks module for details on how to use these.
+///
+#[cfg(test)]
+mod benchmarks {
+    define_sampler_benchs!{ super::Sampler,
+                            "/proc/self/smaps_rollup",
+                            100_000 }
+}
@@ -0,0 +1,655 @@
+//! This module contains a sampling parser for /proc/<pid>/status
+
+use ::columns::{Column, Columns};
+use ::data::{SampledData, SchemaChanged};
+use ::parser::PseudoFileParser;
+use ::procfs::meminfo::{Payload, PayloadKind};
+use ::splitter::SplitLinesBySpace;
+use bytesize::ByteSize;
+use std::str::Lines;
+
+
+// Implement a sampler for /proc/<pid>/status
+define_sampler!{ Sampler : pid "status" => Parser => RecordStream => Data }
+
+
+/// Incremental parser for /proc/<pid>/status
+#[derive(Debug, PartialEq)]
+pub struct Parser {}
+//
+impl PseudoFileParser for Parser {
+    /// Build a parser, using an initial file sample. Here, this is used to
+    /// perform quick schema validation, just to maximize the odds that failure,
+    /// if any, will occur at initialization time rather than run time.
+    fn new(initial_contents: &str) -> Self {
+        let mut validation_stream = RecordStream::new(initial_contents);
+        while let Some(record) = validation_stream.next() {
+            match RecordKind::from_key(record.key()) {
+                RecordKind::DataVolume(_) => { record.parse_data_volume(); },
+                RecordKind::Threads => { record.parse_counter::<u32>(); },
+                RecordKind::ContextSwitches(_) => {
+                    record.parse_counter::<u64>();
+                },
+                RecordKind::Name | RecordKind::State
+                                 | RecordKind::Unsupported => {},
+            }
+        }
+        Self {}
+    }
+}
+//
+// TODO: Implement IncrementalParser once that trait is usable in stable Rust
+impl Parser {
+    /// Parse a pseudo-file sample into a stream of records
+    pub fn parse<'a>(&mut self, file_contents: &'a str) -> RecordStream<'a> {
+        RecordStream::new(file_contents)
+    }
+}
+///
+///
+/// Stream of records from /proc/<pid>/status
+///
+/// This iterator yields a stream of records, each representing a line of
+/// /proc/<pid>/status (i.e. a "Key:<tab>Value" pair).
+///
+pub struct RecordStream<'a> {
+    /// Iterator into the lines of /proc/<pid>/status
+    file_lines: Lines<'a>,
+}
+//
+impl<'a> Iterator for RecordStream<'a> {
+    /// We're outputting records
+    type Item = Record<'a>;
+
+    /// This is how we generate them from file lines
+    fn next(&mut self) -> Option<Self::Item> {
+        self.file_lines.next().map(Record::new)
+    }
+}
+//
+impl<'a> RecordStream<'a> {
+    /// Create a record stream from raw contents
+    fn new(file_contents: &'a str) -> Self {
+        Self {
+            file_lines: file_contents.lines(),
+        }
+    }
+}
+///
+///
+/// Record from /proc/<pid>/status
+#[derive(Debug, PartialEq)]
+pub struct Record<'a> {
+    /// Key of the record, without the trailing colon
+    key: &'a str,
+
+    /// Value of the record, without the surrounding whitespace
+    value: &'a str,
+}
+//
+impl<'a> Record<'a> {
+    /// Tell which key this record is associated with
+    pub fn key(&self) -> &'a str {
+        self.key
+    }
+
+    /// Parse the value of this record as a data volume (e.g. "2640 kB")
+    ///
+    /// We share the logic of /proc/meminfo here, as both files use the same
+    /// (slightly misleading) convention of denoting kibibytes as "kB".
+    ///
+    fn parse_data_volume(&self) -> ByteSize {
+        let mut value_lines = SplitLinesBySpace::new(self.value);
+        let value_columns = value_lines.next().expect("Missing data volume");
+        let payload = Payload::new(value_columns);
+        assert_eq!(payload.kind(), PayloadKind::DataVolume,
+                   "Expected a data volume");
+        payload.parse_data_volume()
+    }
+
+    /// Parse the value of this record as a raw counter
+    fn parse_counter<T: ::std::str::FromStr>(&self) -> T {
+        self.value.parse().ok().expect("Failed to parse counter")
+    }
+
+    /// Construct a record from a line of the file
+    fn new(line: &'a str) -> Self {
+        let colon_idx = line.find(':').expect("Missing status key separator");
+        Self {
+            key: &line[..colon_idx],
+            value: line[colon_idx+1..].trim(),
+        }
+    }
+}
+
+
+/// Records of /proc/<pid>/status that we know how to handle
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RecordKind {
+    /// Name of the executable, read once
+    Name,
+
+    /// Process state, read once
+    State,
+
+    /// One of the memory usage records (VmPeak, VmRSS...)
+    DataVolume(MemoryRecord),
+
+    /// Number of threads in the process
+    Threads,
+
+    /// One of the context switch counters
+    ContextSwitches(ContextSwitchKind),
+
+    /// Something which we do not sample
+    Unsupported,
+}
+//
+impl RecordKind {
+    /// Tell what kind of record is associated with a certain key
+    fn from_key(key: &str) -> Self {
+        match key {
+            "Name" => RecordKind::Name,
+            "State" => RecordKind::State,
+            "VmPeak" => RecordKind::DataVolume(MemoryRecord::VmPeak),
+            "VmSize" => RecordKind::DataVolume(MemoryRecord::VmSize),
+            "VmRSS" => RecordKind::DataVolume(MemoryRecord::VmRSS),
+            "VmData" => RecordKind::DataVolume(MemoryRecord::VmData),
+            "VmStk" => RecordKind::DataVolume(MemoryRecord::VmStk),
+            "VmSwap" => RecordKind::DataVolume(MemoryRecord::VmSwap),
+            "Threads" => RecordKind::Threads,
+            "voluntary_ctxt_switches" =>
+                RecordKind::ContextSwitches(ContextSwitchKind::Voluntary),
+            "nonvoluntary_ctxt_switches" =>
+                RecordKind::ContextSwitches(ContextSwitchKind::Nonvoluntary),
+            _ => RecordKind::Unsupported,
+        }
+    }
+}
+///
+/// Memory usage records of /proc/<pid>/status
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum MemoryRecord { VmPeak, VmSize, VmRSS, VmData, VmStk, VmSwap }
+///
+/// Context switch counters of /proc/<pid>/status
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ContextSwitchKind { Voluntary, Nonvoluntary }
+
+
+/// Data samples from /proc/<pid>/status, in structure-of-array layout
+///
+/// Memory usage records are not provided for kernel threads, and some of them
+/// appeared in relatively recent kernel releases (e.g. VmSwap in Linux
+/// 2.6.34), so they are considered optional.
+///
+#[derive(Debug, PartialEq)]
+pub struct Data {
+    /// Name of the executable (only collected once)
+    name: String,
+
+    /// State of the process (only collected once)
+    state: String,
+
+    /// Peak virtual memory size
+    vm_peak: Option<Vec<ByteSize>>,
+
+    /// Virtual memory size
+    vm_size: Option<Vec<ByteSize>>,
+
+    /// Resident set size
+    vm_rss: Option<Vec<ByteSize>>,
+
+    /// Size of the data segment
+    vm_data: Option<Vec<ByteSize>>,
+
+    /// Size of the stack segment
+    vm_stk: Option<Vec<ByteSize>>,
+
+    /// Amount of swapped-out virtual memory
+    vm_swap: Option<Vec<ByteSize>>,
+
+    /// Number of threads in the process
+    threads: Vec<u32>,
+
+    /// Number of voluntary context switches (e.g. blocking on IO)
+    voluntary_ctxt_switches: Option<Vec<u64>>,
+
+    /// Number of involuntary context switches (e.g. preemption)
+    nonvoluntary_ctxt_switches: Option<Vec<u64>>,
+
+    /// INTERNAL: This vector indicates how each line of /proc/<pid>/status
+    /// maps to the members of this struct, along with the length of the
+    /// associated key for quick schema validation.
+    line_target: Vec<(RecordKind, usize)>,
+}
+//
+impl SampledData for Data {
+    /// Tell how many samples are present in the data store + check consistency
+    fn len(&self) -> usize {
+        let length = self.threads.len();
+        let optional_len = |op: &Option<Vec<ByteSize>>| -> usize {
+            op.as_ref().map_or(length, |vec| vec.len())
+        };
+        debug_assert_eq!(length, optional_len(&self.vm_peak));
+        debug_assert_eq!(length, optional_len(&self.vm_size));
+        debug_assert_eq!(length, optional_len(&self.vm_rss));
+        debug_assert_eq!(length, optional_len(&self.vm_data));
+        debug_assert_eq!(length, optional_len(&self.vm_stk));
+        debug_assert_eq!(length, optional_len(&self.vm_swap));
+        debug_assert_eq!(length,
+                         self.voluntary_ctxt_switches.as_ref()
+                                                     .map_or(length,
+                                                             |v| v.len()));
+        debug_assert_eq!(length,
+                         self.nonvoluntary_ctxt_switches.as_ref()
+                                                        .map_or(length,
+                                                                |v| v.len()));
+        length
+    }
+
+    /// Drop the oldest samples from the data store
+    fn drop_oldest(&mut self, count: usize) {
+        self.threads.drain(..count);
+        let optional_drop = |op: &mut Option<Vec<ByteSize>>| {
+            if let Some(ref mut vec) = *op {
+                vec.drain(..count);
+            }
+        };
+        optional_drop(&mut self.vm_peak);
+        optional_drop(&mut self.vm_size);
+        optional_drop(&mut self.vm_rss);
+        optional_drop(&mut self.vm_data);
+        optional_drop(&mut self.vm_stk);
+        optional_drop(&mut self.vm_swap);
+        if let Some(ref mut vec) = self.voluntary_ctxt_switches {
+            vec.drain(..count);
+        }
+        if let Some(ref mut vec) = self.nonvoluntary_ctxt_switches {
+            vec.drain(..count);
+        }
+    }
+
+    /// Tell the length of the shortest time series in the data store
+    fn min_len(&self) -> usize {
+        let length = self.threads.len();
+        let optional_len = |op: &Option<Vec<ByteSize>>| -> usize {
+            op.as_ref().map_or(length, |vec| vec.len())
+        };
+        let ctxt_len = |op: &Option<Vec<u64>>| -> usize {
+            op.as_ref().map_or(length, |vec| vec.len())
+        };
+        [length,
+         optional_len(&self.vm_peak),
+         optional_len(&self.vm_size),
+         optional_len(&self.vm_rss),
+         optional_len(&self.vm_data),
+         optional_len(&self.vm_stk),
+         optional_len(&self.vm_swap),
+         ctxt_len(&self.voluntary_ctxt_switches),
+         ctxt_len(&self.nonvoluntary_ctxt_switches)].iter()
+                                                    .cloned()
+                                                    .min()
+                                                    .unwrap_or(length)
+    }
+
+    /// Drop the newest samples from the data store
+    fn truncate(&mut self, length: usize) {
+        self.threads.truncate(length);
+        let optional_truncate = |op: &mut Option<Vec<ByteSize>>| {
+            if let Some(ref mut vec) = *op {
+                vec.truncate(length);
+            }
+        };
+        optional_truncate(&mut self.vm_peak);
+        optional_truncate(&mut self.vm_size);
+        optional_truncate(&mut self.vm_rss);
+        optional_truncate(&mut self.vm_data);
+        optional_truncate(&mut self.vm_stk);
+        optional_truncate(&mut self.vm_swap);
+        if let Some(ref mut vec) = self.voluntary_ctxt_switches {
+            vec.truncate(length);
+        }
+        if let Some(ref mut vec) = self.nonvoluntary_ctxt_switches {
+            vec.truncate(length);
+        }
+    }
+}
+//
+// TODO: Implement SampledDataIncremental once that is usable in stable Rust
+impl Data {
+    /// Name of the executable
+    pub fn name(&self) -> &str { &self.name }
+
+    /// State of the process at the time where sampling started
+    pub fn state(&self) -> &str { &self.state }
+
+    /// Peak virtual memory size
+    pub fn vm_peak(&self) -> Option<&[ByteSize]> { Self::opt(&self.vm_peak) }
+
+    /// Virtual memory size
+    pub fn vm_size(&self) -> Option<&[ByteSize]> { Self::opt(&self.vm_size) }
+
+    /// Resident set size
+    pub fn vm_rss(&self) -> Option<&[ByteSize]> { Self::opt(&self.vm_rss) }
+
+    /// Size of the data segment
+    pub fn vm_data(&self) -> Option<&[ByteSize]> { Self::opt(&self.vm_data) }
+
+    /// Size of the stack segment
+    pub fn vm_stk(&self) -> Option<&[ByteSize]> { Self::opt(&self.vm_stk) }
+
+    /// Amount of swapped-out virtual memory
+    pub fn vm_swap(&self) -> Option<&[ByteSize]> { Self::opt(&self.vm_swap) }
+
+    /// Number of threads in the process
+    pub fn threads(&self) -> &[u32] { &self.threads }
+
+    /// Number of voluntary context switches
+    pub fn voluntary_ctxt_switches(&self) -> Option<&[u64]> {
+        Self::opt(&self.voluntary_ctxt_switches)
+    }
+
+    /// Number of involuntary context switches
+    pub fn nonvoluntary_ctxt_switches(&self) -> Option<&[u64]> {
+        Self::opt(&self.nonvoluntary_ctxt_switches)
+    }
+
+    /// Create a new process status data store, using a first sample to know
+    /// the structure of /proc/<pid>/status
+    fn new(stream: RecordStream) -> Self {
+        // Our data store will eventually go there
+        let mut store = Self {
+            name: String::new(),
+            state: String::new(),
+            vm_peak: None,
+            vm_size: None,
+            vm_rss: None,
+            vm_data: None,
+            vm_stk: None,
+            vm_swap: None,
+            threads: Vec::new(),
+            voluntary_ctxt_switches: None,
+            nonvoluntary_ctxt_switches: None,
+            line_target: Vec::new(),
+        };
+
+        // Analyze each record, and set up the matching storage
+        for record in stream {
+            let kind = RecordKind::from_key(record.key());
+            match kind {
+                RecordKind::Name => store.name = record.value.to_owned(),
+                RecordKind::State => store.state = record.value.to_owned(),
+                RecordKind::DataVolume(mem_record) => {
+                    *store.memory_record(mem_record) = Some(Vec::new());
+                },
+                RecordKind::Threads => {},
+                RecordKind::ContextSwitches(ctxt_kind) => {
+                    *store.context_switches(ctxt_kind) = Some(Vec::new());
+                },
+                RecordKind::Unsupported => {},
+            }
+            store.line_target.push((kind, record.key().len()));
+        }
+
+        // Return our data collection setup
+        store
+    }
+
+    /// Parse the contents of /proc/<pid>/status and add a data sample to all
+    /// corresponding entries in the internal data store
+    ///
+    /// If a record has vanished, which notably happens when the kernel hands
+    /// over an empty readout, the partial sample is rolled back.
+    ///
+    fn push(&mut self, mut stream: RecordStream) -> Result<(), SchemaChanged> {
+        // This time, we know how lines of the file map to our members
+        let sample_count = self.len();
+        for target_idx in 0..self.line_target.len() {
+            let (kind, key_len) = self.line_target[target_idx];
+
+            // Check that the record which we observed initially is still there
+            let record = match stream.next() {
+                Some(record) => record,
+                None => {
+                    self.truncate(sample_count);
+                    return Err(
+                        SchemaChanged::new("A status record has vanished")
+                    );
+                }
+            };
+            assert_eq!(record.key().len(), key_len,
+                       "Unsupported structural status change during sampling");
+            debug_assert_eq!(RecordKind::from_key(record.key()), kind,
+                             "Unsupported status change during sampling");
+
+            // Forward the record's payload to its target
+            match kind {
+                RecordKind::DataVolume(mem_record) => {
+                    self.memory_record(mem_record)
+                        .as_mut()
+                        .expect("Memory record storage should be set up")
+                        .push(record.parse_data_volume());
+                },
+                RecordKind::Threads => {
+                    self.threads.push(record.parse_counter());
+                },
+                RecordKind::ContextSwitches(ctxt_kind) => {
+                    self.context_switches(ctxt_kind)
+                        .as_mut()
+                        .expect("Context switch storage should be set up")
+                        .push(record.parse_counter());
+                },
+                RecordKind::Name | RecordKind::State
+                                 | RecordKind::Unsupported => {},
+            }
+        }
+
+        // In debug mode, we also check that records did not appear out of blue
+        debug_assert!(stream.next().is_none(),
+                      "A status record appeared out of nowhere");
+        Ok(())
+    }
+
+    /// INTERNAL: Access the storage associated with a memory record
+    fn memory_record(&mut self,
+                     mem_record: MemoryRecord) -> &mut Option<Vec<ByteSize>> {
+        match mem_record {
+            MemoryRecord::VmPeak => &mut self.vm_peak,
+            MemoryRecord::VmSize => &mut self.vm_size,
+            MemoryRecord::VmRSS => &mut self.vm_rss,
+            MemoryRecord::VmData => &mut self.vm_data,
+            MemoryRecord::VmStk => &mut self.vm_stk,
+            MemoryRecord::VmSwap => &mut self.vm_swap,
+        }
+    }
+
+    /// INTERNAL: Access the storage associated with a context switch counter
+    fn context_switches(&mut self,
+                        ctxt_kind: ContextSwitchKind) -> &mut Option<Vec<u64>> {
+        match ctxt_kind {
+            ContextSwitchKind::Voluntary => &mut self.voluntary_ctxt_switches,
+            ContextSwitchKind::Nonvoluntary =>
+                &mut self.nonvoluntary_ctxt_switches,
+        }
+    }
+
+    /// INTERNAL: Expose an optional time series as an optional slice
+    fn opt<T>(op: &Option<Vec<T>>) -> Option<&[T]> {
+        op.as_ref().map(|vec| &vec[..])
+    }
+}
+//
+/// Statistics are exported under the key of the record that they come from
+/// (e.g. "VmRSS"), provided that the kernel reports them
+impl Columns for Data {
+    fn columns(&self) -> Vec<(String, Column)> {
+        let volumes = [("VmPeak", &self.vm_peak),
+                       ("VmSize", &self.vm_size),
+                       ("VmRSS", &self.vm_rss),
+                       ("VmData", &self.vm_data),
+                       ("VmStk", &self.vm_stk),
+                       ("VmSwap", &self.vm_swap)];
+        let mut columns: Vec<_> =
+            volumes.iter()
+                   .filter_map(|&(key, vec)| vec.as_ref().map(|vec| {
+                       (key.to_owned(), Column::bytes(vec))
+                   }))
+                   .collect();
+        columns.push(("Threads".to_owned(), Column::counts(&self.threads)));
+        let switches =
+            [("voluntary_ctxt_switches", &self.voluntary_ctxt_switches),
+             ("nonvoluntary_ctxt_switches", &self.nonvoluntary_ctxt_switches)];
+        columns.extend(
+            switches.iter()
+                    .filter_map(|&(key, vec)| vec.as_ref().map(|vec| {
+                        (key.to_owned(), Column::counts(vec))
+                    }))
+        );
+        columns
+    }
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use bytesize::ByteSize;
+    use std::process::Command;
+    use super::{Data, Parser, PseudoFileParser, Record, RecordStream, Sampler,
+                SampledData};
+
+    /// Check that records are split properly, including tab separators
+    #[test]
+    fn record_parsing() {
+        let record = Record::new("Name:\tcat");
+        assert_eq!(record.key(), "Name");
+        assert_eq!(record.value, "cat");
+
+        let record = Record::new("VmRSS:\t    1432 kB");
+        assert_eq!(record.key(), "VmRSS");
+        assert_eq!(record.parse_data_volume(), ByteSize::kib(1432));
+
+        let record = Record::new("Threads:\t12");
+        assert_eq!(record.key(), "Threads");
+        assert_eq!(record.parse_counter::<u32>(), 12);
+
+        let record = Record::new("voluntary_ctxt_switches:\t150");
+        assert_eq!(record.parse_counter::<u64>(), 150);
+    }
+
+    /// Check that the parser and data store work as expected
+    #[test]
+    fn sampled_data() {
+        // Build a mock file
+        let file_contents = |rss: usize, ctxt: u64| -> String {
+            format!("Name:\tcat\n\
+                     State:\tR (running)\n\
+                     Tgid:\t3260\n\
+                     VmPeak:\t    2640 kB\n\
+                     VmSize:\t    2640 kB\n\
+                     VmRSS:\t    {} kB\n\
+                     VmData:\t     360 kB\n\
+                     VmStk:\t     132 kB\n\
+                     VmSwap:\t       0 kB\n\
+                     Threads:\t1\n\
+                     SigQ:\t0/24002\n\
+                     voluntary_ctxt_switches:\t{}\n\
+                     nonvoluntary_ctxt_switches:\t1\n", rss, ctxt)
+        };
+        let file1 = file_contents(1432, 1);
+        let file2 = file_contents(2048, 5);
+
+        // Initialize the data store and check its initial state
+        let mut parser = Parser::new(&file1);
+        let mut data = Data::new(parser.parse(&file1));
+        assert_eq!(data.len(), 0);
+        assert_eq!(data.name(), "cat");
+        assert_eq!(data.state(), "R (running)");
+        assert_eq!(data.vm_rss(), Some(&[] as &[ByteSize]));
+
+        // Push some samples
+        data.push(parser.parse(&file1)).unwrap();
+        data.push(parser.parse(&file2)).unwrap();
+        assert_eq!(data.len(), 2);
+        assert_eq!(data.vm_peak(), Some(&[ByteSize::kib(2640),
+                                          ByteSize::kib(2640)][..]));
+        assert_eq!(data.vm_rss(), Some(&[ByteSize::kib(1432),
+                                         ByteSize::kib(2048)][..]));
+        assert_eq!(data.vm_swap(), Some(&[ByteSize::kib(0),
+                                          ByteSize::kib(0)][..]));
+        assert_eq!(data.threads(), &[1, 1]);
+        assert_eq!(data.voluntary_ctxt_switches(), Some(&[1, 5][..]));
+        assert_eq!(data.nonvoluntary_ctxt_switches(), Some(&[1, 1][..]));
+
+        // Empty or truncated readouts are rejected without modifying the store
+        assert!(data.push(parser.parse("")).is_err());
+        let truncated = file2.lines().take(6).collect::<Vec<_>>().join("\n");
+        assert!(data.push(parser.parse(&truncated)).is_err());
+        assert_eq!(data.len(), 2);
+        assert_eq!(data.vm_rss(), Some(&[ByteSize::kib(1432),
+                                         ByteSize::kib(2048)][..]));
+
+        // Drop the oldest sample
+        data.drop_oldest(1);
+        assert_eq!(data.len(), 1);
+        assert_eq!(data.vm_rss(), Some(&[ByteSize::kib(2048)][..]));
+    }
+
+    /// Check that kernel threads, which have no memory records, are supported
+    #[test]
+    fn kernel_thread() {
+        let file = "Name:\tkthreadd\nState:\tS (sleeping)\nThreads:\t1\n";
+        let mut data = Data::new(RecordStream::new(file));
+        data.push(RecordStream::new(file)).unwrap();
+        assert_eq!(data.len(), 1);
+        assert_eq!(data.vm_rss(), None);
+        assert_eq!(data.voluntary_ctxt_switches(), None);
+        assert_eq!(data.threads(), &[1]);
+    }
+
+    /// Check that samplers know which process they are sampling
+    #[test]
+    fn process_identity() {
+        let sampler = Sampler::new().expect("Failed to create a sampler");
+        let process = sampler.process().expect("Missing process identity");
+        assert!(!process.command_line().is_empty());
+        assert_eq!(sampler.command_line(), Some(process.command_line()));
+
+        // Without a /proc/<pid> directory, there is no identity to be known
+        let sampler = Sampler::from_source(|| {
+            Ok("Name:\tcat\nThreads:\t1\n".to_owned())
+        }).expect("Failed to create a sampler");
+        assert_eq!(sampler.process(), None);
+        assert_eq!(sampler.command_line(), None);
+
+        // Once a sampled process is gone, no sampling method should report
+        // data from its former PID
+        let mut child = Command::new("sleep").arg("10")
+                                             .spawn()
+                                             .expect("Failed to start child");
+        let mut sampler = Sampler::for_pid(child.id())
+                                  .expect("Failed to create a sampler");
+        sampler.sample().expect("Failed to sample a live process");
+        child.kill().expect("Failed to kill child");
+        child.wait().expect("Failed to wait for child");
+        assert!(sampler.sample().is_err());
+        assert!(sampler.sample_with(|_stream| ()).is_err());
+        assert!(sampler.sample_with_if_changed(|_stream| ()).is_err());
+        assert_eq!(sampler.samples().len(), 1);
+    }
+
+    /// Check that the sampler works well
+    define_sampler_tests!{ super::Sampler }
+}
+
+
+/// Performance benchmarks
+///
+/// See the lib-wide benchmarks module for details on how to use these.
+///
+#[cfg(test)]
+mod benchmarks {
+    define_sampler_benchs!{ super::Sampler,
+                            "/proc/self/status",
+                            100_000 }
+}
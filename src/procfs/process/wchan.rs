@@ -0,0 +1,286 @@
+//! This module contains a sampling parser for /proc/<pid>/wchan
+//!
+//! This file contains the symbolic name of the kernel function in which the
+//! process is currently sleeping (its "wait channel"), or "0" if it is not
+//! sleeping. Sampling it at regular intervals is a cheap form of statistical
+//! profiling, which tells what a process spends its time blocking on.
+//!
+//! Storing one string per sample would be expensive, but a process only ever
+//! blocks in a small set of kernel functions. So the data store keeps a table
+//! of the symbols that were seen so far, and each sample is stored as an index
+//! into that table.
+//!
+//! Note that the kernel only reports a symbol name if the reader has enough
+//! privileges to look at the target process, and returns "0" otherwise.
+
+use ::columns::{Column, Columns};
+use ::data::{SampledData, SchemaChanged};
+use ::parser::PseudoFileParser;
+
+
+// Implement a sampler for /proc/<pid>/wchan
+define_sampler!{ Sampler : pid "wchan" => Parser => FieldStream => Data }
+
+
+/// Symbol which the kernel reports when the process is not sleeping
+pub const RUNNING: &str = "0";
+
+
+/// Incremental parser for /proc/<pid>/wchan
+#[derive(Debug, PartialEq)]
+pub struct Parser {}
+//
+impl PseudoFileParser for Parser {
+    /// Build a parser, using an initial file sample. There is no schema to
+    /// validate here, and the initial sample may legitimately be empty when
+    /// racing with the kernel, so the sample is ignored.
+    fn new(_initial_contents: &str) -> Self {
+        Self {}
+    }
+}
+//
+// TODO: Implement IncrementalParser once that trait is usable in stable Rust
+impl Parser {
+    /// Begin to parse a pseudo-file sample, streaming its data out
+    pub fn parse<'a>(&mut self, file_contents: &'a str) -> FieldStream<'a> {
+        FieldStream::new(file_contents)
+    }
+}
+///
+///
+/// Stream of parsed data from /proc/<pid>/wchan
+///
+/// This iterator should successively yield...
+///
+/// * The name of the kernel function in which the process sleeps, or "0" if
+///   it is not sleeping
+/// * A None terminator
+///
+pub struct FieldStream<'a> {
+    /// Symbol which remains to be yielded, if any
+    symbol: Option<&'a str>,
+}
+//
+impl<'a> Iterator for FieldStream<'a> {
+    /// We output kernel symbol names
+    type Item = &'a str;
+
+    /// Yield the symbol from /proc/<pid>/wchan
+    fn next(&mut self) -> Option<Self::Item> {
+        self.symbol.take()
+    }
+}
+//
+impl<'a> FieldStream<'a> {
+    /// Set up a FieldStream for a certain sample of /proc/<pid>/wchan
+    fn new(file_contents: &'a str) -> Self {
+        let symbol = file_contents.trim();
+        Self {
+            symbol: if symbol.is_empty() { None } else { Some(symbol) },
+        }
+    }
+}
+
+
+/// Data samples from /proc/<pid>/wchan, stored as indices into a symbol table
+#[derive(Debug, PartialEq)]
+pub struct Data {
+    /// Every symbol which was encountered so far, in order of appearance
+    symbols: Vec<String>,
+
+    /// Index of the sampled symbol in the symbol table, for each sample
+    indices: Vec<u32>,
+}
+//
+impl SampledData for Data {
+    /// Tell how many samples are present in the data store
+    fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    /// Drop the oldest samples from the data store
+    ///
+    /// The symbol table is left untouched, so that the indices of the
+    /// remaining samples stay valid.
+    ///
+    fn drop_oldest(&mut self, count: usize) {
+        self.indices.drain(..count);
+    }
+
+    /// Tell the length of the shortest time series in the data store
+    fn min_len(&self) -> usize {
+        self.indices.len()
+    }
+
+    /// Drop the newest samples from the data store
+    fn truncate(&mut self, length: usize) {
+        self.indices.truncate(length);
+    }
+}
+//
+// TODO: Implement SampledDataIncremental once that is usable in stable Rust
+impl Data {
+    /// Every symbol which was encountered so far, in order of appearance
+    pub fn symbols(&self) -> &[String] {
+        &self.symbols
+    }
+
+    /// Index of the sampled symbol in the symbol table, for each sample
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+
+    /// Symbol which was sampled at a certain index, which is "0" if the
+    /// process was not sleeping
+    pub fn symbol(&self, sample: usize) -> &str {
+        &self.symbols[self.indices[sample] as usize]
+    }
+
+    /// Kernel function in which the process was sleeping at a certain sample
+    /// index, or None if it was not sleeping
+    pub fn blocking_point(&self, sample: usize) -> Option<&str> {
+        match self.symbol(sample) {
+            RUNNING => None,
+            symbol => Some(symbol),
+        }
+    }
+
+    /// Number of samples in which each symbol was observed, from the most
+    /// frequent to the least frequent one
+    ///
+    /// Symbols which were only observed in samples that have since been
+    /// dropped are not reported. Ties are broken by order of appearance.
+    ///
+    pub fn frequencies(&self) -> Vec<(&str, usize)> {
+        let mut counts = vec![0; self.symbols.len()];
+        for &index in &self.indices {
+            counts[index as usize] += 1;
+        }
+        let mut frequencies: Vec<(&str, usize)> =
+            self.symbols.iter()
+                        .zip(counts)
+                        .filter(|&(_, count)| count > 0)
+                        .map(|(symbol, count)| (&symbol[..], count))
+                        .collect();
+        frequencies.sort_by(|a, b| b.1.cmp(&a.1));
+        frequencies
+    }
+
+    /// Kernel function in which the process was most often found sleeping,
+    /// along with the number of samples in which it was observed, or None if
+    /// the process was never observed sleeping
+    pub fn top_blocking_point(&self) -> Option<(&str, usize)> {
+        self.frequencies()
+            .into_iter()
+            .find(|&(symbol, _)| symbol != RUNNING)
+    }
+
+    /// Create a new wait channel data store
+    fn new(_stream: FieldStream) -> Self {
+        Self {
+            symbols: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    /// Push a new sample of /proc/<pid>/wchan into the store
+    ///
+    /// A process blocks in few distinct kernel functions, so a linear search
+    /// through the symbol table is cheaper than hashing the symbol.
+    ///
+    /// Empty readouts, which may occur when racing with the kernel, carry no
+    /// wait channel and are rejected without modifying the store.
+    ///
+    fn push(&mut self, mut stream: FieldStream) -> Result<(), SchemaChanged> {
+        let symbol = stream.next().ok_or_else(|| {
+            SchemaChanged::new("The wait channel is missing")
+        })?;
+        let index = match self.symbols.iter().position(|s| s == symbol) {
+            Some(index) => index,
+            None => {
+                self.symbols.push(symbol.to_owned());
+                self.symbols.len() - 1
+            }
+        };
+        self.indices.push(index as u32);
+        Ok(())
+    }
+}
+//
+/// The symbol table indices are exported as "wchan"
+impl Columns for Data {
+    fn columns(&self) -> Vec<(String, Column)> {
+        vec![("wchan".to_owned(),
+              Column::U64(self.indices.iter().map(|&i| i as u64).collect()))]
+    }
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::{Data, Parser, PseudoFileParser, SampledData, Sampler};
+
+    /// Check that parsing the wait channel works
+    #[test]
+    fn parse_data() {
+        let mut parser = Parser::new("0");
+        let mut stream = parser.parse("do_select");
+        assert_eq!(stream.next(), Some("do_select"));
+        assert_eq!(stream.next(), None);
+        assert_eq!(parser.parse("0\n").next(), Some("0"));
+    }
+
+    /// Check that symbols are interned and summarized properly
+    #[test]
+    fn sampled_data() {
+        let mut parser = Parser::new("0");
+        let mut data = Data::new(parser.parse("0"));
+        assert_eq!(data.len(), 0);
+        assert_eq!(data.top_blocking_point(), None);
+        for &symbol in &["0", "do_select", "futex_wait_queue_me", "0",
+                         "futex_wait_queue_me", "0", "futex_wait_queue_me"] {
+            data.push(parser.parse(symbol)).unwrap();
+        }
+        assert_eq!(data.len(), 7);
+        assert_eq!(data.symbols(),
+                   &["0", "do_select", "futex_wait_queue_me"]);
+        assert_eq!(data.indices(), &[0, 1, 2, 0, 2, 0, 2]);
+        assert_eq!(data.symbol(1), "do_select");
+        assert_eq!(data.blocking_point(0), None);
+        assert_eq!(data.blocking_point(2), Some("futex_wait_queue_me"));
+        assert_eq!(data.frequencies(),
+                   vec![("0", 3), ("futex_wait_queue_me", 3),
+                        ("do_select", 1)]);
+        assert_eq!(data.top_blocking_point(),
+                   Some(("futex_wait_queue_me", 3)));
+
+        // Dropping samples keeps the symbol table valid
+        data.drop_oldest(2);
+        assert_eq!(data.symbol(0), "futex_wait_queue_me");
+        assert_eq!(data.frequencies(),
+                   vec![("futex_wait_queue_me", 3), ("0", 2)]);
+
+        // Empty readouts are rejected without modifying the store
+        assert!(data.push(parser.parse("")).is_err());
+        assert_eq!(data.len(), 5);
+    }
+
+    /// Check that the sampler works well
+    define_sampler_tests!{ Sampler }
+
+    /// Check that the sampler handles process exits well
+    define_pid_sampler_tests!{ Sampler }
+}
+
+
+/// Performance benchmarks
+///
+/// See the lib-wide benchmarks module for details on how to use these.
+///
+#[cfg(test)]
+mod benchmarks {
+    define_sampler_benchs!{ super::Sampler,
+                            "/proc/self/wchan",
+                            1_000_000 }
+}
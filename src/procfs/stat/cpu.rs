@@ -1,9 +1,15 @@
 //! This module contains facilities for parsing and storing the data contained
 //! in the "cpu" sections of /proc/stat.
 
-use ::data::SampledData;
+#[cfg(feature = "binary")]
+use ::binary::{self, BinaryValue};
+use ::columns::{Column, Columns};
+use ::data::{argmax, SampledData, SchemaChanged};
 use ::splitter::SplitColumns;
 use libc;
+use std::borrow::{Borrow, Cow};
+#[cfg(feature = "binary")]
+use std::io::{self, Read, Write};
 use std::time::Duration;
 
 
@@ -33,28 +39,16 @@ use std::time::Duration;
 pub(super) struct RecordFields<'a, 'b> where 'a: 'b {
     /// Data columns of the record, interpreted as CPU timings
     data_columns: SplitColumns<'a, 'b>,
-
-    /// Number of clock ticks in one second (cached from TICKS_PER_SEC)
-    ticks_per_sec: u64,
-
-    /// Number of nanoseconds in one clock tick (cached from NANOSECS_PER_TICK)
-    nanosecs_per_tick: u64,
 }
 //
 impl<'a, 'b> Iterator for RecordFields<'a, 'b> {
-    /// We're outputting real time durations
-    type Item = Duration;
+    /// We're outputting raw CPU tick counts
+    type Item = u64;
 
     /// This is how we generate them from file columns
     fn next(&mut self) -> Option<Self::Item> {
-        self.data_columns.next().map(|str_duration| {
-            let ticks: u64 =
-                str_duration.parse()
-                            .expect("Failed to parse CPU tick counter");
-            let secs = ticks / self.ticks_per_sec;
-            let nanosecs =
-                (ticks % self.ticks_per_sec) * self.nanosecs_per_tick;
-            Duration::new(secs, nanosecs as u32)
+        self.data_columns.next().map(|str_ticks| {
+            str_ticks.parse().expect("Failed to parse CPU tick counter")
         })
     }
 }
@@ -64,11 +58,66 @@ impl<'a, 'b> RecordFields<'a, 'b> {
     pub fn new(data_columns: SplitColumns<'a, 'b>) -> Self {
         Self {
             data_columns,
-            ticks_per_sec: *TICKS_PER_SEC,
-            nanosecs_per_tick: *NANOSECS_PER_TICK,
         }
     }
 }
+
+
+/// Number of CPU ticks per second in the statistics of /proc/stat, as reported
+/// by sysconf(_SC_CLK_TCK) and used by ticks_to_duration() (usually 100)
+pub fn ticks_per_second() -> u64 {
+    *TICKS_PER_SEC
+}
+///
+/// Number of nanoseconds in one CPU tick, as used by ticks_to_duration()
+pub fn nanos_per_tick() -> u64 {
+    *NANOSECS_PER_TICK
+}
+///
+/// Convert an amount of CPU ticks from /proc/stat into a real time duration
+pub fn ticks_to_duration(ticks: u64) -> Duration {
+    let ticks_per_sec = *TICKS_PER_SEC;
+    let secs = ticks / ticks_per_sec;
+    let nanosecs = (ticks % ticks_per_sec) * *NANOSECS_PER_TICK;
+    Duration::new(secs, nanosecs as u32)
+}
+
+
+/// Aggregate statistics over a series of durations, such as those produced by
+/// the "_time" accessors of the CPU statistics below
+///
+/// Summing Durations directly can overflow (and panic) on very long captures,
+/// so these statistics are computed by accumulating integer nanoseconds in a
+/// 128-bit integer, which is exact and cannot overflow in practice.
+///
+pub trait DurationSeries {
+    /// Total of the durations in the series, in nanoseconds
+    fn sum_nanos(&self) -> u128;
+
+    /// Mean of the durations in the series, or None if the series is empty
+    fn mean_duration(&self) -> Option<Duration>;
+}
+//
+impl DurationSeries for [Duration] {
+    fn sum_nanos(&self) -> u128 {
+        self.iter()
+            .map(|d| d.as_secs() as u128 * NANOSECS_PER_SEC
+                     + d.subsec_nanos() as u128)
+            .sum()
+    }
+
+    fn mean_duration(&self) -> Option<Duration> {
+        if self.is_empty() {
+            return None;
+        }
+        let mean_nanos = self.sum_nanos() / self.len() as u128;
+        Some(Duration::new((mean_nanos / NANOSECS_PER_SEC) as u64,
+                           (mean_nanos % NANOSECS_PER_SEC) as u32))
+    }
+}
+//
+/// Number of nanoseconds in one second, as used for Duration accumulation
+const NANOSECS_PER_SEC: u128 = 1_000_000_000;
 //
 lazy_static! {
     /// Number of CPU ticks from the statistics of /proc/stat in one second
@@ -81,79 +130,565 @@ lazy_static! {
 }
 
 
+/// Breakdown of CPU time between two samples, in percent, following the
+/// conventions of mpstat
+///
+/// Each field is named after the mpstat column that it matches (e.g. "usr"
+/// for "%usr"). As in mpstat, guest time is subtracted from user and nice time
+/// so that it is not counted twice, and the percentages thus sum up to 100
+/// (up to floating-point rounding). Timers which the kernel does not provide
+/// are reported as 0%. Values are not rounded: format them with "{:.2}" to get
+/// the exact output of mpstat.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CpuPercentages {
+    /// Time spent in user mode, excluding guests ("%usr")
+    pub usr: f64,
+
+    /// Time spent in user mode with low priority, excluding guests ("%nice")
+    pub nice: f64,
+
+    /// Time spent in system mode, excluding interrupts ("%sys")
+    pub sys: f64,
+
+    /// Time spent idle with outstanding disk I/O ("%iowait")
+    pub iowait: f64,
+
+    /// Time spent servicing hardware interrupts ("%irq")
+    pub irq: f64,
+
+    /// Time spent servicing softirqs ("%soft")
+    pub soft: f64,
+
+    /// Time stolen by other OSs, when virtualized ("%steal")
+    pub steal: f64,
+
+    /// Time spent running a virtualized guest ("%guest")
+    pub guest: f64,
+
+    /// Time spent running a niced virtualized guest ("%gnice")
+    pub gnice: f64,
+
+    /// Time spent idle without outstanding disk I/O ("%idle")
+    pub idle: f64,
+}
+
+
+/// Number of CPU timers that we know about
+const MAX_TIMERS: usize = 10;
+
+
 /// The amount of CPU time that the system spent in various states
+///
+/// CPU time is stored as the raw amount of clock ticks reported by the kernel,
+/// and only converted to real time durations on demand, so that summing or
+/// subtracting many samples does not accumulate rounding errors.
+///
 #[derive(Clone, Debug, PartialEq)]
-pub(super) struct Data {
+pub struct Data {
     /// Time spent in user mode
-    user_time: Vec<Duration>,
+    user_ticks: Vec<u64>,
 
     /// Time spent in user mode with low priority (nice)
-    nice_time: Vec<Duration>,
+    nice_ticks: Vec<u64>,
 
     /// Time spent in system (aka kernel) mode
-    system_time: Vec<Duration>,
+    system_ticks: Vec<u64>,
 
     /// Time spent in the idle task (should match second entry in /proc/uptime)
-    idle_time: Vec<Duration>,
+    idle_ticks: Vec<u64>,
 
     /// Time spent waiting for IO to complete (since Linux 2.5.41)
     /// BEWARE: This measure is mostly meaningless on modern kernels
-    io_wait_time: Option<Vec<Duration>>,
+    io_wait_ticks: Option<Vec<u64>>,
 
     /// Time spent servicing hardware interrupts (since Linux 2.6.0-test4)
-    irq_time: Option<Vec<Duration>>,
+    irq_ticks: Option<Vec<u64>>,
 
     /// Time spent servicing software interrupts (since Linux 2.6.0-test4)
-    softirq_time: Option<Vec<Duration>>,
+    softirq_ticks: Option<Vec<u64>>,
 
     /// "Stolen" time spent in other operating systems when running in a
     /// virtualized environment (since Linux 2.6.11)
-    stolen_time: Option<Vec<Duration>>,
+    stolen_ticks: Option<Vec<u64>>,
 
     /// Time spent running a virtual CPU for guest OSs (since Linux 2.6.24)
-    guest_time: Option<Vec<Duration>>,
+    guest_ticks: Option<Vec<u64>>,
 
     /// Time spent running a niced guest (see above, since Linux 2.6.33)
-    guest_nice_time: Option<Vec<Duration>>,
+    guest_nice_ticks: Option<Vec<u64>>,
+
+    /// Indices of the samples before which the CPU timers were reset by a
+    /// system reboot (as detected via the /proc/stat btime)
+    reset_indices: Vec<usize>,
 }
 //
 impl SampledData for Data {
     /// Tell how many samples are present in the data store + check consistency
     fn len(&self) -> usize {
         // Check the mandatory CPU timers
-        let length = self.user_time.len();
-        debug_assert_eq!(length, self.nice_time.len());
-        debug_assert_eq!(length, self.system_time.len());
-        debug_assert_eq!(length, self.idle_time.len());
+        let length = self.user_ticks.len();
+        debug_assert_eq!(length, self.nice_ticks.len());
+        debug_assert_eq!(length, self.system_ticks.len());
+        debug_assert_eq!(length, self.idle_ticks.len());
 
         // Check the length of the optional CPU timers for consistency
-        let optional_len = |op: &Option<Vec<Duration>>| -> usize {
+        let optional_len = |op: &Option<Vec<u64>>| -> usize {
             op.as_ref().map_or(length, |vec| vec.len())
         };
-        debug_assert_eq!(length, optional_len(&self.io_wait_time));
-        debug_assert_eq!(length, optional_len(&self.irq_time));
-        debug_assert_eq!(length, optional_len(&self.softirq_time));
-        debug_assert_eq!(length, optional_len(&self.stolen_time));
-        debug_assert_eq!(length, optional_len(&self.guest_time));
-        debug_assert_eq!(length, optional_len(&self.guest_nice_time));
+        debug_assert_eq!(length, optional_len(&self.io_wait_ticks));
+        debug_assert_eq!(length, optional_len(&self.irq_ticks));
+        debug_assert_eq!(length, optional_len(&self.softirq_ticks));
+        debug_assert_eq!(length, optional_len(&self.stolen_ticks));
+        debug_assert_eq!(length, optional_len(&self.guest_ticks));
+        debug_assert_eq!(length, optional_len(&self.guest_nice_ticks));
 
         // Return the overall length
         length
     }
+
+    /// Drop the oldest samples from the data store
+    fn drop_oldest(&mut self, count: usize) {
+        // Drop the mandatory CPU timers
+        self.user_ticks.drain(..count);
+        self.nice_ticks.drain(..count);
+        self.system_ticks.drain(..count);
+        self.idle_ticks.drain(..count);
+
+        // Drop the optional CPU timers, if present
+        let optional_drop = |op: &mut Option<Vec<u64>>| {
+            if let Some(ref mut vec) = *op {
+                vec.drain(..count);
+            }
+        };
+        optional_drop(&mut self.io_wait_ticks);
+        optional_drop(&mut self.irq_ticks);
+        optional_drop(&mut self.softirq_ticks);
+        optional_drop(&mut self.stolen_ticks);
+        optional_drop(&mut self.guest_ticks);
+        optional_drop(&mut self.guest_nice_ticks);
+
+        // Shift the reset indices accordingly
+        self.reset_indices.retain(|&idx| idx >= count);
+        for idx in self.reset_indices.iter_mut() {
+            *idx -= count;
+        }
+    }
+
+    /// Tell the length of the shortest time series in the data store
+    fn min_len(&self) -> usize {
+        let optional_len = |op: &Option<Vec<u64>>| -> usize {
+            op.as_ref().map_or(usize::max_value(), |vec| vec.len())
+        };
+        [self.user_ticks.len(),
+         self.nice_ticks.len(),
+         self.system_ticks.len(),
+         self.idle_ticks.len(),
+         optional_len(&self.io_wait_ticks),
+         optional_len(&self.irq_ticks),
+         optional_len(&self.softirq_ticks),
+         optional_len(&self.stolen_ticks),
+         optional_len(&self.guest_ticks),
+         optional_len(&self.guest_nice_ticks)].iter()
+                                               .cloned()
+                                               .min()
+                                               .expect("Timers should exist")
+    }
+
+    /// Drop the newest samples from the data store
+    fn truncate(&mut self, length: usize) {
+        // Truncate the mandatory CPU timers
+        self.user_ticks.truncate(length);
+        self.nice_ticks.truncate(length);
+        self.system_ticks.truncate(length);
+        self.idle_ticks.truncate(length);
+
+        // Truncate the optional CPU timers, if present
+        let optional_truncate = |op: &mut Option<Vec<u64>>| {
+            if let Some(ref mut vec) = *op {
+                vec.truncate(length);
+            }
+        };
+        optional_truncate(&mut self.io_wait_ticks);
+        optional_truncate(&mut self.irq_ticks);
+        optional_truncate(&mut self.softirq_ticks);
+        optional_truncate(&mut self.stolen_ticks);
+        optional_truncate(&mut self.guest_ticks);
+        optional_truncate(&mut self.guest_nice_ticks);
+        self.reset_indices.retain(|&idx| idx < length);
+    }
 }
 //
 // TODO: Implement SampledData2 once that is usable in stable Rust
 impl Data {
+    /// Clock ticks spent in user mode
+    pub fn user_ticks(&self) -> &[u64] { &self.user_ticks }
+
+    /// Time spent in user mode
+    pub fn user_time(&self) -> Vec<Duration> {
+        Self::durations(&self.user_ticks)
+    }
+
+    /// Clock ticks spent in user mode with low priority (nice)
+    pub fn nice_ticks(&self) -> &[u64] { &self.nice_ticks }
+
+    /// Time spent in user mode with low priority (nice)
+    pub fn nice_time(&self) -> Vec<Duration> {
+        Self::durations(&self.nice_ticks)
+    }
+
+    /// Clock ticks spent in system (aka kernel) mode
+    pub fn system_ticks(&self) -> &[u64] { &self.system_ticks }
+
+    /// Time spent in system (aka kernel) mode
+    pub fn system_time(&self) -> Vec<Duration> {
+        Self::durations(&self.system_ticks)
+    }
+
+    /// Clock ticks spent in the idle task
+    pub fn idle_ticks(&self) -> &[u64] { &self.idle_ticks }
+
+    /// Time spent in the idle task
+    pub fn idle_time(&self) -> Vec<Duration> {
+        Self::durations(&self.idle_ticks)
+    }
+
+    /// Clock ticks spent waiting for IO to complete (since Linux 2.5.41)
+    pub fn io_wait_ticks(&self) -> Option<&[u64]> {
+        self.io_wait_ticks.as_ref().map(|vec| &vec[..])
+    }
+
+    /// Time spent waiting for IO to complete (since Linux 2.5.41)
+    pub fn io_wait_time(&self) -> Option<Vec<Duration>> {
+        self.io_wait_ticks.as_ref().map(|vec| Self::durations(vec))
+    }
+
+    /// Clock ticks spent servicing hardware IRQs (since Linux 2.6.0-test4)
+    pub fn irq_ticks(&self) -> Option<&[u64]> {
+        self.irq_ticks.as_ref().map(|vec| &vec[..])
+    }
+
+    /// Time spent servicing hardware interrupts (since Linux 2.6.0-test4)
+    pub fn irq_time(&self) -> Option<Vec<Duration>> {
+        self.irq_ticks.as_ref().map(|vec| Self::durations(vec))
+    }
+
+    /// Clock ticks spent servicing software IRQs (since Linux 2.6.0-test4)
+    pub fn softirq_ticks(&self) -> Option<&[u64]> {
+        self.softirq_ticks.as_ref().map(|vec| &vec[..])
+    }
+
+    /// Time spent servicing software interrupts (since Linux 2.6.0-test4)
+    pub fn softirq_time(&self) -> Option<Vec<Duration>> {
+        self.softirq_ticks.as_ref().map(|vec| Self::durations(vec))
+    }
+
+    /// Clock ticks stolen by other operating systems (since Linux 2.6.11)
+    pub fn stolen_ticks(&self) -> Option<&[u64]> {
+        self.stolen_ticks.as_ref().map(|vec| &vec[..])
+    }
+
+    /// Time stolen by other operating systems (since Linux 2.6.11)
+    pub fn stolen_time(&self) -> Option<Vec<Duration>> {
+        self.stolen_ticks.as_ref().map(|vec| Self::durations(vec))
+    }
+
+    /// Clock ticks spent running virtual CPUs for guests (since Linux 2.6.24)
+    pub fn guest_ticks(&self) -> Option<&[u64]> {
+        self.guest_ticks.as_ref().map(|vec| &vec[..])
+    }
+
+    /// Time spent running a virtual CPU for guest OSs (since Linux 2.6.24)
+    pub fn guest_time(&self) -> Option<Vec<Duration>> {
+        self.guest_ticks.as_ref().map(|vec| Self::durations(vec))
+    }
+
+    /// Clock ticks spent running a niced guest (since Linux 2.6.33)
+    pub fn guest_nice_ticks(&self) -> Option<&[u64]> {
+        self.guest_nice_ticks.as_ref().map(|vec| &vec[..])
+    }
+
+    /// Time spent running a niced guest (since Linux 2.6.33)
+    pub fn guest_nice_time(&self) -> Option<Vec<Duration>> {
+        self.guest_nice_ticks.as_ref().map(|vec| Self::durations(vec))
+    }
+
+    /// Number of CPU timers which are provided by the kernel (between 4 and 10)
+    pub fn timer_count(&self) -> u8 {
+        let optional_timers = [&self.io_wait_ticks,
+                               &self.irq_ticks,
+                               &self.softirq_ticks,
+                               &self.stolen_ticks,
+                               &self.guest_ticks,
+                               &self.guest_nice_ticks];
+        4 + optional_timers.iter().filter(|t| t.is_some()).count() as u8
+    }
+
+    /// Fraction of CPU time which was spent doing work between two samples,
+    /// or None if no CPU time was accounted between these samples
+    ///
+    /// Idle and IO wait time are considered as not doing work. Guest time is
+    /// left out of the total, as the kernel also accounts it as user time.
+    /// If the system rebooted between these samples, only the CPU time spent
+    /// since the last reboot is taken into account.
+    ///
+    pub fn busy_fraction(&self, older: usize, newer: usize) -> Option<f64> {
+        let (work_ticks, idle_ticks) = self.work_and_idle_ticks(older, newer);
+        Self::work_fraction(work_ticks, idle_ticks)
+    }
+
+    /// Clock ticks spent doing work over the whole capture, i.e. between the
+    /// first and the last sample, following the conventions of busy_fraction
+    ///
+    /// Unlike the per-interval helpers, this is computed in exact integer
+    /// arithmetic, so that long-running captures do not suffer from floating
+    /// point rounding accumulation. Sampling intervals during which the system
+    /// rebooted are left out, as the CPU time spent in them is unknown.
+    ///
+    pub fn busy_ticks_total(&self) -> u128 {
+        self.capture_work_and_idle_ticks().0
+    }
+
+    /// Clock ticks spent idle or waiting for IO over the whole capture, i.e.
+    /// between the first and the last sample (see busy_ticks_total)
+    pub fn idle_ticks_total(&self) -> u128 {
+        self.capture_work_and_idle_ticks().1
+    }
+
+    /// Fraction of CPU time which was spent doing work over the whole capture,
+    /// or None if no CPU time was accounted (see busy_ticks_total)
+    ///
+    /// Tick counts are summed up exactly, and only converted to floating point
+    /// for the final division, so this is more precise than averaging the
+    /// results of busy_fraction over every sampling interval.
+    ///
+    pub fn overall_busy_fraction(&self) -> Option<f64> {
+        let (work_ticks, idle_ticks) = self.capture_work_and_idle_ticks();
+        Self::work_fraction(work_ticks, idle_ticks)
+    }
+
+    /// INTERNAL: Clock ticks spent doing work and idle between two samples, as
+    ///           defined by busy_fraction
+    pub(super) fn work_and_idle_ticks(&self,
+                                      older: usize,
+                                      newer: usize) -> (u128, u128) {
+        // This is how we compute the CPU ticks elapsed between the samples.
+        // CPU timers start from zero at boot, and IO wait time may go
+        // backwards, which we treat as no time having elapsed.
+        let rebooted = self.rebooted_between(older, newer);
+        let delta = |vec: &[u64]| -> u128 {
+            if rebooted {
+                vec[newer] as u128
+            } else {
+                vec[newer].saturating_sub(vec[older]) as u128
+            }
+        };
+        let optional_delta = |op: &Option<Vec<u64>>| -> u128 {
+            op.as_ref().map_or(0, |vec| delta(vec))
+        };
+
+        // Sum up the work and idle ticks
+        let work_ticks = delta(&self.user_ticks) + delta(&self.nice_ticks)
+                         + delta(&self.system_ticks)
+                         + optional_delta(&self.irq_ticks)
+                         + optional_delta(&self.softirq_ticks)
+                         + optional_delta(&self.stolen_ticks);
+        let idle_ticks = delta(&self.idle_ticks)
+                         + optional_delta(&self.io_wait_ticks);
+        (work_ticks, idle_ticks)
+    }
+
+    /// INTERNAL: Clock ticks spent doing work and idle over the whole capture
+    fn capture_work_and_idle_ticks(&self) -> (u128, u128) {
+        self.steady_intervals()
+            .map(|newer| self.work_and_idle_ticks(newer-1, newer))
+            .fold((0, 0), |(work, idle), (dwork, didle)| {
+                (work + dwork, idle + didle)
+            })
+    }
+
+    /// INTERNAL: Clock ticks accounted by one of our CPU timers over the whole
+    ///           capture, following the conventions of busy_ticks_total
+    pub(super) fn capture_ticks(&self, timer: &[u64]) -> u128 {
+        self.steady_intervals()
+            .map(|newer| timer[newer].saturating_sub(timer[newer-1]) as u128)
+            .sum()
+    }
+
+    /// INTERNAL: Indices of the samples which end a sampling interval during
+    ///           which the system did not reboot
+    fn steady_intervals<'a>(&'a self) -> impl Iterator<Item=usize> + 'a {
+        (1..self.len()).filter(move |newer| {
+            !self.reset_indices.contains(newer)
+        })
+    }
+
+    /// INTERNAL: Truth that the system rebooted between two samples
+    fn rebooted_between(&self, older: usize, newer: usize) -> bool {
+        debug_assert!(older <= newer, "Samples should be ordered by age");
+        self.reset_indices.iter().any(|&idx| idx > older && idx <= newer)
+    }
+
+    /// INTERNAL: Deduce the fraction of time spent doing work from tick counts
+    fn work_fraction(work_ticks: u128, idle_ticks: u128) -> Option<f64> {
+        let total_ticks = work_ticks + idle_ticks;
+        if total_ticks > 0 {
+            Some(work_ticks as f64 / total_ticks as f64)
+        } else {
+            None
+        }
+    }
+
+    /// Fraction of CPU time which was spent servicing hardware interrupts and
+    /// softirqs between two samples
+    ///
+    /// A high fraction indicates that the CPU is bottlenecked on interrupt
+    /// handling, which commonly happens under heavy network load. If the kernel
+    /// only provides one of the irq and softirq timers, only that one is taken
+    /// into account. Returns None if neither is provided, if no CPU time was
+    /// accounted between these samples, or if the system rebooted in between.
+    ///
+    pub fn interrupt_servicing_fraction_between(&self,
+                                                older: usize,
+                                                newer: usize) -> Option<f64> {
+        // CPU timers are reset by reboots, so deltas cannot span them
+        if self.rebooted_between(older, newer) {
+            return None;
+        }
+
+        // Sum up the interrupt servicing ticks, if they are provided at all
+        let delta = |vec: &Vec<u64>| vec[newer].saturating_sub(vec[older]);
+        let irq_ticks = match (&self.irq_ticks, &self.softirq_ticks) {
+            (&None, &None) => return None,
+            (irq, softirq) => {
+                irq.as_ref().map_or(0, &delta)
+                    + softirq.as_ref().map_or(0, &delta)
+            },
+        };
+
+        // Divide by the total CPU time elapsed between the samples
+        let total_ticks = self.corrected_total_ticks_between(older, newer);
+        if total_ticks > 0 {
+            Some(irq_ticks as f64 / total_ticks as f64)
+        } else {
+            None
+        }
+    }
+
+    /// Breakdown of the CPU time elapsed between two samples, in percent, as
+    /// displayed by mpstat and top (see CpuPercentages)
+    ///
+    /// If no CPU time was accounted between these samples, which happens on
+    /// tickless idle CPUs, the CPU is reported as 100% idle like mpstat does.
+    /// Returns None if the system rebooted between these samples, as CPU
+    /// timers are reset by reboots.
+    ///
+    pub fn percentages_between(&self,
+                               older: usize,
+                               newer: usize) -> Option<CpuPercentages> {
+        // CPU timers are reset by reboots, so deltas cannot span them
+        if self.rebooted_between(older, newer) {
+            return None;
+        }
+
+        // This is how we compute the CPU ticks elapsed between the samples.
+        // Timers which went backwards, as IO wait time may, count as zero.
+        let delta = |vec: &[u64]| vec[newer].saturating_sub(vec[older]);
+        let optional_delta = |op: &Option<Vec<u64>>| -> u64 {
+            op.as_ref().map_or(0, |vec| delta(vec))
+        };
+
+        // The total does not include guest time, which is also accounted as
+        // user and nice time, so we must subtract it from these timers
+        let total_ticks = self.corrected_total_ticks_between(older, newer);
+        if total_ticks == 0 {
+            return Some(CpuPercentages {
+                usr: 0., nice: 0., sys: 0., iowait: 0., irq: 0., soft: 0.,
+                steal: 0., guest: 0., gnice: 0., idle: 100.,
+            });
+        }
+        let guest_ticks = optional_delta(&self.guest_ticks);
+        let guest_nice_ticks = optional_delta(&self.guest_nice_ticks);
+        let percent = |ticks: u64| 100. * ticks as f64 / total_ticks as f64;
+        Some(CpuPercentages {
+            usr: percent(delta(&self.user_ticks).saturating_sub(guest_ticks)),
+            nice: percent(delta(&self.nice_ticks)
+                              .saturating_sub(guest_nice_ticks)),
+            sys: percent(delta(&self.system_ticks)),
+            iowait: percent(optional_delta(&self.io_wait_ticks)),
+            irq: percent(optional_delta(&self.irq_ticks)),
+            soft: percent(optional_delta(&self.softirq_ticks)),
+            steal: percent(optional_delta(&self.stolen_ticks)),
+            guest: percent(guest_ticks),
+            gnice: percent(guest_nice_ticks),
+            idle: percent(delta(&self.idle_ticks)),
+        })
+    }
+
+    /// Index of the sample which ends the busiest sampling interval, i.e. the
+    /// sample "i" for which busy_fraction(i-1, i) is highest
+    ///
+    /// Returns None if there are fewer than two samples, or if no CPU time was
+    /// accounted in any sampling interval.
+    ///
+    pub fn argmax_busy(&self) -> Option<usize> {
+        argmax((1..self.len()).filter_map(|newer| {
+            self.busy_fraction(newer-1, newer).map(|busy| (newer, busy))
+        }))
+    }
+
+    /// Total CPU time elapsed between two samples, correcting for the double
+    /// accounting of virtualized guests
+    ///
+    /// Linux accounts the time spent running a guest OS both as guest time and
+    /// as user time, and the time spent running a niced guest both as
+    /// guest_nice time and as nice time. Naively summing all CPU timers thus
+    /// overestimates the total CPU time on virtualization hosts, which is a
+    /// frequent cause of "CPU utilization exceeds 100%" bugs.
+    ///
+    /// This method sums the deltas of all CPU timers between samples "older"
+    /// and "newer", minus the guest and guest_nice deltas. On older kernels
+    /// which do not report guest timers, all timers are summed normally.
+    ///
+    pub fn corrected_total_between(&self,
+                                   older: usize,
+                                   newer: usize) -> Duration {
+        ticks_to_duration(self.corrected_total_ticks_between(older, newer))
+    }
+
+    /// Total CPU ticks elapsed between two samples, correcting for the double
+    /// accounting of virtualized guests (see corrected_total_between())
+    pub fn corrected_total_ticks_between(&self,
+                                         older: usize,
+                                         newer: usize) -> u64 {
+        // This is how we compute the CPU ticks elapsed between the samples.
+        // Timers which went backwards, as IO wait time may, count as zero.
+        let delta = |vec: &[u64]| vec[newer].saturating_sub(vec[older]);
+        let optional_delta = |op: &Option<Vec<u64>>| -> u64 {
+            op.as_ref().map_or(0, |vec| delta(vec))
+        };
+
+        // Sum up all the CPU timers, except for guest timers which are already
+        // accounted for in the user and nice timers
+        delta(&self.user_ticks) + delta(&self.nice_ticks)
+            + delta(&self.system_ticks)
+            + delta(&self.idle_ticks)
+            + optional_delta(&self.io_wait_ticks)
+            + optional_delta(&self.irq_ticks)
+            + optional_delta(&self.softirq_ticks)
+            + optional_delta(&self.stolen_ticks)
+    }
+
     /// Create new CPU statistics
-    pub fn new(fields: RecordFields) -> Self {
+    pub(super) fn new(fields: RecordFields) -> Self {
         // Check if we know about all CPU timers
         let num_timers = fields.count();
         assert!(num_timers >= 4, "Some expected CPU timers are missing");
-        debug_assert!(num_timers <= 10, "Unknown CPU timers detected");
+        debug_assert!(num_timers <= MAX_TIMERS, "Unknown CPU timers detected");
 
         // Prepare to conditionally create a certain amount of timing Vecs
         let mut created_vecs = 4;
-        let mut conditional_vec = || -> Option<Vec<Duration>> {
+        let mut conditional_vec = || -> Option<Vec<u64>> {
             created_vecs += 1;
             if created_vecs <= num_timers {
                 Some(Vec::new())
@@ -165,48 +700,588 @@ impl Data {
         // Create the statistics
         Self {
             // These CPU timers should always be there
-            user_time: Vec::new(),
-            nice_time: Vec::new(),
-            system_time: Vec::new(),
-            idle_time: Vec::new(),
+            user_ticks: Vec::new(),
+            nice_ticks: Vec::new(),
+            system_ticks: Vec::new(),
+            idle_ticks: Vec::new(),
 
             // These may or may not be there depending on kernel version
-            io_wait_time: conditional_vec(),
-            irq_time: conditional_vec(),
-            softirq_time: conditional_vec(),
-            stolen_time: conditional_vec(),
-            guest_time: conditional_vec(),
-            guest_nice_time: conditional_vec(),
+            io_wait_ticks: conditional_vec(),
+            irq_ticks: conditional_vec(),
+            softirq_ticks: conditional_vec(),
+            stolen_ticks: conditional_vec(),
+            guest_ticks: conditional_vec(),
+            guest_nice_ticks: conditional_vec(),
+
+            // No reboot was observed yet
+            reset_indices: Vec::new(),
         }
     }
 
+    /// Record that the CPU timers were reset by a system reboot before the
+    /// sample with a certain index was taken
+    pub(super) fn mark_reset(&mut self, sample_idx: usize) {
+        self.reset_indices.push(sample_idx);
+    }
+
+    /// Sum the statistics of several CPU threads, sample by sample, into
+    /// synthetic aggregate statistics (or None if there are no threads)
+    ///
+    /// Optional CPU timers are only summed if every thread provides them.
+    ///
+    pub(super) fn sum<T: Borrow<Data>>(threads: &[T]) -> Option<Self> {
+        // All threads should have the same amount of samples
+        let first: &Data = threads.first()?.borrow();
+        let length = first.user_ticks.len();
+        debug_assert!(threads.iter().all(|t| {
+            let t: &Data = t.borrow();
+            t.user_ticks.len() == length
+        }));
+
+        // This is how we sum one CPU timer across all threads, if possible
+        let sum_timer = |timer: &dyn Fn(&Data) -> Option<&Vec<u64>>| {
+            let mut total = vec![0; length];
+            for thread in threads {
+                let thread: &Data = thread.borrow();
+                for (acc, &ticks) in total.iter_mut().zip(timer(thread)?) {
+                    *acc += ticks;
+                }
+            }
+            Some(total)
+        };
+        let sum_mandatory = |timer: &dyn Fn(&Data) -> &Vec<u64>| {
+            sum_timer(&|thread| Some(timer(thread)))
+                     .expect("Mandatory timers should always be present")
+        };
+
+        // Sum all CPU timers, which are reset whenever one thread's are
+        let mut reset_indices = threads.iter()
+                                       .flat_map(|thread| {
+                                           let thread: &Data = thread.borrow();
+                                           thread.reset_indices.iter().cloned()
+                                       })
+                                       .collect::<Vec<_>>();
+        reset_indices.sort();
+        reset_indices.dedup();
+        Some(
+            Self {
+                user_ticks: sum_mandatory(&|t| &t.user_ticks),
+                nice_ticks: sum_mandatory(&|t| &t.nice_ticks),
+                system_ticks: sum_mandatory(&|t| &t.system_ticks),
+                idle_ticks: sum_mandatory(&|t| &t.idle_ticks),
+                io_wait_ticks: sum_timer(&|t| t.io_wait_ticks.as_ref()),
+                irq_ticks: sum_timer(&|t| t.irq_ticks.as_ref()),
+                softirq_ticks: sum_timer(&|t| t.softirq_ticks.as_ref()),
+                stolen_ticks: sum_timer(&|t| t.stolen_ticks.as_ref()),
+                guest_ticks: sum_timer(&|t| t.guest_ticks.as_ref()),
+                guest_nice_ticks: sum_timer(&|t| t.guest_nice_ticks.as_ref()),
+                reset_indices,
+            }
+        )
+    }
+
+    /// Reserve storage for some additional samples
+    pub(super) fn reserve(&mut self, additional: usize) {
+        self.user_ticks.reserve(additional);
+        self.nice_ticks.reserve(additional);
+        self.system_ticks.reserve(additional);
+        self.idle_ticks.reserve(additional);
+        let optional_reserve = |op: &mut Option<Vec<u64>>| {
+            if let Some(ref mut vec) = *op {
+                vec.reserve(additional);
+            }
+        };
+        optional_reserve(&mut self.io_wait_ticks);
+        optional_reserve(&mut self.irq_ticks);
+        optional_reserve(&mut self.softirq_ticks);
+        optional_reserve(&mut self.stolen_ticks);
+        optional_reserve(&mut self.guest_ticks);
+        optional_reserve(&mut self.guest_nice_ticks);
+    }
+
     /// Parse CPU statistics and add them to the internal data store
-    pub fn push(&mut self, mut fields: RecordFields) {
+    ///
+    /// If the record does not feature as many CPU timers as the data store
+    /// was initialized for, e.g. due to a kernel update, nothing is stored and
+    /// a schema change is reported.
+    ///
+    pub(super) fn push(&mut self,
+                       fields: RecordFields) -> Result<(), SchemaChanged> {
+        // Read the CPU timers into a buffer, and check that they are all there
+        let mut timers = [0; MAX_TIMERS];
+        let mut num_timers = 0;
+        for ticks in fields {
+            if num_timers < MAX_TIMERS {
+                timers[num_timers] = ticks;
+            }
+            num_timers += 1;
+        }
+        if num_timers != self.timer_count() as usize {
+            return Err(SchemaChanged::new("The number of CPU timers changed"));
+        }
+        let mut timers = timers.iter().cloned();
+
         // This scope is needed to please rustc's current borrow checker
         {
             // Load the "mandatory" CPU statistics
-            self.user_time.push(fields.next().expect("User time missing"));
-            self.nice_time.push(fields.next().expect("Nice time missing"));
-            self.system_time.push(fields.next().expect("System time missing"));
-            self.idle_time.push(fields.next().expect("Idle time missing"));
+            let mut next_timer = || timers.next().expect("Checked above");
+            self.user_ticks.push(next_timer());
+            self.nice_ticks.push(next_timer());
+            self.system_ticks.push(next_timer());
+            self.idle_ticks.push(next_timer());
 
             // Load the "optional" CPU statistics
-            let mut optional_load = |stat: &mut Option<Vec<Duration>>| {
+            let mut optional_load = |stat: &mut Option<Vec<u64>>| {
                 if let Some(ref mut vec) = *stat {
-                    vec.push(fields.next().expect("A CPU timer went missing"));
+                    vec.push(next_timer());
                 }
             };
-            optional_load(&mut self.io_wait_time);
-            optional_load(&mut self.irq_time);
-            optional_load(&mut self.softirq_time);
-            optional_load(&mut self.stolen_time);
-            optional_load(&mut self.guest_time);
-            optional_load(&mut self.guest_nice_time);
+            optional_load(&mut self.io_wait_ticks);
+            optional_load(&mut self.irq_ticks);
+            optional_load(&mut self.softirq_ticks);
+            optional_load(&mut self.stolen_ticks);
+            optional_load(&mut self.guest_ticks);
+            optional_load(&mut self.guest_nice_ticks);
         }
+        Ok(())
+    }
 
-        // At this point, we should have loaded all available stats
-        debug_assert!(fields.next().is_none(),
-                      "A CPU timer appeared out of nowhere");
+    /// INTERNAL: Convert a series of CPU tick counts into real time durations
+    fn durations(ticks: &[u64]) -> Vec<Duration> {
+        ticks.iter().map(|&t| ticks_to_duration(t)).collect()
+    }
+
+    /// INTERNAL: Every CPU timer which is provided by the kernel, in /proc/stat
+    ///           order
+    fn timers(&self) -> Vec<&Vec<u64>> {
+        let optional_timers = [&self.io_wait_ticks,
+                               &self.irq_ticks,
+                               &self.softirq_ticks,
+                               &self.stolen_ticks,
+                               &self.guest_ticks,
+                               &self.guest_nice_ticks];
+        let mut timers = vec![&self.user_ticks,
+                              &self.nice_ticks,
+                              &self.system_ticks,
+                              &self.idle_ticks];
+        timers.extend(optional_timers.iter().filter_map(|t| t.as_ref()));
+        timers
+    }
+
+    /// INTERNAL: Mutable access to every CPU timer which is provided by the
+    ///           kernel, in /proc/stat order
+    fn timers_mut(&mut self) -> Vec<&mut Vec<u64>> {
+        let Data { ref mut user_ticks,
+                   ref mut nice_ticks,
+                   ref mut system_ticks,
+                   ref mut idle_ticks,
+                   ref mut io_wait_ticks,
+                   ref mut irq_ticks,
+                   ref mut softirq_ticks,
+                   ref mut stolen_ticks,
+                   ref mut guest_ticks,
+                   ref mut guest_nice_ticks,
+                   reset_indices: _ } = *self;
+        let optional_timers = vec![io_wait_ticks,
+                                   irq_ticks,
+                                   softirq_ticks,
+                                   stolen_ticks,
+                                   guest_ticks,
+                                   guest_nice_ticks];
+        let mut timers = vec![user_ticks, nice_ticks, system_ticks, idle_ticks];
+        timers.extend(optional_timers.into_iter().filter_map(|t| t.as_mut()));
+        timers
+    }
+
+    /// INTERNAL: Truth that all CPU timers have the same value in two samples
+    fn samples_equal(&self, sample1: usize, sample2: usize) -> bool {
+        self.timers().iter().all(|timer| timer[sample1] == timer[sample2])
+    }
+
+    /// INTERNAL: Repeat the oldest sample so that it appears a certain amount
+    ///           of times, before the newer samples
+    fn repeat_oldest(&mut self, count: usize) {
+        for timer in self.timers_mut() {
+            let newer = timer.split_off(1);
+            let oldest = timer[0];
+            timer.resize(count, oldest);
+            timer.extend(newer);
+        }
+    }
+}
+//
+/// CPU timers are exported as durations, named after the proc(5) man page
+impl Columns for Data {
+    fn columns(&self) -> Vec<(String, Column)> {
+        let mandatory = [("user", &self.user_ticks),
+                         ("nice", &self.nice_ticks),
+                         ("system", &self.system_ticks),
+                         ("idle", &self.idle_ticks)];
+        let optional = [("iowait", &self.io_wait_ticks),
+                        ("irq", &self.irq_ticks),
+                        ("softirq", &self.softirq_ticks),
+                        ("steal", &self.stolen_ticks),
+                        ("guest", &self.guest_ticks),
+                        ("guest_nice", &self.guest_nice_ticks)];
+        mandatory.iter()
+                 .map(|&(name, ticks)| (name, Some(ticks)))
+                 .chain(optional.iter()
+                                .map(|&(name, ticks)| (name, ticks.as_ref())))
+                 .filter_map(|(name, ticks)| ticks.map(|ticks| {
+                     (name.to_owned(),
+                      Column::durations(&Self::durations(ticks)))
+                 }))
+                 .collect()
+    }
+}
+//
+/// Binary serialization of CPU statistics: the number of CPU timers provided
+/// by the kernel, followed by the time series of each timer in /proc/stat order
+/// and by the timer reset indices
+#[cfg(feature = "binary")]
+impl BinaryValue for Data {
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.timer_count().write_to(writer)?;
+        for timer in [&self.user_ticks,
+                      &self.nice_ticks,
+                      &self.system_ticks,
+                      &self.idle_ticks].iter() {
+            timer.write_to(writer)?;
+        }
+        for timer in [&self.io_wait_ticks,
+                      &self.irq_ticks,
+                      &self.softirq_ticks,
+                      &self.stolen_ticks,
+                      &self.guest_ticks,
+                      &self.guest_nice_ticks].iter() {
+            binary::write_optional(timer, writer)?;
+        }
+        self.reset_indices.write_to(writer)
+    }
+
+    fn read_from<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let num_timers = u8::read_from(reader)?;
+        if num_timers < 4 || num_timers > 10 {
+            return Err(binary::invalid_data("Invalid CPU timer count"));
+        }
+        let mut optional_timers = 4;
+        let mut read_optional = |reader: &mut R| {
+            optional_timers += 1;
+            binary::read_optional(optional_timers <= num_timers, reader)
+        };
+        Ok(
+            Self {
+                user_ticks: Vec::read_from(reader)?,
+                nice_ticks: Vec::read_from(reader)?,
+                system_ticks: Vec::read_from(reader)?,
+                idle_ticks: Vec::read_from(reader)?,
+                io_wait_ticks: read_optional(reader)?,
+                irq_ticks: read_optional(reader)?,
+                softirq_ticks: read_optional(reader)?,
+                stolen_ticks: read_optional(reader)?,
+                guest_ticks: read_optional(reader)?,
+                guest_nice_ticks: read_optional(reader)?,
+                reset_indices: Vec::read_from(reader)?,
+            }
+        )
+    }
+}
+//
+/// Builders for expected data stores, which keep unit tests concise
+#[cfg(test)]
+impl Data {
+    /// Create an empty container for the oldest CPU stats format
+    fn empty() -> Self {
+        Self {
+            user_ticks: Vec::new(),
+            nice_ticks: Vec::new(),
+            system_ticks: Vec::new(),
+            idle_ticks: Vec::new(),
+            io_wait_ticks: None,
+            irq_ticks: None,
+            softirq_ticks: None,
+            stolen_ticks: None,
+            guest_ticks: None,
+            guest_nice_ticks: None,
+            reset_indices: Vec::new(),
+        }
+    }
+
+    /// Expect some more optional CPU timers, in /proc/stat order
+    fn with_optional_timers(mut self, count: usize) -> Self {
+        {
+            let mut optional_timers = [&mut self.io_wait_ticks,
+                                       &mut self.irq_ticks,
+                                       &mut self.softirq_ticks,
+                                       &mut self.stolen_ticks,
+                                       &mut self.guest_ticks,
+                                       &mut self.guest_nice_ticks];
+            let mut missing_timers = optional_timers.iter_mut()
+                                                    .filter(|t| t.is_none());
+            for _ in 0..count {
+                let timer = missing_timers.next()
+                                          .expect("Too many CPU timers");
+                **timer = Some(Vec::new());
+            }
+        }
+        self
+    }
+
+    /// Add a sample of raw CPU tick counts, in /proc/stat order
+    fn with_sample(mut self, ticks: &[u64]) -> Self {
+        {
+            let mut ticks_iter = ticks.iter().cloned();
+            let mut next_ticks = || ticks_iter.next().expect("Missing ticks");
+            self.user_ticks.push(next_ticks());
+            self.nice_ticks.push(next_ticks());
+            self.system_ticks.push(next_ticks());
+            self.idle_ticks.push(next_ticks());
+            for timer in [&mut self.io_wait_ticks,
+                          &mut self.irq_ticks,
+                          &mut self.softirq_ticks,
+                          &mut self.stolen_ticks,
+                          &mut self.guest_ticks,
+                          &mut self.guest_nice_ticks].iter_mut() {
+                if let Some(ref mut vec) = **timer {
+                    vec.push(next_ticks());
+                }
+            }
+        }
+        self
+    }
+}
+
+
+/// CPU statistics of one hardware CPU thread, stored sparsely
+///
+/// On hosts with hundreds of CPU threads, many of them may be parked (e.g.
+/// taken offline) during a measurement, in which case their CPU timers do not
+/// change. Storing the same timer values over and over again wastes RAM, so we
+/// take a shortcut for this use case, in the spirit of the zero optimization
+/// of interrupt counters.
+///
+/// Per-sample and per-interval queries can be answered directly from the
+/// sparse storage, via stored_samples() and stored_index() or via the
+/// analysis helpers below. Use dense() if you need the full time series.
+///
+#[derive(Clone, Debug)]
+pub struct SampledThread {
+    /// Stored samples of the CPU timers. If the CPU timers never changed so
+    /// far, only the first sample is kept (or none if nothing was sampled).
+    data: Data,
+
+    /// If the CPU timers never changed so far, number of samples which were
+    /// taken. Otherwise, every sample is stored and this is None.
+    constant_count: Option<usize>,
+}
+//
+impl SampledThread {
+    /// Access the CPU thread statistics as full time series
+    ///
+    /// CPU threads whose timers never changed are expanded into full time
+    /// series at this point, which takes time and memory proportional to the
+    /// number of samples. Prefer stored_samples() and stored_index() if you
+    /// only need a few samples.
+    ///
+    pub fn dense<'a>(&'a self) -> Cow<'a, Data> {
+        match self.constant_count {
+            Some(count) if count > 1 => {
+                let mut data = self.data.clone();
+                data.repeat_oldest(count);
+                Cow::Owned(data)
+            },
+            _ => Cow::Borrowed(&self.data),
+        }
+    }
+
+    /// Samples of the CPU timers which are actually stored (see stored_index)
+    pub fn stored_samples(&self) -> &Data {
+        &self.data
+    }
+
+    /// Index of a sample within stored_samples()
+    ///
+    /// If the CPU timers never changed, only their first sample is stored, so
+    /// every sample maps to it. Otherwise, this is the identity.
+    ///
+    pub fn stored_index(&self, sample: usize) -> usize {
+        debug_assert!(sample < self.len(), "No such sample");
+        match self.constant_count {
+            Some(_) => 0,
+            None => sample,
+        }
+    }
+
+    /// Truth that the CPU timers never changed so far
+    pub fn is_constant(&self) -> bool {
+        self.constant_count.is_some()
+    }
+
+    /// Number of CPU timers which are provided by the kernel
+    pub fn timer_count(&self) -> u8 {
+        self.data.timer_count()
+    }
+
+    /// Fraction of CPU time which was spent doing work between two samples,
+    /// or None if no CPU time was accounted (see Data::busy_fraction)
+    pub fn busy_fraction(&self, older: usize, newer: usize) -> Option<f64> {
+        self.data.busy_fraction(self.stored_index(older),
+                                self.stored_index(newer))
+    }
+
+    /// INTERNAL: Clock ticks spent doing work and idle between two samples
+    pub(super) fn work_and_idle_ticks(&self,
+                                      older: usize,
+                                      newer: usize) -> (u128, u128) {
+        self.data.work_and_idle_ticks(self.stored_index(older),
+                                      self.stored_index(newer))
+    }
+
+    /// Initialize the CPU thread statistics from a data store, which is
+    /// stored sparsely if all of its samples are equal (as is the case of a
+    /// freshly created one)
+    pub(super) fn new(mut data: Data) -> Self {
+        let length = data.len();
+        if data.reset_indices.is_empty()
+           && (1..length).all(|sample| data.samples_equal(0, sample))
+        {
+            data.truncate(1);
+            Self { data, constant_count: Some(length) }
+        } else {
+            Self { data, constant_count: None }
+        }
+    }
+
+    /// Record that the CPU timers were reset by a system reboot before the
+    /// sample with a certain index was taken
+    ///
+    /// If the CPU timers did not change, the reboot makes no difference to
+    /// any of their deltas, so we keep storing them sparsely.
+    ///
+    pub(super) fn mark_reset(&mut self, sample_idx: usize) {
+        if self.constant_count.is_none() {
+            self.data.mark_reset(sample_idx);
+        }
+    }
+
+    /// Reserve storage for some additional samples. Constant CPU threads do
+    /// not need any.
+    pub(super) fn reserve(&mut self, additional: usize) {
+        if self.constant_count.is_none() {
+            self.data.reserve(additional);
+        }
+    }
+
+    /// Parse CPU statistics and add them to the internal data store (see
+    /// Data::push for schema change handling)
+    pub(super) fn push(&mut self,
+                       fields: RecordFields) -> Result<(), SchemaChanged> {
+        // If the CPU timers changed before, sample them normally
+        let count = match self.constant_count {
+            Some(count) => count,
+            None => return self.data.push(fields),
+        };
+
+        // If they have remained constant so far and still are, just increment
+        // the sample counter
+        self.data.push(fields)?;
+        if count == 0 || self.data.samples_equal(0, 1) {
+            self.data.truncate(1);
+            self.constant_count = Some(count + 1);
+            return Ok(());
+        }
+
+        // Otherwise, move to regular CPU timer sampling
+        self.data.repeat_oldest(count);
+        self.constant_count = None;
+        Ok(())
+    }
+}
+//
+impl SampledData for SampledThread {
+    /// Tell how many samples are present in the data store
+    fn len(&self) -> usize {
+        match self.constant_count {
+            Some(count) => count,
+            None => self.data.len(),
+        }
+    }
+
+    /// Drop the oldest samples from the data store
+    ///
+    /// We do not attempt to go back to the constant state if only equal
+    /// samples remain after this operation: a CPU thread which was active once
+    /// is likely to become active again.
+    ///
+    fn drop_oldest(&mut self, count: usize) {
+        match self.constant_count {
+            Some(ref mut sample_count) => {
+                assert!(count <= *sample_count, "Not enough samples to drop");
+                *sample_count -= count;
+                if *sample_count == 0 {
+                    self.data.truncate(0);
+                }
+            },
+            None => self.data.drop_oldest(count),
+        }
+    }
+
+    /// Tell the length of the shortest time series in the data store
+    fn min_len(&self) -> usize {
+        match self.constant_count {
+            Some(count) => count,
+            None => self.data.min_len(),
+        }
+    }
+
+    /// Drop the newest samples from the data store
+    fn truncate(&mut self, length: usize) {
+        match self.constant_count {
+            Some(ref mut count) => {
+                if length < *count {
+                    *count = length;
+                    self.data.truncate(length);
+                }
+            },
+            None => self.data.truncate(length),
+        }
+    }
+}
+//
+/// CPU thread statistics are equal if they hold the same samples, whether
+/// they are stored sparsely or not
+impl PartialEq for SampledThread {
+    fn eq(&self, other: &Self) -> bool {
+        self.dense() == other.dense()
+    }
+}
+//
+/// Binary serialization of CPU thread statistics, which are expanded into
+/// full time series so that the format does not depend on the storage mode
+#[cfg(feature = "binary")]
+impl BinaryValue for SampledThread {
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.dense().write_to(writer)
+    }
+
+    fn read_from<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Data::read_from(reader).map(SampledThread::new)
+    }
+}
+//
+/// Memory footprint measurements, which storage benchmarks compare
+#[cfg(test)]
+impl SampledThread {
+    /// Heap memory used by the CPU timers, in bytes, followed by the amount
+    /// which the same timers would use if they were stored densely
+    pub(super) fn footprint(&self) -> (usize, usize) {
+        let data_footprint = |data: &Data| -> usize {
+            data.timers()
+                .iter()
+                .map(|timer| timer.capacity() * ::std::mem::size_of::<u64>())
+                .sum()
+        };
+        (data_footprint(&self.data), data_footprint(&self.dense()))
     }
 }
 
@@ -216,45 +1291,43 @@ impl Data {
 mod tests {
     use std::time::Duration;
     use ::splitter::split_line_and_run;
-    use super::{Data, RecordFields, SampledData, NANOSECS_PER_TICK};
+    use super::{ticks_to_duration, Data, DurationSeries, RecordFields,
+                SampledData, SampledThread, NANOSECS_PER_TICK, TICKS_PER_SEC};
 
     /// Test the parsing of valid CPU stats
     #[test]
     fn record_field_parsing() {
-        // Figure out the duration of a kernel tick
-        let tick_duration = *TICK_DURATION;
-
         // Check that the oldest supported CPU stats format is parsed properly
         with_record_fields("165 18 96 1", |mut fields| {
-            assert_eq!(fields.next(), Some(tick_duration*165));
-            assert_eq!(fields.next(), Some(tick_duration*18));
-            assert_eq!(fields.next(), Some(tick_duration*96));
-            assert_eq!(fields.next(), Some(tick_duration));
+            assert_eq!(fields.next(), Some(165));
+            assert_eq!(fields.next(), Some(18));
+            assert_eq!(fields.next(), Some(96));
+            assert_eq!(fields.next(), Some(1));
             assert_eq!(fields.next(), None);
         });
 
         // Check that a slightly extended version parses just as well
         with_record_fields("9 678 6521 151 56", |mut fields| {
-            assert_eq!(fields.next(), Some(tick_duration*9));
-            assert_eq!(fields.next(), Some(tick_duration*678));
-            assert_eq!(fields.next(), Some(tick_duration*6521));
-            assert_eq!(fields.next(), Some(tick_duration*151));
-            assert_eq!(fields.next(), Some(tick_duration*56));
+            assert_eq!(fields.next(), Some(9));
+            assert_eq!(fields.next(), Some(678));
+            assert_eq!(fields.next(), Some(6521));
+            assert_eq!(fields.next(), Some(151));
+            assert_eq!(fields.next(), Some(56));
             assert_eq!(fields.next(), None);
         });
 
         // Check that the newest supported CPU stats format parses as well
         with_record_fields("18 9613 11 941 5 51 9 615 62 14", |mut fields| {
-            assert_eq!(fields.next(), Some(tick_duration*18));
-            assert_eq!(fields.next(), Some(tick_duration*9613));
-            assert_eq!(fields.next(), Some(tick_duration*11));
-            assert_eq!(fields.next(), Some(tick_duration*941));
-            assert_eq!(fields.next(), Some(tick_duration*5));
-            assert_eq!(fields.next(), Some(tick_duration*51));
-            assert_eq!(fields.next(), Some(tick_duration*9));
-            assert_eq!(fields.next(), Some(tick_duration*615));
-            assert_eq!(fields.next(), Some(tick_duration*62));
-            assert_eq!(fields.next(), Some(tick_duration*14));
+            assert_eq!(fields.next(), Some(18));
+            assert_eq!(fields.next(), Some(9613));
+            assert_eq!(fields.next(), Some(11));
+            assert_eq!(fields.next(), Some(941));
+            assert_eq!(fields.next(), Some(5));
+            assert_eq!(fields.next(), Some(51));
+            assert_eq!(fields.next(), Some(9));
+            assert_eq!(fields.next(), Some(615));
+            assert_eq!(fields.next(), Some(62));
+            assert_eq!(fields.next(), Some(14));
             assert_eq!(fields.next(), None);
         });
     }
@@ -262,107 +1335,471 @@ mod tests {
     /// Check that CPU stats containers work well for the oldest stat format
     #[test]
     fn oldest_stats() {
-        // Figure out the duration of a kernel tick
-        let tick_duration = *TICK_DURATION;
-
         // Check that building a container for the oldest stats format works
         let mut data = with_record_fields("94 6316 64 2", Data::new);
-        assert_eq!(data.user_time,          Vec::new());
-        assert_eq!(data.nice_time,          Vec::new());
-        assert_eq!(data.system_time,        Vec::new());
-        assert_eq!(data.idle_time,          Vec::new());
-        assert_eq!(data.io_wait_time,       None);
-        assert_eq!(data.irq_time,           None);
-        assert_eq!(data.softirq_time,       None);
-        assert_eq!(data.stolen_time,        None);
-        assert_eq!(data.guest_time,         None);
-        assert_eq!(data.guest_nice_time,    None);
+        assert_eq!(data.user_ticks,          Vec::<u64>::new());
+        assert_eq!(data.nice_ticks,          Vec::<u64>::new());
+        assert_eq!(data.system_ticks,        Vec::<u64>::new());
+        assert_eq!(data.idle_ticks,          Vec::<u64>::new());
+        assert_eq!(data.io_wait_ticks,       None);
+        assert_eq!(data.irq_ticks,           None);
+        assert_eq!(data.softirq_ticks,       None);
+        assert_eq!(data.stolen_ticks,        None);
+        assert_eq!(data.guest_ticks,         None);
+        assert_eq!(data.guest_nice_ticks,    None);
         assert_eq!(data.len(),              0);
 
         // Check that pushing data into it works as well
-        with_record_fields("46 421 3 7866", |fields| data.push(fields));
-        assert_eq!(data.user_time,          vec![tick_duration*46]);
-        assert_eq!(data.nice_time,          vec![tick_duration*421]);
-        assert_eq!(data.system_time,        vec![tick_duration*3]);
-        assert_eq!(data.idle_time,          vec![tick_duration*7866]);
-        assert_eq!(data.io_wait_time,       None);
-        assert_eq!(data.irq_time,           None);
-        assert_eq!(data.softirq_time,       None);
-        assert_eq!(data.stolen_time,        None);
-        assert_eq!(data.guest_time,         None);
-        assert_eq!(data.guest_nice_time,    None);
+        assert_eq!(data, Data::empty());
+        with_record_fields("46 421 3 7866", |fields| {
+            data.push(fields).unwrap()
+        });
+        assert_eq!(data.user_ticks,          vec![46]);
+        assert_eq!(data.nice_ticks,          vec![421]);
+        assert_eq!(data.system_ticks,        vec![3]);
+        assert_eq!(data.idle_ticks,          vec![7866]);
+        assert_eq!(data.io_wait_ticks,       None);
+        assert_eq!(data.irq_ticks,           None);
+        assert_eq!(data.softirq_ticks,       None);
+        assert_eq!(data.stolen_ticks,        None);
+        assert_eq!(data.guest_ticks,         None);
+        assert_eq!(data.guest_nice_ticks,    None);
         assert_eq!(data.len(),              1);
+        assert_eq!(data, Data::empty().with_sample(&[46, 421, 3, 7866]));
     }
 
     /// Check that the first historical "extented" stats format works as well
     #[test]
     fn extended_stats() {
-        // Figure out the duration of a kernel tick
-        let tick_duration = *TICK_DURATION;
-
         // Check that building a container for the extended stats format works
         let mut data = with_record_fields("66 321 795 12 32", Data::new);
-        assert_eq!(data.user_time,          Vec::new());
-        assert_eq!(data.nice_time,          Vec::new());
-        assert_eq!(data.system_time,        Vec::new());
-        assert_eq!(data.idle_time,          Vec::new());
-        assert_eq!(data.io_wait_time,       Some(Vec::new()));
-        assert_eq!(data.irq_time,           None);
-        assert_eq!(data.softirq_time,       None);
-        assert_eq!(data.stolen_time,        None);
-        assert_eq!(data.guest_time,         None);
-        assert_eq!(data.guest_nice_time,    None);
+        assert_eq!(data.user_ticks,          Vec::<u64>::new());
+        assert_eq!(data.nice_ticks,          Vec::<u64>::new());
+        assert_eq!(data.system_ticks,        Vec::<u64>::new());
+        assert_eq!(data.idle_ticks,          Vec::<u64>::new());
+        assert_eq!(data.io_wait_ticks,       Some(Vec::new()));
+        assert_eq!(data.irq_ticks,           None);
+        assert_eq!(data.softirq_ticks,       None);
+        assert_eq!(data.stolen_ticks,        None);
+        assert_eq!(data.guest_ticks,         None);
+        assert_eq!(data.guest_nice_ticks,    None);
         assert_eq!(data.len(),              0);
 
         // Check that pushing data into it works as well
-        with_record_fields("3122 21 9 46 32", |fields| data.push(fields));
-        assert_eq!(data.user_time,          vec![tick_duration*3122]);
-        assert_eq!(data.nice_time,          vec![tick_duration*21]);
-        assert_eq!(data.system_time,        vec![tick_duration*9]);
-        assert_eq!(data.idle_time,          vec![tick_duration*46]);
-        assert_eq!(data.io_wait_time,       Some(vec![tick_duration*32]));
-        assert_eq!(data.irq_time,           None);
-        assert_eq!(data.softirq_time,       None);
-        assert_eq!(data.stolen_time,        None);
-        assert_eq!(data.guest_time,         None);
-        assert_eq!(data.guest_nice_time,    None);
+        assert_eq!(data, Data::empty().with_optional_timers(1));
+        with_record_fields("3122 21 9 46 32", |fields| {
+            data.push(fields).unwrap()
+        });
+        assert_eq!(data.user_ticks,          vec![3122]);
+        assert_eq!(data.nice_ticks,          vec![21]);
+        assert_eq!(data.system_ticks,        vec![9]);
+        assert_eq!(data.idle_ticks,          vec![46]);
+        assert_eq!(data.io_wait_ticks,       Some(vec![32]));
+        assert_eq!(data.irq_ticks,           None);
+        assert_eq!(data.softirq_ticks,       None);
+        assert_eq!(data.stolen_ticks,        None);
+        assert_eq!(data.guest_ticks,         None);
+        assert_eq!(data.guest_nice_ticks,    None);
         assert_eq!(data.len(),              1);
     }
 
     /// Check that the latest supported stats format works as well
     #[test]
     fn latest_stats() {
-        // Figure out the duration of a kernel tick
-        let tick_duration = *TICK_DURATION;
-
         // Check that building a container for the extended stats format works
         let mut data = with_record_fields("31 854 361 32 6 8 21 9 3 2",
                                           Data::new);
-        assert_eq!(data.user_time,          Vec::new());
-        assert_eq!(data.nice_time,          Vec::new());
-        assert_eq!(data.system_time,        Vec::new());
-        assert_eq!(data.idle_time,          Vec::new());
-        assert_eq!(data.io_wait_time,       Some(Vec::new()));
-        assert_eq!(data.irq_time,           Some(Vec::new()));
-        assert_eq!(data.softirq_time,       Some(Vec::new()));
-        assert_eq!(data.stolen_time,        Some(Vec::new()));
-        assert_eq!(data.guest_time,         Some(Vec::new()));
-        assert_eq!(data.guest_nice_time,    Some(Vec::new()));
+        assert_eq!(data.user_ticks,          Vec::<u64>::new());
+        assert_eq!(data.nice_ticks,          Vec::<u64>::new());
+        assert_eq!(data.system_ticks,        Vec::<u64>::new());
+        assert_eq!(data.idle_ticks,          Vec::<u64>::new());
+        assert_eq!(data.io_wait_ticks,       Some(Vec::new()));
+        assert_eq!(data.irq_ticks,           Some(Vec::new()));
+        assert_eq!(data.softirq_ticks,       Some(Vec::new()));
+        assert_eq!(data.stolen_ticks,        Some(Vec::new()));
+        assert_eq!(data.guest_ticks,         Some(Vec::new()));
+        assert_eq!(data.guest_nice_ticks,    Some(Vec::new()));
         assert_eq!(data.len(),              0);
 
         // Check that pushing data into it works as well
-        with_record_fields("21 61 8 5 9 3 1 7 0 4", |fields| data.push(fields));
-        assert_eq!(data.user_time,          vec![tick_duration*21]);
-        assert_eq!(data.nice_time,          vec![tick_duration*61]);
-        assert_eq!(data.system_time,        vec![tick_duration*8]);
-        assert_eq!(data.idle_time,          vec![tick_duration*5]);
-        assert_eq!(data.io_wait_time,       Some(vec![tick_duration*9]));
-        assert_eq!(data.irq_time,           Some(vec![tick_duration*3]));
-        assert_eq!(data.softirq_time,       Some(vec![tick_duration*1]));
-        assert_eq!(data.stolen_time,        Some(vec![tick_duration*7]));
-        assert_eq!(data.guest_time,         Some(vec![tick_duration*0]));
-        assert_eq!(data.guest_nice_time,    Some(vec![tick_duration*4]));
+        assert_eq!(data, Data::empty().with_optional_timers(6));
+        with_record_fields("21 61 8 5 9 3 1 7 0 4", |fields| {
+            data.push(fields).unwrap()
+        });
+        assert_eq!(data.user_ticks,          vec![21]);
+        assert_eq!(data.nice_ticks,          vec![61]);
+        assert_eq!(data.system_ticks,        vec![8]);
+        assert_eq!(data.idle_ticks,          vec![5]);
+        assert_eq!(data.io_wait_ticks,       Some(vec![9]));
+        assert_eq!(data.irq_ticks,           Some(vec![3]));
+        assert_eq!(data.softirq_ticks,       Some(vec![1]));
+        assert_eq!(data.stolen_ticks,        Some(vec![7]));
+        assert_eq!(data.guest_ticks,         Some(vec![0]));
+        assert_eq!(data.guest_nice_ticks,    Some(vec![4]));
         assert_eq!(data.len(),              1);
+        assert_eq!(data, Data::empty().with_optional_timers(6)
+                                      .with_sample(&[21, 61, 8, 5, 9,
+                                                     3, 1, 7, 0, 4]));
+    }
+
+    /// Check that changes in the number of CPU timers are reported
+    #[test]
+    fn timer_count_change() {
+        // Initialize a container with 4 CPU timers
+        let mut data = with_record_fields("1 2 3 4", Data::new);
+        with_record_fields("1 2 3 4", |fields| data.push(fields).unwrap());
+
+        // Pushing a record with 5 timers should fail without storing anything
+        assert!(with_record_fields("2 3 4 5 6", |fields| data.push(fields))
+                    .is_err());
+        assert_eq!(data, Data::empty().with_sample(&[1, 2, 3, 4]));
+
+        // The same goes for a record with 3 timers
+        assert!(with_record_fields("2 3 4", |fields| data.push(fields))
+                    .is_err());
+        assert_eq!(data.len(), 1);
+    }
+
+    /// Check that raw tick counts are converted to durations as expected
+    #[test]
+    fn tick_conversion() {
+        // Figure out the duration of a kernel tick
+        let tick_duration = *TICK_DURATION;
+
+        // Check the conversion of individual tick counts
+        assert_eq!(ticks_to_duration(0), Duration::new(0, 0));
+        assert_eq!(ticks_to_duration(1), tick_duration);
+        assert_eq!(ticks_to_duration(*TICKS_PER_SEC), Duration::new(1, 0));
+        assert_eq!(ticks_to_duration(*TICKS_PER_SEC + 3),
+                   Duration::new(1, 0) + tick_duration*3);
+
+        // Check that the accessors expose both ticks and durations
+        let mut data = with_record_fields("31 854 361 32 6", Data::new);
+        with_record_fields("21 61 8 5 9", |fields| data.push(fields).unwrap());
+        with_record_fields("42 61 9 7 9", |fields| data.push(fields).unwrap());
+        assert_eq!(data.user_ticks(),   &[21, 42]);
+        assert_eq!(data.user_time(),    vec![tick_duration*21,
+                                             tick_duration*42]);
+        assert_eq!(data.io_wait_ticks(), Some(&[9, 9][..]));
+        assert_eq!(data.io_wait_time(), Some(vec![tick_duration*9,
+                                                  tick_duration*9]));
+        assert_eq!(data.irq_ticks(),    None);
+        assert_eq!(data.irq_time(),     None);
+
+        // Check that the CPU busy fraction is computed from exact tick counts
+        assert_eq!(data.busy_fraction(0, 1), Some(22.0 / 24.0));
+        assert_eq!(data.busy_fraction(1, 1), None);
+    }
+
+    /// Check that duration series are summed and averaged exactly
+    #[test]
+    fn duration_series() {
+        // Empty series have a zero sum and no mean
+        let empty: Vec<Duration> = Vec::new();
+        assert_eq!(empty.sum_nanos(), 0);
+        assert_eq!(empty.mean_duration(), None);
+
+        // Sums are exact, even beyond what a u64 of nanoseconds can hold
+        let durations = vec![Duration::new(u64::max_value(), 999_999_999),
+                             Duration::new(0, 1)];
+        assert_eq!(durations.sum_nanos(),
+                   (u64::max_value() as u128 + 1) * 1_000_000_000);
+        assert_eq!(durations.mean_duration(),
+                   Some(Duration::new(u64::max_value() / 2 + 1, 0)));
+
+        // The CPU statistics' durations can be aggregated this way
+        let tick_duration = *TICK_DURATION;
+        let data = Data::empty().with_sample(&[1, 0, 0, 0])
+                                .with_sample(&[3, 0, 0, 0]);
+        assert_eq!(data.user_time().sum_nanos(),
+                   4 * *NANOSECS_PER_TICK as u128);
+        assert_eq!(data.user_time().mean_duration(),
+                   Some(tick_duration*2));
+    }
+
+    /// Check that the busiest sampling interval is found
+    #[test]
+    fn busiest_interval() {
+        // Not enough samples to compute a busy fraction
+        let data = Data::empty();
+        assert_eq!(data.argmax_busy(), None);
+        let data = data.with_sample(&[0, 0, 0, 0]);
+        assert_eq!(data.argmax_busy(), None);
+
+        // CPU activity peaks in the middle of the series
+        let data = data.with_sample(&[1, 0, 0, 9])
+                       .with_sample(&[9, 0, 1, 10])
+                       .with_sample(&[10, 0, 1, 18]);
+        assert_eq!(data.argmax_busy(), Some(2));
+    }
+
+    /// Check that busy fractions survive timers going backwards and reboots
+    #[test]
+    fn busy_fraction_resets() {
+        // IO wait time may go backwards, which counts as no time elapsed
+        let mut data = Data::empty().with_optional_timers(1)
+                                    .with_sample(&[10, 0, 10, 10, 5])
+                                    .with_sample(&[13, 0, 11, 14, 3])
+                                    .with_sample(&[1, 0, 1, 6, 0])
+                                    .with_sample(&[2, 0, 2, 10, 0]);
+        assert_eq!(data.busy_fraction(0, 1), Some(0.5));
+
+        // Across a reboot, only the time elapsed since boot is considered
+        data.mark_reset(2);
+        assert_eq!(data.busy_fraction(1, 2), Some(0.25));
+        assert_eq!(data.busy_fraction(0, 3), Some(4. / 14.));
+        assert_eq!(data.busy_fraction(2, 3), Some(2. / 6.));
+    }
+
+    /// Check that the corrected CPU time total does not double-count guests
+    #[test]
+    fn corrected_total() {
+        // Figure out the duration of a kernel tick
+        let tick_duration = *TICK_DURATION;
+
+        // Without guest timers, all timers should be summed normally
+        let data = Data::empty().with_optional_timers(4)
+                                .with_sample(&[1, 2, 3, 4, 5, 6, 7, 8])
+                                .with_sample(&[2, 4, 6, 8, 10, 12, 14, 16]);
+        assert_eq!(data.corrected_total_between(0, 1), tick_duration*36);
+
+        // With guest timers, guest time should not be counted twice
+        let data = Data::empty().with_optional_timers(6)
+                                .with_sample(&[10, 10, 0, 0, 0, 0, 0, 0, 0, 0])
+                                .with_sample(&[20, 15, 1, 2, 0, 0, 0, 0, 6, 3]);
+        assert_eq!(data.corrected_total_between(0, 1), tick_duration*18);
+        assert_eq!(data.corrected_total_between(1, 1), Duration::new(0, 0));
+    }
+
+    /// Check that the interrupt servicing fraction uses the available timers
+    #[test]
+    fn interrupt_servicing_fraction() {
+        // Without irq and softirq timers, there is nothing to measure
+        let data = Data::empty().with_optional_timers(1)
+                                .with_sample(&[0, 0, 0, 0, 0])
+                                .with_sample(&[4, 0, 2, 4, 0]);
+        assert_eq!(data.interrupt_servicing_fraction_between(0, 1), None);
+
+        // With only the irq timer, only irq time is accounted
+        let data = Data::empty().with_optional_timers(2)
+                                .with_sample(&[0, 0, 0, 0, 0, 0])
+                                .with_sample(&[4, 0, 2, 1, 1, 2]);
+        assert_eq!(data.interrupt_servicing_fraction_between(0, 1),
+                   Some(0.2));
+
+        // With both timers, irq and softirq times are summed
+        let data = Data::empty().with_optional_timers(4)
+                                .with_sample(&[0, 0, 0, 0, 0, 0, 0, 0])
+                                .with_sample(&[2, 0, 1, 4, 0, 1, 2, 0]);
+        assert_eq!(data.interrupt_servicing_fraction_between(0, 1),
+                   Some(0.3));
+        assert_eq!(data.interrupt_servicing_fraction_between(1, 1), None);
+
+        // Timers going backwards do not underflow, and reboots are rejected
+        let mut data = data.with_sample(&[1, 0, 1, 6, 0, 1, 1, 0])
+                           .with_sample(&[2, 0, 2, 8, 0, 1, 2, 0]);
+        assert_eq!(data.interrupt_servicing_fraction_between(1, 2),
+                   Some(0.));
+        data.mark_reset(2);
+        assert_eq!(data.interrupt_servicing_fraction_between(1, 2), None);
+        assert_eq!(data.interrupt_servicing_fraction_between(0, 3), None);
+        assert_eq!(data.interrupt_servicing_fraction_between(2, 3),
+                   Some(0.2));
+    }
+
+    /// Check that CPU percentages follow the conventions of mpstat
+    #[test]
+    fn percentages() {
+        // Over this interval, 110 ticks elapsed, 10 of which were spent in a
+        // guest and 2 in a niced guest (also accounted as user and nice time)
+        let data = Data::empty().with_optional_timers(6)
+                                .with_sample(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0])
+                                .with_sample(&[30, 8, 12, 50,
+                                               3, 1, 2, 4, 10, 2]);
+        let percentages = data.percentages_between(0, 1).unwrap();
+        let fields = [(percentages.usr, "18.18"),
+                      (percentages.nice, "5.45"),
+                      (percentages.sys, "10.91"),
+                      (percentages.iowait, "2.73"),
+                      (percentages.irq, "0.91"),
+                      (percentages.soft, "1.82"),
+                      (percentages.steal, "3.64"),
+                      (percentages.guest, "9.09"),
+                      (percentages.gnice, "1.82"),
+                      (percentages.idle, "45.45")];
+        for &(value, expected) in &fields {
+            assert_eq!(format!("{:.2}", value), expected);
+        }
+        let sum: f64 = fields.iter().map(|&(value, _)| value).sum();
+        assert!((sum - 100.).abs() < 1e-9);
+
+        // Timers which the kernel does not provide are reported as 0%
+        let data = Data::empty().with_optional_timers(0)
+                                .with_sample(&[0, 0, 0, 0])
+                                .with_sample(&[1, 0, 1, 2]);
+        let percentages = data.percentages_between(0, 1).unwrap();
+        assert_eq!((percentages.usr, percentages.sys, percentages.idle),
+                   (25., 25., 50.));
+        assert_eq!((percentages.iowait, percentages.guest), (0., 0.));
+
+        // If no CPU time elapsed, the CPU is reported as idle
+        let percentages = data.percentages_between(1, 1).unwrap();
+        assert_eq!((percentages.usr, percentages.idle), (0., 100.));
+
+        // Timers going backwards do not underflow, and reboots are rejected
+        let mut data = data.with_sample(&[2, 0, 1, 3])
+                           .with_sample(&[1, 0, 1, 2]);
+        let percentages = data.percentages_between(1, 2).unwrap();
+        assert_eq!((percentages.usr, percentages.idle), (50., 50.));
+        let percentages = data.percentages_between(2, 3).unwrap();
+        assert_eq!((percentages.usr, percentages.idle), (0., 100.));
+        data.mark_reset(3);
+        assert_eq!(data.percentages_between(2, 3), None);
+        assert_eq!(data.percentages_between(0, 3), None);
+    }
+
+    /// Check that long-run utilization is computed exactly
+    #[test]
+    fn overall_busy_fraction() {
+        // Build a long series where work and idle ticks vary irregularly
+        let mut data = Data::empty().with_optional_timers(1);
+        let mut ticks = [0u64; 5];
+        let (mut exact_work, mut exact_idle) = (0u128, 0u128);
+        data = data.with_sample(&ticks);
+        for i in 0..100_000u64 {
+            let increments = [i % 7, i % 3, i % 5, 100 - i % 11, i % 2];
+            for (tick, increment) in ticks.iter_mut().zip(increments.iter()) {
+                *tick += increment;
+            }
+            exact_work += (increments[0] + increments[1] + increments[2])
+                          as u128;
+            exact_idle += (increments[3] + increments[4]) as u128;
+            data = data.with_sample(&ticks);
+        }
+        assert_eq!(data.busy_ticks_total(), exact_work);
+        assert_eq!(data.idle_ticks_total(), exact_idle);
+
+        // The overall fraction should match the exact ratio, whereas the naive
+        // sum of per-interval busy time may only match it approximately
+        let exact_fraction = exact_work as f64
+                             / (exact_work + exact_idle) as f64;
+        let overall_fraction = data.overall_busy_fraction()
+                                   .expect("Some CPU time should be accounted");
+        assert_eq!(overall_fraction, exact_fraction);
+        let mut naive_work = 0.;
+        let mut naive_total = 0.;
+        for i in 1..data.len() {
+            let busy_fraction = data.busy_fraction(i-1, i).unwrap();
+            let interval_ticks = (data.user_ticks[i] - data.user_ticks[i-1]
+                                  + data.nice_ticks[i] - data.nice_ticks[i-1]
+                                  + data.system_ticks[i]
+                                  - data.system_ticks[i-1]
+                                  + data.idle_ticks[i] - data.idle_ticks[i-1]
+                                  + data.io_wait_ticks.as_ref().unwrap()[i]
+                                  - data.io_wait_ticks.as_ref().unwrap()[i-1])
+                                 as f64;
+            naive_work += busy_fraction * interval_ticks;
+            naive_total += interval_ticks;
+        }
+        assert!((naive_work / naive_total - overall_fraction).abs() < 1e-6);
+
+        // Captures with fewer than two samples account no CPU time
+        let data = Data::empty().with_optional_timers(0)
+                                .with_sample(&[1, 2, 3, 4]);
+        assert_eq!(data.busy_ticks_total(), 0);
+        assert_eq!(data.idle_ticks_total(), 0);
+        assert_eq!(data.overall_busy_fraction(), None);
+
+        // Intervals during which the system rebooted are left out, and IO wait
+        // time going backwards does not make the totals underflow
+        let mut data = Data::empty().with_optional_timers(1)
+                                    .with_sample(&[10, 0, 10, 10, 5])
+                                    .with_sample(&[13, 0, 11, 14, 3])
+                                    .with_sample(&[1, 0, 1, 6, 0])
+                                    .with_sample(&[2, 0, 2, 10, 1]);
+        data.mark_reset(2);
+        assert_eq!(data.busy_ticks_total(), 4 + 2);
+        assert_eq!(data.idle_ticks_total(), 4 + 5);
+    }
+
+    /// Check that inconsistent CPU stats can be repaired
+    #[test]
+    fn truncate_to_consistent() {
+        // Simulate a sample which was only partially stored
+        let mut data = Data::empty().with_optional_timers(2)
+                                    .with_sample(&[1, 2, 3, 4, 5, 6])
+                                    .with_sample(&[7, 8, 9, 10, 11, 12]);
+        data.user_ticks.push(13);
+        data.nice_ticks.push(14);
+        data.io_wait_ticks.as_mut().unwrap().push(15);
+        assert_eq!(data.min_len(), 2);
+
+        // Repairing should drop the incomplete sample and nothing else
+        assert_eq!(data.truncate_to_consistent(), 2);
+        assert_eq!(data, Data::empty().with_optional_timers(2)
+                                      .with_sample(&[1, 2, 3, 4, 5, 6])
+                                      .with_sample(&[7, 8, 9, 10, 11, 12]));
+        assert_eq!(data.len(), 2);
+
+        // Repairing a consistent data store should do nothing
+        assert_eq!(data.truncate_to_consistent(), 2);
+        assert_eq!(data.len(), 2);
+    }
+
+    /// Check that CPU threads whose timers do not change are stored sparsely
+    #[test]
+    fn sparse_thread_storage() {
+        // As long as the CPU timers do not change, they are stored sparsely
+        let mut thread = with_record_fields("1 2 3 4 5", |fields| {
+            SampledThread::new(Data::new(fields))
+        });
+        let push = |thread: &mut SampledThread, line: &str| {
+            with_record_fields(line, |fields| thread.push(fields))
+        };
+        for _ in 0..3 {
+            push(&mut thread, "1 2 3 4 5").unwrap();
+        }
+        let constant = Data::empty().with_optional_timers(1)
+                                    .with_sample(&[1, 2, 3, 4, 5])
+                                    .with_sample(&[1, 2, 3, 4, 5])
+                                    .with_sample(&[1, 2, 3, 4, 5]);
+        assert!(thread.is_constant());
+        assert_eq!(thread.len(), 3);
+        assert_eq!(thread.timer_count(), 5);
+        assert_eq!(*thread.dense(), constant);
+        assert_eq!(thread, SampledThread { data: constant.clone(),
+                                           constant_count: None });
+        assert_eq!(SampledThread::new(constant.clone()), thread);
+
+        // Schema changes are reported without storing anything
+        assert!(push(&mut thread, "1 2 3 4").is_err());
+        assert_eq!(thread.len(), 3);
+
+        // Once the CPU timers change, they are stored normally
+        push(&mut thread, "1 2 3 5 5").unwrap();
+        let changed = constant.clone().with_sample(&[1, 2, 3, 5, 5]);
+        assert!(!thread.is_constant());
+        assert_eq!(*thread.dense(), changed);
+        assert!(!SampledThread::new(changed).is_constant());
+
+        // Sparse storage supports dropping samples at both ends
+        let mut sparse = SampledThread::new(constant.clone());
+        sparse.drop_oldest(1);
+        assert_eq!(sparse.len(), 2);
+        sparse.truncate(5);
+        assert_eq!(sparse.min_len(), 2);
+        sparse.truncate(0);
+        assert_eq!(*sparse.dense(), Data::empty().with_optional_timers(1));
+        push(&mut sparse, "6 7 8 9 10").unwrap();
+        assert!(sparse.is_constant());
+        assert_eq!(sparse.len(), 1);
+
+        // ...and dropping the remaining samples of a dense thread keeps it so
+        thread.drop_oldest(3);
+        assert!(!thread.is_constant());
+        assert_eq!(*thread.dense(),
+                   Data::empty().with_optional_timers(1)
+                                .with_sample(&[1, 2, 3, 5, 5]));
     }
 
     /// Build the CPU record fields associated with a certain line of text, and
@@ -1,8 +1,14 @@
 //! This module contains facilities for parsing and storing the data contained
 //! in the IRQ statistics of /proc/stat (intr and softirq).
 
-use ::data::SampledData;
+#[cfg(feature = "binary")]
+use ::binary::{self, BinaryValue};
+use ::columns::{Column, Columns};
+use ::data::{argmax, SampledData};
 use ::splitter::SplitColumns;
+#[cfg(feature = "binary")]
+use std::io::{self, Read, Write};
+use std::time::Duration;
 
 
 /// Interrupt statistics record from /proc/stat
@@ -54,13 +60,17 @@ impl<'a, 'b> Iterator for DetailsIter<'a, 'b> {
 
 /// Interrupt statistics from /proc/stat, in structure-of-array layout
 #[derive(Clone, Debug, PartialEq)]
-pub(super) struct Data {
+pub struct Data {
     /// Total number of interrupts that were serviced. May be higher than the
     /// sum of the breakdown below if there are unnumbered interrupt sources.
     total: Vec<u64>,
 
     /// For each numbered source, details on the amount of serviced interrupt.
-    details: Vec<SampledCounter>
+    details: Vec<SampledCounter>,
+
+    /// Indices of the samples which were taken after the interrupt counters
+    /// were reset by a system reboot (as detected via the /proc/stat btime)
+    reset_indices: Vec<usize>,
 }
 //
 impl SampledData for Data {
@@ -70,20 +80,144 @@ impl SampledData for Data {
         debug_assert!(self.details.iter().all(|vec| vec.len() == length));
         length
     }
+
+    // Drop the oldest samples from the data store
+    fn drop_oldest(&mut self, count: usize) {
+        self.total.drain(..count);
+        for detail in self.details.iter_mut() {
+            detail.drop_oldest(count);
+        }
+        self.reset_indices.retain(|&idx| idx >= count);
+        for idx in self.reset_indices.iter_mut() {
+            *idx -= count;
+        }
+    }
+
+    // Tell the length of the shortest time series in the data store
+    fn min_len(&self) -> usize {
+        self.details.iter()
+                    .map(|detail| detail.len())
+                    .fold(self.total.len(), ::std::cmp::min)
+    }
+
+    // Drop the newest samples from the data store
+    fn truncate(&mut self, length: usize) {
+        self.total.truncate(length);
+        for detail in self.details.iter_mut() {
+            detail.truncate(length);
+        }
+        self.reset_indices.retain(|&idx| idx < length);
+    }
 }
 //
 // TODO: Implement SampledData2 once that is usable in stable Rust
 impl Data {
+    /// Total number of interrupts that were serviced since boot
+    pub fn total(&self) -> &[u64] { &self.total }
+
+    /// Number of interrupt sources which are enumerated by the kernel
+    pub fn source_count(&self) -> usize { self.details.len() }
+
+    /// Number of interrupts from unnumbered sources at a certain sample index
+    ///
+    /// The total interrupt count reported by the kernel may be higher than the
+    /// sum of the per-source breakdown, because some interrupt sources are not
+    /// enumerated. This tells how many interrupts came from such sources.
+    ///
+    /// The kernel does not read the total and the breakdown atomically, and
+    /// per-source counters may wrap around independently of the total, so the
+    /// breakdown may occasionally exceed the total. Zero is reported then.
+    ///
+    pub fn unnumbered_total(&self, index: usize) -> u64 {
+        let numbered_total =
+            self.details.iter()
+                        .map(|detail| detail.get(index))
+                        .fold(0, u64::saturating_add);
+        self.total[index].saturating_sub(numbered_total)
+    }
+
+    /// Index of the sample which ends the sampling interval with the highest
+    /// interrupt rate, i.e. the sample "i" where total[i] - total[i-1] peaks
+    ///
+    /// This assumes a fixed sampling period. Intervals where the interrupt
+    /// count went down, as happens across reboots, are ignored. Returns None if
+    /// there are fewer than two samples.
+    ///
+    pub fn argmax_rate(&self) -> Option<usize> {
+        argmax((1..self.total.len()).filter_map(|newer| {
+            self.total[newer].checked_sub(self.total[newer-1])
+                             .map(|delta| (newer, delta))
+        }))
+    }
+
+    /// Number of interrupts that were serviced between two sample indices,
+    /// accounting for system reboots
+    ///
+    /// If the interrupt counters were reset in the meantime, the total from the
+    /// newer sample is returned as the best available estimate. The same is
+    /// done if the total went down without a reboot being detected, so that
+    /// the result never wraps around to a huge value.
+    ///
+    pub fn total_delta_between(&self, older: usize, newer: usize) -> u64 {
+        assert!(older <= newer, "Samples should be ordered by age");
+        let rebooted = self.reset_indices
+                           .iter()
+                           .any(|&idx| idx > older && idx <= newer);
+        let newer_total = self.total[newer];
+        if rebooted {
+            newer_total
+        } else {
+            newer_total.checked_sub(self.total[older]).unwrap_or(newer_total)
+        }
+    }
+
+    /// Rate at which interrupts were serviced, per second, between two samples
+    /// separated by a certain amount of wall clock time (see
+    /// total_delta_between())
+    ///
+    /// Returns None if no time has elapsed between these samples.
+    ///
+    pub fn total_rate_between(&self,
+                              older: usize,
+                              newer: usize,
+                              elapsed: Duration) -> Option<f64> {
+        let secs = elapsed.as_secs() as f64
+                   + elapsed.subsec_nanos() as f64 * 1e-9;
+        if secs > 0.0 {
+            Some(self.total_delta_between(older, newer) as f64 / secs)
+        } else {
+            None
+        }
+    }
+
     /// Create new interrupt statistics, given the amount of interrupt sources
-    pub fn new(fields: RecordFields) -> Self {
+    pub(super) fn new(fields: RecordFields) -> Self {
         Self {
             total: Vec::new(),
             details: vec![SampledCounter::new(); fields.details.count()],
+            reset_indices: Vec::new(),
+        }
+    }
+
+    /// Record that the interrupt counters were reset by a system reboot before
+    /// the sample with a certain index was taken
+    pub(super) fn mark_reset(&mut self, sample_idx: usize) {
+        self.reset_indices.push(sample_idx);
+    }
+
+    /// Reserve storage for some additional samples. Interrupt sources which
+    /// only recorded zeroes so far do not need any.
+    pub(super) fn reserve(&mut self, additional: usize) {
+        self.total.reserve(additional);
+        for detail in self.details.iter_mut() {
+            if let SampledCounter::Samples(ref mut vec) = *detail {
+                vec.reserve(additional);
+            }
         }
     }
 
     /// Parse interrupt statistics and add them to the internal data store
-    pub fn push(&mut self, fields: RecordFields) {
+    pub(super) fn push(&mut self, fields: RecordFields) {
         // Load the total interrupt count
         self.total.push(fields.total);
 
@@ -99,6 +233,42 @@ impl Data {
                       "An IRQ counter appeared out of nowhere");
     }
 }
+//
+/// Interrupt statistics are exported as a "total" column, followed by one
+/// column per numbered interrupt source, named after its number
+impl Columns for Data {
+    fn columns(&self) -> Vec<(String, Column)> {
+        let mut columns = vec![("total".to_owned(),
+                                Column::counts(&self.total))];
+        for (number, detail) in self.details.iter().enumerate() {
+            let counts = (0..detail.len()).map(|idx| detail.get(idx))
+                                          .collect();
+            columns.push((number.to_string(), Column::U64(counts)));
+        }
+        columns
+    }
+}
+//
+/// Binary serialization of interrupt statistics: the total interrupt counts,
+/// followed by the per-source breakdown and the counter reset indices
+#[cfg(feature = "binary")]
+impl BinaryValue for Data {
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.total.write_to(writer)?;
+        self.details.write_to(writer)?;
+        self.reset_indices.write_to(writer)
+    }
+
+    fn read_from<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(
+            Self {
+                total: Vec::read_from(reader)?,
+                details: Vec::read_from(reader)?,
+                reset_indices: Vec::read_from(reader)?,
+            }
+        )
+    }
+}
 ///
 ///
 /// On some platforms such as x86, there are a lot of hardware IRQs (~500 on my
@@ -145,6 +315,18 @@ impl SampledCounter {
         }
     }
 
+    /// Access the interrupt count at a certain sample index. Zero-optimized
+    /// counters are handled without materializing their zeroes.
+    fn get(&self, index: usize) -> u64 {
+        match *self {
+            SampledCounter::Zeroes(zero_count) => {
+                assert!(index < zero_count, "Out-of-bounds sample index");
+                0
+            },
+            SampledCounter::Samples(ref vec) => vec[index],
+        }
+    }
+
     /// Tell how many interrupt counts we have recorded so far
     fn len(&self) -> usize {
         match *self {
@@ -152,6 +334,64 @@ impl SampledCounter {
             SampledCounter::Samples(ref vec) => vec.len(),
         }
     }
+
+    /// Drop the oldest interrupt counts that we have recorded
+    ///
+    /// We do not attempt to go back to the zero-optimized state if only zeroes
+    /// remain after this operation: an interrupt source which fired once is
+    /// likely to fire again.
+    ///
+    fn drop_oldest(&mut self, count: usize) {
+        match *self {
+            SampledCounter::Zeroes(ref mut zero_count) => {
+                assert!(count <= *zero_count, "Not enough samples to drop");
+                *zero_count -= count;
+            },
+            SampledCounter::Samples(ref mut vec) => {
+                vec.drain(..count);
+            },
+        }
+    }
+
+    /// Drop the newest interrupt counts that we have recorded
+    fn truncate(&mut self, length: usize) {
+        match *self {
+            SampledCounter::Zeroes(ref mut zero_count) => {
+                assert!(length <= *zero_count, "Not enough samples to keep");
+                *zero_count = length;
+            },
+            SampledCounter::Samples(ref mut vec) => {
+                vec.truncate(length);
+            },
+        }
+    }
+}
+//
+/// Binary serialization of interrupt counts, where runs of zeroes are kept
+/// compressed: a tag byte (0 for zeroes, 1 for samples), followed by either
+/// the number of zeroes or the interrupt count samples
+#[cfg(feature = "binary")]
+impl BinaryValue for SampledCounter {
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        match *self {
+            SampledCounter::Zeroes(zero_count) => {
+                0u8.write_to(writer)?;
+                zero_count.write_to(writer)
+            },
+            SampledCounter::Samples(ref vec) => {
+                1u8.write_to(writer)?;
+                vec.write_to(writer)
+            },
+        }
+    }
+
+    fn read_from<R: Read>(reader: &mut R) -> io::Result<Self> {
+        match u8::read_from(reader)? {
+            0 => usize::read_from(reader).map(SampledCounter::Zeroes),
+            1 => Vec::read_from(reader).map(SampledCounter::Samples),
+            _ => Err(binary::invalid_data("Invalid interrupt counter tag")),
+        }
+    }
 }
 
 
@@ -159,6 +399,7 @@ impl SampledCounter {
 #[cfg(test)]
 mod tests {
     use ::splitter::split_line_and_run;
+    use std::time::Duration;
     use super::{Data, DetailsIter, RecordFields, SampledCounter, SampledData};
 
     /// Check that the detailed interrupt count parser works, and that its
@@ -220,6 +461,17 @@ mod tests {
         samples.push(27);
         assert_eq!(samples, SampledCounter::Samples(vec![0, 0, 69, 0, 27]));
         assert_eq!(samples.len(), 5);
+
+        // Dropping old samples works in the regular state...
+        samples.drop_oldest(2);
+        assert_eq!(samples, SampledCounter::Samples(vec![69, 0, 27]));
+        assert_eq!(samples.len(), 3);
+
+        // ...and in the zero-optimized state
+        let mut zeroes = SampledCounter::Zeroes(4);
+        zeroes.drop_oldest(3);
+        assert_eq!(zeroes, SampledCounter::Zeroes(1));
+        assert_eq!(zeroes.len(), 1);
     }
 
     /// Check that full interrupt samples work well
@@ -227,7 +479,7 @@ mod tests {
     fn sampled_data() {
         // Check that initialization works
         let mut data = with_record_fields("666 0 24", Data::new);
-        assert_eq!(data.total, Vec::new());
+        assert_eq!(data.total, Vec::<u64>::new());
         assert_eq!(data.details.len(), 2);
         assert_eq!(data.len(), 0);
 
@@ -244,6 +496,75 @@ mod tests {
         assert_eq!(data.len(), 2);
     }
 
+    /// Check that interrupts from unnumbered sources are counted correctly
+    #[test]
+    fn unnumbered_total() {
+        // Total exceeds the sum of the breakdown, including zero-optimized
+        // interrupt sources in the breakdown
+        let mut data = with_record_fields("100 0 24 0", Data::new);
+        with_record_fields("100 0 24 0", |fields| data.push(fields));
+        with_record_fields("150 0 30 5", |fields| data.push(fields));
+        assert_eq!(data.details[0], SampledCounter::Zeroes(2));
+        assert_eq!(data.unnumbered_total(0), 76);
+        assert_eq!(data.unnumbered_total(1), 115);
+
+        // Total exactly matches the sum of the breakdown
+        let mut data = with_record_fields("42 40 2 0", Data::new);
+        with_record_fields("42 40 2 0", |fields| data.push(fields));
+        assert_eq!(data.unnumbered_total(0), 0);
+
+        // Breakdown exceeds the total
+        let mut data = with_record_fields("42 40 3 0", Data::new);
+        with_record_fields("42 40 3 0", |fields| data.push(fields));
+        assert_eq!(data.unnumbered_total(0), 0);
+    }
+
+    /// Check that the peak interrupt rate is found
+    #[test]
+    fn peak_rate() {
+        // Not enough samples to compute a rate
+        let mut data = with_record_fields("0 0", Data::new);
+        assert_eq!(data.argmax_rate(), None);
+        with_record_fields("100 0", |fields| data.push(fields));
+        assert_eq!(data.argmax_rate(), None);
+
+        // Interrupt activity peaks in the middle of the series
+        for total in &["150 0", "400 0", "500 0"] {
+            with_record_fields(total, |fields| data.push(fields));
+        }
+        assert_eq!(data.argmax_rate(), Some(2));
+    }
+
+    /// Check that interrupt rates remain sensible across counter resets
+    #[test]
+    fn total_rate() {
+        let mut data = with_record_fields("0 0", Data::new);
+        for total in &["1000 0", "1500 0", "200 0", "900 0", "100 0"] {
+            with_record_fields(total, |fields| data.push(fields));
+        }
+        data.mark_reset(2);
+
+        // Regular deltas and rates
+        assert_eq!(data.total_delta_between(0, 1), 500);
+        assert_eq!(data.total_rate_between(0, 1, Duration::new(2, 0)),
+                   Some(250.0));
+        assert_eq!(data.total_rate_between(0, 1, Duration::new(0, 0)), None);
+
+        // Across a reboot, only post-reboot activity is measured
+        assert_eq!(data.total_delta_between(1, 2), 200);
+        assert_eq!(data.total_delta_between(0, 3), 900);
+        assert_eq!(data.total_delta_between(2, 3), 700);
+
+        // A decreasing total without a detected reboot does not wrap around
+        assert_eq!(data.total_delta_between(3, 4), 100);
+
+        // Reset indices are kept in sync with the samples
+        data.drop_oldest(1);
+        assert_eq!(data.reset_indices, vec![1]);
+        data.truncate(1);
+        assert_eq!(data.reset_indices, Vec::<usize>::new());
+    }
+
     /// Build the interrupt record fields associated with a line of text, and
     /// run code taking that as a parameter
     fn with_record_fields<F, R>(line_of_text: &str, functor: F) -> R
@@ -1,18 +1,165 @@
 //! This module contains a sampling parser for /proc/stat
 
-mod cpu;
-mod interrupts;
-mod paging;
-
-use ::data::{SampledData, SampledData0};
+pub mod cpu;
+pub mod interrupts;
+pub mod paging;
+
+#[cfg(feature = "binary")]
+use ::binary::{self, BinaryValue};
+use ::columns::{self, Column, Columns};
+use ::data::{SampledData, SampledData0, SchemaChanged};
 use ::parser::PseudoFileParser;
+use ::procfs::cpuinfo::{CpuTopology, ThreadLocation};
 use ::splitter::{SplitColumns, SplitLinesBySpace};
+use ::streaming::{StreamingItem, StreamingIterator};
 use chrono::{DateTime, TimeZone, Utc};
+use self::cpu::SampledThread;
+#[cfg(feature = "binary")]
+use std::io::Read;
+use std::io::Write;
+use std::mem;
 use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 
 // Implement a sampler for /proc/meminfo
-define_sampler!{ Sampler : "/proc/stat" => Parser => Data }
+define_sampler!{ Sampler : "/proc/stat" => Parser => RecordStream => Data }
+//
+// The sampler can also be told to only keep some records, and report on the
+// overall health of a capture
+impl Sampler {
+    /// Only store the records of /proc/stat which have one of the listed kinds,
+    /// discarding the others, in order to reduce memory usage when only a few
+    /// metrics are of interest (see Data::keep_records())
+    ///
+    /// This must be called before any sample is acquired.
+    ///
+    pub fn with_record_filter(mut self, kinds: &[RecordKind]) -> Self {
+        assert_eq!(self.samples.len(), 0,
+                   "Records must be filtered before sampling starts");
+        self.samples.keep_records(kinds);
+        self
+    }
+
+    /// Summarize the events which may make the samples acquired so far hard
+    /// to analyze, such as reboots or sampling hiccups (see HealthReport)
+    pub fn health_report(&self) -> HealthReport {
+        HealthReport {
+            reboots: self.samples.reset_indices().len(),
+            schema_changes: self.schema_change_count(),
+            non_monotonic_intervals:
+                self.samples.non_monotonic_intervals().len(),
+            interval_outliers:
+                ::analysis::interval_outliers(&self.timestamps).len(),
+        }
+    }
+}
+
+
+/// Summary of the anomalies found in a capture of /proc/stat
+///
+/// None of these anomalies makes a capture unusable, but each of them can
+/// skew an analysis which does not take it into account.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct HealthReport {
+    /// Number of system reboots, which reset every counter of /proc/stat (see
+    /// Data::reset_indices())
+    pub reboots: usize,
+
+    /// Number of samples which could not be stored, because the structure of
+    /// /proc/stat changed (see Sampler::schema_change_count())
+    pub schema_changes: usize,
+
+    /// Number of sampling intervals during which a counter went backwards,
+    /// without a reboot to explain it (see Data::non_monotonic_intervals())
+    pub non_monotonic_intervals: usize,
+
+    /// Number of sampling intervals which were abnormally short or long (see
+    /// analysis::interval_outliers())
+    pub interval_outliers: usize,
+}
+//
+impl HealthReport {
+    /// Truth that no anomaly was found
+    pub fn is_healthy(&self) -> bool {
+        *self == HealthReport {
+            reboots: 0,
+            schema_changes: 0,
+            non_monotonic_intervals: 0,
+            interval_outliers: 0,
+        }
+    }
+}
+
+
+/// Measure the rate at which the CPU timers of /proc/stat advance, in ticks
+/// per second, by sampling them before and after sleeping for some interval
+///
+/// This should match cpu::ticks_per_second(), which is what this crate uses to
+/// convert CPU ticks into durations. Misconfigured containers may report a
+/// wrong clock tick rate, however, in which case all CPU durations from this
+/// crate will be off. The returned calibration tells whether the measurement
+/// and the reported tick rate differ by more than a factor of 2.
+///
+/// The measurement becomes more precise as the interval gets longer. An
+/// interval of a few hundred milliseconds should be enough to detect gross
+/// misconfigurations.
+///
+pub fn calibrate_ticks_per_second(
+    interval: Duration
+) -> io::Result<TickCalibration> {
+    // Sample /proc/stat before and after the calibration interval
+    let mut sampler = Sampler::new()?;
+    sampler.sample()?;
+    thread::sleep(interval);
+    sampler.sample()?;
+
+    // Compute how fast the CPU timers advanced, on average per CPU thread
+    let elapsed = sampler.timestamps()[1] - sampler.timestamps()[0];
+    let elapsed_secs = elapsed.as_secs() as f64
+                       + elapsed.subsec_nanos() as f64 * 1e-9;
+    let data = sampler.samples();
+    let all_cpus = data.all_cpus().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData,
+                       "No global CPU statistics in /proc/stat")
+    })?;
+    let num_threads = data.thread_count().max(1) as f64;
+    let ticks = all_cpus.corrected_total_ticks_between(0, 1) as f64;
+    let measured = ticks / num_threads / elapsed_secs;
+    Ok(TickCalibration { measured, reported: cpu::ticks_per_second() })
+}
+
+
+/// Outcome of a clock tick rate calibration
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TickCalibration {
+    /// Rate at which the CPU timers of /proc/stat were observed to advance,
+    /// in ticks per second
+    measured: f64,
+
+    /// Clock tick rate reported by sysconf(_SC_CLK_TCK)
+    reported: u64,
+}
+//
+impl TickCalibration {
+    /// Observed CPU timer advancement rate, in ticks per second
+    pub fn measured(&self) -> f64 { self.measured }
+
+    /// Clock tick rate reported by the system, in ticks per second
+    pub fn reported(&self) -> u64 { self.reported }
+
+    /// Ratio of the measured tick rate to the reported one
+    pub fn ratio(&self) -> f64 { self.measured / self.reported as f64 }
+
+    /// Truth that the measured and reported tick rates differ by more than a
+    /// factor of 2, which suggests a misconfigured system
+    pub fn is_mismatched(&self) -> bool {
+        let ratio = self.ratio();
+        ratio < 0.5 || ratio > 2.
+    }
+}
 
 
 /// Incremental parser for /proc/stat
@@ -23,12 +170,11 @@ impl PseudoFileParser for Parser {
     /// perform quick schema validation, just to maximize the odds that failure,
     /// if any, will occur at initialization time rather than run time.
     fn new(initial_contents: &str) -> Self {
-        let mut stream = RecordStream::new(initial_contents);
-        while let Some(record) = stream.next() {
+        RecordStream::new(initial_contents).for_each(|record| {
             if let RecordKind::Unsupported(header) = record.kind() {
                 debug_assert!(false, "Unsupported record header: {}", header);
             }
-        }
+        });
         Self {}
     }
 }
@@ -53,6 +199,18 @@ pub struct RecordStream<'a> {
     file_lines: SplitLinesBySpace<'a>,
 }
 //
+impl<'a, 'b> StreamingItem<'b> for RecordStream<'a> {
+    /// We produce records from /proc/stat
+    type Item = Record<'a, 'b>;
+}
+//
+impl<'a> StreamingIterator for RecordStream<'a> {
+    /// Extract the next record from /proc/stat
+    fn next<'b>(&'b mut self) -> Option<Record<'a, 'b>> {
+        RecordStream::next(self)
+    }
+}
+//
 impl<'a> RecordStream<'a> {
     /// Extract the next record from /proc/stat
     pub fn next<'b>(&'b mut self) -> Option<Record<'a, 'b>>
@@ -266,12 +424,12 @@ impl<'a, 'b> Record<'a, 'b> {
     }
 
     /// Parse the current record as a process fork counter
-    fn parse_process_forks(mut self) -> u32 {
+    fn parse_process_forks(mut self) -> u64 {
         // In debug mode, check that we don't misinterpret things
         debug_assert_eq!(self.kind(), RecordKind::ProcessForks);
 
-        // Spawning four billion processes seems somewhat unusual for the uptime
-        // of a typical UNIX machine, so I think we can stick with u32 here
+        // A server which has been up for months can spawn more than four
+        // billion processes, so we must use a 64-bit counter here
         let result = self.data_columns
                          .next().expect("Expected process fork counter")
                          .parse().expect("Failed to parse fork counter");
@@ -365,6 +523,74 @@ pub enum RecordKind {
     ///
     Unsupported(String),
 }
+//
+impl RecordKind {
+    /// INTERNAL: Header of the /proc/stat records of this kind
+    fn header(&self) -> String {
+        match *self {
+            RecordKind::CPUTotal => "cpu".to_owned(),
+            RecordKind::CPUThread(thread_id) => format!("cpu{}", thread_id),
+            RecordKind::PagingTotal => "page".to_owned(),
+            RecordKind::PagingSwap => "swap".to_owned(),
+            RecordKind::InterruptsHW => "intr".to_owned(),
+            RecordKind::ContextSwitches => "ctxt".to_owned(),
+            RecordKind::BootTime => "btime".to_owned(),
+            RecordKind::ProcessForks => "processes".to_owned(),
+            RecordKind::ProcessesRunnable => "procs_running".to_owned(),
+            RecordKind::ProcessesBlocked => "procs_blocked".to_owned(),
+            RecordKind::InterruptsSW => "softirq".to_owned(),
+            RecordKind::Unsupported(ref header) => header.clone(),
+        }
+    }
+}
+//
+/// Binary serialization of record kinds: a tag byte, followed by the thread ID
+/// for CPU thread records or the header for unsupported records
+#[cfg(feature = "binary")]
+impl BinaryValue for RecordKind {
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        match *self {
+            RecordKind::CPUTotal => 0u8.write_to(writer),
+            RecordKind::CPUThread(thread_id) => {
+                1u8.write_to(writer)?;
+                thread_id.write_to(writer)
+            },
+            RecordKind::PagingTotal => 2u8.write_to(writer),
+            RecordKind::PagingSwap => 3u8.write_to(writer),
+            RecordKind::InterruptsHW => 4u8.write_to(writer),
+            RecordKind::ContextSwitches => 5u8.write_to(writer),
+            RecordKind::BootTime => 6u8.write_to(writer),
+            RecordKind::ProcessForks => 7u8.write_to(writer),
+            RecordKind::ProcessesRunnable => 8u8.write_to(writer),
+            RecordKind::ProcessesBlocked => 9u8.write_to(writer),
+            RecordKind::InterruptsSW => 10u8.write_to(writer),
+            RecordKind::Unsupported(ref header) => {
+                11u8.write_to(writer)?;
+                header.write_to(writer)
+            },
+        }
+    }
+
+    fn read_from<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(
+            match u8::read_from(reader)? {
+                0 => RecordKind::CPUTotal,
+                1 => RecordKind::CPUThread(u16::read_from(reader)?),
+                2 => RecordKind::PagingTotal,
+                3 => RecordKind::PagingSwap,
+                4 => RecordKind::InterruptsHW,
+                5 => RecordKind::ContextSwitches,
+                6 => RecordKind::BootTime,
+                7 => RecordKind::ProcessForks,
+                8 => RecordKind::ProcessesRunnable,
+                9 => RecordKind::ProcessesBlocked,
+                10 => RecordKind::InterruptsSW,
+                11 => RecordKind::Unsupported(String::read_from(reader)?),
+                _ => return Err(binary::invalid_data("Invalid record kind")),
+            }
+        )
+    }
+}
 
 
 /// INTERNAL: Helpful wrapper for pushing data into optional containers that we
@@ -382,11 +608,20 @@ macro_rules! force_push {
     ($store:expr, $record_fields:expr) => {
         $store.as_mut()
               .expect("Attempted to push into a nonexistent container")
-              .push($record_fields);
+              .push($record_fields)
     };
 }
 
 
+/// Largest change of the boot time, in seconds, which is not considered to be
+/// a system reboot
+///
+/// The kernel computes the boot time from the current wall clock time and the
+/// uptime, so it jitters by about a second when NTP adjusts the system clock.
+///
+const BOOT_TIME_TOLERANCE_SECS: i64 = 2;
+
+
 /// Data samples from /proc/stat, in structure-of-array layout
 ///
 /// Courtesy of Linux's total lack of promises regarding the variability of
@@ -395,7 +630,7 @@ macro_rules! force_push {
 /// considered optional at this point...
 ///
 #[derive(Clone, Debug, PartialEq)]
-struct Data {
+pub struct Data {
     /// Total CPU usage stats, aggregated across all hardware threads
     all_cpus: Option<cpu::Data>,
 
@@ -404,7 +639,9 @@ struct Data {
     /// An empty Vec here has the same meaning as a None in other entries: the
     /// per-thread breakdown of CPU usage was not provided by the kernel.
     ///
-    each_thread: Vec<cpu::Data>,
+    /// Parked CPU threads are stored sparsely, see cpu::SampledThread.
+    ///
+    each_thread: Vec<SampledThread>,
 
     /// Number of pages that the system paged in and out from disk, overall...
     paging: Option<paging::Data>,
@@ -421,11 +658,19 @@ struct Data {
     /// Number of context switches that the system underwent since boot
     context_switches: Option<Vec<u64>>,
 
-    /// Boot time (only collected once)
+    /// Boot time (collected again only when the system reboots)
     boot_time: Option<DateTime<Utc>>,
 
+    /// Indices of the samples which were taken after a system reboot, as
+    /// detected by a change of boot time. Counters are reset by a reboot, so
+    /// their deltas must not be computed across these sample boundaries.
+    reset_indices: Vec<usize>,
+
     /// Number of process forks that occurred since boot
-    process_forks: Option<Vec<u32>>,
+    process_forks: Option<Vec<u64>>,
+
+    /// INTERNAL: Overflow unwinding state of the process fork counter
+    fork_unwinder: CounterUnwinder,
 
     /// Number of processes in a runnable state (since Linux 2.5.45)
     runnable_processes: Option<Vec<u16>>,
@@ -455,14 +700,17 @@ impl SampledData for Data {
     fn len(&self) -> usize {
         let mut opt_len = None;
         Self::update_len(&mut opt_len, &self.all_cpus);
-        debug_assert!(
-            self.each_thread
-                .iter()
-                .all(|cpu| {
-                    opt_len.expect("each_thread should come with all_cpus") ==
-                        cpu.len()
-                })
-        );
+        for cpu in self.each_thread.iter() {
+            // Per-thread statistics normally come with global ones, but some
+            // emulated procfs implementations only provide the former
+            let cpu_len = cpu.len();
+            match opt_len {
+                Some(len) => debug_assert_eq!(len, cpu_len,
+                                              "Inconsistent amounts of \
+                                               stored samples"),
+                None => opt_len = Some(cpu_len),
+            }
+        }
         Self::update_len(&mut opt_len, &self.paging);
         Self::update_len(&mut opt_len, &self.swapping);
         Self::update_len(&mut opt_len, &self.interrupts);
@@ -473,206 +721,1838 @@ impl SampledData for Data {
         Self::update_len(&mut opt_len, &self.softirqs);
         opt_len.unwrap_or(0)
     }
+
+    /// Drop the oldest samples from all entries of the data store
+    fn drop_oldest(&mut self, count: usize) {
+        Self::drop_oldest_opt(&mut self.all_cpus, count);
+        for cpu in self.each_thread.iter_mut() {
+            cpu.drop_oldest(count);
+        }
+        Self::drop_oldest_opt(&mut self.paging, count);
+        Self::drop_oldest_opt(&mut self.swapping, count);
+        Self::drop_oldest_opt(&mut self.interrupts, count);
+        Self::drop_oldest_opt(&mut self.context_switches, count);
+        Self::drop_oldest_opt(&mut self.process_forks, count);
+        Self::drop_oldest_opt(&mut self.runnable_processes, count);
+        Self::drop_oldest_opt(&mut self.blocked_processes, count);
+        Self::drop_oldest_opt(&mut self.softirqs, count);
+        self.reset_indices.retain(|&idx| idx >= count);
+        for idx in self.reset_indices.iter_mut() {
+            *idx -= count;
+        }
+    }
+
+    /// Tell the length of the shortest time series in the data store
+    fn min_len(&self) -> usize {
+        let mut min_len = None;
+        Self::update_min_len(&mut min_len, &self.all_cpus);
+        for cpu in self.each_thread.iter() {
+            let cpu_len = cpu.min_len();
+            min_len = Some(min_len.map_or(cpu_len, |min| min.min(cpu_len)));
+        }
+        Self::update_min_len(&mut min_len, &self.paging);
+        Self::update_min_len(&mut min_len, &self.swapping);
+        Self::update_min_len(&mut min_len, &self.interrupts);
+        Self::update_min_len(&mut min_len, &self.context_switches);
+        Self::update_min_len(&mut min_len, &self.process_forks);
+        Self::update_min_len(&mut min_len, &self.runnable_processes);
+        Self::update_min_len(&mut min_len, &self.blocked_processes);
+        Self::update_min_len(&mut min_len, &self.softirqs);
+        min_len.unwrap_or(0)
+    }
+
+    /// Drop the newest samples from all entries of the data store
+    fn truncate(&mut self, length: usize) {
+        Self::truncate_opt(&mut self.all_cpus, length);
+        for cpu in self.each_thread.iter_mut() {
+            cpu.truncate(length);
+        }
+        Self::truncate_opt(&mut self.paging, length);
+        Self::truncate_opt(&mut self.swapping, length);
+        Self::truncate_opt(&mut self.interrupts, length);
+        Self::truncate_opt(&mut self.context_switches, length);
+        Self::truncate_opt(&mut self.process_forks, length);
+        Self::truncate_opt(&mut self.runnable_processes, length);
+        Self::truncate_opt(&mut self.blocked_processes, length);
+        Self::truncate_opt(&mut self.softirqs, length);
+        self.reset_indices.retain(|&idx| idx < length);
+    }
 }
 //
 // TODO: Implement SampledData1 once that is usable in stable Rust
 impl Data {
-    /// Create a new statistical data store, using a first sample to know the
-    /// structure of /proc/stat on this system
-    fn new(mut stream: RecordStream) -> Self {
-        // Our statistical data store will eventually go there
-        let mut data = Self {
-            all_cpus: None,
-            each_thread: Vec::new(),
-            paging: None,
-            swapping: None,
-            interrupts: None,
-            context_switches: None,
-            boot_time: None,
-            process_forks: None,
-            runnable_processes: None,
-            blocked_processes: None,
-            softirqs: None,
-            line_target: Vec::new(),
-        };
+    /// Total CPU usage stats, aggregated across all hardware threads
+    pub fn all_cpus(&self) -> Option<&cpu::Data> {
+        self.all_cpus.as_ref()
+    }
 
-        // For each initial record of /proc/stat...
-        while let Some(record) = stream.next() {
-            // ...and check the header
-            let record_kind = record.kind();
-            match record_kind {
-                // Statistics on all CPUs
-                RecordKind::CPUTotal => {
-                    data.all_cpus = Some(
-                        cpu::Data::new(record.parse_cpu())
-                    );
+    /// Per-CPU usage statistics, featuring one entry per hardware CPU thread
+    /// (empty if the kernel does not provide a per-thread breakdown)
+    ///
+    /// CPU threads whose statistics did not change since sampling started,
+    /// as happens when they are parked, are stored sparsely. Individual
+    /// samples and intervals can be queried without expanding them, see
+    /// cpu::SampledThread. Its dense() method provides the full time series.
+    ///
+    pub fn each_thread(&self) -> &[cpu::SampledThread] {
+        &self.each_thread[..]
+    }
+
+    /// Number of hardware CPU threads in the per-thread statistics (zero if
+    /// the kernel does not provide a per-thread breakdown)
+    pub fn thread_count(&self) -> usize {
+        self.each_thread.len()
+    }
+
+    /// Number of CPU threads whose statistics did not change since sampling
+    /// started, and which are thus stored sparsely
+    pub fn constant_thread_count(&self) -> usize {
+        self.each_thread.iter().filter(|thread| thread.is_constant()).count()
+    }
+
+    /// Aggregate CPU statistics, reconstructed by summing the statistics of
+    /// each CPU thread, for procfs implementations which lack the global "cpu"
+    /// line but have per-thread lines (e.g. some emulated environments)
+    ///
+    /// Optional CPU timers are only present in the result if every thread
+    /// provides them. Returns None if the global CPU statistics are provided,
+    /// in which case all_cpus() should be used, or if there is no per-thread
+    /// breakdown to sum.
+    ///
+    pub fn reconstruct_aggregate(&self) -> Option<cpu::Data> {
+        if self.all_cpus.is_some() {
+            return None;
+        }
+        let threads = self.each_thread.iter()
+                                      .map(|thread| thread.dense())
+                                      .collect::<Vec<_>>();
+        cpu::Data::sum(&threads)
+    }
+
+    /// Fraction of CPU time which was spent doing work on each physical
+    /// package (aka socket) between two samples, indexed by package id
+    ///
+    /// This combines the per-thread statistics with the CPU topology from
+    /// /proc/cpuinfo, which should be loaded via CpuTopology::load(). Busy and
+    /// idle ticks are summed over the threads of each package before being
+    /// divided, following the conventions of cpu::Data::busy_fraction(), and
+    /// packages for which no CPU time was accounted are reported as idle. The
+    /// result is empty if the kernel does not provide a per-thread breakdown.
+    ///
+    pub fn package_utilization_between(&self,
+                                       older: usize,
+                                       newer: usize,
+                                       topology: &CpuTopology) -> Vec<f64> {
+        if self.each_thread.is_empty() {
+            return Vec::new();
+        }
+        let mut ticks = vec![(0, 0); topology.package_count()];
+        for (location, work, idle) in self.located_ticks(older,
+                                                         newer,
+                                                         topology) {
+            let package_ticks = &mut ticks[location.physical_id as usize];
+            package_ticks.0 += work;
+            package_ticks.1 += idle;
+        }
+        ticks.into_iter()
+             .map(|(work, idle)| Self::work_fraction(work, idle))
+             .collect()
+    }
+
+    /// Fraction of CPU time which was spent doing work on each physical core
+    /// between two samples, with cores identified by (physical_id, core_id)
+    /// pairs in ascending order
+    ///
+    /// On hyperthreaded CPUs, this tells how busy each physical core is as a
+    /// whole. See package_utilization_between() for the conventions.
+    ///
+    pub fn core_utilization_between(&self,
+                                    older: usize,
+                                    newer: usize,
+                                    topology: &CpuTopology)
+        -> Vec<((u32, u32), f64)>
+    {
+        if self.each_thread.is_empty() {
+            return Vec::new();
+        }
+        let cores = topology.cores();
+        let mut ticks = vec![(0, 0); cores.len()];
+        for (location, work, idle) in self.located_ticks(older,
+                                                         newer,
+                                                         topology) {
+            let core = (location.physical_id, location.core_id);
+            let core_idx = cores.binary_search(&core)
+                                .expect("Cores should come from the topology");
+            ticks[core_idx].0 += work;
+            ticks[core_idx].1 += idle;
+        }
+        cores.into_iter()
+             .zip(ticks.into_iter())
+             .map(|(core, (work, idle))| (core, Self::work_fraction(work,
+                                                                    idle)))
+             .collect()
+    }
+
+    /// Fraction of a cgroup's CPU quota which was used between two samples
+    ///
+    /// Inside of a container, /proc/stat reports the CPU usage of the whole
+    /// host, which is misleading when the container may only use a few CPUs'
+    /// worth of CPU time. This rescales the host-wide busy fraction from the
+    /// number of host CPU threads to cpu_limit, which should come from
+    /// sysfs::cgroup::effective_cpu_count(). The result may exceed 1.0 if the
+    /// host is busy outside of the container. Returns None if the kernel does
+    /// not provide both the global and per-thread CPU statistics, or if no
+    /// CPU time was accounted between the two samples.
+    ///
+    pub fn quota_utilization_between(&self,
+                                     older: usize,
+                                     newer: usize,
+                                     cpu_limit: f64) -> Option<f64> {
+        assert!(cpu_limit > 0., "CPU limits must be positive");
+        if self.each_thread.is_empty() {
+            return None;
+        }
+        let busy_fraction = self.all_cpus.as_ref()?
+                                         .busy_fraction(older, newer)?;
+        Some(busy_fraction * self.each_thread.len() as f64 / cpu_limit)
+    }
+
+    /// Fraction of each CPU thread's maximal compute capacity which was used
+    /// between two samples, accounting for CPU frequency scaling
+    ///
+    /// On power-managed machines, a CPU which is 100% busy at a quarter of its
+    /// maximal frequency only does a quarter of the work that it could do. So
+    /// this weights the busy fraction of each CPU thread (as defined by
+    /// cpu::Data::busy_fraction) by the ratio of its frequency over the
+    /// interval to its maximal frequency. Both frequencies are given per CPU
+    /// thread, in the same unit, and may come e.g. from the "cpu MHz" record of
+    /// /proc/cpuinfo or from cpufreq's scaling_cur_freq and cpuinfo_max_freq.
+    ///
+    /// CPU threads for which no CPU time was accounted, or whose maximal
+    /// frequency is unknown (zero), are reported as idle. If the maximal
+    /// frequency does not include turbo frequencies, the result may exceed 1.
+    /// The result is empty if the kernel does not provide per-thread data.
+    ///
+    pub fn frequency_weighted_utilization_between(&self,
+                                                  older: usize,
+                                                  newer: usize,
+                                                  frequencies: &[f64],
+                                                  max_frequencies: &[f64])
+        -> Vec<f64>
+    {
+        assert_eq!(frequencies.len(), self.each_thread.len(),
+                   "There should be one frequency per CPU thread");
+        assert_eq!(max_frequencies.len(), self.each_thread.len(),
+                   "There should be one maximal frequency per CPU thread");
+        self.each_thread.iter()
+                        .zip(frequencies.iter().zip(max_frequencies.iter()))
+                        .map(|(thread, (&frequency, &max_frequency))| {
+                            if max_frequency <= 0. {
+                                return 0.;
+                            }
+                            let busy_fraction =
+                                thread.busy_fraction(older, newer)
+                                      .unwrap_or(0.);
+                            busy_fraction * frequency / max_frequency
+                        })
+                        .collect()
+    }
+
+    /// INTERNAL: Clock ticks spent doing work and idle between two samples on
+    ///           each CPU thread that is known to the topology, along with the
+    ///           location of that thread
+    fn located_ticks(&self,
+                     older: usize,
+                     newer: usize,
+                     topology: &CpuTopology)
+        -> Vec<(ThreadLocation, u128, u128)>
+    {
+        self.each_thread.iter().enumerate().filter_map(|(idx, thread)| {
+            topology.thread(idx as u32).map(|&location| {
+                let (work, idle) = thread.work_and_idle_ticks(older, newer);
+                (location, work, idle)
+            })
+        }).collect()
+    }
+
+    /// INTERNAL: Fraction of some work and idle ticks that were spent working,
+    ///           considering that no accounted time means idle
+    fn work_fraction(work_ticks: u128, idle_ticks: u128) -> f64 {
+        let total_ticks = work_ticks + idle_ticks;
+        if total_ticks > 0 {
+            work_ticks as f64 / total_ticks as f64
+        } else {
+            0.
+        }
+    }
+
+    /// Number of hardware CPU threads that were online, for each sample
+    ///
+    /// CPU hotplug is not supported yet (see push()), so this is currently a
+    /// constant series equal to the length of each_thread(). It is provided so
+    /// that client code can already account for the number of online CPUs.
+    ///
+    pub fn online_cpu_count(&self) -> Vec<u16> {
+        vec![self.each_thread.len() as u16; self.len()]
+    }
+
+    /// Idle time summed across all CPU threads, for each sample
+    ///
+    /// This is computed from the per-thread CPU statistics, or from the global
+    /// ones if the kernel does not provide a per-thread breakdown. It should
+    /// approximately match the idle time from /proc/uptime. The result is empty
+    /// if /proc/stat provides no CPU statistics at all.
+    ///
+    pub fn total_idle_time(&self) -> Vec<Duration> {
+        if self.each_thread.is_empty() {
+            return self.all_cpus.as_ref()
+                                .map(|cpu| cpu.idle_time())
+                                .unwrap_or_default();
+        }
+        (0..self.len()).map(|sample| {
+            let idle_ticks = self.each_thread.iter().map(|cpu| {
+                cpu.stored_samples().idle_ticks()[cpu.stored_index(sample)]
+            }).sum();
+            cpu::ticks_to_duration(idle_ticks)
+        }).collect()
+    }
+
+    /// Spread of the load across CPU threads between two samples, measured as
+    /// the difference between the highest and lowest busy fraction of any CPU
+    /// thread (see cpu::Data::busy_fraction())
+    ///
+    /// 0.0 means that the load was perfectly balanced, and 1.0 means that some
+    /// CPU threads were fully busy while others were fully idle. CPU threads
+    /// for which no time was accounted, such as offline ones, are ignored.
+    /// Returns None if there are no per-thread statistics, or if no time was
+    /// accounted on any CPU thread between these samples.
+    ///
+    pub fn cpu_imbalance_between(&self,
+                                 older: usize,
+                                 newer: usize) -> Option<f64> {
+        let busy_fractions =
+            self.each_thread
+                .iter()
+                .filter_map(|cpu| cpu.busy_fraction(older, newer));
+        busy_fractions.fold(None, |min_max, busy| {
+            Some(match min_max {
+                Some((min, max)) => (busy.min(min), busy.max(max)),
+                None => (busy, busy),
+            })
+        }).map(|(min, max)| max - min)
+    }
+
+    /// Render the CPU time spent in each state over the whole capture as
+    /// folded stacks, which flamegraph.pl turns into a flame graph
+    ///
+    /// Each line looks like "cpu;core0;user 42", where the count is the exact
+    /// number of clock ticks which CPU thread 0 spent in user mode between the
+    /// first and the last sample. Sampling intervals during which the system
+    /// rebooted are left out, as in cpu::Data::busy_ticks_total(). If there
+    /// are no per-thread statistics, the aggregate over all CPUs is used
+    /// instead, as "cpu;all;...". Lines are emitted in a stable order (by CPU
+    /// thread, then in /proc/stat column order), and states in which no time
+    /// was spent are omitted. Guest time is left out, as the kernel also
+    /// accounts it as user time.
+    ///
+    pub fn write_folded<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        // Constant CPU threads only store one sample, and thus account no time
+        let cpus: Vec<(String, &cpu::Data)> = if self.has_per_cpu() {
+            self.each_thread.iter().enumerate().map(|(id, cpu)| {
+                (format!("core{}", id), cpu.stored_samples())
+            }).collect()
+        } else {
+            self.all_cpus.iter().map(|cpu| ("all".to_owned(), cpu)).collect()
+        };
+        for (name, cpu) in cpus {
+            let states = [("user", Some(cpu.user_ticks())),
+                          ("nice", Some(cpu.nice_ticks())),
+                          ("system", Some(cpu.system_ticks())),
+                          ("idle", Some(cpu.idle_ticks())),
+                          ("iowait", cpu.io_wait_ticks()),
+                          ("irq", cpu.irq_ticks()),
+                          ("softirq", cpu.softirq_ticks()),
+                          ("steal", cpu.stolen_ticks())];
+            for &(state, ticks) in &states {
+                if let Some(ticks) = ticks {
+                    let total = cpu.capture_ticks(ticks);
+                    if total > 0 {
+                        writeln!(out, "cpu;{};{} {}", name, state, total)?;
+                    }
                 }
+            }
+        }
+        Ok(())
+    }
 
-                // Statistics on a specific CPU thread (should be enumerated in
-                // order, from thread 0 to thread Nt-1)
-                RecordKind::CPUThread(thread_id) => {
-                    assert_eq!(thread_id, data.each_thread.len() as u16,
-                               "Unexpected CPU thread ordering");
-                    data.each_thread.push(
-                        cpu::Data::new(record.parse_cpu())
-                    );
-                },
+    /// Number of pages that the system paged in and out from disk
+    pub fn paging(&self) -> Option<&paging::Data> {
+        self.paging.as_ref()
+    }
 
-                // Paging statistics
-                RecordKind::PagingTotal => {
-                    data.paging = Some(
-                        paging::Data::new(record.parse_paging())
-                    );
-                },
+    /// Number of pages that the system swapped in and out from disk
+    pub fn swapping(&self) -> Option<&paging::Data> {
+        self.swapping.as_ref()
+    }
 
-                // Swapping statistics
-                RecordKind::PagingSwap => {
-                    data.swapping = Some(
-                        paging::Data::new(record.parse_paging())
-                    );
-                },
+    /// Statistics on the number of hardware interrupts that were serviced
+    pub fn interrupts(&self) -> Option<&interrupts::Data> {
+        self.interrupts.as_ref()
+    }
 
-                // Hardware interrupt statistics
-                RecordKind::InterruptsHW => {
-                    data.interrupts = Some(
-                        interrupts::Data::new(record.parse_interrupts())
-                    );
-                },
+    /// Number of context switches that the system underwent since boot
+    pub fn context_switches(&self) -> Option<&[u64]> {
+        self.context_switches.as_ref().map(|vec| &vec[..])
+    }
 
-                // Context switch statistics
-                RecordKind::ContextSwitches => {
-                    data.context_switches = Some(Vec::new());
-                },
+    /// Boot time of the system
+    pub fn boot_time(&self) -> Option<DateTime<Utc>> {
+        self.boot_time
+    }
 
-                // Boot time
-                RecordKind::BootTime => {
-                    data.boot_time = Some(record.parse_boot_time());
-                },
+    /// Boot time of the system, as a standard library timestamp
+    ///
+    /// This is the same information as boot_time(), for clients which would
+    /// rather not have chrono types in their interface. Returns None if the
+    /// boot time is unknown, or if it lies before the Unix epoch, which is
+    /// not a valid boot time.
+    ///
+    pub fn boot_time_system(&self) -> Option<SystemTime> {
+        let secs = self.boot_time?.timestamp();
+        if secs < 0 {
+            return None;
+        }
+        Some(UNIX_EPOCH + Duration::from_secs(secs as u64))
+    }
 
-                // Number of process forks since boot
-                RecordKind::ProcessForks => {
-                    data.process_forks = Some(Vec::new());
-                },
+    /// Number of process forks that occurred since boot
+    pub fn process_forks(&self) -> Option<&[u64]> {
+        self.process_forks.as_ref().map(|vec| &vec[..])
+    }
 
-                // Number of processes in the runnable state
-                RecordKind::ProcessesRunnable => {
-                    data.runnable_processes = Some(Vec::new());
-                },
+    /// Number of processes in a runnable state
+    pub fn runnable_processes(&self) -> Option<&[u16]> {
+        self.runnable_processes.as_ref().map(|vec| &vec[..])
+    }
 
-                // Number of processes waiting for I/O
-                RecordKind::ProcessesBlocked => {
-                    data.blocked_processes = Some(Vec::new());
-                },
+    /// Number of processes blocked waiting for I/O
+    pub fn blocked_processes(&self) -> Option<&[u16]> {
+        self.blocked_processes.as_ref().map(|vec| &vec[..])
+    }
 
-                // Softirq statistics
-                RecordKind::InterruptsSW => {
-                    data.softirqs = Some(
-                        interrupts::Data::new(record.parse_interrupts())
-                    );
-                },
+    /// Statistics on the number of softirqs that were serviced
+    pub fn softirqs(&self) -> Option<&interrupts::Data> {
+        self.softirqs.as_ref()
+    }
+
+    /// Truth that aggregated CPU usage statistics are provided by the kernel
+    pub fn has_all_cpus(&self) -> bool { self.all_cpus.is_some() }
+
+    /// Truth that per-thread CPU usage statistics are provided by the kernel
+    pub fn has_per_cpu(&self) -> bool { !self.each_thread.is_empty() }
+
+    /// Truth that paging statistics are provided by the kernel
+    pub fn has_paging(&self) -> bool { self.paging.is_some() }
+
+    /// Truth that swapping statistics are provided by the kernel
+    pub fn has_swapping(&self) -> bool { self.swapping.is_some() }
+
+    /// Truth that hardware interrupt statistics are provided by the kernel
+    pub fn has_interrupts(&self) -> bool { self.interrupts.is_some() }
+
+    /// Truth that the context switch counter is provided by the kernel
+    pub fn has_context_switches(&self) -> bool {
+        self.context_switches.is_some()
+    }
+
+    /// Truth that the boot time is provided by the kernel
+    pub fn has_boot_time(&self) -> bool { self.boot_time.is_some() }
+
+    /// Truth that the process fork counter is provided by the kernel
+    pub fn has_process_forks(&self) -> bool { self.process_forks.is_some() }
+
+    /// Truth that the runnable process count is provided by the kernel
+    pub fn has_runnable_processes(&self) -> bool {
+        self.runnable_processes.is_some()
+    }
+
+    /// Truth that the blocked process count is provided by the kernel
+    pub fn has_blocked_processes(&self) -> bool {
+        self.blocked_processes.is_some()
+    }
+
+    /// Truth that softirq statistics are provided by the kernel
+    pub fn has_softirqs(&self) -> bool { self.softirqs.is_some() }
+
+    /// Number of CPU timers (out of the 10 known ones) which are provided by
+    /// the kernel, or 0 if no CPU usage statistics are provided at all
+    pub fn cpu_timer_count(&self) -> u8 {
+        match self.all_cpus {
+            Some(ref cpu) => cpu.timer_count(),
+            None => self.each_thread.first()
+                                    .map_or(0, |cpu| cpu.timer_count()),
+        }
+    }
+
+    /// Truth that two data stores were sampled from files with the same
+    /// schema, regardless of the sampled values
+    ///
+    /// The schema covers the layout of /proc/stat (which records are present,
+    /// and in which order), the number of CPU threads and CPU timers, and the
+    /// number of enumerated interrupt and softirq sources. This is intended
+    /// for tests of recorded captures, which must tell a change of the parsed
+    /// schema apart from a mere change of the values (see value_eq()).
+    ///
+    pub fn structural_eq(&self, other: &Self) -> bool {
+        let timer_counts = |data: &Self| -> Vec<u8> {
+            data.all_cpus.iter()
+                         .map(|cpu| cpu.timer_count())
+                         .chain(data.each_thread
+                                    .iter()
+                                    .map(|cpu| cpu.timer_count()))
+                         .collect()
+        };
+        let source_count = |irqs: &Option<interrupts::Data>| {
+            irqs.as_ref().map(|irqs| irqs.source_count())
+        };
+        let presence = |data: &Self| {
+            [data.has_all_cpus(), data.has_paging(), data.has_swapping(),
+             data.has_interrupts(), data.has_context_switches(),
+             data.has_boot_time(), data.has_process_forks(),
+             data.has_runnable_processes(), data.has_blocked_processes(),
+             data.has_softirqs()]
+        };
+        self.line_target == other.line_target
+            && presence(self) == presence(other)
+            && self.each_thread.len() == other.each_thread.len()
+            && timer_counts(self) == timer_counts(other)
+            && source_count(&self.interrupts) == source_count(&other.interrupts)
+            && source_count(&self.softirqs) == source_count(&other.softirqs)
+    }
+
+    /// Truth that two data stores have the same schema and the same sampled
+    /// values (see structural_eq())
+    ///
+    /// This is equivalent to the == operator, and is only provided so that
+    /// tests can spell out which kind of equality they are checking.
+    ///
+    pub fn value_eq(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    /// Indices of the samples which were acquired after a system reboot
+    ///
+    /// A reboot is detected by a change of the boot time between two samples.
+    /// All counters of /proc/stat are reset by a reboot, so deltas which span
+    /// one of these sample boundaries only measure post-reboot activity.
+    ///
+    pub fn reset_indices(&self) -> Vec<usize> {
+        self.reset_indices.clone()
+    }
+
+    /// Increase of a counter from /proc/stat (such as context_switches()),
+    /// between two sample indices, accounting for system reboots
+    ///
+    /// If the system rebooted between these samples, the counter was reset in
+    /// the meantime, so the counter value from the newer sample is returned as
+    /// the best available estimate of the increase. If the counter went
+    /// backwards without a reboot (see non_monotonic_intervals()), there is
+    /// no meaningful increase to be measured, and zero is returned.
+    ///
+    pub fn delta(&self, counter: &[u64], older: usize, newer: usize) -> u64 {
+        assert!(older <= newer, "Samples should be ordered by age");
+        let rebooted = self.reset_indices
+                           .iter()
+                           .any(|&idx| idx > older && idx <= newer);
+        if rebooted {
+            counter[newer]
+        } else {
+            counter[newer].saturating_sub(counter[older])
+        }
+    }
+
+    /// Indices of the sampling intervals during which a counter of /proc/stat
+    /// went backwards, although the system did not reboot
+    ///
+    /// Interval N lies between samples N and N+1. The global CPU timers, the
+    /// interrupt and softirq totals, and the context switch and process fork
+    /// counters are checked. The I/O wait timer is left out, as the kernel
+    /// documents that it can legitimately go backwards.
+    ///
+    pub fn non_monotonic_intervals(&self) -> Vec<usize> {
+        // Collect the counters which should never go backwards
+        let mut counters: Vec<&[u64]> = Vec::new();
+        if let Some(ref cpu) = self.all_cpus {
+            counters.extend_from_slice(&[cpu.user_ticks(),
+                                         cpu.nice_ticks(),
+                                         cpu.system_ticks(),
+                                         cpu.idle_ticks()]);
+            counters.extend([cpu.irq_ticks(),
+                             cpu.softirq_ticks(),
+                             cpu.stolen_ticks(),
+                             cpu.guest_ticks(),
+                             cpu.guest_nice_ticks()].iter()
+                                                    .filter_map(|&t| t));
+        }
+        for irqs in self.interrupts.iter().chain(&self.softirqs) {
+            counters.push(irqs.total());
+        }
+        counters.extend(self.context_switches().into_iter()
+                            .chain(self.process_forks()));
+
+        // Report the intervals where one of them decreased without a reboot
+        (1..self.len()).filter(|newer| !self.reset_indices.contains(newer))
+                       .filter(|&newer| {
+                           counters.iter().any(|counter| {
+                               counter[newer] < counter[newer-1]
+                           })
+                       })
+                       .map(|newer| newer - 1)
+                       .collect()
+    }
+
+    /// Rate of change of a counter from /proc/stat, per second, between two
+    /// samples separated by a certain amount of wall clock time (see delta())
+    ///
+    /// Returns None if no time has elapsed between these samples.
+    ///
+    pub fn rate_between(&self,
+                        counter: &[u64],
+                        older: usize,
+                        newer: usize,
+                        elapsed: Duration) -> Option<f64> {
+        let secs = elapsed.as_secs() as f64
+                   + elapsed.subsec_nanos() as f64 * 1e-9;
+        if secs > 0.0 {
+            Some(self.delta(counter, older, newer) as f64 / secs)
+        } else {
+            None
+        }
+    }
+
+    /// Ratio of the amount of softirqs to the amount of hardware interrupts
+    /// that were serviced between two samples (see delta())
+    ///
+    /// A high ratio indicates heavy softirq load (e.g. network or timer
+    /// processing) relative to device interrupts. Returns None if either kind
+    /// of interrupt statistics is absent, or if no hardware interrupt was
+    /// serviced between these samples.
+    ///
+    pub fn softirq_to_hardirq_ratio_between(&self,
+                                            older: usize,
+                                            newer: usize) -> Option<f64> {
+        let (hardirqs, softirqs) = match (&self.interrupts, &self.softirqs) {
+            (&Some(ref hardirqs), &Some(ref softirqs)) => (hardirqs, softirqs),
+            _ => return None,
+        };
+        let hardirq_delta = self.delta(hardirqs.total(), older, newer);
+        if hardirq_delta == 0 { return None; }
+        let softirq_delta = self.delta(softirqs.total(), older, newer);
+        Some(softirq_delta as f64 / hardirq_delta as f64)
+    }
+
+    /// Run queue saturation of a sample, i.e. the number of runnable processes
+    /// divided by the number of CPUs
+    ///
+    /// A value above 1.0 means that there were more runnable processes than
+    /// CPUs to run them. As the number of runnable processes is a gauge, no
+    /// delta is involved. The CPU count is left up to the caller, as it may
+    /// differ from the number of CPU threads in /proc/stat if the process is
+    /// restricted to a subset of the CPUs. Returns None if the kernel does not
+    /// report the number of runnable processes (before Linux 2.5.45).
+    ///
+    pub fn runqueue_saturation(&self,
+                               cpu_count: usize,
+                               index: usize) -> Option<f64> {
+        assert!(cpu_count > 0, "There should be at least one CPU");
+        self.runnable_processes.as_ref().map(|runnable| {
+            runnable[index] as f64 / cpu_count as f64
+        })
+    }
+
+    /// Compact "activity score" of the system between two samples, in [0, 1]
+    ///
+    /// This is a weighted average of the following activity indicators, each
+    /// of which is normalized to the [0, 1] range beforehand:
+    ///
+    /// - The CPU busy fraction (see cpu::Data::busy_fraction())
+    /// - The context switch rate per CPU thread, as r / (r + 10000/s)
+    /// - The hardware interrupt rate per CPU thread, as r / (r + 5000/s)
+    /// - The number of runnable processes per CPU thread, saturating at 1
+    ///
+    /// Rates are computed using the CPU time elapsed between the samples as a
+    /// clock. Indicators which cannot be computed, because /proc/stat lacks
+    /// the required statistics or no CPU time elapsed, are left out and the
+    /// weights of the remaining ones are renormalized accordingly. Returns None
+    /// if no indicator with a nonzero weight is available.
+    ///
+    pub fn activity_score_between(&self,
+                                  older: usize,
+                                  newer: usize,
+                                  weights: &ActivityWeights) -> Option<f64> {
+        // Measure the elapsed time and the number of CPU threads
+        let num_threads = self.each_thread.len();
+        let elapsed_secs = self.all_cpus.as_ref().and_then(|cpu| {
+            let ticks = cpu.corrected_total_ticks_between(older, newer);
+            if ticks == 0 || num_threads == 0 { return None; }
+            Some(ticks as f64 / num_threads as f64
+                 / cpu::ticks_per_second() as f64)
+        });
+        let per_thread_rate = |delta: u64| -> Option<f64> {
+            elapsed_secs.map(|secs| delta as f64 / num_threads as f64 / secs)
+        };
+        let saturate = |rate: f64, reference: f64| rate / (rate + reference);
+
+        // Normalize the activity indicators which are available
+        let cpu_busy = self.all_cpus
+                           .as_ref()
+                           .and_then(|cpu| cpu.busy_fraction(older, newer));
+        let context_switches =
+            self.context_switches
+                .as_ref()
+                .and_then(|ctxt| {
+                    per_thread_rate(self.delta(ctxt, older, newer))
+                })
+                .map(|rate| saturate(rate, 10_000.));
+        let interrupts =
+            self.interrupts
+                .as_ref()
+                .and_then(|intr| {
+                    per_thread_rate(intr.total_delta_between(older, newer))
+                })
+                .map(|rate| saturate(rate, 5_000.));
+        let run_queue = match self.runnable_processes {
+            Some(ref runnable) if num_threads > 0 => {
+                Some((runnable[newer] as f64 / num_threads as f64).min(1.))
+            },
+            _ => None,
+        };
+
+        // Compute the weighted average of these indicators
+        let (weighted_sum, total_weight) =
+            [(cpu_busy, weights.cpu_busy),
+             (context_switches, weights.context_switches),
+             (interrupts, weights.interrupts),
+             (run_queue, weights.run_queue)]
+                .iter()
+                .filter_map(|&(score, weight)| score.map(|s| (s, weight)))
+                .fold((0., 0.), |(sum, total), (score, weight)| {
+                    (sum + score * weight, total + weight)
+                });
+        if total_weight > 0. {
+            Some(weighted_sum / total_weight)
+        } else {
+            None
+        }
+    }
+
+    /// Create a new statistical data store, using a first sample to know the
+    /// structure of /proc/stat on this system
+    fn new(mut stream: RecordStream) -> Self {
+        // Our statistical data store will eventually go there
+        let mut data = Self::empty();
+
+        // For each initial record of /proc/stat...
+        while let Some(record) = stream.next() {
+            // ...and check the header
+            let record_kind = record.kind();
+            match record_kind {
+                // Statistics on all CPUs
+                RecordKind::CPUTotal => {
+                    data.all_cpus = Some(
+                        cpu::Data::new(record.parse_cpu())
+                    );
+                }
+
+                // Statistics on a specific CPU thread (should be enumerated in
+                // order, from thread 0 to thread Nt-1)
+                RecordKind::CPUThread(thread_id) => {
+                    assert_eq!(thread_id, data.each_thread.len() as u16,
+                               "Unexpected CPU thread ordering");
+                    data.each_thread.push(
+                        SampledThread::new(cpu::Data::new(record.parse_cpu()))
+                    );
+                },
+
+                // Paging statistics
+                RecordKind::PagingTotal => {
+                    data.paging = Some(
+                        paging::Data::new(record.parse_paging())
+                    );
+                },
+
+                // Swapping statistics
+                RecordKind::PagingSwap => {
+                    data.swapping = Some(
+                        paging::Data::new(record.parse_paging())
+                    );
+                },
+
+                // Hardware interrupt statistics
+                RecordKind::InterruptsHW => {
+                    data.interrupts = Some(
+                        interrupts::Data::new(record.parse_interrupts())
+                    );
+                },
+
+                // Context switch statistics
+                RecordKind::ContextSwitches => {
+                    data.context_switches = Some(Vec::new());
+                },
+
+                // Boot time
+                RecordKind::BootTime => {
+                    data.boot_time = Some(record.parse_boot_time());
+                },
+
+                // Number of process forks since boot
+                RecordKind::ProcessForks => {
+                    data.process_forks = Some(Vec::new());
+                },
+
+                // Number of processes in the runnable state
+                RecordKind::ProcessesRunnable => {
+                    data.runnable_processes = Some(Vec::new());
+                },
+
+                // Number of processes waiting for I/O
+                RecordKind::ProcessesBlocked => {
+                    data.blocked_processes = Some(Vec::new());
+                },
+
+                // Softirq statistics
+                RecordKind::InterruptsSW => {
+                    data.softirqs = Some(
+                        interrupts::Data::new(record.parse_interrupts())
+                    );
+                },
+
+                // Something we do not support yet? We should!
+                RecordKind::Unsupported(_) => {}
+            }
+
+            // Remember what kind of record that was
+            data.line_target.push(record_kind);
+        }
+
+        // Return our data collection setup
+        data
+    }
+
+    /// Stop storing the records of /proc/stat which do not have one of the
+    /// listed kinds, and drop their storage
+    ///
+    /// The lines of discarded records are only checked against the expected
+    /// file schema during sampling, they are neither parsed nor stored. CPU
+    /// thread records are kept or discarded as a whole, based on whether any
+    /// CPUThread kind is listed, whatever its thread ID. The boot time, which
+    /// takes no per-sample storage, is always kept as it is needed in order to
+    /// detect reboots.
+    ///
+    /// This should be called before any sample is pushed.
+    ///
+    pub fn keep_records(&mut self, kinds: &[RecordKind]) {
+        debug_assert_eq!(self.len(), 0,
+                         "Records should be filtered before sampling starts");
+        let kept = |target: &RecordKind| -> bool {
+            *target == RecordKind::BootTime
+                || kinds.iter().any(|kind| {
+                       mem::discriminant(kind) == mem::discriminant(target)
+                   })
+        };
+        for target in self.line_target.iter_mut() {
+            // Records which are kept are left alone
+            if kept(target) { continue; }
+
+            // Other records lose their storage...
+            match *target {
+                RecordKind::CPUTotal => self.all_cpus = None,
+                RecordKind::CPUThread(_) => self.each_thread.clear(),
+                RecordKind::PagingTotal => self.paging = None,
+                RecordKind::PagingSwap => self.swapping = None,
+                RecordKind::InterruptsHW => self.interrupts = None,
+                RecordKind::ContextSwitches => self.context_switches = None,
+                RecordKind::BootTime => unreachable!(),
+                RecordKind::ProcessForks => self.process_forks = None,
+                RecordKind::ProcessesRunnable => {
+                    self.runnable_processes = None
+                },
+                RecordKind::ProcessesBlocked => self.blocked_processes = None,
+                RecordKind::InterruptsSW => self.softirqs = None,
+                RecordKind::Unsupported(_) => {},
+            }
+
+            // ...and get a target which only checks their header
+            *target = RecordKind::Unsupported(target.header());
+        }
+    }
+
+    /// Parse the contents of /proc/stat and add a data sample to all
+    /// corresponding entries in the internal data store
+    ///
+    /// If a CPU record does not have the expected amount of CPU timers, or if
+    /// a record has disappeared (as happens when the kernel hands over an
+    /// empty readout), the sample is not stored, and a schema change is
+    /// reported.
+    ///
+    fn push(&mut self, stream: RecordStream) -> Result<(), SchemaChanged> {
+        self.push_sample(stream, true)
+    }
+
+    /// Parse a batch of /proc/stat samples, such as the snapshots of a
+    /// recorded capture, and add them to the internal data store
+    ///
+    /// This is faster than pushing the samples one by one, as storage is
+    /// reserved up front and, in release builds, the record structure is only
+    /// checked for the first sample of the batch. This assumes that the schema
+    /// of /proc/stat does not change within the batch, which is the case for
+    /// a capture of a single system. Changes to the CPU timers are still
+    /// detected: the samples which precede them are kept, and an error is
+    /// returned.
+    ///
+    pub fn push_many<'a, I>(&mut self, streams: I) -> Result<(), SchemaChanged>
+        where I: IntoIterator<Item=RecordStream<'a>>
+    {
+        let mut streams = streams.into_iter();
+        self.reserve(streams.size_hint().0);
+        if let Some(first_stream) = streams.next() {
+            self.push_sample(first_stream, true)?;
+        }
+        for stream in streams {
+            self.push_sample(stream, false)?;
+        }
+        Ok(())
+    }
+
+    /// INTERNAL: Add a sample to the internal data store, optionally checking
+    ///           the record structure of the file in release builds
+    fn push_sample(&mut self,
+                   mut stream: RecordStream,
+                   check_schema: bool) -> Result<(), SchemaChanged> {
+        // This is the index of the sample that we are about to push
+        let sample_idx = self.len();
+
+        // This will record whether storing CPU statistics failed, or whether
+        // some records are missing
+        let mut cpu_outcome = Ok(());
+
+        // This will iterate over the hardware CPU thread data
+        let mut thread_iter = self.each_thread.iter_mut();
+
+        // This time, we know how lines of /proc/stat map to our members
+        for target in self.line_target.iter() {
+            // Check that the record structure of the file has not changed. We
+            // do not support events which can change the /proc/stat schema
+            // (such as kernel updates or CPU hotplug) at this point in time,
+            // so all we need to do is to check for schema consistency.
+            let record = match stream.next() {
+                Some(record) => record,
+                None => {
+                    cpu_outcome = Err(SchemaChanged::new(
+                        "A /proc/stat record has disappeared"
+                    ));
+                    break;
+                }
+            };
+            if check_schema {
+                assert!(record.has_kind(target), "Unsupported schema change");
+            } else {
+                debug_assert!(record.has_kind(target),
+                              "Unsupported schema change");
+            }
+
+            // Now we can sample the new contents of that record
+            match *target {
+                RecordKind::CPUTotal => {
+                    cpu_outcome = force_push!(self.all_cpus,
+                                              record.parse_cpu());
+                },
+                RecordKind::CPUThread(_) => {
+                    cpu_outcome = match thread_iter.next() {
+                        Some(cpu) => cpu.push(record.parse_cpu()),
+                        None => panic!("Found a bug in CPU thread iteration"),
+                    };
+                },
+                RecordKind::PagingTotal => {
+                    force_push!(self.paging, record.parse_paging());
+                },
+                RecordKind::PagingSwap => {
+                    force_push!(self.swapping, record.parse_paging());
+                },
+                RecordKind::InterruptsHW => {
+                    force_push!(self.interrupts, record.parse_interrupts());
+                },
+                RecordKind::ContextSwitches => {
+                    force_push!(self.context_switches,
+                                record.parse_context_switches());
+                },
+                RecordKind::BootTime => {
+                    // Boot time only changes significantly when the system
+                    // reboots, which resets every counter of /proc/stat. Keep
+                    // track of that, ignoring clock adjustment jitter.
+                    let boot_time = record.parse_boot_time();
+                    let rebooted = self.boot_time.map_or(true, |old_time| {
+                        let change = (boot_time - old_time).num_seconds();
+                        change.abs() > BOOT_TIME_TOLERANCE_SECS
+                    });
+                    self.boot_time = Some(boot_time);
+                    if rebooted {
+                        self.reset_indices.push(sample_idx);
+                        for irqs in self.interrupts.iter_mut()
+                                                   .chain(&mut self.softirqs) {
+                            irqs.mark_reset(sample_idx);
+                        }
+                    }
+                },
+                RecordKind::ProcessForks => {
+                    // The boot time record comes before this one, so we know
+                    // if the counter was reset by a reboot at this point
+                    let rebooted =
+                        self.reset_indices.last() == Some(&sample_idx);
+                    let forks = self.fork_unwinder
+                                    .unwind(record.parse_process_forks(),
+                                            rebooted);
+                    force_push!(self.process_forks, forks);
+                },
+                RecordKind::ProcessesRunnable => {
+                    force_push!(self.runnable_processes,
+                                record.parse_processes());
+                },
+                RecordKind::ProcessesBlocked => {
+                    force_push!(self.blocked_processes,
+                                record.parse_processes());
+                },
+                RecordKind::InterruptsSW => {
+                    force_push!(self.softirqs, record.parse_interrupts());
+                },
+                RecordKind::Unsupported(_) => {}
+            }
+
+            // If the CPU timers changed, stop here
+            if cpu_outcome.is_err() { break; }
+        }
+
+        // On schema changes, drop the partially stored sample
+        if cpu_outcome.is_err() {
+            self.truncate(sample_idx);
+            return cpu_outcome;
+        }
+
+        // At the end of parsing, we should have consumed all statistics from
+        // the file, otherwise the /proc/stat schema got updated behind our back
+        debug_assert!(stream.next().is_none(), "Unsupported schema change");
+        debug_assert!(thread_iter.next().is_none(),
+                      "Found a bug in CPU thread iteration");
+
+        // If the system rebooted, the CPU timers were reset too. They come
+        // before the boot time in /proc/stat, so they are only marked now.
+        if self.reset_indices.last() == Some(&sample_idx) {
+            for cpu in self.all_cpus.iter_mut() {
+                cpu.mark_reset(sample_idx);
+            }
+            for thread in self.each_thread.iter_mut() {
+                thread.mark_reset(sample_idx);
+            }
+        }
+        Ok(())
+    }
+
+    /// INTERNAL: Reserve storage for some additional samples
+    fn reserve(&mut self, additional: usize) {
+        for cpu in self.all_cpus.iter_mut() {
+            cpu.reserve(additional);
+        }
+        for cpu in self.each_thread.iter_mut() {
+            cpu.reserve(additional);
+        }
+        for paging in self.paging.iter_mut().chain(&mut self.swapping) {
+            paging.reserve(additional);
+        }
+        for irqs in self.interrupts.iter_mut().chain(&mut self.softirqs) {
+            irqs.reserve(additional);
+        }
+        for counter in self.context_switches
+                           .iter_mut()
+                           .chain(&mut self.process_forks) {
+            counter.reserve(additional);
+        }
+        for processes in self.runnable_processes
+                             .iter_mut()
+                             .chain(&mut self.blocked_processes) {
+            processes.reserve(additional);
+        }
+    }
+
+    /// INTERNAL: Create an empty statistical data store, which does not
+    ///           expect any record from /proc/stat
+    fn empty() -> Self {
+        Self {
+            all_cpus: None,
+            each_thread: Vec::new(),
+            paging: None,
+            swapping: None,
+            interrupts: None,
+            context_switches: None,
+            boot_time: None,
+            reset_indices: Vec::new(),
+            process_forks: None,
+            fork_unwinder: CounterUnwinder::new(),
+            runnable_processes: None,
+            blocked_processes: None,
+            softirqs: None,
+            line_target: Vec::new(),
+        }
+    }
+
+    /// INTERNAL: Update our prior knowledge of the amount of stored samples
+    ///           (current_len) according to an optional data source.
+    fn update_len<T>(current_len: &mut Option<usize>, opt_store: &Option<T>)
+        where T: SampledData
+    {
+        // This closure will get us the amount of samples stored inside of the
+        // optional data source as an Option<usize>, if we turn out to need it
+        let get_len = || opt_store.as_ref().map(|store| store.len());
+        
+        // Do we already know the amount of samples that should be in there?
+        match *current_len {
+            // If so, we only need to check if it is as expected, in debug mode
+            Some(old_len) => {
+                if let Some(new_len) = get_len() {
+                    debug_assert_eq!(new_len, old_len,
+                                     "Inconsistent amounts of stored samples");
+                }
+            },
+
+            // If not, we can safely overwrite our knowledge of the amount of
+            // samples with data from our new data source
+            None => *current_len = get_len(),
+        }
+    }
+
+    /// INTERNAL: Drop the oldest samples from an optional data source
+    fn drop_oldest_opt<T>(opt_store: &mut Option<T>, count: usize)
+        where T: SampledData
+    {
+        if let Some(ref mut store) = *opt_store {
+            store.drop_oldest(count);
+        }
+    }
+
+    /// INTERNAL: Update our knowledge of the length of the shortest time series
+    ///           (current_min) according to an optional data source.
+    fn update_min_len<T>(current_min: &mut Option<usize>, opt_store: &Option<T>)
+        where T: SampledData
+    {
+        if let Some(ref store) = *opt_store {
+            let store_len = store.min_len();
+            *current_min = Some(
+                current_min.map_or(store_len, |min| min.min(store_len))
+            );
+        }
+    }
+
+    /// INTERNAL: Drop the newest samples from an optional data source
+    fn truncate_opt<T>(opt_store: &mut Option<T>, length: usize)
+        where T: SampledData
+    {
+        if let Some(ref mut store) = *opt_store {
+            store.truncate(length);
+        }
+    }
+}
+//
+/// Statistics from /proc/stat are exported under the name of the record that
+/// they come from, e.g. "cpu_user", "cpu0_idle", "intr_total" or "ctxt".
+impl Columns for Data {
+    fn columns(&self) -> Vec<(String, Column)> {
+        let mut columns = Vec::new();
+        if let Some(ref all_cpus) = self.all_cpus {
+            columns::extend_prefixed(&mut columns, "cpu_", all_cpus);
+        }
+        for (idx, cpu) in self.each_thread.iter().enumerate() {
+            columns::extend_prefixed(&mut columns,
+                                     &format!("cpu{}_", idx),
+                                     &*cpu.dense());
+        }
+        if let Some(ref paging) = self.paging {
+            columns::extend_prefixed(&mut columns, "page_", paging);
+        }
+        if let Some(ref swapping) = self.swapping {
+            columns::extend_prefixed(&mut columns, "swap_", swapping);
+        }
+        if let Some(ref interrupts) = self.interrupts {
+            columns::extend_prefixed(&mut columns, "intr_", interrupts);
+        }
+        if let Some(ref context_switches) = self.context_switches {
+            columns.push(("ctxt".to_owned(),
+                          Column::counts(context_switches)));
+        }
+        if let Some(ref process_forks) = self.process_forks {
+            columns.push(("processes".to_owned(),
+                          Column::counts(process_forks)));
+        }
+        if let Some(ref runnable_processes) = self.runnable_processes {
+            columns.push(("procs_running".to_owned(),
+                          Column::counts(runnable_processes)));
+        }
+        if let Some(ref blocked_processes) = self.blocked_processes {
+            columns.push(("procs_blocked".to_owned(),
+                          Column::counts(blocked_processes)));
+        }
+        if let Some(ref softirqs) = self.softirqs {
+            columns::extend_prefixed(&mut columns, "softirq_", softirqs);
+        }
+        columns
+    }
+}
+//
+/// Compact binary serialization, see the "binary" module for the general
+/// format. Here, the header lists the records of /proc/stat and which
+/// optional statistics are present, and is followed by these statistics.
+#[cfg(feature = "binary")]
+impl Data {
+    /// Save the sampled data in binary form
+    pub fn write_binary<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        // Write the header
+        binary::write_header(&mut writer, BINARY_MAGIC)?;
+        self.line_target.write_to(&mut writer)?;
+        let presence = [self.has_all_cpus(),
+                        self.has_paging(),
+                        self.has_swapping(),
+                        self.has_interrupts(),
+                        self.has_context_switches(),
+                        self.has_boot_time(),
+                        self.has_process_forks(),
+                        self.has_runnable_processes(),
+                        self.has_blocked_processes(),
+                        self.has_softirqs()];
+        let presence_bits = presence.iter()
+                                    .enumerate()
+                                    .fold(0u16, |bits, (idx, &present)| {
+                                        bits | ((present as u16) << idx)
+                                    });
+        presence_bits.write_to(&mut writer)?;
+
+        // Write the statistics
+        binary::write_optional(&self.all_cpus, &mut writer)?;
+        self.each_thread.write_to(&mut writer)?;
+        binary::write_optional(&self.paging, &mut writer)?;
+        binary::write_optional(&self.swapping, &mut writer)?;
+        binary::write_optional(&self.interrupts, &mut writer)?;
+        binary::write_optional(&self.context_switches, &mut writer)?;
+        if let Some(boot_time) = self.boot_time {
+            boot_time.timestamp().write_to(&mut writer)?;
+            boot_time.timestamp_subsec_nanos().write_to(&mut writer)?;
+        }
+        self.reset_indices.write_to(&mut writer)?;
+        binary::write_optional(&self.process_forks, &mut writer)?;
+        self.fork_unwinder.last_raw.write_to(&mut writer)?;
+        self.fork_unwinder.overflow_offset.write_to(&mut writer)?;
+        binary::write_optional(&self.runnable_processes, &mut writer)?;
+        binary::write_optional(&self.blocked_processes, &mut writer)?;
+        binary::write_optional(&self.softirqs, &mut writer)
+    }
+
+    /// Load sampled data which was saved by write_binary()
+    pub fn read_binary<R: Read>(mut reader: R) -> io::Result<Self> {
+        // Read the header
+        binary::read_header(&mut reader, BINARY_MAGIC)?;
+        let line_target = Vec::read_from(&mut reader)?;
+        let presence_bits = u16::read_from(&mut reader)?;
+        let present = |idx: usize| presence_bits & (1 << idx) != 0;
+
+        // Read the statistics
+        let reader = &mut reader;
+        Ok(
+            Self {
+                all_cpus: binary::read_optional(present(0), reader)?,
+                each_thread: Vec::read_from(reader)?,
+                paging: binary::read_optional(present(1), reader)?,
+                swapping: binary::read_optional(present(2), reader)?,
+                interrupts: binary::read_optional(present(3), reader)?,
+                context_switches: binary::read_optional(present(4), reader)?,
+                boot_time: if present(5) {
+                    let secs = i64::read_from(reader)?;
+                    let nanos = u32::read_from(reader)?;
+                    let boot_time = Utc.timestamp_opt(secs, nanos).single();
+                    Some(boot_time.ok_or_else(|| {
+                        binary::invalid_data("Invalid boot time")
+                    })?)
+                } else {
+                    None
+                },
+                reset_indices: Vec::read_from(reader)?,
+                process_forks: binary::read_optional(present(6), reader)?,
+                fork_unwinder: CounterUnwinder {
+                    last_raw: u64::read_from(reader)?,
+                    overflow_offset: u64::read_from(reader)?,
+                },
+                runnable_processes: binary::read_optional(present(7), reader)?,
+                blocked_processes: binary::read_optional(present(8), reader)?,
+                softirqs: binary::read_optional(present(9), reader)?,
+                line_target,
+            }
+        )
+    }
+}
+//
+/// Prometheus text exposition format, using the metric names of the Prometheus
+/// node exporter, so that dashboards built for it can be reused as is
+#[cfg(feature = "prometheus")]
+impl Data {
+    /// Render the latest sample in Prometheus text exposition format
+    ///
+    /// CPU time is exported in seconds per CPU thread, as in the node exporter
+    /// (which does not export the aggregate over all CPUs). Counters get a
+    /// "_total" suffix, whereas the number of runnable and blocked processes
+    /// and the boot time are exported as gauges. The paging and swapping
+    /// statistics of very old kernels have no node exporter equivalent (it
+    /// takes them from /proc/vmstat) and are not exported. If no sample was
+    /// recorded, nothing is written.
+    ///
+    pub fn write_prometheus<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        // Only the latest sample is exported
+        let last = match self.len().checked_sub(1) {
+            Some(last) => last,
+            None => return Ok(()),
+        };
+
+        // Export CPU time, labeled with CPU thread IDs (which the kernel
+        // enumerates in order, so they match indices in each_thread)
+        if self.has_per_cpu() {
+            let threads = self.each_thread.iter().map(|thread| {
+                (thread.stored_samples(), thread.stored_index(last))
+            }).collect::<Vec<_>>();
+            let secs = |ticks: &[u64], last: usize| {
+                ticks[last] as f64 / cpu::ticks_per_second() as f64
+            };
+            write_metric_header(out,
+                                "node_cpu_seconds_total",
+                                "Seconds the CPUs spent in each mode.",
+                                "counter")?;
+            for (id, &(thread, last)) in threads.iter().enumerate() {
+                let modes = [("user", Some(thread.user_ticks())),
+                             ("nice", Some(thread.nice_ticks())),
+                             ("system", Some(thread.system_ticks())),
+                             ("idle", Some(thread.idle_ticks())),
+                             ("iowait", thread.io_wait_ticks()),
+                             ("irq", thread.irq_ticks()),
+                             ("softirq", thread.softirq_ticks()),
+                             ("steal", thread.stolen_ticks())];
+                for &(mode, ticks) in &modes {
+                    if let Some(ticks) = ticks {
+                        writeln!(out,
+                                 "node_cpu_seconds_total\
+                                  {{cpu=\"{}\",mode=\"{}\"}} {}",
+                                 id, mode, secs(ticks, last))?;
+                    }
+                }
+            }
+            if threads[0].0.guest_ticks().is_some() {
+                write_metric_header(
+                    out,
+                    "node_cpu_guest_seconds_total",
+                    "Seconds the CPUs spent in guests (VMs) for each mode.",
+                    "counter"
+                )?;
+                for (id, &(thread, last)) in threads.iter().enumerate() {
+                    let modes = [("user", thread.guest_ticks()),
+                                 ("nice", thread.guest_nice_ticks())];
+                    for &(mode, ticks) in &modes {
+                        if let Some(ticks) = ticks {
+                            writeln!(out,
+                                     "node_cpu_guest_seconds_total\
+                                      {{cpu=\"{}\",mode=\"{}\"}} {}",
+                                     id, mode, secs(ticks, last))?;
+                        }
+                    }
+                }
+            }
+        }
+
+        // Export the system-wide counters
+        let counters = [
+            (self.interrupts.as_ref().map(|irqs| irqs.total()),
+             "node_intr_total",
+             "Total number of interrupts serviced."),
+            (self.context_switches(),
+             "node_context_switches_total",
+             "Total number of context switches."),
+            (self.process_forks(),
+             "node_forks_total",
+             "Total number of forks."),
+            (self.softirqs.as_ref().map(|softirqs| softirqs.total()),
+             "node_softirqs_total",
+             "Total number of softirqs serviced."),
+        ];
+        for &(counter, name, help) in &counters {
+            if let Some(counter) = counter {
+                write_metric_header(out, name, help, "counter")?;
+                writeln!(out, "{} {}", name, counter[last])?;
+            }
+        }
+
+        // Export the gauges
+        if let Some(boot_time) = self.boot_time {
+            write_metric_header(out,
+                                "node_boot_time_seconds",
+                                "Node boot time, in unixtime.",
+                                "gauge")?;
+            writeln!(out, "node_boot_time_seconds {}", boot_time.timestamp())?;
+        }
+        let gauges = [
+            (self.runnable_processes(),
+             "node_procs_running",
+             "Number of processes in runnable state."),
+            (self.blocked_processes(),
+             "node_procs_blocked",
+             "Number of processes blocked waiting for I/O to complete."),
+        ];
+        for &(gauge, name, help) in &gauges {
+            if let Some(gauge) = gauge {
+                write_metric_header(out, name, help, "gauge")?;
+                writeln!(out, "{} {}", name, gauge[last])?;
+            }
+        }
+        Ok(())
+    }
+}
+//
+/// INTERNAL: Write the HELP and TYPE lines which precede a Prometheus metric
+#[cfg(feature = "prometheus")]
+fn write_metric_header<W: Write>(out: &mut W,
+                                 name: &str,
+                                 help: &str,
+                                 metric_type: &str) -> io::Result<()> {
+    writeln!(out, "# HELP {} {}", name, help)?;
+    writeln!(out, "# TYPE {} {}", name, metric_type)
+}
+//
+/// InfluxDB line protocol, which is what many time-series pipelines ingest
+#[cfg(feature = "influx")]
+impl Data {
+    /// Render every sample in InfluxDB line protocol
+    ///
+    /// Since InfluxDB timestamps are absolute, whereas samplers record the
+    /// acquisition time of each sample as an Instant, the wall-clock time at
+    /// which each sample was acquired must be provided. It is rendered as
+    /// signed nanoseconds since the Unix epoch at the end of each line.
+    ///
+    /// Each sample is rendered as one line per CPU thread, tagged with its
+    /// "core" ID, followed by one line for the aggregate over all CPUs (tagged
+    /// "core=all") and one untagged line for the system-wide counters. CPU time
+    /// is exported in integer nanoseconds (e.g. "idle_ns"), and records which
+    /// the host does not provide are omitted.
+    ///
+    pub fn write_influx_line<W: Write>(&self,
+                                       measurement: &str,
+                                       timestamps: &[SystemTime],
+                                       out: &mut W) -> io::Result<()> {
+        assert_eq!(timestamps.len(), self.len(),
+                   "There should be one timestamp per sample");
+        let measurement = measurement.replace(',', "\\,")
+                                     .replace(' ', "\\ ");
+        for (idx, &timestamp) in timestamps.iter().enumerate() {
+            let timestamp_ns = columns::nanos_since_epoch(timestamp);
+
+            // Export CPU time, labeled with CPU thread IDs (which the kernel
+            // enumerates in order, so they match indices in each_thread)
+            let threads = self.each_thread.iter().enumerate().map(|(id, cpu)| {
+                (id.to_string(), cpu.stored_samples(), cpu.stored_index(idx))
+            });
+            let aggregate = self.all_cpus.iter().map(|all| {
+                ("all".to_owned(), all, idx)
+            });
+            for (core, cpu, idx) in threads.chain(aggregate) {
+                let modes = [("user_ns", Some(cpu.user_ticks())),
+                             ("nice_ns", Some(cpu.nice_ticks())),
+                             ("system_ns", Some(cpu.system_ticks())),
+                             ("idle_ns", Some(cpu.idle_ticks())),
+                             ("iowait_ns", cpu.io_wait_ticks()),
+                             ("irq_ns", cpu.irq_ticks()),
+                             ("softirq_ns", cpu.softirq_ticks()),
+                             ("steal_ns", cpu.stolen_ticks()),
+                             ("guest_ns", cpu.guest_ticks()),
+                             ("guest_nice_ns", cpu.guest_nice_ticks())];
+                let fields = modes.iter().filter_map(|&(name, ticks)| {
+                    ticks.map(|ticks| {
+                        (name, ticks[idx] * cpu::nanos_per_tick())
+                    })
+                });
+                write!(out, "{},core={}", measurement, core)?;
+                write_influx_fields(out, fields)?;
+                writeln!(out, " {}", timestamp_ns)?;
+            }
+
+            // Export the system-wide counters and gauges
+            let gauge = |gauge: Option<&[u16]>| gauge.map(|g| g[idx] as u64);
+            let fields = [
+                ("interrupts",
+                 self.interrupts.as_ref().map(|irqs| irqs.total()[idx])),
+                ("context_switches",
+                 self.context_switches().map(|ctxt| ctxt[idx])),
+                ("forks", self.process_forks().map(|forks| forks[idx])),
+                ("softirqs",
+                 self.softirqs.as_ref().map(|softirqs| softirqs.total()[idx])),
+                ("procs_running", gauge(self.runnable_processes())),
+                ("procs_blocked", gauge(self.blocked_processes())),
+            ];
+            let mut fields = fields.iter()
+                                   .filter_map(|&(name, value)| {
+                                       value.map(|value| (name, value))
+                                   })
+                                   .peekable();
+            if fields.peek().is_some() {
+                write!(out, "{}", measurement)?;
+                write_influx_fields(out, fields)?;
+                writeln!(out, " {}", timestamp_ns)?;
+            }
+        }
+        Ok(())
+    }
+}
+//
+/// INTERNAL: Write the comma-separated integer fields of an InfluxDB line,
+///           preceded by the space which separates them from the tags
+#[cfg(feature = "influx")]
+fn write_influx_fields<W, I>(out: &mut W, fields: I) -> io::Result<()>
+    where W: Write,
+          I: Iterator<Item=(&'static str, u64)>
+{
+    for (idx, (name, value)) in fields.enumerate() {
+        let separator = if idx == 0 { ' ' } else { ',' };
+        write!(out, "{}{}={}i", separator, name, value)?;
+    }
+    Ok(())
+}
+//
+/// Newline-delimited JSON, which is what many log ingestion pipelines expect
+#[cfg(feature = "ndjson")]
+impl Data {
+    /// Render every sample as a JSON object, on a line of its own
+    ///
+    /// As in write_influx_line(), the wall-clock time at which each sample was
+    /// acquired must be provided. It is rendered as the "timestamp_ns" member,
+    /// in signed nanoseconds since the Unix epoch.
+    ///
+    /// The other members are built from columns(), so that they stay in sync
+    /// with other exporters. Per-thread CPU statistics, which columns() names
+    /// after their CPU thread (e.g. "cpu0_user"), are gathered into arrays
+    /// indexed by CPU thread ID (e.g. "each_thread_user"). Missing samples of
+    /// real-valued columns are rendered as null.
+    ///
+    pub fn write_ndjson<W: Write>(&self,
+                                  timestamps: &[SystemTime],
+                                  out: &mut W) -> io::Result<()> {
+        assert_eq!(timestamps.len(), self.len(),
+                   "There should be one timestamp per sample");
+
+        // Group the columns into JSON members, in order of first appearance
+        let columns = self.columns();
+        let mut members: Vec<(String, Vec<usize>, bool)> = Vec::new();
+        for (col_idx, &(ref name, _)) in columns.iter().enumerate() {
+            let (key, is_array) = match thread_column_field(name) {
+                Some(field) => (format!("each_thread_{}", field), true),
+                None => (name.clone(), false),
+            };
+            match members.iter().position(|member| member.0 == key) {
+                Some(pos) => members[pos].1.push(col_idx),
+                None => members.push((key, vec![col_idx], is_array)),
+            }
+        }
+
+        // Write one JSON object per sample
+        for (row, &timestamp) in timestamps.iter().enumerate() {
+            write!(out, "{{\"timestamp_ns\":{}",
+                   columns::nanos_since_epoch(timestamp))?;
+            for &(ref key, ref col_indices, is_array) in members.iter() {
+                write!(out, ",")?;
+                write_json_string(out, key)?;
+                write!(out, ":")?;
+                if is_array { write!(out, "[")?; }
+                for (idx, &col_idx) in col_indices.iter().enumerate() {
+                    if idx > 0 { write!(out, ",")?; }
+                    write_json_value(out, &columns[col_idx].1, row)?;
+                }
+                if is_array { write!(out, "]")?; }
+            }
+            writeln!(out, "}}")?;
+        }
+        Ok(())
+    }
+}
+//
+/// INTERNAL: Name of the field of a per-thread CPU column (e.g. "user" for
+///           "cpu0_user"), or None if this is not a per-thread CPU column
+#[cfg(feature = "ndjson")]
+fn thread_column_field(column_name: &str) -> Option<&str> {
+    if !column_name.starts_with("cpu") { return None; }
+    let after_cpu = &column_name[3..];
+    let digits = after_cpu.bytes().take_while(u8::is_ascii_digit).count();
+    if digits == 0 || after_cpu[digits..].bytes().next() != Some(b'_') {
+        return None;
+    }
+    Some(&after_cpu[digits+1..])
+}
+//
+/// INTERNAL: Write a string as a quoted JSON string
+#[cfg(feature = "ndjson")]
+fn write_json_string<W: Write>(out: &mut W, string: &str) -> io::Result<()> {
+    write!(out, "\"")?;
+    for c in string.chars() {
+        match c {
+            '"' => write!(out, "\\\"")?,
+            '\\' => write!(out, "\\\\")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => write!(out, "{}", c)?,
+        }
+    }
+    write!(out, "\"")
+}
+//
+/// INTERNAL: Write a sample of a column as a JSON value
+#[cfg(feature = "ndjson")]
+fn write_json_value<W: Write>(out: &mut W,
+                              column: &Column,
+                              row: usize) -> io::Result<()> {
+    match *column {
+        Column::U64(ref values)
+        | Column::Nanos(ref values)
+        | Column::Bytes(ref values) => write!(out, "{}", values[row]),
+        Column::F64(ref values) if values[row].is_finite() => {
+            write!(out, "{}", values[row])
+        },
+        Column::F64(_) => write!(out, "null"),
+    }
+}
+//
+/// Comma-separated values, for spreadsheets and plotting tools
+#[cfg(feature = "csv")]
+impl Data {
+    /// Render one sample out of every `factor` as a CSV row, preceded by a
+    /// header row which names the columns
+    ///
+    /// This is meant for captures acquired at a higher rate than what should
+    /// be exported (e.g. sampled at 1 kHz, exported at 10 Hz). Unlike dropping
+    /// samples from the data store, it leaves the high-rate data untouched.
+    /// Samples 0, factor, 2*factor... are exported, so a factor of 1 exports
+    /// every sample.
+    ///
+    /// The columns are those of columns(), in the same order. Missing samples
+    /// of real-valued columns are rendered as empty fields.
+    ///
+    pub fn export_decimated_csv<W: Write>(&self,
+                                          factor: usize,
+                                          out: &mut W) -> io::Result<()> {
+        assert!(factor > 0, "The decimation factor should be positive");
+        let columns = self.columns();
+        write_csv_header(out, &columns)?;
+        for row in (0..self.len()).step_by(factor) {
+            for (idx, &(_, ref column)) in columns.iter().enumerate() {
+                if idx > 0 { write!(out, ",")?; }
+                match *column {
+                    Column::U64(ref values)
+                    | Column::Nanos(ref values)
+                    | Column::Bytes(ref values) => {
+                        write!(out, "{}", values[row])?
+                    },
+                    Column::F64(ref values) if values[row].is_finite() => {
+                        write!(out, "{}", values[row])?
+                    },
+                    Column::F64(_) => {},
+                }
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
+//
+/// INTERNAL: Write the header row of a CSV export, naming each column
+///
+/// Column names are plain identifiers, so they need neither quoting nor
+/// escaping.
+///
+#[cfg(feature = "csv")]
+fn write_csv_header<W: Write>(out: &mut W,
+                              columns: &[(String, Column)]) -> io::Result<()> {
+    for (idx, &(ref name, _)) in columns.iter().enumerate() {
+        if idx > 0 { write!(out, ",")?; }
+        write!(out, "{}", name)?;
+    }
+    writeln!(out)
+}
+//
+/// Magic number identifying /proc/stat data in the binary format
+#[cfg(feature = "binary")]
+const BINARY_MAGIC: &[u8; 4] = b"PFST";
+//
+/// Builders for expected data stores, which keep unit tests concise
+#[cfg(test)]
+impl Data {
+    /// Build a data store from successive samples of /proc/stat contents, as
+    /// other modules' tests cannot access the parsing infrastructure
+    pub(crate) fn from_samples(samples: &[&str]) -> Self {
+        let mut data = Self::new(RecordStream::new(samples[0]));
+        for sample in samples {
+            data.push(RecordStream::new(sample)).unwrap();
+        }
+        data
+    }
+
+    /// Expect global CPU statistics
+    fn with_cpu_total(mut self, cpu: cpu::Data) -> Self {
+        self.all_cpus = Some(cpu);
+        self.line_target.push(RecordKind::CPUTotal);
+        self
+    }
+
+    /// Expect statistics from the next CPU thread
+    fn with_cpu_thread(mut self, cpu: cpu::Data) -> Self {
+        let thread_id = self.each_thread.len() as u16;
+        self.each_thread.push(SampledThread::new(cpu));
+        self.line_target.push(RecordKind::CPUThread(thread_id));
+        self
+    }
+
+    /// Expect global paging statistics
+    fn with_paging(mut self, paging: paging::Data) -> Self {
+        self.paging = Some(paging);
+        self.line_target.push(RecordKind::PagingTotal);
+        self
+    }
+
+    /// Expect swapping statistics
+    fn with_swapping(mut self, swapping: paging::Data) -> Self {
+        self.swapping = Some(swapping);
+        self.line_target.push(RecordKind::PagingSwap);
+        self
+    }
+
+    /// Expect hardware interrupt statistics
+    fn with_interrupts(mut self, interrupts: interrupts::Data) -> Self {
+        self.interrupts = Some(interrupts);
+        self.line_target.push(RecordKind::InterruptsHW);
+        self
+    }
+
+    /// Expect context switch statistics
+    fn with_context_switches(mut self, context_switches: Vec<u64>) -> Self {
+        self.context_switches = Some(context_switches);
+        self.line_target.push(RecordKind::ContextSwitches);
+        self
+    }
+
+    /// Expect a boot time
+    fn with_boot_time(mut self, boot_time: DateTime<Utc>) -> Self {
+        self.boot_time = Some(boot_time);
+        self.line_target.push(RecordKind::BootTime);
+        self
+    }
+
+    /// Expect process fork statistics
+    fn with_process_forks(mut self, process_forks: Vec<u64>) -> Self {
+        if let Some(&last_forks) = process_forks.last() {
+            self.fork_unwinder.last_raw = last_forks;
+        }
+        self.process_forks = Some(process_forks);
+        self.line_target.push(RecordKind::ProcessForks);
+        self
+    }
+
+    /// Expect runnable process statistics
+    fn with_runnable_processes(mut self, runnable: Vec<u16>) -> Self {
+        self.runnable_processes = Some(runnable);
+        self.line_target.push(RecordKind::ProcessesRunnable);
+        self
+    }
+
+    /// Expect blocked process statistics
+    fn with_blocked_processes(mut self, blocked: Vec<u16>) -> Self {
+        self.blocked_processes = Some(blocked);
+        self.line_target.push(RecordKind::ProcessesBlocked);
+        self
+    }
+
+    /// Expect softirq statistics
+    fn with_softirqs(mut self, softirqs: interrupts::Data) -> Self {
+        self.softirqs = Some(softirqs);
+        self.line_target.push(RecordKind::InterruptsSW);
+        self
+    }
+}
+
+
+/// Relative weights of the activity indicators which are combined into the
+/// activity score of Data::activity_score_between()
+///
+/// Only the ratios between weights matter. Setting a weight to zero excludes
+/// the corresponding indicator from the score.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct ActivityWeights {
+    /// Weight of the CPU busy fraction
+    pub cpu_busy: f64,
 
-                // Something we do not support yet? We should!
-                RecordKind::Unsupported(_) => {}
-            }
+    /// Weight of the context switch rate
+    pub context_switches: f64,
 
-            // Remember what kind of record that was
-            data.line_target.push(record_kind);
-        }
+    /// Weight of the hardware interrupt rate
+    pub interrupts: f64,
 
-        // Return our data collection setup
-        data
+    /// Weight of the run queue length
+    pub run_queue: f64,
+}
+//
+/// By default, CPU usage dominates the score, and the other indicators refine
+/// it with information about the scheduling and I/O load of the system.
+impl Default for ActivityWeights {
+    fn default() -> Self {
+        Self {
+            cpu_busy: 0.4,
+            context_switches: 0.2,
+            interrupts: 0.2,
+            run_queue: 0.2,
+        }
     }
+}
 
-    /// Parse the contents of /proc/stat and add a data sample to all
-    /// corresponding entries in the internal data store
-    fn push(&mut self, mut stream: RecordStream) {
-        // This will iterate over the hardware CPU thread data
-        let mut thread_iter = self.each_thread.iter_mut();
 
-        // This time, we know how lines of /proc/stat map to our members
-        for target in self.line_target.iter() {
-            // Check that the record structure of the file has not changed. We
-            // do not support events which can change the /proc/stat schema
-            // (such as kernel updates or CPU hotplug) at this point in time,
-            // so all we need to do is to check for schema consistency.
-            let record = stream.next().expect("Unsupported schema change");
-            assert!(record.has_kind(target), "Unsupported schema change");
+/// INTERNAL: Overflow unwinding state for a kernel counter which is stored as
+///           an "unsigned long", and can thus wrap around on 32-bit kernels.
+///
+/// Wrap-arounds are detected as a decrease of the counter, and compensated by
+/// adding 2^32 to all subsequent readouts. Reboots also reset counters, so they
+/// must be reported to the unwinder in order not to be mistaken for overflows.
+///
+#[derive(Clone, Debug, PartialEq)]
+struct CounterUnwinder {
+    /// Last raw counter value reported by the kernel
+    last_raw: u64,
 
-            // Now we can sample the new contents of that record
-            match *target {
-                RecordKind::CPUTotal => {
-                    force_push!(self.all_cpus, record.parse_cpu());
-                },
-                RecordKind::CPUThread(_) => {
-                    thread_iter.next()
-                               .expect("Found a bug in CPU thread iteration")
-                               .push(record.parse_cpu());
-                },
-                RecordKind::PagingTotal => {
-                    force_push!(self.paging, record.parse_paging());
-                },
-                RecordKind::PagingSwap => {
-                    force_push!(self.swapping, record.parse_paging());
-                },
-                RecordKind::InterruptsHW => {
-                    force_push!(self.interrupts, record.parse_interrupts());
-                },
-                RecordKind::ContextSwitches => {
-                    force_push!(self.context_switches,
-                                record.parse_context_switches());
-                },
-                RecordKind::BootTime => {
-                    // Nothing to do, we only measure boot time once
-                },
-                RecordKind::ProcessForks => {
-                    force_push!(self.process_forks,
-                                record.parse_process_forks());
-                },
-                RecordKind::ProcessesRunnable => {
-                    force_push!(self.runnable_processes,
-                                record.parse_processes());
-                },
-                RecordKind::ProcessesBlocked => {
-                    force_push!(self.blocked_processes,
-                                record.parse_processes());
-                },
-                RecordKind::InterruptsSW => {
-                    force_push!(self.softirqs, record.parse_interrupts());
-                },
-                RecordKind::Unsupported(_) => {}
-            }
+    /// Amount to be added to raw counter values, due to past overflows
+    overflow_offset: u64,
+}
+//
+impl CounterUnwinder {
+    /// Set up overflow unwinding for a new counter
+    fn new() -> Self {
+        Self {
+            last_raw: 0,
+            overflow_offset: 0,
         }
-
-        // At the end of parsing, we should have consumed all statistics from
-        // the file, otherwise the /proc/stat schema got updated behind our back
-        debug_assert!(stream.next().is_none(), "Unsupported schema change");
-        debug_assert!(thread_iter.next().is_none(),
-                      "Found a bug in CPU thread iteration");
     }
 
-    /// INTERNAL: Update our prior knowledge of the amount of stored samples
-    ///           (current_len) according to an optional data source.
-    fn update_len<T>(current_len: &mut Option<usize>, opt_store: &Option<T>)
-        where T: SampledData
-    {
-        // This closure will get us the amount of samples stored inside of the
-        // optional data source as an Option<usize>, if we turn out to need it
-        let get_len = || opt_store.as_ref().map(|store| store.len());
-        
-        // Do we already know the amount of samples that should be in there?
-        match *current_len {
-            // If so, we only need to check if it is as expected, in debug mode
-            Some(old_len) => {
-                if let Some(new_len) = get_len() {
-                    debug_assert_eq!(new_len, old_len,
-                                     "Inconsistent amounts of stored samples");
-                }
-            },
-
-            // If not, we can safely overwrite our knowledge of the amount of
-            // samples with data from our new data source
-            None => *current_len = get_len(),
+    /// Unwind a new raw counter value, telling if the system has rebooted since
+    /// the previous readout
+    fn unwind(&mut self, raw: u64, rebooted: bool) -> u64 {
+        if rebooted {
+            self.overflow_offset = 0;
+        } else if raw < self.last_raw {
+            self.overflow_offset += 1 << 32;
         }
+        self.last_raw = raw;
+        raw + self.overflow_offset
     }
 }
 
@@ -686,6 +2566,21 @@ impl<T> SampledData for Vec<T>
     fn len(&self) -> usize {
         <Vec<T>>::len(self)
     }
+
+    /// Drop the oldest data samples from this container
+    fn drop_oldest(&mut self, count: usize) {
+        self.drain(..count);
+    }
+
+    /// A Vec only has one time series, so it is always consistent
+    fn min_len(&self) -> usize {
+        <Vec<T>>::len(self)
+    }
+
+    /// Drop the newest data samples from this container
+    fn truncate(&mut self, length: usize) {
+        <Vec<T>>::truncate(self, length);
+    }
 }
 //
 impl<T> SampledData0 for Vec<T>
@@ -704,11 +2599,18 @@ impl<T> SampledData0 for Vec<T>
 /// Unit tests
 #[cfg(test)]
 mod tests {
+    #[cfg(feature = "arrow")]
+    use arrow::datatypes::{DataType, TimeUnit};
     use chrono::{TimeZone, Utc};
+    use ::procfs::cpuinfo::{CpuTopology, ThreadLocation};
     use ::splitter::split_line_and_run;
+    use std::io;
+    use std::mem;
+    use std::time::{Duration, UNIX_EPOCH};
     use super::{cpu, interrupts, paging};
-    use super::{Data, Parser, PseudoFileParser, Record, RecordKind,
-                RecordStream, SampledData};
+    use super::{ActivityWeights, Column, Columns, Data, Parser,
+                PseudoFileParser, Record, RecordKind, RecordStream,
+                SampledData, Sampler, SchemaChanged, TickCalibration};
 
     /// Check that CPU stats are parsed properly
     #[test]
@@ -791,6 +2693,31 @@ mod tests {
         with_record("processes 9564", |record| {
             assert_eq!(record.parse_process_forks(), 9564);
         });
+
+        // Long-running servers can spawn more than 2^32 processes
+        with_record("processes 5000000000", |record| {
+            assert_eq!(record.parse_process_forks(), 5_000_000_000);
+        });
+    }
+
+    /// Check that the fork counter is unwound if a 32-bit kernel wraps it
+    #[test]
+    fn process_forks_overflow() {
+        let sample = |forks: u64, btime: u64| -> String {
+            format!("btime {}\nprocesses {}\n", btime, forks)
+        };
+        let mut data = Data::new(RecordStream::new(&sample(0, 1000)));
+        for &(forks, btime) in [(4294967290, 1000),
+                                (5, 1000),
+                                (10, 1000),
+                                (7, 2000),
+                                (4294967295, 2000),
+                                (1, 2000)].iter() {
+            data.push(RecordStream::new(&sample(forks, btime))).unwrap();
+        }
+        assert_eq!(data.process_forks(),
+                   Some(&[4294967290, 4294967301, 4294967306,
+                          7, 4294967295, 4294967297][..]));
     }
 
     /// Check that process activity is parsed properly
@@ -888,7 +2815,7 @@ mod tests {
         {
             let mut data = Data::new(RecordStream::new(file_contents));
             if push {
-                data.push(RecordStream::new(file_contents));
+                data.push(RecordStream::new(file_contents)).unwrap();
             }
             data
         };
@@ -902,7 +2829,7 @@ mod tests {
             });
             if push {
                 with_record(textual_record, |record| {
-                    data.push(record.parse_cpu());
+                    data.push(record.parse_cpu()).unwrap();
                 });
             }
             data
@@ -946,18 +2873,7 @@ mod tests {
         // good base case which we can build other sampled data tests upon.
         let mut stats = String::new();
         let empty_void_stats = new_sampled_data(&stats, false);
-        let mut expected_empty = Data { all_cpus: None,
-                                        each_thread: Vec::new(),
-                                        paging: None,
-                                        swapping: None,
-                                        interrupts: None,
-                                        context_switches: None,
-                                        boot_time: None,
-                                        process_forks: None,
-                                        runnable_processes: None,
-                                        blocked_processes: None,
-                                        softirqs: None,
-                                        line_target: Vec::new() };
+        let mut expected_empty = Data::empty();
         assert_eq!(empty_void_stats, expected_empty);
         let full_void_stats = new_sampled_data(&stats, true);
         let mut expected_full = expected_empty.clone();
@@ -966,7 +2882,7 @@ mod tests {
         // We will then test supported records one by one, in the following way
         let mut check_new_record =
             |extra_text: &str,
-             update_expected: &Fn(&mut Data, bool)|
+             update_expected: &Fn(Data, bool) -> Data|
          {
             // Add new record(s) to our mock file sample
             stats.push_str(extra_text);
@@ -976,7 +2892,8 @@ mod tests {
 
             // Update our expectations of an empty stats container and check
             // that the container which we've built matches them
-            update_expected(&mut expected_empty, false);
+            let old_expected = mem::replace(&mut expected_empty, Data::empty());
+            expected_empty = update_expected(old_expected, false);
             assert_eq!(empty_stats, expected_empty);
             assert_eq!(empty_stats.len(), 0);
 
@@ -985,7 +2902,8 @@ mod tests {
 
             // Update our expectations of a full stats container and check that
             // the container which we've built matches them
-            update_expected(&mut expected_full, true);
+            let old_expected = mem::replace(&mut expected_full, Data::empty());
+            expected_full = update_expected(old_expected, true);
             assert_eq!(full_stats, expected_full);
             assert_eq!(full_stats.len(), 1);
         };
@@ -995,8 +2913,7 @@ mod tests {
         check_new_record(
             CPU_STR,
             &|expected, push| {
-                expected.all_cpus = Some(new_cpu_data(CPU_STR, push));
-                expected.line_target.push(RecordKind::CPUTotal);
+                expected.with_cpu_total(new_cpu_data(CPU_STR, push))
             },
         );
 
@@ -1005,10 +2922,8 @@ mod tests {
             "cpu0 0 1 1 3
              cpu1 1 1 2 1\n",
             &|expected, push| {
-                expected.each_thread = vec![new_cpu_data("cpu0 0 1 1 3", push),
-                                            new_cpu_data("cpu1 1 1 2 1", push)];
-                expected.line_target.push(RecordKind::CPUThread(0));
-                expected.line_target.push(RecordKind::CPUThread(1));
+                expected.with_cpu_thread(new_cpu_data("cpu0 0 1 1 3", push))
+                        .with_cpu_thread(new_cpu_data("cpu1 1 1 2 1", push))
             }
         );
 
@@ -1017,8 +2932,7 @@ mod tests {
         check_new_record(
             PAGE_STR,
             &|expected, push| {
-                expected.paging = Some(new_paging_data(PAGE_STR, push));
-                expected.line_target.push(RecordKind::PagingTotal);
+                expected.with_paging(new_paging_data(PAGE_STR, push))
             }
         );
 
@@ -1027,8 +2941,7 @@ mod tests {
         check_new_record(
             SWAP_STR,
             &|expected, push| {
-                expected.swapping = Some(new_paging_data(SWAP_STR, push));
-                expected.line_target.push(RecordKind::PagingSwap);
+                expected.with_swapping(new_paging_data(SWAP_STR, push))
             }
         );
 
@@ -1037,8 +2950,7 @@ mod tests {
         check_new_record(
             INTR_STR,
             &|expected, push| {
-                expected.interrupts = Some(new_interrupts_data(INTR_STR, push));
-                expected.line_target.push(RecordKind::InterruptsHW);
+                expected.with_interrupts(new_interrupts_data(INTR_STR, push))
             }
         );
 
@@ -1046,10 +2958,9 @@ mod tests {
         check_new_record(
             "ctxt 654321\n",
             &|expected, push| {
-                expected.context_switches = Some(
+                expected.with_context_switches(
                     if push { vec![654321] } else { Vec::new() }
-                );
-                expected.line_target.push(RecordKind::ContextSwitches);
+                )
             }
         );
 
@@ -1057,8 +2968,7 @@ mod tests {
         check_new_record(
             "btime 5738295\n",
             &|expected, _push| {
-                expected.boot_time = Some(Utc.timestamp(5738295, 0));
-                expected.line_target.push(RecordKind::BootTime);
+                expected.with_boot_time(Utc.timestamp(5738295, 0))
             }
         );
 
@@ -1066,10 +2976,9 @@ mod tests {
         check_new_record(
             "processes 94536551\n",
             &|expected, push| {
-                expected.process_forks = Some(
+                expected.with_process_forks(
                     if push { vec![94536551] } else { Vec::new() }
-                );
-                expected.line_target.push(RecordKind::ProcessForks);
+                )
             }
         );
 
@@ -1077,10 +2986,9 @@ mod tests {
         check_new_record(
             "procs_running 1624\n",
             &|expected, push| {
-                expected.runnable_processes = Some(
+                expected.with_runnable_processes(
                     if push { vec![1624] } else { Vec::new() }
-                );
-                expected.line_target.push(RecordKind::ProcessesRunnable);
+                )
             }
         );
 
@@ -1088,10 +2996,9 @@ mod tests {
         check_new_record(
             "procs_blocked 8948\n",
             &|expected, push| {
-                expected.blocked_processes = Some(
+                expected.with_blocked_processes(
                     if push { vec![8948] } else { Vec::new() }
-                );
-                expected.line_target.push(RecordKind::ProcessesBlocked);
+                )
             }
         );
 
@@ -1100,13 +3007,1059 @@ mod tests {
         check_new_record(
             SOFTIRQ_STR,
             &|expected, push| {
-                expected.softirqs =
-                    Some(new_interrupts_data(SOFTIRQ_STR, push));
-                expected.line_target.push(RecordKind::InterruptsSW);
+                expected.with_softirqs(new_interrupts_data(SOFTIRQ_STR, push))
             }
         );
     }
 
+    /// Check that both representations of the boot time agree
+    #[test]
+    fn boot_time_system() {
+        let data = Data::from_samples(&["ctxt 1000\n"]);
+        assert_eq!(data.boot_time_system(), None);
+        let data = Data::from_samples(&["btime 713705\n"]);
+        let boot_time = data.boot_time_system().unwrap();
+        assert_eq!(boot_time, UNIX_EPOCH + Duration::from_secs(713705));
+        let since_epoch = boot_time.duration_since(UNIX_EPOCH).unwrap();
+        assert_eq!(since_epoch.as_secs() as i64,
+                   data.boot_time().unwrap().timestamp());
+
+        // Boot times before the Unix epoch are rejected instead of wrapping
+        let mut data = data;
+        data.boot_time = Utc.timestamp_opt(-1, 0).single();
+        assert!(data.boot_time().is_some());
+        assert_eq!(data.boot_time_system(), None);
+    }
+
+    /// Check that system reboots are detected and handled by counter deltas
+    #[test]
+    fn reboot_detection() {
+        // Sample a system which then reboots, twice
+        const BEFORE_REBOOT: &str = "ctxt 1000\nbtime 5738295\n";
+        const AFTER_REBOOT: &str = "ctxt 30\nbtime 5739000\n";
+        const AFTER_REBOOT_2: &str = "ctxt 20\nbtime 5740000\n";
+        let mut data = Data::new(RecordStream::new(BEFORE_REBOOT));
+        data.push(RecordStream::new(BEFORE_REBOOT)).unwrap();
+        data.push(RecordStream::new("ctxt 1100\nbtime 5738295\n")).unwrap();
+        data.push(RecordStream::new(AFTER_REBOOT)).unwrap();
+        data.push(RecordStream::new("ctxt 80\nbtime 5739000\n")).unwrap();
+        data.push(RecordStream::new(AFTER_REBOOT_2)).unwrap();
+        assert_eq!(data.reset_indices(), vec![2, 4]);
+        assert_eq!(data.boot_time(), Some(Utc.timestamp(5740000, 0)));
+
+        // Deltas should only span reboots by measuring post-reboot activity
+        let ctxt = data.context_switches().unwrap().to_owned();
+        assert_eq!(data.delta(&ctxt, 0, 1), 100);
+        assert_eq!(data.delta(&ctxt, 1, 2), 30);
+        assert_eq!(data.delta(&ctxt, 0, 3), 80);
+        assert_eq!(data.delta(&ctxt, 2, 3), 50);
+        assert_eq!(data.delta(&ctxt, 3, 4), 20);
+        assert_eq!(data.rate_between(&ctxt, 1, 2, Duration::new(2, 0)),
+                   Some(15.0));
+        assert_eq!(data.rate_between(&ctxt, 1, 2, Duration::new(0, 0)), None);
+
+        // Dropping old samples should keep reset indices in sync
+        data.drop_oldest(3);
+        assert_eq!(data.reset_indices(), vec![1]);
+
+        // Counters which go backwards without a reboot have no increase
+        let mut data = Data::new(RecordStream::new(BEFORE_REBOOT));
+        data.push(RecordStream::new(BEFORE_REBOOT)).unwrap();
+        data.push(RecordStream::new("ctxt 5\nbtime 5738295\n")).unwrap();
+        assert_eq!(data.reset_indices(), Vec::<usize>::new());
+        let ctxt = data.context_switches().unwrap().to_owned();
+        assert_eq!(data.delta(&ctxt, 0, 1), 0);
+        assert_eq!(data.rate_between(&ctxt, 0, 1, Duration::new(1, 0)),
+                   Some(0.0));
+
+        // Interrupt totals should be handled in the same way
+        let mut data = Data::new(RecordStream::new("intr 900 0\nbtime 10\n"));
+        data.push(RecordStream::new("intr 1000 0\nbtime 10\n")).unwrap();
+        data.push(RecordStream::new("intr 1500 0\nbtime 10\n")).unwrap();
+        data.push(RecordStream::new("intr 200 0\nbtime 20\n")).unwrap();
+        data.push(RecordStream::new("intr 2000 0\nbtime 30\n")).unwrap();
+        let interrupts = data.interrupts().unwrap();
+        assert_eq!(interrupts.total_delta_between(0, 1), 500);
+        assert_eq!(interrupts.total_delta_between(1, 2), 200);
+        assert_eq!(interrupts.total_delta_between(2, 3), 2000);
+        assert_eq!(interrupts.total_rate_between(1, 2, Duration::new(4, 0)),
+                   Some(50.0));
+
+        // So should CPU timers, both global and per-thread
+        let mut data = Data::new(RecordStream::new(
+            "cpu 90 0 0 10\ncpu0 90 0 0 10\nbtime 10\n"
+        ));
+        data.push(RecordStream::new("cpu 90 0 0 10\ncpu0 90 0 0 10\n\
+                                     btime 10\n")).unwrap();
+        data.push(RecordStream::new("cpu 1 0 0 3\ncpu0 1 0 0 3\n\
+                                     btime 20\n")).unwrap();
+        let all_cpus = data.all_cpus().unwrap();
+        assert_eq!(all_cpus.busy_fraction(0, 1), Some(0.25));
+        assert_eq!(data.each_thread()[0].busy_fraction(0, 1), Some(0.25));
+
+        // Boot time jitter due to clock adjustments is not a reboot
+        let mut data = Data::new(RecordStream::new("ctxt 1\nbtime 100\n"));
+        for &btime in &[100, 101, 99, 101, 100] {
+            let file = format!("ctxt 1\nbtime {}\n", btime);
+            data.push(RecordStream::new(&file)).unwrap();
+        }
+        assert_eq!(data.reset_indices(), Vec::<usize>::new());
+        data.push(RecordStream::new("ctxt 1\nbtime 103\n")).unwrap();
+        assert_eq!(data.reset_indices(), vec![5]);
+    }
+
+    /// Check that the statistics are exported as properly named columns
+    #[test]
+    fn columns() {
+        let data = Data::from_samples(&[
+            "cpu 1 2 3 4\ncpu0 1 2 3 4\nintr 5 5 0\nctxt 42\n",
+            "cpu 2 3 4 5\ncpu0 2 3 4 5\nintr 9 7 2\nctxt 54\n",
+        ]);
+        let columns = data.columns();
+        let names: Vec<_> = columns.iter()
+                                   .map(|&(ref name, _)| &name[..])
+                                   .collect();
+        assert_eq!(names, vec!["cpu_user", "cpu_nice", "cpu_system",
+                               "cpu_idle", "cpu0_user", "cpu0_nice",
+                               "cpu0_system", "cpu0_idle", "intr_total",
+                               "intr_0", "intr_1", "ctxt"]);
+        let tick = cpu::ticks_to_duration(1);
+        let tick_nanos = tick.as_secs() * 1_000_000_000
+                         + tick.subsec_nanos() as u64;
+        assert_eq!(columns[7].1, Column::Nanos(vec![4 * tick_nanos,
+                                                    5 * tick_nanos]));
+        assert_eq!(columns[10].1, Column::U64(vec![0, 2]));
+        assert_eq!(columns[11].1, Column::U64(vec![42, 54]));
+    }
+
+    /// Check that the statistics can be gathered into a 2D array
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn array2() {
+        let data = Data::from_samples(&[
+            "cpu 1 2 3 4\ncpu0 1 2 3 4\nctxt 42\n",
+            "cpu 2 3 4 5\ncpu0 2 3 4 5\nctxt 54\n",
+            "cpu 3 4 5 6\ncpu0 3 4 5 6\nctxt 60\n",
+        ]);
+        let (array, names) = data.to_array2();
+        assert_eq!(array.dim(), (3, 9));
+        assert_eq!(names.len(), 9);
+        assert_eq!(names[8], "ctxt");
+        assert_eq!(array[[1, 8]], 54.);
+
+        let (cpu_array, cpu_names) = data.all_cpus().unwrap().to_array2();
+        assert_eq!(cpu_array.dim(), (3, 4));
+        assert_eq!(cpu_names, vec!["user", "nice", "system", "idle"]);
+        let tick_nanos = cpu::nanos_per_tick() as f64;
+        assert_eq!(cpu_array[[2, 3]], 6. * tick_nanos);
+    }
+
+    /// Check that the statistics can be gathered into an Arrow record batch
+    #[cfg(feature = "arrow")]
+    #[test]
+    fn record_batch() {
+        let data = Data::from_samples(&[
+            "cpu 1 2 3 4\ncpu0 1 2 3 4\nintr 7 3 0 0 4\nctxt 42\n",
+            "cpu 2 3 4 5\ncpu0 2 3 4 5\nintr 9 4 0 0 5\nctxt 54\n",
+            "cpu 3 4 5 6\ncpu0 3 4 5 6\nintr 11 5 0 0 6\nctxt 60\n",
+        ]);
+        let timestamps = [UNIX_EPOCH + Duration::new(1500000100, 0),
+                          UNIX_EPOCH + Duration::new(1500000101, 0),
+                          UNIX_EPOCH + Duration::new(1500000102, 0)];
+        let batch = data.to_record_batch(&timestamps)
+                        .expect("Failed to build a record batch");
+
+        // There should be one row per sample, and one column per field plus
+        // the timestamps, including the zeroed interrupt counters
+        let columns = data.columns();
+        assert_eq!(batch.num_rows(), 3);
+        assert_eq!(batch.num_columns(), columns.len() + 1);
+        let schema = batch.schema();
+        assert_eq!(schema.field(0).name(), "timestamp");
+        assert_eq!(schema.field(0).data_type(),
+                   &DataType::Timestamp(TimeUnit::Nanosecond, None));
+        for (field, &(ref name, _)) in schema.fields()
+                                             .iter()
+                                             .skip(1)
+                                             .zip(columns.iter()) {
+            assert_eq!(field.name(), name);
+            assert_eq!(field.data_type(), &DataType::UInt64);
+        }
+        assert!(batch.columns().iter().all(|column| column.len() == 3));
+    }
+
+    /// Check that the reported clock tick rate matches the observed one
+    #[test]
+    fn tick_calibration() {
+        assert_eq!(cpu::ticks_per_second() * cpu::nanos_per_tick(),
+                   1_000_000_000);
+        let calibration = super::calibrate_ticks_per_second(
+            Duration::from_millis(200)
+        ).expect("Failed to calibrate the clock tick rate");
+        assert_eq!(calibration.reported(), cpu::ticks_per_second());
+        let ratio = calibration.measured() / cpu::ticks_per_second() as f64;
+        assert!(ratio > 0.5 && ratio < 2.);
+        assert!(!calibration.is_mismatched());
+        let broken = TickCalibration { measured: 300., reported: 100 };
+        assert_eq!(broken.ratio(), 3.);
+        assert!(broken.is_mismatched());
+    }
+
+    /// Check that load imbalance across CPU threads is measured properly
+    #[test]
+    fn cpu_imbalance() {
+        // Without per-thread statistics, imbalance cannot be measured
+        let data = Data::from_samples(&["cpu 1 2 3 4\n", "cpu 2 3 4 5\n"]);
+        assert_eq!(data.cpu_imbalance_between(0, 1), None);
+
+        // With a lopsided load, cpu0 is 90% busy and cpu1 is 10% busy. cpu2
+        // is offline, and should not be mistaken for an idle CPU thread.
+        let data = Data::from_samples(&[
+            "cpu 7 0 0 3\ncpu0 0 0 0 0\ncpu1 0 0 0 0\ncpu2 7 0 0 3\n",
+            "cpu 102 0 5 103\ncpu0 90 0 0 10\ncpu1 5 0 5 90\ncpu2 7 0 0 3\n",
+        ]);
+        let imbalance = data.cpu_imbalance_between(0, 1).unwrap();
+        assert!((imbalance - 0.8).abs() < 1e-9);
+        assert_eq!(data.cpu_imbalance_between(1, 1), None);
+    }
+
+    /// Check that the statistics of parked CPU threads are stored sparsely
+    #[test]
+    fn parked_threads() {
+        // cpu1 and cpu3 are parked, so their CPU timers do not change
+        let data = Data::from_samples(&[
+            "cpu 1 0 2 4\ncpu0 1 0 1 1\ncpu1 0 0 1 1\n\
+             cpu2 0 0 0 1\ncpu3 0 0 0 1\n",
+            "cpu 5 0 3 6\ncpu0 4 0 2 1\ncpu1 0 0 1 1\n\
+             cpu2 1 0 0 2\ncpu3 0 0 0 1\n",
+            "cpu 9 0 4 8\ncpu0 7 0 3 1\ncpu1 0 0 1 1\n\
+             cpu2 2 0 0 3\ncpu3 0 0 0 1\n",
+        ]);
+        assert_eq!(data.thread_count(), 4);
+        assert_eq!(data.constant_thread_count(), 2);
+        let threads = data.each_thread()
+                          .iter()
+                          .map(|thread| thread.dense())
+                          .collect::<Vec<_>>();
+        assert_eq!(threads.len(), 4);
+        assert_eq!(threads[1].user_ticks(), &[0, 0, 0]);
+        assert_eq!(threads[1].system_ticks(), &[1, 1, 1]);
+        assert_eq!(threads[2].user_ticks(), &[0, 1, 2]);
+        assert_eq!(threads[3].idle_ticks(), &[1, 1, 1]);
+
+        // Sparse storage is transparent to analysis and comparisons
+        assert_eq!(data.total_idle_time(),
+                   vec![cpu::ticks_to_duration(4),
+                        cpu::ticks_to_duration(5),
+                        cpu::ticks_to_duration(6)]);
+        assert_eq!(data.cpu_imbalance_between(0, 2), Some(0.5));
+        assert!(data.value_eq(&data.clone()));
+    }
+
+    /// Check that CPU time is folded into flame graph stacks properly
+    #[test]
+    fn folded_stacks() {
+        // Per-thread statistics are folded by CPU thread and state, with the
+        // ticks summed over the whole capture
+        let data = Data::from_samples(&[
+            "cpu 5 0 2 10 1\ncpu0 3 0 1 5 1\ncpu1 2 0 1 5 0\n",
+            "cpu 9 0 4 15 1\ncpu0 5 0 2 7 1\ncpu1 4 0 2 8 0\n",
+            "cpu 30 1 6 20 3\ncpu0 20 1 3 8 3\ncpu1 10 0 3 12 0\n",
+        ]);
+        let mut output = Vec::new();
+        data.write_folded(&mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(),
+                   "cpu;core0;user 17\n\
+                    cpu;core0;nice 1\n\
+                    cpu;core0;system 2\n\
+                    cpu;core0;idle 3\n\
+                    cpu;core0;iowait 2\n\
+                    cpu;core1;user 8\n\
+                    cpu;core1;system 2\n\
+                    cpu;core1;idle 7\n");
+
+        // Without them, the aggregate over all CPUs is used
+        let data = Data::from_samples(&["cpu 1 2 3 4\n", "cpu 2 2 5 4\n"]);
+        let mut output = Vec::new();
+        data.write_folded(&mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(),
+                   "cpu;all;user 1\ncpu;all;system 2\n");
+
+        // With a single sample, no time was spent, so nothing is written
+        let data = Data::from_samples(&["cpu 1 2 3 4\n"]);
+        let mut output = Vec::new();
+        data.write_folded(&mut output).unwrap();
+        assert!(output.is_empty());
+
+        // Intervals during which the system rebooted are left out
+        let data = Data::from_samples(&[
+            "cpu 10 0 5 4\nbtime 10\n",
+            "cpu 12 0 5 6\nbtime 10\n",
+            "cpu 1 0 1 1\nbtime 20\n",
+            "cpu 4 0 2 1\nbtime 20\n",
+        ]);
+        let mut output = Vec::new();
+        data.write_folded(&mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(),
+                   "cpu;all;user 5\ncpu;all;system 1\ncpu;all;idle 2\n");
+    }
+
+    /// Check that schema equality is told apart from value equality
+    #[test]
+    fn structural_and_value_eq() {
+        const FILE_CONTENTS: &str = "cpu 2 4 6 8\ncpu0 1 2 3 4\n\
+                                     intr 42 40 2\nctxt 8\n";
+        let data = Data::from_samples(&[FILE_CONTENTS; 2]);
+        assert!(data.structural_eq(&data) && data.value_eq(&data));
+
+        // Different values with the same schema
+        let other_values = Data::from_samples(&[
+            FILE_CONTENTS,
+            "cpu 3 4 6 9\ncpu0 2 2 3 5\nintr 50 45 5\nctxt 12\n",
+        ]);
+        assert!(data.structural_eq(&other_values));
+        assert!(!data.value_eq(&other_values));
+
+        // Different schemas, from the record layout to the CPU timers and
+        // the interrupt sources
+        for &other_schema in &["cpu 2 4 6 8\ncpu0 1 2 3 4\nintr 42 40 2\n",
+                              "cpu 2 4 6 8 1\ncpu0 1 2 3 4 1\n\
+                               intr 42 40 2\nctxt 8\n",
+                              "cpu 2 4 6 8\ncpu0 1 2 3 4\n\
+                               intr 42 40 2 0\nctxt 8\n"] {
+            let other_schema = Data::from_samples(&[other_schema; 2]);
+            assert!(!data.structural_eq(&other_schema));
+            assert!(!data.value_eq(&other_schema));
+        }
+    }
+
+    /// Check that global CPU statistics can be rebuilt from per-thread ones
+    #[test]
+    fn reconstruct_aggregate() {
+        // If the global statistics are there, they should be used directly
+        let data = Data::from_samples(&["cpu 2 4 6 8\ncpu0 1 2 3 4\n"]);
+        assert_eq!(data.reconstruct_aggregate(), None);
+        let data = Data::from_samples(&["ctxt 42\n"]);
+        assert_eq!(data.reconstruct_aggregate(), None);
+
+        // Otherwise, per-thread statistics are summed, keeping only the timers
+        // which every thread provides
+        let data = Data::from_samples(&[
+            "cpu0 1 2 3 4 5 6\ncpu1 10 20 30 40 50\n",
+            "cpu0 2 3 4 5 6 7\ncpu1 20 30 40 50 60\n",
+        ]);
+        let aggregate = data.reconstruct_aggregate()
+                            .expect("Failed to reconstruct aggregate");
+        assert_eq!(aggregate.timer_count(), 5);
+        assert_eq!(aggregate.user_ticks(), &[1+10, 2+20]);
+        assert_eq!(aggregate.idle_ticks(), &[4+40, 5+50]);
+        assert_eq!(aggregate.io_wait_ticks(), Some(&[5+50, 6+60][..]));
+        assert_eq!(data.len(), 2);
+        assert_eq!(aggregate.irq_ticks(), None);
+    }
+
+    /// Check that utilization is aggregated by physical package and core
+    #[test]
+    fn topology_utilization() {
+        // Two packages with one hyperthreaded core each. Over 100 ticks per
+        // thread, cpu0 is 50% busy, cpu1 is 10% busy, cpu2 is idle, and cpu3
+        // is 30% busy. cpu4 is unknown to the topology and is thus ignored.
+        let data = Data::from_samples(&[
+            "cpu0 0 0 0 0\ncpu1 0 0 0 0\ncpu2 0 0 0 0\ncpu3 0 0 0 0\n\
+             cpu4 0 0 0 0\n",
+            "cpu0 30 0 20 50\ncpu1 10 0 0 90\ncpu2 0 0 0 100\n\
+             cpu3 0 0 30 70\ncpu4 100 0 0 0\n",
+        ]);
+        let location = |processor, physical_id| ThreadLocation {
+            processor,
+            physical_id,
+            core_id: 0,
+        };
+        let topology = CpuTopology::new(vec![location(0, 0), location(1, 1),
+                                             location(2, 0), location(3, 1)]);
+        assert_eq!(data.package_utilization_between(0, 1, &topology),
+                   vec![0.25, 0.2]);
+        assert_eq!(data.core_utilization_between(0, 1, &topology),
+                   vec![((0, 0), 0.25), ((1, 0), 0.2)]);
+
+        // No accounted CPU time means idle, and no per-thread breakdown means
+        // that there is nothing to be computed
+        assert_eq!(data.package_utilization_between(1, 1, &topology),
+                   vec![0., 0.]);
+        let data = Data::from_samples(&["cpu 1 2 3 4\n"]);
+        assert!(data.package_utilization_between(0, 0, &topology).is_empty());
+        assert!(data.core_utilization_between(0, 0, &topology).is_empty());
+    }
+
+    /// Check that utilization can be measured against a cgroup CPU quota
+    #[test]
+    fn quota_utilization() {
+        // Four threads, which are 25% busy on average, so one CPU's worth of
+        // CPU time is used
+        let data = Data::from_samples(&[
+            "cpu 0 0 0 0\ncpu0 0 0 0 0\ncpu1 0 0 0 0\ncpu2 0 0 0 0\n\
+             cpu3 0 0 0 0\n",
+            "cpu 60 0 40 300\ncpu0 50 0 0 50\ncpu1 10 0 40 50\n\
+             cpu2 0 0 0 100\ncpu3 0 0 0 100\n",
+        ]);
+        assert_eq!(data.quota_utilization_between(0, 1, 1.), Some(1.));
+        assert_eq!(data.quota_utilization_between(0, 1, 2.), Some(0.5));
+        assert_eq!(data.quota_utilization_between(0, 1, 0.5), Some(2.));
+
+        // No accounted CPU time or no per-thread breakdown means that there
+        // is nothing to be computed
+        assert_eq!(data.quota_utilization_between(1, 1, 1.), None);
+        let data = Data::from_samples(&["cpu 1 2 3 4\n"]);
+        assert_eq!(data.quota_utilization_between(0, 0, 1.), None);
+    }
+
+    /// Check that utilization can be weighted by CPU frequency
+    #[test]
+    fn frequency_weighted_utilization() {
+        // Over 100 ticks per thread, cpu0 is fully busy at a quarter of its
+        // maximal frequency, cpu1 is half busy at its maximal frequency, and
+        // the maximal frequency of cpu2 is unknown
+        let data = Data::from_samples(&[
+            "cpu0 0 0 0 0\ncpu1 0 0 0 0\ncpu2 0 0 0 0\n",
+            "cpu0 100 0 0 0\ncpu1 25 0 25 50\ncpu2 100 0 0 0\n",
+        ]);
+        assert_eq!(
+            data.frequency_weighted_utilization_between(0, 1,
+                                                        &[800., 3200., 800.],
+                                                        &[3200., 3200., 0.]),
+            vec![0.25, 0.5, 0.]
+        );
+
+        // Without per-thread statistics, there is nothing to be computed
+        let data = Data::from_samples(&["cpu 1 2 3 4\n"]);
+        assert!(data.frequency_weighted_utilization_between(0, 0, &[], &[])
+                    .is_empty());
+    }
+
+    /// Check that run queue saturation is computed from runnable processes
+    #[test]
+    fn runqueue_saturation() {
+        let data = Data::from_samples(&["ctxt 8\n"]);
+        assert_eq!(data.runqueue_saturation(4, 0), None);
+        let data = Data::from_samples(&["procs_running 2\n",
+                                        "procs_running 6\n"]);
+        assert_eq!(data.runqueue_saturation(4, 0), Some(0.5));
+        assert_eq!(data.runqueue_saturation(4, 1), Some(1.5));
+    }
+
+    /// Check that activity scores are computed and renormalized correctly
+    #[test]
+    fn activity_score() {
+        // Over 100 ticks per CPU thread, the CPU is 25% busy and the run queue
+        // is saturated. Context switches are not reported at first.
+        const OLDER: &str = "cpu 0 0 0 0\ncpu0 0 0 0 0\ncpu1 0 0 0 0\n\
+                             procs_running 1\n";
+        const NEWER: &str = "cpu 50 0 0 150\ncpu0 40 0 0 60\n\
+                             cpu1 10 0 0 90\nprocs_running 4\n";
+        let data = Data::from_samples(&[OLDER, NEWER]);
+        let weights = ActivityWeights::default();
+        let score = data.activity_score_between(0, 1, &weights).unwrap();
+        assert!((score - (0.4 * 0.25 + 0.2 * 1.) / 0.6).abs() < 1e-9);
+        assert_eq!(data.activity_score_between(1, 1, &weights),
+                   Some(1.),
+                   "Only the run queue is known without elapsed CPU time");
+
+        // Now, report context switches instead of the run queue, at a rate of
+        // 10000/s per CPU thread
+        let ctxt_delta = 2 * 10_000 * 100 / cpu::ticks_per_second();
+        let older = "cpu 0 0 0 0\ncpu0 0 0 0 0\ncpu1 0 0 0 0\nctxt 0\n";
+        let newer = format!("cpu 50 0 0 150\ncpu0 40 0 0 60\n\
+                             cpu1 10 0 0 90\nctxt {}\n", ctxt_delta);
+        let data = Data::from_samples(&[older, &newer]);
+        let ctxt_only = ActivityWeights {
+            cpu_busy: 0.,
+            context_switches: 1.,
+            interrupts: 0.,
+            run_queue: 0.,
+        };
+        let score = data.activity_score_between(0, 1, &ctxt_only).unwrap();
+        assert!((score - 0.5).abs() < 1e-9);
+        let score = data.activity_score_between(0, 1, &weights).unwrap();
+        assert!((score - (0.4 * 0.25 + 0.2 * 0.5) / 0.6).abs() < 1e-9);
+
+        // Indicators which are not available cannot carry the score alone
+        let intr_only = ActivityWeights {
+            cpu_busy: 0.,
+            context_switches: 0.,
+            interrupts: 1.,
+            run_queue: 0.,
+        };
+        assert_eq!(data.activity_score_between(0, 1, &intr_only), None);
+    }
+
+    /// Check that the number of online CPU threads is reported for each sample
+    #[test]
+    fn online_cpu_count() {
+        // Without per-thread records, no CPU thread is reported as online
+        let data = Data::from_samples(&["cpu 1 2 3 4\n", "cpu 2 3 4 5\n"]);
+        assert_eq!(data.online_cpu_count(), vec![0, 0]);
+
+        // Otherwise, every per-thread record is counted
+        const FILE_CONTENTS: &str = "cpu 2 4 6 8\n\
+                                     cpu0 1 2 3 4\n\
+                                     cpu1 1 2 3 4\n";
+        let data = Data::from_samples(&[FILE_CONTENTS; 3]);
+        assert_eq!(data.online_cpu_count(), vec![2, 2, 2]);
+    }
+
+    /// Check that CPU timer count changes are reported as schema changes
+    #[test]
+    fn cpu_timer_count_change() {
+        // Sample a file with 4 CPU timers
+        const FILE_CONTENTS: &str = "cpu 2 4 6 8\n\
+                                     cpu0 1 2 3 4\n\
+                                     ctxt 8\n";
+        let mut data = Data::from_samples(&[FILE_CONTENTS]);
+        let expected = data.clone();
+
+        // A new timer on a CPU thread should be reported without storing
+        // anything, even though the global CPU record was fine
+        let new_timer = "cpu 2 4 6 8\ncpu0 1 2 3 4 5\nctxt 9\n";
+        let error = data.push(RecordStream::new(new_timer)).unwrap_err();
+        assert_eq!(data, expected);
+
+        // Samplers report this error as an I/O error
+        let io_error = io::Error::from(error.clone());
+        assert_eq!(io_error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(io_error.get_ref()
+                           .and_then(|e| e.downcast_ref::<SchemaChanged>()),
+                   Some(&error));
+    }
+
+    /// Check that empty or truncated readouts are reported as schema changes,
+    /// without leaving a partial sample behind
+    #[test]
+    fn missing_records() {
+        const FILE_CONTENTS: &str = "cpu 2 4 6 8\n\
+                                     cpu0 1 2 3 4\n\
+                                     ctxt 8\n";
+        let mut data = Data::from_samples(&[FILE_CONTENTS]);
+        let expected = data.clone();
+        assert!(data.push(RecordStream::new("")).is_err());
+        assert_eq!(data, expected);
+        let truncated = "cpu 3 4 6 9\ncpu0 2 2 3 5\n";
+        assert!(data.push(RecordStream::new(truncated)).is_err());
+        assert_eq!(data, expected);
+        assert_eq!(data.len(), 1);
+    }
+
+    /// Check that bulk pushes are equivalent to individual pushes
+    #[test]
+    fn bulk_push() {
+        // Push a batch of samples, and compare with individual pushes
+        const SAMPLES: [&str; 3] = ["cpu 2 4 6 8\ncpu0 1 2 3 4\nctxt 8\n",
+                                    "cpu 3 4 6 9\ncpu0 2 2 3 5\nctxt 12\n",
+                                    "cpu 5 4 7 9\ncpu0 4 2 4 5\nctxt 20\n"];
+        let mut data = Data::new(RecordStream::new(SAMPLES[0]));
+        data.push_many(SAMPLES.iter().map(|&sample| RecordStream::new(sample)))
+            .unwrap();
+        assert_eq!(data, Data::from_samples(&SAMPLES));
+
+        // On CPU timer changes, the samples before the change are kept
+        let new_timer = "cpu 6 4 7 9\ncpu0 5 2 4 5 1\nctxt 21\n";
+        let mut data = Data::new(RecordStream::new(SAMPLES[0]));
+        assert!(data.push_many([SAMPLES[0], SAMPLES[1], new_timer, SAMPLES[2]]
+                                   .iter()
+                                   .map(|&sample| RecordStream::new(sample)))
+                    .is_err());
+        assert_eq!(data, Data::from_samples(&SAMPLES[..2]));
+    }
+
+    /// Check that records can be filtered out to save memory
+    #[test]
+    fn record_filter() {
+        // Only keep the context switch counter of a mock /proc/stat
+        const SAMPLES: [&str; 2] = ["cpu 2 4 6 8\n\
+                                     cpu0 1 2 3 4\n\
+                                     intr 5 5 0\n\
+                                     ctxt 8\n\
+                                     btime 1\n\
+                                     processes 6\n\
+                                     procs_running 1\n\
+                                     procs_blocked 0\n",
+                                    "cpu 3 4 6 9\n\
+                                     cpu0 2 2 3 5\n\
+                                     intr 9 7 2\n\
+                                     ctxt 12\n\
+                                     btime 1\n\
+                                     processes 7\n\
+                                     procs_running 2\n\
+                                     procs_blocked 0\n"];
+        let mut data = Data::new(RecordStream::new(SAMPLES[0]));
+        data.keep_records(&[RecordKind::ContextSwitches]);
+        for &sample in SAMPLES.iter() {
+            data.push(RecordStream::new(sample)).unwrap();
+        }
+        assert_eq!(data.len(), 2);
+        assert_eq!(data.context_switches(), Some(&[8, 12][..]));
+        assert!(data.all_cpus().is_none());
+        assert_eq!(data.thread_count(), 0);
+        assert!(!data.has_interrupts());
+        assert!(!data.has_process_forks());
+        assert!(!data.has_runnable_processes());
+        assert!(!data.has_blocked_processes());
+
+        // The boot time is always kept, for reboot detection purposes
+        assert_eq!(data.boot_time(), Some(Utc.timestamp(1, 0)));
+
+        // Discarded lines are still expected to be there, in the same order
+        assert_eq!(data.line_target,
+                   vec![RecordKind::Unsupported("cpu".to_owned()),
+                        RecordKind::Unsupported("cpu0".to_owned()),
+                        RecordKind::Unsupported("intr".to_owned()),
+                        RecordKind::ContextSwitches,
+                        RecordKind::BootTime,
+                        RecordKind::Unsupported("processes".to_owned()),
+                        RecordKind::Unsupported("procs_running".to_owned()),
+                        RecordKind::Unsupported("procs_blocked".to_owned())]);
+
+        // CPU thread records are kept as a whole
+        let mut data = Data::new(RecordStream::new(SAMPLES[0]));
+        data.keep_records(&[RecordKind::CPUThread(42)]);
+        data.push(RecordStream::new(SAMPLES[1])).unwrap();
+        assert!(data.all_cpus().is_none());
+        assert_eq!(data.thread_count(), 1);
+        assert!(!data.has_context_switches());
+
+        // Samplers can be told to filter records too
+        let mut sampler = Sampler::new()
+                                  .expect("Failed to create a sampler")
+                                  .with_record_filter(
+                                      &[RecordKind::ContextSwitches]
+                                  );
+        sampler.sample().expect("Failed to acquire a sample");
+        assert_eq!(sampler.samples().len(), 1);
+        assert!(sampler.samples().all_cpus().is_none());
+        assert!(sampler.samples().context_switches().is_some());
+    }
+
+    /// Check that the anomalies of a capture are reported
+    #[test]
+    fn health_report() {
+        // A healthy capture has no anomaly
+        let mut sampler = Sampler::new().expect("Failed to create a sampler");
+        sampler.sample().expect("Failed to acquire a sample");
+        assert!(sampler.health_report().is_healthy());
+
+        // Simulate a capture with a schema change, a counter which goes
+        // backwards, and a reboot
+        let mut contents = vec!["cpu 1 2 3 4\nctxt 100\nbtime 1\n",
+                                "cpu 1 2 3 4\nctxt 100\nbtime 1\n",
+                                "cpu 2 3 4 5\nctxt 120\nbtime 1\n",
+                                "cpu 3 4 5 6 7\nctxt 130\nbtime 1\n",
+                                "cpu 3 4 5 6\nctxt 110\nbtime 1\n",
+                                "cpu 1 1 1 1\nctxt 10\nbtime 100\n",
+                                "cpu 2 2 2 2\nctxt 20\nbtime 100\n"]
+                                   .into_iter();
+        let mut sampler = Sampler::from_source(move || {
+            Ok(contents.next().expect("Sampled too much").to_owned())
+        }).expect("Failed to create a sampler");
+        for _ in 0..6 {
+            let _ = sampler.sample();
+        }
+        assert_eq!(sampler.schema_change_count(), 1);
+        assert_eq!(sampler.samples().len(), 5);
+        assert_eq!(sampler.samples().non_monotonic_intervals(), vec![1]);
+
+        // Make sampling intervals regular, except for one hiccup
+        let start = ::std::time::Instant::now();
+        sampler.timestamps =
+            [0, 10, 20, 30, 530].iter()
+                                .map(|&ms| start + Duration::from_millis(ms))
+                                .collect();
+        let report = sampler.health_report();
+        assert_eq!(report, super::HealthReport {
+            reboots: 1,
+            schema_changes: 1,
+            non_monotonic_intervals: 1,
+            interval_outliers: 1,
+        });
+        assert!(!report.is_healthy());
+    }
+
+    /// Check that "latest only" samplers keep two samples in constant memory
+    #[test]
+    fn latest_only_sampling() {
+        let mut sampler = Sampler::new_latest_only()
+                                  .expect("Failed to create a sampler");
+        sampler.sample().expect("Failed to acquire a sample");
+        assert_eq!(sampler.samples().len(), 1);
+        sampler.sample().expect("Failed to acquire a sample");
+
+        // Once both slots are filled, neither the timestamps nor the data
+        // columns should be reallocated anymore
+        let timestamps_capacity = sampler.timestamps.capacity();
+        let columns = |sampler: &Sampler| {
+            let data = &sampler.samples;
+            (data.all_cpus.as_ref().map(|cpu| cpu.user_ticks().as_ptr()),
+             data.each_thread.first().map(|cpu| {
+                 cpu.stored_samples().user_ticks().as_ptr()
+             }),
+             data.context_switches.as_ref().map(|ctxt| ctxt.as_ptr()),
+             data.context_switches.as_ref().map(|ctxt| ctxt.capacity()))
+        };
+        let initial_columns = columns(&sampler);
+        for _ in 0..50 {
+            sampler.sample().expect("Failed to acquire a sample");
+            assert_eq!(sampler.samples().len(), 2);
+            assert_eq!(sampler.timestamps().len(), 2);
+            assert_eq!(columns(&sampler), initial_columns);
+        }
+        assert_eq!(sampler.timestamps.capacity(), timestamps_capacity);
+
+        // Deltas between the two remaining samples can still be computed
+        let samples = sampler.samples();
+        if let Some(ctxt) = samples.context_switches() {
+            assert_eq!(samples.delta(ctxt, 0, 1), ctxt[1] - ctxt[0]);
+        }
+    }
+
+    /// Check that the idle time is summed across CPU threads
+    #[test]
+    fn total_idle_time() {
+        // Without CPU statistics, no idle time is reported
+        let data = Data::from_samples(&["ctxt 8\n", "ctxt 9\n"]);
+        assert_eq!(data.total_idle_time(), Vec::new());
+
+        // The global CPU statistics are used in the absence of CPU threads...
+        let data = Data::from_samples(&["cpu 1 2 3 50\n", "cpu 1 2 3 70\n"]);
+        assert_eq!(data.total_idle_time(),
+                   vec![cpu::ticks_to_duration(50),
+                        cpu::ticks_to_duration(70)]);
+
+        // ...but per-thread statistics take precedence when available
+        const FILE_CONTENTS: &str = "cpu 2 4 6 99\n\
+                                     cpu0 1 2 3 40\n\
+                                     cpu1 1 2 3 60\n";
+        let data = Data::from_samples(&[FILE_CONTENTS]);
+        assert_eq!(data.total_idle_time(), vec![cpu::ticks_to_duration(100)]);
+    }
+
+    /// Check that the softirq-to-hardirq ratio is computed correctly
+    #[test]
+    fn softirq_to_hardirq_ratio() {
+        // Both kinds of interrupt statistics are needed
+        let data = Data::from_samples(&["intr 10 10\n", "intr 20 20\n"]);
+        assert_eq!(data.softirq_to_hardirq_ratio_between(0, 1), None);
+        let data = Data::from_samples(&["softirq 10 10\n", "softirq 20 20\n"]);
+        assert_eq!(data.softirq_to_hardirq_ratio_between(0, 1), None);
+
+        // If both are present, the ratio of total deltas is reported...
+        let data = Data::from_samples(&["intr 10 10\nsoftirq 40 40\n",
+                                        "intr 20 20\nsoftirq 70 70\n",
+                                        "intr 20 20\nsoftirq 90 90\n"]);
+        assert_eq!(data.softirq_to_hardirq_ratio_between(0, 1), Some(3.0));
+        assert_eq!(data.softirq_to_hardirq_ratio_between(0, 2), Some(5.0));
+
+        // ...unless no hardware interrupt was serviced in the meantime
+        assert_eq!(data.softirq_to_hardirq_ratio_between(1, 2), None);
+    }
+
+    /// Check that the presence of optional statistics is reported correctly
+    #[test]
+    fn field_presence() {
+        // An empty file provides nothing
+        let data = Data::from_samples(&[""]);
+        assert!(!data.has_all_cpus());
+        assert!(!data.has_per_cpu());
+        assert!(!data.has_context_switches());
+        assert!(!data.has_softirqs());
+        assert_eq!(data.cpu_timer_count(), 0);
+
+        // A richer file provides more
+        const FILE_CONTENTS: &str = "cpu 1 2 3 4 5 6 7\n\
+                                     cpu0 1 2 3 4 5 6 7\n\
+                                     ctxt 8\n\
+                                     procs_running 1\n";
+        let data = Data::from_samples(&[FILE_CONTENTS]);
+        assert!(data.has_all_cpus());
+        assert!(data.has_per_cpu());
+        assert!(!data.has_paging());
+        assert!(!data.has_swapping());
+        assert!(!data.has_interrupts());
+        assert!(data.has_context_switches());
+        assert!(!data.has_boot_time());
+        assert!(!data.has_process_forks());
+        assert!(data.has_runnable_processes());
+        assert!(!data.has_blocked_processes());
+        assert!(!data.has_softirqs());
+        assert_eq!(data.cpu_timer_count(), 7);
+    }
+
+    /// Check that sampled data survives a round trip through binary storage
+    #[cfg(feature = "binary")]
+    #[test]
+    fn binary_round_trip() {
+        // Sample a file with most record kinds, including mostly-zero IRQs
+        const FILE_CONTENTS: &str = "cpu 1 2 3 4 5 6 7 8 9 10\n\
+                                     cpu0 1 2 3 4\n\
+                                     page 5 6\n\
+                                     swap 7 8\n\
+                                     intr 7 0 0 0 4 0 0\n\
+                                     ctxt 8\n\
+                                     btime 5738295\n\
+                                     processes 11\n\
+                                     procs_running 1\n\
+                                     procs_blocked 2\n\
+                                     softirq 9 0 9\n";
+        let data = Data::from_samples(&[FILE_CONTENTS; 4]);
+
+        // Check that the data goes through binary storage unchanged
+        let mut buffer = Vec::new();
+        data.write_binary(&mut buffer).expect("Failed to write binary data");
+        let data2 = Data::read_binary(&buffer[..])
+                         .expect("Failed to read binary data");
+        assert_eq!(data2, data);
+
+        // Check that runs of zero interrupt counts are stored compactly
+        let mut intr_line = "intr 1".to_owned();
+        for _ in 0..500 {
+            intr_line.push_str(" 0");
+        }
+        let data = Data::from_samples(&[&intr_line[..]; 100]);
+        let mut buffer = Vec::new();
+        data.write_binary(&mut buffer).expect("Failed to write binary data");
+        assert_eq!(Data::read_binary(&buffer[..]).unwrap(), data);
+        assert!(buffer.len() * 10 < intr_line.len() * 100);
+
+        // Check that out-of-range boot times are rejected
+        let data = Data::from_samples(&["btime 5738295\n"]);
+        let mut buffer = Vec::new();
+        data.write_binary(&mut buffer).expect("Failed to write binary data");
+        let boot_time = 5738295i64.to_le_bytes();
+        let position = buffer.windows(8)
+                             .position(|bytes| bytes == boot_time)
+                             .expect("Boot time should be stored");
+        buffer[position..position+8].copy_from_slice(&i64::MAX.to_le_bytes());
+        let error = Data::read_binary(&buffer[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        // Check that other data is rejected
+        assert!(Data::read_binary(&b"PFMI\x01"[..]).is_err());
+    }
+
+    /// Check that the latest sample is exported in Prometheus format
+    #[cfg(feature = "prometheus")]
+    #[test]
+    fn prometheus_export() {
+        // Nothing is exported until something has been sampled
+        const FILE_CONTENTS: &str = "cpu 300 0 200 500 0 0 0 0 0 0\n\
+                                     cpu0 100 0 100 300 0 0 0 0 0 0\n\
+                                     cpu1 200 0 100 200 0 0 0 0 0 0\n\
+                                     intr 42 40 2\n\
+                                     ctxt 67890\n\
+                                     btime 1500000000\n\
+                                     processes 1234\n\
+                                     procs_running 3\n\
+                                     procs_blocked 1\n";
+        let mut data = Data::new(RecordStream::new(FILE_CONTENTS));
+        let mut output = Vec::new();
+        data.write_prometheus(&mut output).unwrap();
+        assert!(output.is_empty());
+
+        // Only the latest sample is exported
+        data.push(RecordStream::new(FILE_CONTENTS)).unwrap();
+        data.push(RecordStream::new(
+            &FILE_CONTENTS.replace("cpu0 100", "cpu0 150")
+                          .replace("ctxt 67890", "ctxt 70000")
+        )).unwrap();
+        data.write_prometheus(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let ticks_per_sec = cpu::ticks_per_second() as f64;
+        let expected_lines = [
+            "# TYPE node_cpu_seconds_total counter".to_owned(),
+            format!("node_cpu_seconds_total{{cpu=\"0\",mode=\"user\"}} {}",
+                    150. / ticks_per_sec),
+            format!("node_cpu_seconds_total{{cpu=\"1\",mode=\"idle\"}} {}",
+                    200. / ticks_per_sec),
+            "# TYPE node_cpu_guest_seconds_total counter".to_owned(),
+            "# TYPE node_intr_total counter".to_owned(),
+            "node_intr_total 42".to_owned(),
+            "# TYPE node_context_switches_total counter".to_owned(),
+            "node_context_switches_total 70000".to_owned(),
+            "node_forks_total 1234".to_owned(),
+            "# TYPE node_boot_time_seconds gauge".to_owned(),
+            "node_boot_time_seconds 1500000000".to_owned(),
+            "# TYPE node_procs_running gauge".to_owned(),
+            "node_procs_running 3".to_owned(),
+            "node_procs_blocked 1".to_owned(),
+        ];
+        for line in &expected_lines {
+            assert!(output.lines().any(|l| l == line), "Missing {}", line);
+        }
+        assert!(!output.contains("node_cpu_seconds_total{cpu=\"2\""));
+        assert!(!output.contains("node_softirqs_total"));
+    }
+
+    /// Check that samples can be exported as InfluxDB line protocol
+    #[cfg(feature = "influx")]
+    #[test]
+    fn influx_export() {
+        const FILE_CONTENTS: &str = "cpu 300 0 200 500\n\
+                                     cpu0 100 0 100 300\n\
+                                     cpu1 200 0 100 200\n\
+                                     ctxt 67890\n\
+                                     btime 1500000000\n\
+                                     processes 1234\n\
+                                     procs_running 3\n\
+                                     procs_blocked 1\n";
+        let mut data = Data::new(RecordStream::new(FILE_CONTENTS));
+        data.push(RecordStream::new(FILE_CONTENTS)).unwrap();
+        data.push(RecordStream::new(
+            &FILE_CONTENTS.replace("cpu0 100", "cpu0 150")
+                          .replace("ctxt 67890", "ctxt 70000")
+        )).unwrap();
+        let timestamps = [UNIX_EPOCH + Duration::new(1500000100, 0),
+                          UNIX_EPOCH + Duration::new(1500000101, 500)];
+        let mut output = Vec::new();
+        data.write_influx_line("proc stat", &timestamps, &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let ns = |ticks: u64| ticks * cpu::nanos_per_tick();
+        let cpu_line = |core: &str, user, system, idle, time| {
+            format!("proc\\ stat,core={} user_ns={}i,nice_ns=0i,\
+                     system_ns={}i,idle_ns={}i {}",
+                    core, ns(user), ns(system), ns(idle), time)
+        };
+        let expected_lines = [
+            cpu_line("0", 100, 100, 300, "1500000100000000000"),
+            cpu_line("1", 200, 100, 200, "1500000100000000000"),
+            cpu_line("all", 300, 200, 500, "1500000100000000000"),
+            "proc\\ stat context_switches=67890i,forks=1234i,\
+             procs_running=3i,procs_blocked=1i 1500000100000000000"
+                .to_owned(),
+            cpu_line("0", 150, 100, 300, "1500000101000000500"),
+            cpu_line("1", 200, 100, 200, "1500000101000000500"),
+            cpu_line("all", 300, 200, 500, "1500000101000000500"),
+            "proc\\ stat context_switches=70000i,forks=1234i,\
+             procs_running=3i,procs_blocked=1i 1500000101000000500"
+                .to_owned(),
+        ];
+        assert_eq!(output.lines().collect::<Vec<_>>(), expected_lines);
+    }
+
+    /// Check that the newline-delimited JSON export works
+    #[cfg(feature = "ndjson")]
+    #[test]
+    fn ndjson_export() {
+        const FILE_CONTENTS: &str = "cpu 300 0 200 500\n\
+                                     cpu0 100 0 100 300\n\
+                                     cpu1 200 0 100 200\n\
+                                     ctxt 67890\n\
+                                     btime 1500000000\n";
+        let mut data = Data::new(RecordStream::new(FILE_CONTENTS));
+        data.push(RecordStream::new(FILE_CONTENTS)).unwrap();
+        data.push(RecordStream::new(
+            &FILE_CONTENTS.replace("cpu0 100", "cpu0 150")
+                          .replace("ctxt 67890", "ctxt 70000")
+        )).unwrap();
+        let timestamps = [UNIX_EPOCH + Duration::new(1500000100, 0),
+                          UNIX_EPOCH + Duration::new(1500000101, 500)];
+        let mut output = Vec::new();
+        data.write_ndjson(&timestamps, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        // There should be one valid JSON object per sample
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), data.len());
+        for line in lines.iter() {
+            let value: serde_json::Value = serde_json::from_str(line)
+                .unwrap_or_else(|error| panic!("Invalid JSON {}: {}",
+                                               line, error));
+            assert!(value.is_object(), "Not a JSON object: {}", line);
+        }
+
+        // Per-thread statistics should be gathered into arrays
+        let ns = |ticks: u64| ticks * cpu::nanos_per_tick();
+        assert_eq!(lines[1], format!(
+            "{{\"timestamp_ns\":1500000101000000500,\
+              \"cpu_user\":{},\"cpu_nice\":0,\"cpu_system\":{},\
+              \"cpu_idle\":{},\"each_thread_user\":[{},{}],\
+              \"each_thread_nice\":[0,0],\"each_thread_system\":[{},{}],\
+              \"each_thread_idle\":[{},{}],\"ctxt\":70000}}",
+            ns(300), ns(200), ns(500), ns(150), ns(200), ns(100), ns(100),
+            ns(300), ns(200)
+        ));
+    }
+
+    /// Check that the decimated CSV export works
+    #[cfg(feature = "csv")]
+    #[test]
+    fn decimated_csv_export() {
+        const FILE_CONTENTS: &str = "cpu 300 0 200 500\n\
+                                     cpu0 300 0 200 500\n\
+                                     ctxt 67890\n\
+                                     btime 1500000000\n";
+        let mut data = Data::new(RecordStream::new(FILE_CONTENTS));
+        for ctxt in 0..5 {
+            data.push(RecordStream::new(
+                &FILE_CONTENTS.replace("67890", &ctxt.to_string())
+            )).unwrap();
+        }
+        let export = |factor: usize| {
+            let mut output = Vec::new();
+            data.export_decimated_csv(factor, &mut output).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        // Every sample is exported with a factor of 1
+        let output = export(1);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), data.len() + 1);
+        assert_eq!(lines[0], "cpu_user,cpu_nice,cpu_system,cpu_idle,\
+                              cpu0_user,cpu0_nice,cpu0_system,cpu0_idle,ctxt");
+
+        // Otherwise, one sample out of every "factor" is exported
+        let output = export(2);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3 + 1);
+        let ctxts: Vec<&str> = lines[1..].iter()
+                                         .map(|line| line.rsplit(',')
+                                                         .next()
+                                                         .unwrap())
+                                         .collect();
+        assert_eq!(ctxts, vec!["0", "2", "4"]);
+        assert_eq!(export(5).lines().count(), 1 + 1);
+        assert_eq!(export(6).lines().count(), 1 + 1);
+
+        // The data store is left untouched
+        assert_eq!(data.len(), 5);
+    }
+
+    /// Check that a partially stored sample can be dropped to repair the data
+    #[test]
+    fn truncate_to_consistent() {
+        // Sample a simple file twice, then simulate a partial third sample
+        const FILE_CONTENTS: &str = "cpu 1 2 3 4\n\
+                                     page 5 6\n\
+                                     intr 7 3 4\n\
+                                     ctxt 8\n\
+                                     btime 5738295\n\
+                                     processes 11\n";
+        let mut data = Data::new(RecordStream::new(FILE_CONTENTS));
+        data.push(RecordStream::new(FILE_CONTENTS)).unwrap();
+        data.push(RecordStream::new(FILE_CONTENTS)).unwrap();
+        let complete_data = data.clone();
+        data.context_switches.as_mut().unwrap().push(9);
+        data.process_forks.as_mut().unwrap().push(10);
+        assert_eq!(data.min_len(), 2);
+
+        // Repairing should get us back to the complete samples
+        assert_eq!(data.truncate_to_consistent(), 2);
+        assert_eq!(data, complete_data);
+        assert_eq!(data.len(), 2);
+
+        // Missing samples should be handled in the same way
+        data.paging.as_mut().unwrap().drop_oldest(1);
+        assert_eq!(data.truncate_to_consistent(), 1);
+        assert_eq!(data.len(), 1);
+    }
+
     /// Build the record structure associated with a certain line of text
     fn with_record<F, R>(line_of_text: &str, functor: F) -> R
         where F: FnOnce(Record) -> R
@@ -1185,7 +4138,107 @@ mod tests {
 ///
 #[cfg(test)]
 mod benchmarks {
+    use super::{Data, RecordStream};
+
     define_sampler_benchs!{ super::Sampler,
                             "/proc/stat",
                             100_000 }
+
+    /// Number of samples in the replay benchmarks
+    const REPLAY_LENGTH: usize = 1_000;
+
+    /// Benchmark for storing a replayed capture with individual pushes...
+    #[test]
+    #[ignore]
+    fn replay_single_pushes() {
+        let contents = read_contents();
+        testbench::benchmark(100, || {
+            let mut data = Data::new(RecordStream::new(&contents));
+            for _ in 0..REPLAY_LENGTH {
+                data.push(RecordStream::new(&contents))
+                    .expect("Failed to push a sample");
+            }
+        });
+    }
+
+    /// ...and with a bulk push
+    #[test]
+    #[ignore]
+    fn replay_bulk_push() {
+        let contents = read_contents();
+        testbench::benchmark(100, || {
+            let mut data = Data::new(RecordStream::new(&contents));
+            data.push_many((0..REPLAY_LENGTH).map(|_| {
+                RecordStream::new(&contents)
+            })).expect("Failed to push samples");
+        });
+    }
+
+    /// Number of CPU threads of the simulated many-core host
+    const MANY_CORE_THREADS: usize = 256;
+
+    /// Benchmark for storing a capture of a many-core host where all CPU
+    /// threads but one are parked...
+    #[test]
+    #[ignore]
+    fn many_core_idle_push() {
+        let samples = many_core_idle_samples();
+        testbench::benchmark(100, || {
+            store_samples(&samples);
+        });
+    }
+
+    /// ...and comparison of the resulting memory footprint with that of a
+    /// dense storage of each CPU thread's statistics
+    #[test]
+    #[ignore]
+    fn many_core_idle_footprint() {
+        let data = store_samples(&many_core_idle_samples());
+        assert_eq!(data.constant_thread_count(), MANY_CORE_THREADS - 1);
+        let (sparse, dense) =
+            data.each_thread
+                .iter()
+                .map(|thread| thread.footprint())
+                .fold((0, 0), |(sparse, dense), (thread_sparse, thread_dense)| {
+                    (sparse + thread_sparse, dense + thread_dense)
+                });
+        assert!(sparse * 100 < dense,
+                "CPU thread storage: {} bytes, versus {} bytes if dense",
+                sparse, dense);
+    }
+
+    /// INTERNAL: Simulate the /proc/stat contents of a many-core host where
+    ///           all CPU threads but the first one are parked
+    fn many_core_idle_samples() -> Vec<String> {
+        (0..REPLAY_LENGTH+1).map(|sample| {
+            let ticks = sample * 100;
+            let mut contents = format!("cpu {} 0 {} 0 0 0 0 0 0 0\n\
+                                        cpu0 {} 0 {} 0 0 0 0 0 0 0\n",
+                                       ticks, ticks, ticks, ticks);
+            for thread in 1..MANY_CORE_THREADS {
+                contents.push_str(&format!("cpu{} 0 0 0 0 0 0 0 0 0 0\n",
+                                           thread));
+            }
+            contents
+        }).collect()
+    }
+
+    /// INTERNAL: Store some samples of /proc/stat, using the first one to
+    ///           set up the data store
+    fn store_samples(samples: &[String]) -> Data {
+        let mut data = Data::new(RecordStream::new(&samples[0]));
+        for sample in &samples[1..] {
+            data.push(RecordStream::new(sample))
+                .expect("Failed to push a sample");
+        }
+        data
+    }
+
+    /// INTERNAL: Read the current contents of /proc/stat
+    fn read_contents() -> String {
+        let mut reader = ProcFileReader::open("/proc/stat")
+                                        .expect("Failed to open /proc/stat");
+        reader.sample(|contents| contents.to_owned())
+              .expect("Failed to read /proc/stat")
+    }
 }
@@ -1,8 +1,15 @@
 //! This module contains facilities for parsing and storing the data contained
 //! in the paging statistics of /proc/stat (page and swap).
 
+#[cfg(feature = "binary")]
+use ::binary::BinaryValue;
+use ::columns::{Column, Columns};
 use ::data::SampledData;
 use ::splitter::SplitColumns;
+use bytesize::ByteSize;
+use libc;
+#[cfg(feature = "binary")]
+use std::io::{self, Read, Write};
 
 
 /// Paging statistics record from /proc/stat
@@ -44,8 +51,14 @@ impl RecordFields {
 
 
 /// Storage paging ativity statistics
+///
+/// BEWARE: The "page" and "swap" records of /proc/stat count memory pages,
+/// whose size is system-dependent, whereas the similarly named "pgpgin" and
+/// "pgpgout" counters of /proc/vmstat count KiB. Do not mix them up: use the
+/// incoming_bytes() and outgoing_bytes() accessors if you need a common unit.
+///
 #[derive(Clone, Debug, PartialEq)]
-pub(super) struct Data {
+pub struct Data {
     /// Number of RAM pages that were paged in from disk
     incoming: Vec<u64>,
 
@@ -60,23 +73,103 @@ impl SampledData for Data {
         debug_assert_eq!(length, self.outgoing.len());
         length
     }
+
+    // Drop the oldest samples from the data store
+    fn drop_oldest(&mut self, count: usize) {
+        self.incoming.drain(..count);
+        self.outgoing.drain(..count);
+    }
+
+    // Tell the length of the shortest time series in the data store
+    fn min_len(&self) -> usize {
+        ::std::cmp::min(self.incoming.len(), self.outgoing.len())
+    }
+
+    // Drop the newest samples from the data store
+    fn truncate(&mut self, length: usize) {
+        self.incoming.truncate(length);
+        self.outgoing.truncate(length);
+    }
 }
 //
 // TODO: Implement SampledData2 once that is usable in stable Rust
 impl Data {
+    /// Number of RAM pages that were paged in from disk
+    pub fn incoming(&self) -> &[u64] { &self.incoming }
+
+    /// Number of RAM pages that were paged out to disk
+    pub fn outgoing(&self) -> &[u64] { &self.outgoing }
+
+    /// Volume of RAM that was paged in from disk
+    pub fn incoming_bytes(&self) -> Vec<ByteSize> {
+        Self::pages_to_bytes(&self.incoming, *PAGE_SIZE)
+    }
+
+    /// Volume of RAM that was paged out to disk
+    pub fn outgoing_bytes(&self) -> Vec<ByteSize> {
+        Self::pages_to_bytes(&self.outgoing, *PAGE_SIZE)
+    }
+
     /// Create new paging statistics
-    pub fn new(_fields: RecordFields) -> Self {
+    pub(super) fn new(_fields: RecordFields) -> Self {
         Self {
             incoming: Vec::new(),
             outgoing: Vec::new(),
         }
     }
 
+    /// Reserve storage for some additional samples
+    pub(super) fn reserve(&mut self, additional: usize) {
+        self.incoming.reserve(additional);
+        self.outgoing.reserve(additional);
+    }
+
     /// Parse paging statistics and add them to the internal data store
-    pub fn push(&mut self, fields: RecordFields) {
+    pub(super) fn push(&mut self, fields: RecordFields) {
         self.incoming.push(fields.incoming);
         self.outgoing.push(fields.outgoing);
     }
+
+    /// INTERNAL: Convert a series of page counts into data volumes
+    fn pages_to_bytes(pages: &[u64], page_size: usize) -> Vec<ByteSize> {
+        pages.iter()
+             .map(|&count| ByteSize::b(count as usize * page_size))
+             .collect()
+    }
+}
+//
+/// Paging statistics are exported as data volumes
+impl Columns for Data {
+    fn columns(&self) -> Vec<(String, Column)> {
+        vec![("in".to_owned(), Column::bytes(&self.incoming_bytes())),
+             ("out".to_owned(), Column::bytes(&self.outgoing_bytes()))]
+    }
+}
+//
+/// Binary serialization of paging statistics: the incoming page counts,
+/// followed by the outgoing page counts
+#[cfg(feature = "binary")]
+impl BinaryValue for Data {
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.incoming.write_to(writer)?;
+        self.outgoing.write_to(writer)
+    }
+
+    fn read_from<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(
+            Self {
+                incoming: Vec::read_from(reader)?,
+                outgoing: Vec::read_from(reader)?,
+            }
+        )
+    }
+}
+//
+lazy_static! {
+    /// Size of a memory page on the host system, in bytes
+    static ref PAGE_SIZE: usize = unsafe {
+        libc::sysconf(libc::_SC_PAGESIZE) as usize
+    };
 }
 
 
@@ -84,7 +177,8 @@ impl Data {
 #[cfg(test)]
 mod tests {
     use ::splitter::split_line_and_run;
-    use super::{Data, RecordFields, SampledData};
+    use bytesize::ByteSize;
+    use super::{Data, RecordFields, SampledData, PAGE_SIZE};
 
     /// Check that paging statistics parsing works as expected
     #[test]
@@ -100,8 +194,8 @@ mod tests {
     fn sampled_data() {
         // The initial state should be right
         let mut data = with_record_fields("4 312", Data::new);
-        assert_eq!(data.incoming, Vec::new());
-        assert_eq!(data.outgoing, Vec::new());
+        assert_eq!(data.incoming, Vec::<u64>::new());
+        assert_eq!(data.outgoing, Vec::<u64>::new());
         assert_eq!(data.len(),    0);
 
         // Pushing data in should work correctly
@@ -115,6 +209,20 @@ mod tests {
         assert_eq!(data.len(),    2);
     }
 
+    /// Check that page counts are converted into data volumes correctly
+    #[test]
+    fn page_volumes() {
+        // Check the conversion against a known page size
+        assert_eq!(Data::pages_to_bytes(&[0, 1, 3], 4096),
+                   vec![ByteSize::b(0), ByteSize::kib(4), ByteSize::kib(12)]);
+
+        // Check that the public accessors use the host page size
+        let mut data = with_record_fields("0 0", Data::new);
+        with_record_fields("2 5", |fields| data.push(fields));
+        assert_eq!(data.incoming_bytes(), vec![ByteSize::b(2 * *PAGE_SIZE)]);
+        assert_eq!(data.outgoing_bytes(), vec![ByteSize::b(5 * *PAGE_SIZE)]);
+    }
+
     /// Build the paging record fields associated with a certain line of text,
     /// and run code taking that as a parameter
     fn with_record_fields<F, R>(line_of_text: &str, functor: F) -> R
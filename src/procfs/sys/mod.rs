@@ -0,0 +1,6 @@
+//! This module contains parsers for the contents of /proc/sys
+//!
+//! /proc/sys exposes the kernel's tunables (aka "sysctls") and a few related
+//! statistics, most of which are individual files containing a single value.
+
+pub mod scalar;
@@ -0,0 +1,192 @@
+//! This module contains a sampling parser for single-integer files of /proc/sys
+//!
+//! Many files in /proc/sys, such as /proc/sys/kernel/random/entropy_avail or
+//! /proc/sys/vm/dirty_ratio, contain a single integer. Rather than having a
+//! dedicated module for each of them, this module provides a sampler which can
+//! be pointed to any such file. Files which contain several values, such as
+//! /proc/sys/fs/inode-nr, are not supported and will be rejected.
+
+use ::columns::{Column, Columns};
+use ::data::SampledData;
+use ::reader::ProcFileReader;
+use std::io;
+use std::path::Path;
+
+
+/// Mechanism for sampling a pseudo-file which contains a single integer
+pub struct Sampler {
+    /// User-provided name of the sampled quantity, for display purposes
+    label: String,
+
+    /// Reader object for the pseudo-file
+    reader: ProcFileReader,
+
+    /// Samples of data extracted from the pseudo-file
+    samples: Data,
+}
+//
+impl Sampler {
+    /// Create a new sampler for a certain single-integer pseudo-file, giving
+    /// the sampled quantity a label (e.g. "Available entropy")
+    ///
+    /// An error will be returned if the file does not contain an integer.
+    ///
+    pub fn new<P: AsRef<Path>>(file_location: P,
+                               label: &str) -> io::Result<Self> {
+        let mut reader = ProcFileReader::open(file_location)?;
+        reader.sample(Self::parse)??;
+        Ok(
+            Self {
+                label: label.to_owned(),
+                reader,
+                samples: Data::new(),
+            }
+        )
+    }
+
+    /// Acquire a new sample of data from the pseudo-file
+    pub fn sample(&mut self) -> io::Result<()> {
+        let value = self.reader.sample(Self::parse)??;
+        self.samples.push(value);
+        Ok(())
+    }
+
+    /// Label of the sampled quantity
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// Access the samples that were acquired so far
+    pub fn samples(&self) -> &Data {
+        &self.samples
+    }
+
+    /// INTERNAL: Parse the integer contained in the pseudo-file
+    fn parse(file_contents: &str) -> io::Result<i64> {
+        file_contents.trim().parse().map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidData,
+                           "Pseudo-file does not contain a single integer")
+        })
+    }
+}
+
+
+/// Data samples from a single-integer pseudo-file
+#[derive(Debug, PartialEq)]
+pub struct Data {
+    /// Sampled integer values
+    values: Vec<i64>,
+}
+//
+impl SampledData for Data {
+    /// Tell how many samples are present in the data store
+    fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Drop the oldest samples from the data store
+    fn drop_oldest(&mut self, count: usize) {
+        self.values.drain(..count);
+    }
+
+    /// Tell the length of the shortest time series in the data store
+    fn min_len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Drop the newest samples from the data store
+    fn truncate(&mut self, length: usize) {
+        self.values.truncate(length);
+    }
+}
+//
+impl Data {
+    /// Sampled integer values
+    pub fn values(&self) -> &[i64] {
+        &self.values
+    }
+
+    /// Create a new single-integer data store
+    fn new() -> Self {
+        Self {
+            values: Vec::new(),
+        }
+    }
+
+    /// Add a sampled integer to the data store
+    fn push(&mut self, value: i64) {
+        self.values.push(value);
+    }
+}
+//
+/// The sampled integer is exported as a "value" column. Since it may be
+/// negative, it is converted to a floating-point number.
+impl Columns for Data {
+    fn columns(&self) -> Vec<(String, Column)> {
+        let values = self.values.iter().map(|&value| value as f64).collect();
+        vec![("value".to_owned(), Column::F64(values))]
+    }
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use ::data::SampledData;
+    use std::io::ErrorKind;
+    use super::Sampler;
+
+    /// Check that integers are parsed correctly, and that the rest is rejected
+    #[test]
+    fn parse() {
+        assert_eq!(Sampler::parse("256\n").unwrap(), 256);
+        assert_eq!(Sampler::parse("-1\n").unwrap(), -1);
+        assert_eq!(Sampler::parse("9515\t0\n").unwrap_err().kind(),
+                   ErrorKind::InvalidData);
+        assert_eq!(Sampler::parse("\n").unwrap_err().kind(),
+                   ErrorKind::InvalidData);
+    }
+
+    /// Check that sampling single-integer files works
+    #[test]
+    fn sampling() {
+        let mut sampler = Sampler::new("/proc/sys/kernel/pid_max", "PID limit")
+                                  .expect("Failed to create a sampler");
+        assert_eq!(sampler.label(), "PID limit");
+        assert_eq!(sampler.samples().len(), 0);
+        sampler.sample().expect("Failed to acquire a first sample");
+        sampler.sample().expect("Failed to acquire a second sample");
+        assert_eq!(sampler.samples().len(), 2);
+        assert!(sampler.samples().values()[0] > 0);
+    }
+
+    /// Check that files which do not contain a single integer are rejected
+    #[test]
+    fn invalid_files() {
+        assert!(Sampler::new("/proc/sys/fs/inode-nr", "Inodes").is_err());
+        assert!(Sampler::new("/proc/version", "Version").is_err());
+    }
+}
+
+
+/// Performance benchmarks
+///
+/// See the lib-wide benchmarks module for details on how to use these.
+///
+#[cfg(test)]
+mod benchmarks {
+    use testbench;
+    use super::Sampler;
+
+    /// Benchmark for the full pseudo-file sampling overhead
+    #[test]
+    #[ignore]
+    fn sampling_overhead() {
+        let mut sampler =
+            Sampler::new("/proc/sys/kernel/random/entropy_avail", "Entropy")
+                    .expect("Failed to create a sampler");
+        testbench::benchmark(1_000_000, || {
+            sampler.sample().expect("Failed to sample data");
+        });
+    }
+}
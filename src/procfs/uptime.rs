@@ -1,13 +1,14 @@
 //! This module contains a sampling parser for /proc/uptime
 
-use ::data::SampledData;
+use ::columns::{Column, Columns};
+use ::data::{SampledData, SchemaChanged};
 use ::parser::PseudoFileParser;
 use std::str::SplitWhitespace;
 use std::time::Duration;
 
 
 // Implement a sampler for /proc/uptime
-define_sampler!{ Sampler : "/proc/uptime" => Parser => Data }
+define_sampler!{ Sampler : "/proc/uptime" => Parser => FieldStream => Data }
 
 
 /// Incremental parser for /proc/uptime
@@ -17,13 +18,16 @@ impl PseudoFileParser for Parser {
     /// Build a parser, using an initial file sample. Here, this is used to
     /// perform quick schema validation, just to maximize the odds that failure,
     /// if any, will occur at initialization time rather than run time.
+    ///
+    /// Fields beyond the uptime and idle time, which future kernels may add,
+    /// are ignored in both debug and release builds.
+    ///
     fn new(initial_contents: &str) -> Self {
         let mut raw_fields = initial_contents.split_whitespace();
         for _ in 0..2 {
             let field = raw_fields.next().expect("Missing uptime or idle time");
             FieldStream::parse_duration_secs(field);
         }
-        debug_assert_eq!(raw_fields.next(), None, "Unsupported field detected");
         Self {}
     }
 }
@@ -43,6 +47,7 @@ impl Parser {
 ///
 /// * The machine uptime (wall clock time elapsed since boot)
 /// * The idle time (total CPU time spent in the idle state)
+/// * Any field added by future kernels, which the data store ignores
 /// * A None terminator
 ///
 pub struct FieldStream<'a> {
@@ -129,10 +134,72 @@ impl SampledData for Data {
         debug_assert_eq!(length, self.cpu_idle_time.len());
         length
     }
+
+    /// Drop the oldest samples from the data store
+    fn drop_oldest(&mut self, count: usize) {
+        self.wall_clock_uptime.drain(..count);
+        self.cpu_idle_time.drain(..count);
+    }
+
+    /// Tell the length of the shortest time series in the data store
+    fn min_len(&self) -> usize {
+        ::std::cmp::min(self.wall_clock_uptime.len(), self.cpu_idle_time.len())
+    }
+
+    /// Drop the newest samples from the data store
+    fn truncate(&mut self, length: usize) {
+        self.wall_clock_uptime.truncate(length);
+        self.cpu_idle_time.truncate(length);
+    }
 }
 //
 // TODO: Implement SampledDataIncremental once that is usable in stable Rust
 impl Data {
+    /// Elapsed wall clock time since the system was started
+    pub fn wall_clock_uptime(&self) -> &[Duration] {
+        &self.wall_clock_uptime
+    }
+
+    /// Cumulative amount of time spent by all CPUs in the idle state
+    pub fn cpu_idle_time(&self) -> &[Duration] {
+        &self.cpu_idle_time
+    }
+
+    /// Fraction of the available CPU time which was spent idle between two
+    /// samples, given the number of CPUs (hardware threads) of the system
+    ///
+    /// BEWARE: Despite its name, the idle time from /proc/uptime is summed
+    /// across all CPUs. So on a multi-core machine, it increases faster than
+    /// the wall clock uptime, up to N times faster on an idle N-core machine.
+    /// It can only be compared with the wall clock uptime on single-core
+    /// systems, where both fields track each other closely. This method
+    /// accounts for that by normalizing the idle time by the CPU count.
+    ///
+    /// The result is clamped to [0, 1], as the kernel's idle time accounting
+    /// is not perfectly synchronized with its uptime clock. If no time elapsed
+    /// between the two samples, zero is returned.
+    ///
+    pub fn idle_fraction_between(&self,
+                                 older: usize,
+                                 newer: usize,
+                                 cpu_count: usize) -> f64 {
+        // Compute the elapsed wall clock and idle times, in seconds
+        assert!(cpu_count > 0, "There should be at least one CPU");
+        let secs = |d: Duration| d.as_secs() as f64
+                                 + d.subsec_nanos() as f64 * 1e-9;
+        let uptime_delta = secs(self.wall_clock_uptime[newer])
+                           - secs(self.wall_clock_uptime[older]);
+        let idle_delta = secs(self.cpu_idle_time[newer])
+                         - secs(self.cpu_idle_time[older]);
+
+        // Normalize the idle time by the total available CPU time
+        if uptime_delta <= 0.0 {
+            return 0.0;
+        }
+        let fraction = idle_delta / (cpu_count as f64 * uptime_delta);
+        fraction.max(0.0).min(1.0)
+    }
+
     /// Create a new uptime data store
     fn new(_stream: FieldStream) -> Self {
         Self {
@@ -142,19 +209,32 @@ impl Data {
     }
 
     /// Push a new stream of parsed data from /proc/uptime into the store
-    fn push(&mut self, mut stream: FieldStream) {
+    ///
+    /// Empty readouts, which may occur when racing with the kernel, carry no
+    /// uptime and are rejected without modifying the store.
+    ///
+    fn push(&mut self, mut stream: FieldStream) -> Result<(), SchemaChanged> {
         // Start parsing our input data sample
-        self.wall_clock_uptime.push(
-            stream.next().expect("Machine uptime is missing")
-        );
-        self.cpu_idle_time.push(
-            stream.next().expect("Machine idle time is missing")
-        );
-
-        // If this debug assert fails, the contents of the file have been
-        // extended by a kernel revision, and the code should be updated
-        debug_assert_eq!(stream.next(), None,
-                         "Unsupported entry in /proc/uptime");
+        let uptime = stream.next().ok_or_else(|| {
+            SchemaChanged::new("Machine uptime is missing")
+        })?;
+        let idle_time = stream.next().ok_or_else(|| {
+            SchemaChanged::new("Machine idle time is missing")
+        })?;
+        self.wall_clock_uptime.push(uptime);
+        self.cpu_idle_time.push(idle_time);
+
+        // Any extra field was added by a newer kernel revision, which we do
+        // not know about. It is left unparsed, so that its format does not
+        // matter, and consistently ignored in debug and release builds.
+        Ok(())
+    }
+}
+//
+impl Columns for Data {
+    fn columns(&self) -> Vec<(String, Column)> {
+        vec![("uptime".to_owned(), Column::durations(&self.wall_clock_uptime)),
+             ("idle".to_owned(), Column::durations(&self.cpu_idle_time))]
     }
 }
 
@@ -162,6 +242,7 @@ impl Data {
 /// Unit tests
 #[cfg(test)]
 mod tests {
+    use std::fs::File;
     use std::thread;
     use std::time::Duration;
     use super::{Data, FieldStream, Parser, PseudoFileParser, SampledData,
@@ -218,7 +299,27 @@ mod tests {
         let initial = "145.16 16546.1469";
         let mut parser = Parser::new(initial);
         let mut data = Data::new(parser.parse(initial));
-        data.push(parser.parse("614.461  10645.163"));
+        data.push(parser.parse("614.461  10645.163")).unwrap();
+        assert_eq!(data.wall_clock_uptime,
+                   vec![Duration::new(614, 461_000_000)]);
+        assert_eq!(data.cpu_idle_time,
+                   vec![Duration::new(10645, 163_000_000)]);
+        assert_eq!(data.len(), 1);
+
+        // Empty or truncated readouts are rejected without modifying the store
+        assert!(data.push(parser.parse("")).is_err());
+        assert!(data.push(parser.parse("614.461")).is_err());
+        assert_eq!(data.len(), 1);
+        assert_eq!(data.wall_clock_uptime.len(), 1);
+    }
+
+    /// Check that fields added by future kernels are ignored
+    #[test]
+    fn extra_fields() {
+        let initial = "145.16 16546.1469 42.0";
+        let mut parser = Parser::new(initial);
+        let mut data = Data::new(parser.parse(initial));
+        data.push(parser.parse("614.461  10645.163 something_new")).unwrap();
         assert_eq!(data.wall_clock_uptime,
                    vec![Duration::new(614, 461_000_000)]);
         assert_eq!(data.cpu_idle_time,
@@ -226,9 +327,56 @@ mod tests {
         assert_eq!(data.len(), 1);
     }
 
+    /// Check that the idle fraction accounts for the CPU count
+    #[test]
+    fn idle_fraction() {
+        // On a single-core VM, idle time tracks uptime closely
+        let mut parser = Parser::new("100.00 90.00");
+        let mut data = Data::new(parser.parse("100.00 90.00"));
+        data.push(parser.parse("100.00 90.00")).unwrap();
+        data.push(parser.parse("110.00 97.50")).unwrap();
+        assert_eq!(data.idle_fraction_between(0, 1, 1), 0.75);
+
+        // On a 64-core box, idle time is up to ~64x the uptime
+        let mut data = Data::new(parser.parse("100.00 6000.00"));
+        data.push(parser.parse("100.00 6000.00")).unwrap();
+        data.push(parser.parse("110.00 6320.00")).unwrap();
+        data.push(parser.parse("120.00 6960.00")).unwrap();
+        assert_eq!(data.idle_fraction_between(0, 1, 64), 0.5);
+        assert_eq!(data.idle_fraction_between(1, 2, 64), 1.0);
+
+        // Accounting jitter should be clamped, and no elapsed time yields zero
+        assert_eq!(data.idle_fraction_between(1, 2, 32), 1.0);
+        assert_eq!(data.idle_fraction_between(2, 2, 64), 0.0);
+    }
+
     /// Check that the sampler works well
     define_sampler_tests!{ Sampler }
 
+    /// Check that the sampler can parse contents from a user-provided source
+    #[test]
+    fn remote_source() {
+        let mut contents = vec!["10.00 20.00", "13.52 50.34", "14.00 51.00"]
+                               .into_iter();
+        let mut uptime = Sampler::from_source(move || {
+            Ok(contents.next().expect("Sampled too often").to_owned())
+        }).expect("Failed to create a sampler");
+        uptime.sample().expect("Failed to sample uptime once");
+        uptime.sample().expect("Failed to sample uptime twice");
+        assert_eq!(uptime.samples.wall_clock_uptime,
+                   vec![Duration::new(13, 520_000_000), Duration::new(14, 0)]);
+    }
+
+    /// Check that the sampler can read through a caller-provided file handle
+    #[test]
+    fn existing_file() {
+        let file = File::open("/proc/uptime").expect("Failed to open uptime");
+        let mut uptime = Sampler::from_file(file)
+                                 .expect("Failed to create a sampler");
+        uptime.sample().expect("Failed to sample uptime");
+        assert_eq!(uptime.samples.len(), 1);
+    }
+
     /// Check that the sampled uptime increases over time
     #[test]
     fn increasing_uptime() {
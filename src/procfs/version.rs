@@ -11,9 +11,13 @@ use std::io::{Read, Result};
 
 lazy_static! {
     /// Host version (shouldn't change, and should thus only be loaded once)
+    ///
+    /// If the host's kernel version cannot be loaded or parsed, this falls
+    /// back to an unknown version (see LinuxVersion::is_unknown()), so that
+    /// merely checking the version does not bring down the whole program.
+    ///
     pub static ref LINUX_VERSION: LinuxVersion =
-        LinuxVersion::load()
-                     .expect("Failed to load the host's kernel version");
+        LinuxVersion::load_lenient().unwrap_or_else(LinuxVersion::unknown);
 }
 
 
@@ -58,6 +62,36 @@ impl LinuxVersion {
         Ok(Self::parse(trimmed_version))
     }
 
+    /// Load kernel versioning information from /proc/version, returning None
+    /// if it cannot be read or parsed instead of failing
+    pub fn load_lenient() -> Option<Self> {
+        let mut file = File::open("/proc/version").ok()?;
+        let mut raw_version = String::new();
+        file.read_to_string(&mut raw_version).ok()?;
+        Self::parse_lenient(raw_version.trim_right())
+    }
+
+    /// Placeholder for a kernel version which could not be determined
+    ///
+    /// It compares as older than every actual kernel version, and should be
+    /// checked for with is_unknown() before relying on version checks.
+    ///
+    pub fn unknown() -> Self {
+        Self {
+            major: 0,
+            minor: 0,
+            bugfix: 0,
+            distro_flavour: None,
+            build_info: String::new(),
+        }
+    }
+
+    /// Truth that this kernel version could not be determined
+    pub fn is_unknown(&self) -> bool {
+        // Actual Linux major versions start at 1
+        self.major == 0
+    }
+
     /// Check if we are using at least a certain kernel version (included)
     pub fn greater_eq(&self, major: u8, minor: u8, bugfix: u8) -> bool {
         // Test major version
@@ -80,31 +114,51 @@ impl LinuxVersion {
     /// INTERNAL: Parse the (trimmed) contents of /proc/version
     fn parse(trimmed_version: &str) -> Self {
         // Make sure that we are running on Linux
-        assert_eq!(&trimmed_version[0..5], "Linux",
-                   "This library only supports Linux's flavour of procfs");
+        assert!(trimmed_version.starts_with("Linux"),
+                "This library only supports Linux's flavour of procfs");
+
+        // Parse the version, failing if it does not look as expected
+        Self::parse_lenient(trimmed_version)
+             .expect("Failed to parse /proc/version")
+    }
 
+    /// INTERNAL: Parse the (trimmed) contents of /proc/version, returning None
+    ///           if they do not start with a recognizable kernel version
+    ///
+    /// Distributions are quite creative with kernel version strings, so only
+    /// the version numbers are required. Whatever directly follows them (e.g.
+    /// "-generic" or "+") is treated as a distribution flavour, and the rest is
+    /// treated as build information. Version numbers which are too large to be
+    /// stored (as happens with long-lived stable kernels, e.g. 4.14.300) are
+    /// saturated, which keeps version comparisons correct.
+    ///
+    fn parse_lenient(trimmed_version: &str) -> Option<Self> {
         // The contents of /proc/version should match this regex
-        let version_regex = Regex::new(r"^Linux version (?P<major>[1-9]\d*)\.(?P<minor>\d+)(?:\.(?P<bugfix>\d+))?(?:-(?P<distro_flavour>\S+))? (?P<build_info>.+)$")
+        let version_regex = Regex::new(r"^Linux version (?P<major>[1-9]\d*)\.(?P<minor>\d+)(?:\.(?P<bugfix>\d+))?(?:-?(?P<distro_flavour>\S+))?(?:\s+(?P<build_info>.*))?$")
                                   .expect("Failed to compile the regex");
-        let captures = version_regex.captures(trimmed_version)
-                                    .expect("Failed to parse /proc/version");
+        let captures = version_regex.captures(trimmed_version)?;
+
+        // Version numbers are only made of digits, so they can only fail to
+        // parse by overflowing
+        let version_number = |name: &str| -> u8 {
+            captures.name(name).map_or(0, |m| {
+                m.as_str().parse().unwrap_or(u8::max_value())
+            })
+        };
 
         // Return the parsed kernel version
-        Self {
-            major: captures["major"].parse()
-                                    .expect("Failed to parse major version"),
-            minor: captures["minor"].parse()
-                                    .expect("Failed to parse minor version"),
-            bugfix:
-                captures.name("bugfix")
-                        .map_or(0,
-                               |m| m.as_str()
-                                    .parse()
-                                    .expect("Failed to parse bugfix version")),
-            distro_flavour: captures.name("distro_flavour")
-                                    .map(|m| m.as_str().to_owned()),
-            build_info: captures["build_info"].to_owned(),
-        }
+        Some(
+            Self {
+                major: version_number("major"),
+                minor: version_number("minor"),
+                bugfix: version_number("bugfix"),
+                distro_flavour: captures.name("distro_flavour")
+                                        .map(|m| m.as_str().to_owned()),
+                build_info: captures.name("build_info")
+                                    .map_or(String::new(),
+                                            |m| m.as_str().to_owned()),
+            }
+        )
     }
 }
 
@@ -166,12 +220,69 @@ mod tests {
         );
     }
 
+    /// Test the lenient parser on version strings found in the wild
+    #[test]
+    fn parse_oddball_versions() {
+        // Windows Subsystem for Linux v1
+        let wsl = LinuxVersion::parse_lenient(
+            "Linux version 4.4.0-19041-Microsoft (Microsoft@Microsoft.com) \
+             (gcc version 5.4.0 (GCC) ) #488-Microsoft Mon Sep 01 13:43:00 \
+             PST 2020"
+        ).expect("Failed to parse WSL1 version");
+        assert_eq!((wsl.major, wsl.minor, wsl.bugfix), (4, 4, 0));
+        assert_eq!(wsl.distro_flavour, Some(String::from("19041-Microsoft")));
+
+        // Android
+        let android = LinuxVersion::parse_lenient(
+            "Linux version 4.14.190-perf-g6a37d5e (builder@android) \
+             (clang version 10.0.7) #1 SMP PREEMPT Tue Jan 5 21:44:57 CST 2021"
+        ).expect("Failed to parse Android version");
+        assert_eq!((android.major, android.minor, android.bugfix),
+                   (4, 14, 190));
+        assert_eq!(android.distro_flavour,
+                   Some(String::from("perf-g6a37d5e")));
+
+        // Custom kernels with a dirty source tree, and no separator
+        let dirty = LinuxVersion::parse_lenient(
+            "Linux version 5.10.0+ (root@buildhost) #1 SMP"
+        ).expect("Failed to parse custom kernel version");
+        assert_eq!((dirty.major, dirty.minor, dirty.bugfix), (5, 10, 0));
+        assert_eq!(dirty.distro_flavour, Some(String::from("+")));
+        assert_eq!(dirty.build_info, "(root@buildhost) #1 SMP");
+
+        // Long-lived stable kernels and missing build information
+        let stable = LinuxVersion::parse_lenient("Linux version 4.14.336")
+                                  .expect("Failed to parse stable version");
+        assert_eq!((stable.major, stable.minor, stable.bugfix), (4, 14, 255));
+        assert!(stable.greater_eq(4, 14, 254));
+        assert_eq!(stable.build_info, "");
+
+        // Version strings which are not recognizable at all
+        assert_eq!(LinuxVersion::parse_lenient(""), None);
+        assert_eq!(LinuxVersion::parse_lenient("Linux version unknown"), None);
+        assert_eq!(
+            LinuxVersion::parse_lenient("Darwin Kernel Version 20.1.0"),
+            None
+        );
+    }
+
+    /// Check that unknown kernel versions are reported as such
+    #[test]
+    fn unknown_version() {
+        let unknown = LinuxVersion::unknown();
+        assert!(unknown.is_unknown());
+        assert!(unknown.smaller(1, 0, 0));
+        assert!(!LINUX_VERSION.is_unknown());
+    }
+
     /// Check that reading the kernel version string of the host works
     #[test]
     fn load_host_version() {
         assert_eq!(LinuxVersion::load().expect("Failed to load kernel version"),
                    *LINUX_VERSION,
                    "Loaded kernel version should match our public static");
+        assert_eq!(LinuxVersion::load_lenient().as_ref(),
+                   Some(&*LINUX_VERSION));
     }
 
     /// Check that kernel version compatibility checks work
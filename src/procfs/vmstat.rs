@@ -0,0 +1,433 @@
+//! This module contains a sampling parser for /proc/vmstat
+//!
+//! This file exposes dozens of virtual memory counters and gauges, as a list
+//! of "name value" lines whose contents vary from one kernel version to the
+//! next. Raw values are available by name, and the sampler additionally
+//! computes per-second rates for the counters which tell about memory
+//! pressure (page faults, page reclaim and swapping).
+
+use ::columns::{Column, Columns};
+use ::data::{SampledData, SchemaChanged};
+use ::intern::intern;
+use ::parser::PseudoFileParser;
+use ::splitter::{SplitColumns, SplitLinesBySpace};
+use std::sync::Arc;
+
+
+// Implement a sampler for /proc/vmstat
+define_sampler!{ Sampler : "/proc/vmstat" => Parser => RecordStream => Data }
+//
+// The sampler also provides rates, which require the sample timestamps
+impl Sampler {
+    /// Rate at which page faults occurred between two samples, in faults per
+    /// second, including minor faults which did not require any disk I/O
+    ///
+    /// Returns None if the kernel does not provide this counter, or if no time
+    /// elapsed between these samples.
+    ///
+    pub fn pgfault_rate(&self, older: usize, newer: usize) -> Option<f64> {
+        self.counter_rate(older, newer, |key| key == "pgfault")
+    }
+
+    /// Rate at which major page faults, which require disk I/O, occurred
+    /// between two samples, in faults per second (see pgfault_rate())
+    pub fn pgmajfault_rate(&self, older: usize, newer: usize) -> Option<f64> {
+        self.counter_rate(older, newer, |key| key == "pgmajfault")
+    }
+
+    /// Rate at which the kernel scanned pages for reclaim between two
+    /// samples, in pages per second
+    ///
+    /// This sums the "pgscan_*" counters of every reclaim context (kswapd,
+    /// direct reclaim...), which on older kernels are further split by memory
+    /// zone. The "pgscan_anon" and "pgscan_file" counters, which provide
+    /// another breakdown of the same scans, and "pgscan_direct_throttle", which
+    /// counts throttling events rather than pages, are left out. Returns None
+    /// if the kernel provides no such counter, or if no time elapsed between
+    /// these samples.
+    ///
+    pub fn pgscan_rate(&self, older: usize, newer: usize) -> Option<f64> {
+        self.counter_rate(older, newer, |key| {
+            key.starts_with("pgscan_")
+                && !["pgscan_anon", "pgscan_file", "pgscan_direct_throttle"]
+                        .contains(&key)
+        })
+    }
+
+    /// Rate at which the kernel reclaimed pages between two samples, in pages
+    /// per second (see pgscan_rate() for the summed counters)
+    pub fn pgsteal_rate(&self, older: usize, newer: usize) -> Option<f64> {
+        self.counter_rate(older, newer, |key| {
+            key.starts_with("pgsteal_")
+                && !["pgsteal_anon", "pgsteal_file"].contains(&key)
+        })
+    }
+
+    /// Rate at which pages were swapped in between two samples, in pages per
+    /// second (see pgfault_rate())
+    pub fn pswpin_rate(&self, older: usize, newer: usize) -> Option<f64> {
+        self.counter_rate(older, newer, |key| key == "pswpin")
+    }
+
+    /// Rate at which pages were swapped out between two samples, in pages per
+    /// second (see pgfault_rate())
+    pub fn pswpout_rate(&self, older: usize, newer: usize) -> Option<f64> {
+        self.counter_rate(older, newer, |key| key == "pswpout")
+    }
+
+    /// INTERNAL: Rate of change of the sum of the counters whose key matches
+    ///           a predicate, or None if no key matches
+    fn counter_rate<F>(&self,
+                       older: usize,
+                       newer: usize,
+                       matches: F) -> Option<f64>
+        where F: Fn(&str) -> bool
+    {
+        assert!(older <= newer, "Samples should be ordered by age");
+        let mut matching = self.samples
+                               .keys
+                               .iter()
+                               .zip(self.samples.values.iter())
+                               .filter(|&(key, _)| matches(key))
+                               .peekable();
+        matching.peek()?;
+        let delta: u64 =
+            matching.map(|(_, vec)| vec[newer].saturating_sub(vec[older]))
+                    .sum();
+        let elapsed = self.timestamps[newer] - self.timestamps[older];
+        let secs = elapsed.as_secs() as f64
+                   + elapsed.subsec_nanos() as f64 * 1e-9;
+        if secs > 0.0 { Some(delta as f64 / secs) } else { None }
+    }
+}
+
+
+/// Incremental parser for /proc/vmstat
+#[derive(Debug, PartialEq)]
+pub struct Parser {}
+//
+impl PseudoFileParser for Parser {
+    /// Build a parser, using an initial file sample. Here, this is used to
+    /// perform quick schema validation, just to maximize the odds that failure,
+    /// if any, will occur at initialization time rather than run time.
+    fn new(initial_contents: &str) -> Self {
+        let mut validation_stream = RecordStream::new(initial_contents);
+        while let Some(record) = validation_stream.next() {
+            record.key();
+            record.parse_value();
+        }
+        Self {}
+    }
+}
+//
+// TODO: Implement IncrementalParser once that trait is usable in stable Rust
+impl Parser {
+    /// Parse a pseudo-file sample into a stream of records
+    pub fn parse<'a>(&mut self, file_contents: &'a str) -> RecordStream<'a> {
+        RecordStream::new(file_contents)
+    }
+}
+///
+///
+/// Stream of records from /proc/vmstat
+///
+/// This streaming iterator should yield a stream of records, each representing
+/// a line of /proc/vmstat (i.e. a named counter or gauge).
+///
+pub struct RecordStream<'a> {
+    /// Iterator into the lines and columns of /proc/vmstat
+    file_lines: SplitLinesBySpace<'a>,
+}
+//
+impl<'a> RecordStream<'a> {
+    /// Extract the next record from /proc/vmstat
+    pub fn next<'b>(&'b mut self) -> Option<Record<'a, 'b>>
+        where 'a: 'b
+    {
+        self.file_lines.next().map(Record::new)
+    }
+
+    /// Create a record stream from raw contents
+    fn new(file_contents: &'a str) -> Self {
+        Self {
+            file_lines: SplitLinesBySpace::new(file_contents),
+        }
+    }
+}
+///
+///
+/// Record from /proc/vmstat (named counter or gauge)
+pub struct Record<'a, 'b> where 'a: 'b {
+    /// Name of the record
+    key_field: &'a str,
+
+    /// Iterator into the value's column
+    value_columns: SplitColumns<'a, 'b>,
+}
+//
+impl<'a, 'b> Record<'a, 'b> {
+    /// Tell how this record is named (e.g. "pgfault")
+    pub fn key(&self) -> &'a str {
+        self.key_field
+    }
+
+    /// Parse the value of the record
+    pub fn parse_value(mut self) -> u64 {
+        let value = self.value_columns.next()
+                                      .expect("Missing vmstat value")
+                                      .parse()
+                                      .expect("Failed to parse vmstat value");
+        debug_assert_eq!(self.value_columns.next(), None,
+                         "Unexpected additional vmstat column");
+        value
+    }
+
+    /// Construct a record from associated file columns
+    fn new(mut record_columns: SplitColumns<'a, 'b>) -> Self {
+        Self {
+            key_field: record_columns.next().expect("Missing vmstat key"),
+            value_columns: record_columns,
+        }
+    }
+}
+
+
+/// Data samples from /proc/vmstat, in structure-of-array layout
+///
+/// As with /proc/meminfo, the layout is optimized for fast sampling rather
+/// than fast lookup of a specific key. Records can be looked up by key, and
+/// the sampler provides rates for the most useful counters.
+///
+#[derive(Debug, PartialEq)]
+pub struct Data {
+    /// Sampled values, in file order
+    values: Vec<Vec<u64>>,
+
+    /// Keys associated with each record, again in file order. These are
+    /// interned, as they are the same for every sampler.
+    keys: Vec<Arc<str>>,
+}
+//
+impl SampledData for Data {
+    /// Tell how many samples are present in the data store + check consistency
+    fn len(&self) -> usize {
+        let length = self.values.first().map_or(0, |vec| vec.len());
+        debug_assert!(self.values.iter().all(|vec| vec.len() == length));
+        length
+    }
+
+    /// Drop the oldest samples from the data store
+    fn drop_oldest(&mut self, count: usize) {
+        for vec in self.values.iter_mut() {
+            vec.drain(..count);
+        }
+    }
+
+    /// Tell the length of the shortest time series in the data store
+    fn min_len(&self) -> usize {
+        self.values.iter().map(|vec| vec.len()).min().unwrap_or(0)
+    }
+
+    /// Drop the newest samples from the data store
+    fn truncate(&mut self, length: usize) {
+        for vec in self.values.iter_mut() {
+            vec.truncate(length);
+        }
+    }
+}
+//
+// TODO: Implement SampledDataIncremental once that is usable in stable Rust
+impl Data {
+    /// Names of the records from /proc/vmstat, in file order
+    pub fn keys<'a>(&'a self) -> impl Iterator<Item=&'a str> + 'a {
+        self.keys.iter().map(|key| &**key)
+    }
+
+    /// Samples of a record (e.g. "pgmajfault"), if present
+    ///
+    /// This performs a linear search across the records, so if you need
+    /// frequent access, consider caching the result.
+    ///
+    pub fn value(&self, key: &str) -> Option<&[u64]> {
+        self.keys.iter()
+                 .position(|k| &**k == key)
+                 .map(|idx| &self.values[idx][..])
+    }
+
+    /// Create a new vmstat data store, using a first sample to know the
+    /// structure of /proc/vmstat
+    fn new(mut stream: RecordStream) -> Self {
+        let mut keys = Vec::new();
+        while let Some(record) = stream.next() {
+            keys.push(intern(record.key()));
+        }
+        Self {
+            values: vec![Vec::new(); keys.len()],
+            keys,
+        }
+    }
+
+    /// Parse the contents of /proc/vmstat and add a data sample to all
+    /// corresponding entries in the internal data store
+    ///
+    /// If a record has disappeared, which notably happens when the kernel
+    /// hands over an empty readout, the partial sample is rolled back.
+    ///
+    fn push(&mut self, mut stream: RecordStream) -> Result<(), SchemaChanged> {
+        let sample_count = self.len();
+        let mut recorded = 0;
+        for (key, vec) in self.keys.iter().zip(self.values.iter_mut()) {
+            let record = match stream.next() {
+                Some(record) => record,
+                None => break,
+            };
+            debug_assert_eq!(record.key(), &**key,
+                             "Unsupported schema change");
+            vec.push(record.parse_value());
+            recorded += 1;
+        }
+
+        // If a record has disappeared, roll back the partial sample
+        if recorded < self.values.len() {
+            for vec in self.values[..recorded].iter_mut() {
+                vec.truncate(sample_count);
+            }
+            return Err(SchemaChanged::new("A vmstat record has disappeared"));
+        }
+        debug_assert!(stream.next().is_none(), "Unsupported schema change");
+        Ok(())
+    }
+}
+//
+/// Records are exported under their key (e.g. "pgfault")
+impl Columns for Data {
+    fn columns(&self) -> Vec<(String, Column)> {
+        self.keys.iter()
+                 .zip(self.values.iter())
+                 .map(|(key, vec)| (key.to_string(), Column::counts(vec)))
+                 .collect()
+    }
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use super::{Data, Parser, PseudoFileParser, SampledData, Sampler};
+
+    /// Build a mock vmstat file, with an older kernel's per-zone scan
+    /// counters and a newer kernel's per-type breakdown
+    fn mock_file(pgfault: u64, pgmajfault: u64, scanned: u64) -> String {
+        format!("nr_free_pages 860951\n\
+                 pswpin 12\n\
+                 pswpout 40\n\
+                 pgfault {}\n\
+                 pgmajfault {}\n\
+                 pgsteal_kswapd {}\n\
+                 pgsteal_direct 0\n\
+                 pgscan_kswapd_normal {}\n\
+                 pgscan_direct_normal {}\n\
+                 pgscan_direct_throttle 7\n\
+                 pgscan_anon {}\n\
+                 pgscan_file 0\n\
+                 pgsteal_anon {}\n",
+                pgfault, pgmajfault, scanned / 2,
+                scanned / 2, scanned / 2, scanned, scanned / 2)
+    }
+
+    /// Check that parsing vmstat records works
+    #[test]
+    fn record_stream() {
+        let file = mock_file(1000, 10, 64);
+        let mut parser = Parser::new(&file);
+        let mut stream = parser.parse(&file);
+        {
+            let record = stream.next().expect("Missing first record");
+            assert_eq!(record.key(), "nr_free_pages");
+            assert_eq!(record.parse_value(), 860951);
+        }
+        for _ in 0..12 {
+            stream.next().expect("Missing record");
+        }
+        assert!(stream.next().is_none());
+    }
+
+    /// Check that sampled data works as expected
+    #[test]
+    fn sampled_data() {
+        let initial = mock_file(1000, 10, 64);
+        let mut parser = Parser::new(&initial);
+        let mut data = Data::new(parser.parse(&initial));
+        assert_eq!(data.keys().count(), 13);
+        assert_eq!(data.keys().nth(3), Some("pgfault"));
+        assert_eq!(data.len(), 0);
+        data.push(parser.parse(&initial)).unwrap();
+        data.push(parser.parse(&mock_file(3000, 20, 128))).unwrap();
+        assert_eq!(data.len(), 2);
+        assert_eq!(data.value("pgfault"), Some(&[1000, 3000][..]));
+        assert_eq!(data.value("pgmajfault"), Some(&[10, 20][..]));
+        assert_eq!(data.value("nr_dirty"), None);
+
+        // Empty readouts are rejected without modifying the store
+        assert!(data.push(parser.parse("")).is_err());
+        assert_eq!(data.len(), 2);
+        assert_eq!(data.value("pgfault"), Some(&[1000, 3000][..]));
+
+        data.drop_oldest(1);
+        assert_eq!(data.len(), 1);
+        assert_eq!(data.value("pgfault"), Some(&[3000][..]));
+    }
+
+    /// Check that memory pressure rates are computed correctly
+    #[test]
+    fn rates() {
+        // Replace the samples of a sampler with known data, acquired two
+        // seconds apart from each other
+        let mut sampler = Sampler::new().expect("Failed to create a sampler");
+        let file1 = mock_file(1000, 10, 64);
+        let file2 = mock_file(3000, 20, 128);
+        let mut parser = Parser::new(&file1);
+        let mut data = Data::new(parser.parse(&file1));
+        data.push(parser.parse(&file1)).unwrap();
+        data.push(parser.parse(&file2)).unwrap();
+        let start = Instant::now();
+        sampler.samples = data;
+        sampler.timestamps = vec![start, start + Duration::from_secs(2)];
+
+        // Check the rates, including the sums over reclaim contexts
+        assert_eq!(sampler.pgfault_rate(0, 1), Some(1000.));
+        assert_eq!(sampler.pgmajfault_rate(0, 1), Some(5.));
+        assert_eq!(sampler.pgscan_rate(0, 1), Some(32.));
+        assert_eq!(sampler.pgsteal_rate(0, 1), Some(16.));
+        assert_eq!(sampler.pswpin_rate(0, 1), Some(0.));
+        assert_eq!(sampler.pswpout_rate(0, 1), Some(0.));
+        assert_eq!(sampler.pgfault_rate(1, 1), None);
+
+        // Counters which the kernel does not provide have no rate
+        let file = "nr_free_pages 42\n";
+        let mut parser = Parser::new(file);
+        let mut data = Data::new(parser.parse(file));
+        data.push(parser.parse(file)).unwrap();
+        data.push(parser.parse(file)).unwrap();
+        sampler.samples = data;
+        assert_eq!(sampler.pgfault_rate(0, 1), None);
+        assert_eq!(sampler.pgscan_rate(0, 1), None);
+        assert_eq!(sampler.pswpout_rate(0, 1), None);
+    }
+
+    /// Check that the sampler works well
+    define_sampler_tests!{ Sampler }
+}
+
+
+/// Performance benchmarks
+///
+/// See the lib-wide benchmarks module for details on how to use these.
+///
+#[cfg(test)]
+mod benchmarks {
+    define_sampler_benchs!{ super::Sampler,
+                            "/proc/vmstat",
+                            100_000 }
+}
@@ -17,6 +17,10 @@
 //!
 //! The SamplingReader that is provided in this module is designed to properly
 //! account for these characteristics while reading these pseudo-files.
+//!
+//! It can also get the contents of the pseudo-file from a user-provided source
+//! instead of a local file, for agent architectures where the raw contents of
+//! /proc are shipped to a central host for parsing.
 
 use std::fs::File;
 use std::io::{Read, Result, Seek, SeekFrom};
@@ -30,23 +34,59 @@ use std::path::Path;
 /// sysfs and check that the above assumptions still hold.
 ///
 pub(crate) struct ProcFileReader {
-    /// Persistent handle to the file being sampled
-    file_handle: File,
+    /// Where the contents of the pseudo-file come from
+    source: ContentSource,
 
     /// Buffer in which the characters that are read out will be stored
     readout_buffer: String,
+
+    /// Buffer in which raw bytes are read out by sample_bytes(). This is only
+    /// allocated once that method has been called.
+    readout_bytes: Vec<u8>,
+
+    /// Contents of the previous readout, kept for change detection. This is
+    /// only allocated once sample_if_changed() has been called.
+    previous_contents: Option<String>,
 }
 //
 impl ProcFileReader {
     /// Attempt to open a pseudo-file
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
         let file_handle = File::open(path)?;
-        Ok(
-            Self {
-                file_handle,
-                readout_buffer: String::new(),
-            }
-        )
+        Self::from_file(file_handle)
+    }
+
+    /// Open a pseudo-file which is known to be large, reserving room for
+    /// "capacity" bytes of contents upfront
+    ///
+    /// The readout buffer grows as needed to fit the file contents, so this is
+    /// only an optimization: it avoids repeated reallocation during the first
+    /// readout, which matters when many samplers are set up at once.
+    ///
+    pub fn open_with_capacity<P: AsRef<Path>>(path: P,
+                                              capacity: usize) -> Result<Self>
+    {
+        let mut reader = Self::open(path)?;
+        reader.readout_buffer.reserve(capacity);
+        Ok(reader)
+    }
+
+    /// Sample a pseudo-file through a handle which the caller already opened
+    ///
+    /// The handle is rewound, so that sampling always starts from the
+    /// beginning of the file regardless of prior use.
+    ///
+    pub fn from_file(mut file_handle: File) -> Result<Self> {
+        file_handle.seek(SeekFrom::Start(0u64))?;
+        Ok(Self::with_source(ContentSource::File(file_handle)))
+    }
+
+    /// Get the contents of a pseudo-file from a user-provided source, which is
+    /// called once per sample and returns the latest file contents
+    pub fn from_source<S>(source: S) -> Self
+        where S: FnMut() -> Result<String> + Send + 'static
+    {
+        Self::with_source(ContentSource::Remote(Box::new(source)))
     }
 
     /// Acquire a new sample of data from the file
@@ -62,28 +102,163 @@ impl ProcFileReader {
     /// somewhere. So the only possible errors are logic errors in the parser
     /// and major system issues such as OOM, for which panicking is fine.
     ///
-    pub fn sample<F, R>(&mut self, mut parser: F) -> Result<R>
-        where F: FnMut(&str) -> R
+    pub fn sample<F, R>(&mut self, parser: F) -> Result<R>
+        where F: FnOnce(&str) -> R
     {
         // Read the current contents of the file
-        self.file_handle.read_to_string(&mut self.readout_buffer)?;
+        self.read_contents()?;
 
         // Run the user-provided parser on the file contents
         let result = parser(&self.readout_buffer);
 
         // Reset the reader state to prepare for the next sample
         self.readout_buffer.clear();
-        self.file_handle.seek(SeekFrom::Start(0u64))?;
+        self.rewind()?;
 
         // Return the parser's results
         Ok(result)
     }
+
+    /// Acquire a new sample of data from the file, as raw bytes
+    ///
+    /// This works like sample(), but skips the UTF-8 validation of the file
+    /// contents, which is measurable overhead at the highest sampling rates.
+    /// It is meant for parsers which only expect ASCII text and can work on
+    /// bytes directly.
+    ///
+    pub fn sample_bytes<F, R>(&mut self, parser: F) -> Result<R>
+        where F: FnOnce(&[u8]) -> R
+    {
+        // Read the current contents of the file
+        match self.source {
+            ContentSource::File(ref mut file_handle) => {
+                file_handle.read_to_end(&mut self.readout_bytes)?;
+                if self.readout_bytes.is_empty() {
+                    file_handle.seek(SeekFrom::Start(0u64))?;
+                    file_handle.read_to_end(&mut self.readout_bytes)?;
+                }
+            },
+            ContentSource::Remote(ref mut source) => {
+                self.readout_bytes = source()?.into_bytes();
+            },
+        }
+
+        // Run the user-provided parser on the file contents
+        let result = parser(&self.readout_bytes);
+
+        // Reset the reader state to prepare for the next sample
+        self.readout_bytes.clear();
+        self.rewind()?;
+        Ok(result)
+    }
+
+    /// Acquire a new sample of data from the file, but only hand it to the
+    /// user-provided parser if it differs from the previous sample
+    ///
+    /// This is meant for pseudo-files which change rarely, but are sampled at
+    /// a high rate, where re-parsing identical contents is a waste of time.
+    /// The comparison is a byte-for-byte equality check, which is much cheaper
+    /// than parsing. Returns true if the parser was called.
+    ///
+    /// The previous sample is only remembered by this method, so the first
+    /// call always invokes the parser, and so does the first call after the
+    /// contents changed. Interleaving calls to sample() is fine, but their
+    /// readouts are not taken into account for change detection.
+    ///
+    pub fn sample_if_changed<F>(&mut self, parser: F) -> Result<bool>
+        where F: FnOnce(&str)
+    {
+        // Read the current contents of the file
+        self.read_contents()?;
+
+        // Check if they differ from the previous readout, if any
+        let changed = match self.previous_contents {
+            Some(ref previous) => {
+                previous.as_bytes() != self.readout_buffer.as_bytes()
+            },
+            None => true,
+        };
+
+        // If so, run the user-provided parser and keep the new contents
+        // around, recycling the previous buffer as the next readout buffer
+        if changed {
+            parser(&self.readout_buffer);
+            let mut recycled_buffer =
+                self.previous_contents.take().unwrap_or_default();
+            ::std::mem::swap(&mut recycled_buffer, &mut self.readout_buffer);
+            self.previous_contents = Some(recycled_buffer);
+        }
+
+        // Reset the reader state to prepare for the next sample
+        self.readout_buffer.clear();
+        self.rewind()?;
+        Ok(changed)
+    }
+
+    /// INTERNAL: Read the current contents of the file into the readout buffer
+    ///
+    /// Reading a pseudo-file at the exact moment where the kernel regenerates
+    /// it can result in an empty readout. This is observable when sampling at
+    /// high rates, so we retry once, from the start of the file, before
+    /// handing such an empty readout over to the parser. sample_bytes() does
+    /// the same.
+    ///
+    /// User-provided sources are trusted to produce complete contents.
+    ///
+    fn read_contents(&mut self) -> Result<()> {
+        match self.source {
+            ContentSource::File(ref mut file_handle) => {
+                file_handle.read_to_string(&mut self.readout_buffer)?;
+                if self.readout_buffer.is_empty() {
+                    file_handle.seek(SeekFrom::Start(0u64))?;
+                    file_handle.read_to_string(&mut self.readout_buffer)?;
+                }
+            },
+            ContentSource::Remote(ref mut source) => {
+                self.readout_buffer = source()?;
+            },
+        }
+        Ok(())
+    }
+
+    /// INTERNAL: Prepare the content source for the next readout
+    fn rewind(&mut self) -> Result<()> {
+        if let ContentSource::File(ref mut file_handle) = self.source {
+            file_handle.seek(SeekFrom::Start(0u64))?;
+        }
+        Ok(())
+    }
+
+    /// INTERNAL: Build a reader around some content source
+    fn with_source(source: ContentSource) -> Self {
+        Self {
+            source,
+            readout_buffer: String::new(),
+            readout_bytes: Vec::new(),
+            previous_contents: None,
+        }
+    }
+}
+
+
+/// INTERNAL: Source of the contents of a pseudo-file
+enum ContentSource {
+    /// Local file, which is read again on every sample
+    File(File),
+
+    /// User-provided source of file contents (e.g. forwarded from a remote
+    /// host), which is called again on every sample
+    Remote(Box<dyn FnMut() -> Result<String> + Send>),
 }
 
 
 /// Unit tests
 #[cfg(test)]
 mod tests {
+    use std::env;
+    use std::fs::{self, File, OpenOptions};
+    use std::io::{self, Write};
+    use std::process;
     use std::thread;
     use std::time::Duration;
     use super::ProcFileReader;
@@ -112,4 +287,144 @@ mod tests {
         // The contents should have changed
         assert!(meas1 != meas2, "Uptime should change over time");
     }
+
+    /// Check that unchanged file contents are only parsed once
+    #[test]
+    fn change_detection() {
+        // Open a file which should not change, and one which should
+        let mut version_reader =
+            ProcFileReader::open("/proc/version")
+                           .expect("Should be able to open /proc/version");
+        let mut uptime_reader =
+            ProcFileReader::open("/proc/uptime")
+                           .expect("Should be able to open /proc/uptime");
+
+        // The first readout should always be parsed
+        let mut calls = 0;
+        assert!(version_reader.sample_if_changed(|_| calls += 1)
+                              .expect("Should be able to read version"));
+        assert!(uptime_reader.sample_if_changed(|_| calls += 1)
+                             .expect("Should be able to read uptime"));
+        assert_eq!(calls, 2);
+
+        // After some time, only the file that changed should be parsed again
+        thread::sleep(Duration::from_millis(50));
+        assert!(!version_reader.sample_if_changed(|_| calls += 1)
+                               .expect("Should be able to read version"));
+        assert!(uptime_reader.sample_if_changed(|_| calls += 1)
+                             .expect("Should be able to read uptime"));
+        assert_eq!(calls, 3);
+
+        // Regular sampling should still work in between
+        let mut version = String::new();
+        version_reader.sample(|text| version.push_str(text))
+                      .expect("Should be able to read version");
+        assert!(version.starts_with("Linux"));
+        assert!(!version_reader.sample_if_changed(|_| calls += 1)
+                               .expect("Should be able to read version"));
+        assert_eq!(calls, 3);
+    }
+
+    /// Check that file contents can come from a user-provided source
+    #[test]
+    fn remote_source() {
+        // Set up a source which produces a few file contents, then fails
+        let mut contents = vec!["1 2\n", "3 4\n"].into_iter();
+        let mut reader = ProcFileReader::from_source(move || {
+            contents.next()
+                    .map(|text| text.to_owned())
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::Other,
+                                                  "Connection lost"))
+        });
+
+        // Sampling should forward the source's contents, then its error
+        let mut readouts = Vec::new();
+        reader.sample(|text| readouts.push(text.to_owned()))
+              .expect("Should be able to read the first contents");
+        assert!(reader.sample_if_changed(|text| readouts.push(text.to_owned()))
+                      .expect("Should be able to read the second contents"));
+        assert_eq!(readouts, vec!["1 2\n", "3 4\n"]);
+        assert!(reader.sample(|_| ()).is_err());
+    }
+
+    /// Check that raw bytes and text readouts have the same contents
+    #[test]
+    fn bytes_sampling() {
+        let mut reader =
+            ProcFileReader::open("/proc/version")
+                           .expect("Should be able to open /proc/version");
+        let mut text = String::new();
+        reader.sample(|contents| text.push_str(contents))
+              .expect("Should be able to read version as text");
+        for _ in 0..2 {
+            let mut bytes = Vec::new();
+            reader.sample_bytes(|contents| bytes.extend_from_slice(contents))
+                  .expect("Should be able to read version as bytes");
+            assert_eq!(bytes, text.as_bytes());
+        }
+
+        // User-provided sources are supported as well
+        let mut reader = ProcFileReader::from_source(|| Ok("1 2\n".to_owned()));
+        let mut bytes = Vec::new();
+        reader.sample_bytes(|contents| bytes.extend_from_slice(contents))
+              .expect("Should be able to read the source");
+        assert_eq!(bytes, b"1 2\n");
+    }
+
+    /// Check that the readout buffer capacity hint is honored
+    #[test]
+    fn capacity_hint() {
+        let mut reader =
+            ProcFileReader::open_with_capacity("/proc/uptime", 1 << 16)
+                           .expect("Should be able to open /proc/uptime");
+        assert!(reader.readout_buffer.capacity() >= 1 << 16);
+        let mut uptime = String::new();
+        reader.sample(|text| uptime.push_str(text))
+              .expect("Should be able to read uptime");
+        assert!(!uptime.is_empty());
+        assert!(reader.readout_buffer.capacity() >= 1 << 16);
+    }
+
+    /// Check that files which were opened by the caller can be sampled
+    #[test]
+    fn existing_file() {
+        // Create a file and leave its handle at the end of the contents
+        let path = env::temp_dir().join(format!("existing-handle-{}",
+                                                process::id()));
+        let mut file = OpenOptions::new().read(true)
+                                         .write(true)
+                                         .create(true)
+                                         .truncate(true)
+                                         .open(&path)
+                                         .expect("Should be able to create it");
+        file.write_all(b"42\n").expect("Should be able to write it");
+
+        // Wrapping the handle should sample the full contents, every time
+        let mut reader = ProcFileReader::from_file(file)
+                                        .expect("Should be able to wrap it");
+        for _ in 0..2 {
+            let mut readout = None;
+            reader.sample(|text| readout = Some(text.to_owned()))
+                  .expect("Should be able to read the file");
+            assert_eq!(readout, Some("42\n".to_owned()));
+        }
+        fs::remove_file(&path).expect("Should be able to remove the file");
+    }
+
+    /// Check that empty readouts are handed over to the parser
+    #[test]
+    fn empty_file() {
+        let path = env::temp_dir().join(format!("empty-readout-{}",
+                                                process::id()));
+        File::create(&path).expect("Should be able to create an empty file");
+        let mut reader = ProcFileReader::open(&path)
+                                        .expect("Should be able to open it");
+        for _ in 0..2 {
+            let mut readout = None;
+            reader.sample(|text| readout = Some(text.to_owned()))
+                  .expect("Empty readouts should not be an error");
+            assert_eq!(readout, Some(String::new()));
+        }
+        fs::remove_file(&path).expect("Should be able to remove the file");
+    }
 }
@@ -0,0 +1,296 @@
+//! This module allows replaying recorded snapshots of a pseudo-file
+//!
+//! Sometimes, one wants to analyze pseudo-file contents which were not sampled
+//! live on the host system, for example because they were captured on another
+//! machine, or because they exhibit some interesting behaviour (such as a
+//! schema change) which we want to reproduce in regression tests.
+//!
+//! For this purpose, every sampler provides a replay() constructor which takes
+//! a directory of numbered snapshots of its pseudo-file, named after the file
+//! (e.g. "stat.0000", "stat.0001"...), and feeds them in order through the
+//! parser and data container, as if they had been sampled live. This module
+//! provides the infrastructure needed by that constructor.
+//!
+//! Long captures are more conveniently stored as a single capture file, which
+//! every sampler can replay using the replay_capture() constructor. In such a
+//! file, each snapshot is preceded by a line containing its length in bytes,
+//! in decimal form. Captures compress very well, so if the "flate2" feature is
+//! enabled, capture files whose name ends in ".gz" are decompressed on the fly.
+
+#[cfg(feature = "flate2")]
+use flate2::read::GzDecoder;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+
+/// List the snapshots of a pseudo-file which are present in a directory, in
+/// sampling order
+///
+/// Snapshot files are named after the pseudo-file that they were taken from,
+/// followed by a dot and a decimal sample number. For example, snapshots of
+/// /proc/stat should be called "stat.0", "stat.1"... or "stat.0000",
+/// "stat.0001"... Other files from the directory are ignored.
+///
+/// An error is returned if no snapshot is found.
+///
+pub(crate) fn snapshot_files<P>(directory: P,
+                                file_location: &str) -> io::Result<Vec<PathBuf>>
+    where P: AsRef<Path>
+{
+    // Snapshots are named after the last component of the pseudo-file path
+    let file_name = Path::new(file_location).file_name()
+                                            .and_then(|name| name.to_str())
+                                            .expect("Invalid pseudo-file path");
+
+    // Collect the numbered snapshots from the directory
+    let mut snapshots = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        let index = path.file_name()
+                        .and_then(|name| name.to_str())
+                        .and_then(|name| snapshot_index(name, file_name));
+        if let Some(index) = index {
+            snapshots.push((index, path));
+        }
+    }
+
+    // Make sure that there is something to replay
+    if snapshots.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound,
+                                  "No pseudo-file snapshot found"));
+    }
+
+    // Sort the snapshots by sample number
+    snapshots.sort_by_key(|&(index, _)| index);
+    Ok(snapshots.into_iter().map(|(_, path)| path).collect())
+}
+
+
+/// Load the contents of a pseudo-file snapshot into a buffer, replacing any
+/// previous contents of that buffer
+pub(crate) fn read_snapshot(path: &Path,
+                            buffer: &mut String) -> io::Result<()> {
+    buffer.clear();
+    File::open(path)?.read_to_string(buffer)?;
+    Ok(())
+}
+
+
+/// Sequential reader for the snapshots of a capture file
+pub(crate) struct CaptureReader {
+    /// Buffered reader of the (decompressed) capture file
+    reader: Box<dyn BufRead>,
+
+    /// Buffer for the snapshot length headers
+    header: String,
+}
+//
+impl CaptureReader {
+    /// Open a capture file and load its first snapshot into a buffer,
+    /// replacing any previous contents of that buffer
+    ///
+    /// An error is returned if the capture file contains no snapshot, or if it
+    /// is gzip-compressed and the "flate2" feature is not enabled.
+    ///
+    pub fn open(path: &Path, buffer: &mut String) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let reader: Box<dyn BufRead> =
+            if path.extension().map_or(false, |ext| ext == "gz") {
+                Self::decompress(file)?
+            } else {
+                Box::new(BufReader::new(file))
+            };
+        let mut capture = Self {
+            reader,
+            header: String::new(),
+        };
+        if !capture.next_snapshot(buffer)? {
+            return Err(io::Error::new(io::ErrorKind::NotFound,
+                                      "No pseudo-file snapshot found"));
+        }
+        Ok(capture)
+    }
+
+    /// Load the next snapshot into a buffer, replacing any previous contents
+    /// of that buffer, and tell whether there was a snapshot left to read
+    pub fn next_snapshot(&mut self, buffer: &mut String) -> io::Result<bool> {
+        // Read the length header, if we did not reach the end of the capture
+        self.header.clear();
+        if self.reader.read_line(&mut self.header)? == 0 {
+            return Ok(false);
+        }
+        let length: u64 = self.header.trim_end().parse().map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidData,
+                           "Invalid snapshot length in capture file")
+        })?;
+
+        // Read the snapshot
+        buffer.clear();
+        let read_length = (&mut self.reader).take(length)
+                                            .read_to_string(buffer)?;
+        if read_length as u64 != length {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                      "Truncated snapshot in capture file"));
+        }
+        Ok(true)
+    }
+
+    /// INTERNAL: Decompress a gzip-compressed capture file on the fly
+    #[cfg(feature = "flate2")]
+    fn decompress(file: File) -> io::Result<Box<dyn BufRead>> {
+        Ok(Box::new(BufReader::new(GzDecoder::new(file))))
+    }
+
+    /// INTERNAL: Report that gzip-compressed capture files are not supported
+    #[cfg(not(feature = "flate2"))]
+    fn decompress(_file: File) -> io::Result<Box<dyn BufRead>> {
+        Err(io::Error::new(io::ErrorKind::InvalidInput,
+                           "Reading compressed captures requires the \
+                            \"flate2\" feature"))
+    }
+}
+
+
+/// INTERNAL: Extract the sample number of a snapshot from its file name, or
+///           return None if that file is not a snapshot of the pseudo-file
+fn snapshot_index(snapshot_name: &str, file_name: &str) -> Option<u64> {
+    if !snapshot_name.starts_with(file_name) {
+        return None;
+    }
+    let suffix = &snapshot_name[file_name.len()..];
+    if !suffix.starts_with('.') {
+        return None;
+    }
+    suffix[1..].parse().ok()
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use ::data::SampledData;
+    use ::procfs::stat;
+    use ::scratch::{scratch_directory, write_file};
+    #[cfg(feature = "flate2")]
+    use flate2::{Compression, write::GzEncoder};
+    use std::fs;
+    #[cfg(feature = "flate2")]
+    use std::fs::File;
+    #[cfg(feature = "flate2")]
+    use std::io::Write;
+    use super::{snapshot_files, snapshot_index};
+
+    /// Check that snapshot file names are recognized correctly
+    #[test]
+    fn snapshot_names() {
+        assert_eq!(snapshot_index("stat.0000", "stat"), Some(0));
+        assert_eq!(snapshot_index("stat.42", "stat"), Some(42));
+        assert_eq!(snapshot_index("stat", "stat"), None);
+        assert_eq!(snapshot_index("stat.", "stat"), None);
+        assert_eq!(snapshot_index("stat.old", "stat"), None);
+        assert_eq!(snapshot_index("statm.0000", "stat"), None);
+        assert_eq!(snapshot_index("meminfo.0000", "stat"), None);
+    }
+
+    /// Check that a directory of /proc/stat snapshots is replayed correctly
+    #[test]
+    fn replay_stat() {
+        // Write two hand-written snapshots, out of order and with some noise
+        let directory = scratch_directory("replay_stat");
+        write_file(&directory.join("stat.0001"),
+                   "cpu 20 2 30 400\n\
+                    ctxt 1300\n\
+                    btime 1500000000\n");
+        write_file(&directory.join("stat.0000"),
+                   "cpu 10 1 20 300\n\
+                    ctxt 1000\n\
+                    btime 1500000000\n");
+        write_file(&directory.join("meminfo.0000"), "MemTotal: 42 kB\n");
+
+        // Check that snapshots are listed in order, ignoring other files
+        let snapshots = snapshot_files(&directory, "/proc/stat")
+                                      .expect("Failed to list snapshots");
+        assert_eq!(snapshots, vec![directory.join("stat.0000"),
+                                   directory.join("stat.0001")]);
+
+        // Check that replaying them is equivalent to sampling them live
+        let data = stat::Sampler::replay(&directory)
+                                 .expect("Failed to replay snapshots");
+        assert_eq!(data.len(), 2);
+        assert_eq!(data.context_switches(), Some(&[1000, 1300][..]));
+        let cpu = data.all_cpus().expect("Missing CPU statistics");
+        assert_eq!(cpu.user_ticks(), &[10, 20]);
+        assert_eq!(cpu.idle_ticks(), &[300, 400]);
+
+        // Replaying an empty directory should fail
+        let empty = scratch_directory("replay_stat_empty");
+        assert!(stat::Sampler::replay(&empty).is_err());
+
+        // Clean up after ourselves
+        fs::remove_dir_all(directory).expect("Failed to clean up");
+        fs::remove_dir_all(empty).expect("Failed to clean up");
+    }
+
+    /// Check that capture files are replayed correctly
+    #[test]
+    fn replay_capture() {
+        let directory = scratch_directory("replay_capture");
+        let capture = directory.join("stat.capture");
+        write_file(&capture, &capture_contents());
+        let data = stat::Sampler::replay_capture(&capture)
+                                 .expect("Failed to replay capture");
+        assert_eq!(data.len(), 2);
+        assert_eq!(data.context_switches(), Some(&[1000, 1300][..]));
+
+        // Empty and truncated captures should be rejected
+        write_file(&directory.join("empty.capture"), "");
+        assert!(stat::Sampler::replay_capture(
+            directory.join("empty.capture")
+        ).is_err());
+        write_file(&directory.join("truncated.capture"), "42\nctxt 1000\n");
+        assert!(stat::Sampler::replay_capture(
+            directory.join("truncated.capture")
+        ).is_err());
+
+        // Without the flate2 feature, compressed captures are rejected
+        #[cfg(not(feature = "flate2"))]
+        {
+            write_file(&directory.join("stat.capture.gz"), "");
+            assert!(stat::Sampler::replay_capture(
+                directory.join("stat.capture.gz")
+            ).is_err());
+        }
+        fs::remove_dir_all(directory).expect("Failed to clean up");
+    }
+
+    /// Check that gzip-compressed capture files are replayed correctly
+    #[cfg(feature = "flate2")]
+    #[test]
+    fn replay_compressed_capture() {
+        let directory = scratch_directory("replay_compressed_capture");
+        let capture = directory.join("stat.capture.gz");
+        let mut encoder = GzEncoder::new(
+            File::create(&capture).expect("Failed to create capture"),
+            Compression::default()
+        );
+        encoder.write_all(capture_contents().as_bytes())
+               .and_then(|_| encoder.finish())
+               .expect("Failed to write capture");
+        let data = stat::Sampler::replay_capture(&capture)
+                                 .expect("Failed to replay capture");
+        assert_eq!(data.len(), 2);
+        assert_eq!(data.context_switches(), Some(&[1000, 1300][..]));
+        fs::remove_dir_all(directory).expect("Failed to clean up");
+    }
+
+    /// Build a capture file of two /proc/stat snapshots
+    fn capture_contents() -> String {
+        let mut capture = String::new();
+        for snapshot in &["ctxt 1000\nbtime 1500000000\n",
+                          "ctxt 1300\nbtime 1500000000\n"] {
+            capture.push_str(&format!("{}\n{}", snapshot.len(), snapshot));
+        }
+        capture
+    }
+}
@@ -18,11 +18,38 @@
 /// method is called. For example, the invocation...
 ///
 /// `define_sampler!(MemInfoSampler : "/proc/meminfo" => MemInfoParser
+///                                                   => MemInfoStream
 ///                                                   => MemInfoData)`
 ///
 /// ...defines a sampler called "MemInfoSampler" which loads data from the file
-/// /proc/meminfo and feeds it to a parser of type "MemInfoParser", and then
-/// stores the parsed data into a container called "MemInfoData".
+/// /proc/meminfo and feeds it to a parser of type "MemInfoParser", whose output
+/// is a record stream of type "MemInfoStream<'a>", and then stores the parsed
+/// data into a container called "MemInfoData".
+///
+/// The stream type must be named because the parse step and the store step are
+/// separable: a sampler can also hand its freshly parsed record stream to user
+/// code via sample_with(), bypassing the internal data container.
+///
+/// Per-process pseudo-files, which live in /proc/<pid>, are handled by putting
+/// the "pid" keyword in front of the file name. For example, the invocation...
+///
+/// `define_sampler!(StatusSampler : pid "status" => StatusParser
+///                                               => StatusStream
+///                                               => StatusData)`
+///
+/// ...defines a sampler for /proc/self/status, which can also be pointed to
+/// /proc/<pid>/status for any other process via its for_pid() constructor.
+/// Such samplers also record the identity of the process that they sample, so
+/// that they can report its command line and detect PID reuse.
+///
+/// Pseudo-files which are known to be large, such as socket tables, can come
+/// with a hint of how many bytes should be initially reserved for reading
+/// them, so that the first readout does not need to grow its buffer. This is
+/// done by appending a "buffer" clause to the file location, for example...
+///
+/// `define_sampler!(TcpSampler : "/proc/net/tcp", buffer 65536 => TcpParser
+///                                                             => TcpStream
+///                                                             => TcpData)`
 ///
 /// In today's Rust, this job must be done via macros, because Rust does not yet
 /// support generics with value parameters. In future Rust, once this genericity
@@ -34,29 +61,241 @@
 /// what the data container's interface should be like.
 ///
 macro_rules! define_sampler {
-    ($sampler: ident : $file_location:expr => $parser:ty => $container:ty) => {
+    // Sampler for a per-process pseudo-file, such as /proc/<pid>/status
+    ($sampler: ident : pid $file_name:expr => $parser:ty
+                                          => $stream:ident
+                                          => $container:ty) => {
+        define_sampler!{ @common $sampler : $file_name, None => $parser
+                                                             => $stream
+                                                             => $container }
+        //
+        impl $sampler {
+            /// Create a new sampler for /proc/self/$file_name, which describes
+            /// the process in which this code is running
+            pub fn new() -> io::Result<Self> {
+                Self::open_process(None, None)
+            }
+
+            /// Create a new sampler for /proc/<pid>/$file_name
+            ///
+            /// If the process exits and its PID is handed over to another
+            /// process, sampling fails with a PidReused error (see the
+            /// procfs::process::identity module).
+            ///
+            pub fn for_pid(pid: u32) -> io::Result<Self> {
+                Self::open_process(Some(pid), None)
+            }
+
+            /// Create a new sampler for /proc/<pid>/$file_name which only
+            /// keeps the most recent "capacity" samples around (see the
+            /// new_bounded() constructor of system-wide samplers)
+            pub fn for_pid_bounded(pid: u32,
+                                   capacity: usize) -> io::Result<Self> {
+                assert!(capacity > 0, "Bounded samplers need some capacity");
+                Self::open_process(Some(pid), Some(capacity))
+            }
+
+            /// Create a new sampler for /proc/self/$file_name which only keeps
+            /// the most recent "capacity" samples around
+            pub fn new_bounded(capacity: usize) -> io::Result<Self> {
+                assert!(capacity > 0, "Bounded samplers need some capacity");
+                Self::open_process(None, Some(capacity))
+            }
+
+            /// Identity of the sampled process, or None if this sampler was
+            /// built from a user-provided source or file handle
+            pub fn process(&self)
+                -> Option<&::procfs::process::identity::ProcessIdentity>
+            {
+                self.process.as_ref()
+            }
+
+            /// Command line of the sampled process, if known (see process())
+            pub fn command_line(&self) -> Option<&str> {
+                self.process.as_ref().map(|process| process.command_line())
+            }
+
+            /// INTERNAL: Create a new sampler for /proc/<pid>/$file_name, or
+            ///           /proc/self/$file_name if no PID is specified
+            fn open_process(pid: Option<u32>,
+                            capacity: Option<usize>) -> io::Result<Self> {
+                use ::procfs::process::identity::ProcessIdentity;
+                let (process, file_location) = match pid {
+                    Some(pid) => {
+                        (ProcessIdentity::for_pid(pid)?,
+                         format!("/proc/{}/{}", pid, $file_name))
+                    },
+                    None => {
+                        (ProcessIdentity::for_self()?,
+                         concat!("/proc/self/", $file_name).to_owned())
+                    },
+                };
+                let mut sampler = Self::open(file_location, capacity)?;
+                sampler.process = Some(process);
+                Ok(sampler)
+            }
+        }
+    };
+
+    // Sampler for a large pseudo-file with a fixed location, such as
+    // /proc/net/tcp, with a hint of how large the readout buffer should be
+    ($sampler: ident : $file_location:expr, buffer $buffer_hint:expr
+                                           => $parser:ty
+                                           => $stream:ident
+                                           => $container:ty) => {
+        define_sampler!{ @common $sampler : $file_location, Some($buffer_hint)
+                                           => $parser
+                                           => $stream
+                                           => $container }
+        define_sampler!{ @fixed $sampler : $file_location }
+    };
+
+    // Sampler for a pseudo-file with a fixed location, such as /proc/stat
+    ($sampler: ident : $file_location:expr => $parser:ty
+                                           => $stream:ident
+                                           => $container:ty) => {
+        define_sampler!{ @common $sampler : $file_location, None => $parser
+                                                                 => $stream
+                                                                 => $container }
+        define_sampler!{ @fixed $sampler : $file_location }
+    };
+
+    // INTERNAL: Constructors of samplers for pseudo-files with fixed locations
+    (@fixed $sampler: ident : $file_location:expr) => {
+        impl $sampler {
+            /// Create a new sampler for $file_location
+            pub fn new() -> io::Result<Self> {
+                Self::open($file_location, None)
+            }
+
+            /// Create a new sampler for $file_location which only keeps the
+            /// most recent "capacity" samples around, dropping older ones
+            ///
+            /// This is intended for long-running monitoring at high sampling
+            /// rates, where keeping all samples would use unbounded memory.
+            ///
+            /// Once "capacity" samples have been acquired, each new sample
+            /// drops the oldest one, so exactly "capacity" samples are
+            /// available from that point on. The remaining samples must then
+            /// be moved to the front of the storage, so large capacities make
+            /// sampling more expensive.
+            ///
+            pub fn new_bounded(capacity: usize) -> io::Result<Self> {
+                assert!(capacity > 0, "Bounded samplers need some capacity");
+                Self::open($file_location, Some(capacity))
+            }
+
+            /// Create a new sampler for $file_location which only keeps the
+            /// latest sample and the one before it around
+            ///
+            /// This is intended for low-frequency polling, where only the
+            /// change since the previous sample is of interest: the *_between()
+            /// helpers of the data container can be called on the indices
+            /// (len-2, len-1). Storage is used as two slots: once both are
+            /// filled, each new sample moves the newest one into the first
+            /// slot and takes the second one, so the storage never grows and
+            /// memory usage is constant no matter how long the sampler runs.
+            ///
+            /// If the new sample is rejected due to a schema change, only the
+            /// newest sample remains afterwards.
+            ///
+            pub fn new_latest_only() -> io::Result<Self> {
+                let mut sampler = Self::open($file_location, Some(2))?;
+                sampler.latest_only = true;
+                Ok(sampler)
+            }
+        }
+    };
+
+    // INTERNAL: Sampler definition which does not depend on the file location
+    (@common $sampler: ident : $file_location:expr,
+                               $buffer_hint:expr => $parser:ty
+                                                 => $stream:ident
+                                                 => $container:ty) => {
         // Hopefully the host won't need to import these...
         use ::reader::ProcFileReader;
         use std::io;
+        use std::path::Path;
 
-        /// Mechanism for sampling measurements from $file_location
+        /// Mechanism for sampling measurements from a pseudo-file
         pub struct $sampler {
-            /// Reader object for $file_location
+            /// Reader object for the pseudo-file
             reader: ProcFileReader,
 
-            /// Streaming parser for $file_location
+            /// Streaming parser for the pseudo-file
             parser: $parser,
 
-            /// Samples of data extracted from $file_location
-            samples: $container
+            /// Samples of data extracted from the pseudo-file
+            samples: $container,
+
+            /// Time at which each stored sample was acquired
+            timestamps: Vec<::std::time::Instant>,
+
+            /// Maximal amount of samples to be kept around, if bounded
+            capacity: Option<usize>,
+
+            /// Truth that only the two latest samples are kept around, using
+            /// the storage as two slots (see new_latest_only())
+            latest_only: bool,
+
+            /// Interval at which the user intends to sample, if known
+            target_interval: Option<::std::time::Duration>,
+
+            /// Number of samples which were rejected due to schema changes
+            schema_changes: usize,
+
+            /// Identity of the sampled process, for per-process samplers
+            process: Option<::procfs::process::identity::ProcessIdentity>,
         }
         //
         impl $sampler {
-            /// Create a new sampler for $file_location
-            pub fn new() -> io::Result<Self> {
-                // Set up a sampling reader
-                let mut reader = ProcFileReader::open($file_location)?;
+            /// Create a new sampler which gets the contents of the pseudo-file
+            /// from a user-provided source instead of reading it
+            ///
+            /// This allows parsing pseudo-file contents which were forwarded
+            /// from another host, e.g. by a monitoring agent. The source is
+            /// called once at construction time, in order to set up parsing
+            /// and storage, and then once per sample.
+            ///
+            pub fn from_source<S>(source: S) -> io::Result<Self>
+                where S: FnMut() -> io::Result<String> + Send + 'static
+            {
+                Self::with_reader(ProcFileReader::from_source(source), None)
+            }
 
+            /// Create a new sampler which reads the pseudo-file through a file
+            /// handle that was opened by the caller
+            ///
+            /// This is useful when the file must be opened in a special way,
+            /// or was inherited from another process. It also allows pinning a
+            /// /proc/<pid> file: the handle keeps referring to the original
+            /// process even if its PID gets reused by a new one.
+            ///
+            pub fn from_file(file: ::std::fs::File) -> io::Result<Self> {
+                Self::with_reader(ProcFileReader::from_file(file)?, None)
+            }
+
+            /// INTERNAL: Create a new sampler, possibly with bounded capacity
+            fn open<P: AsRef<Path>>(file_location: P,
+                                    capacity: Option<usize>) -> io::Result<Self>
+            {
+                let buffer_hint: Option<usize> = $buffer_hint;
+                let reader = match buffer_hint {
+                    Some(buffer_capacity) => {
+                        ProcFileReader::open_with_capacity(file_location,
+                                                           buffer_capacity)?
+                    },
+                    None => ProcFileReader::open(file_location)?,
+                };
+                let mut sampler = Self::with_reader(reader, capacity)?;
+                ::data::SampledData::load_host_details(&mut sampler.samples);
+                Ok(sampler)
+            }
+
+            /// INTERNAL: Create a new sampler around a pseudo-file reader
+            fn with_reader(mut reader: ProcFileReader,
+                           capacity: Option<usize>) -> io::Result<Self>
+            {
                 // Build parsing and storage infrastructure from a first sample
                 let (parser, samples) = reader.sample(|file| {
                     let mut parser = <$parser>::new(file);
@@ -70,18 +309,317 @@ macro_rules! define_sampler {
                         reader,
                         parser,
                         samples,
+                        timestamps: Vec::new(),
+                        capacity,
+                        latest_only: false,
+                        target_interval: None,
+                        schema_changes: 0,
+                        process: None,
                     }
                 )
             }
 
-            /// Acquire a new sample of data from $file_location
+            /// Tell at which interval this sampler is meant to be sampled, so
+            /// that deviations from it can be measured by interval_jitter()
+            ///
+            /// The sampler does not enforce this interval in any way, that is
+            /// the job of the sampling loop (see e.g. the "pacer" module).
+            ///
+            pub fn with_target_interval(mut self,
+                                        interval: ::std::time::Duration)
+                -> Self
+            {
+                self.target_interval = Some(interval);
+                self
+            }
+
+            /// Acquire a new sample of data from the pseudo-file
+            ///
+            /// If the structure of the pseudo-file changed in a way that the
+            /// data container cannot follow, no data is stored, and an error
+            /// wrapping a SchemaChanged is returned. Similarly, per-process
+            /// samplers check that their PID still refers to the same process
+            /// (see ProcessIdentity::check()), both before and after reading
+            /// the pseudo-file, and discard the sample if it does not.
+            ///
             pub fn sample(&mut self) -> io::Result<()> {
+                // Make sure that we are still sampling the same process
+                Self::check_process(self.process.as_ref())?;
+
+                // Parse the pseudo-file and store the new sample
+                let timestamp = ::std::time::Instant::now();
+                {
+                    let samples = &mut self.samples;
+                    let timestamps = &mut self.timestamps;
+                    let latest_only = self.latest_only;
+                    let outcome = Self::parse_sample(
+                        &mut self.reader,
+                        &mut self.parser,
+                        self.process.as_ref(),
+                        |stream| {
+                            // In latest-only mode, free the second slot once
+                            // the file was read (see new_latest_only())
+                            if latest_only && timestamps.len() == 2 {
+                                ::data::SampledData::drop_oldest(samples, 1);
+                                timestamps.remove(0);
+                            }
+                            samples.push(stream)
+                        }
+                    )?;
+                    let result = ::data::PushOutcome::into_result(outcome);
+                    if let Err(schema_change) = result {
+                        self.schema_changes += 1;
+                        return Err(schema_change.into());
+                    }
+                }
+                self.timestamps.push(timestamp);
+
+                // If we went over capacity, drop the oldest samples (see
+                // new_bounded())
+                if let Some(capacity) = self.capacity {
+                    let length = ::data::SampledData::len(&self.samples);
+                    if length > capacity {
+                        self.drop_oldest(length - capacity);
+                    }
+                }
+                Ok(())
+            }
+
+            /// Acquire a new sample of data from the pseudo-file, and hand the
+            /// freshly parsed record stream to a user-provided closure instead
+            /// of storing it in the sampler's internal data container
+            ///
+            /// This is intended for streaming use cases, such as forwarding
+            /// every sample to a time-series database, where buffering samples
+            /// in memory would be wasteful. The samples() container is left
+            /// untouched by this method. As in sample(), per-process samplers
+            /// do not call the closure if their PID was reused.
+            ///
+            pub fn sample_with<F, R>(&mut self, f: F) -> io::Result<R>
+                where F: for<'a> FnOnce($stream<'a>) -> R
+            {
+                Self::check_process(self.process.as_ref())?;
+                Self::parse_sample(&mut self.reader,
+                                   &mut self.parser,
+                                   self.process.as_ref(),
+                                   f)
+            }
+
+            /// Like sample_with(), but only parse the pseudo-file and call the
+            /// user-provided closure if the file contents changed since the
+            /// last call to this method, returning None otherwise
+            ///
+            /// This saves parsing time on pseudo-files which rarely change,
+            /// but are sampled at a high rate.
+            ///
+            pub fn sample_with_if_changed<F, R>(&mut self,
+                                                f: F) -> io::Result<Option<R>>
+                where F: for<'a> FnOnce($stream<'a>) -> R
+            {
+                Self::check_process(self.process.as_ref())?;
+                let process = self.process.as_ref();
                 let parser = &mut self.parser;
-                let samples = &mut self.samples;
-                self.reader.sample(|file| {
-                    let stream = parser.parse(file);
-                    samples.push(stream);
-                })
+                let mut result = None;
+                self.reader.sample_if_changed(|file| {
+                    result = Some(Self::check_process(process).map(|()| {
+                        f(parser.parse(file))
+                    }));
+                })?;
+                result.map_or(Ok(None), |result| result.map(Some))
+            }
+
+            /// Access the samples that were acquired so far
+            pub fn samples(&self) -> &$container {
+                &self.samples
+            }
+
+            /// Number of samples which were not stored since this sampler was
+            /// created, because the structure of the pseudo-file changed in a
+            /// way that the data container cannot follow (see sample())
+            pub fn schema_change_count(&self) -> usize {
+                self.schema_changes
+            }
+
+            /// Time at which each of the samples was acquired
+            pub fn timestamps(&self) -> &[::std::time::Instant] {
+                &self.timestamps
+            }
+
+            /// Deviation of each interval between consecutive samples from the
+            /// target interval given to with_target_interval()
+            ///
+            /// Entry i is about the interval between samples i and i+1, so the
+            /// first sample, which has no predecessor, has no entry. Late and
+            /// early samples are not told apart: both are usually caused by
+            /// scheduling jitter, which makes rates computed over the affected
+            /// intervals less trustworthy.
+            ///
+            /// Returns None if no target interval was configured.
+            ///
+            pub fn interval_jitter(&self)
+                -> Option<Vec<::std::time::Duration>>
+            {
+                let target = self.target_interval?;
+                Some(self.timestamps.windows(2).map(|pair| {
+                    let interval = pair[1] - pair[0];
+                    if interval > target {
+                        interval - target
+                    } else {
+                        target - interval
+                    }
+                }).collect())
+            }
+
+            /// Drop the samples which were acquired more than "window" ago
+            ///
+            /// Unlike bounded samplers, which keep a fixed amount of samples
+            /// around, this allows keeping the samples from a fixed wall clock
+            /// time window, which is what monitoring dashboards usually need.
+            ///
+            pub fn retain_last(&mut self, window: ::std::time::Duration) {
+                let now = ::std::time::Instant::now();
+                if let Some(cutoff) = now.checked_sub(window) {
+                    self.retain_since(cutoff);
+                }
+            }
+
+            /// Drop the most recent sample, along with its timestamp, if any
+            ///
+            /// This is intended for keeping several samplers in sync, when a
+            /// sample was acquired from this one but sampling another one
+            /// failed afterwards.
+            ///
+            pub fn drop_newest(&mut self) {
+                if let Some(length) = self.timestamps.len().checked_sub(1) {
+                    ::data::SampledData::truncate(&mut self.samples, length);
+                    self.timestamps.truncate(length);
+                }
+            }
+
+            /// Repair the sample storage after an incomplete sample, by
+            /// truncating all of its time series to the shortest one, and tell
+            /// how many complete samples remain
+            ///
+            /// This is only needed if sampling panicked and the panic was
+            /// caught, which may leave a sample partially stored.
+            ///
+            pub fn truncate_to_consistent(&mut self) -> usize {
+                let length = ::data::SampledData::truncate_to_consistent(
+                    &mut self.samples
+                );
+                self.timestamps.truncate(length);
+                length
+            }
+
+            /// Replay a directory of recorded snapshots of the pseudo-file, as
+            /// if they had been sampled live, and return the resulting data
+            ///
+            /// See the "replay" module for the expected snapshot file names.
+            /// The first snapshot is used to set up the parser and the data
+            /// container, then every snapshot is parsed and stored in order.
+            ///
+            pub fn replay<P>(directory: P) -> io::Result<$container>
+                where P: AsRef<Path>
+            {
+                // Find the snapshots
+                let snapshots = ::replay::snapshot_files(directory,
+                                                         $file_location)?;
+
+                // Build parsing and storage infrastructure from the first one
+                let mut contents = String::new();
+                ::replay::read_snapshot(&snapshots[0], &mut contents)?;
+                let mut parser = <$parser>::new(&contents);
+                let mut samples = <$container>::new(parser.parse(&contents));
+
+                // Feed all snapshots into the data container, in order
+                for snapshot in snapshots.iter() {
+                    ::replay::read_snapshot(snapshot, &mut contents)?;
+                    let outcome = samples.push(parser.parse(&contents));
+                    ::data::PushOutcome::into_result(outcome)?;
+                }
+                Ok(samples)
+            }
+
+            /// Replay a capture file of the pseudo-file, which contains every
+            /// snapshot in sequence, and return the resulting data
+            ///
+            /// See the "replay" module for the capture file format and for the
+            /// handling of compressed captures. Otherwise, this works like
+            /// replay().
+            ///
+            pub fn replay_capture<P>(capture_file: P) -> io::Result<$container>
+                where P: AsRef<Path>
+            {
+                // Open the capture and set up parsing and storage
+                let mut contents = String::new();
+                let mut capture =
+                    ::replay::CaptureReader::open(capture_file.as_ref(),
+                                                  &mut contents)?;
+                let mut parser = <$parser>::new(&contents);
+                let mut samples = <$container>::new(parser.parse(&contents));
+
+                // Feed all snapshots into the data container, in order
+                loop {
+                    let outcome = samples.push(parser.parse(&contents));
+                    ::data::PushOutcome::into_result(outcome)?;
+                    if !capture.next_snapshot(&mut contents)? { break; }
+                }
+                Ok(samples)
+            }
+
+            /// INTERNAL: Drop the samples which were acquired before a cutoff
+            fn retain_since(&mut self, cutoff: ::std::time::Instant) {
+                let count = self.timestamps.iter()
+                                           .take_while(|&&t| t < cutoff)
+                                           .count();
+                self.drop_oldest(count);
+            }
+
+            /// INTERNAL: Drop the oldest samples, along with their timestamps
+            fn drop_oldest(&mut self, count: usize) {
+                ::data::SampledData::drop_oldest(&mut self.samples, count);
+                self.timestamps.drain(..count);
+            }
+
+            /// INTERNAL: Read the pseudo-file, parse it, and hand the resulting
+            /// record stream to some consumer, without storing anything
+            ///
+            /// If a sampled process is specified, the PID may have been handed
+            /// over to another process while the pseudo-file was being read,
+            /// so its identity is checked again before parsing.
+            ///
+            fn parse_sample<F, R>(
+                reader: &mut ProcFileReader,
+                parser: &mut $parser,
+                process: Option<&::procfs::process::identity::ProcessIdentity>,
+                consumer: F
+            ) -> io::Result<R>
+                where F: for<'a> FnOnce($stream<'a>) -> R
+            {
+                reader.sample(|file| {
+                    Self::check_process(process)?;
+                    Ok(consumer(parser.parse(file)))
+                })?
+            }
+
+            /// INTERNAL: Check that the sampled process, if any, is still the
+            /// one which sampling started with (see ProcessIdentity::check())
+            fn check_process(
+                process: Option<&::procfs::process::identity::ProcessIdentity>
+            ) -> io::Result<()> {
+                process.map_or(Ok(()), |process| process.check())
+            }
+        }
+        //
+        impl ::pacer::PacedSampler for $sampler {
+            type Data = $container;
+
+            fn sample(&mut self) -> io::Result<()> {
+                $sampler::sample(self)
+            }
+
+            fn samples(&self) -> &$container {
+                $sampler::samples(self)
             }
         }
     };
@@ -114,6 +652,142 @@ macro_rules! define_sampler_tests {
            sampler.sample().expect("Failed to acquire a second sample");
            assert_eq!(sampler.samples.len(), 2);
         }
+
+        /// Check that bounded samplers do not grow beyond twice their
+        /// capacity, and go back to their capacity when they reach it
+        #[test]
+        fn bounded_sampling() {
+            const CAPACITY: usize = 3;
+            let mut sampler = <$sampler>::new_bounded(CAPACITY)
+                                         .expect("Failed to create a sampler");
+            for sample in 1..CAPACITY+6 {
+                sampler.sample().expect("Failed to acquire a sample");
+                let expected_len = ::std::cmp::min(sample, CAPACITY);
+                assert_eq!(sampler.samples.len(), expected_len);
+                assert_eq!(sampler.timestamps.len(), expected_len);
+            }
+        }
+
+        /// Check that streaming samples to a closure bypasses internal storage
+        #[test]
+        fn streaming_sampling() {
+            let mut sampler = <$sampler>::new()
+                                         .expect("Failed to create a sampler");
+            let mut calls = 0;
+            sampler.sample_with(|_stream| calls += 1)
+                   .expect("Failed to acquire a sample");
+            assert_eq!(calls, 1);
+            assert!(sampler.sample_with_if_changed(|_stream| calls += 1)
+                           .expect("Failed to acquire a sample")
+                           .is_some());
+            assert_eq!(calls, 2);
+            assert_eq!(sampler.samples.len(), 0);
+        }
+
+        /// Check that samples can be dropped based on their timestamps
+        #[test]
+        fn time_window() {
+            // Acquire a few samples, and pretend that they were acquired one
+            // second apart from each other
+            let mut sampler = <$sampler>::new()
+                                         .expect("Failed to create a sampler");
+            for _ in 0..4 {
+                sampler.sample().expect("Failed to acquire a sample");
+            }
+            assert_eq!(sampler.timestamps().len(), 4);
+            let start = sampler.timestamps[0];
+            let secs = |s: u64| ::std::time::Duration::from_secs(s);
+            for (idx, timestamp) in sampler.timestamps.iter_mut().enumerate() {
+                *timestamp = start + secs(idx as u64);
+            }
+
+            // Drop the samples from before the middle of that time span
+            sampler.retain_since(start + secs(1) + secs(1) / 2);
+            assert_eq!(sampler.samples.len(), 2);
+            assert_eq!(sampler.timestamps().len(), 2);
+            assert_eq!(sampler.timestamps[0], start + secs(2));
+
+            // A long enough time window keeps everything
+            sampler.retain_last(secs(3600));
+            assert_eq!(sampler.samples.len(), 2);
+        }
+
+        /// Check that the jitter of sampling intervals is measured correctly
+        #[test]
+        fn interval_jitter() {
+            // Acquire a few samples, and pretend that they were acquired at
+            // irregular intervals around a 10ms target
+            let millis = |ms: u64| ::std::time::Duration::from_millis(ms);
+            let sampler = <$sampler>::new()
+                                     .expect("Failed to create a sampler");
+            assert_eq!(sampler.interval_jitter(), None);
+            let mut sampler = sampler.with_target_interval(millis(10));
+            assert_eq!(sampler.interval_jitter(), Some(Vec::new()));
+            for _ in 0..4 {
+                sampler.sample().expect("Failed to acquire a sample");
+            }
+            let start = sampler.timestamps[0];
+            for (timestamp, &ms) in sampler.timestamps.iter_mut()
+                                                      .zip(&[0, 10, 23, 30]) {
+                *timestamp = start + millis(ms);
+            }
+
+            // The first sample has no predecessor, and is thus omitted
+            assert_eq!(sampler.interval_jitter(),
+                       Some(vec![millis(0), millis(3), millis(3)]));
+        }
+
+        /// Check that empty readouts, which may occur when racing with the
+        /// kernel, are parsed into an empty stream rather than panicking, and
+        /// that the data container leaves no partial sample behind if it
+        /// rejects them
+        #[test]
+        fn empty_readout() {
+            let mut sampler = <$sampler>::new()
+                                         .expect("Failed to create a sampler");
+            {
+                let mut stream = sampler.parser.parse("");
+                assert!(stream.next().is_none());
+            }
+            sampler.sample().expect("Failed to acquire a sample");
+            let outcome = sampler.samples.push(sampler.parser.parse(""));
+            if ::data::PushOutcome::into_result(outcome).is_err() {
+                assert_eq!(::data::SampledData::len(&sampler.samples), 1);
+                assert_eq!(::data::SampledData::min_len(&sampler.samples), 1);
+            }
+        }
+    };
+}
+
+
+/// Generate the tests associated with a certain per-process sampler
+///
+/// This macro should be invoked inside of the unit tests module of a sampler
+/// for some /proc/<pid> pseudo-file, next to define_sampler_tests. It only
+/// relies on the for_pid(), sample() and samples() methods, so it can also be
+/// used for per-process samplers which are not built via define_sampler.
+///
+#[cfg(test)]
+macro_rules! define_pid_sampler_tests {
+    ($sampler:ty) => {
+        /// Check that the exit of a sampled process is reported as an I/O error
+        #[test]
+        fn process_exit() {
+            // Start a process, and sample it
+            let mut child = ::std::process::Command::new("sleep")
+                                                    .arg("10")
+                                                    .spawn()
+                                                    .expect("Failed to start");
+            let mut sampler = <$sampler>::for_pid(child.id())
+                                         .expect("Failed to create a sampler");
+            sampler.sample().expect("Failed to sample a running process");
+
+            // Once the process is gone, sampling should fail
+            child.kill().expect("Failed to kill child");
+            child.wait().expect("Failed to wait for child");
+            assert!(sampler.sample().is_err());
+            assert_eq!(::data::SampledData::len(sampler.samples()), 1);
+        }
     };
 }
 
@@ -144,6 +818,20 @@ macro_rules! define_sampler_benchs {
             });
         }
 
+        /// Benchmark for the raw pseudo-file readout overhead, without UTF-8
+        /// validation (compare with readout_overhead)
+        #[test]
+        #[ignore]
+        fn bytes_readout_overhead() {
+            let mut reader =
+                ProcFileReader::open($file_location)
+                               .expect("Failed to open pseudo-file");
+            testbench::benchmark($bench_iters, || {
+                reader.sample_bytes(|_| {})
+                      .expect("Failed to read pseudo-file");
+            });
+        }
+
         /// Benchmark for the full pseudo-file sampling overhead
         #[test]
         #[ignore]
@@ -0,0 +1,41 @@
+//! This module contains filesystem helpers for unit tests
+//!
+//! Some tests need a fake pseudo-filesystem tree, e.g. a sysfs directory with
+//! a few devices in it, or a directory of recorded samples. These helpers
+//! create such trees in the system's temporary directory.
+
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+
+/// Number of scratch directories created so far by this process
+static DIRECTORY_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+
+/// Create an empty scratch directory for a test
+///
+/// The directory name features the process ID and a per-process counter, so
+/// that concurrent tests and test runs never step on each other's toes. Tests
+/// should remove the directory once they are done with it.
+///
+pub fn scratch_directory(test_name: &str) -> PathBuf {
+    let mut directory = env::temp_dir();
+    directory.push(format!("performancer-{}-{}-{}",
+                           test_name,
+                           process::id(),
+                           DIRECTORY_COUNT.fetch_add(1, Ordering::Relaxed)));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).expect("Failed to create directory");
+    directory
+}
+
+/// Write a file with some contents
+pub fn write_file(path: &Path, contents: &str) {
+    File::create(path)
+         .and_then(|mut file| file.write_all(contents.as_bytes()))
+         .expect("Failed to write file");
+}
@@ -18,10 +18,20 @@
 //!   like multiple whitespace characters, whereas we know that the Linux kernel
 //!   will only send us ASCII text and only separate it by newlines and spaces.
 //!
+//! Some files are aligned using tabs rather than spaces, so tabs are treated
+//! as column separators too. A run of mixed spaces and tabs is a single column
+//! separator, exactly like a run of spaces.
+//!
+//! The only concession which we make to non-kernel input is that a carriage
+//! return which immediately precedes a newline is treated as a space. This way,
+//! pseudo-file contents which went through a layer that converted line endings
+//! to "\r\n" (e.g. snapshots saved on Windows) are still parsed correctly.
+//!
 //! We thus provide a mechanism for separating the lines and space-separated
 //! columns of ASCII pseudo-files, achieving much better performance than
 //! regular Rust iterators in this scenario.
 
+use ::streaming::{StreamingItem, StreamingIterator};
 use std::ascii::AsciiExt;
 
 
@@ -76,7 +86,6 @@ impl<'a> SplitLinesBySpace<'a> {
     }
 
     /// Iterate over lines (see caveats in struct description)
-    /// TODO: Consider implementing some variation of StreamingIterator
     pub fn next<'b>(&'b mut self) -> Option<SplitColumns<'a, 'b>>
         where 'a: 'b
     {
@@ -126,9 +135,11 @@ impl<'a> SplitLinesBySpace<'a> {
         assert_eq!(self.status, LineSpaceSplitterStatus::InsideLine);
 
         // Consume input chars until we reach something that's not a space
+        // (tabs being treated as spaces)
         let first_non_space = loop {
             match self.char_iter.next() {
-                Some(' ') => continue,
+                Some(' ') | Some('\t') => continue,
+                Some('\r') if self.char_iter.peek() == Some('\n') => continue,
                 other => break other,
             }
         };
@@ -163,9 +174,17 @@ impl<'a> SplitLinesBySpace<'a> {
         // character or end of the input text), to locate the end of the column.
         let last_idx = loop {
             match self.char_iter.next() {
-                // We can discard spaces and end-of-input terminators: we don't
-                // care about spaces, and the character iterator is fused.
-                Some(' ') | None => break self.char_iter.prev_index(),
+                // We can discard spaces, tabs and end-of-input terminators: we
+                // don't care about spaces, and the character iterator is fused.
+                Some(' ') | Some('\t') | None => {
+                    break self.char_iter.prev_index();
+                },
+
+                // Carriage returns are treated as spaces if they are part of
+                // a "\r\n" line ending, and as data otherwise.
+                Some('\r') if self.char_iter.peek() == Some('\n') => {
+                    break self.char_iter.prev_index();
+                },
 
                 // We must backtrack on end-of-line terminators, as they can
                 // only be handled by the next column iterator invocation.
@@ -184,6 +203,18 @@ impl<'a> SplitLinesBySpace<'a> {
         Some(&self.target[first_idx..last_idx])
     }
 }
+//
+impl<'a, 'b> StreamingItem<'b> for SplitLinesBySpace<'a> {
+    /// We iterate over the space-separated columns of each line
+    type Item = SplitColumns<'a, 'b>;
+}
+//
+impl<'a> StreamingIterator for SplitLinesBySpace<'a> {
+    /// Iterate over lines (see caveats in struct description)
+    fn next<'b>(&'b mut self) -> Option<SplitColumns<'a, 'b>> {
+        SplitLinesBySpace::next(self)
+    }
+}
 ///
 /// State machine used by SplitLinesBySpace when iterating over lines
 #[derive(Debug, PartialEq)]
@@ -223,6 +254,7 @@ impl<'a, 'b> Iterator for SplitColumns<'a, 'b> {
 /// - Input is ASCII-only (so, for example, 1 byte = 1 character)
 /// - We need characters all the time, but indices only infrequently
 /// - We may rarely backtrack on one specific character ('\n')
+/// - We may rarely look ahead by one character ('\r' before '\n')
 ///
 /// This iterator is fused: it will continue to output None indefinitely after
 /// the end. We will later signal this via the FusedIterator marker trait.
@@ -264,6 +296,12 @@ impl<'a> FastCharIndices<'a> {
     fn back(&mut self) {
         self.next_char_index -= 1;
     }
+
+    /// Tell what the next character will be, without consuming it
+    #[inline]
+    fn peek(&self) -> Option<char> {
+        self.raw_bytes.get(self.next_char_index).map(|b| char::from(*b))
+    }
 }
 ///
 impl<'a> Iterator for FastCharIndices<'a> {
@@ -404,6 +442,28 @@ mod tests {
         test_splitter("This. Is\nSPARTA", &[&[&"This.", &"Is"], &[&"SPARTA"]]);
     }
 
+    /// Test that SplitLinesBySpace handles "\r\n" line endings
+    #[test]
+    fn carriage_returns() {
+        // Carriage returns before a newline are treated as spaces...
+        test_splitter("\r\n",         &[&[]]);
+        test_splitter("a\r\n",        &[&[&"a"]]);
+        test_splitter("a \r\n",       &[&[&"a"]]);
+        test_splitter("a b\r\nc d\r\n", &[&[&"a", &"b"], &[&"c", &"d"]]);
+
+        // ...but other carriage returns are treated as data
+        test_splitter("a\rb\r",       &[&[&"a\rb\r"]]);
+    }
+
+    /// Test that SplitLinesBySpace treats tabs as spaces
+    #[test]
+    fn tabs() {
+        test_splitter("a\tb  c\n",     &[&[&"a", &"b", &"c"]]);
+        test_splitter("\t",            &[&[]]);
+        test_splitter(" \t a\t\n\tb", &[&[&"a"], &[&"b"]]);
+        test_splitter("a\t\r\n",       &[&[&"a"]]);
+    }
+
     // Test that split_line_and_run behaves as expected:
     #[test]
     fn split_line_and_run() {
@@ -0,0 +1,59 @@
+//! This module defines a streaming variant of the standard Iterator trait
+//!
+//! For performance reasons, the record streams of most parsers hand out records
+//! which borrow from the record stream itself (e.g. its line splitter), so that
+//! a record must be dropped before the next one can be fetched. This pattern
+//! cannot be expressed using std::iter::Iterator, whose items must outlive the
+//! iterator's borrow, so each of these types provides an inherent next()
+//! method instead. The StreamingIterator trait gives them a common interface,
+//! which allows some iteration patterns to be implemented once for all of them.
+
+
+/// Type of the items produced by a StreamingIterator, when it is borrowed for
+/// a lifetime 'b
+///
+/// Ideally, this would be a generic associated type of StreamingIterator, but
+/// closures which accept items of any lifetime ("for<'b>" bounds) would then
+/// require the iterator to be 'static, due to a current limitation of the Rust
+/// type system. The defaulted type parameter works around this by carrying the
+/// implied "Self: 'b" bound, and should never be specified explicitly.
+///
+pub(crate) trait StreamingItem<'b, ImplicitBound = &'b Self> {
+    /// Type of the items produced by the iterator
+    type Item;
+}
+
+
+/// Iterator whose items may borrow from the iterator itself
+pub(crate) trait StreamingIterator: for<'b> StreamingItem<'b> {
+    /// Produce the next item, if any
+    fn next<'b>(&'b mut self) -> Option<<Self as StreamingItem<'b>>::Item>;
+
+    /// Call a closure on each remaining item (e.g. to validate a file schema)
+    fn for_each<F>(mut self, mut f: F)
+        where Self: Sized,
+              F: for<'b> FnMut(<Self as StreamingItem<'b>>::Item)
+    {
+        while let Some(item) = self.next() {
+            f(item);
+        }
+    }
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use ::splitter::SplitLinesBySpace;
+    use super::StreamingIterator;
+
+    /// Check that the provided iteration patterns work as expected
+    #[test]
+    fn for_each() {
+        let mut columns = Vec::new();
+        SplitLinesBySpace::new("a b\nc\n").for_each(|line| {
+            columns.push(line.count());
+        });
+        assert_eq!(columns, vec![2, 1]);
+    }
+}
@@ -0,0 +1,235 @@
+//! This module contains a reader for the CPU limits of the active cgroup
+//!
+//! Inside of a container, /proc/stat still reports the CPU usage of the whole
+//! host, which is misleading when the container is only allowed to use a
+//! fraction of the host's CPUs through a CPU quota. For example, a container
+//! which is limited to 2 CPUs on a 32-thread host maxes out at 6.25% of host
+//! CPU usage. Dividing host CPU usage by effective_cpu_count() instead of
+//! the number of host CPU threads gives back a meaningful utilization figure.
+//!
+//! The limits are read from the cgroup filesystem, starting from the cgroup of
+//! the sampling process, as listed in /proc/self/cgroup, and going up to the
+//! root of the hierarchy. Inside of a container, the root of the hierarchy is
+//! normally the container's cgroup.
+
+use ::reader::ProcFileReader;
+use std::path::{Component, Path};
+
+
+/// Location of the cgroup filesystem
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// Location of the cgroup membership list of the sampling process
+const SELF_CGROUP: &str = "/proc/self/cgroup";
+
+/// Directories in which the v1 CPU controller can be mounted
+const CPU_CONTROLLER_DIRS: [&str; 2] = ["cpu", "cpu,cpuacct"];
+
+
+/// Number of CPUs which the active cgroup is allowed to use, as determined by
+/// its CPU quota (e.g. 1.5 for 150ms of CPU time per 100ms period)
+///
+/// Both the cgroup v2 interface (cpu.max) and the cgroup v1 interface
+/// (cpu.cfs_quota_us and cpu.cfs_period_us) are supported. Quotas are
+/// enforced at every level of the cgroup hierarchy, so the most restrictive
+/// quota between the cgroup of the sampling process and the root of the
+/// hierarchy is returned. Returns None if none of these cgroups has a CPU
+/// quota, or if their CPU limits cannot be read.
+///
+/// CPU quotas can be changed at any time, so this function reads them again
+/// on every call. Call it once per analysis rather than once per sample.
+///
+pub fn effective_cpu_count() -> Option<f64> {
+    let membership = ProcFileReader::open(SELF_CGROUP)
+                                    .and_then(|mut reader| {
+                                        reader.sample(|text| text.to_owned())
+                                    })
+                                    .unwrap_or_default();
+    cpu_limit(Path::new(CGROUP_ROOT), &membership)
+}
+
+
+/// INTERNAL: Read the CPU limit of a cgroup filesystem mounted at some
+///           location, given the contents of /proc/self/cgroup
+fn cpu_limit(root: &Path, membership: &str) -> Option<f64> {
+    // On cgroup v2, the quota and period are both found in cpu.max, and the
+    // root of the hierarchy lists the available controllers
+    if root.join("cgroup.controllers").exists() {
+        let path = cgroup_path(membership, None).unwrap_or("/");
+        return min_limit(root, path, |dir| {
+            let mut reader = ProcFileReader::open(dir.join("cpu.max")).ok()?;
+            reader.sample(parse_cpu_max).ok()?
+        });
+    }
+
+    // On cgroup v1, they are in separate files of the CPU controller
+    let path = cgroup_path(membership, Some("cpu")).unwrap_or("/");
+    for controller in CPU_CONTROLLER_DIRS.iter().map(|dir| root.join(dir)) {
+        if !controller.is_dir() { continue; }
+        return min_limit(&controller, path, |dir| {
+            let read_value = |file_name: &str| -> Option<i64> {
+                let mut reader = ProcFileReader::open(dir.join(file_name))
+                                                .ok()?;
+                reader.sample(|text| text.trim().parse().ok()).ok()?
+            };
+            let quota = read_value("cpu.cfs_quota_us")?;
+            let period = read_value("cpu.cfs_period_us")?;
+
+            // A negative quota (normally -1) means that there is no limit
+            if quota > 0 && period > 0 {
+                Some(quota as f64 / period as f64)
+            } else {
+                None
+            }
+        });
+    }
+    None
+}
+
+/// INTERNAL: Find the most restrictive CPU limit between a cgroup and the root
+///           of its hierarchy, given a way to read the limit of one cgroup
+///
+/// Inside of a container, the cgroup path from /proc/self/cgroup may refer to
+/// the host's hierarchy, which is not visible. Cgroups whose limits cannot be
+/// read are thus skipped, and the root is always checked.
+///
+fn min_limit<F>(root: &Path, path: &str, read_limit: F) -> Option<f64>
+    where F: Fn(&Path) -> Option<f64>
+{
+    let mut dir = root.to_path_buf();
+    let mut min_limit = read_limit(&dir);
+    for component in Path::new(path).components() {
+        if let Component::Normal(name) = component {
+            dir.push(name);
+            if let Some(limit) = read_limit(&dir) {
+                min_limit = Some(min_limit.map_or(limit, |min| min.min(limit)));
+            }
+        }
+    }
+    min_limit
+}
+
+/// INTERNAL: Find the path of the cgroup of the sampling process, from the
+///           contents of /proc/self/cgroup
+///
+/// Each line of /proc/self/cgroup has the form "ID:CONTROLLERS:PATH", where
+/// CONTROLLERS is a comma-separated list of cgroup v1 controllers, which is
+/// empty for the cgroup v2 hierarchy. Pass the name of a v1 controller to look
+/// up its hierarchy, or None to look up the v2 hierarchy.
+///
+fn cgroup_path<'a>(membership: &'a str,
+                   controller: Option<&str>) -> Option<&'a str> {
+    membership.lines().find_map(|line| {
+        let mut columns = line.splitn(3, ':');
+        let _id = columns.next()?;
+        let controllers = columns.next()?;
+        let path = columns.next()?;
+        let matches = match controller {
+            Some(name) => controllers.split(',').any(|c| c == name),
+            None => controllers.is_empty(),
+        };
+        if matches { Some(path) } else { None }
+    })
+}
+
+/// INTERNAL: Parse the cgroup v2 cpu.max file, which contains a quota and a
+///           period in microseconds, where a "max" quota means no limit
+fn parse_cpu_max(text: &str) -> Option<f64> {
+    let mut columns = text.split_whitespace();
+    let quota = columns.next()?;
+    let period: u64 = columns.next().map_or(Some(100_000), |p| p.parse().ok())?;
+    if quota == "max" || period == 0 {
+        return None;
+    }
+    let quota: u64 = quota.parse().ok()?;
+    Some(quota as f64 / period as f64)
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use ::scratch::{scratch_directory, write_file};
+    use std::fs;
+    use super::{cgroup_path, cpu_limit, effective_cpu_count, parse_cpu_max};
+
+    /// Check that the cgroup v2 CPU limit is parsed correctly
+    #[test]
+    fn cpu_max() {
+        assert_eq!(parse_cpu_max("150000 100000\n"), Some(1.5));
+        assert_eq!(parse_cpu_max("50000\n"), Some(0.5));
+        assert_eq!(parse_cpu_max("max 100000\n"), None);
+        assert_eq!(parse_cpu_max(""), None);
+    }
+
+    /// Check that the cgroup of the sampling process is found
+    #[test]
+    fn own_cgroup() {
+        const MEMBERSHIP: &str = "12:cpu,cpuacct:/docker/abc\n\
+                                  11:memory:/docker/abc\n\
+                                  0::/user.slice/session-1.scope\n";
+        assert_eq!(cgroup_path(MEMBERSHIP, Some("cpu")), Some("/docker/abc"));
+        assert_eq!(cgroup_path(MEMBERSHIP, Some("cpuacct")),
+                   Some("/docker/abc"));
+        assert_eq!(cgroup_path(MEMBERSHIP, Some("cpuset")), None);
+        assert_eq!(cgroup_path(MEMBERSHIP, None),
+                   Some("/user.slice/session-1.scope"));
+        assert_eq!(cgroup_path("", None), None);
+    }
+
+    /// Check that CPU limits are found in cgroup v1 and v2 filesystems
+    #[test]
+    fn cpu_limits() {
+        // cgroup v2, at the root of the hierarchy
+        let root = scratch_directory("cgroup_v2");
+        write_file(&root.join("cgroup.controllers"), "cpu memory\n");
+        assert_eq!(cpu_limit(&root, "0::/\n"), None);
+        write_file(&root.join("cpu.max"), "200000 100000\n");
+        assert_eq!(cpu_limit(&root, "0::/\n"), Some(2.));
+        write_file(&root.join("cpu.max"), "max 100000\n");
+        assert_eq!(cpu_limit(&root, "0::/\n"), None);
+
+        // cgroup v2, in a nested cgroup, where the most restrictive quota
+        // up the hierarchy applies
+        let parent = root.join("parent");
+        let child = parent.join("child");
+        fs::create_dir_all(&child).expect("Failed to create directory");
+        write_file(&child.join("cpu.max"), "300000 100000\n");
+        assert_eq!(cpu_limit(&root, "0::/parent/child\n"), Some(3.));
+        write_file(&parent.join("cpu.max"), "150000 100000\n");
+        assert_eq!(cpu_limit(&root, "0::/parent/child\n"), Some(1.5));
+        assert_eq!(cpu_limit(&root, "0::/parent\n"), Some(1.5));
+
+        // Cgroups which are not visible, as in containers, are skipped
+        assert_eq!(cpu_limit(&root, "0::/parent/child/hidden\n"), Some(1.5));
+        fs::remove_dir_all(root).expect("Failed to clean up");
+
+        // cgroup v1, with and without a quota
+        let root = scratch_directory("cgroup_v1");
+        let controller = root.join("cpu,cpuacct");
+        let membership = "4:cpu,cpuacct:/batch\n";
+        fs::create_dir_all(&controller).expect("Failed to create directory");
+        write_file(&controller.join("cpu.cfs_period_us"), "100000\n");
+        write_file(&controller.join("cpu.cfs_quota_us"), "-1\n");
+        assert_eq!(cpu_limit(&root, membership), None);
+        write_file(&controller.join("cpu.cfs_quota_us"), "25000\n");
+        assert_eq!(cpu_limit(&root, membership), Some(0.25));
+
+        // cgroup v1, in a nested cgroup with a more restrictive quota
+        let batch = controller.join("batch");
+        fs::create_dir_all(&batch).expect("Failed to create directory");
+        write_file(&batch.join("cpu.cfs_period_us"), "100000\n");
+        write_file(&batch.join("cpu.cfs_quota_us"), "10000\n");
+        assert_eq!(cpu_limit(&root, membership), Some(0.1));
+        assert_eq!(cpu_limit(&root, ""), Some(0.25));
+        fs::remove_dir_all(root).expect("Failed to clean up");
+    }
+
+    /// Check that the CPU limit of the host, if any, is sensible
+    #[test]
+    fn host_cpu_limit() {
+        if let Some(cpu_count) = effective_cpu_count() {
+            assert!(cpu_count > 0.);
+        }
+    }
+}
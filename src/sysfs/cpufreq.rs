@@ -0,0 +1,403 @@
+//! This module contains a sampling parser for the CPU frequencies from
+//! /sys/devices/system/cpu
+//!
+//! With frequency scaling, the amount of work that a busy CPU does depends on
+//! its current clock frequency, which /proc/stat knows nothing about. Sampling
+//! these frequencies allows e.g. weighting CPU utilization by frequency (see
+//! stat::Data::frequency_weighted_utilization_between).
+
+use ::columns::{Column, Columns};
+use ::data::SampledData;
+use ::reader::ProcFileReader;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+
+/// Location of the CPU directories in sysfs
+const CPU_ROOT: &str = "/sys/devices/system/cpu";
+
+/// Prefix of the CPU directories
+const CPU_PREFIX: &str = "cpu";
+
+
+/// Mechanism for sampling the current clock frequency of each CPU
+///
+/// Each CPU is described by a directory called /sys/devices/system/cpu/cpuN,
+/// whose "cpufreq" subdirectory features a "scaling_cur_freq" file (current
+/// frequency in kHz) and a "cpuinfo_max_freq" file (maximal frequency in kHz).
+///
+/// CPUs are enumerated once, when the sampler is created. The cpufreq
+/// directory of a CPU vanishes when that CPU is taken offline via hotplug, so
+/// failing to read the frequency of a CPU is not an error: the corresponding
+/// sample is just missing, and reading will be attempted again next time.
+///
+pub struct Sampler {
+    /// cpufreq directories of the CPUs, in the same order as the data store
+    cpufreq_dirs: Vec<PathBuf>,
+
+    /// Frequency readers of the CPUs, in the same order as the data store
+    readers: Vec<Option<ProcFileReader>>,
+
+    /// Samples of data extracted from the CPUs
+    samples: Data,
+}
+//
+impl Sampler {
+    /// Create a new sampler of the system's CPU frequencies
+    pub fn new() -> io::Result<Self> {
+        Self::open(CPU_ROOT)
+    }
+
+    /// Acquire a new sample of CPU frequencies
+    pub fn sample(&mut self) -> io::Result<()> {
+        let sample_idx = self.samples.sample_count;
+        for ((reader, core), cpufreq_dir) in
+            self.readers.iter_mut()
+                        .zip(self.samples.cores.iter_mut())
+                        .zip(self.cpufreq_dirs.iter())
+        {
+            // Try to reopen the frequency file of offline CPUs
+            if reader.is_none() {
+                *reader = ProcFileReader::open(
+                    cpufreq_dir.join("scaling_cur_freq")
+                ).ok();
+            }
+
+            // Read the current frequency, recording a gap on failure
+            let frequency = reader.as_mut().and_then(|reader| {
+                reader.sample(Self::parse_khz).ok().and_then(|khz| khz)
+            });
+            match frequency {
+                Some(khz) => {
+                    if core.max_khz.is_none() {
+                        core.max_khz = Self::read_max_khz(cpufreq_dir);
+                    }
+                    core.push(sample_idx, khz);
+                },
+                None => *reader = None,
+            }
+        }
+        self.samples.sample_count += 1;
+        Ok(())
+    }
+
+    /// Access the samples that were acquired so far
+    pub fn samples(&self) -> &Data {
+        &self.samples
+    }
+
+    /// INTERNAL: Create a sampler for the CPUs from a certain directory
+    fn open<P: AsRef<Path>>(root: P) -> io::Result<Self> {
+        let root = root.as_ref();
+        let mut cpufreq_dirs = Vec::new();
+        let mut readers = Vec::new();
+        let mut samples = Data::new();
+        for cpu in Self::enumerate(root)? {
+            let cpufreq_dir = Self::cpufreq_dir(root, cpu);
+            readers.push(
+                ProcFileReader::open(cpufreq_dir.join("scaling_cur_freq")).ok()
+            );
+            samples.cores.push(CoreData::new(cpu,
+                                             Self::read_max_khz(&cpufreq_dir)));
+            cpufreq_dirs.push(cpufreq_dir);
+        }
+        Ok(
+            Self {
+                cpufreq_dirs,
+                readers,
+                samples,
+            }
+        )
+    }
+
+    /// INTERNAL: List the CPUs from a directory, in numerical order
+    fn enumerate(root: &Path) -> io::Result<Vec<u32>> {
+        let mut cpus = Vec::new();
+        for entry in fs::read_dir(root)? {
+            let file_name = entry?.file_name();
+            let name = match file_name.to_str() {
+                Some(name) => name,
+                None => continue,
+            };
+            if !name.starts_with(CPU_PREFIX) {
+                continue;
+            }
+            if let Ok(number) = name[CPU_PREFIX.len()..].parse::<u32>() {
+                cpus.push(number);
+            }
+        }
+        cpus.sort();
+        Ok(cpus)
+    }
+
+    /// INTERNAL: Location of the cpufreq directory of a CPU
+    fn cpufreq_dir(root: &Path, cpu: u32) -> PathBuf {
+        root.join(format!("{}{}", CPU_PREFIX, cpu)).join("cpufreq")
+    }
+
+    /// INTERNAL: Read the maximal frequency of a CPU, if available
+    fn read_max_khz(cpufreq_dir: &Path) -> Option<u32> {
+        let mut reader =
+            ProcFileReader::open(cpufreq_dir.join("cpuinfo_max_freq")).ok()?;
+        reader.sample(Self::parse_khz).ok()?
+    }
+
+    /// INTERNAL: Parse a frequency in kHz. Some cpufreq drivers report an
+    ///           unknown current frequency as "<unknown>", hence the Option.
+    fn parse_khz(text: &str) -> Option<u32> {
+        text.trim().parse().ok()
+    }
+}
+
+
+/// Frequency samples from the CPUs
+///
+/// As with thermal zones, each CPU may be missing from some samples, so we
+/// keep one time series per CPU along with the indices of the samples where
+/// that CPU's frequency could be read.
+///
+#[derive(Debug, PartialEq)]
+pub struct Data {
+    /// Frequencies of every CPU, in numerical order
+    cores: Vec<CoreData>,
+
+    /// Number of samples that were recorded
+    sample_count: usize,
+}
+//
+impl SampledData for Data {
+    /// Tell how many samples are present in the data store + check consistency
+    fn len(&self) -> usize {
+        debug_assert!(self.cores.iter().all(|core| {
+            core.frequencies.len() == core.sample_indices.len() &&
+            core.sample_indices.last().map_or(true, |&idx| {
+                idx < self.sample_count
+            })
+        }));
+        self.sample_count
+    }
+
+    /// Drop the oldest samples from the data store
+    fn drop_oldest(&mut self, count: usize) {
+        assert!(count <= self.sample_count, "Not enough samples to drop");
+        for core in self.cores.iter_mut() {
+            core.drop_samples_before(count);
+        }
+        self.sample_count -= count;
+    }
+
+    /// Tell how many complete samples are present in the data store
+    fn min_len(&self) -> usize {
+        self.cores.iter()
+                  .filter_map(|core| {
+                      core.sample_indices.get(core.frequencies.len()).cloned()
+                  })
+                  .fold(self.sample_count, ::std::cmp::min)
+    }
+
+    /// Drop the newest samples from the data store
+    fn truncate(&mut self, length: usize) {
+        assert!(length <= self.sample_count, "Not enough samples to keep");
+        for core in self.cores.iter_mut() {
+            core.drop_samples_from(length);
+        }
+        self.sample_count = length;
+    }
+}
+//
+impl Data {
+    /// Frequencies of every CPU, in numerical order
+    pub fn cores(&self) -> &[CoreData] {
+        &self.cores
+    }
+
+    /// Frequencies of a certain CPU (as numbered in /proc/stat), if known
+    pub fn core(&self, cpu: u32) -> Option<&CoreData> {
+        self.cores.iter().find(|core| core.cpu == cpu)
+    }
+
+    /// Create an empty data store
+    fn new() -> Self {
+        Self {
+            cores: Vec::new(),
+            sample_count: 0,
+        }
+    }
+}
+//
+/// Frequencies are exported in kHz, under the name of the CPU directory (e.g.
+/// "cpu0"). Samples where a CPU's frequency could not be read are NaN.
+impl Columns for Data {
+    fn columns(&self) -> Vec<(String, Column)> {
+        self.cores.iter().map(|core| {
+            (format!("{}{}", CPU_PREFIX, core.cpu),
+             Column::sparse(self.sample_count,
+                            &core.sample_indices,
+                            &core.frequencies,
+                            f64::from))
+        }).collect()
+    }
+}
+///
+///
+/// Sampled frequencies of one CPU
+#[derive(Debug, PartialEq)]
+pub struct CoreData {
+    /// Number of the CPU, as in /proc/stat
+    cpu: u32,
+
+    /// Maximal frequency of the CPU in kHz, if known
+    max_khz: Option<u32>,
+
+    /// Indices of the samples in which the frequency could be read
+    sample_indices: Vec<usize>,
+
+    /// Frequencies of the CPU, in kHz
+    frequencies: Vec<u32>,
+}
+//
+impl CoreData {
+    /// Number of the CPU, as in /proc/stat
+    pub fn cpu(&self) -> u32 { self.cpu }
+
+    /// Maximal frequency of the CPU in kHz, if known. This is only read once,
+    /// as soon as the CPU's cpufreq directory is available.
+    pub fn max_khz(&self) -> Option<u32> { self.max_khz }
+
+    /// Indices of the samples in which the frequency could be read. The
+    /// frequency time series of this CPU are aligned with this one.
+    pub fn sample_indices(&self) -> &[usize] { &self.sample_indices }
+
+    /// Frequencies of the CPU, in kHz
+    pub fn khz(&self) -> &[u32] { &self.frequencies }
+
+    /// Frequencies of the CPU, relative to its maximal frequency, if known
+    pub fn relative_frequencies(&self) -> Option<Vec<f64>> {
+        let max_khz = f64::from(self.max_khz?);
+        Some(self.frequencies.iter()
+                             .map(|&khz| f64::from(khz) / max_khz)
+                             .collect())
+    }
+
+    /// Set up storage for the frequencies of a CPU
+    fn new(cpu: u32, max_khz: Option<u32>) -> Self {
+        Self {
+            cpu,
+            max_khz,
+            sample_indices: Vec::new(),
+            frequencies: Vec::new(),
+        }
+    }
+
+    /// Record the frequency of this CPU for a certain sample
+    fn push(&mut self, sample_idx: usize, khz: u32) {
+        self.sample_indices.push(sample_idx);
+        self.frequencies.push(khz);
+    }
+
+    /// Drop the frequencies from samples older than a certain sample index,
+    /// and renumber the remaining samples accordingly
+    fn drop_samples_before(&mut self, sample_idx: usize) {
+        let count = self.sample_indices.iter()
+                                       .take_while(|&&idx| idx < sample_idx)
+                                       .count();
+        self.sample_indices.drain(..count);
+        for idx in self.sample_indices.iter_mut() {
+            *idx -= sample_idx;
+        }
+        self.frequencies.drain(..count);
+    }
+
+    /// Drop the frequencies from samples at or after a certain sample index
+    fn drop_samples_from(&mut self, sample_idx: usize) {
+        let count = self.sample_indices.iter()
+                                       .take_while(|&&idx| idx < sample_idx)
+                                       .count();
+        self.sample_indices.truncate(count);
+        self.frequencies.truncate(count);
+    }
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use ::columns::{Column, Columns};
+    use ::data::SampledData;
+    use ::scratch::{scratch_directory, write_file};
+    use std::fs;
+    use std::path::Path;
+    use super::Sampler;
+
+    /// Check that CPU frequencies are sampled correctly, even with gaps
+    #[test]
+    fn frequencies_with_gaps() {
+        // Set up a fake sysfs CPU directory with an online CPU, an offline
+        // CPU, and some noise
+        let root = scratch_directory("cpufreq");
+        write_cpufreq(&root, 0, "1200000\n", 3600000);
+        fs::create_dir_all(root.join("cpu1")).expect("Failed to create cpu1");
+        fs::create_dir_all(root.join("cpufreq"))
+           .expect("Failed to create directory");
+        fs::create_dir_all(root.join("cpuidle"))
+           .expect("Failed to create directory");
+
+        // Sample these CPUs
+        let mut sampler = Sampler::open(&root)
+                                  .expect("Failed to create a sampler");
+        assert_eq!(sampler.samples().cores().len(), 2);
+        sampler.sample().expect("Failed to sample CPU frequencies");
+
+        // Bring the second CPU online, and make the first report an unknown
+        // frequency
+        write_cpufreq(&root, 1, "800000\n", 3200000);
+        write_file(&root.join("cpu0/cpufreq/scaling_cur_freq"), "<unknown>\n");
+        sampler.sample().expect("Failed to sample CPU frequencies");
+        {
+            let data = sampler.samples();
+            assert_eq!(data.len(), 2);
+            let cpu0 = data.core(0).expect("Missing cpu0");
+            assert_eq!(cpu0.max_khz(), Some(3600000));
+            assert_eq!(cpu0.sample_indices(), &[0]);
+            assert_eq!(cpu0.khz(), &[1200000]);
+            assert_eq!(cpu0.relative_frequencies(), Some(vec![1. / 3.]));
+            let cpu1 = data.core(1).expect("Missing cpu1");
+            assert_eq!(cpu1.max_khz(), Some(3200000));
+            assert_eq!(cpu1.sample_indices(), &[1]);
+            assert_eq!(cpu1.khz(), &[800000]);
+            match data.columns()[1].1 {
+                Column::F64(ref values) => {
+                    assert!(values[0].is_nan());
+                    assert_eq!(values[1], 800000.);
+                },
+                _ => panic!("Frequencies should be real-valued"),
+            }
+        }
+
+        // A CPU whose frequency becomes readable again is sampled again
+        write_file(&root.join("cpu0/cpufreq/scaling_cur_freq"), "2400000\n");
+        sampler.sample().expect("Failed to sample CPU frequencies");
+        assert_eq!(sampler.samples().core(0).unwrap().sample_indices(),
+                   &[0, 2]);
+
+        // Check that dropping old samples renumbers everything correctly
+        sampler.samples.drop_oldest(1);
+        assert_eq!(sampler.samples().len(), 2);
+        assert_eq!(sampler.samples().core(0).unwrap().sample_indices(), &[1]);
+        assert_eq!(sampler.samples().core(1).unwrap().sample_indices(),
+                   &[0, 1]);
+
+        // Clean up after ourselves
+        fs::remove_dir_all(root).expect("Failed to clean up");
+    }
+
+    /// Create a fake cpufreq directory
+    fn write_cpufreq(root: &Path, cpu: u32, cur_freq: &str, max_freq: u32) {
+        let cpufreq_dir = root.join(format!("cpu{}", cpu)).join("cpufreq");
+        fs::create_dir_all(&cpufreq_dir).expect("Failed to create cpufreq");
+        write_file(&cpufreq_dir.join("scaling_cur_freq"), cur_freq);
+        write_file(&cpufreq_dir.join("cpuinfo_max_freq"),
+                   &format!("{}\n", max_freq));
+    }
+}
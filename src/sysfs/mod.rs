@@ -0,0 +1,14 @@
+//! This module contains parsers for selected contents of sysfs.
+//!
+//! Unlike procfs, sysfs is mostly a giant map of the kernel's device model,
+//! and most of it is not interesting for performance studies. We only cover
+//! the few parts of it which provide useful system activity metrics, such as
+//! hardware temperatures, or which are needed to interpret these metrics, such
+//! as the CPU limits of containers.
+//!
+//! Sysfs follows a "one value per file" convention, so each submodule usually
+//! samples a set of files, rather than a single pseudo-file as in procfs.
+
+pub mod cgroup;
+pub mod cpufreq;
+pub mod thermal;
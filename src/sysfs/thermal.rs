@@ -0,0 +1,466 @@
+//! This module contains a sampling parser for /sys/class/thermal
+
+use ::columns::{Column, Columns};
+use ::data::SampledData;
+use ::reader::ProcFileReader;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::str;
+
+
+/// Location of the thermal zones in sysfs
+const THERMAL_ROOT: &str = "/sys/class/thermal";
+
+/// Prefix of the thermal zone directories
+const ZONE_PREFIX: &str = "thermal_zone";
+
+
+/// Mechanism for sampling the temperature of thermal zones
+///
+/// Each thermal zone is a directory called /sys/class/thermal/thermal_zoneN,
+/// featuring a "type" file (describing what the zone is about, e.g. "acpitz"
+/// or "x86_pkg_temp") and a "temp" file (the current temperature of the zone
+/// in millidegrees Celsius). Thermal zones can come and go on some platforms,
+/// so they are re-enumerated on every sample.
+///
+pub struct Sampler {
+    /// Directory in which thermal zones are enumerated
+    root: PathBuf,
+
+    /// Thermal zones which were present during the last sample, in order
+    active_zones: Vec<ActiveZone>,
+
+    /// Samples of data extracted from the thermal zones
+    samples: Data,
+}
+//
+impl Sampler {
+    /// Create a new sampler of the system's thermal zones
+    pub fn new() -> io::Result<Self> {
+        Self::open(THERMAL_ROOT)
+    }
+
+    /// Acquire a new sample of thermal zone temperatures
+    pub fn sample(&mut self) -> io::Result<()> {
+        // Check if the set of thermal zones has changed, and if so, update our
+        // knowledge of the active thermal zones accordingly
+        let sample_idx = self.samples.sample_count;
+        let zone_names = Self::enumerate(&self.root)?;
+        let set_changed =
+            zone_names.len() != self.active_zones.len() ||
+            zone_names.iter()
+                      .zip(self.active_zones.iter())
+                      .any(|(name, zone)| *name != zone.name);
+        if set_changed {
+            self.update_zones(zone_names)?;
+            self.samples.zone_set_changes.push(sample_idx);
+        }
+
+        // Sample the temperature of each active thermal zone
+        for zone in self.active_zones.iter_mut() {
+            let temperature =
+                zone.temp_reader.sample_bytes(Self::parse_temperature)??;
+            self.samples.zones[zone.data_idx].push(sample_idx, temperature);
+        }
+        self.samples.sample_count += 1;
+        Ok(())
+    }
+
+    /// Access the samples that were acquired so far
+    pub fn samples(&self) -> &Data {
+        &self.samples
+    }
+
+    /// INTERNAL: Create a sampler for thermal zones from a certain directory
+    fn open<P: AsRef<Path>>(root: P) -> io::Result<Self> {
+        let mut sampler = Self {
+            root: root.as_ref().to_owned(),
+            active_zones: Vec::new(),
+            samples: Data::new(),
+        };
+        let zone_names = Self::enumerate(&sampler.root)?;
+        sampler.update_zones(zone_names)?;
+        Ok(sampler)
+    }
+
+    /// INTERNAL: List the thermal zones from a directory, in numerical order
+    fn enumerate(root: &Path) -> io::Result<Vec<String>> {
+        let mut zones = Vec::new();
+        for entry in fs::read_dir(root)? {
+            let file_name = entry?.file_name();
+            let name = match file_name.to_str() {
+                Some(name) => name,
+                None => continue,
+            };
+            if !name.starts_with(ZONE_PREFIX) {
+                continue;
+            }
+            if let Ok(number) = name[ZONE_PREFIX.len()..].parse::<u32>() {
+                zones.push((number, name.to_owned()));
+            }
+        }
+        zones.sort();
+        Ok(zones.into_iter().map(|(_, name)| name).collect())
+    }
+
+    /// INTERNAL: Set up sampling for a new set of thermal zones, reusing the
+    ///           readers and data stores of the zones that we already know
+    fn update_zones(&mut self, zone_names: Vec<String>) -> io::Result<()> {
+        let mut old_zones = ::std::mem::replace(&mut self.active_zones,
+                                                Vec::new());
+        for name in zone_names {
+            let zone = match old_zones.iter().position(|z| z.name == name) {
+                Some(pos) => old_zones.swap_remove(pos),
+                None => {
+                    let zone_dir = self.root.join(&name);
+                    let kind = Self::read_type(&zone_dir)?;
+                    ActiveZone {
+                        data_idx: self.samples.find_or_insert(&name, &kind),
+                        temp_reader: ProcFileReader::open(
+                            zone_dir.join("temp")
+                        )?,
+                        name,
+                    }
+                }
+            };
+            self.active_zones.push(zone);
+        }
+        Ok(())
+    }
+
+    /// INTERNAL: Read the type of a thermal zone
+    fn read_type(zone_dir: &Path) -> io::Result<String> {
+        let mut kind = String::new();
+        File::open(zone_dir.join("type"))?.read_to_string(&mut kind)?;
+        Ok(kind.trim().to_owned())
+    }
+
+    /// INTERNAL: Parse a temperature in millidegrees Celsius
+    ///
+    /// Temperatures are plain ASCII integers which are read once per zone and
+    /// per sample, so we read them as raw bytes and only validate the few
+    /// bytes of the number as UTF-8. Some drivers report garbage when the
+    /// sensor cannot be read, which is reported as an InvalidData error.
+    ///
+    fn parse_temperature(bytes: &[u8]) -> io::Result<i32> {
+        str::from_utf8(bytes).ok()
+                             .and_then(|text| text.trim().parse::<i32>().ok())
+                             .ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData,
+                           "Failed to parse thermal zone temperature")
+        })
+    }
+}
+///
+///
+/// INTERNAL: Thermal zone which is currently being sampled
+struct ActiveZone {
+    /// Name of the thermal zone directory (e.g. "thermal_zone0")
+    name: String,
+
+    /// Reader for the temperature of the zone
+    temp_reader: ProcFileReader,
+
+    /// Index of the zone in the data store
+    data_idx: usize,
+}
+
+
+/// Temperature samples from the thermal zones
+///
+/// As in /proc/net/wireless, we keep one time series per thermal zone ever
+/// observed, along with the indices of the samples where that zone was present,
+/// and record the sample indices at which the set of thermal zones changed.
+///
+#[derive(Debug, PartialEq)]
+pub struct Data {
+    /// Temperatures of every thermal zone that we have seen
+    zones: Vec<ZoneData>,
+
+    /// Number of samples that were recorded
+    sample_count: usize,
+
+    /// Sample indices at which the set of thermal zones changed
+    zone_set_changes: Vec<usize>,
+}
+//
+impl SampledData for Data {
+    /// Tell how many samples are present in the data store + check consistency
+    fn len(&self) -> usize {
+        debug_assert!(self.zones.iter().all(|zone| {
+            zone.temperatures.len() == zone.sample_indices.len() &&
+            zone.sample_indices.last().map_or(true, |&idx| {
+                idx < self.sample_count
+            })
+        }));
+        self.sample_count
+    }
+
+    /// Drop the oldest samples from the data store
+    fn drop_oldest(&mut self, count: usize) {
+        assert!(count <= self.sample_count, "Not enough samples to drop");
+        for zone in self.zones.iter_mut() {
+            zone.drop_samples_before(count);
+        }
+        self.zone_set_changes.retain(|&idx| idx >= count);
+        for idx in self.zone_set_changes.iter_mut() {
+            *idx -= count;
+        }
+        self.sample_count -= count;
+    }
+
+    /// Tell how many complete samples are present in the data store
+    fn min_len(&self) -> usize {
+        self.zones.iter()
+                  .filter_map(|zone| {
+                      zone.sample_indices.get(zone.temperatures.len()).cloned()
+                  })
+                  .fold(self.sample_count, ::std::cmp::min)
+    }
+
+    /// Drop the newest samples from the data store
+    fn truncate(&mut self, length: usize) {
+        assert!(length <= self.sample_count, "Not enough samples to keep");
+        for zone in self.zones.iter_mut() {
+            zone.drop_samples_from(length);
+        }
+        self.zone_set_changes.retain(|&idx| idx < length);
+        self.sample_count = length;
+    }
+}
+//
+impl Data {
+    /// Temperatures of every thermal zone that was observed so far
+    pub fn zones(&self) -> &[ZoneData] {
+        &self.zones
+    }
+
+    /// Temperatures of the first thermal zone of a certain type (e.g.
+    /// "x86_pkg_temp"), if such a zone was observed
+    pub fn zone(&self, kind: &str) -> Option<&ZoneData> {
+        self.zones.iter().find(|zone| zone.kind == kind)
+    }
+
+    /// Sample indices at which the set of thermal zones changed
+    pub fn zone_set_changes(&self) -> &[usize] {
+        &self.zone_set_changes
+    }
+
+    /// Create an empty data store
+    fn new() -> Self {
+        Self {
+            zones: Vec::new(),
+            sample_count: 0,
+            zone_set_changes: Vec::new(),
+        }
+    }
+
+    /// INTERNAL: Find a thermal zone, or start tracking it if it is new
+    fn find_or_insert(&mut self, name: &str, kind: &str) -> usize {
+        let position = self.zones.iter().position(|zone| {
+            zone.name == name && zone.kind == kind
+        });
+        match position {
+            Some(idx) => idx,
+            None => {
+                self.zones.push(ZoneData::new(name, kind));
+                self.zones.len() - 1
+            }
+        }
+    }
+}
+//
+/// Temperatures are exported in degrees Celsius, under the name of the
+/// thermal zone (e.g. "thermal_zone0"). As zones may come and go, the samples
+/// where a zone was absent are NaN.
+impl Columns for Data {
+    fn columns(&self) -> Vec<(String, Column)> {
+        self.zones.iter().map(|zone| {
+            (zone.name.clone(),
+             Column::sparse(self.sample_count,
+                            &zone.sample_indices,
+                            &zone.temperatures,
+                            |millis| f64::from(millis) / 1000.))
+        }).collect()
+    }
+}
+///
+///
+/// Sampled temperatures of one thermal zone
+#[derive(Debug, PartialEq)]
+pub struct ZoneData {
+    /// Name of the thermal zone directory (e.g. "thermal_zone0")
+    name: String,
+
+    /// Type of the thermal zone (e.g. "x86_pkg_temp")
+    kind: String,
+
+    /// Indices of the samples in which this zone was present
+    sample_indices: Vec<usize>,
+
+    /// Temperatures of the zone, in millidegrees Celsius
+    temperatures: Vec<i32>,
+}
+//
+impl ZoneData {
+    /// Name of the thermal zone directory (e.g. "thermal_zone0")
+    pub fn name(&self) -> &str { &self.name }
+
+    /// Type of the thermal zone (e.g. "x86_pkg_temp")
+    pub fn kind(&self) -> &str { &self.kind }
+
+    /// Indices of the samples in which this zone was present. The temperature
+    /// time series of this zone are aligned with this one.
+    pub fn sample_indices(&self) -> &[usize] { &self.sample_indices }
+
+    /// Temperatures of the zone, in millidegrees Celsius
+    pub fn millicelsius(&self) -> &[i32] { &self.temperatures }
+
+    /// Temperatures of the zone, in degrees Celsius
+    pub fn celsius(&self) -> Vec<f32> {
+        self.temperatures.iter()
+                         .map(|&milli| milli as f32 / 1000.0)
+                         .collect()
+    }
+
+    /// Set up storage for the temperatures of a thermal zone
+    fn new(name: &str, kind: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            kind: kind.to_owned(),
+            sample_indices: Vec::new(),
+            temperatures: Vec::new(),
+        }
+    }
+
+    /// Record the temperature of this zone for a certain sample
+    fn push(&mut self, sample_idx: usize, temperature: i32) {
+        self.sample_indices.push(sample_idx);
+        self.temperatures.push(temperature);
+    }
+
+    /// Drop the temperatures from samples older than a certain sample index,
+    /// and renumber the remaining samples accordingly
+    fn drop_samples_before(&mut self, sample_idx: usize) {
+        let count = self.sample_indices.iter()
+                                       .take_while(|&&idx| idx < sample_idx)
+                                       .count();
+        self.sample_indices.drain(..count);
+        for idx in self.sample_indices.iter_mut() {
+            *idx -= sample_idx;
+        }
+        self.temperatures.drain(..count);
+    }
+
+    /// Drop the temperatures from samples at or after a certain sample index
+    fn drop_samples_from(&mut self, sample_idx: usize) {
+        let count = self.sample_indices.iter()
+                                       .take_while(|&&idx| idx < sample_idx)
+                                       .count();
+        self.sample_indices.truncate(count);
+        self.temperatures.truncate(count);
+    }
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use ::data::SampledData;
+    use ::scratch::{scratch_directory, write_file};
+    use std::fs;
+    use std::io;
+    use std::path::Path;
+    use super::Sampler;
+
+    /// Check that temperatures are parsed correctly, and garbage is rejected
+    #[test]
+    fn parse_temperature() {
+        assert_eq!(Sampler::parse_temperature(b"45000\n").unwrap(), 45000);
+        assert_eq!(Sampler::parse_temperature(b"-7500\n").unwrap(), -7500);
+        assert_eq!(Sampler::parse_temperature(b"  0 ").unwrap(), 0);
+        let invalid: [&[u8]; 6] = [b"", b"\n", b"-", b"4a000\n",
+                                   b"99999999999\n", b"\xff\n"];
+        for bytes in &invalid {
+            let error = Sampler::parse_temperature(bytes).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    /// Check that thermal zones are sampled correctly, even if they change
+    #[test]
+    fn dynamic_zones() {
+        // Set up a fake sysfs thermal directory with two zones and some noise
+        let root = scratch_directory("thermal_zones");
+        write_zone(&root, 0, "acpitz", 27800);
+        write_zone(&root, 1, "x86_pkg_temp", 45000);
+        fs::create_dir_all(root.join("cooling_device0"))
+           .expect("Failed to create directory");
+
+        // Sample these zones
+        let mut sampler = Sampler::open(&root)
+                                  .expect("Failed to create a sampler");
+        assert_eq!(sampler.samples().len(), 0);
+        sampler.sample().expect("Failed to sample thermal zones");
+        write_temperature(&root, 1, 51500);
+        sampler.sample().expect("Failed to sample thermal zones");
+        {
+            let data = sampler.samples();
+            assert_eq!(data.len(), 2);
+            assert_eq!(data.zones().len(), 2);
+            assert_eq!(data.zone_set_changes(), &[] as &[usize]);
+            let pkg = data.zone("x86_pkg_temp").expect("Missing zone");
+            assert_eq!(pkg.name(), "thermal_zone1");
+            assert_eq!(pkg.sample_indices(), &[0, 1]);
+            assert_eq!(pkg.millicelsius(), &[45000, 51500]);
+            assert_eq!(pkg.celsius(), vec![45.0, 51.5]);
+        }
+
+        // Make a zone disappear, and another one appear
+        fs::remove_dir_all(root.join("thermal_zone0"))
+           .expect("Failed to remove zone");
+        write_zone(&root, 10, "iwlwifi_1", -1000);
+        sampler.sample().expect("Failed to sample thermal zones");
+        {
+            let data = sampler.samples();
+            assert_eq!(data.len(), 3);
+            assert_eq!(data.zone_set_changes(), &[2]);
+            let acpi = data.zone("acpitz").expect("Missing zone");
+            assert_eq!(acpi.sample_indices(), &[0, 1]);
+            let wifi = data.zone("iwlwifi_1").expect("Missing zone");
+            assert_eq!(wifi.sample_indices(), &[2]);
+            assert_eq!(wifi.celsius(), vec![-1.0]);
+            let pkg = data.zone("x86_pkg_temp").expect("Missing zone");
+            assert_eq!(pkg.sample_indices(), &[0, 1, 2]);
+        }
+
+        // Check that dropping old samples renumbers everything correctly
+        sampler.samples.drop_oldest(2);
+        {
+            let data = sampler.samples();
+            assert_eq!(data.len(), 1);
+            assert_eq!(data.zone_set_changes(), &[0]);
+            assert_eq!(data.zone("acpitz").unwrap().sample_indices(),
+                       &[] as &[usize]);
+            assert_eq!(data.zone("iwlwifi_1").unwrap().sample_indices(), &[0]);
+        }
+
+        // Clean up after ourselves
+        fs::remove_dir_all(root).expect("Failed to clean up");
+    }
+
+    /// Create a fake thermal zone
+    fn write_zone(root: &Path, number: u32, kind: &str, temperature: i32) {
+        let zone_dir = root.join(format!("thermal_zone{}", number));
+        fs::create_dir_all(&zone_dir).expect("Failed to create zone");
+        write_file(&zone_dir.join("type"), &format!("{}\n", kind));
+        write_temperature(root, number, temperature);
+    }
+
+    /// Update the temperature of a fake thermal zone
+    fn write_temperature(root: &Path, number: u32, temperature: i32) {
+        let path = root.join(format!("thermal_zone{}", number)).join("temp");
+        write_file(&path, &format!("{}\n", temperature));
+    }
+}
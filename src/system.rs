@@ -0,0 +1,481 @@
+//! This module provides a combined sampler for system-wide activity metrics
+//!
+//! Most users of this library are not interested in the minute details of
+//! every pseudo-file, but rather in the handful of headline metrics that system
+//! monitoring dashboards display, such as CPU load or memory usage. This module
+//! samples the relevant pseudo-files together and computes these metrics from
+//! the two most recent samples.
+
+use ::data::SampledData;
+use ::procfs::{loadavg, meminfo, stat, uptime};
+use bytesize::ByteSize;
+use std::io;
+use std::time::{Duration, Instant};
+
+
+/// Combined sampler for the system-wide pseudo-files of procfs
+///
+/// Each pseudo-file is only sampled if it was requested at construction time,
+/// so that users only pay the sampling overhead of the metrics that they need.
+pub struct SystemSampler {
+    /// Sampler for /proc/stat, if requested
+    stat: Option<stat::Sampler>,
+
+    /// Sampler for /proc/meminfo, if requested
+    meminfo: Option<meminfo::Sampler>,
+
+    /// Sampler for /proc/uptime, if requested
+    uptime: Option<uptime::Sampler>,
+
+    /// Sampler for /proc/loadavg, if requested
+    loadavg: Option<loadavg::Sampler>,
+
+    /// CPU limit of the sampling process' cgroup, if requested and set
+    cpu_limit: Option<f64>,
+
+    /// Time at which each sample was acquired
+    timestamps: Vec<Instant>,
+}
+//
+impl SystemSampler {
+    /// Create a system sampler which does not sample anything yet
+    pub fn new() -> Self {
+        Self {
+            stat: None,
+            meminfo: None,
+            uptime: None,
+            loadavg: None,
+            cpu_limit: None,
+            timestamps: Vec::new(),
+        }
+    }
+
+    /// Add /proc/stat to the set of sampled pseudo-files
+    pub fn with_stat(mut self) -> io::Result<Self> {
+        self.check_unsampled();
+        self.stat = Some(stat::Sampler::new()?);
+        Ok(self)
+    }
+
+    /// Add /proc/meminfo to the set of sampled pseudo-files
+    pub fn with_meminfo(mut self) -> io::Result<Self> {
+        self.check_unsampled();
+        self.meminfo = Some(meminfo::Sampler::new()?);
+        Ok(self)
+    }
+
+    /// Add /proc/uptime to the set of sampled pseudo-files
+    pub fn with_uptime(mut self) -> io::Result<Self> {
+        self.check_unsampled();
+        self.uptime = Some(uptime::Sampler::new()?);
+        Ok(self)
+    }
+
+    /// Add /proc/loadavg to the set of sampled pseudo-files
+    pub fn with_loadavg(mut self) -> io::Result<Self> {
+        self.check_unsampled();
+        self.loadavg = Some(loadavg::Sampler::new()?);
+        Ok(self)
+    }
+
+    /// Measure CPU utilization against the CPU quota of the sampling
+    /// process' cgroup, in addition to the host's CPU capacity
+    ///
+    /// The quota is read once, using sysfs::cgroup::effective_cpu_count(), so
+    /// a new sampler should be created if it changes. If there is no quota,
+    /// the quota-relative utilization is not computed.
+    ///
+    pub fn with_cgroup_quota(mut self) -> Self {
+        self.cpu_limit = ::sysfs::cgroup::effective_cpu_count();
+        self
+    }
+
+    /// Acquire a new sample of every requested pseudo-file
+    ///
+    /// The sample is only stored if every pseudo-file was successfully
+    /// sampled. Otherwise, the pseudo-files which were sampled before the
+    /// failure are rolled back, so that all of them keep the same amount of
+    /// samples, and the error is returned.
+    ///
+    pub fn sample(&mut self) -> io::Result<()> {
+        let timestamp = Instant::now();
+        if let Err(error) = self.sample_all() {
+            self.roll_back();
+            return Err(error);
+        }
+        self.timestamps.push(timestamp);
+        Ok(())
+    }
+
+    /// Sampled /proc/stat data, if requested
+    pub fn stat(&self) -> Option<&stat::Data> {
+        self.stat.as_ref().map(|sampler| sampler.samples())
+    }
+
+    /// Sampled /proc/meminfo data, if requested
+    pub fn meminfo(&self) -> Option<&meminfo::Data> {
+        self.meminfo.as_ref().map(|sampler| sampler.samples())
+    }
+
+    /// Sampled /proc/uptime data, if requested
+    pub fn uptime(&self) -> Option<&uptime::Data> {
+        self.uptime.as_ref().map(|sampler| sampler.samples())
+    }
+
+    /// Sampled /proc/loadavg data, if requested
+    pub fn loadavg(&self) -> Option<&loadavg::Data> {
+        self.loadavg.as_ref().map(|sampler| sampler.samples())
+    }
+
+    /// Difference between the total CPU idle time from /proc/stat and the one
+    /// from /proc/uptime, in seconds, for each sample
+    ///
+    /// Both pseudo-files are computed by the kernel from the same idle time
+    /// accounting, so this should stay close to zero. Small discrepancies are
+    /// expected, as the files are not read at the same time and do not have
+    /// the same resolution, but large ones hint at an issue with the
+    /// conversion of /proc/stat's clock ticks to durations.
+    ///
+    /// Returns None unless both /proc/stat and /proc/uptime are sampled, and
+    /// /proc/stat provides CPU statistics.
+    ///
+    pub fn idle_time_discrepancy(&self) -> Option<Vec<f64>> {
+        let stat_idle = self.stat()?.total_idle_time();
+        let uptime_idle = self.uptime()?.cpu_idle_time();
+        if stat_idle.len() != uptime_idle.len() {
+            return None;
+        }
+        let secs = |d: &Duration| d.as_secs() as f64
+                                  + d.subsec_nanos() as f64 * 1e-9;
+        Some(
+            stat_idle.iter()
+                     .zip(uptime_idle.iter())
+                     .map(|(stat, uptime)| secs(stat) - secs(uptime))
+                     .collect()
+        )
+    }
+
+    /// Compute headline metrics from the two most recent samples, or return
+    /// None if less than two samples were acquired so far
+    pub fn snapshot(&self) -> Option<Snapshot> {
+        // We need two samples to compute rates of change
+        let num_samples = self.timestamps.len();
+        if num_samples < 2 {
+            return None;
+        }
+        let (older, newer) = (num_samples - 2, num_samples - 1);
+        let elapsed = self.timestamps[newer] - self.timestamps[older];
+
+        // Compute the metrics from /proc/stat, if available
+        let stat = self.stat();
+        debug_assert!(stat.map_or(true, |data| data.len() == num_samples),
+                      "Inconsistent amount of /proc/stat samples");
+        let cpu_busy_percent = stat.and_then(|data| data.all_cpus())
+                                   .and_then(|cpu| {
+                                       Self::busy_percent(cpu, older, newer)
+                                   });
+        let core_busy_percent = stat.and_then(|data| {
+            let threads = data.each_thread();
+            if threads.is_empty() {
+                return None;
+            }
+            threads.iter()
+                   .map(|cpu| {
+                       cpu.busy_fraction(older, newer)
+                          .map(|fraction| fraction * 100.0)
+                   })
+                   .collect()
+        });
+        let quota_busy_percent = stat.and_then(|data| {
+            self.cpu_limit.and_then(|limit| {
+                data.quota_utilization_between(older, newer, limit)
+            })
+        }).map(|fraction| fraction * 100.0);
+        let context_switch_rate = stat.and_then(|data| {
+            data.context_switches().and_then(|counts| {
+                data.rate_between(counts, older, newer, elapsed)
+            })
+        });
+        let interrupt_rate = stat.and_then(|data| {
+            data.interrupts().and_then(|intr| {
+                data.rate_between(intr.total(), older, newer, elapsed)
+            })
+        });
+
+        // Compute the metrics from /proc/meminfo, if available
+        let meminfo = self.meminfo();
+        debug_assert!(meminfo.map_or(true, |data| data.len() == num_samples),
+                      "Inconsistent amount of /proc/meminfo samples");
+        let memory_available = meminfo.and_then(|data| {
+            data.data_volume("MemAvailable")
+        }).map(|vec| vec[newer]);
+        let memory_used = meminfo.and_then(|data| data.data_volume("MemTotal"))
+                                 .and_then(|total| {
+            memory_available.map(|available| {
+                ByteSize::b(total[newer].as_usize() - available.as_usize())
+            })
+        });
+
+        // Fetch the latest load averages from /proc/loadavg, if available
+        let loadavg = self.loadavg();
+        debug_assert!(loadavg.map_or(true, |data| data.len() == num_samples),
+                      "Inconsistent amount of /proc/loadavg samples");
+        let load_average = loadavg.and_then(loadavg::Data::latest);
+
+        // Return the snapshot
+        Some(
+            Snapshot {
+                elapsed,
+                cpu_busy_percent,
+                core_busy_percent,
+                quota_busy_percent,
+                memory_used,
+                memory_available,
+                context_switch_rate,
+                interrupt_rate,
+                load_average,
+            }
+        )
+    }
+
+    /// INTERNAL: Acquire a new sample of every requested pseudo-file, stopping
+    ///           at the first failure
+    fn sample_all(&mut self) -> io::Result<()> {
+        if let Some(ref mut stat) = self.stat {
+            stat.sample()?;
+        }
+        if let Some(ref mut meminfo) = self.meminfo {
+            meminfo.sample()?;
+        }
+        if let Some(ref mut uptime) = self.uptime {
+            uptime.sample()?;
+        }
+        if let Some(ref mut loadavg) = self.loadavg {
+            loadavg.sample()?;
+        }
+        Ok(())
+    }
+
+    /// INTERNAL: Drop the newest sample of the pseudo-files which were sampled
+    ///           more times than the system sampler, after a failed sample
+    fn roll_back(&mut self) {
+        let length = self.timestamps.len();
+        if let Some(ref mut stat) = self.stat {
+            if stat.timestamps().len() > length { stat.drop_newest(); }
+        }
+        if let Some(ref mut meminfo) = self.meminfo {
+            if meminfo.timestamps().len() > length { meminfo.drop_newest(); }
+        }
+        if let Some(ref mut uptime) = self.uptime {
+            if uptime.timestamps().len() > length { uptime.drop_newest(); }
+        }
+        if let Some(ref mut loadavg) = self.loadavg {
+            if loadavg.timestamps().len() > length { loadavg.drop_newest(); }
+        }
+    }
+
+    /// INTERNAL: Check that no sample was acquired yet, as the set of sampled
+    ///           pseudo-files must not change during sampling
+    fn check_unsampled(&self) {
+        assert!(self.timestamps.is_empty(),
+                "Sampled pseudo-files must be selected before sampling");
+    }
+
+    /// INTERNAL: Percentage of CPU time spent doing work between two samples
+    fn busy_percent(cpu: &stat::cpu::Data,
+                    older: usize,
+                    newer: usize) -> Option<f64> {
+        cpu.busy_fraction(older, newer).map(|fraction| fraction * 100.0)
+    }
+}
+//
+impl Default for SystemSampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// Headline system activity metrics, computed from two consecutive samples
+///
+/// Metrics whose source pseudo-file is not being sampled, or which are not
+/// provided by the host kernel, are set to None.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    /// Wall clock time elapsed between the two samples
+    pub elapsed: Duration,
+
+    /// Percentage of CPU time spent doing work, across all CPU threads
+    pub cpu_busy_percent: Option<f64>,
+
+    /// Percentage of CPU time spent doing work, for each CPU thread
+    pub core_busy_percent: Option<Vec<f64>>,
+
+    /// Percentage of the cgroup CPU quota which was used, if requested via
+    /// SystemSampler::with_cgroup_quota() and the cgroup has a CPU quota
+    pub quota_busy_percent: Option<f64>,
+
+    /// Amount of memory in use (total memory minus available memory)
+    pub memory_used: Option<ByteSize>,
+
+    /// Amount of memory available for starting new applications
+    pub memory_available: Option<ByteSize>,
+
+    /// Context switches per second
+    pub context_switch_rate: Option<f64>,
+
+    /// Hardware interrupts serviced per second
+    pub interrupt_rate: Option<f64>,
+
+    /// Load averages from the most recent sample, as smoothed by the kernel
+    pub load_average: Option<loadavg::LoadAverages>,
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use ::data::SampledData;
+    use ::procfs::{meminfo, stat};
+    use std::fs;
+    use std::io;
+    use std::thread;
+    use std::time::Duration;
+    use super::SystemSampler;
+
+    /// Check that snapshots are only available after two samples
+    #[test]
+    fn snapshot_availability() {
+        let mut sampler = SystemSampler::new().with_meminfo()
+                                              .expect("Failed to sample meminfo");
+        assert_eq!(sampler.snapshot(), None);
+        sampler.sample().expect("Failed to acquire a first sample");
+        assert_eq!(sampler.snapshot(), None);
+        sampler.sample().expect("Failed to acquire a second sample");
+        let snapshot = sampler.snapshot().expect("Snapshot should be there");
+        assert!(snapshot.memory_available.is_some());
+        assert_eq!(snapshot.cpu_busy_percent, None);
+        assert_eq!(snapshot.core_busy_percent, None);
+        assert_eq!(snapshot.quota_busy_percent, None);
+        assert_eq!(snapshot.context_switch_rate, None);
+        assert_eq!(snapshot.interrupt_rate, None);
+        assert_eq!(snapshot.load_average, None);
+    }
+
+    /// Check that load averages are reported when sampling loadavg
+    #[test]
+    fn load_average() {
+        let mut sampler =
+            SystemSampler::new().with_loadavg()
+                                .expect("Failed to sample loadavg");
+        sampler.sample().expect("Failed to acquire a first sample");
+        sampler.sample().expect("Failed to acquire a second sample");
+        let snapshot = sampler.snapshot().expect("Snapshot should be there");
+        let load = snapshot.load_average.expect("Missing load average");
+        assert_eq!(Some(load), sampler.loadavg().unwrap().latest());
+        assert!(load.one_minute >= 0.0);
+        assert_eq!(snapshot.memory_available, None);
+    }
+
+    /// Check that a failed sample leaves no partial sample behind
+    #[test]
+    fn partial_failure() {
+        // Set up a /proc/meminfo source which fails on its second sample,
+        // after /proc/stat was successfully sampled
+        let mut calls = 0;
+        let meminfo_source = move || {
+            calls += 1;
+            if calls == 3 {
+                Err(io::Error::new(io::ErrorKind::Other, "Mock failure"))
+            } else {
+                fs::read_to_string("/proc/meminfo")
+            }
+        };
+        let stat = stat::Sampler::new().expect("Failed to sample stat");
+        let meminfo = meminfo::Sampler::from_source(meminfo_source)
+                                       .expect("Failed to sample meminfo");
+        let mut sampler = SystemSampler::new();
+        sampler.stat = Some(stat);
+        sampler.meminfo = Some(meminfo);
+
+        // The failed sample should be rolled back in every pseudo-file
+        sampler.sample().expect("Failed to acquire a first sample");
+        assert!(sampler.sample().is_err());
+        assert_eq!(sampler.timestamps.len(), 1);
+        assert_eq!(sampler.stat.as_ref().unwrap().timestamps().len(), 1);
+        assert_eq!(sampler.stat().unwrap().len(), 1);
+        assert_eq!(sampler.meminfo().unwrap().len(), 1);
+
+        // Sampling works again once the source recovers
+        sampler.sample().expect("Failed to acquire a second sample");
+        assert_eq!(sampler.stat().unwrap().len(), 2);
+        assert!(sampler.snapshot().is_some());
+    }
+
+    /// Check that snapshots are populated when sampling stat and meminfo
+    #[test]
+    fn stat_and_meminfo() {
+        // Sample /proc/stat and /proc/meminfo, leaving some time in between so
+        // that the kernel accounts for a few clock ticks
+        let mut sampler = SystemSampler::new().with_stat()
+                                              .expect("Failed to sample stat")
+                                              .with_meminfo()
+                                              .expect("Failed to sample meminfo");
+        sampler.sample().expect("Failed to acquire a first sample");
+        thread::sleep(Duration::from_millis(100));
+        sampler.sample().expect("Failed to acquire a second sample");
+
+        // Check that the snapshot is filled in as expected
+        let snapshot = sampler.snapshot().expect("Snapshot should be there");
+        assert!(snapshot.elapsed >= Duration::from_millis(100));
+        let cpu_busy = snapshot.cpu_busy_percent.expect("Missing CPU load");
+        assert!(cpu_busy >= 0.0 && cpu_busy <= 100.0);
+        let core_busy = snapshot.core_busy_percent.expect("Missing core load");
+        assert!(!core_busy.is_empty());
+        assert!(core_busy.iter().all(|&busy| busy >= 0.0 && busy <= 100.0));
+        assert!(snapshot.memory_used.is_some());
+        assert!(snapshot.memory_available.is_some());
+        assert!(snapshot.context_switch_rate.expect("Missing context switches")
+                >= 0.0);
+        assert!(snapshot.interrupt_rate.expect("Missing interrupt rate") >= 0.0);
+
+        // The cgroup quota is only accounted for when requested
+        assert_eq!(snapshot.quota_busy_percent, None);
+        let mut sampler = SystemSampler::new().with_stat()
+                                              .expect("Failed to sample stat")
+                                              .with_cgroup_quota();
+        sampler.sample().expect("Failed to acquire a first sample");
+        thread::sleep(Duration::from_millis(100));
+        sampler.sample().expect("Failed to acquire a second sample");
+        let snapshot = sampler.snapshot().expect("Snapshot should be there");
+        if let Some(quota_busy) = snapshot.quota_busy_percent {
+            assert!(quota_busy >= 0.0);
+        }
+    }
+
+    /// Check that the idle times of /proc/stat and /proc/uptime are consistent
+    #[test]
+    fn idle_time_consistency() {
+        // The consistency check requires both pseudo-files
+        let mut sampler = SystemSampler::new().with_stat()
+                                              .expect("Failed to sample stat");
+        sampler.sample().expect("Failed to acquire a sample");
+        assert_eq!(sampler.idle_time_discrepancy(), None);
+
+        // When both are sampled, the idle times should match within a second
+        // per CPU thread (accounting for the resolution of /proc/uptime and
+        // for the idle time accrued between reads of the two files)
+        let mut sampler = SystemSampler::new().with_stat()
+                                              .expect("Failed to sample stat")
+                                              .with_uptime()
+                                              .expect("Failed to sample uptime");
+        sampler.sample().expect("Failed to acquire a first sample");
+        sampler.sample().expect("Failed to acquire a second sample");
+        let num_threads = sampler.stat().unwrap().thread_count().max(1);
+        let discrepancy = sampler.idle_time_discrepancy()
+                                 .expect("Missing idle time discrepancy");
+        assert_eq!(discrepancy.len(), 2);
+        assert!(discrepancy.iter().all(|d| d.abs() < num_threads as f64));
+    }
+}